@@ -7,11 +7,31 @@ use std::{
 	path::{self, Path, PathBuf},
 };
 
-mandatory_and_unique!("mcu-esp32", "mcu-esp32c3", "mcu-nrf52832", "mcu-nrf52840");
-mandatory_and_unique!("imu-stubbed", "imu-mpu6050", "imu-bmi160");
+mandatory_and_unique!(
+	"mcu-esp32",
+	"mcu-esp32c3",
+	"mcu-esp32s3",
+	"mcu-nrf52832",
+	"mcu-nrf52840"
+);
+mandatory_and_unique!(
+	"imu-stubbed",
+	"imu-mpu6050",
+	"imu-mpu6050-raw",
+	"imu-bmi160",
+	"imu-bno055",
+	"imu-bno08x"
+);
 mandatory_and_unique!("log-rtt", "log-usb-serial", "log-uart");
-mandatory_and_unique!("net-wifi", "net-ble", "net-stubbed");
-mandatory_and_unique!("fusion-stubbed", "fusion-dcm");
+mandatory_and_unique!("net-wifi", "net-ble", "net-espnow", "net-stubbed");
+mandatory_and_unique!(
+	"fusion-stubbed",
+	"fusion-dcm",
+	"fusion-budget",
+	"fusion-fixed",
+	"fusion-vqf",
+	"fusion-external"
+);
 
 #[cfg(any(feature = "mcu-nrf52840", feature = "mcu-nrf52832"))]
 mandatory_and_unique!(
@@ -23,12 +43,12 @@ mandatory_and_unique!(
 
 /// Use memory.x.feature file as memory map
 macro_rules! memory_x {
-	($mcu:literal) => {
+	($mcu:literal, $storage_base:expr) => {
 		#[cfg(feature = $mcu)]
 		{
 			let memoryx_content =
 				String::from(include_str!(concat!("linker_scripts/memory.x.", $mcu)));
-			memoryx(memoryx_content)
+			memoryx(memoryx_content, $storage_base)
 		}
 	};
 }
@@ -43,21 +63,48 @@ fn main() -> Result<()> {
 
 	// Any relevant env vars for the build script are listed here.
 	println!("cargo:rerun-if-env-changed=BOARD");
+	println!("cargo:rerun-if-env-changed=DSCP");
+	println!("cargo:rerun-if-env-changed=WMM_AC");
+	println!("cargo:rerun-if-env-changed=SENSOR_TIMEOUT_MS");
+	println!("cargo:rerun-if-env-changed=IMU_RATE_HZ");
+	println!("cargo:rerun-if-env-changed=AUX_IMU_RATE_HZ");
+	println!("cargo:rerun-if-env-changed=LOG_UDP");
+	println!("cargo:rerun-if-env-changed=LOG_UDP_RATE");
+	println!("cargo:rerun-if-env-changed=LOG_UDP_AT_BOOT");
+	println!("cargo:rerun-if-env-changed=MAG_RATE_HZ");
+	println!("cargo:rerun-if-env-changed=SSID");
+	println!("cargo:rerun-if-env-changed=PASSWORD");
+	println!("cargo:rerun-if-env-changed=OTA_PUBLIC_KEY");
+	println!("cargo:rerun-if-env-changed=OTA_UNSIGNED");
 	let _ = dotenvy::dotenv();
-	#[cfg(all(feature = "mcu-nrf52832", feature = "log-usb-serial"))]
-	compile_error!("the nrf52832 doesn't support USB!");
+	check_feature_combos()?;
+	check_wifi_provisioning();
+	check_ota_key()?;
 
 	// NOTE: Can't use the `cfg_aliases` in the build script itself, only applies to
 	// rest of codebase.
 	cfg_aliases! {
 		mcu_f_nrf52: { any(feature = "mcu-nrf52840", feature = "mcu-nrf52832") },
-		mcu_f_esp32: { any(feature = "mcu-esp32", feature = "mcu-esp32c3") },
+		mcu_f_esp32: { any(
+			feature = "mcu-esp32",
+			feature = "mcu-esp32c3",
+			feature = "mcu-esp32s3"
+		)},
 		bbq: { all(
 			any(mcu_f_nrf52),
 			any(feature = "log-uart", feature = "log-usb-serial")
 		)},
+		// The serial console reads from the USB-Serial-JTAG peripheral, which only
+		// these have.
+		console: { all(
+			feature = "console",
+			feature = "log-usb-serial",
+			any(feature = "mcu-esp32c3", feature = "mcu-esp32s3")
+		)},
+		// The softdevice is only enabled for BLE. Other builds keep it out of the way.
+		softdevice: { all(mcu_f_nrf52, feature = "net-ble") },
 		cortex_m: { mcu_f_nrf52 },
-		xtensa: { any(feature = "mcu-esp32") },
+		xtensa: { any(feature = "mcu-esp32", feature = "mcu-esp32s3") },
 		riscv: { any(feature = "mcu-esp32c3") },
 	}
 
@@ -66,18 +113,195 @@ fn main() -> Result<()> {
 	println!("cargo:rustc-link-arg=-Tesp32c3_rom_functions.x");
 	#[cfg(all(feature = "esp-wifi", feature = "mcu-esp32"))]
 	println!("cargo:rustc-link-arg=-Tesp32_rom_functions.x");
-
-	memory_x!("mcu-nrf52832");
-	memory_x!("mcu-nrf52840");
+	#[cfg(all(feature = "esp-wifi", feature = "mcu-esp32s3"))]
+	println!("cargo:rustc-link-arg=-Tesp32s3_rom_functions.x");
 
 	let board_cfg = BoardConfig::from_file(&BoardConfig::get_path()?)?;
 	board_cfg.apply_to_env();
 
+	let storage_base = board_cfg.storage.base()?;
+	println!("cargo:rustc-env=STORAGE_BASE={storage_base}");
+	memory_x!("mcu-nrf52832", storage_base);
+	memory_x!("mcu-nrf52840", storage_base);
+
+	if feature("log-udp") {
+		let log_udp_cfg = LogUdpConfig::from_env()?;
+		log_udp_cfg.apply_to_env();
+	}
+
+	let dscp = qos_dscp()?;
+	println!("cargo:rustc-env=QOS_DSCP={dscp}");
+
+	let sensor_timeout_ms: u32 = match env::var("SENSOR_TIMEOUT_MS") {
+		Ok(s) => s.trim().parse().wrap_err("Invalid `SENSOR_TIMEOUT_MS`")?,
+		Err(_) => 500,
+	};
+	println!("cargo:rustc-env=SENSOR_TIMEOUT_MS={sensor_timeout_ms}");
+
+	let imu_rate_hz: u32 = match env::var("IMU_RATE_HZ") {
+		Ok(s) => s.trim().parse().wrap_err("Invalid `IMU_RATE_HZ`")?,
+		Err(_) => 0,
+	};
+	println!("cargo:rustc-env=IMU_RATE_HZ={imu_rate_hz}");
+	let aux_imu_rate_hz: u32 = match env::var("AUX_IMU_RATE_HZ") {
+		Ok(s) => s.trim().parse().wrap_err("Invalid `AUX_IMU_RATE_HZ`")?,
+		Err(_) => 50,
+	};
+	println!("cargo:rustc-env=AUX_IMU_RATE_HZ={aux_imu_rate_hz}");
+
+	let mag_rate_hz: u32 = match env::var("MAG_RATE_HZ") {
+		Ok(s) => s.trim().parse().wrap_err("Invalid `MAG_RATE_HZ`")?,
+		Err(_) => 10,
+	};
+	// The AK8963 takes up to 9ms per measurement.
+	if !(1..=100).contains(&mag_rate_hz) {
+		return Err(eyre!("`MAG_RATE_HZ` must be from 1 to 100"));
+	}
+	println!("cargo:rustc-env=MAG_RATE_HZ={mag_rate_hz}");
+
+	Ok(())
+}
+
+/// Feature combinations that can't work, and why.
+#[rustfmt::skip]
+const UNSUPPORTED: &[(&[&str], &str)] = &[
+	(&["mcu-nrf52832", "log-usb-serial"], "the nrf52832 doesn't support USB"),
+	(&["mcu-esp32", "log-usb-serial"], "the esp32 doesn't support USB, use `log-uart` instead"),
+	(&["mcu-esp32", "log-rtt"], "RTT isn't supported on the esp32, use `log-uart` instead"),
+	(&["mcu-esp32s3", "log-rtt"], "RTT isn't supported on the esp32s3, use `log-usb-serial` instead"),
+	(&["mcu-nrf52840", "net-wifi"], "the nrf52840 has no Wi-Fi"),
+	(&["mcu-nrf52832", "net-wifi"], "the nrf52832 has no Wi-Fi"),
+	(&["mcu-nrf52840", "net-ble", "nrf-boot-none"], "BLE on the nrf52 needs a softdevice, use `nrf-boot-s140` or `nrf-boot-s132`"),
+	(&["mcu-nrf52840", "net-ble", "nrf-boot-mbr"], "BLE on the nrf52 needs a softdevice, use `nrf-boot-s140` or `nrf-boot-s132`"),
+	(&["mcu-nrf52832", "net-ble", "nrf-boot-none"], "BLE on the nrf52 needs a softdevice, use `nrf-boot-s132`"),
+	(&["mcu-nrf52832", "net-ble", "nrf-boot-mbr"], "BLE on the nrf52 needs a softdevice, use `nrf-boot-s132`"),
+	(&["mcu-nrf52840", "net-ble", "log-usb-serial"], "the softdevice owns the USB power events, use `log-rtt` or `log-uart`"),
+	(&["mcu-nrf52840", "net-espnow"], "ESP-NOW only exists on the ESP32 family"),
+	(&["mcu-nrf52832", "net-espnow"], "ESP-NOW only exists on the ESP32 family"),
+	(&["constrained", "log-udp"], "the log bridge needs more RAM than `constrained` leaves"),
+	(&["constrained", "net-ble"], "BLE needs more RAM than `constrained` leaves"),
+	(&["constrained", "ota"], "updates need more RAM than `constrained` leaves"),
+	(&["direct-boot", "ota"], "direct boot has no bootloader to switch to the updated firmware"),
+];
+/// Features that only work together with one of some other features.
+const ONLY_WITH: &[(&str, &[&str])] = &[
+	("direct-boot", &["mcu-esp32c3"]),
+	("nrf-boot-none", &["mcu-nrf52840", "mcu-nrf52832"]),
+	("nrf-boot-mbr", &["mcu-nrf52840", "mcu-nrf52832"]),
+	("nrf-boot-s132", &["mcu-nrf52840", "mcu-nrf52832"]),
+	("nrf-boot-s140", &["mcu-nrf52840", "mcu-nrf52832"]),
+	("log-udp", &["net-wifi"]),
+	("log-udp", &["log-uart", "log-usb-serial"]),
+	("ota", &["mcu-esp32", "mcu-esp32c3", "mcu-esp32s3"]),
+	("ota", &["net-wifi"]),
+];
+/// Combinations that build, but probably aren't what was intended.
+#[rustfmt::skip]
+const SUSPICIOUS: &[(&[&str], &str)] = &[
+	(&["imu-bno055", "fusion-dcm"], "the BNO055 fuses on-chip, so `fusion-dcm` is unused"),
+	(&["imu-bno055", "fusion-budget"], "the BNO055 fuses on-chip, so `fusion-budget` is unused"),
+	(&["imu-bno055", "fusion-fixed"], "the BNO055 fuses on-chip, so `fusion-fixed` is unused"),
+	(&["imu-bno055", "fusion-vqf"], "the BNO055 fuses on-chip, so `fusion-vqf` is unused"),
+	(&["imu-bno055", "fusion-external"], "the BNO055 fuses on-chip, so `fusion-external` is unused"),
+	(&["imu-bno08x", "fusion-dcm"], "the BNO08x fuses on-chip, so `fusion-dcm` is unused"),
+	(&["imu-bno08x", "fusion-budget"], "the BNO08x fuses on-chip, so `fusion-budget` is unused"),
+	(&["imu-bno08x", "fusion-fixed"], "the BNO08x fuses on-chip, so `fusion-fixed` is unused"),
+	(&["imu-bno08x", "fusion-vqf"], "the BNO08x fuses on-chip, so `fusion-vqf` is unused"),
+	(&["imu-bno08x", "fusion-external"], "the BNO08x fuses on-chip, so `fusion-external` is unused"),
+	(&["imu-mpu6050-raw", "fusion-stubbed"], "`fusion-stubbed` ignores the readings of `imu-mpu6050-raw`, so the rotation never changes"),
+	(&["imu-bmi160", "fusion-stubbed"], "`fusion-stubbed` ignores the readings of `imu-bmi160`, so the rotation never changes"),
+	(&["bus-dma", "mcu-esp32"], "the esp32 HAL has no async I2C yet, so `bus-dma` falls back to blocking reads"),
+	(&["bus-dma", "mcu-esp32c3"], "the esp32c3 HAL has no async I2C yet, so `bus-dma` falls back to blocking reads"),
+	(&["bus-dma", "mcu-esp32s3"], "the esp32s3 HAL has no async I2C yet, so `bus-dma` falls back to blocking reads"),
+	(&["bus-dma", "imu-bmi160"], "the BMI160 driver crate only does blocking reads, so `bus-dma` is unused"),
+	(&["bus-dma", "imu-mpu6050"], "the MPU-6050 DMP driver crate only does blocking reads, so `bus-dma` is unused"),
+];
+
+/// Whether a cargo feature of this crate is enabled.
+fn feature(name: &str) -> bool {
+	let var = format!("CARGO_FEATURE_{}", name.to_uppercase().replace('-', "_"));
+	env::var_os(var).is_some()
+}
+
+/// Errors for combinations of features that can't work, and warns about ones that
+/// are probably mistakes. The mutually exclusive groups are checked separately by
+/// `mandatory_and_unique!`.
+fn check_feature_combos() -> Result<()> {
+	let mut errors = Vec::new();
+	for (combo, reason) in UNSUPPORTED {
+		if combo.iter().all(|f| feature(f)) {
+			errors.push(format!("`{}`: {reason}", combo.join("` + `")));
+		}
+	}
+	for (f, others) in ONLY_WITH {
+		if feature(f) && !others.iter().any(|o| feature(o)) {
+			errors.push(format!("`{f}` only works with `{}`", others.join("` or `")));
+		}
+	}
+	for (combo, reason) in SUSPICIOUS {
+		if combo.iter().all(|f| feature(f)) {
+			println!("cargo:warning={reason}");
+		}
+	}
+	if errors.is_empty() {
+		return Ok(());
+	}
+	Err(eyre!(
+		"Unsupported combination of features:\n  {}\nSee docs/Building.md for the \
+		 presets that are known to work.",
+		errors.join("\n  ")
+	))
+}
+
+/// Warns about Wi-Fi builds that can never connect, because they have no network
+/// and no serial console to be given one with `SET WIFI`.
+fn check_wifi_provisioning() {
+	let has_console = feature("console")
+		&& feature("log-usb-serial")
+		&& (feature("mcu-esp32c3") || feature("mcu-esp32s3"));
+	if feature("net-wifi") && env::var_os("SSID").is_none() && !has_console {
+		println!(
+			"cargo:warning=without `SSID` the tracker waits to be given a Wi-Fi network \
+			 over the serial console, which needs `console` and `log-usb-serial` on the \
+			 esp32c3 or esp32s3"
+		);
+	}
+}
+
+/// Passes on the key that updates have to be signed with, which `ota` needs unless
+/// `OTA_UNSIGNED=1` explicitly takes updates from anyone on the network.
+fn check_ota_key() -> Result<()> {
+	if !feature("ota") {
+		return Ok(());
+	}
+	let Ok(key) = env::var("OTA_PUBLIC_KEY") else {
+		if env::var("OTA_UNSIGNED").as_deref() == Ok("1") {
+			println!(
+				"cargo:warning=with `OTA_UNSIGNED`, anyone on the network can install \
+				 firmware on the tracker"
+			);
+			return Ok(());
+		}
+		return Err(eyre!(
+			"`ota` needs the public key that updates are signed with in \
+			 `OTA_PUBLIC_KEY`, see docs/Building.md. Set `OTA_UNSIGNED=1` to take \
+			 unsigned updates instead."
+		));
+	};
+	let key = key.trim();
+	if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+		return Err(eyre!(
+			"`OTA_PUBLIC_KEY` has to be 32 bytes in hex, like `sign_firmware keygen` \
+			 prints"
+		));
+	}
+	println!("cargo:rustc-cfg=ota_signed");
+	println!("cargo:rustc-env=OTA_PUBLIC_KEY={key}");
 	Ok(())
 }
 
 #[allow(dead_code)]
-fn memoryx(memoryx: String) {
+fn memoryx(memoryx: String, storage_base: u32) {
 	#[allow(unused_variables)]
 	let layout = MemoryLayout::RAW;
 	#[cfg(feature = "nrf-boot-mbr")]
@@ -86,12 +310,20 @@ fn memoryx(memoryx: String) {
 	let layout = MemoryLayout::S140;
 	#[cfg(feature = "nrf-boot-s132")]
 	let layout = MemoryLayout::S132;
+	// The softdevice only needs RAM of its own once BLE enables it. When this is too
+	// little for its config, it logs how much it needs on boot.
+	#[cfg(feature = "net-ble")]
+	let layout = MemoryLayout {
+		sd_ram_size: BLE_SD_RAM_SIZE,
+		..layout
+	};
 
 	let memoryx = memoryx.replace(
 		"APP_CODE_BASE",
 		&format!("{:#x}", layout.sd_flash_size + layout.mbr_size),
 	);
 	let memoryx = memoryx.replace("SD_RAM_SIZE", &format!("{:#x}", layout.sd_ram_size));
+	let memoryx = memoryx.replace("STORAGE_BASE", &format!("{storage_base:#x}"));
 
 	// panic!("{}", memoryx); // for debugging
 	let out = path::PathBuf::from(env::var("OUT_DIR").unwrap());
@@ -99,6 +331,10 @@ fn memoryx(memoryx: String) {
 	println!("cargo:rustc-link-search={}", out.display());
 }
 
+/// The RAM of the softdevice with the config of `networking::ble`.
+#[allow(dead_code)]
+const BLE_SD_RAM_SIZE: usize = 0x8000;
+
 /// Describes data to fill `memory.x` with
 struct MemoryLayout {
 	mbr_size: usize,
@@ -137,7 +373,20 @@ impl MemoryLayout {
 
 #[derive(Debug, Deserialize)]
 struct BoardConfig {
+	/// Whether a second imu can be plugged in, at the alternate address of the chip.
+	#[serde(default)]
+	extension: bool,
 	pins: Pins,
+	#[serde(default)]
+	antenna: Antenna,
+	#[serde(default)]
+	storage: Storage,
+	battery: Option<Battery>,
+	led: Option<Led>,
+	display: Option<Display>,
+	#[serde(default)]
+	power: Power,
+	spi: Option<Spi>,
 }
 #[derive(Debug, Deserialize)]
 struct Pins {
@@ -147,13 +396,251 @@ struct Pins {
 	int1: String,
 	tx: String,
 	rx: String,
+	/// A push button between the pin and ground, if the board has one.
+	button: Option<String>,
+}
+/// For boards with an RF switch between an internal and an external antenna.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Antenna {
+	/// The pin that controls the RF switch, if there is one.
+	switch: Option<String>,
+	#[serde(default)]
+	select: AntennaSelect,
+	/// Whether driving `switch` high selects the external antenna.
+	#[serde(default = "default_true")]
+	external_high: bool,
+}
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AntennaSelect {
+	#[default]
+	Internal,
+	External,
+	/// Picks whichever antenna receives the access point better.
+	Auto,
+}
+/// Where the nRF52 keeps the records of `src/storage` in flash.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Storage {
+	/// The address of the first of its 7 pages, like `"0xD9000"`. Defaults to the
+	/// pages that the Adafruit bootloader keeps for user data. Boards with another
+	/// bootloader need to move them below it.
+	address: Option<String>,
+}
+impl Storage {
+	fn base(&self) -> Result<u32> {
+		let Some(address) = &self.address else {
+			return Ok(if feature("mcu-nrf52832") {
+				0x6D000
+			} else {
+				0xED000
+			});
+		};
+		if !feature("mcu-nrf52840") && !feature("mcu-nrf52832") {
+			return Err(eyre!("`storage.address` is only supported on the nRF52"));
+		}
+		let base = address
+			.trim()
+			.strip_prefix("0x")
+			.and_then(|hex| u32::from_str_radix(hex, 16).ok())
+			.ok_or_else(|| {
+				eyre!("Invalid `storage.address`, expected hex like 0xD9000")
+			})?;
+		if base % 0x1000 != 0 {
+			return Err(eyre!(
+				"`storage.address` must be at the start of a flash page"
+			));
+		}
+		Ok(base)
+	}
+}
+/// For boards that measure their battery through a voltage divider.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Battery {
+	/// The pin the divider is connected to, which has to be an ADC input.
+	pin: String,
+	/// How many times the battery voltage is higher than the voltage at the pin, like
+	/// `2.0` for two equal resistors.
+	divider: f32,
+}
+/// For boards with a status LED, see `src/led`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Led {
+	pin: String,
+	#[serde(default)]
+	kind: LedKind,
+	/// Whether driving the pin low turns a plain LED on.
+	#[serde(default)]
+	active_low: bool,
+}
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum LedKind {
+	/// A plain LED on a GPIO, which can only be on or off.
+	#[default]
+	Gpio,
+	/// A WS2812 (NeoPixel), driven over SPI.
+	Ws2812,
+}
+/// For boards with a small screen on the I2C bus of the imu, see `src/display`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Display {
+	/// Only the SSD1306 so far.
+	#[serde(default)]
+	kind: DisplayKind,
+	/// The I2C address, like `"0x3C"`, which is where most modules are.
+	#[serde(default = "default_display_address")]
+	address: String,
+	/// In pixels, 32 or 64. The width is always 128.
+	#[serde(default = "default_display_height")]
+	height: u8,
+}
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum DisplayKind {
+	/// An SSD1306 OLED, or a clone of it like the SSD1315.
+	#[default]
+	Ssd1306,
+}
+impl Display {
+	fn address(&self) -> Result<u8> {
+		self.address
+			.trim()
+			.strip_prefix("0x")
+			.and_then(|hex| u8::from_str_radix(hex, 16).ok())
+			.filter(|address| *address < 0x80)
+			.ok_or_else(|| eyre!("Invalid `display.address`, expected hex like 0x3C"))
+	}
+}
+/// For boards with the imu on SPI instead of I2C, see `src/imu/regs.rs`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spi {
+	sck: String,
+	mosi: String,
+	miso: String,
+	/// The chip select of the imu.
+	cs: String,
+	/// Defaults to 1 MHz, the most that the MPU-9250 takes for writing registers.
+	#[serde(default = "default_spi_frequency_khz")]
+	frequency_khz: u32,
+}
+/// How long the tracker has to lie still before it saves power, see `src/power.rs`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Power {
+	/// Seconds before idling, or `0` to never idle.
+	#[serde(default = "default_idle_after")]
+	idle_after: u32,
+	/// Seconds before going into deep sleep, or `0` to never sleep. Waking up needs
+	/// the interrupt pin `int0` of the imu to be connected.
+	#[serde(default)]
+	sleep_after: u32,
+}
+impl Default for Power {
+	fn default() -> Self {
+		Self {
+			idle_after: default_idle_after(),
+			sleep_after: 0,
+		}
+	}
+}
+fn default_idle_after() -> u32 {
+	10
+}
+fn default_spi_frequency_khz() -> u32 {
+	1000
+}
+fn default_display_address() -> String {
+	"0x3C".to_owned()
+}
+fn default_display_height() -> u8 {
+	64
+}
+fn default_true() -> bool {
+	true
 }
 impl BoardConfig {
 	/// Loads a board config from a file
 	fn from_file(p: &Path) -> Result<Self> {
 		let s = std::fs::read_to_string(p)
 			.wrap_err(format!("Failed to read board toml: {}", p.display()))?;
-		toml::from_str(&s).wrap_err("Failed to deserialize board toml")
+		let cfg: Self =
+			toml::from_str(&s).wrap_err("Failed to deserialize board toml")?;
+		if cfg.antenna.switch.is_none()
+			&& !matches!(cfg.antenna.select, AntennaSelect::Internal)
+		{
+			return Err(eyre!(
+				"`antenna.select` requires the board to have an `antenna.switch` pin"
+			));
+		}
+		if let Some(battery) = &cfg.battery {
+			if battery.divider.is_nan() || battery.divider < 1. {
+				return Err(eyre!("`battery.divider` must be at least 1"));
+			}
+		}
+		if let Some(led) = &cfg.led {
+			if led.kind == LedKind::Ws2812 {
+				if feature("mcu-nrf52840") || feature("mcu-nrf52832") {
+					return Err(eyre!(
+						"`led.kind = \"ws2812\"` is only supported on the ESP32 family"
+					));
+				}
+				if led.active_low {
+					return Err(eyre!("`led.active_low` only applies to plain LEDs"));
+				}
+			}
+		}
+		if let Some(display) = &cfg.display {
+			display.address()?;
+			if display.height != 32 && display.height != 64 {
+				return Err(eyre!("`display.height` must be 32 or 64"));
+			}
+			if cfg.spi.is_some() && feature("status-display") {
+				return Err(eyre!(
+					"`[display]` shares the I2C bus of the IMU, which `[spi]` boards \
+					 don't set up"
+				));
+			}
+		}
+		if let Some(spi) = &cfg.spi {
+			if !feature("imu-mpu6050-raw") {
+				return Err(eyre!("`[spi]` is only supported by `imu-mpu6050-raw`"));
+			}
+			if cfg.extension {
+				return Err(eyre!(
+					"`[spi]` only has room for the main IMU, extensions need I2C"
+				));
+			}
+			let ws2812 = cfg.led.as_ref().map(|l| l.kind) == Some(LedKind::Ws2812);
+			if ws2812 && feature("status-led") {
+				return Err(eyre!(
+					"`[spi]` and `led.kind = \"ws2812\"` both need the SPI2 peripheral"
+				));
+			}
+			if spi.frequency_khz == 0 {
+				return Err(eyre!("`spi.frequency_khz` must not be 0"));
+			}
+		}
+		let power = &cfg.power;
+		if power.sleep_after != 0 {
+			if !feature("mcu-nrf52840") && !feature("mcu-nrf52832") {
+				return Err(eyre!(
+					"`power.sleep_after` is only supported on the nRF52"
+				));
+			}
+			if power.idle_after != 0 && power.sleep_after < power.idle_after {
+				return Err(eyre!(
+					"`power.sleep_after` must not be shorter than `power.idle_after`"
+				));
+			}
+		}
+		Ok(cfg)
 	}
 	/// Gets the path to the board config, or errors if we can't pick one.
 	fn get_path() -> Result<PathBuf> {
@@ -196,5 +683,174 @@ impl BoardConfig {
 		set_var!("PIN_INT1", int1);
 		set_var!("PIN_TX", tx);
 		set_var!("PIN_RX", rx);
+		if let Some(pin) = &self.pins.button {
+			println!("cargo:rustc-cfg=button");
+			println!("cargo:rustc-env=PIN_BUTTON={pin}");
+		}
+		if self.extension {
+			println!("cargo:rustc-cfg=imu_extension");
+		}
+
+		let antenna = &self.antenna;
+		if let Some(pin) = &antenna.switch {
+			println!("cargo:rustc-cfg=antenna_switch");
+			println!("cargo:rustc-env=PIN_ANT_SWITCH={pin}");
+		}
+		println!("cargo:rustc-env=ANTENNA_SELECT={}", antenna.select as u8);
+		println!(
+			"cargo:rustc-env=ANTENNA_EXTERNAL_HIGH={}",
+			antenna.external_high as u8
+		);
+
+		if let Some(battery) = &self.battery {
+			println!("cargo:rustc-cfg=battery");
+			println!("cargo:rustc-env=PIN_BATTERY={}", battery.pin);
+			// The firmware can't parse floats at compile time.
+			println!(
+				"cargo:rustc-env=BATTERY_DIVIDER_MILLI={}",
+				(battery.divider * 1000.).round() as u32
+			);
+		}
+
+		// Boards keep their `[led]` in builds without the LED, which leave the pin
+		// alone.
+		if let Some(led) = self.led.as_ref().filter(|_| feature("status-led")) {
+			println!("cargo:rustc-cfg=led");
+			println!("cargo:rustc-env=PIN_LED={}", led.pin);
+			if led.kind == LedKind::Ws2812 {
+				println!("cargo:rustc-cfg=led_ws2812");
+			}
+			println!("cargo:rustc-env=LED_ACTIVE_LOW={}", led.active_low as u8);
+		}
+
+		// Like the LED, boards keep their `[display]` in builds without it.
+		if let Some(display) =
+			self.display.as_ref().filter(|_| feature("status-display"))
+		{
+			println!("cargo:rustc-cfg=display");
+			match display.kind {
+				DisplayKind::Ssd1306 => println!("cargo:rustc-cfg=display_ssd1306"),
+			}
+			// Checked when loading.
+			let address = display.address().unwrap();
+			println!("cargo:rustc-env=DISPLAY_ADDRESS={address}");
+			println!("cargo:rustc-env=DISPLAY_HEIGHT={}", display.height);
+		}
+
+		if let Some(spi) = &self.spi {
+			println!("cargo:rustc-cfg=spi_imu");
+			println!("cargo:rustc-env=PIN_SPI_SCK={}", spi.sck);
+			println!("cargo:rustc-env=PIN_SPI_MOSI={}", spi.mosi);
+			println!("cargo:rustc-env=PIN_SPI_MISO={}", spi.miso);
+			println!("cargo:rustc-env=PIN_SPI_CS={}", spi.cs);
+			println!("cargo:rustc-env=SPI_FREQ_KHZ={}", spi.frequency_khz);
+		}
+
+		let power = &self.power;
+		println!("cargo:rustc-env=POWER_IDLE_AFTER_S={}", power.idle_after);
+		println!("cargo:rustc-env=POWER_SLEEP_AFTER_S={}", power.sleep_after);
+		if power.sleep_after != 0 {
+			println!("cargo:rustc-cfg=deep_sleep");
+		}
+	}
+}
+
+/// Where the `log-udp` feature mirrors the logs to.
+#[derive(Debug)]
+struct LogUdpConfig {
+	addr: std::net::SocketAddrV4,
+	/// The most bytes per second to send, so that logging can't starve the tracking
+	/// data.
+	rate: u32,
+	/// Whether to start mirroring right away, instead of waiting to be enabled over
+	/// serial.
+	at_boot: bool,
+}
+impl LogUdpConfig {
+	/// Reads the config from `LOG_UDP`, which is required and looks like
+	/// `192.168.1.10:6971`, and the optional `LOG_UDP_RATE` and `LOG_UDP_AT_BOOT`.
+	fn from_env() -> Result<Self> {
+		let addr = env::var("LOG_UDP")
+			.wrap_err("The `log-udp` feature needs `LOG_UDP` to be set")?
+			.trim()
+			.parse()
+			.wrap_err("Invalid `LOG_UDP`, expected an IPv4 address and port")?;
+		let rate = match env::var("LOG_UDP_RATE") {
+			Ok(s) => s.trim().parse().wrap_err("Invalid `LOG_UDP_RATE`")?,
+			Err(_) => 4096,
+		};
+		let at_boot = match env::var("LOG_UDP_AT_BOOT") {
+			Ok(s) => s.trim().parse().wrap_err("Invalid `LOG_UDP_AT_BOOT`")?,
+			Err(_) => false,
+		};
+		Ok(Self {
+			addr,
+			rate,
+			at_boot,
+		})
+	}
+
+	/// Applies the config to cargo's environment variables
+	fn apply_to_env(&self) {
+		for (i, octet) in self.addr.ip().octets().iter().enumerate() {
+			println!("cargo:rustc-env=LOG_UDP_IP{i}={octet}");
+		}
+		println!("cargo:rustc-env=LOG_UDP_PORT={}", self.addr.port());
+		println!("cargo:rustc-env=LOG_UDP_RATE={}", self.rate);
+		println!("cargo:rustc-env=LOG_UDP_AT_BOOT={}", self.at_boot as u8);
+	}
+}
+
+/// The DiffServ code point to mark tracker packets with, from `DSCP`, or from the
+/// Wi-Fi access category in `WMM_AC`. Defaults to best effort.
+fn qos_dscp() -> Result<u8> {
+	match (env::var("DSCP"), env::var("WMM_AC")) {
+		(Ok(_), Ok(_)) => Err(eyre!("Set either `DSCP` or `WMM_AC`, not both")),
+		(Ok(s), Err(_)) => parse_dscp(&s).wrap_err("Invalid `DSCP`"),
+		(Err(_), Ok(s)) => parse_wmm_ac(&s).wrap_err("Invalid `WMM_AC`"),
+		(Err(_), Err(_)) => Ok(0),
+	}
+}
+
+/// Parses either a DSCP class name like `EF`, `AF41` or `CS5`, or a plain number.
+fn parse_dscp(s: &str) -> Result<u8> {
+	let s = s.trim().to_ascii_uppercase();
+	let dscp = match s.as_str() {
+		"BE" | "DF" => 0,
+		"EF" => 46,
+		"VA" => 44,
+		s if s.starts_with("CS") => {
+			let class: u8 = s[2..].parse().wrap_err("Invalid class selector")?;
+			if class > 7 {
+				return Err(eyre!("Class selector must be between 0 and 7"));
+			}
+			class * 8
+		}
+		s if s.starts_with("AF") && s.len() == 4 => {
+			let class = s.as_bytes()[2].wrapping_sub(b'0');
+			let drop = s.as_bytes()[3].wrapping_sub(b'0');
+			if !(1..=4).contains(&class) || !(1..=3).contains(&drop) {
+				return Err(eyre!("Assured forwarding must be between AF11 and AF43"));
+			}
+			class * 8 + drop * 2
+		}
+		s => s.parse().wrap_err("Expected a DSCP class name or number")?,
+	};
+	if dscp > 63 {
+		return Err(eyre!("DSCP must be between 0 and 63"));
+	}
+	Ok(dscp)
+}
+
+/// Parses a WMM access category, `BE`, `BK`, `VI` or `VO`, into the DSCP that
+/// RFC 8325 maps to it. `esp-wifi` doesn't let us pick the access category of the
+/// frames we send, so this only marks the DSCP that access points map to it.
+fn parse_wmm_ac(s: &str) -> Result<u8> {
+	match s.trim().to_ascii_uppercase().as_str() {
+		"BE" => Ok(0),
+		"BK" => Ok(8),
+		"VI" => Ok(34),
+		"VO" => Ok(46),
+		_ => Err(eyre!("Expected BE, BK, VI or VO")),
 	}
 }
@@ -51,6 +51,17 @@ unsafe impl defmt::Logger for Logger {
 }
 
 fn do_write(bytes: &[u8]) {
+	// Safety: only called between `acquire()` and `release()`, which hold the
+	// critical section.
+	#[cfg(feature = "tee")]
+	crate::tee::write(unsafe { critical_section::CriticalSection::new() }, bytes);
+
+	print(bytes);
+}
+
+/// Writes `bytes` to the serial port as they are. Only called with the critical
+/// section held, so that nobody else writes in between.
+pub(crate) fn print(bytes: &[u8]) {
 	// Unfortunately esp-println only lets us print &str:
 	// https://github.com/esp-rs/esp-println/issues/19
 	use core::fmt::Write;
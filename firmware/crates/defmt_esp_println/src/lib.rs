@@ -27,3 +27,12 @@
 
 #[cfg(any(feature = "uart", feature = "jtag_serial"))]
 mod esp32_all;
+
+#[cfg(any(feature = "uart", feature = "jtag_serial"))]
+pub mod raw;
+
+#[cfg(all(feature = "tee", any(feature = "uart", feature = "jtag_serial")))]
+pub mod tee;
+
+#[cfg(all(feature = "jtag_serial", any(feature = "esp32c3", feature = "esp32s3")))]
+pub mod serial_in;
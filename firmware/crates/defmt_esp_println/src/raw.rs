@@ -0,0 +1,16 @@
+//! Writes text to the serial port next to the defmt stream, for answers that a tool
+//! on the host reads without decoding defmt. It never ends up inside of a frame,
+//! and has the zero bytes that end frames before and after it, so that a defmt
+//! decoder skips over it like over a corrupt frame.
+//!
+//! It isn't copied to [`tee`](crate::tee), which only holds defmt frames.
+
+/// Writes `line` in one piece, between two zero bytes. It must not contain zero
+/// bytes itself.
+pub fn write_line(line: &[u8]) {
+	critical_section::with(|_| {
+		crate::esp32_all::print(&[0]);
+		crate::esp32_all::print(line);
+		crate::esp32_all::print(b"\n\0");
+	});
+}
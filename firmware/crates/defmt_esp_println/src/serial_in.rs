@@ -0,0 +1,35 @@
+//! Reads what the host sends over the USB-Serial-JTAG peripheral. esp-println only
+//! writes to it, so this pokes the same registers directly.
+
+#[cfg(feature = "esp32c3")]
+const FIFO_REG: usize = 0x6004_3000;
+#[cfg(feature = "esp32c3")]
+const CONF_REG: usize = 0x6004_3004;
+#[cfg(feature = "esp32s3")]
+const FIFO_REG: usize = 0x6003_8000;
+#[cfg(feature = "esp32s3")]
+const CONF_REG: usize = 0x6003_8004;
+
+/// `SERIAL_OUT_EP_DATA_AVAIL` in the conf register, set while there is data from
+/// the host.
+const DATA_AVAILABLE: u32 = 1 << 2;
+
+/// Reads up to `buf.len()` bytes that are waiting, without blocking. Returns how
+/// many bytes were read.
+pub fn read(buf: &mut [u8]) -> usize {
+	let fifo = FIFO_REG as *const u32;
+	let conf = CONF_REG as *const u32;
+	let mut n = 0;
+	while n < buf.len() {
+		// Safety: these are valid MMIO registers of the USB-Serial-JTAG peripheral,
+		// and reading the fifo only pops a byte the host sent.
+		unsafe {
+			if conf.read_volatile() & DATA_AVAILABLE == 0 {
+				break;
+			}
+			buf[n] = fifo.read_volatile() as u8;
+		}
+		n += 1;
+	}
+	n
+}
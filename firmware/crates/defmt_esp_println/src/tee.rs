@@ -0,0 +1,81 @@
+//! Keeps a copy of the encoded log output in a ring buffer, so that it can be sent
+//! somewhere else as well, like over the network.
+//!
+//! The buffer holds the raw defmt stream, so whatever is read from it can be
+//! decoded with `defmt-print` just like the serial output. When the buffer is full,
+//! new output is dropped, and the decoder picks up again at the next frame.
+
+use atomic_polyfill::{AtomicBool, AtomicU32};
+use core::cell::RefCell;
+use core::sync::atomic::Ordering;
+use critical_section::Mutex;
+
+const CAPACITY: usize = 2048;
+
+struct Ring {
+	buf: [u8; CAPACITY],
+	/// Index of the oldest byte.
+	start: usize,
+	len: usize,
+}
+
+static RING: Mutex<RefCell<Ring>> = Mutex::new(RefCell::new(Ring {
+	buf: [0; CAPACITY],
+	start: 0,
+	len: 0,
+}));
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Starts or stops copying the log output. Stopping also discards whatever wasn't
+/// read yet.
+pub fn set_enabled(enabled: bool) {
+	ENABLED.store(enabled, Ordering::Relaxed);
+	if !enabled {
+		critical_section::with(|cs| RING.borrow_ref_mut(cs).len = 0);
+	}
+}
+
+pub fn is_enabled() -> bool {
+	ENABLED.load(Ordering::Relaxed)
+}
+
+/// Takes up to `buf.len()` bytes of log output, and returns how many bytes that
+/// was.
+pub fn read(buf: &mut [u8]) -> usize {
+	critical_section::with(|cs| {
+		let mut ring = RING.borrow_ref_mut(cs);
+		let n = buf.len().min(ring.len);
+		for (i, b) in buf[..n].iter_mut().enumerate() {
+			*b = ring.buf[(ring.start + i) % CAPACITY];
+		}
+		ring.start = (ring.start + n) % CAPACITY;
+		ring.len -= n;
+		n
+	})
+}
+
+/// How many bytes of log output were dropped because the buffer was full, since
+/// the last call.
+pub fn take_dropped() -> u32 {
+	DROPPED.swap(0, Ordering::Relaxed)
+}
+
+/// Called by the logger with every chunk of output. The logger already holds the
+/// critical section.
+pub(crate) fn write(cs: critical_section::CriticalSection, bytes: &[u8]) {
+	if !is_enabled() {
+		return;
+	}
+	let mut ring = RING.borrow_ref_mut(cs);
+	let free = CAPACITY - ring.len;
+	if bytes.len() > free {
+		DROPPED.fetch_add(bytes.len() as u32, Ordering::Relaxed);
+		return;
+	}
+	for &b in bytes {
+		let end = (ring.start + ring.len) % CAPACITY;
+		ring.buf[end] = b;
+		ring.len += 1;
+	}
+}
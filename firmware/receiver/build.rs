@@ -0,0 +1,4 @@
+fn main() {
+	// Link into Espressif's radio driver blobs, like the firmware does.
+	println!("cargo:rustc-link-arg=-Tesp32c3_rom_functions.x");
+}
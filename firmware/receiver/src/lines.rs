@@ -0,0 +1,142 @@
+//! The lines that the receiver and `tools/espnow_bridge` exchange over USB serial.
+//! MAC addresses and packets are in hex, without separators.
+//!
+//! To the host, between the defmt frames, see `defmt_esp_println::raw`:
+//! - `SLMV ESPNOW READY` once ESP-NOW is up, so after every reboot.
+//! - `SLMV ESPNOW PAIR <mac>` for every pairing request that a tracker broadcasts.
+//! - `SLMV ESPNOW FRAME <mac> <packet>` for every other frame.
+//!
+//! From the host, each ending with a newline:
+//! - `ACCEPT <mac> [<slot>]` pairs with a tracker that asked to, and gives it a slot
+//!   to send in, see [`crate::Beacons`].
+//! - `SEND <mac> <packet>` sends a packet to a tracker.
+//! - `SLOTS <slots> <slot_us>` starts the beacons of a cycle of `slots` slots,
+//!   `slot_us` microseconds each, or stops them with `SLOTS 0 0`.
+
+use crate::{Mac, MAX_FRAME_LEN};
+
+/// Starts every line to the host, so that the bridge can tell them from the logs.
+const PREFIX: &[u8] = b"SLMV ESPNOW ";
+/// `FRAME`, a MAC address and a full frame, the longest line to the host.
+const MAX_LINE_LEN: usize = PREFIX.len() + 6 + 12 + 1 + 2 * MAX_FRAME_LEN;
+/// `SEND`, a MAC address and a full frame, the longest line from the host.
+pub const MAX_COMMAND_LEN: usize = 5 + 12 + 1 + 2 * MAX_FRAME_LEN;
+
+/// What the host told us to do.
+pub enum Command<'a> {
+	Accept(Mac, Option<u8>),
+	Send(Mac, &'a [u8]),
+	Slots { slots: u8, slot_us: u16 },
+}
+
+/// Parses a line from the host, without its newline. Decodes the packet of `SEND`
+/// in place.
+pub fn parse(line: &mut [u8]) -> Option<Command<'_>> {
+	let line = match line {
+		[line @ .., b'\r'] => line,
+		line => line,
+	};
+	let (word, rest) = split_word(line);
+	match &*word {
+		b"ACCEPT" => {
+			let (mac, slot) = split_word(rest);
+			let slot = match slot {
+				[] => None,
+				slot => Some(parse_number(slot)?),
+			};
+			Some(Command::Accept(parse_mac(mac)?, slot))
+		}
+		b"SEND" => {
+			let (mac, hex) = split_word(rest);
+			let mac = parse_mac(mac)?;
+			let len = decode_in_place(hex)?;
+			Some(Command::Send(mac, &hex[..len]))
+		}
+		b"SLOTS" => {
+			let (slots, slot_us) = split_word(rest);
+			Some(Command::Slots {
+				slots: parse_number(slots)?,
+				slot_us: parse_number(slot_us)?,
+			})
+		}
+		_ => None,
+	}
+}
+
+/// Splits off everything up to the first space, and the space itself.
+fn split_word(line: &mut [u8]) -> (&mut [u8], &mut [u8]) {
+	match line.iter().position(|&b| b == b' ') {
+		Some(i) => {
+			let (word, rest) = line.split_at_mut(i);
+			(word, &mut rest[1..])
+		}
+		None => line.split_at_mut(line.len()),
+	}
+}
+
+fn parse_number<T: core::str::FromStr>(digits: &[u8]) -> Option<T> {
+	core::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+fn parse_mac(hex: &mut [u8]) -> Option<Mac> {
+	let len = decode_in_place(hex)?;
+	hex[..len].try_into().ok()
+}
+
+/// Decodes `hex` into its first half, and returns how many bytes that is.
+fn decode_in_place(hex: &mut [u8]) -> Option<usize> {
+	if hex.len() % 2 != 0 {
+		return None;
+	}
+	for i in 0..hex.len() / 2 {
+		let high = nibble(hex[2 * i])?;
+		let low = nibble(hex[2 * i + 1])?;
+		hex[i] = high << 4 | low;
+	}
+	Some(hex.len() / 2)
+}
+
+fn nibble(digit: u8) -> Option<u8> {
+	match digit {
+		b'0'..=b'9' => Some(digit - b'0'),
+		b'a'..=b'f' => Some(digit - b'a' + 10),
+		b'A'..=b'F' => Some(digit - b'A' + 10),
+		_ => None,
+	}
+}
+
+/// A line to the host, which starts with [`PREFIX`].
+pub struct Line {
+	buf: [u8; MAX_LINE_LEN],
+	len: usize,
+}
+impl Line {
+	pub fn new() -> Self {
+		let mut line = Self {
+			buf: [0; MAX_LINE_LEN],
+			len: 0,
+		};
+		line.push(PREFIX);
+		line
+	}
+
+	/// Appends `bytes`, or as many of them as still fit.
+	pub fn push(&mut self, bytes: &[u8]) -> &mut Self {
+		let n = bytes.len().min(MAX_LINE_LEN - self.len);
+		self.buf[self.len..][..n].copy_from_slice(&bytes[..n]);
+		self.len += n;
+		self
+	}
+
+	pub fn push_hex(&mut self, bytes: &[u8]) -> &mut Self {
+		const DIGITS: &[u8; 16] = b"0123456789abcdef";
+		for &b in bytes {
+			self.push(&[DIGITS[usize::from(b >> 4)], DIGITS[usize::from(b & 0xf)]]);
+		}
+		self
+	}
+
+	pub fn write(&self) {
+		defmt_esp_println::raw::write_line(&self.buf[..self.len]);
+	}
+}
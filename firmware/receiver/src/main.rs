@@ -0,0 +1,214 @@
+//! The receiver of trackers built with `net-espnow`: an ESP32-C3 plugged into the
+//! computer that runs the server. It hands every ESP-NOW frame of its trackers to
+//! `tools/espnow_bridge` over USB serial, which sends them on to the server over
+//! UDP, and sends the server's packets back to the trackers the same way.
+//!
+//! The receiver itself keeps no state: the bridge decides which trackers to pair
+//! with and which slots they get, and ESP-NOW peers are added again on demand after
+//! a reboot. The lines it exchanges with the bridge are described in [`lines`].
+
+#![no_std]
+#![no_main]
+#![deny(unsafe_op_in_unsafe_fn)]
+
+mod lines;
+
+use defmt::{debug, info, warn};
+use defmt_esp_println::serial_in;
+use esp32c3_hal::{
+	clock::{ClockControl, CpuClock},
+	prelude::*,
+	systimer::SystemTimer,
+	timer::TimerGroup,
+	Rng, Rtc,
+};
+use esp_wifi::esp_now::{EspNow, PeerInfo, ReceivedData, BROADCAST_ADDRESS};
+use riscv_rt::entry;
+
+use defmt_esp_println as _;
+use panic_defmt as _;
+
+use self::lines::{Command, Line};
+
+pub type Mac = [u8; 6];
+
+/// The most that fits into one ESP-NOW frame.
+pub const MAX_FRAME_LEN: usize = 250;
+/// Broadcast by trackers that aren't paired yet, the same as `REQUEST` of
+/// `firmware/src/networking/espnow/pairing.rs`.
+const PAIRING_REQUEST: &[u8] = b"SLVR PAIR?";
+/// What we answer a tracker with that the bridge accepted, like `ACCEPT` there.
+const PAIRING_ACCEPT: &[u8] = b"SLVR PAIR!";
+/// Broadcast at the start of every cycle, the same as `BEACON` of
+/// `firmware/src/networking/espnow/tdma.rs`, which describes the schedule.
+const BEACON: &[u8] = b"SLVR SYNC";
+
+#[entry]
+fn main() -> ! {
+	let p = esp32c3_hal::pac::Peripherals::take().unwrap();
+
+	let system = p.SYSTEM.split();
+	// The ESP-Wifi module requires 160MHz for cpu clock speeed
+	let clocks =
+		ClockControl::configure(system.clock_control, CpuClock::Clock160MHz).freeze();
+
+	// Disable the RTC and TIMG watchdog timers
+	let mut rtc = Rtc::new(p.RTC_CNTL);
+	let mut wdt0 = TimerGroup::new(p.TIMG0, &clocks).wdt;
+	let mut wdt1 = TimerGroup::new(p.TIMG1, &clocks).wdt;
+	rtc.rwdt.disable();
+	rtc.swd.disable();
+	wdt0.disable();
+	wdt1.disable();
+
+	esp_wifi::init_heap();
+	let systimer = SystemTimer::new(p.SYSTIMER);
+	esp_wifi::initialize(systimer.alarm0, Rng::new(p.RNG), &clocks)
+		.expect("failed to initialize esp-wifi");
+	let mut esp_now = esp_wifi::esp_now::esp_now()
+		.initialize()
+		.expect("Failed to initialize ESP-NOW");
+	info!("Initialized ESP-NOW");
+	Line::new().push(b"READY").write();
+
+	// Nothing else runs here, so unlike the trackers we can poll without pause.
+	let mut input = [0; lines::MAX_COMMAND_LEN + 2];
+	let mut len = 0;
+	let mut beacons = None;
+	loop {
+		if let Some(beacons) = &mut beacons {
+			beacons.poll(&mut esp_now);
+		}
+		while let Some(frame) = esp_now.receive() {
+			forward(&frame);
+		}
+
+		len += serial_in::read(&mut input[len..]);
+		while let Some(end) = input[..len].iter().position(|&b| b == b'\n') {
+			match lines::parse(&mut input[..end]) {
+				Some(command) => run(&mut esp_now, &mut beacons, command),
+				None => warn!("Ignoring an invalid line from the host"),
+			}
+			input.copy_within(end + 1..len, 0);
+			len -= end + 1;
+		}
+		if len == input.len() {
+			warn!("Ignoring a line from the host that is too long");
+			len = 0;
+		}
+	}
+}
+
+/// Hands a frame that a tracker sent to the host.
+fn forward(frame: &ReceivedData) {
+	let tracker = frame.info.src_address;
+	let data = frame.get_data();
+	// Of another receiver nearby.
+	if data.starts_with(BEACON) {
+		return;
+	}
+	let mut line = Line::new();
+	if data == PAIRING_REQUEST {
+		line.push(b"PAIR ").push_hex(&tracker);
+	} else {
+		line.push(b"FRAME ")
+			.push_hex(&tracker)
+			.push(b" ")
+			.push_hex(data);
+	}
+	line.write();
+}
+
+fn run(esp_now: &mut EspNow, beacons: &mut Option<Beacons>, command: Command) {
+	let mut accept = [0; PAIRING_ACCEPT.len() + 1];
+	let (tracker, data) = match command {
+		Command::Accept(tracker, slot) => {
+			info!("Pairing with {=[u8]:02x} in slot {}", tracker, slot);
+			accept[..PAIRING_ACCEPT.len()].copy_from_slice(PAIRING_ACCEPT);
+			let len = match slot {
+				Some(slot) => {
+					accept[PAIRING_ACCEPT.len()] = slot;
+					accept.len()
+				}
+				None => PAIRING_ACCEPT.len(),
+			};
+			(tracker, &accept[..len])
+		}
+		Command::Send(tracker, packet) => (tracker, packet),
+		Command::Slots { slots, slot_us } => {
+			*beacons = Beacons::new(slots, slot_us);
+			match beacons {
+				Some(_) => info!("Cycling through {} slots of {} us", slots, slot_us),
+				None => info!("Not scheduling the trackers"),
+			}
+			add_peer(esp_now, BROADCAST_ADDRESS);
+			return;
+		}
+	};
+	add_peer(esp_now, tracker);
+	if let Err(e) = esp_now.send(&tracker, data) {
+		debug!("Failed to send: {}", defmt::Debug2Format(&e));
+	}
+}
+
+/// Marks the start of every cycle of slots that the trackers send in. What we send
+/// to the trackers doesn't wait for a slot, it is rare enough that the retries of
+/// ESP-NOW take care of the collisions.
+pub struct Beacons {
+	slots: u8,
+	slot_us: u16,
+	/// When the next cycle starts, in ticks of the system timer.
+	next: u64,
+}
+impl Beacons {
+	fn new(slots: u8, slot_us: u16) -> Option<Self> {
+		(slots > 0 && slot_us > 0).then(|| Self {
+			slots,
+			slot_us,
+			next: SystemTimer::now(),
+		})
+	}
+
+	fn cycle_ticks(&self) -> u64 {
+		u64::from(self.slots) * u64::from(self.slot_us) * SystemTimer::TICKS_PER_SECOND
+			/ 1_000_000
+	}
+
+	/// Broadcasts the beacon if the next cycle started, followed by how many slots
+	/// there are and how long each is.
+	fn poll(&mut self, esp_now: &mut EspNow) {
+		let now = SystemTimer::now();
+		if now < self.next {
+			return;
+		}
+		let mut frame = [0; BEACON.len() + 3];
+		frame[..BEACON.len()].copy_from_slice(BEACON);
+		frame[BEACON.len()] = self.slots;
+		frame[BEACON.len() + 1..].copy_from_slice(&self.slot_us.to_le_bytes());
+		if let Err(e) = esp_now.send(&BROADCAST_ADDRESS, &frame) {
+			debug!("Failed to send the beacon: {}", defmt::Debug2Format(&e));
+		}
+		// From when the cycle should have started, so that the trackers don't see our
+		// polling as drift. Unless we fell behind by a whole cycle.
+		self.next += self.cycle_ticks();
+		if self.next <= now {
+			self.next = now + self.cycle_ticks();
+		}
+	}
+}
+
+fn add_peer(esp_now: &mut EspNow, mac: Mac) {
+	if esp_now.peer_exists(&mac).unwrap_or(false) {
+		return;
+	}
+	let peer = PeerInfo {
+		peer_address: mac,
+		lmk: None,
+		// Whatever channel the radio is on, which the trackers have to match.
+		channel: None,
+		encrypt: false,
+	};
+	if let Err(e) = esp_now.add_peer(peer) {
+		warn!("Failed to add ESP-NOW peer: {}", defmt::Debug2Format(&e));
+	}
+}
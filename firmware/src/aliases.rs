@@ -6,6 +6,7 @@ pub mod ඞ {
 	pub use esp32_hal::Delay as DelayConcrete;
 
 	pub type I2cConcrete<'a> = esp32_hal::i2c::I2C<esp32_hal::pac::I2C0>;
+	pub type SpiConcrete<'a> = esp32_hal::spi::Spi<esp32_hal::pac::SPI2>;
 
 	pub type BbqPeripheral<'a> = ();
 }
@@ -16,6 +17,18 @@ pub mod ඞ {
 	pub use esp32c3_hal::Delay as DelayConcrete;
 
 	pub type I2cConcrete<'a> = esp32c3_hal::i2c::I2C<esp32c3_hal::pac::I2C0>;
+	pub type SpiConcrete<'a> = esp32c3_hal::spi::Spi<esp32c3_hal::pac::SPI2>;
+
+	pub type BbqPeripheral<'a> = ();
+}
+
+#[cfg(feature = "mcu-esp32s3")]
+pub mod ඞ {
+	pub use esp32s3_hal::ehal;
+	pub use esp32s3_hal::Delay as DelayConcrete;
+
+	pub type I2cConcrete<'a> = esp32s3_hal::i2c::I2C<esp32s3_hal::pac::I2C0>;
+	pub type SpiConcrete<'a> = esp32s3_hal::spi::Spi<esp32s3_hal::pac::SPI2>;
 
 	pub type BbqPeripheral<'a> = ();
 }
@@ -26,18 +39,20 @@ pub mod ඞ {
 
 	pub type I2cConcrete<'a> =
 		embassy_nrf::twim::Twim<'a, embassy_nrf::peripherals::TWISPI0>;
+	pub type SpiConcrete<'a> =
+		embassy_nrf::spim::Spim<'a, embassy_nrf::peripherals::SPI2>;
 
 	pub type UartConcrete<'a> =
 		embassy_nrf::uarte::Uarte<'a, embassy_nrf::peripherals::UARTE0>;
 
-	#[cfg(feature = "mcu-nrf52840")]
+	#[cfg(all(feature = "mcu-nrf52840", not(softdevice)))]
 	pub type UsbDriverConcrete<'a> = embassy_nrf::usb::Driver<
 		'a,
 		embassy_nrf::peripherals::USBD,
 		embassy_nrf::usb::PowerUsb,
 	>;
 
-	#[cfg(feature = "mcu-nrf52832")]
+	#[cfg(any(feature = "mcu-nrf52832", softdevice))]
 	pub type UsbDriverConcrete<'a> = ();
 
 	#[cfg(all(bbq, feature = "log-usb-serial"))]
@@ -48,6 +63,12 @@ pub mod ඞ {
 	pub type BbqPeripheralConcrete<'a> = ();
 }
 
+/// The bus that the imus are on, which is I2C unless the board has an `[spi]` table.
+#[cfg(not(spi_imu))]
+pub type ImuBusConcrete<'a> = ඞ::I2cConcrete<'a>;
+#[cfg(spi_imu)]
+pub type ImuBusConcrete<'a> = crate::imu::regs::SpiRegisters<ඞ::SpiConcrete<'a>>;
+
 pub trait I2c:
 	embedded_hal::blocking::i2c::Write<Error = <Self as I2c>::Error>
 	+ embedded_hal::blocking::i2c::WriteRead<Error = <Self as I2c>::Error>
@@ -65,6 +86,45 @@ impl<
 	type Error = E;
 }
 
+/// Reads `buf.len()` registers starting at `reg` in one transaction, which is how
+/// the drivers get their samples. With `bus-dma` on the nRF52, the TWIM does this
+/// with EasyDMA while the CPU is free for other tasks. Everywhere else it falls back
+/// to a blocking read, until the HALs there have async I2C. The drivers read through
+/// [`crate::imu::bus::SharedBus`], which goes ahead of the other users of the bus.
+pub trait BurstRead: I2c {
+	async fn burst_read(
+		&mut self,
+		address: u8,
+		reg: u8,
+		buf: &mut [u8],
+	) -> Result<(), <Self as I2c>::Error>;
+}
+#[cfg(not(all(mcu_f_nrf52, feature = "bus-dma")))]
+impl BurstRead for ඞ::I2cConcrete<'_> {
+	async fn burst_read(
+		&mut self,
+		address: u8,
+		reg: u8,
+		buf: &mut [u8],
+	) -> Result<(), <Self as I2c>::Error> {
+		// The blocking one, the nRF52 HAL also has an async `write_read`.
+		embedded_hal::blocking::i2c::WriteRead::write_read(self, address, &[reg], buf)
+	}
+}
+#[cfg(all(mcu_f_nrf52, feature = "bus-dma"))]
+impl BurstRead for ඞ::I2cConcrete<'_> {
+	async fn burst_read(
+		&mut self,
+		address: u8,
+		reg: u8,
+		buf: &mut [u8],
+	) -> Result<(), <Self as I2c>::Error> {
+		// EasyDMA can only read from RAM, which is where `reg` is.
+		let reg = [reg];
+		embassy_nrf::twim::Twim::write_read(self, address, &reg, buf).await
+	}
+}
+
 pub trait Delay:
 	embedded_hal::blocking::delay::DelayMs<u8> + embedded_hal::blocking::delay::DelayMs<u32>
 {
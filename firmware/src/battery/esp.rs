@@ -0,0 +1,22 @@
+extern crate alloc;
+
+use alloc::boxed::Box;
+
+/// The pin voltage in mV that reads as the largest value, at the 11 dB attenuation
+/// we use. Without the calibration in eFuse this is only roughly right.
+#[cfg(feature = "mcu-esp32c3")]
+const FULL_SCALE_MV: u32 = 2500;
+#[cfg(not(feature = "mcu-esp32c3"))]
+const FULL_SCALE_MV: u32 = 3100;
+const MAX_READING: u32 = (1 << 12) - 1;
+
+/// Takes a raw 12 bit reading. A closure, since the pins of the HALs each have their
+/// own type.
+pub type Adc = Box<dyn FnMut() -> Option<u16> + Send>;
+
+/// Reads the voltage at the pin in mV.
+pub async fn read_millivolts(adc: &mut Adc) -> Option<u32> {
+	// A conversion only takes microseconds, so it is fine to block for it.
+	let raw = adc()?;
+	Some(u32::from(raw) * FULL_SCALE_MV / MAX_READING)
+}
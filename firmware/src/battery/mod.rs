@@ -0,0 +1,177 @@
+//! Measures the battery on boards that have it on an ADC pin through a voltage
+//! divider, configured with `[battery]` in the board toml, and reports it to the
+//! server.
+//!
+//! Single readings of the ADC are noisy, and sag whenever the radio transmits, so
+//! they are smoothed before being turned into a percentage. A LiPo's voltage isn't
+//! linear in how full it is, so the percentage comes from a typical discharge curve
+//! instead.
+//!
+//! Near empty, the sag while transmitting can pull the battery low enough for the
+//! regulator to drop out, which resets the MCU in the middle of a session. So a
+//! single reading that sags that far makes the tracker back off, sending less until
+//! the battery recovers. See [`Backoff`].
+
+#[cfg(mcu_f_esp32)]
+#[path = "esp.rs"]
+mod ඞ;
+
+#[cfg(mcu_f_nrf52)]
+#[path = "nrf.rs"]
+mod ඞ;
+
+pub use self::ඞ::Adc;
+
+use defmt::{debug, info, warn};
+use embassy_executor::task;
+use embassy_time::{Duration, Timer};
+
+use crate::events::{self, Event, EventBus};
+use crate::utils::parse_u32;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// How many samples go into each report.
+const SAMPLES_PER_REPORT: u32 = 10;
+/// How much each sample moves the average.
+const SMOOTHING: f32 = 0.2;
+/// The percentage below which the battery counts as low.
+const LOW_PERCENT: u8 = 15;
+/// How far the battery has to recover above [`LOW_PERCENT`] before it can be low
+/// again, so that the sag while transmitting doesn't keep crossing it.
+const LOW_HYSTERESIS: u8 = 5;
+/// A single reading below this, in mV, means that the battery sags close to where
+/// the regulator drops out while transmitting.
+const SAG_MV: f32 = 3450.;
+/// The smoothed voltage in mV has to stay above this for [`RECOVER_SAMPLES`] before
+/// the tracker stops backing off.
+const RECOVER_MV: f32 = 3600.;
+const RECOVER_SAMPLES: u32 = 30;
+/// How many times the battery voltage is higher than the voltage at the pin.
+const DIVIDER: f32 = parse_u32(env!("BATTERY_DIVIDER_MILLI")) as f32 / 1000.;
+
+/// The voltage of a typical LiPo cell in mV, and how full it is at that voltage,
+/// from full to empty.
+const DISCHARGE_CURVE: [(f32, f32); 12] = [
+	(4200., 100.),
+	(4100., 90.),
+	(4000., 78.),
+	(3950., 70.),
+	(3900., 62.),
+	(3850., 53.),
+	(3800., 40.),
+	(3750., 25.),
+	(3700., 13.),
+	(3650., 7.),
+	(3500., 3.),
+	(3300., 0.),
+];
+
+/// How full the battery is from 0 to 100, at `millivolts`.
+fn percent(millivolts: f32) -> u8 {
+	let (full, _) = DISCHARGE_CURVE[0];
+	if millivolts >= full {
+		return 100;
+	}
+	for pair in DISCHARGE_CURVE.windows(2) {
+		let [(hi_mv, hi_pct), (lo_mv, lo_pct)] = [pair[0], pair[1]];
+		if millivolts >= lo_mv {
+			let t = (millivolts - lo_mv) / (hi_mv - lo_mv);
+			return (lo_pct + t * (hi_pct - lo_pct)) as u8;
+		}
+	}
+	0
+}
+
+/// Whether the tracker should send less, to keep the battery from sagging into a
+/// brown-out.
+struct Backoff {
+	active: bool,
+	/// How many samples in a row were above [`RECOVER_MV`] while backing off.
+	recovered: u32,
+}
+impl Backoff {
+	/// Looks at a raw and a smoothed reading, in mV. Returns whether the tracker
+	/// should back off, if that changed.
+	fn update(&mut self, millivolts: f32, smoothed: f32) -> Option<bool> {
+		if millivolts < SAG_MV {
+			self.recovered = 0;
+			return (!self.active).then(|| {
+				self.active = true;
+				true
+			});
+		}
+		if !self.active {
+			return None;
+		}
+		self.recovered = if smoothed >= RECOVER_MV {
+			self.recovered + 1
+		} else {
+			0
+		};
+		(self.recovered >= RECOVER_SAMPLES).then(|| {
+			self.active = false;
+			false
+		})
+	}
+}
+
+#[task]
+pub async fn battery_task(events: &'static EventBus, mut adc: Adc) -> ! {
+	debug!("Battery task");
+	let mut average: Option<f32> = None;
+	let mut low = false;
+	let mut samples = 0;
+	let mut backoff = Backoff {
+		active: false,
+		recovered: 0,
+	};
+	loop {
+		Timer::after(SAMPLE_INTERVAL).await;
+		let Some(pin_mv) = ඞ::read_millivolts(&mut adc).await else {
+			warn!("Failed to read the battery voltage");
+			continue;
+		};
+		let millivolts = pin_mv as f32 * DIVIDER;
+		let smoothed = match average {
+			Some(a) => a + SMOOTHING * (millivolts - a),
+			None => millivolts,
+		};
+		average = Some(smoothed);
+		if let Some(active) = backoff.update(millivolts, smoothed) {
+			let millivolts = millivolts as u16;
+			if active {
+				warn!("Battery sagged to {} mV, backing off", millivolts);
+				crate::diag::tx_backoff();
+			} else {
+				info!("Battery recovered to {} mV", millivolts);
+			}
+			events::publish(events, Event::TxBackoff { active, millivolts });
+		}
+		samples += 1;
+		if samples < SAMPLES_PER_REPORT {
+			continue;
+		}
+		samples = 0;
+
+		let percent = percent(smoothed);
+		let millivolts = smoothed as u16;
+		crate::diag::battery(millivolts, percent);
+		let was_low = low;
+		if !low && percent < LOW_PERCENT {
+			low = true;
+		} else if low && percent >= LOW_PERCENT + LOW_HYSTERESIS {
+			low = false;
+		}
+		events::publish(
+			events,
+			Event::Battery {
+				millivolts,
+				percent,
+				low,
+			},
+		);
+		if low && !was_low {
+			events::publish(events, Event::BatteryLow { percent });
+		}
+	}
+}
@@ -0,0 +1,17 @@
+use embassy_nrf::saadc::Saadc;
+
+/// The pin voltage in mV that reads as the largest value, with the default gain of
+/// 1/6 and the internal 0.6 V reference.
+const FULL_SCALE_MV: i32 = 3600;
+/// The default resolution is 12 bits.
+const MAX_READING: i32 = 1 << 12;
+
+pub type Adc = Saadc<'static, 1>;
+
+/// Reads the voltage at the pin in mV.
+pub async fn read_millivolts(adc: &mut Adc) -> Option<u32> {
+	let mut buf = [0; 1];
+	adc.sample(&mut buf).await;
+	// Slightly negative readings are just noise around zero.
+	Some((i32::from(buf[0]).max(0) * FULL_SCALE_MV / MAX_READING) as u32)
+}
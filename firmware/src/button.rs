@@ -0,0 +1,68 @@
+//! The button on boards that have one, configured with `button` in the `[pins]`
+//! table of the board toml. It is wired to ground, so pressed reads low.
+//!
+//! A short press calibrates the gyro bias, and holding it for
+//! [`LONG_PRESS`] starts the six-position accelerometer calibration. Holding it for
+//! [`FORGET_NETWORK_PRESS`] forgets the stored Wi-Fi credentials instead, see
+//! `credentials`, or with ESP-NOW the receiver, see `pairing`.
+
+extern crate alloc;
+
+use crate::events::{self, Event, EventBus};
+use crate::imu::calibration::Routine;
+
+use alloc::boxed::Box;
+use core::convert::Infallible;
+use defmt::debug;
+use embassy_executor::task;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_hal::digital::v2::InputPin;
+
+/// Also debounces the button, since a bounce is over by the next poll.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+const LONG_PRESS: Duration = Duration::from_secs(2);
+#[cfg(any(feature = "net-wifi", feature = "net-espnow"))]
+const FORGET_NETWORK_PRESS: Duration = Duration::from_secs(10);
+
+pub type Button = Box<dyn InputPin<Error = Infallible> + Send>;
+
+#[task]
+pub async fn button_task(events: &'static EventBus, button: Button) {
+	let mut pressed_since = None;
+	loop {
+		Timer::after(POLL_INTERVAL).await;
+		// Can't fail, the error type is `Infallible`.
+		let pressed = button.is_low().unwrap_or(false);
+		match (pressed, pressed_since) {
+			(true, None) => pressed_since = Some(Instant::now()),
+			(false, Some(since)) => {
+				pressed_since = None;
+				let held = Instant::now() - since;
+				#[cfg(feature = "net-wifi")]
+				if held >= FORGET_NETWORK_PRESS {
+					crate::networking::wifi::credentials::clear();
+					continue;
+				}
+				#[cfg(feature = "net-espnow")]
+				if held >= FORGET_NETWORK_PRESS {
+					crate::networking::espnow::pairing::forget();
+					continue;
+				}
+				let routine = if held >= LONG_PRESS {
+					Routine::Accel
+				} else {
+					Routine::GyroBias
+				};
+				debug!("Button released, calibrating with {}", routine);
+				events::publish(
+					events,
+					Event::CalibrationRequested {
+						sensor_id: 0,
+						routine,
+					},
+				);
+			}
+			_ => (),
+		}
+	}
+}
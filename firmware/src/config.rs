@@ -0,0 +1,195 @@
+//! Settings that the server can change at runtime with `CbPacket::SetConfig`,
+//! instead of them being constants of the build. They are stored in `storage`, so
+//! they survive reboots, and keep their defaults until they are first changed.
+//!
+//! The control task owns the settings and answers every change with
+//! `SbPacket::ConfigStatus`. It publishes the ones that other tasks apply as an
+//! `Event::ConfigChanged`, and applies the TX power itself, through `tx_power`.
+
+use defmt::{info, warn, Format};
+use embassy_time::Duration;
+use firmware_protocol::{
+	CONFIG_FUSION, CONFIG_FUSION_ACCEL_GAIN, CONFIG_LED, CONFIG_OUT_OF_RANGE,
+	CONFIG_ROTATION_RATE, CONFIG_TX_POWER, CONFIG_UNKNOWN_KEY, CONFIG_UNSUPPORTED,
+};
+
+use crate::imu::Algorithm;
+use crate::networking::tx_power;
+use crate::storage::{self, Slot};
+
+/// Bumped whenever the stored format changes, so that old records are ignored.
+/// Settings that were added later are appended instead, and keep their defaults
+/// when loading a record from before them.
+const VERSION: u8 = 1;
+/// The version, the rotation rate, and the accelerometer gain.
+const V1_LEN: usize = 1 + 2 + 2;
+/// And whether the LED is on, the fusion algorithm, and the TX power.
+const STORED_LEN: usize = V1_LEN + 3;
+
+/// More than any imu delivers, so anything above it is probably a mistake.
+const MAX_ROTATION_RATE_HZ: u16 = 1000;
+/// The accelerometer gain is in thousandths, from a tenth to ten times the default.
+const DEFAULT_ACCEL_GAIN: u16 = 1000;
+const ACCEL_GAINS: core::ops::RangeInclusive<u16> = 100..=10_000;
+/// The fusers that implement `Fuser::set_accel_gain`. Imus that fuse on-chip ignore
+/// the gain, which the imu task warns about.
+#[cfg(not(feature = "fusion-external"))]
+const FUSION_TUNABLE: bool = cfg!(any(
+	feature = "fusion-vqf",
+	feature = "fusion-budget",
+	feature = "fusion-fixed"
+));
+#[cfg(feature = "fusion-external")]
+const FUSION_TUNABLE: bool =
+	<external_fuser::Fuser as fuser_api::ExternalFuser>::ACCEL_GAIN;
+/// Only boards with a status LED can turn it off.
+const HAS_LED: bool = cfg!(led);
+/// Only Wi-Fi takes a TX power, BLE keeps the default of the softdevice.
+const HAS_TX_POWER: bool = cfg!(feature = "net-wifi");
+
+/// A setting that changed, for the tasks that apply it.
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+	RotationRate { hz: u16 },
+	AccelGain { milli: u16 },
+	Led { enabled: bool },
+	Fusion { algorithm: Algorithm },
+	TxPower { quarter_dbm: i8 },
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+	/// The most rotations per second to send for each sensor, `0` for no limit.
+	rotation_rate_hz: u16,
+	/// How strongly the fusion corrects with the accelerometer, in thousandths of
+	/// its default.
+	pub accel_gain_milli: u16,
+	/// Whether the status LED shows anything, see `led`.
+	pub led: bool,
+	/// Which fuser the imus use, see `Swappable`.
+	pub fusion: Algorithm,
+	/// The most power to transmit with, in quarter dBm, see `tx_power`.
+	pub tx_power: i8,
+}
+impl Config {
+	const DEFAULT: Self = Self {
+		rotation_rate_hz: 0,
+		accel_gain_milli: DEFAULT_ACCEL_GAIN,
+		led: true,
+		fusion: Algorithm::BuiltIn,
+		tx_power: tx_power::MAX,
+	};
+
+	/// The stored settings, or the defaults if there are none.
+	pub fn load() -> Self {
+		let mut bytes = [0; STORED_LEN];
+		match storage::load(Slot::Config, &mut bytes) {
+			Ok(len @ V1_LEN..=STORED_LEN) if bytes[0] == VERSION => {
+				let c = Self::from_bytes(&bytes[..len]);
+				info!("Loaded config: {}", c);
+				return c;
+			}
+			Ok(_) => warn!("Ignoring the stored config, it is outdated"),
+			Err(storage::Error::Empty) => (),
+			Err(e) => warn!("Failed to load the config: {}", e),
+		}
+		Self::DEFAULT
+	}
+
+	pub fn store(&self) -> Result<(), storage::Error> {
+		let mut bytes = [0; STORED_LEN];
+		bytes[0] = VERSION;
+		bytes[1..3].copy_from_slice(&self.rotation_rate_hz.to_le_bytes());
+		bytes[3..5].copy_from_slice(&self.accel_gain_milli.to_le_bytes());
+		bytes[5] = self.led.into();
+		bytes[6] = self.fusion.to_protocol() as u8;
+		bytes[7] = self.tx_power as u8;
+		storage::store(Slot::Config, &bytes)
+	}
+
+	/// Takes a record of at least [`V1_LEN`] bytes.
+	fn from_bytes(bytes: &[u8]) -> Self {
+		let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+		// A build with other limits might have stored them.
+		Self {
+			rotation_rate_hz: u16_at(1).min(MAX_ROTATION_RATE_HZ),
+			accel_gain_milli: u16_at(3).clamp(*ACCEL_GAINS.start(), *ACCEL_GAINS.end()),
+			led: bytes.get(5).map_or(Self::DEFAULT.led, |&b| b != 0),
+			fusion: bytes
+				.get(6)
+				.and_then(|&b| Algorithm::from_protocol(b.into()))
+				.unwrap_or(Self::DEFAULT.fusion),
+			tx_power: bytes.get(7).map_or(Self::DEFAULT.tx_power, |&b| {
+				(b as i8).clamp(tx_power::MIN, tx_power::MAX)
+			}),
+		}
+	}
+
+	/// Changes the setting `key` to `value`, or returns the `CONFIG_*` status to
+	/// reject it with. The setting isn't stored yet.
+	pub fn set(&mut self, key: u8, value: u32) -> Result<Setting, u8> {
+		let in_range = |range: core::ops::RangeInclusive<u16>| {
+			u16::try_from(value)
+				.ok()
+				.filter(|v| range.contains(v))
+				.ok_or(CONFIG_OUT_OF_RANGE)
+		};
+		let setting = match key {
+			CONFIG_ROTATION_RATE => Setting::RotationRate {
+				hz: in_range(0..=MAX_ROTATION_RATE_HZ)?,
+			},
+			CONFIG_FUSION_ACCEL_GAIN if FUSION_TUNABLE => Setting::AccelGain {
+				milli: in_range(ACCEL_GAINS)?,
+			},
+			CONFIG_LED if HAS_LED => Setting::Led {
+				enabled: in_range(0..=1)? != 0,
+			},
+			CONFIG_FUSION => Setting::Fusion {
+				algorithm: Algorithm::from_protocol(value)
+					.ok_or(CONFIG_OUT_OF_RANGE)?,
+			},
+			CONFIG_TX_POWER if HAS_TX_POWER => Setting::TxPower {
+				// Fits, since both ends of the range do.
+				quarter_dbm: in_range(tx_power::MIN as u16..=tx_power::MAX as u16)?
+					as i8,
+			},
+			CONFIG_FUSION_ACCEL_GAIN | CONFIG_TX_POWER | CONFIG_LED => {
+				return Err(CONFIG_UNSUPPORTED)
+			}
+			_ => return Err(CONFIG_UNKNOWN_KEY),
+		};
+		match setting {
+			Setting::RotationRate { hz } => self.rotation_rate_hz = hz,
+			Setting::AccelGain { milli } => self.accel_gain_milli = milli,
+			Setting::Led { enabled } => self.led = enabled,
+			Setting::Fusion { algorithm } => self.fusion = algorithm,
+			Setting::TxPower { quarter_dbm } => self.tx_power = quarter_dbm,
+		}
+		Ok(setting)
+	}
+
+	/// The current value of the setting `key`, in the unit of the protocol. `0` for
+	/// the ones we don't have.
+	pub fn get(&self, key: u8) -> u32 {
+		match key {
+			CONFIG_ROTATION_RATE => self.rotation_rate_hz.into(),
+			CONFIG_FUSION_ACCEL_GAIN => self.accel_gain_milli.into(),
+			CONFIG_LED => self.led.into(),
+			CONFIG_FUSION => self.fusion.to_protocol(),
+			CONFIG_TX_POWER if HAS_TX_POWER => self.tx_power as u32,
+			_ => 0,
+		}
+	}
+
+	/// How long to wait between the rotations of a sensor, if at all.
+	pub fn rotation_interval(&self) -> Option<Duration> {
+		(self.rotation_rate_hz > 0).then(|| {
+			Duration::from_micros(1_000_000 / u64::from(self.rotation_rate_hz))
+		})
+	}
+}
+
+/// The accelerometer gain as a factor of the default.
+pub fn accel_gain(milli: u16) -> f32 {
+	f32::from(milli) / f32::from(DEFAULT_ACCEL_GAIN)
+}
@@ -0,0 +1,85 @@
+//! Commands typed into the USB serial console:
+//!
+//! - `DIAG` prints a hardware triage report, see `diag`.
+//! - `log udp on` and `log udp off` toggle mirroring the logs, see `log_bridge`.
+//! - `SET WIFI "<ssid>" "<password>"` and `CLEAR WIFI` provision the tracker, see
+//!   `credentials`.
+//! - `UNPAIR` forgets the ESP-NOW receiver, see `pairing`.
+//! - `STORAGE ...` reads and writes the records in flash, see `storage::console`.
+//! - `REBOOT` restarts the tracker.
+
+use defmt::{info, warn};
+use defmt_esp_println::serial_in;
+use embassy_time::{Duration, Timer};
+
+#[cfg(feature = "net-espnow")]
+use crate::networking::espnow::pairing;
+#[cfg(feature = "net-wifi")]
+use crate::networking::wifi::credentials;
+#[cfg(feature = "log-udp")]
+use crate::networking::wifi::log_bridge::{self, tee};
+use crate::storage;
+
+/// Enough for the longest command, `STORAGE WRITE` with the largest record.
+const LINE_LEN: usize = STORAGE.len() + storage::console::MAX_COMMAND_LEN;
+/// Followed by the SSID and the password.
+#[cfg(feature = "net-wifi")]
+const SET_WIFI: &[u8] = b"SET WIFI ";
+/// Followed by what to do with which record.
+const STORAGE: &[u8] = b"STORAGE ";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Applies the commands that were typed into the serial console.
+pub async fn serial_commands() -> ! {
+	let mut line = [0; LINE_LEN];
+	let mut len = 0;
+	loop {
+		Timer::after(POLL_INTERVAL).await;
+		let mut buf = [0; 32];
+		let n = serial_in::read(&mut buf);
+		for &b in &buf[..n] {
+			if b != b'\n' && b != b'\r' {
+				// Overlong lines aren't commands, so it doesn't matter that they get
+				// cut off.
+				if len < LINE_LEN {
+					line[len] = b;
+					len += 1;
+				}
+				continue;
+			}
+			match &line[..len] {
+				b"DIAG" => crate::diag::print(),
+				#[cfg(feature = "log-udp")]
+				b"log udp on" => {
+					tee::set_enabled(true);
+					info!(
+						"mirroring logs to {}:{}",
+						log_bridge::HOST,
+						log_bridge::PORT
+					);
+				}
+				#[cfg(feature = "log-udp")]
+				b"log udp off" => {
+					info!("no longer mirroring logs");
+					tee::set_enabled(false);
+				}
+				#[cfg(feature = "net-wifi")]
+				l if l.starts_with(SET_WIFI) => credentials::set(&l[SET_WIFI.len()..]),
+				#[cfg(feature = "net-wifi")]
+				b"CLEAR WIFI" => credentials::clear(),
+				#[cfg(feature = "net-espnow")]
+				b"UNPAIR" => pairing::forget(),
+				l if l.starts_with(STORAGE) => {
+					storage::console::command(&l[STORAGE.len()..])
+				}
+				b"REBOOT" => {
+					storage::flush().await;
+					crate::peripherals::reboot()
+				}
+				b"" => (),
+				other => warn!("unknown command {=[u8]:a}", other),
+			}
+			len = 0;
+		}
+	}
+}
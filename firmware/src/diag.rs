@@ -0,0 +1,229 @@
+//! A triage report for hardware builders, printed when `DIAG` is typed into the
+//! serial console. Each line looks like `diag <section>: <key>=<value>`, so reports
+//! from different builds are easy to compare.
+//!
+//! The tasks that own the hardware fill in their sections as they go, since they
+//! can't be interrupted to run tests on demand. So the I2C bus is scanned once at
+//! boot, before the IMU driver takes it over, Wi-Fi is reported as of its last
+//! scan, and flash as of the last time the calibration was loaded or stored.
+//!
+//! Without the `diag` feature nothing is recorded, the bus isn't scanned, and `DIAG`
+//! only says so.
+#![cfg_attr(not(feature = "diag"), allow(dead_code))]
+
+use core::cell::RefCell;
+
+use defmt::info;
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use firmware_protocol::{ImuType, SensorStatus};
+
+use crate::aliases::I2c;
+use crate::storage;
+
+/// The 7-bit addresses that aren't reserved.
+const I2C_ADDRESSES: core::ops::Range<u8> = 0x08..0x78;
+
+#[derive(Clone, Copy)]
+struct Imu {
+	imu_type: ImuType,
+	status: SensorStatus,
+	rate_hz: Option<u32>,
+}
+
+#[derive(Clone, Copy)]
+struct Wifi {
+	ap_count: usize,
+	/// The RSSI and channel of the configured SSID, if it was found.
+	ap: Option<(i8, u8)>,
+	ip: Option<[u8; 4]>,
+}
+
+#[derive(Clone, Copy)]
+struct Report {
+	/// Bit `n` is set if address `n` acknowledged.
+	i2c_devices: Option<u128>,
+	/// Set once the IMU driver initialized, which includes checking its chip id.
+	imu: Option<Imu>,
+	wifi: Option<Wifi>,
+	temperature: Option<f32>,
+	/// Whether a calibration is stored, or why accessing it failed.
+	calibration: Option<Result<bool, storage::Error>>,
+	/// The voltage in mV and the percentage, as of the last report to the server.
+	battery: Option<(u16, u8)>,
+	/// How often the battery sagged enough to back off since boot.
+	tx_backoffs: u32,
+}
+
+#[cfg(feature = "diag")]
+static REPORT: Mutex<CriticalSectionRawMutex, RefCell<Report>> =
+	Mutex::new(RefCell::new(Report {
+		i2c_devices: None,
+		imu: None,
+		wifi: None,
+		temperature: None,
+		calibration: None,
+		battery: None,
+		tx_backoffs: 0,
+	}));
+
+fn update(f: impl FnOnce(&mut Report)) {
+	#[cfg(feature = "diag")]
+	REPORT.lock(|r| f(&mut r.borrow_mut()));
+	#[cfg(not(feature = "diag"))]
+	let _ = f;
+}
+
+/// Probes every address on the bus with a one byte read, and records which ones
+/// answered.
+pub fn scan_i2c(i2c: &mut impl I2c) {
+	if cfg!(not(feature = "diag")) {
+		return;
+	}
+	let mut found = 0u128;
+	for addr in I2C_ADDRESSES {
+		if i2c.read(addr, &mut [0]).is_ok() {
+			found |= 1 << addr;
+		}
+	}
+	update(|r| r.i2c_devices = Some(found));
+}
+
+pub fn imu_initialized(imu_type: ImuType) {
+	update(|r| {
+		r.imu = Some(Imu {
+			imu_type,
+			status: SensorStatus::Ok,
+			rate_hz: None,
+		})
+	});
+}
+
+pub fn imu_status(status: SensorStatus) {
+	update(|r| {
+		if let Some(imu) = &mut r.imu {
+			imu.status = status;
+		}
+	});
+}
+
+pub fn imu_rate(rate_hz: u32) {
+	update(|r| {
+		if let Some(imu) = &mut r.imu {
+			imu.rate_hz = Some(rate_hz);
+		}
+	});
+}
+
+/// Records a Wi-Fi scan that saw `ap_count` access points, and the RSSI and channel
+/// of the configured one if it was among them.
+pub fn wifi_scan(ap_count: usize, ap: Option<(i8, u8)>) {
+	update(|r| {
+		let ip = r.wifi.and_then(|w| w.ip);
+		r.wifi = Some(Wifi { ap_count, ap, ip });
+	});
+}
+
+pub fn wifi_ip(ip: [u8; 4]) {
+	update(|r| {
+		if let Some(wifi) = &mut r.wifi {
+			wifi.ip = Some(ip);
+		}
+	});
+}
+
+pub fn temperature(celsius: f32) {
+	update(|r| r.temperature = Some(celsius));
+}
+
+pub fn calibration(stored: Result<bool, storage::Error>) {
+	update(|r| r.calibration = Some(stored));
+}
+
+#[cfg_attr(not(battery), allow(dead_code))]
+pub fn battery(millivolts: u16, percent: u8) {
+	update(|r| r.battery = Some((millivolts, percent)));
+}
+
+#[cfg_attr(not(battery), allow(dead_code))]
+pub fn tx_backoff() {
+	update(|r| r.tx_backoffs += 1);
+}
+
+pub fn print() {
+	#[cfg(feature = "diag")]
+	print_report();
+	#[cfg(not(feature = "diag"))]
+	info!("diag unsupported: built without the `diag` feature");
+}
+
+#[cfg(feature = "diag")]
+fn print_report() {
+	// Logging takes a while, which interrupts shouldn't have to wait for.
+	let r = REPORT.lock(|r| *r.borrow());
+	info!("diag begin");
+	match r.i2c_devices {
+		Some(found) => {
+			let mut addrs = [0u8; I2C_ADDRESSES.end as usize];
+			let mut n = 0;
+			for addr in I2C_ADDRESSES.filter(|a| found & (1 << a) != 0) {
+				addrs[n] = addr;
+				n += 1;
+			}
+			info!("diag i2c: count={} addresses={=[u8]:#04x}", n, &addrs[..n]);
+		}
+		None => info!("diag i2c: not scanned"),
+	}
+	match r.imu {
+		Some(imu) => info!(
+			"diag imu: type={} id_check=passed status={} rate_hz={}",
+			defmt::Debug2Format(&imu.imu_type),
+			defmt::Debug2Format(&imu.status),
+			imu.rate_hz,
+		),
+		None => info!("diag imu: not initialized"),
+	}
+	match r.calibration {
+		Some(Ok(true)) => info!("diag flash: calibration=stored"),
+		Some(Ok(false)) => info!("diag flash: calibration=none"),
+		Some(Err(e)) => info!("diag flash: calibration=error error={}", e),
+		None => info!("diag flash: not accessed"),
+	}
+	match r.wifi {
+		Some(Wifi {
+			ap_count,
+			ap: Some((rssi, channel)),
+			ip,
+		}) => info!(
+			"diag wifi: aps={} ssid_found=true rssi={} channel={} ip={}",
+			ap_count, rssi, channel, ip
+		),
+		Some(Wifi { ap_count, .. }) => {
+			info!("diag wifi: aps={} ssid_found=false", ap_count)
+		}
+		None => info!("diag wifi: not scanned"),
+	}
+	match r.battery {
+		Some((mv, percent)) => info!(
+			"diag battery: voltage_mv={} percent={} tx_backoffs={}",
+			mv, percent, r.tx_backoffs
+		),
+		None if cfg!(battery) => info!("diag battery: not measured"),
+		None => info!("diag battery: unsupported"),
+	}
+	match r.temperature {
+		Some(t) => info!("diag mcu: temperature_c={}", t),
+		None => info!("diag mcu: temperature_c=unknown"),
+	}
+	let heap = crate::memory::heap();
+	match crate::memory::stack() {
+		Some(stack) => info!(
+			"diag memory: heap_used={} heap_free={} stack_peak={} stack_size={}",
+			heap.used, heap.free, stack.peak, stack.size
+		),
+		None => info!(
+			"diag memory: heap_used={} heap_free={} stack_peak=unknown",
+			heap.used, heap.free
+		),
+	}
+	info!("diag end");
+}
@@ -0,0 +1,188 @@
+//! A small screen on boards with a `[display]` in their toml, so far only the
+//! SSD1306 OLED. It shares the I2C bus of the imus, see `crate::imu::bus`, and
+//! shows, one line each:
+//!
+//! - The name of the tracker, `SlimeVR` and the end of its MAC address.
+//! - The charge of the battery, on boards that measure it.
+//! - How far the connection got, or which stage of booting failed, like the LED.
+//! - The IP address, once Wi-Fi got one.
+//!
+//! The lines are only drawn again when they changed, one at a time, so that the
+//! imus wait for at most one line to be sent. Displays with 64 rows leave a blank
+//! row between the lines. The display turns off before the tracker goes into deep
+//! sleep.
+
+#[cfg(display_ssd1306)]
+mod ssd1306;
+
+use core::fmt::Write;
+use defmt::{debug, warn};
+use embassy_executor::task;
+
+use crate::events::{BootStage, Event, EventSubscriber};
+use crate::imu::ImuBus;
+
+#[cfg(display_ssd1306)]
+use self::ssd1306::{Error, Ssd1306 as Display, HEIGHT, WIDTH};
+
+const LINES: usize = 4;
+/// Characters of the 8 by 8 font in each line.
+const COLUMNS: usize = WIDTH / 8;
+/// How many pages of 8 rows each line takes.
+const PAGES_PER_LINE: u8 = HEIGHT / 8 / LINES as u8;
+
+/// One line of text, filled up with spaces.
+type Line = [u8; COLUMNS];
+const BLANK: Line = [b' '; COLUMNS];
+
+/// `args` as a line, cut off once it is full.
+fn line(args: core::fmt::Arguments) -> Line {
+	struct Writer(Line, usize);
+	impl Write for Writer {
+		fn write_str(&mut self, s: &str) -> core::fmt::Result {
+			for c in s.bytes() {
+				if let Some(slot) = self.0.get_mut(self.1) {
+					*slot = if c.is_ascii() { c } else { b'?' };
+					self.1 += 1;
+				}
+			}
+			Ok(())
+		}
+	}
+	let mut writer = Writer(BLANK, 0);
+	// The writer never fails.
+	let _ = writer.write_fmt(args);
+	writer.0
+}
+
+/// The columns that show `line`.
+fn render(line: &Line) -> [u8; WIDTH] {
+	let mut columns = [0; WIDTH];
+	for (c, columns) in line.iter().zip(columns.chunks_exact_mut(8)) {
+		// Each byte of the glyph is a row, with the leftmost pixel in the lowest bit.
+		let glyph = font8x8::legacy::BASIC_LEGACY[usize::from(c & 0x7F)];
+		for (x, column) in columns.iter_mut().enumerate() {
+			*column = glyph
+				.iter()
+				.enumerate()
+				.fold(0, |column, (y, row)| column | ((row >> x) & 1) << y);
+		}
+	}
+	columns
+}
+
+/// What the tracker is doing, as far as the display shows it.
+#[derive(Debug, Default)]
+struct Status {
+	battery_percent: Option<u8>,
+	/// The last stage of booting that failed, and hasn't succeeded since.
+	boot_failed: Option<BootStage>,
+	link_up: bool,
+	connected: bool,
+	ip: Option<[u8; 4]>,
+}
+impl Status {
+	fn update(&mut self, event: Event) {
+		match event {
+			Event::Battery { percent, .. } => self.battery_percent = Some(percent),
+			Event::BootFailed(stage) => self.boot_failed = Some(stage),
+			Event::LinkChanged { up } => {
+				if up && self.boot_failed == Some(BootStage::Network) {
+					self.boot_failed = None;
+				}
+				self.link_up = up;
+				self.connected &= up;
+				if !up {
+					self.ip = None;
+				}
+			}
+			Event::ConnectionChanged { connected } => {
+				if connected && self.boot_failed != Some(BootStage::Imu) {
+					self.boot_failed = None;
+				}
+				self.connected = connected;
+				self.link_up |= connected;
+			}
+			Event::IpAssigned(ip) => self.ip = Some(ip),
+			_ => (),
+		}
+	}
+
+	fn lines(&self, name: &Line) -> [Line; LINES] {
+		let connection = match self.boot_failed {
+			Some(BootStage::Imu) => "IMU failed",
+			Some(BootStage::Network) => "No network",
+			Some(BootStage::Server) => "No server",
+			Some(BootStage::Incompatible) => "Server too old",
+			None if self.connected => "Connected",
+			None if self.link_up => "Finding server",
+			None => "Searching",
+		};
+		let battery = match self.battery_percent {
+			Some(percent) => line(format_args!("Battery {}%", percent)),
+			None => BLANK,
+		};
+		let ip = match self.ip {
+			Some([a, b, c, d]) => line(format_args!("{}.{}.{}.{}", a, b, c, d)),
+			None => BLANK,
+		};
+		[*name, battery, line(format_args!("{}", connection)), ip]
+	}
+}
+
+/// Draws the lines that differ from `shown`, which is `None` before the display
+/// is set up. Those that were drawn before an error stay in `shown`.
+async fn draw(
+	display: &mut Display,
+	shown: &mut Option<[Line; LINES]>,
+	lines: &[Line; LINES],
+) -> Result<(), Error> {
+	let shown = match shown {
+		Some(shown) => shown,
+		None => {
+			display.init().await?;
+			shown.insert([BLANK; LINES])
+		}
+	};
+	for (i, (old, new)) in shown.iter_mut().zip(lines).enumerate() {
+		if old != new {
+			display.page(i as u8 * PAGES_PER_LINE, &render(new)).await?;
+			*old = *new;
+		}
+	}
+	Ok(())
+}
+
+/// Takes a subscriber from before the executor started, like the LED, so that
+/// the stages of booting show up.
+#[task]
+pub async fn display_task(mut events: EventSubscriber, bus: ImuBus) -> ! {
+	debug!("Display task");
+	let [.., a, b] = crate::peripherals::chip_id();
+	let name = line(format_args!("SlimeVR {:02x}{:02x}", a, b));
+	let mut display = Display::new(bus);
+	let mut status = Status::default();
+	let mut shown = None;
+	let mut failing = false;
+	loop {
+		let lines = status.lines(&name);
+		match draw(&mut display, &mut shown, &lines).await {
+			Ok(()) => failing = false,
+			Err(e) => {
+				// Like a display that isn't plugged in. Drawing sets it up again.
+				if !failing {
+					warn!("Failed to draw on the display: {}", defmt::Debug2Format(&e));
+				}
+				failing = true;
+				shown = None;
+			}
+		}
+		match events.next_message_pure().await {
+			Event::Sleeping => {
+				let _ = display.turn_off().await;
+				core::future::pending::<()>().await;
+			}
+			event => status.update(event),
+		}
+	}
+}
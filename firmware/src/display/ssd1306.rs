@@ -0,0 +1,77 @@
+//! The SSD1306 OLED, and clones of it like the SSD1315, in page addressing mode.
+//! The screen is split into pages of 8 rows, and each byte sets one column of a
+//! page, with the top pixel in the lowest bit.
+
+use embedded_hal::blocking::i2c::Write;
+
+use crate::aliases::{I2c, ImuBusConcrete};
+use crate::imu::ImuBus;
+use crate::utils::parse_u32;
+
+const ADDRESS: u8 = parse_u32(env!("DISPLAY_ADDRESS")) as u8;
+pub const HEIGHT: u8 = parse_u32(env!("DISPLAY_HEIGHT")) as u8;
+pub const WIDTH: usize = 128;
+/// Starts a transfer of commands.
+const COMMANDS: u8 = 0x00;
+/// Starts a transfer of columns.
+const DATA: u8 = 0x40;
+
+pub type Error = <ImuBusConcrete<'static> as I2c>::Error;
+
+pub struct Ssd1306 {
+	bus: ImuBus,
+}
+impl Ssd1306 {
+	pub fn new(bus: ImuBus) -> Self {
+		Self { bus }
+	}
+
+	/// Sets the display up, clears it, and turns it on.
+	pub async fn init(&mut self) -> Result<(), Error> {
+		let com_pins = if HEIGHT == 64 { 0x12 } else { 0x02 };
+		#[rustfmt::skip]
+		let setup = [
+			0xAE, // Off while setting up
+			0xD5, 0x80, // The default clock
+			0xA8, HEIGHT - 1, // How many rows there are
+			0xD3, 0x00, // Not shifted
+			0x40, // Starting at the first row
+			0x8D, 0x14, // The charge pump, since modules have no other supply
+			0x20, 0x02, // Page addressing
+			0xA1, 0xC8, // Rotated, the way that modules are wired
+			0xDA, com_pins,
+			0x81, 0x8F, // The contrast
+			0xD9, 0xF1, // The pre-charge period
+			0xDB, 0x40, // The deselect level
+			0xA4, // Shows what is in RAM
+			0xA6, // Not inverted
+		];
+		self.commands(&setup).await?;
+		for page in 0..HEIGHT / 8 {
+			self.page(page, &[0; WIDTH]).await?;
+		}
+		self.commands(&[0xAF]).await
+	}
+
+	pub async fn turn_off(&mut self) -> Result<(), Error> {
+		self.commands(&[0xAE]).await
+	}
+
+	/// Sets the columns of `page`, in one turn on the bus.
+	pub async fn page(&mut self, page: u8, columns: &[u8; WIDTH]) -> Result<(), Error> {
+		let mut data = [DATA; WIDTH + 1];
+		data[1..].copy_from_slice(columns);
+		let mut i2c = self.bus.lock().await;
+		// Explicitly, the nRF52 HAL also has an async `write`. First to the first
+		// column of the page.
+		Write::write(&mut *i2c, ADDRESS, &[COMMANDS, 0xB0 | page, 0x00, 0x10])?;
+		Write::write(&mut *i2c, ADDRESS, &data)
+	}
+
+	async fn commands(&mut self, commands: &[u8]) -> Result<(), Error> {
+		let mut buf = [COMMANDS; 32];
+		buf[1..=commands.len()].copy_from_slice(commands);
+		let mut i2c = self.bus.lock().await;
+		Write::write(&mut *i2c, ADDRESS, &buf[..=commands.len()])
+	}
+}
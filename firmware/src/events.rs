@@ -0,0 +1,160 @@
+//! Events that several tasks care about, like the connection to the server coming
+//! and going. Producers publish them without knowing who listens, so reacting to an
+//! event somewhere new (like an LED or a vibration motor) only takes another
+//! subscriber, see [`NUM_SUBSCRIBERS`].
+//!
+//! Producers never wait for consumers, so that a slow consumer can't stall the imu.
+//! A consumer that falls more than [`CAPACITY`] events behind misses the oldest
+//! ones instead.
+
+use defmt::{debug, info, warn, Format};
+use embassy_executor::task;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::pubsub::{PubSubChannel, Subscriber};
+
+use crate::config::Setting;
+use crate::imu::calibration::Routine;
+use crate::imu::reset::ResetKind;
+use crate::imu::SensorError;
+use crate::power::PowerState;
+use crate::thermal::ThermalState;
+
+/// The stages of booting after power on, in order. The tracker is up once it
+/// reached the server.
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BootStage {
+	/// Initializing the imus.
+	Imu,
+	/// Bringing up the link, like joining Wi-Fi. Only Wi-Fi can fail at it so far.
+	#[cfg_attr(not(feature = "net-wifi"), allow(dead_code))]
+	Network,
+	/// Finding the server.
+	Server,
+	/// Found a server, but one too old to talk to. The search goes on.
+	Incompatible,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Format)]
+pub enum Event {
+	/// The imus were initialized, so booting went on to the network.
+	ImusReady,
+	/// A stage of booting failed. An imu that is missing stops the tracker, the
+	/// other stages keep retrying, and are done once the link comes up or the
+	/// server answers.
+	BootFailed(BootStage),
+	/// We found the server, or it stopped responding.
+	ConnectionChanged { connected: bool },
+	/// The link that the packets travel over came up or went down, like Wi-Fi
+	/// getting an address or a BLE central connecting. The server may still be
+	/// unreachable while it is up.
+	#[cfg_attr(
+		not(any(
+			feature = "net-wifi",
+			feature = "net-espnow",
+			all(feature = "net-ble", mcu_f_nrf52)
+		)),
+		allow(dead_code)
+	)]
+	LinkChanged { up: bool },
+	/// Wi-Fi got an address from DHCP, after the link came up.
+	#[cfg_attr(not(feature = "net-wifi"), allow(dead_code))]
+	IpAssigned([u8; 4]),
+	/// The battery was measured, on boards that can. `low` is whether it is below
+	/// the level of [`Event::BatteryLow`].
+	#[cfg_attr(not(battery), allow(dead_code))]
+	Battery {
+		millivolts: u16,
+		percent: u8,
+		low: bool,
+	},
+	/// The battery fell below the level at which it should be charged soon.
+	#[cfg_attr(not(battery), allow(dead_code))]
+	BatteryLow { percent: u8 },
+	/// The battery sagged close to browning out while transmitting, so less should be
+	/// sent and with less power, or it recovered again. `millivolts` is the reading
+	/// that decided it.
+	#[cfg_attr(not(battery), allow(dead_code))]
+	TxBackoff { active: bool, millivolts: u16 },
+	/// The fusion of a sensor reports that it is fully calibrated.
+	CalibrationDone { sensor_id: u8 },
+	/// A calibration routine of a sensor started, or ended because it was done or
+	/// timed out.
+	Calibrating { sensor_id: u8, running: bool },
+	/// A sensor went offline because of an error.
+	ImuError { sensor_id: u8, error: SensorError },
+	/// A sensor that went offline produces data again.
+	ImuRecovered { sensor_id: u8 },
+	/// A sensor was tapped, `double` for the second tap of a double tap.
+	Tap { sensor_id: u8, double: bool },
+	/// The die temperature of a sensor was measured, in degrees Celsius. Only
+	/// published for imus that measure it.
+	Temperature { sensor_id: u8, celsius: f32 },
+	/// The MCU got hotter or cooled down.
+	Thermal(ThermalState),
+	/// A sensor's rate was lowered because the CPU couldn't keep up, or raised again
+	/// once it could.
+	RateScaled {
+		sensor_id: u8,
+		rate_hz: u32,
+		lowered: bool,
+	},
+	/// The server asked for a sensor's raw samples for `duration_ms`, or to stop
+	/// them with `0`.
+	RawStreamRequested { sensor_id: u8, duration_ms: u32 },
+	/// The button or the server started a calibration routine of a sensor.
+	CalibrationRequested { sensor_id: u8, routine: Routine },
+	/// The server asked to reinitialize a sensor and its fusion.
+	RestartRequested { sensor_id: u8 },
+	/// The user reset the orientation of a sensor on the server.
+	ResetRequested { sensor_id: u8, kind: ResetKind },
+	/// The tracker started or stopped saving power, because it lay still.
+	Power(PowerState),
+	/// The imu was armed to wake the tracker up, which goes into deep sleep next.
+	#[cfg_attr(not(deep_sleep), allow(dead_code))]
+	Sleeping,
+	/// The server changed a setting.
+	ConfigChanged(Setting),
+}
+
+/// How many events a consumer can fall behind before it misses some.
+pub const CAPACITY: usize = 4;
+/// The protocol, imu, and event log tasks, and the LED and display tasks on boards
+/// with them.
+pub const NUM_SUBSCRIBERS: usize = 3 + cfg!(led) as usize + cfg!(display) as usize;
+/// Producers use immediate publishers, which don't take a slot.
+const NUM_PUBLISHERS: usize = 0;
+
+pub type EventBus =
+	PubSubChannel<NoopRawMutex, Event, CAPACITY, NUM_SUBSCRIBERS, NUM_PUBLISHERS>;
+/// For consumers that have to subscribe before the executor starts, so that they
+/// don't miss what the first tasks publish while booting.
+#[cfg_attr(not(any(led, display)), allow(dead_code))]
+pub type EventSubscriber =
+	Subscriber<'static, NoopRawMutex, Event, CAPACITY, NUM_SUBSCRIBERS, NUM_PUBLISHERS>;
+
+/// Publishes `event` to every subscriber, without waiting.
+pub fn publish(bus: &EventBus, event: Event) {
+	bus.immediate_publisher().publish_immediate(event);
+}
+
+/// Logs every event, so that they show up in one place when debugging.
+#[task]
+pub async fn event_log_task(bus: &'static EventBus) -> ! {
+	debug!("Event log task");
+	let mut events = bus.subscriber().unwrap();
+	loop {
+		match events.next_message_pure().await {
+			e @ (Event::BatteryLow { .. }
+			| Event::ImuError { .. }
+			| Event::BootFailed(_)
+			| Event::TxBackoff { active: true, .. }) => {
+				warn!("event: {}", e)
+			}
+			// Measured every few seconds, which would drown out everything else.
+			e @ (Event::Battery { .. } | Event::Temperature { .. }) => {
+				debug!("event: {}", e)
+			}
+			e => info!("event: {}", e),
+		}
+	}
+}
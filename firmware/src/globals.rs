@@ -3,11 +3,12 @@ extern crate alloc;
 // Set up global heap allocator
 #[cfg(mcu_f_esp32)]
 #[global_allocator]
-static ALLOCATOR: esp_alloc::EspHeap = esp_alloc::EspHeap::empty();
+pub static ALLOCATOR: esp_alloc::EspHeap = esp_alloc::EspHeap::empty();
 
 #[cfg(cortex_m)]
 #[global_allocator]
-static ALLOCATOR: alloc_cortex_m::CortexMHeap = alloc_cortex_m::CortexMHeap::empty();
+pub static ALLOCATOR: alloc_cortex_m::CortexMHeap =
+	alloc_cortex_m::CortexMHeap::empty();
 
 #[alloc_error_handler]
 fn oom(_: core::alloc::Layout) -> ! {
@@ -38,7 +39,11 @@ use nrf_softdevice as _;
 pub fn setup() {
 	// Initialize the global allocator BEFORE you use it
 	{
+		// We only allocate to serialize packets, which are small.
+		#[cfg(not(feature = "constrained"))]
 		const HEAP_SIZE: usize = 10 * 1024;
+		#[cfg(feature = "constrained")]
+		const HEAP_SIZE: usize = 2 * 1024;
 		static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
 
 		unsafe {
@@ -0,0 +1,135 @@
+//! Lets the imus share one I2C bus with each other, and with other peripherals on
+//! it, like a display. The bus is behind an async mutex, so that a transfer that
+//! awaits, like a burst read with `bus-dma`, has the bus to itself until it is done.
+//!
+//! The imus' reads of their samples go ahead of everyone else who waits for the
+//! bus, see [`Bus::lock_imu`], so that others only get it in between. Others should
+//! keep what they do with it short, like one page of a display at a time, since the
+//! imus have to wait for that to finish. Blocking transfers can't wait for the bus,
+//! and fail with [`BusError::Busy`] while another task is in the middle of a
+//! transfer that awaits. The imus only make those while setting up, which is
+//! retried like after any other error.
+
+use core::cell::Cell;
+use embassy_futures::yield_now;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+use crate::aliases::{BurstRead, I2c};
+
+pub type BusGuard<'a, I> = MutexGuard<'a, NoopRawMutex, I>;
+
+pub struct Bus<I> {
+	i2c: Mutex<NoopRawMutex, I>,
+	/// How many reads of the imus are waiting for the bus.
+	imu_waiting: Cell<u8>,
+}
+impl<I> Bus<I> {
+	pub fn new(i2c: I) -> Self {
+		Self {
+			i2c: Mutex::new(i2c),
+			imu_waiting: Cell::new(0),
+		}
+	}
+
+	/// Waits for the bus, ahead of everyone in [`Bus::lock`].
+	pub async fn lock_imu(&self) -> BusGuard<'_, I> {
+		// Also stops counting if the read is dropped while waiting, like on a timeout.
+		struct Waiting<'a>(&'a Cell<u8>);
+		impl Drop for Waiting<'_> {
+			fn drop(&mut self) {
+				self.0.set(self.0.get() - 1);
+			}
+		}
+		self.imu_waiting.set(self.imu_waiting.get() + 1);
+		let _waiting = Waiting(&self.imu_waiting);
+		self.i2c.lock().await
+	}
+
+	/// Waits for the bus, and until no imu is waiting for it.
+	#[cfg_attr(not(display), allow(dead_code))]
+	pub async fn lock(&self) -> BusGuard<'_, I> {
+		loop {
+			while self.imu_waiting.get() > 0 {
+				yield_now().await;
+			}
+			let guard = self.i2c.lock().await;
+			// Otherwise an imu started waiting while we did, which goes first.
+			if self.imu_waiting.get() == 0 {
+				return guard;
+			}
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum BusError<E> {
+	/// Another task was in the middle of a transfer that awaits.
+	Busy,
+	I2c(E),
+}
+
+/// The bus for one imu driver.
+pub struct SharedBus<'a, I>(&'a Bus<I>);
+impl<'a, I> SharedBus<'a, I> {
+	pub fn new(bus: &'a Bus<I>) -> Self {
+		Self(bus)
+	}
+
+	/// The bus for a blocking transfer, which doesn't wait for it.
+	fn now<E>(&self) -> Result<BusGuard<'a, I>, BusError<E>> {
+		self.0.i2c.try_lock().map_err(|_| BusError::Busy)
+	}
+}
+// Not derived, since that would need `I: Clone`.
+impl<I> Clone for SharedBus<'_, I> {
+	fn clone(&self) -> Self {
+		Self(self.0)
+	}
+}
+
+impl<I: I2c> Write for SharedBus<'_, I> {
+	type Error = BusError<<I as I2c>::Error>;
+
+	fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+		self.now()?.write(address, bytes).map_err(BusError::I2c)
+	}
+}
+
+impl<I: I2c> WriteRead for SharedBus<'_, I> {
+	type Error = BusError<<I as I2c>::Error>;
+
+	fn write_read(
+		&mut self,
+		address: u8,
+		bytes: &[u8],
+		buffer: &mut [u8],
+	) -> Result<(), Self::Error> {
+		self.now()?
+			.write_read(address, bytes, buffer)
+			.map_err(BusError::I2c)
+	}
+}
+
+impl<I: I2c> Read for SharedBus<'_, I> {
+	type Error = BusError<<I as I2c>::Error>;
+
+	fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Self::Error> {
+		self.now()?.read(address, buffer).map_err(BusError::I2c)
+	}
+}
+
+impl<I: BurstRead> BurstRead for SharedBus<'_, I> {
+	async fn burst_read(
+		&mut self,
+		address: u8,
+		reg: u8,
+		buf: &mut [u8],
+	) -> Result<(), <Self as I2c>::Error> {
+		let mut i2c = self.0.lock_imu().await;
+		i2c.burst_read(address, reg, buf)
+			.await
+			.map_err(BusError::I2c)
+	}
+}
@@ -0,0 +1,299 @@
+//! Calibrates the gyro bias and the accelerometer on the tracker, and keeps the
+//! result in `storage` so that it survives reboots. It is applied to every sample
+//! before fusion.
+//!
+//! There are two routines, started with the button or by the server:
+//! - [`Routine::GyroBias`] waits for the tracker to lie still, and takes the average
+//!   angular velocity as the bias.
+//! - [`Routine::Accel`] is the six-position calibration. The tracker is laid still on
+//!   each of its six sides in turn, in any order. Opposite sides see gravity with
+//!   opposite signs, so their average is the offset of that axis, and their
+//!   difference tells how much its scale is off.
+//!
+//! Both routines find out whether the tracker lies still from the samples alone, so
+//! they are robust to being started while it is still moving.
+//!
+//! The server can also start [`Routine::Mag`], which runs alongside them, see
+//! [`crate::imu::mag_calibration`].
+
+use defmt::{debug, info, warn, Format};
+use embassy_time::{Duration, Instant};
+use firmware_protocol::{CALIBRATE_ACCEL, CALIBRATE_GYRO_BIAS, CALIBRATE_MAG};
+use nalgebra::Vector3;
+
+use crate::imu::mag_calibration::MagCalibrator;
+use crate::imu::{Accel, Gyro, UnfusedData};
+use crate::storage::{self, Slot};
+
+/// Standard gravity in m/s^2.
+pub const G: f32 = 9.80665;
+/// How long the tracker has to lie still before a routine takes its samples.
+const REST_TIME: Duration = Duration::from_secs(2);
+/// How far the gyro may stray from its average while the tracker lies still, in
+/// rad/s. A bit more than the noise of cheap gyros, and far less than a hand holding
+/// it still.
+const REST_GYRO_RANGE: f32 = 0.05;
+/// How far the accelerometer may stray from its average while at rest, in m/s^2.
+const REST_ACCEL_RANGE: f32 = 0.3;
+/// When a routine gives up, if the tracker never got to rest in all the needed ways.
+pub const TIMEOUT: Duration = Duration::from_secs(120);
+/// How much of gravity has to be along one axis, for a side to count as lying flat.
+const FLAT_FRACTION: f32 = 0.9;
+/// Bumped whenever the layout of the stored calibration changes. Older layouts are
+/// ignored.
+const VERSION: u8 = 1;
+/// The version, and nine `f32`s.
+const STORED_LEN: usize = 1 + 9 * 4;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Routine {
+	GyroBias,
+	Accel,
+	Mag,
+}
+impl Routine {
+	/// The routine that the server asked for with `CbPacket::Calibrate`.
+	pub fn from_protocol(routine: u8) -> Option<Self> {
+		match routine {
+			CALIBRATE_GYRO_BIAS => Some(Self::GyroBias),
+			CALIBRATE_ACCEL => Some(Self::Accel),
+			CALIBRATE_MAG => Some(Self::Mag),
+			_ => None,
+		}
+	}
+}
+
+/// Corrections for the gyro and accelerometer readings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calibration {
+	pub gyro_bias: Gyro,
+	pub accel_offset: Accel,
+	/// Multiplies each axis after the offset was removed.
+	pub accel_scale: Accel,
+}
+impl Calibration {
+	/// Leaves the readings as they are.
+	pub fn identity() -> Self {
+		Self {
+			gyro_bias: Gyro::zeros(),
+			accel_offset: Accel::zeros(),
+			accel_scale: Accel::repeat(1.),
+		}
+	}
+
+	/// The stored calibration, or the identity if there is none.
+	pub fn load() -> Self {
+		let mut bytes = [0; STORED_LEN];
+		match storage::load(Slot::Calibration, &mut bytes) {
+			Ok(STORED_LEN) if bytes[0] == VERSION => {
+				let c = Self::from_bytes(&bytes);
+				info!("Loaded IMU calibration: {}", defmt::Debug2Format(&c));
+				crate::diag::calibration(Ok(true));
+				return c;
+			}
+			Ok(_) => {
+				warn!("Ignoring the stored IMU calibration, it is outdated");
+				crate::diag::calibration(Ok(false));
+			}
+			Err(storage::Error::Empty) => {
+				info!("The IMU isn't calibrated yet");
+				crate::diag::calibration(Ok(false));
+			}
+			Err(e) => {
+				warn!("Failed to load the IMU calibration: {}", e);
+				crate::diag::calibration(Err(e));
+			}
+		}
+		Self::identity()
+	}
+
+	fn store(&self) {
+		let mut bytes = [0; STORED_LEN];
+		bytes[0] = VERSION;
+		let values = self
+			.gyro_bias
+			.iter()
+			.chain(self.accel_offset.iter())
+			.chain(self.accel_scale.iter());
+		for (chunk, value) in bytes[1..].chunks_exact_mut(4).zip(values) {
+			chunk.copy_from_slice(&value.to_le_bytes());
+		}
+		match storage::store(Slot::Calibration, &bytes) {
+			Ok(()) => crate::diag::calibration(Ok(true)),
+			Err(e) => {
+				warn!("Failed to store the IMU calibration: {}", e);
+				crate::diag::calibration(Err(e));
+			}
+		}
+	}
+
+	fn from_bytes(bytes: &[u8; STORED_LEN]) -> Self {
+		let mut values = bytes[1..]
+			.chunks_exact(4)
+			.map(|c| f32::from_le_bytes(c.try_into().unwrap()));
+		let mut next = || Vector3::from_fn(|_, _| values.next().unwrap());
+		Self {
+			gyro_bias: next(),
+			accel_offset: next(),
+			accel_scale: next(),
+		}
+	}
+
+	pub fn apply(&self, data: &mut UnfusedData) {
+		data.gyro -= self.gyro_bias;
+		data.accel = (data.accel - self.accel_offset).component_mul(&self.accel_scale);
+	}
+}
+
+/// Decides whether the tracker lies still, and averages the samples while it does.
+struct RestDetector {
+	since: Instant,
+	count: u32,
+	gyro_sum: Gyro,
+	accel_sum: Accel,
+}
+impl RestDetector {
+	fn new(now: Instant) -> Self {
+		Self {
+			since: now,
+			count: 0,
+			gyro_sum: Gyro::zeros(),
+			accel_sum: Accel::zeros(),
+		}
+	}
+
+	/// Returns the average gyro and accelerometer readings, once the tracker lay still
+	/// for [`REST_TIME`]. Starts over afterwards.
+	fn update(&mut self, raw: &UnfusedData, now: Instant) -> Option<(Gyro, Accel)> {
+		if self.count > 0 {
+			let n = self.count as f32;
+			let moved = (raw.gyro - self.gyro_sum / n).amax() > REST_GYRO_RANGE
+				|| (raw.accel - self.accel_sum / n).amax() > REST_ACCEL_RANGE;
+			if moved {
+				*self = Self::new(now);
+			}
+		}
+		self.count += 1;
+		self.gyro_sum += raw.gyro;
+		self.accel_sum += raw.accel;
+		if now - self.since < REST_TIME {
+			return None;
+		}
+		let n = self.count as f32;
+		let mean = (self.gyro_sum / n, self.accel_sum / n);
+		*self = Self::new(now);
+		Some(mean)
+	}
+}
+
+enum Active {
+	GyroBias,
+	/// The average accelerometer reading on each side, in the order +x, -x, +y, -y,
+	/// +z, -z, once the tracker lay on it.
+	Accel([Option<Accel>; 6]),
+}
+
+/// Applies the calibration, and runs the routines that update it.
+pub struct Calibrator {
+	calibration: Calibration,
+	active: Option<(Active, RestDetector, Instant)>,
+	mag: MagCalibrator,
+}
+impl Calibrator {
+	pub fn new() -> Self {
+		Self {
+			calibration: Calibration::load(),
+			active: None,
+			mag: MagCalibrator::new(),
+		}
+	}
+
+	/// Starts `routine`, replacing the one that was running. [`Routine::Mag`] only
+	/// replaces itself.
+	pub fn start(&mut self, routine: Routine) {
+		let now = Instant::now();
+		let active = match routine {
+			Routine::GyroBias => {
+				info!("Calibrating the gyro, lay the tracker down and keep it still");
+				Active::GyroBias
+			}
+			Routine::Accel => {
+				info!(
+					"Calibrating the accelerometer, lay the tracker still on each of its \
+					six sides in turn"
+				);
+				Active::Accel([None; 6])
+			}
+			Routine::Mag => {
+				self.mag.start();
+				return;
+			}
+		};
+		self.active = Some((active, RestDetector::new(now), now + TIMEOUT));
+	}
+
+	/// Whether a routine is running.
+	pub fn is_active(&self) -> bool {
+		self.active.is_some() || self.mag.is_active()
+	}
+
+	/// Feeds the uncalibrated sample `raw` to the running routine, if any, and then
+	/// calibrates it.
+	pub fn process(&mut self, raw: &mut UnfusedData) {
+		if let Some((_, rest, deadline)) = &mut self.active {
+			let now = Instant::now();
+			if now >= *deadline {
+				warn!("Calibration timed out, keeping the previous one");
+				self.active = None;
+			} else if let Some((gyro, accel)) = rest.update(raw, now) {
+				if let Some(calibration) = self.on_rest(gyro, accel) {
+					info!("Calibrated the IMU: {}", defmt::Debug2Format(&calibration));
+					self.calibration = calibration;
+					self.calibration.store();
+					self.active = None;
+				}
+			}
+		}
+		self.calibration.apply(raw);
+		raw.mag = self.mag.process(raw.mag.as_ref());
+	}
+
+	/// Uses the averages of a period of rest, and returns the new calibration once the
+	/// routine is done.
+	fn on_rest(&mut self, gyro: Gyro, accel: Accel) -> Option<Calibration> {
+		let (active, _, _) = self.active.as_mut()?;
+		let mut calibration = self.calibration;
+		match active {
+			Active::GyroBias => {
+				calibration.gyro_bias = gyro;
+				Some(calibration)
+			}
+			Active::Accel(sides) => {
+				let axis = accel.iamax();
+				if accel[axis].abs() < FLAT_FRACTION * accel.norm() {
+					debug!("Tracker isn't lying flat on a side, ignoring it");
+					return None;
+				}
+				let side = axis * 2 + usize::from(accel[axis] < 0.);
+				if sides[side].is_none() {
+					sides[side] = Some(accel);
+					let done = sides.iter().filter(|s| s.is_some()).count();
+					info!("Calibrated side {} of 6", done);
+				}
+				let mut offset = Accel::zeros();
+				let mut scale = Accel::zeros();
+				for axis in 0..3 {
+					let pos = sides[axis * 2]?;
+					let neg = sides[axis * 2 + 1]?;
+					offset[axis] = (pos[axis] + neg[axis]) / 2.;
+					scale[axis] = 2. * G / (pos[axis] - neg[axis]);
+				}
+				// Lying still is also a good time to measure the gyro bias.
+				calibration.gyro_bias = gyro;
+				calibration.accel_offset = offset;
+				calibration.accel_scale = scale;
+				Some(calibration)
+			}
+		}
+	}
+}
@@ -4,7 +4,7 @@ use self::math::GyroFsr;
 use crate::aliases::I2c;
 use crate::imu::drivers::bmi160::math::AccelFsr;
 use crate::imu::fusion::{new_fuser, FusedImu};
-use crate::imu::{FusedData, Imu, UnfusedData};
+use crate::imu::{Address, FusedData, Imu, UnfusedData};
 use crate::utils;
 
 use ::bmi160::{AccelerometerPowerMode, GyroscopePowerMode, SensorSelector};
@@ -35,9 +35,14 @@ pub struct Bmi160<I: I2c> {
 	driver: BmiDriver<I>,
 }
 impl<I: I2c> Bmi160<I> {
-	pub fn new(i2c: I, delay: &mut impl DelayMs<u32>) -> Result<Self, InitError<I>> {
+	pub fn new(
+		i2c: I,
+		address: Address,
+		delay: &mut impl DelayMs<u32>,
+	) -> Result<Self, InitError<I>> {
 		debug!("Constructing BMI160...");
-		let addr = ::bmi160::SlaveAddr::Alternative(false);
+		// Whether SDO is pulled high.
+		let addr = ::bmi160::SlaveAddr::Alternative(address == Address::Alternate);
 		debug!("I2C address: {:?}", defmt::Debug2Format(&addr));
 
 		macro_rules! unwrap_or_err {
@@ -61,23 +66,25 @@ impl<I: I2c> Bmi160<I> {
 				let mut driver = BmiDriver::new_with_i2c(i2c, addr);
 				let id = unwrap_or_err!(driver, driver.chip_id());
 				debug!("Constructed BMI with chip id: {}", id);
-				unwrap_or_err!(
-					driver,
-					driver.set_accel_power_mode(AccelerometerPowerMode::Normal)
-				);
-				unwrap_or_err!(
-					driver,
-					driver.set_gyro_power_mode(GyroscopePowerMode::Normal)
-				);
-				debug!("BMI power mode set to Normal");
-				delay.delay_ms(100);
-				Ok(Self { driver })
+				let mut bmi = Self { driver };
+				unwrap_or_err!(bmi.driver, bmi.power_up(delay));
+				Ok(bmi)
 			},
 			|i| debug!("Retrying IMU connection (attempts so far: {})", i + 1),
 		)
 		// Map converts from tuple -> struct
 		.map_err(|(i2c, error)| InitError { i2c, error })
 	}
+
+	fn power_up(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), BmiError<I>> {
+		self.driver
+			.set_accel_power_mode(AccelerometerPowerMode::Normal)?;
+		self.driver
+			.set_gyro_power_mode(GyroscopePowerMode::Normal)?;
+		debug!("BMI power mode set to Normal");
+		delay.delay_ms(100);
+		Ok(())
+	}
 }
 
 impl<I: I2c> Imu for Bmi160<I> {
@@ -110,18 +117,24 @@ impl<I: I2c> Imu for Bmi160<I> {
 		let gyro = vector![g(gyro.x), g(gyro.y), g(gyro.z)];
 		let accel = vector![a(accel.x), a(accel.y), a(accel.z)];
 
-		Ok(UnfusedData { accel, gyro })
+		// A magnetometer on the auxiliary interface isn't supported, so there is none.
+		Ok(UnfusedData {
+			accel,
+			gyro,
+			mag: None,
+		})
+	}
+
+	fn restart(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Self::Error> {
+		self.power_up(delay)
 	}
 }
 
 #[allow(dead_code)]
 pub fn new_imu(
 	i2c: impl crate::aliases::I2c,
+	address: Address,
 	delay: &mut impl DelayMs<u32>,
-) -> impl Imu<Data = FusedData> {
-	let bmi = Bmi160::new(i2c, delay).expect("Failed to initialize BMI160");
-	FusedImu {
-		fuser: new_fuser(),
-		imu: bmi,
-	}
+) -> Result<impl Imu<Data = FusedData>, impl core::fmt::Debug> {
+	Bmi160::new(i2c, address, delay).map(|bmi| FusedImu::new(bmi, new_fuser()))
 }
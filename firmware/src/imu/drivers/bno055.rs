@@ -0,0 +1,261 @@
+//! Driver for the Bosch BNO055, running its on-chip fusion in NDOF mode.
+//!
+//! The BNO055 is fairly quirky: it takes a long time to boot, has to be in config
+//! mode for most register writes, and stretches the I2C clock whenever its
+//! microcontroller is busy. We run the bus at 100kHz and retry failed transactions
+//! to deal with the latter.
+
+use crate::aliases::{BurstRead, I2c};
+use crate::imu::{Address, FusedData, Imu, Quat};
+use crate::utils;
+
+use defmt::{debug, trace, warn};
+use embassy_time::{Duration, Ticker};
+use embedded_hal::blocking::delay::DelayMs;
+use firmware_protocol::ImuType;
+use futures_util::StreamExt;
+use nalgebra::vector;
+
+/// The address when the COM3 pin is low, which most breakout boards default to, and
+/// when it is high.
+const ADDRESS: u8 = 0x28;
+const ALTERNATE_ADDRESS: u8 = 0x29;
+const CHIP_ID: u8 = 0xA0;
+/// How often to retry a single I2C transaction that got NACKed, which happens when
+/// the BNO055 stretches the clock for too long.
+const I2C_RETRIES: u8 = 3;
+/// The fusion output data rate in NDOF mode is fixed at 100Hz.
+const DATA_PERIOD: Duration = Duration::from_millis(10);
+
+/// Quaternion data is in units of 1/2^14.
+const LSB_PER_QUAT: f32 = (1 << 14) as f32;
+/// Gyro data is in units of 1/900 rad/s, once we select radians.
+const LSB_PER_RAD: f32 = 900.;
+
+/// Register addresses on page 0.
+#[allow(dead_code)]
+mod reg {
+	pub const CHIP_ID: u8 = 0x00;
+	pub const PAGE_ID: u8 = 0x07;
+	pub const GYR_DATA: u8 = 0x14;
+	pub const QUA_DATA: u8 = 0x20;
+	pub const TEMP: u8 = 0x34;
+	pub const CALIB_STAT: u8 = 0x35;
+	pub const SYS_STATUS: u8 = 0x39;
+	pub const SYS_ERR: u8 = 0x3A;
+	pub const UNIT_SEL: u8 = 0x3B;
+	pub const OPR_MODE: u8 = 0x3D;
+	pub const PWR_MODE: u8 = 0x3E;
+	pub const SYS_TRIGGER: u8 = 0x3F;
+}
+
+/// Values for `OPR_MODE`.
+mod mode {
+	pub const CONFIG: u8 = 0x00;
+	/// 9 degrees of freedom fusion with fast magnetometer calibration.
+	pub const NDOF: u8 = 0x0C;
+}
+
+/// `SYS_TRIGGER` bit that resets the whole chip.
+const RST_SYS: u8 = 1 << 5;
+/// `UNIT_SEL` bit that switches angular rate from dps to rad/s.
+const GYR_UNIT_RAD: u8 = 1 << 1;
+
+#[derive(Debug)]
+pub enum Error<E> {
+	I2c(E),
+	/// The chip didn't identify itself as a BNO055.
+	WrongChipId(u8),
+}
+impl<E> From<E> for Error<E> {
+	fn from(e: E) -> Self {
+		Self::I2c(e)
+	}
+}
+
+pub struct InitError<I: I2c> {
+	pub i2c: I,
+	pub error: Error<<I as I2c>::Error>,
+}
+impl<I: I2c> core::fmt::Debug for InitError<I> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		self.error.fmt(f)
+	}
+}
+
+pub struct Bno055<I: BurstRead> {
+	i2c: I,
+	address: u8,
+	ticker: Ticker,
+}
+impl<I: BurstRead> Bno055<I> {
+	pub fn new(
+		i2c: I,
+		address: Address,
+		delay: &mut impl DelayMs<u32>,
+	) -> Result<Self, InitError<I>> {
+		debug!("Constructing BNO055...");
+		let address = address.pick(ADDRESS, ALTERNATE_ADDRESS);
+		debug!("I2C address: {:x}", address);
+
+		utils::retry(
+			4,
+			i2c,
+			|i2c| {
+				let mut bno = Self {
+					i2c,
+					address,
+					ticker: Ticker::every(DATA_PERIOD),
+				};
+				match bno.init(delay) {
+					Ok(()) => Ok(bno),
+					Err(error) => Err((bno.i2c, error)),
+				}
+			},
+			|i| warn!("Retrying IMU connection (attempts so far: {})", i + 1),
+		)
+		.map_err(|(i2c, error)| InitError { i2c, error })
+	}
+
+	fn init(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Error<I::Error>> {
+		// Takes 650ms to boot from power on, and doesn't answer until then.
+		delay.delay_ms(650);
+		let id = self.read_reg(reg::CHIP_ID)?;
+		if id != CHIP_ID {
+			return Err(Error::WrongChipId(id));
+		}
+		debug!("Found BNO055");
+
+		// A reset gets us into a known state, even if only the mcu was restarted.
+		self.write_reg(reg::OPR_MODE, mode::CONFIG)?;
+		delay.delay_ms(20);
+		self.write_reg(reg::SYS_TRIGGER, RST_SYS)?;
+		delay.delay_ms(650);
+		trace!("Reset BNO055");
+
+		// The page id isn't reset along with everything else.
+		self.write_reg(reg::PAGE_ID, 0)?;
+		self.write_reg(reg::PWR_MODE, 0)?;
+		self.write_reg(reg::UNIT_SEL, GYR_UNIT_RAD)?;
+		self.write_reg(reg::SYS_TRIGGER, 0)?;
+		delay.delay_ms(10);
+
+		// Switching out of config mode takes 7ms.
+		self.write_reg(reg::OPR_MODE, mode::NDOF)?;
+		delay.delay_ms(20);
+		debug!("BNO055 is in NDOF mode");
+		Ok(())
+	}
+
+	fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Error<I::Error>> {
+		let address = self.address;
+		self.retry(|i2c| i2c.write(address, &[reg, value]))
+	}
+
+	fn read_reg(&mut self, reg: u8) -> Result<u8, Error<I::Error>> {
+		let mut buf = [0];
+		self.read_regs(reg, &mut buf)?;
+		Ok(buf[0])
+	}
+
+	fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Error<I::Error>> {
+		let address = self.address;
+		self.retry(|i2c| i2c.write_read(address, &[reg], buf))
+	}
+
+	/// Like `read_regs`, but without blocking while DMA is available. Only for the
+	/// samples, since that is where the time goes.
+	async fn burst_read(
+		&mut self,
+		reg: u8,
+		buf: &mut [u8],
+	) -> Result<(), Error<I::Error>> {
+		let mut result = self.i2c.burst_read(self.address, reg, buf).await;
+		for _ in 0..I2C_RETRIES {
+			if result.is_ok() {
+				break;
+			}
+			trace!("Retrying I2C transaction");
+			result = self.i2c.burst_read(self.address, reg, buf).await;
+		}
+		Ok(result?)
+	}
+
+	/// Retries one I2C transaction, because a long clock stretch can make it fail.
+	fn retry<T>(
+		&mut self,
+		mut f: impl FnMut(&mut I) -> Result<T, I::Error>,
+	) -> Result<T, Error<I::Error>> {
+		let mut result = f(&mut self.i2c);
+		for _ in 0..I2C_RETRIES {
+			if result.is_ok() {
+				break;
+			}
+			trace!("Retrying I2C transaction");
+			result = f(&mut self.i2c);
+		}
+		Ok(result?)
+	}
+
+	/// The system calibration status from `0` (uncalibrated) to `3` (fully
+	/// calibrated), which is how the server expects fusion accuracy.
+	fn accuracy(&mut self) -> Result<u8, Error<I::Error>> {
+		let calib = self.read_reg(reg::CALIB_STAT)?;
+		Ok(calib >> 6)
+	}
+}
+
+impl<I: BurstRead> Imu for Bno055<I> {
+	type Error = Error<<I as I2c>::Error>;
+	type Data = FusedData;
+
+	const IMU_TYPE: ImuType = ImuType::Bno055;
+
+	async fn next_data(&mut self) -> Result<Self::Data, Self::Error> {
+		let q = loop {
+			self.ticker.next().await;
+			let mut buf = [0; 8];
+			self.burst_read(reg::QUA_DATA, &mut buf).await?;
+			let [w, x, y, z] = [0, 2, 4, 6].map(|i| {
+				i16::from_le_bytes([buf[i], buf[i + 1]]) as f32 / LSB_PER_QUAT
+			});
+			// Reads as all zeroes until the fusion has its first output.
+			if let Some(q) = Quat::try_new(nalgebra::Quaternion::new(w, x, y, z), 0.1) {
+				break q;
+			}
+		};
+
+		let mut buf = [0; 6];
+		self.burst_read(reg::GYR_DATA, &mut buf).await?;
+		let [x, y, z] = [0, 2, 4]
+			.map(|i| i16::from_le_bytes([buf[i], buf[i + 1]]) as f32 / LSB_PER_RAD);
+
+		Ok(FusedData {
+			q,
+			angular_velocity: Some(vector![x, y, z]),
+			accuracy: Some(self.accuracy()?),
+			raw: None,
+			linear_accel: None,
+			tap: None,
+		})
+	}
+
+	fn restart(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Self::Error> {
+		self.init(delay)
+	}
+
+	/// In whole degrees, since `UNIT_SEL` keeps the default of Celsius.
+	fn temperature(&mut self) -> Result<Option<f32>, Self::Error> {
+		let temp = self.read_reg(reg::TEMP)? as i8;
+		Ok(Some(f32::from(temp)))
+	}
+}
+
+#[allow(dead_code)]
+pub fn new_imu(
+	i2c: impl crate::aliases::BurstRead,
+	address: Address,
+	delay: &mut impl DelayMs<u32>,
+) -> Result<impl Imu<Data = FusedData>, impl core::fmt::Debug> {
+	Bno055::new(i2c, address, delay)
+}
@@ -0,0 +1,318 @@
+//! Driver for the Hillcrest/CEVA BNO080 family (BNO080, BNO085 and BNO086), using
+//! the rotation vector that its on-chip fusion outputs. Its tap detector reports the
+//! taps.
+//!
+//! Unlike most imus it has no registers. Instead it speaks SH-2 over SHTP, which
+//! splits the bus into channels that carry packets, each led by a 4 byte header. We
+//! poll it instead of waiting for its interrupt pin, reading one packet at a time.
+//! Reading only the header leaves the packet pending, so we read that first to learn
+//! how long the packet is.
+
+use crate::aliases::I2c;
+use crate::imu::{Accel, Address, FusedData, Gyro, Imu, Quat, Tap};
+use crate::utils;
+
+use defmt::{debug, info, trace, warn};
+use embassy_time::{Duration, Ticker};
+use embedded_hal::blocking::delay::DelayMs;
+use firmware_protocol::{ImuType, TAP_DOUBLE};
+use futures_util::StreamExt;
+use nalgebra::vector;
+
+/// The address when SA0 is low, and when it is high.
+const ADDRESS: u8 = 0x4A;
+const ALTERNATE_ADDRESS: u8 = 0x4B;
+const DATA_PERIOD: Duration = Duration::from_millis(10);
+const HEADER_LEN: usize = 4;
+/// Enough for the sensor reports we enable. Longer packets, like the advertisement
+/// after a reset, are cut off, and we ignore whatever they hold.
+const MAX_PACKET_LEN: usize = 64;
+/// Set in the length of a packet that continues a cut off one.
+const CONTINUATION: u16 = 1 << 15;
+/// How many packets to wait through for the answer to a request.
+const MAX_PACKETS_UNTIL_RESPONSE: u8 = 20;
+
+/// Rotation vector data is in units of 1/2^14.
+const LSB_PER_QUAT: f32 = (1 << 14) as f32;
+/// Gyro data is in units of 1/2^9 rad/s.
+const LSB_PER_RAD: f32 = (1 << 9) as f32;
+/// Linear acceleration is in units of 1/2^8 m/s^2.
+const LSB_PER_ACCEL: f32 = (1 << 8) as f32;
+
+/// The SHTP channels.
+mod channel {
+	pub const EXECUTABLE: u8 = 1;
+	pub const CONTROL: u8 = 2;
+	pub const REPORTS: u8 = 3;
+}
+
+/// Report ids on the control channel.
+mod control {
+	pub const PRODUCT_ID_RESPONSE: u8 = 0xF8;
+	pub const PRODUCT_ID_REQUEST: u8 = 0xF9;
+	pub const SET_FEATURE: u8 = 0xFD;
+}
+
+/// Report ids on the sensor report channel.
+mod report {
+	pub const GYROSCOPE: u8 = 0x02;
+	pub const LINEAR_ACCELERATION: u8 = 0x04;
+	pub const ROTATION_VECTOR: u8 = 0x05;
+	pub const TAP_DETECTOR: u8 = 0x10;
+	pub const TIMESTAMP_REBASE: u8 = 0xFA;
+	pub const BASE_TIMESTAMP: u8 = 0xFB;
+
+	/// How long a report is, or `None` if we don't know it.
+	pub fn len(id: u8) -> Option<usize> {
+		Some(match id {
+			GYROSCOPE | LINEAR_ACCELERATION => 10,
+			ROTATION_VECTOR => 14,
+			TAP_DETECTOR | TIMESTAMP_REBASE | BASE_TIMESTAMP => 5,
+			_ => return None,
+		})
+	}
+}
+
+/// The command on the executable channel that resets the chip.
+const EXECUTABLE_RESET: u8 = 1;
+
+#[derive(Debug)]
+pub enum Error<E> {
+	I2c(E),
+	/// The chip never answered the product id request.
+	NoProductId,
+}
+impl<E> From<E> for Error<E> {
+	fn from(e: E) -> Self {
+		Self::I2c(e)
+	}
+}
+
+pub struct InitError<I: I2c> {
+	pub i2c: I,
+	pub error: Error<<I as I2c>::Error>,
+}
+impl<I: I2c> core::fmt::Debug for InitError<I> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		self.error.fmt(f)
+	}
+}
+
+pub struct Bno08x<I: I2c> {
+	i2c: I,
+	address: u8,
+	ticker: Ticker,
+	/// The sequence number of the next packet we send on each channel.
+	seq: [u8; 6],
+	/// The latest gyro report, which usually arrives in the same packet as the
+	/// rotation vector.
+	gyro: Option<Gyro>,
+	/// Like the gyro report.
+	linear_accel: Option<Accel>,
+	/// A tap that was reported since the last rotation vector.
+	tap: Option<Tap>,
+}
+impl<I: I2c> Bno08x<I> {
+	pub fn new(
+		i2c: I,
+		address: Address,
+		delay: &mut impl DelayMs<u32>,
+	) -> Result<Self, InitError<I>> {
+		debug!("Constructing BNO08x...");
+		let address = address.pick(ADDRESS, ALTERNATE_ADDRESS);
+		debug!("I2C address: {:x}", address);
+
+		utils::retry(
+			4,
+			i2c,
+			|i2c| {
+				let mut bno = Self {
+					i2c,
+					address,
+					ticker: Ticker::every(DATA_PERIOD),
+					seq: [0; 6],
+					gyro: None,
+					linear_accel: None,
+					tap: None,
+				};
+				match bno.init(delay) {
+					Ok(()) => Ok(bno),
+					Err(error) => Err((bno.i2c, error)),
+				}
+			},
+			|i| warn!("Retrying IMU connection (attempts so far: {})", i + 1),
+		)
+		.map_err(|(i2c, error)| InitError { i2c, error })
+	}
+
+	fn init(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Error<I::Error>> {
+		// A reset gets us into a known state, even if only the mcu was restarted.
+		self.send(channel::EXECUTABLE, &[EXECUTABLE_RESET])?;
+		delay.delay_ms(300);
+		// The chip announces itself after a reset, which we don't need.
+		let mut buf = [0; MAX_PACKET_LEN];
+		while self.read_packet(&mut buf)?.is_some() {}
+		trace!("Reset BNO08x");
+
+		self.send(channel::CONTROL, &[control::PRODUCT_ID_REQUEST, 0])?;
+		let mut found = false;
+		for _ in 0..MAX_PACKETS_UNTIL_RESPONSE {
+			delay.delay_ms(10);
+			if let Some((channel::CONTROL, payload)) = self.read_packet(&mut buf)? {
+				if payload.first() == Some(&control::PRODUCT_ID_RESPONSE)
+					&& payload.len() >= 4
+				{
+					info!("Found BNO08x, firmware {}.{}", payload[2], payload[3]);
+					found = true;
+					break;
+				}
+			}
+		}
+		if !found {
+			return Err(Error::NoProductId);
+		}
+
+		let interval_us = DATA_PERIOD.as_micros() as u32;
+		self.enable(report::ROTATION_VECTOR, interval_us)?;
+		self.enable(report::GYROSCOPE, interval_us)?;
+		self.enable(report::LINEAR_ACCELERATION, interval_us)?;
+		// Only reports when it detects a tap, at most this often.
+		self.enable(report::TAP_DETECTOR, interval_us)?;
+		debug!("BNO08x is reporting rotation vectors");
+		Ok(())
+	}
+
+	/// Asks for the sensor report `id` every `interval_us`.
+	fn enable(&mut self, id: u8, interval_us: u32) -> Result<(), Error<I::Error>> {
+		let mut cmd = [0; 17];
+		cmd[0] = control::SET_FEATURE;
+		cmd[1] = id;
+		// Feature flags and change sensitivity stay zero.
+		cmd[5..9].copy_from_slice(&interval_us.to_le_bytes());
+		// Batch interval and sensor specific configuration stay zero too.
+		self.send(channel::CONTROL, &cmd)
+	}
+
+	fn send(&mut self, channel: u8, payload: &[u8]) -> Result<(), Error<I::Error>> {
+		let mut buf = [0; HEADER_LEN + 17];
+		let len = HEADER_LEN + payload.len();
+		buf[..2].copy_from_slice(&(len as u16).to_le_bytes());
+		buf[2] = channel;
+		buf[3] = self.seq[channel as usize];
+		self.seq[channel as usize] = self.seq[channel as usize].wrapping_add(1);
+		buf[HEADER_LEN..len].copy_from_slice(payload);
+		Ok(self.i2c.write(self.address, &buf[..len])?)
+	}
+
+	/// Reads the next packet, and returns its channel and payload. Returns `None`
+	/// when there is nothing to read, or the packet continues one that we cut off.
+	fn read_packet<'b>(
+		&mut self,
+		buf: &'b mut [u8; MAX_PACKET_LEN],
+	) -> Result<Option<(u8, &'b [u8])>, Error<I::Error>> {
+		let mut header = [0; HEADER_LEN];
+		self.i2c.read(self.address, &mut header)?;
+		let len = u16::from_le_bytes([header[0], header[1]]);
+		// All ones is what an idle bus reads as.
+		if len == u16::MAX || len & CONTINUATION != 0 {
+			return Ok(None);
+		}
+		let len = usize::from(len).min(MAX_PACKET_LEN);
+		if len <= HEADER_LEN {
+			return Ok(None);
+		}
+		// The packet starts over with its header.
+		self.i2c.read(self.address, &mut buf[..len])?;
+		Ok(Some((buf[2], &buf[HEADER_LEN..len])))
+	}
+
+	/// Takes the sensor reports out of a packet, and returns the fused data if it
+	/// held a rotation vector.
+	fn parse_reports(&mut self, mut payload: &[u8]) -> Option<FusedData> {
+		let mut rotation = None;
+		while let Some(len) = payload.first().copied().and_then(report::len) {
+			if payload.len() < len {
+				break;
+			}
+			let (r, rest) = payload.split_at(len);
+			payload = rest;
+			// Sensor reports lead with their id, a sequence number, a status and a
+			// delay.
+			let value =
+				|i: usize| i16::from_le_bytes([r[4 + 2 * i], r[5 + 2 * i]]) as f32;
+			match r[0] {
+				report::GYROSCOPE => {
+					let [x, y, z] = [0, 1, 2].map(value);
+					self.gyro = Some(vector![x, y, z] / LSB_PER_RAD);
+				}
+				report::LINEAR_ACCELERATION => {
+					let [x, y, z] = [0, 1, 2].map(value);
+					self.linear_accel = Some(vector![x, y, z] / LSB_PER_ACCEL);
+				}
+				// The flags hold the axes of the tap, and whether it was the second
+				// of a double tap, in the layout of `SbPacket::Tap`.
+				report::TAP_DETECTOR => {
+					let double = r[4] & TAP_DOUBLE != 0;
+					self.tap = Some(Tap { double });
+				}
+				report::ROTATION_VECTOR => {
+					let [i, j, k, w] = [0, 1, 2, 3].map(|i| value(i) / LSB_PER_QUAT);
+					// The low bits of the status are its accuracy, from 0 to 3.
+					rotation =
+						Some((nalgebra::Quaternion::new(w, i, j, k), r[2] & 0b11));
+				}
+				_ => (),
+			}
+		}
+		let (q, accuracy) = rotation?;
+		Some(FusedData {
+			// Reads as all zeroes until the fusion has its first output.
+			q: Quat::try_new(q, 0.1)?,
+			angular_velocity: self.gyro,
+			accuracy: Some(accuracy),
+			raw: None,
+			linear_accel: self.linear_accel,
+			tap: self.tap.take(),
+		})
+	}
+}
+
+impl<I: I2c> Imu for Bno08x<I> {
+	type Error = Error<<I as I2c>::Error>;
+	type Data = FusedData;
+
+	const IMU_TYPE: ImuType = ImuType::Bno085;
+
+	async fn next_data(&mut self) -> Result<Self::Data, Self::Error> {
+		let mut buf = [0; MAX_PACKET_LEN];
+		loop {
+			self.ticker.next().await;
+			// The gyro and rotation vector can arrive in separate packets, so read all
+			// that queued up since the last tick.
+			while let Some((channel, payload)) = self.read_packet(&mut buf)? {
+				if channel != channel::REPORTS {
+					continue;
+				}
+				if let Some(data) = self.parse_reports(payload) {
+					return Ok(data);
+				}
+			}
+		}
+	}
+
+	fn restart(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Self::Error> {
+		self.gyro = None;
+		self.linear_accel = None;
+		self.tap = None;
+		self.init(delay)
+	}
+}
+
+#[allow(dead_code)]
+pub fn new_imu(
+	i2c: impl crate::aliases::I2c,
+	address: Address,
+	delay: &mut impl DelayMs<u32>,
+) -> Result<impl Imu<Data = FusedData>, impl core::fmt::Debug> {
+	Bno08x::new(i2c, address, delay)
+}
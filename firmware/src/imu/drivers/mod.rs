@@ -1,3 +1,6 @@
 pub mod bmi160;
+pub mod bno055;
+pub mod bno08x;
 pub mod mpu6050;
+pub mod mpu6050_raw;
 pub mod stubbed;
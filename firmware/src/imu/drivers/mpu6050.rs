@@ -1,5 +1,5 @@
 use crate::aliases::I2c;
-use crate::imu::{FusedData, Imu, Quat};
+use crate::imu::{self, FusedData, Imu, Quat};
 use crate::utils::{self, nb2a};
 
 use defmt::{debug, trace, warn};
@@ -14,9 +14,13 @@ pub struct Mpu6050<I: I2c> {
 	fifo_buf: [u8; 28],
 }
 impl<I: I2c> Mpu6050<I> {
-	pub fn new(i2c: I, delay: &mut impl DelayMs<u32>) -> Result<Self, InitError<I>> {
+	pub fn new(
+		i2c: I,
+		address: imu::Address,
+		delay: &mut impl DelayMs<u32>,
+	) -> Result<Self, InitError<I>> {
 		debug!("Constructing MPU...");
-		let addr = Address::from(0x68);
+		let addr = Address::from(address.pick(0x68, 0x69));
 		debug!("I2C address: {:x}", addr.0);
 
 		utils::retry(
@@ -74,14 +78,29 @@ impl<I: I2c> Imu for Mpu6050<I> {
 	const IMU_TYPE: ImuType = ImuType::Mpu6050;
 
 	async fn next_data(&mut self) -> Result<Self::Data, Self::Error> {
-		nb2a(|| self.quat()).await.map(|quat| FusedData { q: quat })
+		// TODO: The DMP FIFO packet also contains gyro data, we could parse it.
+		nb2a(|| self.quat()).await.map(|quat| FusedData {
+			q: quat,
+			angular_velocity: None,
+			accuracy: None,
+			raw: None,
+			linear_accel: None,
+			tap: None,
+		})
+	}
+
+	fn restart(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Self::Error> {
+		// Resets the chip before loading the DMP firmware again.
+		self.mpu.initialize_dmp(delay)
 	}
 }
 
 #[allow(dead_code)]
 pub fn new_imu(
 	i2c: impl crate::aliases::I2c,
+	address: imu::Address,
 	delay: &mut impl DelayMs<u32>,
-) -> impl Imu<Data = FusedData> {
-	Mpu6050::new(i2c, delay).expect("Failed to create mpu6050")
+) -> Result<impl Imu<Data = FusedData>, impl core::fmt::Debug> {
+	// Only the error is `Debug`, the bus we get back along with it isn't.
+	Mpu6050::new(i2c, address, delay).map_err(|e| e.error)
 }
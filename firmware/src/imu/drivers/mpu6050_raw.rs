@@ -0,0 +1,389 @@
+//! Minimal MPU-6050 driver that reads the raw accelerometer and gyroscope, instead
+//! of using the DMP. Meant to be paired with the `budget` fuser.
+//!
+//! Also works with the MPU-9250, which has the same registers plus an AK8963
+//! magnetometer inside. Its readings are passed on too, for a fuser like `vqf` that
+//! can correct yaw with them. It only measures at `MAG_RATE_HZ`, powering down in
+//! between.
+//!
+//! Talks to the chip in registers, so it also works over SPI with the MPU-6000 and
+//! the MPU-9250, see [`crate::imu::regs`]. The magnetometer of the MPU-9250 is only
+//! read over I2C, though.
+
+use crate::aliases::BurstRead;
+use crate::imu::fusion::{new_fuser, FusedImu};
+use crate::imu::regs::{I2cRegisters, Registers};
+use crate::imu::{Address, FusedData, Imu, Mag, UnfusedData, MAG_RATE_HZ};
+use crate::utils;
+
+use defmt::{debug, trace, warn};
+use embassy_time::{Duration, Instant, Ticker};
+use embedded_hal::blocking::delay::DelayMs;
+use firmware_protocol::ImuType;
+use futures_util::StreamExt;
+use nalgebra::vector;
+
+/// The address when AD0 is low, and when it is high.
+const ADDRESS: u8 = 0x68;
+const ALTERNATE_ADDRESS: u8 = 0x69;
+const WHO_AM_I: u8 = 0x68;
+const WHO_AM_I_MPU9250: u8 = 0x71;
+/// The AK8963 inside the MPU-9250, once the MPU passes its bus through to it.
+const MAG_ADDRESS: u8 = 0x0C;
+const MAG_WHO_AM_I: u8 = 0x48;
+/// The sample rate, 1kHz / (1 + 4) = 200Hz.
+const SAMPLE_DIVIDER: u8 = 4;
+const SAMPLE_PERIOD: Duration = Duration::from_millis(5);
+/// Over SPI, which reads a sample in a fraction of the time, 1kHz / (1 + 1) = 500Hz.
+const SPI_SAMPLE_DIVIDER: u8 = 1;
+const SPI_SAMPLE_PERIOD: Duration = Duration::from_millis(2);
+/// The sample rate while the tracker lies still, 1kHz / (1 + 49) = 20Hz.
+const LOW_POWER_DIVIDER: u8 = 49;
+const LOW_POWER_PERIOD: Duration = Duration::from_millis(50);
+/// How much the acceleration has to change to wake the tracker up, at 2 mg per LSB.
+const WAKE_THRESHOLD: u8 = 20;
+const MAG_PERIOD: Duration = Duration::from_micros(1_000_000 / MAG_RATE_HZ as u64);
+/// 16 bit output, measuring once and then powering down again.
+const MAG_SINGLE_MEASUREMENT: u8 = 0x11;
+
+const RAD_PER_DEG: f32 = core::f32::consts::PI / 180.;
+/// At a full scale range of +/- 500 deg/s.
+const LSB_PER_DPS: f32 = 65.5;
+/// At a full scale range of +/- 4g.
+const LSB_PER_G: f32 = 8192.;
+const ACCEL_PER_G: f32 = 9.81;
+/// The temperature sensor of the MPU-6050, and the one of the MPU-9250, which is
+/// scaled and offset differently.
+const LSB_PER_CELSIUS: f32 = 340.;
+const CELSIUS_OFFSET: f32 = 36.53;
+const LSB_PER_CELSIUS_MPU9250: f32 = 333.87;
+const CELSIUS_OFFSET_MPU9250: f32 = 21.;
+/// In the 16 bit output mode, before the factory sensitivity adjustment.
+const MICROTESLA_PER_LSB: f32 = 0.15;
+
+mod reg {
+	pub const SMPLRT_DIV: u8 = 0x19;
+	pub const CONFIG: u8 = 0x1A;
+	pub const GYRO_CONFIG: u8 = 0x1B;
+	pub const ACCEL_CONFIG: u8 = 0x1C;
+	pub const MOT_THR: u8 = 0x1F;
+	pub const MOT_DUR: u8 = 0x20;
+	pub const INT_PIN_CFG: u8 = 0x37;
+	pub const INT_ENABLE: u8 = 0x38;
+	pub const ACCEL_XOUT_H: u8 = 0x3B;
+	pub const MOT_DETECT_CTRL: u8 = 0x69;
+	pub const USER_CTRL: u8 = 0x6A;
+	pub const PWR_MGMT_1: u8 = 0x6B;
+	pub const PWR_MGMT_2: u8 = 0x6C;
+	pub const WHO_AM_I: u8 = 0x75;
+}
+
+mod mag_reg {
+	pub const WIA: u8 = 0x00;
+	pub const ST1: u8 = 0x02;
+	pub const CNTL1: u8 = 0x0A;
+	pub const ASAX: u8 = 0x10;
+}
+
+#[derive(Debug)]
+pub enum Error<E> {
+	Bus(E),
+	/// The chip didn't identify itself as an MPU-6050 or MPU-9250.
+	WrongChipId(u8),
+	/// The magnetometer of an MPU-9250 didn't identify itself as an AK8963.
+	WrongMagId(u8),
+}
+impl<E> From<E> for Error<E> {
+	fn from(e: E) -> Self {
+		Self::Bus(e)
+	}
+}
+
+pub struct InitError<R: Registers> {
+	pub regs: R,
+	pub error: Error<R::Error>,
+}
+impl<R: Registers> core::fmt::Debug for InitError<R> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		self.error.fmt(f)
+	}
+}
+
+struct Magnetometer<R> {
+	regs: R,
+	/// Microtesla per LSB of each axis.
+	sensitivity: [f32; 3],
+	/// When to read the measurement that was started last, which is done well
+	/// before then.
+	due: Instant,
+}
+impl<R: Registers> Magnetometer<R> {
+	/// Reads the last measurement, and starts the next one. Returns `None` if the
+	/// measurement isn't done yet, so that it is read again with the next sample.
+	async fn read(&mut self) -> Result<Option<Mag>, Error<R::Error>> {
+		// Status 1, the little endian readings, then status 2, which has to be read
+		// for the next measurement to be latched.
+		let mut buf = [0; 8];
+		self.regs.burst_read(mag_reg::ST1, &mut buf).await?;
+		if buf[0] & 0x01 == 0 {
+			return Ok(None);
+		}
+		self.regs
+			.write_reg(mag_reg::CNTL1, MAG_SINGLE_MEASUREMENT)?;
+		self.due = Instant::now() + MAG_PERIOD;
+		let overflow = buf[7] & 0x08 != 0;
+		if overflow {
+			return Ok(None);
+		}
+		let [x, y, z] = [0, 1, 2].map(|i| {
+			let raw = i16::from_le_bytes([buf[1 + 2 * i], buf[2 + 2 * i]]);
+			raw as f32 * self.sensitivity[i]
+		});
+		// Its x and y are swapped and its z points the other way, compared to the
+		// accelerometer.
+		Ok(Some(vector![y, x, -z]))
+	}
+}
+
+pub struct Mpu6050Raw<R: Registers> {
+	regs: R,
+	address: Address,
+	ticker: Ticker,
+	/// Whether the chip is an MPU-9250, even if we don't use its magnetometer.
+	mpu9250: bool,
+	mag: Option<Magnetometer<R>>,
+	/// In degrees Celsius, from the last sample, which has it between the
+	/// accelerometer and the gyro anyway.
+	temperature: Option<f32>,
+}
+impl<R: Registers> Mpu6050Raw<R> {
+	/// `address` is the one that `regs` is at, or [`Address::Primary`] over SPI.
+	pub fn new(
+		regs: R,
+		address: Address,
+		delay: &mut impl DelayMs<u32>,
+	) -> Result<Self, InitError<R>> {
+		debug!("Constructing raw MPU...");
+		utils::retry(
+			4,
+			regs,
+			|regs| {
+				let mut mpu = Self {
+					regs,
+					address,
+					ticker: Ticker::every(SAMPLE_PERIOD),
+					mpu9250: false,
+					mag: None,
+					temperature: None,
+				};
+				match mpu.init(delay) {
+					Ok(()) => Ok(mpu),
+					Err(error) => Err((mpu.regs, error)),
+				}
+			},
+			|i| warn!("Retrying IMU connection (attempts so far: {})", i + 1),
+		)
+		.map_err(|(regs, error)| InitError { regs, error })
+	}
+
+	fn init(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Error<R::Error>> {
+		delay.delay_ms(100);
+		trace!("Flushing the bus");
+		self.regs.flush();
+		delay.delay_ms(100);
+
+		let mut id = [0];
+		self.regs.read_regs(reg::WHO_AM_I, &mut id)?;
+		if id[0] != WHO_AM_I && id[0] != WHO_AM_I_MPU9250 {
+			return Err(Error::WrongChipId(id[0]));
+		}
+		self.mpu9250 = id[0] == WHO_AM_I_MPU9250;
+
+		// Wakes up, with the x gyro as the clock since it is more stable.
+		self.write_reg(reg::PWR_MGMT_1, 0x01)?;
+		delay.delay_ms(10);
+		if R::SPI {
+			// Turns off I2C, which it could mistake SPI traffic for.
+			self.write_reg(reg::USER_CTRL, 0x10)?;
+		}
+		// Undoes the wake-on-motion of deep sleep, which these survive.
+		self.write_reg(reg::PWR_MGMT_2, 0x00)?;
+		self.write_reg(reg::INT_ENABLE, 0x00)?;
+		self.write_reg(reg::INT_PIN_CFG, 0x00)?;
+		// 44Hz low pass filter, which also makes the gyro output rate 1kHz.
+		self.write_reg(reg::CONFIG, 0x03)?;
+		let (divider, period) = Self::sample_rate();
+		self.write_reg(reg::SMPLRT_DIV, divider)?;
+		self.ticker = Ticker::every(period);
+		// +/- 500 deg/s
+		self.write_reg(reg::GYRO_CONFIG, 1 << 3)?;
+		// +/- 4g
+		self.write_reg(reg::ACCEL_CONFIG, 1 << 3)?;
+		// The magnetometer of an extension would end up at the same address as our
+		// own, so only the primary one gets it.
+		if self.mpu9250 && self.address == Address::Primary {
+			self.mag = self.init_mag(delay)?;
+		}
+		debug!("Initialized raw MPU");
+		Ok(())
+	}
+
+	fn init_mag(
+		&mut self,
+		delay: &mut impl DelayMs<u32>,
+	) -> Result<Option<Magnetometer<R>>, Error<R::Error>> {
+		// TODO: Over SPI, it could be read with the I2C master of the MPU instead.
+		let Some(mut regs) = self.regs.neighbor(MAG_ADDRESS) else {
+			debug!("Skipping the MPU-9250 magnetometer, which needs I2C");
+			return Ok(None);
+		};
+		// Bypass mode connects the magnetometer to our bus.
+		self.write_reg(reg::INT_PIN_CFG, 0x02)?;
+		let mut id = [0];
+		regs.read_regs(mag_reg::WIA, &mut id)?;
+		if id[0] != MAG_WHO_AM_I {
+			return Err(Error::WrongMagId(id[0]));
+		}
+
+		// The factory sensitivity adjustments can only be read in fuse ROM mode.
+		regs.write_reg(mag_reg::CNTL1, 0x0F)?;
+		delay.delay_ms(10);
+		let mut asa = [0; 3];
+		regs.read_regs(mag_reg::ASAX, &mut asa)?;
+		regs.write_reg(mag_reg::CNTL1, 0x00)?;
+		delay.delay_ms(10);
+		regs.write_reg(mag_reg::CNTL1, MAG_SINGLE_MEASUREMENT)?;
+
+		let sensitivity =
+			asa.map(|a| ((a as f32 - 128.) / 256. + 1.) * MICROTESLA_PER_LSB);
+		debug!("Initialized MPU-9250 magnetometer");
+		Ok(Some(Magnetometer {
+			regs,
+			sensitivity,
+			due: Instant::now() + MAG_PERIOD,
+		}))
+	}
+
+	/// The divider of the sample rate, and the time between samples.
+	fn sample_rate() -> (u8, Duration) {
+		if R::SPI {
+			(SPI_SAMPLE_DIVIDER, SPI_SAMPLE_PERIOD)
+		} else {
+			(SAMPLE_DIVIDER, SAMPLE_PERIOD)
+		}
+	}
+
+	fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), R::Error> {
+		self.regs.write_reg(reg, value)
+	}
+}
+
+impl<R: Registers> Imu for Mpu6050Raw<R> {
+	type Error = Error<R::Error>;
+	type Data = UnfusedData;
+
+	const IMU_TYPE: ImuType = ImuType::Mpu6050;
+
+	async fn next_data(&mut self) -> Result<Self::Data, Self::Error> {
+		self.ticker.next().await;
+
+		// Accel, temperature, then gyro, all big endian.
+		let mut buf = [0; 14];
+		self.regs.burst_read(reg::ACCEL_XOUT_H, &mut buf).await?;
+		let raw = |i: usize| i16::from_be_bytes([buf[i], buf[i + 1]]) as f32;
+
+		#[inline]
+		fn a(raw: f32) -> f32 {
+			raw / LSB_PER_G * ACCEL_PER_G
+		}
+		#[inline]
+		fn g(raw: f32) -> f32 {
+			raw / LSB_PER_DPS * RAD_PER_DEG
+		}
+
+		let accel = vector![a(raw(0)), a(raw(2)), a(raw(4))];
+		let gyro = vector![g(raw(8)), g(raw(10)), g(raw(12))];
+		self.temperature = Some(if self.mpu9250 {
+			raw(6) / LSB_PER_CELSIUS_MPU9250 + CELSIUS_OFFSET_MPU9250
+		} else {
+			raw(6) / LSB_PER_CELSIUS + CELSIUS_OFFSET
+		});
+
+		let mag = match &mut self.mag {
+			Some(mag) if Instant::now() >= mag.due => mag.read().await?,
+			_ => None,
+		};
+
+		Ok(UnfusedData { accel, gyro, mag })
+	}
+
+	fn restart(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Self::Error> {
+		self.mag = None;
+		self.temperature = None;
+		self.init(delay)
+	}
+
+	fn temperature(&mut self) -> Result<Option<f32>, Self::Error> {
+		Ok(self.temperature)
+	}
+
+	/// The gyro draws the most power, but the fusion needs it, so all that is left
+	/// is measuring less often.
+	fn set_low_power(&mut self, enabled: bool) -> Result<bool, Self::Error> {
+		let (divider, period) = if enabled {
+			(LOW_POWER_DIVIDER, LOW_POWER_PERIOD)
+		} else {
+			Self::sample_rate()
+		};
+		self.write_reg(reg::SMPLRT_DIV, divider)?;
+		self.ticker = Ticker::every(period);
+		Ok(true)
+	}
+
+	/// Follows InvenSense's sequence for the motion interrupt in the accelerometer's
+	/// low power mode.
+	fn arm_wake_on_motion(&mut self) -> Result<bool, Self::Error> {
+		// TODO: The MPU-9250 moved its motion interrupt to other registers.
+		if self.mpu9250 {
+			return Ok(false);
+		}
+		// The gyros go into standby.
+		self.write_reg(reg::PWR_MGMT_1, 0x00)?;
+		self.write_reg(reg::PWR_MGMT_2, 0x07)?;
+		// +/- 4g, with the 5Hz high pass filter so that only changes count.
+		self.write_reg(reg::ACCEL_CONFIG, (1 << 3) | 0x01)?;
+		self.write_reg(reg::MOT_THR, WAKE_THRESHOLD)?;
+		// For at least 1ms, and without delaying the accelerometer's power up.
+		self.write_reg(reg::MOT_DUR, 1)?;
+		self.write_reg(reg::MOT_DETECT_CTRL, 0x15)?;
+		// Active high, and latched so that the MCU can't miss it.
+		self.write_reg(reg::INT_PIN_CFG, 0x20)?;
+		self.write_reg(reg::INT_ENABLE, 0x40)?;
+		// Wakes up to measure at 5Hz, and cycles with the temperature sensor off.
+		self.write_reg(reg::PWR_MGMT_2, 0x47)?;
+		self.write_reg(reg::PWR_MGMT_1, 0x28)?;
+		Ok(true)
+	}
+}
+
+#[allow(dead_code)]
+pub fn new_imu(
+	i2c: impl BurstRead + Clone,
+	address: Address,
+	delay: &mut impl DelayMs<u32>,
+) -> Result<impl Imu<Data = FusedData>, impl core::fmt::Debug> {
+	let i2c_address = address.pick(ADDRESS, ALTERNATE_ADDRESS);
+	debug!("I2C address: {:x}", i2c_address);
+	let regs = I2cRegisters::new(i2c, i2c_address);
+	Mpu6050Raw::new(regs, address, delay).map(|mpu| FusedImu::new(mpu, new_fuser()))
+}
+
+/// For the only imu on an SPI bus.
+#[allow(dead_code)]
+pub fn new_spi_imu(
+	spi: impl Registers,
+	delay: &mut impl DelayMs<u32>,
+) -> Result<impl Imu<Data = FusedData>, impl core::fmt::Debug> {
+	Mpu6050Raw::new(spi, Address::Primary, delay)
+		.map(|mpu| FusedImu::new(mpu, new_fuser()))
+}
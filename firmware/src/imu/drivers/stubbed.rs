@@ -1,4 +1,4 @@
-use crate::imu::{FusedData, Imu, Quat};
+use crate::imu::{Address, FusedData, Imu, Quat};
 
 use defmt::debug;
 use embassy_time::{Duration, Ticker};
@@ -24,15 +24,25 @@ impl Imu for FakeImu {
 		self.0.next().await;
 		Ok(FusedData {
 			q: Quat::identity(),
+			angular_velocity: Some(nalgebra::Vector3::zeros()),
+			accuracy: None,
+			raw: None,
+			linear_accel: None,
+			tap: None,
 		})
 	}
+
+	fn restart(&mut self, _delay: &mut impl DelayMs<u32>) -> Result<(), Self::Error> {
+		Ok(())
+	}
 }
 
 #[allow(dead_code)]
 pub fn new_imu(
 	_i2c: impl crate::aliases::I2c,
+	address: Address,
 	_delay: &mut impl DelayMs<u32>,
-) -> impl Imu<Data = FusedData> {
-	debug!("Created FakeImu");
-	FakeImu::new()
+) -> Result<impl Imu<Data = FusedData>, impl core::fmt::Debug> {
+	debug!("Created FakeImu at the {} address", address);
+	Ok::<_, ()>(FakeImu::new())
 }
@@ -1,25 +1,43 @@
-mod dcm;
-mod stubbed;
+//! Fuses the readings of imus that don't fuse on-chip, with the fusers of the
+//! `fusers` crate. Those build on the host, so their tests run there.
 
-pub use self::dcm::Dcm;
-pub use self::stubbed::Stubbed;
+pub use fusers::{Algorithm, Fuser};
+use fusers::{Clock, Estimate, Swappable};
 
+use crate::imu::calibration::{Calibrator, Routine, G};
+use crate::imu::tap::TapDetector;
 use crate::imu::{FusedData, Imu, UnfusedData};
 
+use embassy_time::Instant;
+use embedded_hal::blocking::delay::DelayMs;
 use firmware_protocol::ImuType;
+use nalgebra::vector;
 
-/// Represents a sensor fusion algorithm that will take an imu's `UnfusedData` and
-/// do math to turn it into `FusedData`, suitable for use as orientation.
-pub trait Fuser {
-	// Note: Intentionally not async, this should only be doing math, not io or
-	// any internal awaiting.
-	fn process(&mut self, unfused: &UnfusedData) -> FusedData;
+/// The clock of embassy, which the fusers use on the tracker.
+#[derive(Debug, Default, Clone, Copy)]
+struct SystemClock;
+impl Clock for SystemClock {
+	fn now(&self) -> core::time::Duration {
+		core::time::Duration::from_micros(Instant::now().as_micros())
+	}
 }
 
-/// Combines an unfused `Imu` with a `Fuser`.
+/// Combines an unfused `Imu` with a `Fuser`, calibrating the readings in between.
 pub struct FusedImu<I: Imu, F: Fuser> {
-	pub imu: I,
-	pub fuser: F,
+	imu: I,
+	fuser: F,
+	calibrator: Calibrator,
+	taps: TapDetector,
+}
+impl<I: Imu, F: Fuser> FusedImu<I, F> {
+	pub fn new(imu: I, fuser: F) -> Self {
+		Self {
+			imu,
+			fuser,
+			calibrator: Calibrator::new(),
+			taps: TapDetector::new(),
+		}
+	}
 }
 impl<I: Imu<Data = UnfusedData>, F: Fuser> Imu for FusedImu<I, F> {
 	type Error = I::Error;
@@ -28,17 +46,88 @@ impl<I: Imu<Data = UnfusedData>, F: Fuser> Imu for FusedImu<I, F> {
 	const IMU_TYPE: ImuType = I::IMU_TYPE;
 
 	async fn next_data(&mut self) -> Result<Self::Data, Self::Error> {
-		let unfused = self.imu.next_data().await?;
-		Ok(self.fuser.process(&unfused))
+		let mut unfused = self.imu.next_data().await?;
+		// Streamed raw samples stay uncalibrated, so they can be used to calibrate.
+		let raw = unfused;
+		self.calibrator.process(&mut unfused);
+		let Estimate {
+			q,
+			angular_velocity,
+			linear_accel,
+			accuracy,
+		} = self.fuser.process(&unfused);
+		// The fusers keep z up, so that is where gravity pulls the accelerometer.
+		let gravity = q.inverse_transform_vector(&vector![0., 0., G]);
+		let linear_accel = linear_accel.unwrap_or(unfused.accel - gravity);
+		let now = Instant::now();
+		Ok(FusedData {
+			q,
+			// Unless the fuser knows better, the gyro reading is the angular velocity.
+			angular_velocity: angular_velocity.or(Some(unfused.gyro)),
+			accuracy,
+			raw: Some((now, raw)),
+			linear_accel: Some(linear_accel),
+			tap: self.taps.update(&linear_accel, now),
+		})
+	}
+
+	fn calibrate(&mut self, routine: Routine) -> bool {
+		self.calibrator.start(routine);
+		true
+	}
+
+	fn is_calibrating(&self) -> bool {
+		self.calibrator.is_active()
+	}
+
+	fn reset_fusion(&mut self) -> bool {
+		self.fuser.reset();
+		true
+	}
+
+	/// Keeps the calibration, since that is stored anyway.
+	fn restart(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Self::Error> {
+		self.imu.restart(delay)?;
+		self.fuser.reset();
+		Ok(())
+	}
+
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		self.fuser.set_accel_gain(gain)
+	}
+
+	fn set_fusion(&mut self, algorithm: Algorithm) -> bool {
+		self.fuser.switch(algorithm)
+	}
+
+	fn set_low_power(&mut self, enabled: bool) -> Result<bool, Self::Error> {
+		self.imu.set_low_power(enabled)
+	}
+
+	fn temperature(&mut self) -> Result<Option<f32>, Self::Error> {
+		self.imu.temperature()
+	}
+
+	fn arm_wake_on_motion(&mut self) -> Result<bool, Self::Error> {
+		self.imu.arm_wake_on_motion()
 	}
 }
 
-/// Builds a new fuser. The concrete impl is determined by a feature flag.
+/// Builds a new fuser. The concrete impl is determined by a feature flag, and the
+/// server can switch to the others at runtime.
 pub fn new_fuser() -> impl Fuser {
 	#[cfg(feature = "fusion-stubbed")]
-	let f = Stubbed::new();
+	let f = fusers::Stubbed::new(SystemClock);
 	#[cfg(feature = "fusion-dcm")]
-	let f = Dcm::new();
+	let f = fusers::Dcm::new(SystemClock);
+	#[cfg(feature = "fusion-budget")]
+	let f = fusers::Budget::new(SystemClock);
+	#[cfg(feature = "fusion-fixed")]
+	let f = fusers::Fixed::new(SystemClock);
+	#[cfg(feature = "fusion-vqf")]
+	let f = fusers::Vqf::new(SystemClock, crate::imu::MAG_RATE_HZ);
+	#[cfg(feature = "fusion-external")]
+	let f = fusers::External::<external_fuser::Fuser, _>::new(SystemClock);
 
-	f
+	Swappable::new(f, SystemClock)
 }
@@ -0,0 +1,174 @@
+//! Detects sensors being connected or disconnected at runtime, so that they can be
+//! registered and unregistered with the server without needing a reboot. Also
+//! detects sensors that stopped producing data, so that the server can mark them as
+//! erroring instead of frozen.
+//!
+//! Before a driver gets to initialize a sensor, [`probe`] reads the id register of
+//! every chip that we know at the sensor's address, so that a missing sensor or one
+//! that the firmware wasn't built for is told apart from one that failed to start.
+//! Sensors that are missing, or that got disconnected, are probed again every
+//! [`PROBE_INTERVAL`], like an extension that is plugged in later.
+
+use defmt::Format;
+use embassy_time::Duration;
+use firmware_protocol::{ImuType, SensorStatus};
+
+use crate::aliases::I2c;
+use crate::imu::Address;
+
+/// How many consecutive failed reads before we consider a sensor disconnected.
+const DISCONNECT_THRESHOLD: u8 = 10;
+/// If a sensor doesn't produce any data for this long, it is considered offline.
+/// Configured with `SENSOR_TIMEOUT_MS` in `.env`.
+pub const SENSOR_TIMEOUT: Duration =
+	Duration::from_millis(crate::utils::parse_u32(env!("SENSOR_TIMEOUT_MS")) as u64);
+
+/// How often the address of a missing sensor is probed.
+#[cfg_attr(spi_imu, allow(dead_code))]
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A chip that answered [`probe`].
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Chip {
+	Mpu6050,
+	Mpu9250,
+	Bmi160,
+	Bno055,
+	Bno08x,
+}
+impl Chip {
+	/// Whether the driver of this build talks to the chip.
+	pub const fn is_supported(self) -> bool {
+		match self {
+			Self::Mpu6050 => {
+				cfg!(any(feature = "imu-mpu6050", feature = "imu-mpu6050-raw"))
+			}
+			Self::Mpu9250 => cfg!(feature = "imu-mpu6050-raw"),
+			Self::Bmi160 => cfg!(feature = "imu-bmi160"),
+			Self::Bno055 => cfg!(feature = "imu-bno055"),
+			Self::Bno08x => cfg!(feature = "imu-bno08x"),
+		}
+	}
+}
+
+/// The addresses of the chips, and where their id is, with the ids that they answer
+/// with. The BNO08x talk SHTP, which has no registers, so any answer counts.
+const KNOWN: [((u8, u8), Option<u8>, &[(u8, Chip)]); 4] = [
+	((0x68, 0x69), Some(0x00), &[(0xD1, Chip::Bmi160)]),
+	(
+		(0x68, 0x69),
+		Some(0x75),
+		&[(0x68, Chip::Mpu6050), (0x71, Chip::Mpu9250)],
+	),
+	((0x28, 0x29), Some(0x00), &[(0xA0, Chip::Bno055)]),
+	((0x4A, 0x4B), None, &[(0, Chip::Bno08x)]),
+];
+
+/// Finds out which chip is at `address`, if any.
+#[cfg_attr(spi_imu, allow(dead_code))]
+pub fn probe(i2c: &mut impl I2c, address: Address) -> Option<Chip> {
+	for ((primary, alternate), id_reg, ids) in KNOWN {
+		let address = address.pick(primary, alternate);
+		let mut id = [0];
+		let answered = match id_reg {
+			Some(reg) => i2c.write_read(address, &[reg], &mut id).is_ok(),
+			None => i2c.read(address, &mut id).is_ok(),
+		};
+		if !answered {
+			continue;
+		}
+		let found = ids
+			.iter()
+			.find(|(expected, _)| id_reg.is_none() || *expected == id[0]);
+		match found {
+			Some(&(_, chip)) => return Some(chip),
+			None => defmt::trace!("Unknown id {:#x} at {:#x}", id[0], address),
+		}
+	}
+	None
+}
+
+/// Why there is no sensor at an address.
+#[derive(Debug)]
+pub enum ConnectError<E> {
+	/// No chip that we know answered.
+	Absent,
+	/// A chip answered that the driver of this build doesn't talk to.
+	Unsupported(Chip),
+	/// The driver failed to initialize the chip.
+	Init(E),
+}
+
+/// Sent whenever a sensor gets connected or disconnected.
+#[derive(Debug, Clone, Copy)]
+pub struct SensorEvent {
+	pub sensor_id: u8,
+	pub status: SensorStatus,
+	pub imu_type: ImuType,
+	/// Why the sensor went offline, if it was due to an error.
+	pub error: Option<SensorError>,
+}
+
+/// The error codes we send in `SbPacket::SensorError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+#[repr(u8)]
+pub enum SensorError {
+	/// Reads from the sensor kept failing.
+	Disconnected = 1,
+	/// The sensor didn't produce any data within [`SENSOR_TIMEOUT`].
+	Timeout = 2,
+}
+impl SensorError {
+	/// What went wrong, for the `SbPacket::Diagnostic` that is sent alongside.
+	pub const fn message(self) -> &'static str {
+		match self {
+			Self::Disconnected => "Sensor stopped responding, check its wiring",
+			Self::Timeout => "Sensor stopped producing data",
+		}
+	}
+}
+
+/// Tracks whether a sensor is still connected, based on whether reads from it are
+/// succeeding.
+pub struct Presence {
+	consecutive_failures: u8,
+	status: SensorStatus,
+}
+impl Presence {
+	/// A sensor that was just successfully initialized.
+	pub const fn new() -> Self {
+		Self {
+			consecutive_failures: 0,
+			status: SensorStatus::Ok,
+		}
+	}
+
+	/// Call after every successful read. Returns the new status if it changed.
+	pub fn on_success(&mut self) -> Option<SensorStatus> {
+		self.consecutive_failures = 0;
+		self.set_status(SensorStatus::Ok)
+	}
+
+	/// Call when the sensor hasn't produced data for [`SENSOR_TIMEOUT`]. Returns the
+	/// new status if it changed.
+	pub fn on_timeout(&mut self) -> Option<SensorStatus> {
+		self.set_status(SensorStatus::Offline)
+	}
+
+	/// Call after every failed read. Returns the new status if it changed.
+	pub fn on_failure(&mut self) -> Option<SensorStatus> {
+		self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+		if self.consecutive_failures < DISCONNECT_THRESHOLD {
+			return None;
+		}
+		self.set_status(SensorStatus::Offline)
+	}
+
+	fn set_status(&mut self, status: SensorStatus) -> Option<SensorStatus> {
+		if self.status == status {
+			return None;
+		}
+		self.status = status;
+		Some(status)
+	}
+}
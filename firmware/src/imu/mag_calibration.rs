@@ -0,0 +1,201 @@
+//! Hard and soft iron calibration of magnetometers. Hard iron distortion, from
+//! magnets and magnetized metal that move along with the tracker, adds a constant
+//! offset. Soft iron distortion, from metal that bends the field, stretches the
+//! sphere of readings into an ellipsoid. Both are undone before fusion.
+//!
+//! The calibration is measured on the tracker with [`Routine::Mag`], which the server
+//! starts, and kept in `storage` like the one of the gyro and accelerometer. While it
+//! runs, the tracker is turned around slowly in every direction, and the smallest and
+//! largest reading of each axis are kept. Once they stop growing, their middle is
+//! the hard iron offset, and each axis is scaled so that they all span as much as
+//! their average. That leaves out soft iron distortion that is askew to the axes,
+//! which is the smaller part of it on a tracker.
+//!
+//! [`Routine::Mag`]: crate::imu::calibration::Routine::Mag
+
+use defmt::{info, warn};
+use embassy_time::{Duration, Instant};
+use nalgebra::Matrix3;
+
+use crate::imu::calibration::TIMEOUT;
+use crate::imu::Mag;
+use crate::storage::{self, Slot};
+
+/// How far each axis has to span, in microtesla. The field of the earth is at least
+/// 25µT, so turning the tracker all the way around spans at least twice that.
+const MIN_RANGE: f32 = 40.;
+/// How much a smallest or largest reading has to grow by to count, in microtesla.
+/// More than the noise of cheap magnetometers.
+const MIN_GROWTH: f32 = 1.;
+/// How long the readings have to stay within what was seen, before the routine is
+/// done.
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+/// Bumped whenever the layout of the stored calibration changes. Older layouts are
+/// ignored.
+const VERSION: u8 = 1;
+/// The version, the hard iron offset, and the soft iron matrix by rows.
+const STORED_LEN: usize = 1 + 12 * 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MagCalibration {
+	hard_iron: Mag,
+	soft_iron: Matrix3<f32>,
+}
+impl MagCalibration {
+	/// Leaves the readings as they are.
+	pub fn identity() -> Self {
+		Self {
+			hard_iron: Mag::zeros(),
+			soft_iron: Matrix3::identity(),
+		}
+	}
+
+	/// The stored calibration, or the identity if there is none.
+	pub fn load() -> Self {
+		let mut bytes = [0; STORED_LEN];
+		match storage::load(Slot::MagCalibration, &mut bytes) {
+			Ok(STORED_LEN) if bytes[0] == VERSION => {
+				let c = Self::from_bytes(&bytes);
+				info!(
+					"Loaded magnetometer calibration: {}",
+					defmt::Debug2Format(&c)
+				);
+				return c;
+			}
+			Ok(_) => {
+				warn!("Ignoring the stored magnetometer calibration, it is outdated")
+			}
+			Err(storage::Error::Empty) => {}
+			Err(e) => warn!("Failed to load the magnetometer calibration: {}", e),
+		}
+		Self::identity()
+	}
+
+	fn store(&self) {
+		let mut bytes = [0; STORED_LEN];
+		bytes[0] = VERSION;
+		let values = self
+			.hard_iron
+			.iter()
+			.chain(self.soft_iron.transpose().iter());
+		for (chunk, value) in bytes[1..].chunks_exact_mut(4).zip(values) {
+			chunk.copy_from_slice(&value.to_le_bytes());
+		}
+		if let Err(e) = storage::store(Slot::MagCalibration, &bytes) {
+			warn!("Failed to store the magnetometer calibration: {}", e);
+		}
+	}
+
+	fn from_bytes(bytes: &[u8; STORED_LEN]) -> Self {
+		let mut values = [0.; 12];
+		for (value, chunk) in values.iter_mut().zip(bytes[1..].chunks_exact(4)) {
+			*value = f32::from_le_bytes(chunk.try_into().unwrap());
+		}
+		Self {
+			hard_iron: Mag::from_row_slice(&values[..3]),
+			soft_iron: Matrix3::from_row_slice(&values[3..]),
+		}
+	}
+
+	pub fn apply(&self, raw: &Mag) -> Mag {
+		self.soft_iron * (raw - self.hard_iron)
+	}
+}
+
+/// The smallest and largest readings of each axis so far.
+struct Extremes {
+	min: Mag,
+	max: Mag,
+	/// When one of them last grew by [`MIN_GROWTH`].
+	grew: Instant,
+}
+impl Extremes {
+	/// Counts `raw`, and returns the calibration once the extremes settled.
+	fn update(&mut self, raw: &Mag, now: Instant) -> Option<MagCalibration> {
+		let grew =
+			(self.min - raw).max() > MIN_GROWTH || (raw - self.max).max() > MIN_GROWTH;
+		self.min = self.min.inf(raw);
+		self.max = self.max.sup(raw);
+		if grew {
+			self.grew = now;
+			return None;
+		}
+		let range = self.max - self.min;
+		if range.min() < MIN_RANGE || now - self.grew < SETTLE_TIME {
+			return None;
+		}
+		let radius = range / 2.;
+		let mean = radius.mean();
+		Some(MagCalibration {
+			hard_iron: (self.max + self.min) / 2.,
+			soft_iron: Matrix3::from_diagonal(&radius.map(|r| mean / r)),
+		})
+	}
+}
+
+/// Applies the calibration, and runs the routine that updates it.
+pub struct MagCalibrator {
+	calibration: MagCalibration,
+	/// The extremes, once there was a reading, and when the routine gives up.
+	active: Option<(Option<Extremes>, Instant)>,
+}
+impl MagCalibrator {
+	pub fn new() -> Self {
+		Self {
+			calibration: MagCalibration::load(),
+			active: None,
+		}
+	}
+
+	/// Starts the routine, or starts it over.
+	pub fn start(&mut self) {
+		info!(
+			"Calibrating the magnetometer, turn the tracker slowly in every direction"
+		);
+		self.active = Some((None, Instant::now() + TIMEOUT));
+	}
+
+	/// Whether the routine is running.
+	pub fn is_active(&self) -> bool {
+		self.active.is_some()
+	}
+
+	/// Runs the routine until its deadline, and feeds it the uncalibrated reading
+	/// `raw` if there is one. Returns the calibrated reading.
+	pub fn process(&mut self, raw: Option<&Mag>) -> Option<Mag> {
+		if let Some((extremes, deadline)) = &mut self.active {
+			let now = Instant::now();
+			if now >= *deadline {
+				match extremes {
+					Some(_) => warn!(
+						"Magnetometer calibration timed out, keeping the previous one"
+					),
+					None => warn!("Can't calibrate the magnetometer, the IMU has none"),
+				}
+				self.active = None;
+			} else if let Some(raw) = raw {
+				let calibration = match extremes {
+					Some(extremes) => extremes.update(raw, now),
+					None => {
+						*extremes = Some(Extremes {
+							min: *raw,
+							max: *raw,
+							grew: now,
+						});
+						None
+					}
+				};
+				if let Some(calibration) = calibration {
+					info!(
+						"Calibrated the magnetometer: {}",
+						defmt::Debug2Format(&calibration)
+					);
+					self.calibration = calibration;
+					self.calibration.store();
+					self.active = None;
+				}
+			}
+		}
+		raw.map(|raw| self.calibration.apply(raw))
+	}
+}
@@ -1,26 +1,117 @@
+#[cfg(not(spi_imu))]
+pub mod bus;
+pub mod calibration;
 mod drivers;
 mod fusion;
+mod hotplug;
+mod mag_calibration;
+pub mod regs;
+pub mod reset;
+mod schedule;
+mod tap;
 
-use defmt::{debug, info, trace, warn};
+pub use self::fusion::Algorithm;
+pub use self::hotplug::{SensorError, SensorEvent};
+pub use self::tap::Tap;
+/// Drivers report the magnetometer only with the sample after each new measurement,
+/// see [`MAG_RATE_HZ`].
+pub use fusers::UnfusedData;
+
+use defmt::{debug, error, info, trace, warn, Format};
 use embassy_executor::task;
-use firmware_protocol::ImuType;
+use embassy_futures::select::select_array;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use embedded_hal::blocking::delay::DelayMs;
+use firmware_protocol::{ImuType, SensorStatus};
 
+#[cfg(not(spi_imu))]
+use crate::imu::{bus::SharedBus, hotplug::ConnectError};
 use crate::{
-	aliases::ඞ::{DelayConcrete, I2cConcrete},
-	utils::Unreliable,
+	aliases::{ImuBusConcrete, ඞ::DelayConcrete},
+	config::Setting,
+	events::{self, BootStage, Event, EventBus},
+	imu::calibration::Routine,
+	imu::hotplug::{Presence, SENSOR_TIMEOUT},
+	imu::reset::{ResetKind, YawOffset},
+	imu::schedule::{Next, Scheduler},
+	power::{PowerState, Stillness},
+	thermal::ThermalState,
+	utils::{Reliable, Unreliable},
 };
 
 pub type Quat = nalgebra::UnitQuaternion<f32>;
 pub type Accel = nalgebra::Vector3<f32>;
 pub type Gyro = nalgebra::Vector3<f32>;
+pub type Mag = nalgebra::Vector3<f32>;
+
+/// How often magnetometers measure, from `MAG_RATE_HZ` in `.env`. Usually less
+/// often than the other sensors are read, since measuring costs a lot of power on
+/// some chips and yaw drifts slowly anyway.
+pub const MAG_RATE_HZ: u32 = crate::utils::parse_u32(env!("MAG_RATE_HZ"));
+
+/// The bus that the imu task reads from. I2C is shared with the other peripherals
+/// on it, see [`bus`], SPI is the imu's alone.
+#[cfg(not(spi_imu))]
+pub type ImuBus = &'static bus::Bus<ImuBusConcrete<'static>>;
+#[cfg(spi_imu)]
+pub type ImuBus = ImuBusConcrete<'static>;
+
+/// How many imus the tracker reads. Boards with `extension = true` have a second
+/// one on the same bus, at the alternate address of the chip.
+pub const MAX_IMUS: usize = if cfg!(imu_extension) { 2 } else { 1 };
+
+/// Which of the two addresses that most imus can be strapped to a sensor is at.
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Address {
+	/// Where breakout boards put the imu by default, used by the main imu.
+	Primary,
+	/// Used by the imu of an extension.
+	Alternate,
+}
+impl Address {
+	/// The address of the sensor with this id.
+	#[cfg_attr(spi_imu, allow(dead_code))]
+	fn of_sensor(sensor_id: usize) -> Self {
+		if sensor_id == 0 {
+			Self::Primary
+		} else {
+			Self::Alternate
+		}
+	}
 
-pub struct UnfusedData {
-	pub accel: Accel,
-	pub gyro: Gyro,
+	pub const fn pick(self, primary: u8, alternate: u8) -> u8 {
+		match self {
+			Self::Primary => primary,
+			Self::Alternate => alternate,
+		}
+	}
 }
 
+/// How often the temperature of each imu is reported. It changes slowly, and only
+/// matters for explaining drift.
+const TEMPERATURE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The longest raw stream that can be requested, so that a tool that crashes
+/// doesn't leave the tracker flooding the network.
+const MAX_RAW_STREAM: Duration = Duration::from_secs(60);
+
 pub struct FusedData {
 	pub q: Quat,
+	/// Angular velocity in rad/s, if the imu provides it.
+	pub angular_velocity: Option<Gyro>,
+	/// How well calibrated the fusion is, from `0` (not at all) to `3` (fully), if
+	/// the imu or fuser can tell.
+	pub accuracy: Option<u8>,
+	/// The sample that the fusion was computed from and when it was read, if the
+	/// fusion runs on the MCU. Only kept while a raw stream was requested.
+	pub raw: Option<(Instant, UnfusedData)>,
+	/// The acceleration in m/s^2 without gravity, in the frame of the sensor, if the
+	/// imu or the fusion can tell which way gravity points.
+	pub linear_accel: Option<Accel>,
+	/// A tap that ended with this sample, from the tap engine of the imu or
+	/// [`tap::TapDetector`] of the fusion on the MCU. Imus that fuse on-chip without
+	/// a tap engine never report one.
+	pub tap: Option<Tap>,
 }
 
 pub trait Imu {
@@ -31,30 +122,409 @@ pub trait Imu {
 	const IMU_TYPE: ImuType;
 	/// Performs IO to get the next data from the imu.
 	async fn next_data(&mut self) -> Result<Self::Data, Self::Error>;
+
+	/// Starts a calibration routine, and returns whether the imu supports it. Imus
+	/// that fuse on-chip calibrate themselves, so they don't by default.
+	fn calibrate(&mut self, routine: Routine) -> bool {
+		let _ = routine;
+		false
+	}
+
+	/// Whether a routine from [`Imu::calibrate`] is still running.
+	fn is_calibrating(&self) -> bool {
+		false
+	}
+
+	/// Makes the fusion estimate which way is down again from the accelerometer, and
+	/// returns whether the imu supports it. Imus that fuse on-chip keep their own
+	/// estimate, so they don't by default.
+	fn reset_fusion(&mut self) -> bool {
+		false
+	}
+
+	/// Scales how strongly the fusion corrects with the accelerometer, by `gain` times
+	/// the default, and returns whether the imu supports it. Imus that fuse on-chip
+	/// don't by default.
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		let _ = gain;
+		false
+	}
+
+	/// Switches the fusion to `algorithm`, and returns whether the imu supports it.
+	/// Imus that fuse on-chip don't by default.
+	fn set_fusion(&mut self, algorithm: Algorithm) -> bool {
+		let _ = algorithm;
+		false
+	}
+
+	/// Initializes the imu again, like it was after booting, and forgets the state
+	/// of its fusion. Blocks for as long as initializing does. Also called after
+	/// [`Imu::next_data`] timed out, since dropping it may stop it at any await.
+	fn restart(&mut self, delay: &mut impl DelayMs<u32>) -> Result<(), Self::Error>;
+
+	/// Lets the imu measure less often and draw less power while the tracker lies
+	/// still, or goes back to normal. Returns whether the imu supports it.
+	fn set_low_power(&mut self, enabled: bool) -> Result<bool, Self::Error> {
+		let _ = enabled;
+		Ok(false)
+	}
+
+	/// The die temperature in degrees Celsius, if the imu measures it. Only asked for
+	/// every [`TEMPERATURE_INTERVAL`], so it may do its own IO.
+	fn temperature(&mut self) -> Result<Option<f32>, Self::Error> {
+		Ok(None)
+	}
+
+	/// Makes the imu raise its interrupt pin `int0` once it moves, to wake the MCU
+	/// from deep sleep. Nothing is read from it afterwards. Returns whether the imu
+	/// supports it.
+	#[cfg_attr(not(deep_sleep), allow(dead_code))]
+	fn arm_wake_on_motion(&mut self) -> Result<bool, Self::Error> {
+		Ok(false)
+	}
 }
 
-/// Gets data from the IMU
+/// What the imu task keeps track of for each sensor.
+struct SensorState {
+	presence: Presence,
+	enabled: bool,
+	calibrated: bool,
+	/// Whether a calibration routine is running.
+	calibrating: bool,
+	yaw_offset: YawOffset,
+	last_rate_hz: u32,
+	/// When the requested raw stream ends.
+	raw_until: Option<Instant>,
+	restart: bool,
+}
+impl SensorState {
+	fn new(enabled: bool) -> Self {
+		Self {
+			presence: Presence::new(),
+			enabled,
+			calibrated: false,
+			calibrating: false,
+			yaw_offset: YawOffset::new(),
+			last_rate_hz: u32::MAX,
+			raw_until: None,
+			restart: false,
+		}
+	}
+}
+
+/// Gets data from the imus, which all share one bus. Sensor `0` is the main imu, the
+/// one of an extension is sensor `1`. Boards with the imu on SPI have no extension.
 #[task]
 pub async fn imu_task(
-	quat_signal: &'static Unreliable<Quat>,
-	i2c: I2cConcrete<'static>,
+	fused_signals: &'static [Unreliable<FusedData>; MAX_IMUS],
+	sensor_events: &'static Reliable<SensorEvent>,
+	sensor_enabled: &'static [Unreliable<bool>; MAX_IMUS],
+	events: &'static EventBus,
+	bus: ImuBus,
 	mut delay: DelayConcrete,
 ) -> ! {
 	debug!("Imu task");
 
-	let mut imu = new_imu(i2c, &mut delay);
-	info!("Initialized IMU!");
+	#[cfg(spi_imu)]
+	let mut bus = Some(bus);
+	let mut imus: [Option<_>; MAX_IMUS] = core::array::from_fn(|sensor_id| {
+		#[cfg(not(spi_imu))]
+		let imu = connect(bus, sensor_id, &mut delay);
+		// There is only the main imu.
+		#[cfg(spi_imu)]
+		let imu = new_imu(bus.take().unwrap(), &mut delay);
+		match imu {
+			Ok(imu) => Some(imu),
+			Err(err) if sensor_id == 0 => {
+				error!("Failed to initialize IMU: {}", defmt::Debug2Format(&err));
+				None
+			}
+			Err(err) => {
+				warn!("No IMU on the extension: {}", defmt::Debug2Format(&err));
+				None
+			}
+		}
+	});
+	if imus[0].is_none() {
+		// Instead of panicking, so that the LED can tell which stage failed.
+		events::publish(events, Event::BootFailed(BootStage::Imu));
+		match core::future::pending::<core::convert::Infallible>().await {}
+	}
+	info!("Initialized {} IMU(s)!", imus.iter().flatten().count());
+	events::publish(events, Event::ImusReady);
+
+	let imu_type = imu_type(&imus);
+	crate::diag::imu_initialized(imu_type);
+	let mut config = crate::config::Config::load();
+	set_accel_gain(&mut imus, config.accel_gain_milli);
+	set_fusion(&mut imus, config.fusion);
+	let send_status =
+		move |sensor_id: usize, status: SensorStatus, error: Option<SensorError>| {
+			let sensor_id = sensor_id as u8;
+			if sensor_id == 0 {
+				crate::diag::imu_status(status);
+			}
+			if let Some(error) = error {
+				events::publish(events, Event::ImuError { sensor_id, error });
+			}
+			sensor_events.send(SensorEvent {
+				sensor_id,
+				status,
+				imu_type,
+				error,
+			})
+		};
+	let mut sensors: [SensorState; MAX_IMUS] =
+		core::array::from_fn(|sensor_id| SensorState::new(imus[sensor_id].is_some()));
+	for (sensor_id, imu) in imus.iter().enumerate() {
+		if imu.is_some() {
+			send_status(sensor_id, SensorStatus::Ok, None).await;
+		}
+	}
+
+	let mut events_in = events.subscriber().unwrap();
+	let mut thermal_state = ThermalState::Normal;
+	let mut scheduler = Scheduler::<MAX_IMUS>::new();
+	let mut stillness = Stillness::new(Instant::now());
+	let mut power_state = PowerState::Active;
+	// Calibrating needs every sample, for as long as a routine might take.
+	let mut awake_until = Instant::now();
+	let mut next_temperature = Instant::now();
+	#[cfg(not(spi_imu))]
+	let mut next_probe = Instant::now() + hotplug::PROBE_INTERVAL;
 
 	let mut i = 0;
 	loop {
-		let q = match imu.next_data().await {
-			Ok(q) => q.q,
-			Err(err) => {
-				warn!("Error in IMU: {}", defmt::Debug2Format(&err));
+		// Sampling stays paused until a sensor gets re-enabled.
+		let woken = if sensors.iter().any(|s| s.enabled) {
+			None
+		} else {
+			let waits: [_; MAX_IMUS] =
+				core::array::from_fn(|sensor_id| sensor_enabled[sensor_id].wait());
+			Some(select_array(waits).await)
+		};
+		for (sensor_id, sensor) in sensors.iter_mut().enumerate() {
+			let enabled = match woken {
+				Some((enabled, woken_id)) if woken_id == sensor_id => enabled,
+				_ if sensor_enabled[sensor_id].signaled() => {
+					sensor_enabled[sensor_id].wait().await
+				}
+				_ => continue,
+			};
+			if enabled == sensor.enabled {
+				continue;
+			}
+			sensor.enabled = enabled;
+			if enabled {
+				info!("IMU {} enabled", sensor_id);
+				sensor.presence = Presence::new();
+				send_status(sensor_id, SensorStatus::Ok, None).await;
+			} else {
+				info!("IMU {} disabled", sensor_id);
+				send_status(sensor_id, SensorStatus::Offline, None).await;
+			}
+		}
+		while let Some(event) = events_in.try_next_message_pure() {
+			match event {
+				Event::Thermal(state) => thermal_state = state,
+				// Nobody is listening to the stream anymore.
+				Event::ConnectionChanged { connected: false } => {
+					sensors.iter_mut().for_each(|s| s.raw_until = None);
+				}
+				Event::RawStreamRequested {
+					sensor_id,
+					duration_ms,
+				} => {
+					let Some(sensor) = sensors.get_mut(usize::from(sensor_id)) else {
+						continue;
+					};
+					let duration = Duration::from_millis(duration_ms.into());
+					sensor.raw_until = (duration_ms > 0)
+						.then(|| Instant::now() + duration.min(MAX_RAW_STREAM));
+					info!(
+						"Raw stream of IMU {} requested for {} ms",
+						sensor_id, duration_ms
+					);
+				}
+				Event::CalibrationRequested { sensor_id, routine } => {
+					let imu = imus.get_mut(usize::from(sensor_id));
+					let Some(Some(imu)) = imu else {
+						continue;
+					};
+					if !imu.calibrate(routine) {
+						warn!("The IMU calibrates itself, ignoring {}", routine);
+						continue;
+					}
+					sensors[usize::from(sensor_id)].calibrating = true;
+					let running = true;
+					events::publish(events, Event::Calibrating { sensor_id, running });
+					awake_until = Instant::now() + calibration::TIMEOUT;
+				}
+				Event::RestartRequested { sensor_id } => {
+					if let Some(sensor) = sensors.get_mut(usize::from(sensor_id)) {
+						sensor.restart = true;
+					}
+				}
+				Event::ResetRequested { sensor_id, kind } => {
+					let id = usize::from(sensor_id);
+					let (Some(sensor), Some(Some(imu))) =
+						(sensors.get_mut(id), imus.get_mut(id))
+					else {
+						continue;
+					};
+					info!("Resetting the orientation of IMU {}: {}", sensor_id, kind);
+					if kind == ResetKind::Full && !imu.reset_fusion() {
+						debug!("The IMU fuses on-chip, only resetting yaw");
+					}
+					sensor.yaw_offset.reset();
+				}
+				Event::ConfigChanged(Setting::AccelGain { milli }) => {
+					info!("Setting the accelerometer gain to {}/1000", milli);
+					config.accel_gain_milli = milli;
+					set_accel_gain(&mut imus, milli);
+				}
+				Event::ConfigChanged(Setting::Fusion { algorithm }) => {
+					info!("Switching the fusion to {}", algorithm);
+					config.fusion = algorithm;
+					set_fusion(&mut imus, algorithm);
+				}
+				_ => (),
+			}
+		}
+		// Only the imu starts over, the connection to the server stays up.
+		let restarting = sensors.iter_mut().zip(&mut imus).enumerate();
+		for (sensor_id, (sensor, imu)) in restarting {
+			if !core::mem::take(&mut sensor.restart) {
+				continue;
+			}
+			let Some(imu) = imu else {
+				continue;
+			};
+			info!("Restarting IMU {}", sensor_id);
+			let started = Instant::now();
+			match imu.restart(&mut delay) {
+				Ok(()) => {
+					info!("Restarted IMU in {} ms", started.elapsed().as_millis());
+					sensor.calibrated = false;
+					sensor.yaw_offset = YawOffset::new();
+					if let Some(status) = sensor.presence.on_success() {
+						send_status(sensor_id, status, None).await;
+					}
+				}
+				Err(err) => {
+					warn!("Failed to restart IMU: {}", defmt::Debug2Format(&err));
+					if let Some(status) = sensor.presence.on_failure() {
+						send_status(sensor_id, status, Some(SensorError::Disconnected))
+							.await;
+					}
+				}
+			}
+		}
+
+		// Sensors that are missing come back once they answer again, the ones that
+		// are still connected don't notice.
+		#[cfg(not(spi_imu))]
+		if Instant::now() >= next_probe {
+			next_probe = Instant::now() + hotplug::PROBE_INTERVAL;
+			let missing = sensors.iter_mut().zip(&mut imus).enumerate();
+			for (sensor_id, (sensor, slot)) in missing {
+				if slot.is_some() {
+					continue;
+				}
+				match connect(bus, sensor_id, &mut delay) {
+					Ok(imu) => *slot = Some(imu),
+					Err(ConnectError::Absent) => continue,
+					// Already logged while booting, or when it disconnected.
+					Err(err) => {
+						debug!("IMU {}: {}", sensor_id, defmt::Debug2Format(&err));
+						continue;
+					}
+				}
+				info!("IMU {} connected", sensor_id);
+				set_accel_gain(core::slice::from_mut(slot), config.accel_gain_milli);
+				set_fusion(core::slice::from_mut(slot), config.fusion);
+				*sensor = SensorState::new(sensor.enabled);
+				if sensor.enabled {
+					send_status(sensor_id, SensorStatus::Ok, None).await;
+				}
+			}
+		}
+
+		if Instant::now() >= next_temperature {
+			next_temperature = Instant::now() + TEMPERATURE_INTERVAL;
+			report_temperatures(&mut imus, &sensors, events);
+		}
+
+		let started = Instant::now();
+		if let Some(rates) = scheduler.report(started) {
+			debug!("IMUs polled at {} Hz", rates);
+			crate::diag::imu_rate(rates[0]);
+		}
+		let sensor_id = match scheduler.next(started) {
+			Next::Poll(sensor_id) => sensor_id,
+			Next::WaitUntil(t) => {
+				Timer::at(t).await;
+				continue;
+			}
+		};
+		let sensor = &mut sensors[sensor_id];
+		let Some(imu) = imus[sensor_id].as_mut().filter(|_| sensor.enabled) else {
+			scheduler.skip(sensor_id, started);
+			continue;
+		};
+
+		// We never repeat old data, so the server stops getting rotations as soon as
+		// the imu stops producing them.
+		let result = with_timeout(SENSOR_TIMEOUT, imu.next_data()).await;
+		if let Some(rate_hz) = scheduler.record(sensor_id, started, Instant::now()) {
+			let lowered = rate_hz < sensor.last_rate_hz;
+			sensor.last_rate_hz = rate_hz;
+			events::publish(
+				events,
+				Event::RateScaled {
+					sensor_id: sensor_id as u8,
+					rate_hz,
+					lowered,
+				},
+			);
+		}
+		let mut data = match result {
+			Ok(Ok(data)) => {
+				if let Some(status) = sensor.presence.on_success() {
+					info!("IMU {} reconnected", sensor_id);
+					send_status(sensor_id, status, None).await;
+					let sensor_id = sensor_id as u8;
+					events::publish(events, Event::ImuRecovered { sensor_id });
+				}
+				data
+			}
+			Ok(Err(err)) => {
+				warn!("Error in IMU {}: {}", sensor_id, defmt::Debug2Format(&err));
+				if let Some(status) = sensor.presence.on_failure() {
+					warn!("IMU {} disconnected", sensor_id);
+					send_status(sensor_id, status, Some(SensorError::Disconnected))
+						.await;
+					// Probed again, and initialized anew once it is back.
+					#[cfg(not(spi_imu))]
+					{
+						imus[sensor_id] = None;
+					}
+				}
+				continue;
+			}
+			Err(_timeout) => {
+				if let Some(status) = sensor.presence.on_timeout() {
+					warn!("IMU {} stopped producing data", sensor_id);
+					send_status(sensor_id, status, Some(SensorError::Timeout)).await;
+				}
+				// The read was dropped wherever it was, which can leave the driver
+				// halfway through a transfer, so it starts over from a known state.
+				sensor.restart = true;
 				continue;
 			}
 		};
 		if i % 1000 == 0 {
+			let q = data.q;
 			trace!(
 				"Quat values: x: {}, y: {}, z: {}, w: {}",
 				q.coords.x,
@@ -64,20 +534,211 @@ pub async fn imu_task(
 			);
 		}
 		i += 1;
-		quat_signal.signal(q);
+		sensor.yaw_offset.apply(&mut data.q);
+		if let Some(Tap { double }) = data.tap.take() {
+			// Through the event bus, since the signal would drop taps that the
+			// protocol task doesn't pick up before the next sample.
+			let sensor_id = sensor_id as u8;
+			events::publish(events, Event::Tap { sensor_id, double });
+		}
+		let fully_calibrated = data.accuracy == Some(3);
+		if fully_calibrated && !sensor.calibrated {
+			events::publish(
+				events,
+				Event::CalibrationDone {
+					sensor_id: sensor_id as u8,
+				},
+			);
+		}
+		sensor.calibrated = fully_calibrated;
+		if sensor.calibrating && !imu.is_calibrating() {
+			sensor.calibrating = false;
+			let (sensor_id, running) = (sensor_id as u8, false);
+			events::publish(events, Event::Calibrating { sensor_id, running });
+		}
+		match sensor.raw_until {
+			Some(until) if Instant::now() >= until => {
+				info!("Raw stream of IMU {} ended", sensor_id);
+				sensor.raw_until = None;
+			}
+			Some(_) if data.raw.is_none() => {
+				warn!("The IMU fuses on-chip, so there is no raw data to stream");
+				sensor.raw_until = None;
+			}
+			_ => (),
+		}
+		if sensor.raw_until.is_none() {
+			data.raw = None;
+		}
+		let q = data.q;
+		fused_signals[sensor_id].signal(data);
+
+		// The main sensor decides whether the tracker lies still. A raw stream wants
+		// every sample, like calibrating does.
+		if sensor_id == 0 {
+			let now = Instant::now();
+			let busy =
+				now < awake_until || sensors.iter().any(|s| s.raw_until.is_some());
+			let changed = if busy {
+				stillness.wake(now)
+			} else {
+				stillness.update(&q, now)
+			};
+			if let Some(state) = changed {
+				power_state = state;
+				set_power_state(&mut imus, state, events).await;
+			}
+		}
+
+		// Sampling less often lets the MCU cool down, or save power.
+		let interval = thermal_state
+			.sample_interval()
+			.max(power_state.sample_interval());
+		if let Some(interval) = interval {
+			Timer::after(interval).await;
+		}
+	}
+}
+
+/// Applies the accelerometer gain of the config to the fusion of every imu.
+fn set_accel_gain<I: Imu>(imus: &mut [Option<I>], milli: u16) {
+	let gain = crate::config::accel_gain(milli);
+	for imu in imus.iter_mut().flatten() {
+		if !imu.set_accel_gain(gain) && gain != 1. {
+			warn!("The IMU fuses on-chip, ignoring the accelerometer gain");
+		}
 	}
 }
 
+/// Switches the fusion of every imu to the one of the config.
+fn set_fusion<I: Imu>(imus: &mut [Option<I>], algorithm: Algorithm) {
+	for imu in imus.iter_mut().flatten() {
+		if !imu.set_fusion(algorithm) && algorithm != Algorithm::BuiltIn {
+			warn!("The IMU fuses on-chip, ignoring the fusion algorithm");
+		}
+	}
+}
+
+/// Publishes the temperature of every enabled imu that measures it.
+fn report_temperatures<I: Imu>(
+	imus: &mut [Option<I>],
+	sensors: &[SensorState],
+	events: &EventBus,
+) {
+	for (sensor_id, (imu, sensor)) in imus.iter_mut().zip(sensors).enumerate() {
+		let Some(imu) = imu.as_mut().filter(|_| sensor.enabled) else {
+			continue;
+		};
+		match imu.temperature() {
+			Ok(Some(celsius)) => {
+				let sensor_id = sensor_id as u8;
+				events::publish(events, Event::Temperature { sensor_id, celsius });
+			}
+			Ok(None) => (),
+			Err(err) => warn!(
+				"Failed to read the temperature of IMU {}: {}",
+				sensor_id,
+				defmt::Debug2Format(&err)
+			),
+		}
+	}
+}
+
+/// Lets the imus save power along with the tracker, and goes into deep sleep once
+/// it is time to.
+async fn set_power_state<I: Imu>(
+	imus: &mut [Option<I>],
+	state: PowerState,
+	events: &EventBus,
+) {
+	events::publish(events, Event::Power(state));
+	let low_power = state != PowerState::Active;
+	for (sensor_id, imu) in imus.iter_mut().enumerate() {
+		let Some(imu) = imu else {
+			continue;
+		};
+		if let Err(err) = imu.set_low_power(low_power) {
+			warn!(
+				"Failed to change the power mode of IMU {}: {}",
+				sensor_id,
+				defmt::Debug2Format(&err)
+			);
+		}
+	}
+	#[cfg(deep_sleep)]
+	if state == PowerState::Sleep {
+		let Some(Some(imu)) = imus.first_mut() else {
+			return;
+		};
+		match imu.arm_wake_on_motion() {
+			Ok(true) => crate::power::deep_sleep(events).await,
+			Ok(false) => warn!("The IMU can't wake the tracker up, staying idle"),
+			Err(err) => warn!(
+				"Failed to arm the IMU's motion interrupt: {}",
+				defmt::Debug2Format(&err)
+			),
+		}
+	}
+}
+
+fn imu_type<I: Imu>(_imus: &[Option<I>]) -> ImuType {
+	I::IMU_TYPE
+}
+
+/// Initializes the imu of `sensor_id`, once [`hotplug::probe`] found a chip there
+/// that the driver of this build talks to.
+#[cfg(not(spi_imu))]
+fn connect(
+	bus: ImuBus,
+	sensor_id: usize,
+	delay: &mut impl crate::aliases::Delay,
+) -> Result<impl Imu<Data = FusedData>, ConnectError<impl core::fmt::Debug>> {
+	let address = Address::of_sensor(sensor_id);
+	let mut i2c = SharedBus::new(bus);
+	// The stub has no chip to answer.
+	if !cfg!(feature = "imu-stubbed") {
+		match hotplug::probe(&mut i2c, address) {
+			None => return Err(ConnectError::Absent),
+			Some(chip) if !chip.is_supported() => {
+				return Err(ConnectError::Unsupported(chip))
+			}
+			Some(chip) => debug!("Found a {} at the {} address", chip, address),
+		}
+	}
+	new_imu(i2c, address, delay).map_err(ConnectError::Init)
+}
+
+#[cfg(not(spi_imu))]
 fn new_imu(
-	i2c: impl crate::aliases::I2c,
+	i2c: impl crate::aliases::BurstRead + Clone,
+	address: Address,
 	delay: &mut impl crate::aliases::Delay,
-) -> impl Imu<Data = FusedData> {
+) -> Result<impl Imu<Data = FusedData>, impl core::fmt::Debug> {
 	use crate::imu::drivers as d;
 
 	#[cfg(feature = "imu-bmi160")]
-	return d::bmi160::new_imu(i2c, delay);
+	return d::bmi160::new_imu(i2c, address, delay);
+	#[cfg(feature = "imu-bno055")]
+	return d::bno055::new_imu(i2c, address, delay);
+	#[cfg(feature = "imu-bno08x")]
+	return d::bno08x::new_imu(i2c, address, delay);
 	#[cfg(feature = "imu-mpu6050")]
-	return d::mpu6050::new_imu(i2c, delay);
+	return d::mpu6050::new_imu(i2c, address, delay);
+	#[cfg(feature = "imu-mpu6050-raw")]
+	return d::mpu6050_raw::new_imu(i2c, address, delay);
 	#[cfg(feature = "imu-stubbed")]
-	return d::stubbed::new_imu(i2c, delay);
+	return d::stubbed::new_imu(i2c, address, delay);
+}
+
+/// Only the drivers that talk to their chip in registers support SPI, which the
+/// build script checks.
+#[cfg(spi_imu)]
+fn new_imu(
+	spi: impl regs::Registers,
+	delay: &mut impl crate::aliases::Delay,
+) -> Result<impl Imu<Data = FusedData>, impl core::fmt::Debug> {
+	use crate::imu::drivers as d;
+
+	#[cfg(feature = "imu-mpu6050-raw")]
+	return d::mpu6050_raw::new_spi_imu(spi, delay);
 }
@@ -0,0 +1,145 @@
+//! Register access to an imu, over I2C or SPI, so that the drivers that talk to
+//! their chip in registers work over either. Which one a board uses is set by its
+//! `[spi]` table, see `boards/README.md`.
+//!
+//! SPI is faster and has the imu to itself, but leaves no room for an extension,
+//! and no I2C bus for a magnetometer that the imu passes its bus through to.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::convert::Infallible;
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::aliases::{BurstRead, I2c};
+
+/// Set in the register address of SPI reads, by the chips that we read over SPI.
+const SPI_READ: u8 = 0x80;
+
+pub trait Registers: Sized {
+	type Error: core::fmt::Debug;
+
+	/// Whether the registers are read over SPI. Some chips have to turn their I2C
+	/// interface off then, so that they don't mistake SPI traffic for it.
+	const SPI: bool;
+
+	/// Gets the bus into a known state before initializing the chip.
+	fn flush(&mut self);
+
+	fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Self::Error>;
+
+	/// Reads `buf.len()` registers starting at `reg`.
+	fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+	/// Like [`Registers::read_regs`], for the samples, see [`BurstRead`].
+	async fn burst_read(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+	/// The registers of the chip at `address` on the same I2C bus, like a
+	/// magnetometer that the imu passes its bus through to. `None` over SPI.
+	fn neighbor(&self, address: u8) -> Option<Self>;
+}
+
+/// The registers of the chip at `address` of an I2C bus.
+pub struct I2cRegisters<I> {
+	i2c: I,
+	address: u8,
+}
+impl<I> I2cRegisters<I> {
+	pub fn new(i2c: I, address: u8) -> Self {
+		Self { i2c, address }
+	}
+}
+
+impl<I: BurstRead + Clone> Registers for I2cRegisters<I> {
+	type Error = <I as I2c>::Error;
+
+	const SPI: bool = false;
+
+	fn flush(&mut self) {
+		// Bogus data, which ends whatever transaction the chip was stuck in.
+		let _ = self.i2c.write(self.address, &[0]);
+	}
+
+	fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Self::Error> {
+		self.i2c.write(self.address, &[reg, value])
+	}
+
+	fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+		self.i2c.write_read(self.address, &[reg], buf)
+	}
+
+	async fn burst_read(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+		self.i2c.burst_read(self.address, reg, buf).await
+	}
+
+	fn neighbor(&self, address: u8) -> Option<Self> {
+		Some(Self::new(self.i2c.clone(), address))
+	}
+}
+
+/// The chip select of an SPI imu, which is low while it is talked to.
+pub type ChipSelect = Box<dyn OutputPin<Error = Infallible> + Send>;
+
+/// The registers of the only chip on an SPI bus.
+#[cfg_attr(not(spi_imu), allow(dead_code))]
+pub struct SpiRegisters<S> {
+	spi: S,
+	cs: ChipSelect,
+}
+#[cfg_attr(not(spi_imu), allow(dead_code))]
+impl<S> SpiRegisters<S> {
+	pub fn new(spi: S, mut cs: ChipSelect) -> Self {
+		// Can't fail, the error type is `Infallible`.
+		let _ = cs.set_high();
+		Self { spi, cs }
+	}
+
+	/// Runs `f` with the chip selected, and deselects it even if `f` fails.
+	fn selected<T, E>(
+		&mut self,
+		f: impl FnOnce(&mut S) -> Result<T, E>,
+	) -> Result<T, E> {
+		let _ = self.cs.set_low();
+		let result = f(&mut self.spi);
+		let _ = self.cs.set_high();
+		result
+	}
+}
+
+impl<S, E> Registers for SpiRegisters<S>
+where
+	S: Transfer<u8, Error = E> + Write<u8, Error = E>,
+	E: core::fmt::Debug,
+{
+	type Error = E;
+
+	const SPI: bool = true;
+
+	fn flush(&mut self) {
+		// A dummy read, of a register that most chips don't have, which also switches
+		// chips like the BMI160 from I2C to SPI.
+		let _ = self.read_regs(0x7F, &mut [0]);
+	}
+
+	fn write_reg(&mut self, reg: u8, value: u8) -> Result<(), Self::Error> {
+		self.selected(|spi| spi.write(&[reg & !SPI_READ, value]))
+	}
+
+	fn read_regs(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+		self.selected(|spi| {
+			spi.write(&[reg | SPI_READ])?;
+			buf.fill(0);
+			spi.transfer(buf).map(|_| ())
+		})
+	}
+
+	// TODO: Read with DMA, once the HALs have async SPI.
+	async fn burst_read(&mut self, reg: u8, buf: &mut [u8]) -> Result<(), Self::Error> {
+		self.read_regs(reg, buf)
+	}
+
+	fn neighbor(&self, _address: u8) -> Option<Self> {
+		None
+	}
+}
@@ -0,0 +1,58 @@
+//! Resets of the orientation, which the user starts on the server and the server
+//! relays to the tracker.
+//!
+//! Yaw is zeroed by rotating the output of every imu, so it works the same whether
+//! the fusion runs on the MCU or on-chip. Only fusion on the MCU can estimate which
+//! way is down again for [`ResetKind::Full`], chips keep their own estimate.
+
+use defmt::Format;
+use firmware_protocol::{RESET_FULL, RESET_YAW};
+use nalgebra::Vector3;
+
+use crate::imu::Quat;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum ResetKind {
+	Yaw,
+	Full,
+}
+impl ResetKind {
+	/// The reset that the server asked for with `CbPacket::ResetOrientation`.
+	pub fn from_protocol(kind: u8) -> Option<Self> {
+		match kind {
+			RESET_YAW => Some(Self::Yaw),
+			RESET_FULL => Some(Self::Full),
+			_ => None,
+		}
+	}
+}
+
+/// Rotates the orientation about the vertical, so that yaw is zero at the heading of
+/// the last reset.
+pub struct YawOffset {
+	offset: Quat,
+	/// Whether to zero the yaw at the next orientation.
+	pending: bool,
+}
+impl YawOffset {
+	pub fn new() -> Self {
+		Self {
+			offset: Quat::identity(),
+			pending: false,
+		}
+	}
+
+	/// Makes the heading of the next orientation the new zero.
+	pub fn reset(&mut self) {
+		self.pending = true;
+	}
+
+	pub fn apply(&mut self, q: &mut Quat) {
+		if self.pending {
+			self.pending = false;
+			let (_roll, _pitch, yaw) = q.euler_angles();
+			self.offset = Quat::from_axis_angle(&Vector3::z_axis(), -yaw);
+		}
+		*q = self.offset * *q;
+	}
+}
@@ -0,0 +1,218 @@
+//! Decides which sensor to poll next, when several of them share a bus.
+//!
+//! The primary sensor (id 0) always gets polled as soon as it is due, so it keeps
+//! its target rate. The auxiliary sensors take turns with the time that is left over,
+//! and only get the bus if their read is expected to finish before the primary is
+//! due again. Under contention they slow down, instead of the primary.
+//!
+//! The target rates are configured with `IMU_RATE_HZ` and `AUX_IMU_RATE_HZ` in `.env`.
+//! A rate of `0` polls as fast as possible, which leaves nothing for the auxiliary
+//! sensors if it is the primary's.
+//!
+//! When a sensor's reads (including fusion) keep taking longer than its interval,
+//! the CPU can't keep up, and the samples would only queue up as latency. Its rate
+//! is then halved, up to [`MAX_SCALE_STEPS`] times. Once reads would comfortably
+//! fit into the faster interval again for [`RECOVER_TIME`], the rate goes back up
+//! one step. The gap between the two conditions is the hysteresis, so the rate
+//! doesn't flip back and forth. Time spent sleeping for thermal throttling doesn't
+//! count, only the reads themselves.
+
+use embassy_time::{Duration, Instant};
+
+use crate::utils::parse_u32;
+
+const PRIMARY_RATE_HZ: u32 = parse_u32(env!("IMU_RATE_HZ"));
+const AUX_RATE_HZ: u32 = parse_u32(env!("AUX_IMU_RATE_HZ"));
+/// How often the achieved rates are reported.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How many reads are looked at together to decide whether we are overloaded.
+const LOAD_WINDOW: u32 = 50;
+/// How many of the reads in a window may miss their deadline, before the rate is
+/// lowered.
+const MAX_MISSED_PERCENT: u32 = 10;
+/// How much of the faster interval the reads may take on average, for the rate to
+/// be raised again.
+const RECOVER_LOAD_PERCENT: u32 = 50;
+/// How long the reads have to fit comfortably, before the rate is raised again.
+const RECOVER_TIME: Duration = Duration::from_secs(5);
+/// How long to leave a sensor that isn't polled alone, even if it wants to be polled
+/// as fast as possible.
+const SKIP_INTERVAL: Duration = Duration::from_millis(10);
+/// How many times the rate can be halved.
+const MAX_SCALE_STEPS: u32 = 3;
+
+/// What to do next.
+pub enum Next {
+	/// Poll the sensor with this id.
+	Poll(usize),
+	/// Nothing is due, wait until then.
+	WaitUntil(Instant),
+}
+
+struct Sensor {
+	/// The interval at the configured rate. Zero if it should be polled as fast as
+	/// possible.
+	target: Duration,
+	/// The interval right now, which is `target` doubled `steps` times.
+	interval: Duration,
+	steps: u32,
+	next_due: Instant,
+	/// How long a read takes on average.
+	cost: Duration,
+	/// How many reads happened since the last report.
+	polls: u32,
+	/// How many reads in the current load window took longer than `interval`, and
+	/// how many there were in total.
+	missed: u32,
+	window: u32,
+	/// Since when the reads would fit into the faster interval.
+	fits_since: Option<Instant>,
+}
+impl Sensor {
+	fn new(rate_hz: u32, now: Instant) -> Self {
+		let target = match rate_hz {
+			0 => Duration::from_ticks(0),
+			hz => Duration::from_micros(1_000_000 / u64::from(hz)),
+		};
+		Self {
+			target,
+			interval: target,
+			steps: 0,
+			next_due: now,
+			cost: Duration::from_ticks(0),
+			polls: 0,
+			missed: 0,
+			window: 0,
+			fits_since: None,
+		}
+	}
+
+	fn set_steps(&mut self, steps: u32) -> u32 {
+		self.steps = steps;
+		self.interval = self.target * (1 << steps);
+		self.fits_since = None;
+		(1_000_000 / self.interval.as_micros()) as u32
+	}
+
+	/// Adapts the interval to how long the read that just finished took. Returns
+	/// the new rate in Hz if it changed.
+	fn scale(&mut self, took: Duration, finished: Instant) -> Option<u32> {
+		if self.target.as_ticks() == 0 {
+			return None;
+		}
+		self.window += 1;
+		if took > self.interval {
+			self.missed += 1;
+		}
+		if self.window >= LOAD_WINDOW {
+			let overloaded = self.missed * 100 > self.window * MAX_MISSED_PERCENT;
+			self.window = 0;
+			self.missed = 0;
+			if overloaded && self.steps < MAX_SCALE_STEPS {
+				return Some(self.set_steps(self.steps + 1));
+			}
+		}
+		if self.steps == 0 {
+			return None;
+		}
+		let faster = self.target * (1 << (self.steps - 1));
+		if self.cost * 100 > faster * RECOVER_LOAD_PERCENT {
+			self.fits_since = None;
+			return None;
+		}
+		let since = *self.fits_since.get_or_insert(finished);
+		if finished - since < RECOVER_TIME {
+			return None;
+		}
+		Some(self.set_steps(self.steps - 1))
+	}
+}
+
+/// Schedules the polling of `N` sensors.
+pub struct Scheduler<const N: usize> {
+	sensors: [Sensor; N],
+	/// Which of the auxiliary sensors gets the next chance, counting from 0.
+	next_aux: usize,
+	last_report: Instant,
+}
+impl<const N: usize> Scheduler<N> {
+	pub fn new() -> Self {
+		let now = Instant::now();
+		Self {
+			sensors: core::array::from_fn(|i| {
+				Sensor::new(if i == 0 { PRIMARY_RATE_HZ } else { AUX_RATE_HZ }, now)
+			}),
+			next_aux: 0,
+			last_report: now,
+		}
+	}
+
+	pub fn next(&mut self, now: Instant) -> Next {
+		let primary = &self.sensors[0];
+		if now >= primary.next_due {
+			return Next::Poll(0);
+		}
+		let slack = primary.next_due - now;
+
+		let num_aux = N - 1;
+		for k in 0..num_aux {
+			let aux = (self.next_aux + k) % num_aux;
+			let sensor = &self.sensors[aux + 1];
+			if now >= sensor.next_due && sensor.cost <= slack {
+				self.next_aux = (aux + 1) % num_aux;
+				return Next::Poll(aux + 1);
+			}
+		}
+
+		// Aux sensors that are already due are waiting for more slack, which only
+		// comes once the primary has been polled.
+		let wake = self.sensors[1..]
+			.iter()
+			.map(|s| s.next_due)
+			.filter(|&due| due > now)
+			.fold(primary.next_due, Instant::min);
+		Next::WaitUntil(wake)
+	}
+
+	/// Call after each read of sensor `id`, whether it succeeded or not. Returns the
+	/// new rate of the sensor in Hz, if it had to be scaled.
+	pub fn record(
+		&mut self,
+		id: usize,
+		started: Instant,
+		finished: Instant,
+	) -> Option<u32> {
+		let sensor = &mut self.sensors[id];
+		let took = finished - started;
+		// An exponential moving average, so that one slow read doesn't starve the aux
+		// sensors for long.
+		sensor.cost = (sensor.cost * 7 + took) / 8;
+		sensor.polls += 1;
+		// Keep to the rate on average, but don't try to catch up on reads that were
+		// missed entirely.
+		sensor.next_due = (sensor.next_due + sensor.interval).max(started);
+		sensor.scale(took, finished)
+	}
+
+	/// Call instead of [`Self::record`] when sensor `id` was due, but isn't polled
+	/// because it is disabled or missing, so that it doesn't stay due.
+	pub fn skip(&mut self, id: usize, now: Instant) {
+		let sensor = &mut self.sensors[id];
+		sensor.next_due = now + sensor.interval.max(SKIP_INTERVAL);
+	}
+
+	/// The rate in Hz that each sensor was actually polled at, every
+	/// [`REPORT_INTERVAL`].
+	pub fn report(&mut self, now: Instant) -> Option<[u32; N]> {
+		let elapsed = (now - self.last_report).as_millis();
+		if elapsed < REPORT_INTERVAL.as_millis() {
+			return None;
+		}
+		self.last_report = now;
+		Some(core::array::from_fn(|i| {
+			let polls = core::mem::take(&mut self.sensors[i].polls);
+			(u64::from(polls) * 1000 / elapsed) as u32
+		}))
+	}
+}
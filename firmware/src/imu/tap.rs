@@ -0,0 +1,72 @@
+//! Detects taps on the tracker from its linear acceleration, for imus without a tap
+//! engine of their own. The server uses taps for reset gestures.
+//!
+//! A tap is a short, sharp spike: the acceleration jumps above [`THRESHOLD`] and
+//! falls back below [`QUIET`] within [`MAX_SPIKE`]. Anything longer is the tracker
+//! being moved or hit, like a foot landing, and doesn't count. A second tap within
+//! [`DOUBLE_WINDOW`] of the first makes a double tap.
+
+use defmt::Format;
+use embassy_time::{Duration, Instant};
+
+use crate::imu::Accel;
+
+/// How hard a tap has to be, in m/s^2. About twice gravity.
+const THRESHOLD: f32 = 20.;
+/// Below this, in m/s^2, the spike of a tap is over.
+const QUIET: f32 = 5.;
+/// The longest that the spike of a tap lasts.
+const MAX_SPIKE: Duration = Duration::from_millis(60);
+/// How long to ignore spikes after a tap, while the tracker still rings from it.
+const DEAD_TIME: Duration = Duration::from_millis(100);
+/// How soon the second tap of a double tap has to follow the first.
+const DOUBLE_WINDOW: Duration = Duration::from_millis(400);
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub struct Tap {
+	/// Whether this is the second tap of a double tap. The first one was already
+	/// reported as a single tap.
+	pub double: bool,
+}
+
+pub struct TapDetector {
+	/// When the acceleration rose above [`THRESHOLD`], while it still is.
+	spike: Option<Instant>,
+	/// When the last single tap ended, while a second one would make a double tap.
+	last_tap: Option<Instant>,
+	/// Spikes before this are ignored.
+	dead_until: Instant,
+}
+impl TapDetector {
+	pub fn new() -> Self {
+		Self {
+			spike: None,
+			last_tap: None,
+			dead_until: Instant::from_ticks(0),
+		}
+	}
+
+	/// Takes the next sample of the linear acceleration, measured at `now`, and
+	/// returns the tap that it ended, if any.
+	pub fn update(&mut self, linear_accel: &Accel, now: Instant) -> Option<Tap> {
+		let magnitude = linear_accel.norm();
+		let Some(started) = self.spike else {
+			if magnitude > THRESHOLD && now >= self.dead_until {
+				self.spike = Some(now);
+			}
+			return None;
+		};
+		if magnitude > QUIET {
+			return None;
+		}
+		self.spike = None;
+		if now - started > MAX_SPIKE {
+			return None;
+		}
+		self.dead_until = now + DEAD_TIME;
+		let double = self.last_tap.map_or(false, |t| now - t <= DOUBLE_WINDOW);
+		// A third tap starts the next double tap.
+		self.last_tap = (!double).then_some(now);
+		Some(Tap { double })
+	}
+}
@@ -0,0 +1,27 @@
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::convert::Infallible;
+use embedded_hal::digital::v2::OutputPin;
+
+use super::Color;
+use crate::utils::parse_u32;
+
+/// Whether driving the pin low turns the LED on.
+const ACTIVE_LOW: bool = parse_u32(env!("LED_ACTIVE_LOW")) != 0;
+
+pub type Pin = Box<dyn OutputPin<Error = Infallible> + Send>;
+
+/// A plain LED, which is either on or off.
+pub struct Gpio(Pin);
+impl Gpio {
+	pub fn new(pin: Pin) -> Self {
+		Self(pin)
+	}
+
+	pub fn show(&mut self, color: Color) {
+		let on = color != Color::OFF;
+		// Can't fail, the error type is `Infallible`.
+		let _ = self.0.set_state((on != ACTIVE_LOW).into());
+	}
+}
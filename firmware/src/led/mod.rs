@@ -0,0 +1,320 @@
+//! The status LED on boards that have one, configured with the `[led]` table of the
+//! board toml. It blinks a [`Pattern`] for what the tracker is doing, which it learns
+//! from the events of the network, imu, and battery tasks. While booting, each
+//! stage shows up as soon as it is reached:
+//!
+//! - Powered on, initializing the imus: solid, white. Lit by `main` right away,
+//!   before anything else.
+//! - Searching for the network: a slow blink, blue.
+//! - On the network, but the server hasn't answered yet: a short blink every
+//!   second, blue.
+//! - Connected to the server: a short blink every three seconds, green.
+//! - Low battery: two short blinks every two seconds, orange.
+//! - Calibrating: a fast blink, yellow.
+//! - A stage failed: long blinks and a pause, red, as many as the number of the
+//!   stage. One for the imu, which stops the tracker and also shows a sensor that
+//!   failed later on, two for the network, three for the server, and four for a
+//!   server that is too old to talk to. The last three keep retrying, and clear
+//!   once they succeed.
+//!
+//! Later ones in the list win over earlier ones, except for the low battery blinks.
+//! Those go on top of whatever else shows, like the blinks for the connection,
+//! other than while booting or failing, so that the red codes stay easy to count. A
+//! plain LED on a GPIO only shows the blinking, a WS2812 also the color. The server
+//! can turn the LED off with `CONFIG_LED`, see `config`.
+//!
+//! The LED has a task of its own, which only ever waits for the event bus and its
+//! timer. Blinks are timed from when their pattern started rather than from the
+//! last blink, so that a task that holds up the executor for a while, like the
+//! network one while reconnecting, delays the next change of the LED, but doesn't
+//! shift or stretch the ones after it.
+
+#[cfg(not(led_ws2812))]
+mod gpio;
+#[cfg(led_ws2812)]
+mod ws2812;
+
+use defmt::{debug, Format};
+use embassy_executor::task;
+use embassy_futures::select::{select, Either};
+use embassy_time::{Duration, Instant, Timer};
+
+use crate::config::{Config, Setting};
+use crate::events::{BootStage, Event, EventSubscriber};
+
+#[cfg(not(led_ws2812))]
+pub use self::gpio::Gpio as Led;
+#[cfg(led_ws2812)]
+pub use self::ws2812::{Spi, Ws2812 as Led, SPI_FREQ_KHZ};
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+	pub r: u8,
+	pub g: u8,
+	pub b: u8,
+}
+/// A WS2812 at full brightness is blinding, so the colors are dim.
+impl Color {
+	const OFF: Self = Self::new(0, 0, 0);
+	const WHITE: Self = Self::new(24, 24, 24);
+	const BLUE: Self = Self::new(0, 0, 32);
+	const GREEN: Self = Self::new(0, 32, 0);
+	const ORANGE: Self = Self::new(32, 8, 0);
+	const YELLOW: Self = Self::new(24, 24, 0);
+	const RED: Self = Self::new(32, 0, 0);
+
+	const fn new(r: u8, g: u8, b: u8) -> Self {
+		Self { r, g, b }
+	}
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Pattern {
+	/// Powered on, but the imus aren't initialized yet.
+	Booting,
+	/// The link to the server's network is down, like while searching for Wi-Fi.
+	Searching,
+	/// The link is up, but the server hasn't answered yet.
+	Linked,
+	Connected,
+	LowBattery,
+	Calibrating,
+	Failed(BootStage),
+}
+impl Pattern {
+	/// The color, and how long the LED is on and then off for each blink. Repeats
+	/// from the start once it ends.
+	fn steps(self) -> (Color, &'static [(u64, u64)]) {
+		const CODE: (u64, u64) = (400, 400);
+		const CODE_END: (u64, u64) = (400, 2000);
+		match self {
+			// Blinking only starts once the imus are up, so a board that never gets
+			// there stays lit.
+			Self::Booting => (Color::WHITE, &[(1000, 0)]),
+			Self::Searching => (Color::BLUE, &[(500, 500)]),
+			Self::Linked => (Color::BLUE, &[(100, 900)]),
+			Self::Connected => (Color::GREEN, &[(50, 2950)]),
+			Self::LowBattery => (Color::ORANGE, &[(100, 150), (100, 1650)]),
+			Self::Calibrating => (Color::YELLOW, &[(100, 100)]),
+			Self::Failed(BootStage::Imu) => (Color::RED, &[CODE_END]),
+			Self::Failed(BootStage::Network) => (Color::RED, &[CODE, CODE_END]),
+			Self::Failed(BootStage::Server) => (Color::RED, &[CODE, CODE, CODE_END]),
+			Self::Failed(BootStage::Incompatible) => {
+				(Color::RED, &[CODE, CODE, CODE, CODE_END])
+			}
+		}
+	}
+
+	/// The color at `elapsed` into the pattern, `None` while it is off, and how much
+	/// longer until that changes.
+	fn at(self, elapsed: Duration) -> (Option<Color>, Duration) {
+		let (color, steps) = self.steps();
+		let period: u64 = steps.iter().map(|(on_ms, off_ms)| on_ms + off_ms).sum();
+		let mut ms = elapsed.as_millis() % period;
+		for &(on_ms, off_ms) in steps {
+			if ms < on_ms {
+				return (Some(color), Duration::from_millis(on_ms - ms));
+			}
+			ms -= on_ms;
+			if ms < off_ms {
+				return (None, Duration::from_millis(off_ms - ms));
+			}
+			ms -= off_ms;
+		}
+		unreachable!()
+	}
+}
+
+/// A pattern, and when it started, which its blinks are timed from.
+#[derive(Debug, Clone, Copy)]
+struct Layer {
+	pattern: Pattern,
+	since: Instant,
+}
+impl Layer {
+	fn new(pattern: Pattern, now: Instant) -> Self {
+		debug!("LED: {}", pattern);
+		Self {
+			pattern,
+			since: now,
+		}
+	}
+
+	/// Starts over with `pattern` if it is another one than before.
+	fn switch(self, pattern: Pattern, now: Instant) -> Self {
+		if pattern == self.pattern {
+			self
+		} else {
+			Self::new(pattern, now)
+		}
+	}
+
+	/// The color at `now`, if any, and when that changes.
+	fn at(self, now: Instant) -> (Option<Color>, Instant) {
+		let (color, left) = self.pattern.at(now - self.since);
+		(color, now + left)
+	}
+}
+
+/// What the LED shows at `now`, with `overlay` on top of `base` while it is on,
+/// and when that changes.
+fn frame(base: Layer, overlay: Option<Layer>, now: Instant) -> (Color, Instant) {
+	let (color, until) = base.at(now);
+	match overlay.map(|overlay| overlay.at(now)) {
+		Some((Some(color), next)) => (color, until.min(next)),
+		Some((None, next)) => (color.unwrap_or(Color::OFF), until.min(next)),
+		None => (color.unwrap_or(Color::OFF), until),
+	}
+}
+
+/// What the tracker is doing, as far as the LED shows it.
+#[derive(Debug, Default)]
+struct Status {
+	imus_ready: bool,
+	/// The earliest stage of booting that failed, and hasn't succeeded since.
+	boot_failed: Option<BootStage>,
+	link_up: bool,
+	connected: bool,
+	battery_low: bool,
+	/// One bit for each sensor.
+	calibrating: u8,
+	failed: u8,
+}
+impl Status {
+	fn update(&mut self, event: Event) {
+		let bit = |sensor_id: u8| 1u8.checked_shl(sensor_id.into()).unwrap_or(0);
+		match event {
+			Event::ImusReady => self.imus_ready = true,
+			Event::BootFailed(stage) => {
+				// A server was found after all.
+				if stage == BootStage::Incompatible {
+					self.clear_failed(BootStage::Server);
+				}
+				self.boot_failed =
+					Some(self.boot_failed.map_or(stage, |s| s.min(stage)));
+			}
+			// Without the link, there is no telling whether the server is there.
+			Event::LinkChanged { up } => {
+				let stage = if up {
+					BootStage::Network
+				} else {
+					BootStage::Server
+				};
+				self.clear_failed(stage);
+				self.link_up = up;
+				self.connected &= up;
+			}
+			// Reaching the server means that the link is up, also for links that
+			// don't tell.
+			Event::ConnectionChanged { connected } => {
+				if connected {
+					self.clear_failed(BootStage::Network);
+					self.clear_failed(BootStage::Server);
+					self.clear_failed(BootStage::Incompatible);
+				}
+				self.connected = connected;
+				self.link_up |= connected;
+			}
+			Event::Battery { low, .. } => self.battery_low = low,
+			Event::BatteryLow { .. } => self.battery_low = true,
+			Event::Calibrating {
+				sensor_id,
+				running: true,
+			} => self.calibrating |= bit(sensor_id),
+			Event::Calibrating {
+				sensor_id,
+				running: false,
+			} => self.calibrating &= !bit(sensor_id),
+			Event::ImuError { sensor_id, .. } => self.failed |= bit(sensor_id),
+			Event::ImuRecovered { sensor_id } => self.failed &= !bit(sensor_id),
+			_ => (),
+		}
+	}
+
+	/// Forgets that `stage` failed, once it succeeded or there is no telling anymore.
+	fn clear_failed(&mut self, stage: BootStage) {
+		if self.boot_failed == Some(stage) {
+			self.boot_failed = None;
+		}
+	}
+
+	/// What shows, other than the low battery blinks.
+	fn base(&self) -> Pattern {
+		if let Some(stage) = self.boot_failed {
+			Pattern::Failed(stage)
+		} else if self.failed != 0 {
+			Pattern::Failed(BootStage::Imu)
+		} else if !self.imus_ready {
+			Pattern::Booting
+		} else if self.calibrating != 0 {
+			Pattern::Calibrating
+		} else if self.connected {
+			Pattern::Connected
+		} else if self.link_up {
+			Pattern::Linked
+		} else {
+			Pattern::Searching
+		}
+	}
+
+	/// What goes on top of [`Self::base`].
+	fn overlay(&self) -> Option<Pattern> {
+		let countable = matches!(self.base(), Pattern::Booting | Pattern::Failed(_));
+		(self.battery_low && !countable).then_some(Pattern::LowBattery)
+	}
+}
+
+/// Lights the LED for [`Pattern::Booting`], as soon as the peripherals are up. Even
+/// if the server turned it off, the setting is only loaded later.
+pub fn power_on(led: &mut Led) {
+	led.show(Pattern::Booting.steps().0);
+}
+
+/// Takes a subscriber from before the executor started, so that no stage of booting
+/// is missed.
+#[task]
+pub async fn led_task(mut events: EventSubscriber, mut led: Led) -> ! {
+	debug!("LED task");
+	let mut status = Status::default();
+	let mut enabled = Config::load().led;
+	let mut base = Layer::new(status.base(), Instant::now());
+	let mut overlay: Option<Layer> = None;
+	let mut shown = None;
+	loop {
+		let (color, next) = if enabled {
+			let (color, next) = frame(base, overlay, Instant::now());
+			(color, Some(next))
+		} else {
+			(Color::OFF, None)
+		};
+		// Writing a WS2812 takes a while, and most wake ups don't change anything.
+		if shown != Some(color) {
+			led.show(color);
+			shown = Some(color);
+		}
+		let timer = async {
+			match next {
+				Some(next) => Timer::at(next).await,
+				None => core::future::pending().await,
+			}
+		};
+		let event = match select(events.next_message_pure(), timer).await {
+			Either::First(event) => event,
+			Either::Second(()) => continue,
+		};
+		match event {
+			Event::ConfigChanged(Setting::Led { enabled: e }) => {
+				debug!("LED enabled: {}", e);
+				enabled = e;
+			}
+			event => status.update(event),
+		}
+		// Most events don't change the patterns, which keep their timing meanwhile.
+		let now = Instant::now();
+		base = base.switch(status.base(), now);
+		overlay = status.overlay().map(|pattern| match overlay {
+			Some(overlay) => overlay.switch(pattern, now),
+			None => Layer::new(pattern, now),
+		});
+	}
+}
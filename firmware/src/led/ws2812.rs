@@ -0,0 +1,46 @@
+//! Drives a WS2812 from the MOSI pin of an SPI peripheral, since its timing is too
+//! tight to toggle a GPIO by hand. At [`SPI_FREQ_KHZ`], four bits on the wire take
+//! as long as one bit of the LED: `1000` is a `0` and `1110` is a `1`.
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+
+use super::Color;
+
+/// Four times the 800 kHz of the LED.
+pub const SPI_FREQ_KHZ: u32 = 3200;
+/// The LED latches the color after the data line was low for at least 280 us, which
+/// is about this many bytes.
+const RESET_LEN: usize = 112;
+/// Green, red, and blue, with four bytes on the wire for each.
+const DATA_LEN: usize = 3 * 4;
+
+/// Writes the bytes out of MOSI at [`SPI_FREQ_KHZ`], blocking until they are sent.
+pub type Spi = Box<dyn FnMut(&[u8]) + Send>;
+
+pub struct Ws2812 {
+	spi: Spi,
+	buffer: [u8; DATA_LEN + RESET_LEN],
+}
+impl Ws2812 {
+	pub fn new(spi: Spi) -> Self {
+		Self {
+			spi,
+			buffer: [0; DATA_LEN + RESET_LEN],
+		}
+	}
+
+	pub fn show(&mut self, color: Color) {
+		for (i, byte) in [color.g, color.r, color.b].into_iter().enumerate() {
+			for half in 0..2 {
+				let bits = byte << (4 * half);
+				let encode = |bit: u8| if bits & bit != 0 { 0b1110 } else { 0b1000 };
+				self.buffer[i * 4 + half * 2] = encode(0x80) << 4 | encode(0x40);
+				self.buffer[i * 4 + half * 2 + 1] = encode(0x20) << 4 | encode(0x10);
+			}
+		}
+		// The rest of the buffer stays zero, for the reset.
+		(self.spi)(&self.buffer);
+	}
+}
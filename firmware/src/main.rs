@@ -15,10 +15,29 @@
 load_dotenv::try_load_dotenv!();
 
 mod aliases;
+#[cfg(battery)]
+mod battery;
+#[cfg(button)]
+mod button;
+mod config;
+#[cfg(console)]
+mod console;
+mod diag;
+#[cfg(display)]
+mod display;
+mod events;
 mod globals;
 mod imu;
+#[cfg(led)]
+mod led;
+mod memory;
 mod networking;
+#[cfg(feature = "ota")]
+mod ota;
 mod peripherals;
+mod power;
+mod storage;
+mod thermal;
 mod utils;
 
 #[cfg(bbq)]
@@ -37,39 +56,114 @@ use xtensa_lx_rt::entry;
 
 #[entry]
 fn main() -> ! {
-	use crate::imu::Quat;
+	use crate::events::EventBus;
+	use crate::imu::{FusedData, SensorEvent, MAX_IMUS};
 	use crate::networking::protocol::Packets;
-	use crate::utils::Unreliable;
+	use crate::utils::{Reliable, Unreliable};
 	use embedded_hal::blocking::delay::DelayMs;
 
 	#[cfg(bbq)]
 	let bbq = defmt_bbq::init().unwrap();
 
 	self::globals::setup();
+	self::memory::paint_stack();
 	debug!("Booted");
 	defmt::trace!("Trace");
 
 	let p = self::peripherals::ඞ::get_peripherals();
 	#[allow(unused)]
 	let (bbq_peripheral, mut p) = p.bbq_peripheral();
+	// Right away, so that a board that doesn't get any further still shows that it
+	// has power.
+	#[cfg(led)]
+	crate::led::power_on(&mut p.led);
 
 	p.delay.delay_ms(500u32);
 	debug!("Initialized peripherals");
+	// Before anything that an update might have broken.
+	#[cfg(feature = "ota")]
+	let unconfirmed = crate::ota::check_boot();
 
 	static PACKETS: StaticCell<Packets> = StaticCell::new();
 	let packets: &'static Packets = PACKETS.init(Packets::new());
 
-	static QUAT: StaticCell<Unreliable<Quat>> = StaticCell::new();
-	let quat: &'static Unreliable<Quat> = QUAT.init(Unreliable::new());
+	static FUSED: StaticCell<[Unreliable<FusedData>; MAX_IMUS]> = StaticCell::new();
+	let fused: &'static [Unreliable<FusedData>; MAX_IMUS] =
+		FUSED.init(core::array::from_fn(|_| Unreliable::new()));
+
+	static SENSOR_EVENTS: StaticCell<Reliable<SensorEvent>> = StaticCell::new();
+	let sensor_events: &'static Reliable<SensorEvent> =
+		SENSOR_EVENTS.init(Reliable::new());
+
+	static SENSOR_ENABLED: StaticCell<[Unreliable<bool>; MAX_IMUS]> = StaticCell::new();
+	let sensor_enabled: &'static [Unreliable<bool>; MAX_IMUS] =
+		SENSOR_ENABLED.init(core::array::from_fn(|_| Unreliable::new()));
+
+	static EVENTS: StaticCell<EventBus> = StaticCell::new();
+	let events: &'static EventBus = EVENTS.init(EventBus::new());
+	// Before the imu task publishes how initializing went.
+	#[cfg(led)]
+	let led_events = events.subscriber().unwrap();
+	#[cfg(display)]
+	let display_events = events.subscriber().unwrap();
+
+	#[cfg(not(spi_imu))]
+	let imu_bus: crate::imu::ImuBus = {
+		use crate::aliases::ImuBusConcrete;
+		use crate::imu::bus::Bus;
+
+		static IMU_BUS: StaticCell<Bus<ImuBusConcrete<'static>>> = StaticCell::new();
+		let mut i2c = p.imu_bus;
+		// Before the drivers take over the bus.
+		crate::diag::scan_i2c(&mut i2c);
+		IMU_BUS.init(Bus::new(i2c))
+	};
+	#[cfg(spi_imu)]
+	let imu_bus = p.imu_bus;
 
 	static EXECUTOR: StaticCell<Executor> = StaticCell::new();
 	EXECUTOR.init(Executor::new()).run(move |s| {
-		s.spawn(crate::networking::protocol::control_task(packets, quat))
+		s.spawn(crate::networking::protocol::control_task(
+			packets,
+			fused,
+			sensor_events,
+			sensor_enabled,
+			events,
+		))
+		.unwrap();
+		s.spawn(crate::networking::network_task(packets, p.radio, events))
+			.unwrap();
+		s.spawn(crate::imu::imu_task(
+			fused,
+			sensor_events,
+			sensor_enabled,
+			events,
+			imu_bus,
+			p.delay,
+		))
+		.unwrap();
+		s.spawn(crate::thermal::thermal_task(events, p.temp))
 			.unwrap();
-		s.spawn(crate::networking::network_task(packets)).unwrap();
-		s.spawn(crate::imu::imu_task(quat, p.i2c, p.delay)).unwrap();
+		s.spawn(crate::events::event_log_task(events)).unwrap();
+		#[cfg(button)]
+		s.spawn(crate::button::button_task(events, p.button))
+			.unwrap();
+		#[cfg(battery)]
+		s.spawn(crate::battery::battery_task(events, p.battery))
+			.unwrap();
+		#[cfg(led)]
+		s.spawn(crate::led::led_task(led_events, p.led)).unwrap();
+		#[cfg(display)]
+		s.spawn(crate::display::display_task(display_events, imu_bus))
+			.unwrap();
+		#[cfg(console)]
+		s.spawn(serial_commands_task()).unwrap();
 		#[cfg(bbq)]
 		s.spawn(logger_task(bbq, bbq_peripheral)).unwrap();
+		#[cfg(feature = "ota")]
+		if unconfirmed {
+			s.spawn(crate::ota::confirm_task()).unwrap();
+		}
 	});
 }
 
@@ -81,3 +175,9 @@ async fn logger_task(
 ) {
 	crate::bbq_logger::ඞ::logger_task(bbq, logger_peripheral).await;
 }
+
+#[cfg(console)]
+#[embassy_executor::task]
+async fn serial_commands_task() {
+	crate::console::serial_commands().await;
+}
@@ -0,0 +1,99 @@
+//! Measures how much memory we use, so that stack sizes can be tuned with data, and
+//! crashes after long sessions can be told apart from running out of memory.
+//!
+//! The embassy tasks are futures in statics that all run on the main stack, so
+//! there is no stack per task. The main stack's high-water mark covers the deepest
+//! call in any of them. We find it by filling the free part of the stack with a
+//! pattern at boot, and checking how much of the pattern got overwritten since.
+
+use crate::globals::ALLOCATOR;
+
+/// What the unused part of the stack is filled with.
+#[cfg(cortex_m)]
+const PAINT: u32 = 0xC570_C570;
+/// Left unpainted below the stack pointer at boot, for the frame of [`paint_stack`]
+/// itself.
+#[cfg(cortex_m)]
+const PAINT_MARGIN: usize = 256;
+
+#[derive(Clone, Copy)]
+pub struct Stack {
+	/// The most stack that was ever in use, in bytes.
+	pub peak: usize,
+	pub size: usize,
+}
+
+#[derive(Clone, Copy)]
+pub struct Heap {
+	pub used: usize,
+	pub free: usize,
+}
+
+pub fn heap() -> Heap {
+	Heap {
+		used: ALLOCATOR.used(),
+		free: ALLOCATOR.free(),
+	}
+}
+
+#[cfg(cortex_m)]
+mod bounds {
+	extern "C" {
+		/// The end of the statics, set by `cortex-m-rt`. The stack grows down
+		/// towards it.
+		static __sheap: u32;
+		/// Where the stack starts, set by `cortex-m-rt`.
+		static _stack_start: u32;
+	}
+
+	/// The lowest and highest address of the stack, aligned to words.
+	pub fn get() -> (usize, usize) {
+		// Safety: Only the addresses of the symbols are used, not their values.
+		unsafe {
+			(
+				core::ptr::addr_of!(__sheap) as usize,
+				core::ptr::addr_of!(_stack_start) as usize,
+			)
+		}
+	}
+}
+
+/// Fills the unused part of the stack with [`PAINT`]. Call once at boot, before
+/// starting the executor.
+pub fn paint_stack() {
+	#[cfg(cortex_m)]
+	{
+		let (bottom, _top) = bounds::get();
+		let sp = cortex_m::register::msp::read() as usize;
+		for addr in (bottom..sp - PAINT_MARGIN).step_by(4) {
+			// Safety: Nothing lives below the stack pointer, and the area between
+			// the statics and the stack belongs to the stack.
+			unsafe { (addr as *mut u32).write_volatile(PAINT) };
+		}
+	}
+}
+
+/// The high-water mark of the main stack, if we can tell on this MCU.
+#[cfg(cortex_m)]
+pub fn stack() -> Option<Stack> {
+	let (bottom, top) = bounds::get();
+	// Stops at the first word that got overwritten, since the stack grows down.
+	let untouched = (bottom..top)
+		.step_by(4)
+		// Safety: See `paint_stack`, reading is fine even where it is in use.
+		.take_while(|&addr| unsafe { (addr as *const u32).read_volatile() } == PAINT)
+		.count() * 4;
+	let size = top - bottom;
+	Some(Stack {
+		peak: size - untouched,
+		size,
+	})
+}
+
+/// The high-water mark of the main stack, if we can tell on this MCU.
+#[cfg(not(cortex_m))]
+pub fn stack() -> Option<Stack> {
+	// TODO: The esp linker scripts place the stack differently per chip, find its
+	// bounds there too.
+	None
+}
@@ -1,3 +1,4 @@
+use crate::events::EventBus;
 use crate::networking::Packets;
 use bleps::{
 	ad_structure::{
@@ -11,7 +12,7 @@ use defmt::{debug, error, trace, warn};
 use embassy_futures::yield_now;
 use esp_wifi::{self, ble::controller::BleConnector, current_millis};
 
-pub async fn network_task(_packets: &Packets) -> ! {
+pub async fn network_task(_packets: &Packets, _events: &EventBus) -> ! {
 	// HCI is the host-controller interface, which lets the MCU communicate to the BLE hardware through a standard
 	// command interface
 	let connector = BleConnector {};
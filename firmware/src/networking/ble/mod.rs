@@ -1,3 +1,23 @@
-#[cfg(feature = "net-ble")]
+//! BLE makes the tracker a peripheral that a phone, or a bridge to the server, connects
+//! to instead of a Wi-Fi network.
+//!
+//! On the nrf52 it goes through the softdevice, and exposes:
+//! - The tracker service `133712e0-2354-11eb-9f10-fbc30a62cf38`, advertised so that
+//!   bridges can find trackers by it, with the characteristics:
+//!   - `13370001-…` for the packets, in the same format as the UDP datagrams of
+//!     `net-wifi`, one packet per value. Serverbound ones are notified, clientbound
+//!     ones are written without response.
+//!   - `13370002-…` for text commands like the ones of the USB serial console. So far
+//!     that is `DIAG`, which prints the triage report to the log.
+//! - The standard battery service, so that phones show the charge without knowing
+//!   about SlimeVR.
+//!
+//! The ESP32 family only advertises a placeholder service with `bleps` so far.
+
+#[cfg(mcu_f_esp32)]
 #[path = "esp.rs"]
 pub mod ඞ;
+
+#[cfg(mcu_f_nrf52)]
+#[path = "nrf.rs"]
+pub mod ඞ;
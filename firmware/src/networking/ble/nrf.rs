@@ -0,0 +1,262 @@
+use core::cell::Cell;
+use core::mem;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use defmt::{debug, info, trace, unwrap, warn};
+use embassy_futures::select::{select, select3, Either, Either3};
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::channel::Channel;
+use firmware_protocol::SbPacket;
+use heapless::Vec;
+use nrf_softdevice::ble::{gatt_server, peripheral, Connection};
+use nrf_softdevice::{raw, Softdevice};
+
+use crate::events::{Event, EventBus};
+use crate::networking::protocol::Packets;
+use crate::networking::transport::{self, LinkError, Transport};
+
+/// What the tracker calls itself, in the scan response and the GAP device name.
+const NAME: &str = "SlimeVR-Rust";
+/// The largest ATT MTU of the softdevice. Centrals that negotiate a smaller one can't
+/// take the larger packets, like the handshake.
+const ATT_MTU: u16 = 247;
+/// What fits into one notification with [`ATT_MTU`].
+const MAX_PACKET_LEN: usize = ATT_MTU as usize - 3;
+/// Enough for the longest command.
+const COMMAND_LEN: usize = 32;
+/// How many clientbound packets can wait for the control task.
+const INCOMING_LEN: usize = 2;
+
+/// Whether [`network_task`] enabled the softdevice yet.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[nrf_softdevice::gatt_service(uuid = "180f")]
+pub struct BatteryService {
+	#[characteristic(uuid = "2a19", read, notify)]
+	battery_level: u8,
+}
+
+#[nrf_softdevice::gatt_service(uuid = "133712e0-2354-11eb-9f10-fbc30a62cf38")]
+pub struct TrackerService {
+	#[characteristic(
+		uuid = "13370001-2354-11eb-9f10-fbc30a62cf38",
+		write_without_response,
+		notify
+	)]
+	packets: Vec<u8, MAX_PACKET_LEN>,
+	#[characteristic(uuid = "13370002-2354-11eb-9f10-fbc30a62cf38", write)]
+	command: Vec<u8, COMMAND_LEN>,
+}
+
+#[nrf_softdevice::gatt_server]
+pub struct Server {
+	battery: BatteryService,
+	tracker: TrackerService,
+}
+
+pub async fn network_task(packets: &Packets, events: &EventBus) -> ! {
+	let sd = Softdevice::enable(&softdevice_config());
+	let server = unwrap!(Server::new(sd));
+	debug!("Initialized BLE");
+	let sd: &Softdevice = sd;
+	ENABLED.store(true, Ordering::Release);
+	let storage = crate::storage::run(sd);
+	match select3(sd.run(), serve(sd, &server, packets, events), storage).await {
+		Either3::First(never) | Either3::Second(never) | Either3::Third(never) => never,
+	}
+}
+
+/// The softdevice, for the other tasks that need it, like [`crate::thermal`]. `None`
+/// until it is enabled.
+pub fn softdevice() -> Option<&'static Softdevice> {
+	// Safety: It is enabled, and we only ever hand out shared references to it.
+	ENABLED
+		.load(Ordering::Acquire)
+		.then(|| unsafe { Softdevice::steal() })
+}
+
+/// Advertises until a central connects, and exchanges packets with it until it
+/// disconnects again.
+async fn serve(
+	sd: &Softdevice,
+	server: &Server,
+	packets: &Packets,
+	events: &EventBus,
+) -> ! {
+	// Kept across centrals, like over Wi-Fi.
+	let mut tx_seq = 0;
+	loop {
+		let conn = match select(advertise(sd), discard(server, packets)).await {
+			Either::First(Ok(conn)) => conn,
+			Either::First(Err(e)) => {
+				warn!("Failed to advertise: {}", e);
+				continue;
+			}
+			Either::Second(never) => never,
+		};
+		info!("BLE central connected");
+		crate::events::publish(events, Event::LinkChanged { up: true });
+		let link = Link {
+			conn: &conn,
+			server,
+			subscribed: Cell::new(false),
+			incoming: Channel::new(),
+		};
+		// The softdevice calls us back for writes, the values are picked up by
+		// `recv_packet`.
+		let gatt = gatt_server::run(&conn, server, |e| match e {
+			ServerEvent::Tracker(TrackerServiceEvent::PacketsWrite(data)) => {
+				if link.incoming.try_send(data).is_err() {
+					warn!("Dropping a clientbound packet, the control task is busy");
+				}
+			}
+			ServerEvent::Tracker(TrackerServiceEvent::PacketsCccdWrite {
+				notifications,
+			}) => link.subscribed.set(notifications),
+			ServerEvent::Tracker(TrackerServiceEvent::CommandWrite(command)) => {
+				match &command[..] {
+					b"DIAG" => crate::diag::print(),
+					other => warn!("unknown command {=[u8]:a}", other),
+				}
+			}
+			ServerEvent::Battery(BatteryServiceEvent::BatteryLevelCccdWrite {
+				..
+			}) => (),
+		});
+		// Shared with the callbacks, so the transport is a reference.
+		let mut shared = &link;
+		let exchange = transport::exchange(&mut shared, packets, &mut tx_seq);
+		match select(gatt, exchange).await {
+			Either::First(_) => info!("BLE central disconnected"),
+			// Never returns, the link only goes down with the connection.
+			Either::Second(()) => (),
+		}
+		crate::events::publish(events, Event::LinkChanged { up: false });
+	}
+}
+
+async fn advertise(sd: &Softdevice) -> Result<Connection, peripheral::AdvertiseError> {
+	#[rustfmt::skip]
+	let adv_data = &[
+		0x02, raw::BLE_GAP_AD_TYPE_FLAGS as u8, raw::BLE_GAP_ADV_FLAGS_LE_ONLY_GENERAL_DISC_MODE as u8,
+		// The UUID of the tracker service, least significant byte first.
+		0x11, raw::BLE_GAP_AD_TYPE_128BIT_SERVICE_UUID_COMPLETE as u8,
+		0x38, 0xcf, 0x62, 0x0a, 0xc3, 0xfb, 0x10, 0x9f, 0xeb, 0x11, 0x54, 0x23, 0xe0, 0x12, 0x37, 0x13,
+	];
+	let mut scan_data: Vec<u8, 31> = Vec::new();
+	// Can't fail, the name is short enough.
+	let _ = scan_data.extend_from_slice(&[
+		NAME.len() as u8 + 1,
+		raw::BLE_GAP_AD_TYPE_COMPLETE_LOCAL_NAME as u8,
+	]);
+	let _ = scan_data.extend_from_slice(NAME.as_bytes());
+	let adv = peripheral::ConnectableAdvertisement::ScannableUndirected {
+		adv_data,
+		scan_data: &scan_data,
+	};
+	debug!("Advertising");
+	peripheral::advertise_connectable(sd, adv, &peripheral::Config::default()).await
+}
+
+/// The connected central.
+struct Link<'a> {
+	conn: &'a Connection,
+	server: &'a Server,
+	/// Only notified once the central subscribed.
+	subscribed: Cell<bool>,
+	/// The packets that the central wrote.
+	incoming: Channel<NoopRawMutex, Vec<u8, MAX_PACKET_LEN>, INCOMING_LEN>,
+}
+impl Transport for &Link<'_> {
+	const MAX_PACKET_LEN: usize = MAX_PACKET_LEN;
+
+	async fn send_packet(
+		&mut self,
+		msg: &SbPacket,
+		packet: &[u8],
+	) -> Result<(), LinkError> {
+		update_battery(self.server, msg, Some(self.conn));
+		if !self.subscribed.get() {
+			return Ok(());
+		}
+		// Can't fail, `exchange` doesn't serialize more than fits.
+		let value = Vec::from_slice(packet).unwrap();
+		self.server
+			.tracker
+			.packets_notify(self.conn, &value)
+			.map_err(|e| {
+				debug!("Failed to notify: {}", e);
+				LinkError::Send
+			})
+	}
+
+	async fn recv_packet(&mut self, buffer: &mut [u8]) -> Result<usize, LinkError> {
+		loop {
+			let data = self.incoming.recv().await;
+			let Some(buffer) = buffer.get_mut(..data.len()) else { trace!("Discarding {}", &data[..]); continue };
+			buffer.copy_from_slice(&data);
+			return Ok(data.len());
+		}
+	}
+}
+
+/// Drops the serverbound packets while nobody is connected, so that the control task
+/// doesn't block on them. The battery level is still kept up to date, for when
+/// someone connects.
+async fn discard(server: &Server, packets: &Packets) -> ! {
+	loop {
+		let msg = packets.serverbound.recv().await;
+		update_battery(server, &msg, None);
+	}
+}
+
+/// Takes the battery level from the packets that report it to the server.
+fn update_battery(server: &Server, msg: &SbPacket, conn: Option<&Connection>) {
+	let SbPacket::BatteryLevel { level, .. } = msg else { return };
+	let percent = (level * 100.).clamp(0., 100.) as u8;
+	if let Err(e) = server.battery.battery_level_set(&percent) {
+		warn!("Failed to set the battery level: {}", e);
+	}
+	if let Some(conn) = conn {
+		// Fails if the central didn't subscribe, which is fine.
+		let _ = server.battery.battery_level_notify(conn, &percent);
+	}
+}
+
+fn softdevice_config() -> nrf_softdevice::Config {
+	nrf_softdevice::Config {
+		// Not every board has a 32 kHz crystal, so use the internal oscillator.
+		clock: Some(raw::nrf_clock_lf_cfg_t {
+			source: raw::NRF_CLOCK_LF_SRC_RC as u8,
+			rc_ctiv: 16,
+			rc_temp_ctiv: 2,
+			accuracy: raw::NRF_CLOCK_LF_ACCURACY_500_PPM as u8,
+		}),
+		conn_gap: Some(raw::ble_gap_conn_cfg_t {
+			conn_count: 1,
+			event_length: 24,
+		}),
+		conn_gatt: Some(raw::ble_gatt_conn_cfg_t { att_mtu: ATT_MTU }),
+		gatts_attr_tab_size: Some(raw::ble_gatts_cfg_attr_tab_size_t {
+			attr_tab_size: raw::BLE_GATTS_ATTR_TAB_SIZE_DEFAULT,
+		}),
+		gap_role_count: Some(raw::ble_gap_cfg_role_count_t {
+			adv_set_count: 1,
+			periph_role_count: 1,
+			central_role_count: 0,
+			central_sec_count: 0,
+			_bitfield_1: raw::ble_gap_cfg_role_count_t::new_bitfield_1(0),
+		}),
+		gap_device_name: Some(raw::ble_gap_cfg_device_name_t {
+			p_value: NAME.as_ptr() as _,
+			current_len: NAME.len() as u16,
+			max_len: NAME.len() as u16,
+			// Safety: All zeroes means that the central can't change the name.
+			write_perm: unsafe { mem::zeroed() },
+			_bitfield_1: raw::ble_gap_cfg_device_name_t::new_bitfield_1(
+				raw::BLE_GATTS_VLOC_STACK as u8,
+			),
+		}),
+		..Default::default()
+	}
+}
@@ -0,0 +1,156 @@
+use defmt::{debug, info, trace, warn};
+use embassy_time::{Duration, Instant, Timer};
+use esp_wifi::esp_now::{EspNow, PeerInfo, ReceivedData, BROADCAST_ADDRESS};
+use firmware_protocol::SbPacket;
+
+use crate::events::{Event, EventBus};
+use crate::networking::espnow::pairing::{self, Mac, Pairing};
+use crate::networking::espnow::tdma::Schedule;
+use crate::networking::protocol::Packets;
+use crate::networking::transport::{self, LinkError, Transport};
+
+/// The most that fits into one ESP-NOW frame.
+const MAX_FRAME_LEN: usize = 250;
+/// How often to broadcast a pairing request, until a receiver answers.
+const PAIRING_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to sleep between looking for received frames. The driver queues them,
+/// so this only adds latency, to packets and to when we see a beacon, which the
+/// guard of `tdma` leaves room for.
+const RECEIVE_POLL_INTERVAL: Duration = Duration::from_micros(250);
+
+pub async fn network_task(packets: &Packets, events: &EventBus) -> ! {
+	let mut esp_now = esp_wifi::esp_now::esp_now()
+		.initialize()
+		.expect("Failed to initialize ESP-NOW");
+	debug!("Initialized ESP-NOW");
+	crate::networking::tx_power::apply();
+	let Pairing { receiver, slot } = match pairing::load() {
+		Some(pairing) => pairing,
+		None => pair(&mut esp_now).await,
+	};
+	add_peer(&mut esp_now, receiver);
+	info!("Sending to receiver {=[u8]:02x}", receiver);
+
+	crate::events::publish(events, Event::LinkChanged { up: true });
+
+	let mut link = Link {
+		esp_now,
+		receiver,
+		schedule: slot.map(Schedule::new),
+	};
+	let mut tx_seq = 0;
+	loop {
+		// Nothing tells us when the receiver goes away, so the link never goes down.
+		transport::exchange(&mut link, packets, &mut tx_seq).await;
+	}
+}
+
+/// The receiver we paired with.
+struct Link {
+	esp_now: EspNow,
+	receiver: Mac,
+	/// When we may send, if the receiver gave us a slot.
+	schedule: Option<Schedule>,
+}
+impl Transport for Link {
+	const MAX_PACKET_LEN: usize = MAX_FRAME_LEN;
+
+	async fn send_packet(
+		&mut self,
+		_: &SbPacket,
+		packet: &[u8],
+	) -> Result<(), LinkError> {
+		if let Some(schedule) = &self.schedule {
+			Timer::at(schedule.send_at(Instant::now())).await;
+		}
+		self.esp_now.send(&self.receiver, packet).map_err(|e| {
+			debug!("Failed to send: {}", defmt::Debug2Format(&e));
+			LinkError::Send
+		})
+	}
+
+	async fn recv_packet(&mut self, buffer: &mut [u8]) -> Result<usize, LinkError> {
+		loop {
+			let frame = recv_frame(&mut self.esp_now).await;
+			// Like the pairing requests of other trackers.
+			if frame.info.src_address != self.receiver {
+				continue;
+			}
+			let data = frame.get_data();
+			if let Some(schedule) = &mut self.schedule {
+				if schedule.beacon(data, Instant::now()) {
+					continue;
+				}
+			}
+			let Some(buffer) = buffer.get_mut(..data.len()) else {
+				trace!("Discarding {}", data);
+				continue;
+			};
+			buffer.copy_from_slice(data);
+			return Ok(data.len());
+		}
+	}
+}
+
+/// Broadcasts pairing requests until a receiver accepts one, and stores it.
+async fn pair(esp_now: &mut EspNow) -> Pairing {
+	info!("Not paired yet, put the receiver into pairing mode");
+	add_peer(esp_now, BROADCAST_ADDRESS);
+	let mut next_request = Instant::now();
+	loop {
+		if Instant::now() >= next_request {
+			next_request = Instant::now() + PAIRING_INTERVAL;
+			if let Err(e) = esp_now.send(&BROADCAST_ADDRESS, pairing::REQUEST) {
+				warn!(
+					"Failed to send pairing request: {}",
+					defmt::Debug2Format(&e)
+				);
+			}
+		}
+		if let Some(frame) = esp_now.receive() {
+			let slot = match frame.get_data().strip_prefix(pairing::ACCEPT) {
+				// Receivers that don't schedule the trackers leave out the slot.
+				Some([]) => Some(None),
+				Some(&[slot]) => Some(Some(slot)),
+				_ => None,
+			};
+			if let Some(slot) = slot {
+				let pairing = Pairing {
+					receiver: frame.info.src_address,
+					slot,
+				};
+				pairing::store(pairing);
+				return pairing;
+			}
+		}
+		Timer::after(RECEIVE_POLL_INTERVAL).await;
+	}
+}
+
+fn add_peer(esp_now: &mut EspNow, mac: Mac) {
+	if esp_now.peer_exists(&mac).unwrap_or(false) {
+		return;
+	}
+	let peer = PeerInfo {
+		peer_address: mac,
+		lmk: None,
+		// Whatever channel the radio is on, which the receiver has to match.
+		channel: None,
+		encrypt: false,
+	};
+	if let Err(e) = esp_now.add_peer(peer) {
+		warn!("Failed to add ESP-NOW peer: {}", defmt::Debug2Format(&e));
+	}
+}
+
+/// Waits for the next frame. The esp-wifi we are on has no receive future, nor a
+/// callback to wake us, so we look again after a short sleep, instead of spinning
+/// and keeping the imu task from running.
+async fn recv_frame(esp_now: &mut EspNow) -> ReceivedData {
+	loop {
+		if let Some(frame) = esp_now.receive() {
+			return frame;
+		}
+		Timer::after(RECEIVE_POLL_INTERVAL).await
+	}
+}
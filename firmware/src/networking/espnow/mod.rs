@@ -0,0 +1,16 @@
+//! ESP-NOW sends frames straight to another ESP32 without an access point, which
+//! takes less time and power than Wi-Fi. The tracker pairs with a receiver, a dongle
+//! with the firmware in `firmware/receiver`, that hands the frames to
+//! `tools/espnow_bridge` over USB serial, and that on to the server.
+//!
+//! Each frame holds exactly one packet, with its sequence number, in the same format
+//! as the UDP datagrams of `net-wifi`. The receiver forwards them to the server as
+//! they are, and sends the server's packets back the same way. Receivers with many
+//! trackers also give each of them a slot to send in, see [`tdma`].
+
+pub mod pairing;
+pub mod tdma;
+
+#[cfg(feature = "net-espnow")]
+#[path = "esp.rs"]
+pub mod ඞ;
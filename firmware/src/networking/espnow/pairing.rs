@@ -0,0 +1,81 @@
+//! Which receiver the tracker sends to.
+//!
+//! Until a tracker is paired, it broadcasts [`REQUEST`] every second. A receiver in
+//! pairing mode answers with [`ACCEPT`], followed by the slot of the tracker if it
+//! schedules them, see [`super::tdma`]. The tracker stores its MAC address and the
+//! slot in flash, so that it sends to it from then on. `UNPAIR` on the serial
+//! console or holding the button for long forgets the receiver again, and reboots to
+//! pair anew.
+
+use defmt::{info, warn};
+
+use crate::storage::{self, Slot};
+
+pub type Mac = [u8; 6];
+
+#[derive(Clone, Copy)]
+pub struct Pairing {
+	pub receiver: Mac,
+	/// Where we send in each cycle, or `None` for receivers that don't schedule.
+	pub slot: Option<u8>,
+}
+
+/// Broadcast by trackers that aren't paired yet.
+pub const REQUEST: &[u8] = b"SLVR PAIR?";
+/// Sent back by a receiver in pairing mode.
+pub const ACCEPT: &[u8] = b"SLVR PAIR!";
+
+/// The receiver that the tracker is paired with, if any.
+pub fn load() -> Option<Pairing> {
+	let mut buffer = [0; 7];
+	match storage::load(Slot::EspNowReceiver, &mut buffer) {
+		// Paired before receivers handed out slots.
+		Ok(6) => Some(Pairing {
+			receiver: buffer[..6].try_into().unwrap(),
+			slot: None,
+		}),
+		Ok(7) => Some(Pairing {
+			receiver: buffer[..6].try_into().unwrap(),
+			slot: Some(buffer[6]),
+		}),
+		Ok(_) => {
+			warn!("The stored ESP-NOW receiver is invalid");
+			None
+		}
+		Err(storage::Error::Empty) => None,
+		Err(e) => {
+			warn!("Failed to load the ESP-NOW receiver: {}", e);
+			None
+		}
+	}
+}
+
+/// Pairs with the receiver. The pairing still holds until the next reboot if storing
+/// it fails.
+pub fn store(pairing: Pairing) {
+	let mut buffer = [0; 7];
+	buffer[..6].copy_from_slice(&pairing.receiver);
+	let len = match pairing.slot {
+		Some(slot) => {
+			buffer[6] = slot;
+			7
+		}
+		None => 6,
+	};
+	match storage::store(Slot::EspNowReceiver, &buffer[..len]) {
+		Ok(()) => info!("Paired with receiver {=[u8]:02x}", pairing.receiver),
+		Err(e) => warn!("Failed to store the ESP-NOW receiver: {}", e),
+	}
+}
+
+/// Forgets the receiver, and reboots to pair with a new one. Only returns if that
+/// failed.
+#[cfg_attr(not(any(console, button)), allow(dead_code))]
+pub fn forget() {
+	if let Err(e) = storage::clear(Slot::EspNowReceiver) {
+		warn!("Failed to forget the ESP-NOW receiver: {}", e);
+		return;
+	}
+	info!("Forgot the ESP-NOW receiver");
+	crate::peripherals::reboot();
+}
@@ -0,0 +1,130 @@
+//! Time division between the trackers that share a receiver. With ten or more
+//! trackers that each send a frame every few milliseconds, frames collide on the
+//! channel, and the retries after each collision eat the airtime of everyone else.
+//!
+//! Instead, the receiver hands out a slot when it accepts a pairing request, and
+//! broadcasts [`BEACON`] at the start of every cycle, followed by how many slots a
+//! cycle has and how long each slot is in microseconds, as a little endian `u16`. A
+//! tracker only sends during its own slot, and holds packets that come up outside of
+//! it until the slot comes around again.
+//!
+//! Our clock runs a little faster or slower than the receiver's, so the cycles
+//! would drift apart between beacons. Each beacon sets the start of the cycle anew,
+//! and the error of where we expected it also corrects how long we think a cycle
+//! takes, so that missing a few beacons doesn't put us into the slot of a neighbor.
+//! Without beacons, like from a receiver that doesn't schedule, we send whenever.
+
+use defmt::{debug, info};
+use embassy_time::{Duration, Instant};
+
+/// Broadcast by the receiver at the start of each cycle.
+pub const BEACON: &[u8] = b"SLVR SYNC";
+/// How long we keep to the schedule after the last beacon.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(1);
+/// Stop sending this much before the end of the slot, so that the last frame is on
+/// the air before the next tracker's slot starts, and beacons that we polled late
+/// don't matter.
+const GUARD_US: f32 = 500.;
+/// How much of the error between two beacons goes into the length of a cycle.
+const DRIFT_GAIN: f32 = 0.25;
+/// The most our clock and the receiver's can disagree, in parts per million. Any
+/// more is a beacon that we polled late, not drift.
+const MAX_DRIFT_PPM: f32 = 200.;
+
+pub struct Schedule {
+	/// Our slot, from when we paired.
+	slot: u8,
+	cycle: Option<Cycle>,
+}
+
+/// What the last beacon said, and our estimate of how it maps to our clock.
+struct Cycle {
+	slots: u8,
+	slot_us: u16,
+	/// When the last beacon arrived.
+	start: Instant,
+	/// How long a cycle takes by our clock, in microseconds.
+	period_us: f32,
+}
+impl Cycle {
+	fn nominal_us(&self) -> f32 {
+		self.slots as f32 * self.slot_us as f32
+	}
+
+	fn scale(&self) -> f32 {
+		self.period_us / self.nominal_us()
+	}
+}
+
+impl Schedule {
+	pub fn new(slot: u8) -> Self {
+		Self { slot, cycle: None }
+	}
+
+	/// Takes `frame` if it is a beacon that arrived `now`, and returns whether it
+	/// was.
+	pub fn beacon(&mut self, frame: &[u8], now: Instant) -> bool {
+		let Some(&[slots, lo, hi]) = frame.strip_prefix(BEACON) else { return false };
+		let slot_us = u16::from_le_bytes([lo, hi]);
+		if slots == 0 || slot_us as f32 <= GUARD_US {
+			return true;
+		}
+		match &mut self.cycle {
+			Some(cycle)
+				if now - cycle.start < SYNC_TIMEOUT
+					&& cycle.slots == slots
+					&& cycle.slot_us == slot_us =>
+			{
+				let elapsed = (now - cycle.start).as_micros() as f32;
+				// Rounded, there is no `f32::round` without std.
+				let cycles = (elapsed / cycle.period_us + 0.5) as u32 as f32;
+				if cycles >= 1. {
+					let error = elapsed - cycles * cycle.period_us;
+					let nominal = cycle.nominal_us();
+					let max = nominal * MAX_DRIFT_PPM / 1e6;
+					cycle.period_us = (cycle.period_us + error / cycles * DRIFT_GAIN)
+						.clamp(nominal - max, nominal + max);
+				}
+				cycle.start = now;
+			}
+			_ => {
+				if self.slot < slots {
+					info!("Sending in slot {} of {}", self.slot, slots);
+				} else {
+					debug!("Slot {} is not in a cycle of {}", self.slot, slots);
+				}
+				self.cycle = Some(Cycle {
+					slots,
+					slot_us,
+					start: now,
+					period_us: slots as f32 * slot_us as f32,
+				});
+			}
+		}
+		true
+	}
+
+	/// When we may send next, which is `now` during our slot, or without a schedule
+	/// to keep to.
+	pub fn send_at(&self, now: Instant) -> Instant {
+		let Some(cycle) = &self.cycle else { return now };
+		// Too long since the last beacon, or the receiver renumbered the slots since
+		// we paired, so that ours isn't one of them.
+		if now - cycle.start >= SYNC_TIMEOUT || self.slot >= cycle.slots {
+			return now;
+		}
+		let elapsed = (now - cycle.start).as_micros() as f32;
+		let cycle_start = (elapsed / cycle.period_us) as u32 as f32 * cycle.period_us;
+		let slot_us = cycle.slot_us as f32 * cycle.scale();
+		let slot_start = cycle_start + self.slot as f32 * slot_us;
+		let slot_end = slot_start + slot_us - GUARD_US;
+		let at = if elapsed < slot_start {
+			slot_start
+		} else if elapsed < slot_end {
+			return now;
+		} else {
+			slot_start + cycle.period_us
+		};
+		cycle.start + Duration::from_micros(at as u64)
+	}
+}
@@ -1,24 +1,66 @@
 pub mod protocol;
+pub mod tx_power;
 #[cfg(feature = "net-wifi")]
 pub mod wifi;
 
 #[cfg(feature = "net-ble")]
 pub mod ble;
 
+#[cfg(feature = "net-espnow")]
+pub mod espnow;
+
+#[cfg(any(feature = "net-espnow", all(feature = "net-ble", mcu_f_nrf52)))]
+pub mod transport;
+
 use defmt::debug;
 use embassy_executor::task;
 
+use crate::events::EventBus;
 use crate::networking::protocol::Packets;
+#[cfg(feature = "net-wifi")]
+use crate::networking::wifi::antenna::{AntennaSwitch, Switch};
+
+/// The parts of the board that only the network task uses.
+#[cfg_attr(not(feature = "net-wifi"), derive(Default))]
+pub struct Radio {
+	#[cfg(feature = "net-wifi")]
+	antenna: AntennaSwitch,
+}
+impl Radio {
+	/// `antenna_switch` is the pin of the RF switch, on boards that have one.
+	#[cfg(feature = "net-wifi")]
+	pub fn new(antenna_switch: Option<Switch>) -> Self {
+		Self {
+			antenna: AntennaSwitch::new(antenna_switch),
+		}
+	}
+
+	#[cfg(not(feature = "net-wifi"))]
+	pub fn new() -> Self {
+		Self {}
+	}
+}
 
 #[task]
-pub async fn network_task(msg_signals: &'static Packets) {
+pub async fn network_task(
+	msg_signals: &'static Packets,
+	radio: Radio,
+	events: &'static EventBus,
+) {
 	debug!("Network task");
 	#[cfg(feature = "net-wifi")]
-	self::wifi::ඞ::network_task(msg_signals).await;
+	self::wifi::ඞ::network_task(msg_signals, radio.antenna, events).await;
+	#[cfg(not(feature = "net-wifi"))]
+	let _ = radio;
 	#[cfg(feature = "net-ble")]
-	self::ble::ඞ::network_task(msg_signals).await;
+	self::ble::ඞ::network_task(msg_signals, events).await;
+	#[cfg(feature = "net-espnow")]
+	self::espnow::ඞ::network_task(msg_signals, events).await;
 	#[cfg(feature = "net-stubbed")]
-	stubbed_network_task(msg_signals).await;
+	{
+		let _ = events;
+		stubbed_network_task(msg_signals).await;
+	}
 }
 
 /// This does nothing, its a "fake" networking task meant to facilitate testing and
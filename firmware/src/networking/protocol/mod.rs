@@ -1,72 +1,219 @@
 //! The protocol implementation to communicate with the SlimeVR Server.
 
+extern crate alloc;
+
 mod packets;
+mod session;
 pub use self::packets::Packets;
 
-use defmt::{debug, trace};
+use defmt::{debug, trace, warn};
 use embassy_executor::task;
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select4, select_array, Either, Either4};
+use embassy_time::{Duration, Instant, Timer};
 
 use firmware_protocol::{
 	BoardType, CbPacket, ImuType, McuType, SbPacket, SensorDataType, SensorStatus,
+	CAPABILITY_CRC32, CONFIG_NOT_STORED, CONFIG_OK, PROTOCOL_BUILD, TAP_DOUBLE,
 };
 
-use crate::imu::Quat;
+use self::session::{Session, Step};
+use crate::config::{Config, Setting};
+use crate::events::{self, BootStage, Event, EventBus};
+use crate::imu::calibration::Routine;
+use crate::imu::reset::ResetKind;
+use crate::imu::{FusedData, SensorEvent, UnfusedData, MAX_IMUS};
+use crate::networking::tx_power::{self, Reason};
+use crate::thermal::ThermalState;
 use crate::utils::{Reliable, Unreliable};
 
 #[allow(dead_code)]
 mod v2;
 
+/// The maximum number of sensors that can be registered with the server.
+const MAX_SENSORS: usize = 2;
+
+/// The optional protocol features we advertise after the handshake. Checksums are
+/// added by the Wi-Fi network task, BLE has its own.
+const CAPABILITIES: u32 = if cfg!(feature = "net-wifi") {
+	CAPABILITY_CRC32
+} else {
+	0
+};
+
+/// Sent in `SbPacket::Diagnostic` when a sensor's rate is scaled. Chosen to not
+/// collide with the `SensorError` codes.
+const CODE_RATE_SCALED: u8 = 0x81;
+/// Sent in `SbPacket::Diagnostic` when the tracker backs off because the battery
+/// sags, or stops again.
+const CODE_TX_BACKOFF: u8 = 0x82;
+/// Sent in `sensor_id`, since backing off isn't about a particular sensor.
+const NO_SENSOR: u8 = 0xFF;
+/// The fastest that rotations are sent while backing off, since each packet is a
+/// burst of current for the radio.
+const BACKOFF_ROTATION_INTERVAL: Duration = Duration::from_millis(20);
+/// The most TX power while backing off, in quarter dBm. 15 dBm, which draws a good
+/// deal less current than the most, and still reaches across a room.
+const BACKOFF_TX_POWER: i8 = 60;
+
+/// What we tell the server that we are, and take firmware offers for. None of the
+/// official board types match ours.
+pub const BOARD: BoardType = BoardType::Custom;
+pub const MCU: McuType = if cfg!(mcu_f_esp32) {
+	McuType::Esp32
+} else {
+	McuType::Unknown(0)
+};
+
+/// How often the angular velocity of each sensor is sent at most. The receiver keeps
+/// extrapolating with the last one, so that it takes a fraction of the packets of
+/// the rotations, instead of as many again.
+const ANGULAR_VELOCITY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The sensors we know about, indexed by sensor id.
+type Sensors = [Option<(SensorStatus, ImuType)>; MAX_SENSORS];
+
 #[task]
 pub async fn control_task(
 	packets: &'static Packets,
-	quat: &'static Unreliable<Quat>,
+	fused: &'static [Unreliable<FusedData>; MAX_IMUS],
+	sensor_events: &'static Reliable<SensorEvent>,
+	sensor_enabled: &'static [Unreliable<bool>; MAX_IMUS],
+	events: &'static EventBus,
 ) -> ! {
 	debug!("Control task!");
+	let mut sensors: Sensors = [None; MAX_SENSORS];
+	let mut session = Session::new(Instant::now());
+	let mut config = Config::load();
+	tx_power::limit(Reason::Config, Some(config.tx_power));
+	// When the last rotation of each sensor was sent, to keep to the configured rate.
+	let mut last_rotation = [Instant::from_ticks(0); MAX_IMUS];
+	// And of the angular velocity, see `ANGULAR_VELOCITY_INTERVAL`.
+	let mut last_velocity = [Instant::from_ticks(0); MAX_IMUS];
+	let mut backoff = false;
+	let events_out = events;
+	let mut events = events.subscriber().unwrap();
 	async {
 		loop {
-			match select(packets.clientbound.recv(), quat.wait()).await {
-				Either::First(cb_msg) => {
-					handle_cb_msg(cb_msg, &packets.serverbound).await
+			let fused_msg: [_; MAX_IMUS] =
+				core::array::from_fn(|sensor_id| fused[sensor_id].wait());
+			match select4(
+				select(packets.clientbound.recv(), Timer::at(session.deadline())),
+				select_array(fused_msg),
+				sensor_events.recv(),
+				events.next_message_pure(),
+			)
+			.await
+			{
+				Either4::First(Either::First(cb_msg)) => {
+					let step = session.on_packet(&cb_msg, Instant::now());
+					handle_step(step, packets, &sensors, events_out).await;
+					handle_cb_msg(
+						cb_msg,
+						&packets.serverbound,
+						&sensors,
+						sensor_enabled,
+						&mut config,
+						events_out,
+					)
+					.await
+				}
+				Either4::First(Either::Second(())) => {
+					let step = session.tick(Instant::now());
+					handle_step(step, packets, &sensors, events_out).await
+				}
+				// The server has no use for rotations before the handshake. A raw
+				// stream wants every sample, whatever the rate.
+				Either4::Second((mut fused_msg, sensor_id))
+					if session.is_streaming() =>
+				{
+					let now = Instant::now();
+					let due = rotation_interval(&config, backoff)
+						.map_or(true, |i| now - last_rotation[sensor_id] >= i);
+					if due || fused_msg.raw.is_some() {
+						last_rotation[sensor_id] = now;
+						if now - last_velocity[sensor_id] < ANGULAR_VELOCITY_INTERVAL {
+							fused_msg.angular_velocity = None;
+						} else if fused_msg.angular_velocity.is_some() {
+							last_velocity[sensor_id] = now;
+						}
+						handle_fused(sensor_id as u8, fused_msg, &packets.serverbound)
+							.await
+					}
+				}
+				Either4::Second(_) => (),
+				Either4::Third(event) => {
+					handle_sensor_event(event, &packets.serverbound, &mut sensors).await
+				}
+				Either4::Fourth(Event::LinkChanged { up: true }) => {
+					session.on_link_up(Instant::now())
+				}
+				Either4::Fourth(Event::Thermal(state)) => {
+					handle_thermal(state, &packets.serverbound).await
 				}
-				Either::Second(quat_msg) => {
-					handle_quat(quat_msg, &packets.serverbound).await
+				// Reaching the server means that an update worked.
+				#[cfg(feature = "ota")]
+				Either4::Fourth(Event::ConnectionChanged { connected: true }) => {
+					crate::ota::confirm()
 				}
+				Either4::Fourth(Event::Battery {
+					millivolts,
+					percent,
+					..
+				}) => handle_battery(millivolts, percent, &packets.serverbound).await,
+				Either4::Fourth(Event::TxBackoff { active, millivolts }) => {
+					backoff = active;
+					handle_tx_backoff(active, millivolts, &packets.serverbound).await
+				}
+				#[cfg(deep_sleep)]
+				Either4::Fourth(Event::Sleeping) => {
+					packets.serverbound.send(SbPacket::Sleeping).await
+				}
+				Either4::Fourth(Event::Temperature { sensor_id, celsius }) => {
+					handle_temperature(sensor_id, celsius, &packets.serverbound).await
+				}
+				Either4::Fourth(Event::Tap { sensor_id, double }) => {
+					handle_tap(sensor_id, double, &packets.serverbound).await
+				}
+				Either4::Fourth(Event::RateScaled {
+					sensor_id,
+					rate_hz,
+					lowered,
+				}) => {
+					handle_rate_scaled(
+						sensor_id,
+						rate_hz,
+						lowered,
+						&packets.serverbound,
+					)
+					.await
+				}
+				// Sensor errors are already reported by `handle_sensor_event`.
+				Either4::Fourth(_) => (),
 			}
 		}
 	}
 	.await
 }
 
-async fn handle_cb_msg(cb_msg: CbPacket, sb_chan: &Reliable<SbPacket>) {
+async fn handle_cb_msg(
+	cb_msg: CbPacket,
+	sb_chan: &Reliable<SbPacket>,
+	sensors: &Sensors,
+	sensor_enabled: &[Unreliable<bool>; MAX_IMUS],
+	config: &mut Config,
+	events: &EventBus,
+) {
+	// Only sensors that the imu task found are registered.
+	let exists = |sensor_id: u8| {
+		sensors
+			.get(usize::from(sensor_id))
+			.map_or(false, Option::is_some)
+	};
 	match cb_msg {
-		// Identify ourself when discovery packet is received
-		CbPacket::Discovery => {
-			trace!("protocol: received Discovery");
-			sb_chan
-				.send(SbPacket::Handshake {
-					// TODO: Compile time constants for board and MCU
-					board: BoardType::Custom,
-					// Should this IMU type be whatever the first IMU of the system is?
-					imu: ImuType::Unknown(0xFF),
-					mcu: McuType::Esp32,
-					imu_info: (0, 0, 0), // These appear to be inert
-					// Needs to be >=9 to use newer protocol, this is hard-coded in
-					// the java server :(
-					build: 10,
-					firmware: "SlimeVR-Rust".into(),
-					mac_address: [0; 6],
-				})
-				.await;
-
-			// After handshake, we are supposed to send `SensorInfo` only once.
-			sb_chan
-				.send(SbPacket::SensorInfo {
-					sensor_id: 0, // First sensor (of two)
-					sensor_status: SensorStatus::Ok,
-					sensor_type: ImuType::Unknown(0xFF),
-				})
-				.await;
+		// The session answers these.
+		CbPacket::Discovery => trace!("protocol: received Discovery"),
+		CbPacket::HandshakeResponse { .. } => {
+			trace!("protocol: received HandshakeResponse")
 		}
 		// When heartbeat is received, we should reply with heartbeat 0 aka Discovery
 		// The protocol is asymmetric so its a bit unintuitive.
@@ -79,17 +226,400 @@ async fn handle_cb_msg(cb_msg: CbPacket, sb_chan: &Reliable<SbPacket>) {
 			trace!("protocol: received Ping");
 			sb_chan.send(SbPacket::Ping { challenge }).await;
 		}
+		// The imu task reports the new status, once it has actually paused or
+		// resumed.
+		CbPacket::SetSensorEnabled { sensor_id, enabled } => {
+			debug!(
+				"protocol: setting sensor {} enabled to {}",
+				sensor_id, enabled
+			);
+			let signal = sensor_enabled.get(usize::from(sensor_id));
+			if let Some(signal) = signal.filter(|_| exists(sensor_id)) {
+				signal.signal(enabled);
+			} else {
+				warn!("Can't toggle sensor {}, it doesn't exist", sensor_id);
+			}
+		}
+		// The imu task stops the stream on its own once the duration is up.
+		CbPacket::StreamRawData {
+			sensor_id,
+			duration_ms,
+		} => {
+			debug!(
+				"protocol: streaming raw data of sensor {} for {} ms",
+				sensor_id, duration_ms
+			);
+			if exists(sensor_id) {
+				events::publish(
+					events,
+					Event::RawStreamRequested {
+						sensor_id,
+						duration_ms,
+					},
+				);
+			} else {
+				warn!("Can't stream sensor {}, it doesn't exist", sensor_id);
+			}
+		}
+		CbPacket::Calibrate { sensor_id, routine } => {
+			debug!(
+				"protocol: calibrating sensor {} with routine {}",
+				sensor_id, routine
+			);
+			match Routine::from_protocol(routine) {
+				Some(routine) if exists(sensor_id) => events::publish(
+					events,
+					Event::CalibrationRequested { sensor_id, routine },
+				),
+				Some(_) => {
+					warn!("Can't calibrate sensor {}, it doesn't exist", sensor_id)
+				}
+				None => warn!("Unknown calibration routine {}", routine),
+			}
+		}
+		// The imu task reports the sensor as offline if it doesn't come back.
+		CbPacket::RestartSensor { sensor_id } => {
+			debug!("protocol: restarting sensor {}", sensor_id);
+			if exists(sensor_id) {
+				events::publish(events, Event::RestartRequested { sensor_id });
+			} else {
+				warn!("Can't restart sensor {}, it doesn't exist", sensor_id);
+			}
+		}
+		CbPacket::ResetOrientation { sensor_id, kind } => {
+			debug!(
+				"protocol: resetting sensor {} with kind {}",
+				sensor_id, kind
+			);
+			match ResetKind::from_protocol(kind) {
+				Some(kind) if exists(sensor_id) => {
+					events::publish(events, Event::ResetRequested { sensor_id, kind })
+				}
+				Some(_) => warn!("Can't reset sensor {}, it doesn't exist", sensor_id),
+				None => warn!("Unknown reset kind {}", kind),
+			}
+		}
+		CbPacket::OtaBegin { size, crc32 } => {
+			debug!("protocol: starting an OTA update of {} bytes", size);
+			#[cfg(feature = "ota")]
+			let status = crate::ota::begin(size, crc32);
+			#[cfg(not(feature = "ota"))]
+			let status = {
+				let _ = crc32;
+				warn!("Built without the `ota` feature, can't update");
+				SbPacket::OtaStatus {
+					offset: 0,
+					status: firmware_protocol::OTA_UNSUPPORTED,
+				}
+			};
+			sb_chan.send(status).await;
+		}
+		#[cfg(feature = "ota")]
+		CbPacket::OtaData { offset, data, .. } => {
+			trace!("protocol: received {} bytes of OTA data", data.len());
+			sb_chan.send(crate::ota::write(offset, &data)).await;
+		}
+		#[cfg(feature = "ota")]
+		CbPacket::OtaEnd => {
+			debug!("protocol: finishing the OTA update");
+			let (status, reboot) = crate::ota::finish();
+			sb_chan.send(status).await;
+			if reboot {
+				// Gives the network task time to send the status.
+				embassy_time::Timer::after(crate::ota::REBOOT_DELAY).await;
+				crate::ota::reboot();
+			}
+		}
+		CbPacket::SetConfig { key, value } => {
+			debug!("protocol: setting config {} to {}", key, value);
+			let status = match config.set(key, value) {
+				Ok(setting) => {
+					if let Setting::TxPower { quarter_dbm } = setting {
+						tx_power::limit(Reason::Config, Some(quarter_dbm));
+					}
+					events::publish(events, Event::ConfigChanged(setting));
+					match config.store() {
+						Ok(()) => CONFIG_OK,
+						Err(e) => {
+							warn!("Failed to store the config: {}", e);
+							CONFIG_NOT_STORED
+						}
+					}
+				}
+				Err(status) => {
+					warn!("Rejected config {} = {}: {}", key, value, status);
+					status
+				}
+			};
+			sb_chan
+				.send(SbPacket::ConfigStatus {
+					key,
+					value: config.get(key),
+					status,
+				})
+				.await;
+		}
+		// With the feature, the network task downloads the firmware itself.
+		#[cfg(not(feature = "ota"))]
+		CbPacket::FirmwareOffer { .. } => {
+			warn!("Built without the `ota` feature, can't update");
+			sb_chan
+				.send(SbPacket::OtaStatus {
+					offset: 0,
+					status: firmware_protocol::OTA_UNSUPPORTED,
+				})
+				.await;
+		}
 		_ => (),
 	}
 }
 
-async fn handle_quat(quat: Quat, sb_chan: &Reliable<SbPacket>) {
+/// Carries out what the session asks for after a transition.
+async fn handle_step(
+	step: Step,
+	packets: &Packets,
+	sensors: &Sensors,
+	events: &EventBus,
+) {
+	let sb_chan = &packets.serverbound;
+	match step {
+		Step::Nothing => (),
+		Step::Handshake => {
+			trace!("protocol: sending Handshake");
+			sb_chan.send(handshake(sensors)).await;
+		}
+		Step::Register => register(sb_chan, sensors).await,
+		Step::Connected => {
+			packets.set_extensions(true);
+			register(sb_chan, sensors).await;
+			events::publish(events, Event::ConnectionChanged { connected: true });
+		}
+		Step::Lost => {
+			events::publish(events, Event::ConnectionChanged { connected: false })
+		}
+		Step::NotFound => {
+			sb_chan.send(handshake(sensors)).await;
+			events::publish(events, Event::BootFailed(BootStage::Server));
+		}
+		Step::Incompatible => {
+			events::publish(events, Event::BootFailed(BootStage::Incompatible))
+		}
+		Step::Extensions(enabled) => packets.set_extensions(enabled),
+	}
+}
+
+/// Identifies us to the server. A handshake only has room for one imu type, so it
+/// is the one of the first sensor, the server learns about the others from their
+/// `SensorInfo`.
+fn handshake(sensors: &Sensors) -> SbPacket {
+	let imu = sensors
+		.iter()
+		.flatten()
+		.next()
+		.map_or(ImuType::Unknown(0xFF), |&(_, imu_type)| imu_type);
+	SbPacket::Handshake {
+		board: BOARD,
+		imu,
+		mcu: MCU,
+		imu_info: (0, 0, 0), // These appear to be inert
+		build: PROTOCOL_BUILD,
+		firmware: concat!("SlimeVR-Rust ", env!("CARGO_PKG_VERSION")).into(),
+		mac_address: crate::peripherals::chip_id(),
+	}
+}
+
+/// Tells the server about our capabilities and sensors. It expects this once after
+/// every handshake. The capabilities also go out without any, since the answer
+/// tells whether the server takes our extensions.
+async fn register(sb_chan: &Reliable<SbPacket>, sensors: &Sensors) {
+	sb_chan
+		.send(SbPacket::Capabilities {
+			flags: CAPABILITIES,
+		})
+		.await;
+	for (sensor_id, sensor) in sensors.iter().enumerate() {
+		let Some((sensor_status, sensor_type)) = *sensor else {
+			continue;
+		};
+		send_sensor_info(sb_chan, sensor_id as u8, sensor_status, sensor_type).await;
+	}
+}
+
+/// Sends the `SensorInfo` of a sensor, and the id that the server can remember it
+/// by across reflashes.
+async fn send_sensor_info(
+	sb_chan: &Reliable<SbPacket>,
+	sensor_id: u8,
+	sensor_status: SensorStatus,
+	sensor_type: ImuType,
+) {
+	sb_chan
+		.send(SbPacket::SensorInfo {
+			sensor_id,
+			sensor_status,
+			sensor_type,
+		})
+		.await;
+	let [a, b, c, d, e, f] = crate::peripherals::chip_id();
+	let unique_id = u64::from_be_bytes([a, b, c, d, e, f, 0, sensor_id]);
+	sb_chan
+		.send(SbPacket::SensorUniqueId {
+			sensor_id,
+			unique_id,
+		})
+		.await;
+}
+
+/// Registers or unregisters a sensor with the server when it gets plugged in or
+/// unplugged.
+async fn handle_sensor_event(
+	event: SensorEvent,
+	sb_chan: &Reliable<SbPacket>,
+	sensors: &mut Sensors,
+) {
+	let Some(sensor) = sensors.get_mut(usize::from(event.sensor_id)) else {
+		warn!("Ignoring event for sensor {}, too many sensors", event.sensor_id);
+		return;
+	};
+	*sensor = Some((event.status, event.imu_type));
+	send_sensor_info(sb_chan, event.sensor_id, event.status, event.imu_type).await;
+	if let Some(error) = event.error {
+		sb_chan
+			.send(SbPacket::SensorError {
+				sensor_id: event.sensor_id,
+				error: error as u8,
+			})
+			.await;
+		sb_chan
+			.send(SbPacket::Diagnostic {
+				sensor_id: event.sensor_id,
+				code: error as u8,
+				message: error.message().into(),
+			})
+			.await;
+	}
+}
+
+/// Lets the server know that we are throttling, so it can warn the user.
+async fn handle_thermal(state: ThermalState, sb_chan: &Reliable<SbPacket>) {
+	sb_chan
+		.send(SbPacket::ThermalState { state: state as u8 })
+		.await;
+}
+
+async fn handle_battery(millivolts: u16, percent: u8, sb_chan: &Reliable<SbPacket>) {
+	sb_chan
+		.send(SbPacket::BatteryLevel {
+			voltage: f32::from(millivolts) / 1000.,
+			level: f32::from(percent) / 100.,
+		})
+		.await;
+}
+
+/// How long to wait between rotations of a sensor, if at all.
+fn rotation_interval(config: &Config, backoff: bool) -> Option<Duration> {
+	let interval = config.rotation_interval();
+	if !backoff {
+		return interval;
+	}
+	Some(interval.map_or(BACKOFF_ROTATION_INTERVAL, |i| {
+		i.max(BACKOFF_ROTATION_INTERVAL)
+	}))
+}
+
+/// Lowers the TX power while backing off, and lets the server know why the rate
+/// dropped, like [`handle_rate_scaled`].
+async fn handle_tx_backoff(
+	active: bool,
+	millivolts: u16,
+	sb_chan: &Reliable<SbPacket>,
+) {
+	tx_power::limit(Reason::Battery, active.then_some(BACKOFF_TX_POWER));
+	let message = if active {
+		alloc::format!("Battery sagged to {millivolts} mV, sending less")
+	} else {
+		alloc::format!("Battery recovered to {millivolts} mV")
+	};
+	sb_chan
+		.send(SbPacket::Diagnostic {
+			sensor_id: NO_SENSOR,
+			code: CODE_TX_BACKOFF,
+			message: message.into(),
+		})
+		.await;
+}
+
+async fn handle_temperature(sensor_id: u8, celsius: f32, sb_chan: &Reliable<SbPacket>) {
+	sb_chan
+		.send(SbPacket::Temperature {
+			sensor_id,
+			temperature: celsius,
+		})
+		.await;
+}
+
+async fn handle_tap(sensor_id: u8, double: bool, sb_chan: &Reliable<SbPacket>) {
+	let tap = if double { TAP_DOUBLE } else { 0 };
+	sb_chan.send(SbPacket::Tap { sensor_id, tap }).await;
+}
+
+/// Lets the server know that a sensor's rate changed on its own, so a drop in the
+/// rate doesn't look like a connection problem.
+async fn handle_rate_scaled(
+	sensor_id: u8,
+	rate_hz: u32,
+	lowered: bool,
+	sb_chan: &Reliable<SbPacket>,
+) {
+	let message = if lowered {
+		alloc::format!("CPU can't keep up, lowered the rate to {rate_hz} Hz")
+	} else {
+		alloc::format!("CPU caught up, raised the rate to {rate_hz} Hz")
+	};
+	sb_chan
+		.send(SbPacket::Diagnostic {
+			sensor_id,
+			code: CODE_RATE_SCALED,
+			message: message.into(),
+		})
+		.await;
+}
+
+async fn handle_fused(sensor_id: u8, fused: FusedData, sb_chan: &Reliable<SbPacket>) {
 	sb_chan
 		.send(SbPacket::RotationData {
-			sensor_id: 0,                      // First sensor
+			sensor_id,
 			data_type: SensorDataType::Normal, // Rotation data without magnetometer correction.
-			quat: quat.into_inner().into(),
-			calibration_info: 0,
+			quat: fused.q.into_inner().into(),
+			calibration_info: fused.accuracy.unwrap_or(0),
 		})
-		.await
+		.await;
+	if let Some(w) = fused.angular_velocity {
+		sb_chan
+			.send(SbPacket::AngularVelocity {
+				sensor_id,
+				vector: (w.x, w.y, w.z),
+			})
+			.await;
+	}
+	if let Some(a) = fused.linear_accel {
+		sb_chan
+			.send(SbPacket::Acceleration {
+				vector: (a.x, a.y, a.z),
+				sensor_id,
+			})
+			.await;
+	}
+	if let Some((at, raw)) = fused.raw {
+		let UnfusedData { accel, gyro, .. } = raw;
+		sb_chan
+			.send(SbPacket::RawImuData {
+				sensor_id,
+				// Wrapping around is fine, tools only care about the differences.
+				timestamp_us: at.as_micros() as u32,
+				accel: (accel.x, accel.y, accel.z),
+				gyro: (gyro.x, gyro.y, gyro.z),
+			})
+			.await;
+	}
 }
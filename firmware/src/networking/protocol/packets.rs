@@ -1,4 +1,5 @@
 use crate::utils::Reliable;
+use core::cell::Cell;
 use firmware_protocol::{CbPacket, SbPacket};
 
 /// Packets is an accessor to internal logic <-> network messaging system
@@ -7,6 +8,9 @@ pub struct Packets {
 	pub serverbound: Reliable<SbPacket>,
 	/// The latest `Message` that could be received
 	pub clientbound: Reliable<CbPacket>,
+	/// Whether the server takes the extensions of SlimeVR-Rust, which the session
+	/// finds out after each handshake. Until then, it is assumed to.
+	extensions: Cell<bool>,
 }
 
 impl Packets {
@@ -14,6 +18,20 @@ impl Packets {
 		Packets {
 			serverbound: Reliable::new(),
 			clientbound: Reliable::new(),
+			extensions: Cell::new(true),
 		}
 	}
+
+	pub fn set_extensions(&self, enabled: bool) {
+		self.extensions.set(enabled);
+	}
+
+	/// Whether the network task should send `msg`, instead of dropping it because
+	/// the server wouldn't know what to do with it. Raw samples only go out when
+	/// something asked for them, which might not be the server.
+	pub fn allows(&self, msg: &SbPacket) -> bool {
+		self.extensions.get()
+			|| !msg.is_extension()
+			|| matches!(msg, SbPacket::RawImuData { .. })
+	}
 }
@@ -0,0 +1,215 @@
+//! Where we stand with the server. Until a server answers our handshake, we keep
+//! broadcasting it, and only once one did do we stream to it. A server that goes
+//! quiet, like one that restarted and forgot about us, sends us back to searching.
+//!
+//! Servers older than [`SERVER_VERSION`] can't parse our packets, so we keep
+//! searching past them, and report them once. Newer ones are talked to like that
+//! version. Our extensions, the packets with ids from 1000, only go to a server that
+//! answers `SbPacket::Capabilities`, or sends extensions of its own, since the
+//! official server ignores them.
+
+use defmt::{error, info, warn, Format};
+use embassy_time::{Duration, Instant};
+use firmware_protocol::{CbPacket, SERVER_VERSION};
+
+/// How often to broadcast the handshake while there is no server.
+const HANDSHAKE_INTERVAL: Duration = Duration::from_secs(1);
+/// The server sends a heartbeat every second, so hearing nothing for this long
+/// means that it is gone.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long to search before failing the server stage of booting. The search goes
+/// on, the server might only be starting up.
+const FIND_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long the server has to answer `SbPacket::Capabilities` with
+/// `CbPacket::EnableCapabilities`, before it only gets the packets of the official
+/// firmware.
+const EXTENSIONS_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum State {
+	/// Broadcasting the handshake, until a server answers it.
+	Searching,
+	/// A server answered the handshake, and gets our data.
+	Streaming,
+}
+
+/// What the control task has to do after a transition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum Step {
+	Nothing,
+	/// Send the handshake, which the network task broadcasts.
+	Handshake,
+	/// A server that we already stream to answered another handshake, so it
+	/// restarted in the meantime. Tell it about the sensors again.
+	Register,
+	/// Found a server. Tell it about the sensors, and everyone else that we are
+	/// connected.
+	Connected,
+	/// The server went quiet.
+	Lost,
+	/// Searched for [`FIND_TIMEOUT`] without finding a server. Send the handshake
+	/// again, and give up on the server stage of booting.
+	NotFound,
+	/// A server that we can't talk to answered the handshake.
+	Incompatible,
+	/// Whether to send the server our extensions, now that we know.
+	Extensions(bool),
+}
+
+pub struct Session {
+	state: State,
+	/// When the last packet arrived.
+	last_rx: Instant,
+	/// When to broadcast the handshake again, while searching.
+	next_handshake: Instant,
+	/// When the search started, until it hit [`FIND_TIMEOUT`].
+	searching_since: Option<Instant>,
+	/// The version of the last server that we couldn't talk to, which was already
+	/// reported.
+	rejected: Option<u8>,
+	/// Whether the server takes our extensions.
+	extensions: bool,
+	/// Until when the server can still answer our capabilities, after connecting.
+	extensions_by: Option<Instant>,
+}
+impl Session {
+	/// Starts out searching, with a handshake right away.
+	pub fn new(now: Instant) -> Self {
+		Self {
+			state: State::Searching,
+			last_rx: now,
+			next_handshake: now,
+			searching_since: Some(now),
+			rejected: None,
+			extensions: true,
+			extensions_by: None,
+		}
+	}
+
+	/// Gives the search the whole [`FIND_TIMEOUT`] again, since there was no way to
+	/// reach a server while the link was down.
+	pub fn on_link_up(&mut self, now: Instant) {
+		if self.state == State::Searching {
+			self.searching_since = Some(now);
+		}
+	}
+
+	pub fn is_streaming(&self) -> bool {
+		self.state == State::Streaming
+	}
+
+	/// When [`Self::tick`] has to be called next.
+	pub fn deadline(&self) -> Instant {
+		match self.state {
+			State::Searching => self.next_handshake,
+			State::Streaming => {
+				let timeout = self.last_rx + SERVER_TIMEOUT;
+				self.extensions_by.map_or(timeout, |by| by.min(timeout))
+			}
+		}
+	}
+
+	pub fn on_packet(&mut self, packet: &CbPacket, now: Instant) -> Step {
+		self.last_rx = now;
+		match (self.state, packet) {
+			// A server that just started looks for trackers, and doesn't know about
+			// us even if we were streaming to it before.
+			(_, CbPacket::Discovery) => {
+				self.next_handshake = now + HANDSHAKE_INTERVAL;
+				Step::Handshake
+			}
+			(State::Searching, CbPacket::HandshakeResponse { version }) => {
+				self.on_handshake(*version, now)
+			}
+			// It might have been updated in the meantime, so ask it again.
+			(State::Streaming, CbPacket::HandshakeResponse { .. }) => {
+				self.extensions_by = Some(now + EXTENSIONS_TIMEOUT);
+				Step::Register
+			}
+			(State::Streaming, packet) if packet.is_extension() => {
+				self.extensions_by = None;
+				if self.extensions {
+					return Step::Nothing;
+				}
+				info!("The server takes our extensions after all");
+				self.extensions = true;
+				Step::Extensions(true)
+			}
+			_ => Step::Nothing,
+		}
+	}
+
+	fn on_handshake(&mut self, version: u8, now: Instant) -> Step {
+		if !version.is_ascii_digit() || version < SERVER_VERSION {
+			// The search goes on, in case there is another server, but it did find
+			// one.
+			self.searching_since = None;
+			if self.rejected == Some(version) {
+				return Step::Nothing;
+			}
+			self.rejected = Some(version);
+			error!(
+				"Server version {} is too old, update it to version {} or newer",
+				version as char, SERVER_VERSION as char
+			);
+			return Step::Incompatible;
+		}
+		if version > SERVER_VERSION {
+			info!(
+				"Server version {} is newer than ours, talking to it like version {}",
+				version as char, SERVER_VERSION as char
+			);
+		}
+		info!("Handshake accepted by server version {}", version as char);
+		self.state = State::Streaming;
+		self.rejected = None;
+		self.extensions = true;
+		self.extensions_by = Some(now + EXTENSIONS_TIMEOUT);
+		Step::Connected
+	}
+
+	/// Broadcasts the handshake while searching, and notices when the server goes
+	/// quiet.
+	pub fn tick(&mut self, now: Instant) -> Step {
+		if now < self.deadline() {
+			return Step::Nothing;
+		}
+		match self.state {
+			State::Searching => {
+				self.next_handshake = now + HANDSHAKE_INTERVAL;
+				match self.searching_since {
+					Some(since) if now - since >= FIND_TIMEOUT => {
+						warn!(
+							"No server for {} s, still looking",
+							FIND_TIMEOUT.as_secs()
+						);
+						self.searching_since = None;
+						Step::NotFound
+					}
+					_ => Step::Handshake,
+				}
+			}
+			// Hasn't timed out yet, so it was the capabilities that went unanswered.
+			State::Streaming if now < self.last_rx + SERVER_TIMEOUT => {
+				self.extensions_by = None;
+				if !self.extensions {
+					return Step::Nothing;
+				}
+				warn!("The server doesn't take our extensions, leaving them out");
+				self.extensions = false;
+				Step::Extensions(false)
+			}
+			State::Streaming => {
+				warn!(
+					"Heard nothing from the server for {} s",
+					SERVER_TIMEOUT.as_secs()
+				);
+				self.state = State::Searching;
+				self.extensions_by = None;
+				self.next_handshake = now;
+				self.searching_since = Some(now);
+				Step::Lost
+			}
+		}
+	}
+}
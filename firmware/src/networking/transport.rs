@@ -0,0 +1,85 @@
+//! What the links to the server have in common, so that the packets are framed the
+//! same way no matter how they travel. A [`Transport`] only moves packets that are
+//! already serialized, one per frame, and [`exchange`] connects it to the control
+//! task: it numbers and serializes the serverbound packets, and deserializes the
+//! clientbound ones.
+//!
+//! ESP-NOW and BLE on the nrf52 are transports. Bringing a link up is still up to
+//! each of them, since a BLE central connects to us while ESP-NOW has to pair first,
+//! and they publish `Event::LinkChanged` when it comes and goes. Wi-Fi keeps its own
+//! loop for now, because UDP also has to find the server, checksum the datagrams,
+//! and route raw samples to other tools than the server. A USB serial link to a
+//! receiver would be another transport, once there is one.
+
+use defmt::{trace, warn, Format};
+use embassy_futures::select::{select, Either};
+use firmware_protocol::{CbPacket, Packet, SbPacket};
+
+use crate::networking::protocol::Packets;
+
+/// Enough for the frames of every transport, see [`Transport::MAX_PACKET_LEN`].
+const BUFFER_LEN: usize = 256;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum LinkError {
+	/// The packet didn't go out, but the link is still up.
+	Send,
+	/// The other side went away.
+	Disconnected,
+}
+
+/// A link that carries one serialized packet per frame.
+pub trait Transport {
+	/// The largest packet that fits into one frame, with its sequence number.
+	const MAX_PACKET_LEN: usize;
+
+	/// Sends `packet`, which is `msg` serialized, for links that also look at what
+	/// they send.
+	async fn send_packet(
+		&mut self,
+		msg: &SbPacket,
+		packet: &[u8],
+	) -> Result<(), LinkError>;
+
+	/// Waits for the next packet from the server, writes it to the start of `buffer`
+	/// and returns its length. Only fails with [`LinkError::Disconnected`]. Has to be
+	/// cancel safe, it is raced against the serverbound packets.
+	async fn recv_packet(&mut self, buffer: &mut [u8]) -> Result<usize, LinkError>;
+}
+
+/// Exchanges packets between the control task and `link`, until the link goes down.
+/// `tx_seq` is kept across links, so that the server doesn't drop our packets as
+/// out of order when we reconnect.
+///
+/// Frames between two peers can't overtake each other, so unlike over UDP there is
+/// no need to check the sequence numbers of the server. Ours still have to count up,
+/// the server drops packets that don't.
+pub async fn exchange<T: Transport>(link: &mut T, packets: &Packets, tx_seq: &mut u64) {
+	let mut buffer = [0; BUFFER_LEN];
+	loop {
+		match select(link.recv_packet(&mut buffer), packets.serverbound.recv()).await {
+			Either::First(Ok(len)) => {
+				let data = &buffer[..len];
+				let Ok(packet) = Packet::<CbPacket>::deserialize_from(data) else { trace!("Discarding {}", data); continue };
+				let (_, msg) = packet.split();
+				packets.clientbound.send(msg).await;
+			}
+			// Receiving only fails once the link is down.
+			Either::First(Err(_)) => return,
+			// A server without our extensions ignores them anyway.
+			Either::Second(msg) if !packets.allows(&msg) => continue,
+			Either::Second(msg) => {
+				let packet = Packet::new(*tx_seq, msg);
+				let out = &mut buffer[..T::MAX_PACKET_LEN.min(BUFFER_LEN)];
+				let Ok(len) = packet.serialize_into(out) else { warn!("Failed to serialize outgoing packet"); continue };
+				*tx_seq += 1;
+				let (_, msg) = packet.split();
+				match link.send_packet(&msg, &buffer[..len]).await {
+					Ok(()) => (),
+					Err(LinkError::Send) => warn!("Failed to send #{}", *tx_seq),
+					Err(LinkError::Disconnected) => return,
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,69 @@
+//! How strongly the radio transmits. Several parts of the firmware ask for less than
+//! the most, each for its own [`Reason`], and the lowest of them wins:
+//! - The server, with `CONFIG_TX_POWER`, see [`crate::config`].
+//! - [`crate::thermal`], while the MCU is hot.
+//! - The battery, while it sags under load, see [`crate::networking::protocol`].
+//!
+//! Over Wi-Fi, and ESP-NOW which shares its radio, that goes to
+//! `esp_wifi_set_max_tx_power`, as soon as Wi-Fi is started and whenever it changes.
+//! BLE keeps the default power of the softdevice so far.
+
+use core::cell::Cell;
+
+use defmt::{debug, Format};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+
+/// In quarter dBm, like esp-wifi takes it. 21 dBm, the most that esp-wifi takes.
+pub const MAX: i8 = 84;
+/// 2 dBm, the least that esp-wifi takes.
+pub const MIN: i8 = 8;
+
+/// Why the power is limited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum Reason {
+	Config = 0,
+	Thermal = 1,
+	Battery = 2,
+}
+
+static LIMITS: Mutex<CriticalSectionRawMutex, Cell<[i8; 3]>> =
+	Mutex::new(Cell::new([MAX; 3]));
+
+/// Limits the power to `quarter_dbm` for `reason`, or lifts its limit with `None`.
+pub fn limit(reason: Reason, quarter_dbm: Option<i8>) {
+	let quarter_dbm = quarter_dbm.unwrap_or(MAX).clamp(MIN, MAX);
+	let before = current();
+	LIMITS.lock(|limits| {
+		let mut l = limits.get();
+		l[reason as usize] = quarter_dbm;
+		limits.set(l);
+	});
+	if current() != before {
+		debug!(
+			"TX power limited to {} dBm for {}",
+			f32::from(current()) / 4.,
+			reason
+		);
+		apply();
+	}
+}
+
+/// The power that the radio should transmit with, in quarter dBm.
+pub fn current() -> i8 {
+	LIMITS.lock(|limits| limits.get().into_iter().min().unwrap_or(MAX))
+}
+
+/// Hands [`current`] to the radio. Call again once it has started, it forgets it
+/// while it is stopped.
+pub fn apply() {
+	#[cfg(any(feature = "net-wifi", feature = "net-espnow"))]
+	{
+		// Safety: Only takes a number, esp-wifi checks whether it is running.
+		let err =
+			unsafe { esp_wifi::binary::include::esp_wifi_set_max_tx_power(current()) };
+		// Fails while Wi-Fi isn't started, it gets applied again once it is.
+		if err != 0 {
+			debug!("Failed to set the TX power: {}", err);
+		}
+	}
+}
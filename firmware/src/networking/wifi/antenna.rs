@@ -0,0 +1,69 @@
+//! Antenna selection, for boards with an RF switch between an internal and an
+//! external antenna. This is configured with the `[antenna]` table of the board
+//! toml, which `build.rs` validates.
+
+extern crate alloc;
+
+use crate::utils::parse_u32;
+
+use alloc::boxed::Box;
+use core::convert::Infallible;
+use defmt::{debug, Format};
+use embedded_hal::digital::v2::OutputPin;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Antenna {
+	Internal,
+	External,
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Selection {
+	Fixed(Antenna),
+	/// Pick whichever antenna receives the access point better when scanning.
+	Auto,
+}
+
+/// How the antenna is selected. Matches the order of `AntennaSelect` in `build.rs`.
+pub const SELECTION: Selection = match parse_u32(env!("ANTENNA_SELECT")) {
+	0 => Selection::Fixed(Antenna::Internal),
+	1 => Selection::Fixed(Antenna::External),
+	_ => Selection::Auto,
+};
+/// Whether driving the switch high selects the external antenna.
+const EXTERNAL_HIGH: bool = parse_u32(env!("ANTENNA_EXTERNAL_HIGH")) != 0;
+
+pub type Switch = Box<dyn OutputPin<Error = Infallible> + Send>;
+
+/// The RF switch, owned by the network task. Does nothing on boards without one.
+pub struct AntennaSwitch(Option<Switch>);
+impl AntennaSwitch {
+	/// Takes control of the RF switch, and selects the antenna to boot with. For auto
+	/// selection that is the internal one, until the first scan.
+	pub fn new(switch: Option<Switch>) -> Self {
+		let mut s = Self(switch);
+		s.select(match SELECTION {
+			Selection::Fixed(antenna) => antenna,
+			Selection::Auto => Antenna::Internal,
+		});
+		s
+	}
+
+	/// Whether we can choose between antennas at runtime.
+	pub fn is_auto(&self) -> bool {
+		SELECTION == Selection::Auto && self.0.is_some()
+	}
+
+	/// Switches to `antenna`.
+	pub fn select(&mut self, antenna: Antenna) {
+		let Some(switch) = self.0.as_mut() else { return };
+		debug!("Selecting {} antenna", antenna);
+		let high = (antenna == Antenna::External) == EXTERNAL_HIGH;
+		// Can't fail, the error type is `Infallible`.
+		let _ = if high {
+			switch.set_high()
+		} else {
+			switch.set_low()
+		};
+	}
+}
@@ -0,0 +1,105 @@
+//! Optional CRC-32 after every packet, to catch corruption that the UDP checksum
+//! missed on a flaky link. We advertise it with `SbPacket::Capabilities` after the
+//! handshake, and only use it once the other side turns it on with
+//! `CbPacket::EnableCapabilities`, so the official server keeps working unchanged.
+//!
+//! Packets that fail the check are dropped and counted, and the count is reported
+//! in a `SbPacket::Diagnostic` every [`REPORT_INTERVAL`].
+
+extern crate alloc;
+
+use defmt::{info, trace};
+use embassy_time::{Duration, Instant};
+use firmware_protocol::{
+	CbPacket, DeserializeError, Packet, SbPacket, SerializeError, CAPABILITY_CRC32,
+};
+
+/// How often the number of rejected packets is reported, if there were any.
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+/// Sent in `sensor_id`, since this isn't about a particular sensor.
+const NO_SENSOR: u8 = 0xFF;
+/// Sent in `code`. Chosen to not collide with the `SensorError` codes.
+const CODE_BAD_CHECKSUM: u8 = 0x80;
+
+pub struct Checksums {
+	enabled: bool,
+	/// How many packets failed the check since the last report.
+	rejected: u32,
+	last_report: Instant,
+}
+impl Checksums {
+	pub fn new() -> Self {
+		Self {
+			enabled: false,
+			rejected: 0,
+			last_report: Instant::now(),
+		}
+	}
+
+	/// Call with the flags of a `CbPacket::EnableCapabilities`.
+	pub fn enable(&mut self, flags: u32) {
+		let enabled = flags & CAPABILITY_CRC32 != 0;
+		if enabled != self.enabled {
+			info!("Packet checksums enabled: {}", enabled);
+		}
+		self.enabled = enabled;
+	}
+
+	pub fn serialize(
+		&self,
+		packet: Packet<SbPacket>,
+		buf: &mut [u8],
+	) -> Result<usize, SerializeError> {
+		if self.enabled {
+			packet.serialize_into_checked(buf)
+		} else {
+			packet.serialize_into(buf)
+		}
+	}
+
+	/// Returns the sequence number and the packet if it was valid.
+	pub fn deserialize(&mut self, buf: &[u8]) -> Option<(u64, CbPacket)> {
+		if !self.enabled {
+			return Packet::deserialize_from(buf).ok().map(Packet::split);
+		}
+		match Packet::deserialize_from_checked(buf) {
+			Ok(packet) => Some(packet.split()),
+			Err(DeserializeError::BadChecksum) => {
+				// A restarted server doesn't know that it turned checksums on, and
+				// starts over with a discovery.
+				if let Ok(packet) = Packet::deserialize_from(buf) {
+					if let (seq, CbPacket::Discovery) = packet.split() {
+						info!("Server restarted, disabling packet checksums");
+						self.enabled = false;
+						return Some((seq, CbPacket::Discovery));
+					}
+				}
+				trace!("Bad checksum on {}", buf);
+				self.rejected = self.rejected.saturating_add(1);
+				None
+			}
+			Err(_) => None,
+		}
+	}
+
+	/// The diagnostic to send every [`REPORT_INTERVAL`], if any packets were rejected.
+	pub fn report(&mut self, now: Instant) -> Option<SbPacket> {
+		if now - self.last_report < REPORT_INTERVAL {
+			return None;
+		}
+		self.last_report = now;
+		let rejected = core::mem::take(&mut self.rejected);
+		if rejected == 0 {
+			return None;
+		}
+		let message = alloc::format!(
+			"Dropped {rejected} packets with bad checksums in the last {}s",
+			REPORT_INTERVAL.as_secs()
+		);
+		Some(SbPacket::Diagnostic {
+			sensor_id: NO_SENSOR,
+			code: CODE_BAD_CHECKSUM,
+			message: message.into(),
+		})
+	}
+}
@@ -0,0 +1,162 @@
+//! The Wi-Fi network to connect to.
+//!
+//! Builds with `SSID` and `PASSWORD` set always connect to that network. Builds
+//! without them, like prebuilt ones, use the credentials stored in flash. Until some
+//! are stored the tracker doesn't connect, and waits to be provisioned over the
+//! serial console with `SET WIFI "<ssid>" "<password>"`, which is what the SlimeVR
+//! server sends to its Wi-Fi setup. `CLEAR WIFI` or holding the button for long
+//! forgets them again. Both reboot, so that the tracker starts over with the new
+//! network.
+
+use defmt::{info, warn};
+use embassy_time::{Duration, Timer};
+
+use crate::storage::{self, Slot};
+
+const BUILD_SSID: Option<&str> = option_env!("SSID");
+const BUILD_PASSWORD: Option<&str> = option_env!("PASSWORD");
+/// The longest SSID and password that Wi-Fi allows.
+const MAX_SSID_LEN: usize = 32;
+const MAX_PASSWORD_LEN: usize = 64;
+/// How often to remind the user that the tracker needs to be provisioned.
+const PROVISIONING_REMINDER: Duration = Duration::from_secs(30);
+
+pub struct Credentials {
+	ssid: [u8; MAX_SSID_LEN],
+	ssid_len: usize,
+	password: [u8; MAX_PASSWORD_LEN],
+	password_len: usize,
+}
+impl Credentials {
+	/// Returns `None` if either doesn't fit.
+	fn new(ssid: &[u8], password: &[u8]) -> Option<Self> {
+		if ssid.is_empty() || ssid.len() > MAX_SSID_LEN {
+			return None;
+		}
+		if password.len() > MAX_PASSWORD_LEN {
+			return None;
+		}
+		// Both end up in `&str`s.
+		core::str::from_utf8(ssid).ok()?;
+		core::str::from_utf8(password).ok()?;
+		let mut c = Self {
+			ssid: [0; MAX_SSID_LEN],
+			ssid_len: ssid.len(),
+			password: [0; MAX_PASSWORD_LEN],
+			password_len: password.len(),
+		};
+		c.ssid[..ssid.len()].copy_from_slice(ssid);
+		c.password[..password.len()].copy_from_slice(password);
+		Some(c)
+	}
+
+	pub fn ssid(&self) -> &str {
+		// Checked in `new`.
+		core::str::from_utf8(&self.ssid[..self.ssid_len]).unwrap_or_default()
+	}
+
+	pub fn password(&self) -> &str {
+		core::str::from_utf8(&self.password[..self.password_len]).unwrap_or_default()
+	}
+
+	/// The length of the SSID, the SSID, the length of the password, and the
+	/// password.
+	fn to_bytes(&self, buf: &mut [u8; 2 + MAX_SSID_LEN + MAX_PASSWORD_LEN]) -> usize {
+		let (ssid, password) = (self.ssid().as_bytes(), self.password().as_bytes());
+		buf[0] = ssid.len() as u8;
+		buf[1..][..ssid.len()].copy_from_slice(ssid);
+		buf[1 + ssid.len()] = password.len() as u8;
+		buf[2 + ssid.len()..][..password.len()].copy_from_slice(password);
+		2 + ssid.len() + password.len()
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Option<Self> {
+		let (&ssid_len, rest) = bytes.split_first()?;
+		let ssid = rest.get(..ssid_len as usize)?;
+		let (&password_len, rest) = rest[ssid.len()..].split_first()?;
+		let password = rest.get(..password_len as usize)?;
+		Self::new(ssid, password)
+	}
+}
+
+/// The credentials of the build, or else the stored ones. Waits until the tracker
+/// is provisioned if there are neither, which ends with a reboot.
+pub async fn get() -> Credentials {
+	if let (Some(ssid), Some(password)) = (BUILD_SSID, BUILD_PASSWORD) {
+		match Credentials::new(ssid.as_bytes(), password.as_bytes()) {
+			Some(c) => return c,
+			None => warn!("The `SSID` or `PASSWORD` of the build is too long"),
+		}
+	}
+	let mut buf = [0; storage::MAX_PAYLOAD_LEN];
+	let loaded = storage::load(Slot::WifiCredentials, &mut buf);
+	match loaded.map(|len| Credentials::from_bytes(&buf[..len])) {
+		Ok(Some(c)) => return c,
+		Ok(None) => warn!("The stored Wi-Fi credentials are invalid"),
+		Err(storage::Error::Empty) => (),
+		Err(e) => warn!("Failed to load the Wi-Fi credentials: {}", e),
+	}
+	loop {
+		info!(
+			"No Wi-Fi credentials, send `SET WIFI \"<ssid>\" \"<password>\"` over the \
+			serial console to provision the tracker"
+		);
+		Timer::after(PROVISIONING_REMINDER).await;
+	}
+}
+
+/// Parses the arguments of `SET WIFI`, which are the SSID and the password, each in
+/// double quotes. Neither can contain quotes themselves.
+fn parse_set(args: &[u8]) -> Option<Credentials> {
+	let mut quoted = args.split(|&b| b == b'"');
+	// Only spaces are allowed outside of quotes.
+	let outside_ok = |s: &[u8]| s.iter().all(|&b| b == b' ');
+	let (before, ssid, between, password, after) = (
+		quoted.next()?,
+		quoted.next()?,
+		quoted.next()?,
+		quoted.next()?,
+		quoted.next()?,
+	);
+	if quoted.next().is_some() || ![before, between, after].into_iter().all(outside_ok)
+	{
+		return None;
+	}
+	Credentials::new(ssid, password)
+}
+
+/// Stores the credentials given to `SET WIFI`, and reboots to connect with them.
+/// Only returns if that failed.
+#[cfg_attr(not(console), allow(dead_code))]
+pub fn set(args: &[u8]) {
+	let Some(credentials) = parse_set(args) else {
+		warn!("usage: SET WIFI \"<ssid>\" \"<password>\"");
+		return;
+	};
+	if BUILD_SSID.is_some() {
+		warn!("this build connects to its own Wi-Fi, so the new network gets ignored");
+	}
+	let mut buf = [0; 2 + MAX_SSID_LEN + MAX_PASSWORD_LEN];
+	let len = credentials.to_bytes(&mut buf);
+	if let Err(e) = storage::store(Slot::WifiCredentials, &buf[..len]) {
+		warn!("Failed to store the Wi-Fi credentials: {}", e);
+		return;
+	}
+	info!(
+		"Stored the Wi-Fi credentials for {=str}",
+		credentials.ssid()
+	);
+	crate::peripherals::reboot();
+}
+
+/// Forgets the stored credentials, and reboots to wait for new ones. Only returns if
+/// that failed.
+#[cfg_attr(not(any(console, button)), allow(dead_code))]
+pub fn clear() {
+	if let Err(e) = storage::clear(Slot::WifiCredentials) {
+		warn!("Failed to clear the Wi-Fi credentials: {}", e);
+		return;
+	}
+	info!("Cleared the Wi-Fi credentials");
+	crate::peripherals::reboot();
+}
@@ -0,0 +1,175 @@
+//! Downloads the firmware of a `CbPacket::FirmwareOffer` from the server that offered
+//! it, and hands it to `ota` as it arrives.
+//!
+//! This is just enough of an HTTP/1.0 client for that: the server closes the
+//! connection after the body, so there is no chunked encoding or keep-alive to deal
+//! with. Reads from the TCP sockets of `stack` block until there is data, which stalls
+//! the other tasks meanwhile. That's fine, the tracker isn't tracking while it
+//! updates anyway.
+
+extern crate alloc;
+
+use defmt::{info, warn, Format};
+use embassy_time::Timer;
+use firmware_protocol::{CbPacket, SbPacket, OTA_FAILED, OTA_OK};
+use smoltcp::wire::IpAddress;
+
+use crate::networking::wifi::stack::{Stack, TcpSocket};
+use crate::ota;
+
+/// The image is handed to `ota` in chunks of up to this size. Also has to fit the
+/// status line and headers of the response.
+const BUFFER_LEN: usize = 1024;
+/// The receive window of the socket.
+const RX_BUFFER_LEN: usize = 2048;
+/// Only the request goes out.
+const TX_BUFFER_LEN: usize = 256;
+/// How often to tell the server how far we got, in bytes of the image.
+const REPORT_INTERVAL: u32 = 16 * 1024;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+enum Error {
+	Connect,
+	/// The response wasn't `200 OK`.
+	Status(u16),
+	/// The response didn't look like HTTP, or its headers didn't fit.
+	Malformed,
+	/// `ota` didn't take a chunk. Its status was already reported.
+	Rejected,
+}
+
+/// Takes the firmware `offer` of the server at `host`, if it is for us. Every
+/// `SbPacket::OtaStatus` goes to `report`, which has to send it right away. Doesn't
+/// return if the update worked, it reboots into the new firmware instead.
+pub async fn run(
+	network: &Stack<'_>,
+	host: IpAddress,
+	offer: CbPacket,
+	mut report: impl FnMut(SbPacket),
+) {
+	let CbPacket::FirmwareOffer { mcu, board, version, port, path, size, crc32 } = offer else { return };
+	let (Ok(version), Ok(path)) = (version.to_string(), path.to_string()) else { warn!("Firmware offer isn't UTF-8"); report(status(0, OTA_FAILED)); return };
+	if let Err(e) = ota::check(&mcu, &board, &version) {
+		report(status(0, e));
+		return;
+	}
+	info!(
+		"Downloading firmware {} from {}:{}{}",
+		version.as_str(),
+		host,
+		port,
+		path.as_str()
+	);
+	let begin = ota::begin(size, crc32);
+	let began = is_ok(&begin);
+	report(begin);
+	if !began {
+		return;
+	}
+
+	let mut rx_buffer = [0; RX_BUFFER_LEN];
+	let mut tx_buffer = [0; TX_BUFFER_LEN];
+	let mut socket = network.tcp_socket(&mut rx_buffer, &mut tx_buffer);
+	let result = fetch(&mut socket, host, port, &path, &mut report);
+	socket.disconnect();
+	match result {
+		Ok(()) => (),
+		Err(Error::Rejected) => return,
+		Err(e) => {
+			warn!("Failed to download firmware: {}", e);
+			report(status(0, OTA_FAILED));
+			return;
+		}
+	}
+
+	let (finished, reboot) = ota::finish();
+	report(finished);
+	if reboot {
+		Timer::after(ota::REBOOT_DELAY).await;
+		ota::reboot();
+	}
+}
+
+/// Requests `path` and writes the body of the response to `ota`. Whether all of
+/// the image arrived is left to `ota::finish`.
+fn fetch(
+	socket: &mut TcpSocket<'_, '_>,
+	host: IpAddress,
+	port: u16,
+	path: &str,
+	report: &mut impl FnMut(SbPacket),
+) -> Result<(), Error> {
+	socket.open(host, port).map_err(|_| Error::Connect)?;
+	// IPv6 addresses go in brackets, so that the port can be told apart.
+	let request = match host {
+		IpAddress::Ipv6(host) => {
+			alloc::format!("GET {path} HTTP/1.0\r\nHost: [{host}]:{port}\r\n\r\n")
+		}
+		_ => alloc::format!("GET {path} HTTP/1.0\r\nHost: {host}:{port}\r\n\r\n"),
+	};
+	socket
+		.write_all(request.as_bytes())
+		.and_then(|()| socket.flush())
+		.map_err(|_| Error::Connect)?;
+
+	let mut buffer = [0; BUFFER_LEN];
+	let mut len = 0;
+	// The headers may be followed by the start of the body in the same read.
+	let head_len = loop {
+		if len == buffer.len() {
+			return Err(Error::Malformed);
+		}
+		match socket.read(&mut buffer[len..]) {
+			Ok(0) | Err(_) => return Err(Error::Malformed),
+			Ok(n) => len += n,
+		}
+		if let Some(end) = buffer[..len].windows(4).position(|w| w == b"\r\n\r\n") {
+			break end + 4;
+		}
+	};
+	match status_code(&buffer[..head_len]) {
+		Some(200) => (),
+		Some(code) => return Err(Error::Status(code)),
+		None => return Err(Error::Malformed),
+	}
+
+	let mut offset = 0;
+	let mut next_report = REPORT_INTERVAL;
+	let (mut start, mut end) = (head_len, len);
+	loop {
+		if start < end {
+			let written = ota::write(offset, &buffer[start..end]);
+			if !is_ok(&written) {
+				report(written);
+				return Err(Error::Rejected);
+			}
+			offset += (end - start) as u32;
+			if offset >= next_report {
+				report(written);
+				next_report += REPORT_INTERVAL;
+			}
+		}
+		// Depending on how the server closes the connection, the end might be
+		// reported as an error.
+		(start, end) = match socket.read(&mut buffer) {
+			Ok(0) | Err(_) => return Ok(()),
+			Ok(n) => (0, n),
+		};
+	}
+}
+
+/// The status code from the status line at the start of `head`, like the `200` of
+/// `HTTP/1.0 200 OK`.
+fn status_code(head: &[u8]) -> Option<u16> {
+	let line = head.split(|&b| b == b'\r').next()?;
+	let code = line.split(|&b| b == b' ').nth(1)?;
+	core::str::from_utf8(code).ok()?.parse().ok()
+}
+
+fn status(offset: u32, status: u8) -> SbPacket {
+	SbPacket::OtaStatus { offset, status }
+}
+
+fn is_ok(packet: &SbPacket) -> bool {
+	matches!(packet, SbPacket::OtaStatus { status: OTA_OK, .. })
+}
@@ -2,84 +2,255 @@ extern crate alloc;
 
 use defmt::{error, info, trace, warn};
 use embassy_futures::{
-	select::{select, Either},
+	select::{select3, Either3},
 	yield_now,
 };
-use embedded_svc::ipv4::Interface;
+use embassy_time::{Duration, Instant, Timer};
+use embedded_svc::wifi::ClientConfiguration;
 use esp_wifi::{
-	create_network_stack_storage, current_millis, network_stack_storage,
+	create_network_stack_storage, network_stack_storage,
 	wifi::utils::create_network_interface,
-	wifi_interface::{IoError as WifiError, Network, UdpSocket},
 };
-use smoltcp::{socket::UdpPacketMetadata, wire::Ipv4Address};
+use smoltcp::{socket::UdpPacketMetadata, wire::IpAddress};
 
+use crate::events::{Event, EventBus};
 use crate::networking::protocol::Packets;
-use firmware_protocol::Packet;
+use crate::networking::wifi::antenna::AntennaSwitch;
+use crate::networking::wifi::checksum::Checksums;
+use crate::networking::wifi::credentials::{self, Credentials};
+#[cfg(feature = "ota")]
+use crate::networking::wifi::download;
+#[cfg(feature = "log-udp")]
+use crate::networking::wifi::log_bridge::{self, LogBridge};
+use crate::networking::wifi::slots::Slots;
+use crate::networking::wifi::stack::{self, Stack, UdpSocket};
+use crate::networking::wifi::timing::ResumeTimer;
+use firmware_protocol::{CbPacket, Packet, SbPacket};
 
 // SlimeVR default UDP port on both sides of connection
 const PORT: u16 = 6969;
 
-pub async fn network_task(packets: &Packets) -> ! {
+/// The server sends heartbeats regularly. If we don't hear anything from it for this
+/// long, the Wi-Fi driver has probably stalled without reporting a disconnect.
+const STALL_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often to check for a stall when there is no other network activity.
+#[cfg(not(feature = "log-udp"))]
+const IDLE_INTERVAL: Duration = Duration::from_secs(1);
+/// The logs get flushed at the same time, which needs to happen more often.
+#[cfg(feature = "log-udp")]
+const IDLE_INTERVAL: Duration = log_bridge::FLUSH_INTERVAL;
+
+/// Matches modern MTU sizes and is more than enough for the SlimeVR protocol.
+#[cfg(not(feature = "constrained"))]
+const BUFFER_SIZE: usize = 1536;
+/// Still fits the largest packet we send or receive, a handshake with a 255 byte
+/// firmware string.
+#[cfg(feature = "constrained")]
+const BUFFER_SIZE: usize = 384;
+
+/// How long DHCP may take before we go on without IPv4, assuming that the network
+/// only has IPv6.
+const DHCP_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Our port for mirroring logs from, see `log_bridge`.
+#[cfg(feature = "log-udp")]
+const LOG_PORT: u16 = 6971;
+
+/// What our UDP packets are marked with, from `DSCP` or `WMM_AC` in `.env`. The
+/// vendored smoltcp marks them, see `crates/smoltcp/PATCHES.md`.
+const DSCP: u8 = crate::utils::parse_u32(env!("QOS_DSCP")) as u8;
+
+/// Problems on the tracker are also broadcast to this port, so that the overlay can
+/// show them without going through the server.
+const DIAGNOSTICS_PORT: u16 = 6970;
+
+pub async fn network_task(
+	packets: &Packets,
+	mut antenna: AntennaSwitch,
+	events: &EventBus,
+) -> ! {
+	smoltcp::wire::set_udp_dscp(DSCP);
+	let credentials = credentials::get().await;
+	// Kept across sessions, so that reconnecting is fast.
+	let mut cached_ap = None;
+	// Also kept, the server would drop our packets as out of order if we started
+	// over.
+	let mut tx_seq = 0;
+	loop {
+		run_session(
+			packets,
+			events,
+			&credentials,
+			&mut cached_ap,
+			&mut antenna,
+			&mut tx_seq,
+		)
+		.await;
+		crate::events::publish(events, Event::LinkChanged { up: false });
+		warn!("Wi-Fi stalled, resetting the network interface");
+	}
+}
+
+/// Connects to Wi-Fi and exchanges packets with the server, returning when the
+/// connection stalls.
+async fn run_session(
+	packets: &Packets,
+	events: &EventBus,
+	credentials: &Credentials,
+	cached_ap: &mut Option<ClientConfiguration>,
+	antenna: &mut AntennaSwitch,
+	tx_seq: &mut u64,
+) {
+	let mut resume_timer = Some(ResumeTimer::start());
+
+	// Has to outlive `storage`, which the interface borrows along with it.
+	let mut stack_storage = stack::Storage::new();
 	// TODO: Maybe we should look at the macros in the future for better config
 	// (socket_count, neighbour_cache_count, routes_store_count, multicast_store_count)
-	let mut storage = create_network_stack_storage!(3, 8, 1, 1);
+	// The sockets are DHCP, ICMPv6 for SLAAC, the packets, and the logs.
+	#[cfg(not(feature = "ota"))]
+	let mut storage = create_network_stack_storage!(4, 8, 1, 1);
+	// One more for downloading firmware over TCP.
+	#[cfg(feature = "ota")]
+	let mut storage = create_network_stack_storage!(5, 8, 1, 1);
 	let ethernet = create_network_interface(network_stack_storage!(storage));
 	let mut wifi = esp_wifi::wifi_interface::Wifi::new(ethernet);
-	super::connect_wifi(&mut wifi)
+	super::connect_wifi(&mut wifi, credentials, cached_ap, antenna, events)
 		.await
 		.expect("Couldn't connect to wifi");
+	if let Some(t) = &resume_timer {
+		t.milestone("wifi connected");
+	}
 
-	let network = Network::new(wifi, current_millis);
+	let network = Stack::new(wifi, &mut stack_storage);
 
-	// Wait till DHCP assigns us an IP
+	// Wait till DHCP assigns us an IP. Networks without IPv4 never do, so we go on
+	// with IPv6 after a while, from SLAAC or only link-local, see `stack`.
+	let dhcp_started = Instant::now();
 	let client_ip = loop {
 		yield_now().await;
 		network.work();
-		let Ok(ip) = network.get_ip_info() else { continue };
-		break ip.ip.octets();
+		if let Some(ip) = network.ipv4() {
+			break Some(ip.0);
+		}
+		if dhcp_started.elapsed() > DHCP_TIMEOUT {
+			warn!(
+				"No IPv4 address from DHCP after {} s, going on with IPv6 ({})",
+				DHCP_TIMEOUT.as_secs(),
+				network.ipv6()
+			);
+			break None;
+		}
 	};
 
-	info!("DHCP IP: {}", client_ip);
+	if let Some(client_ip) = client_ip {
+		info!("DHCP IP: {}", client_ip);
+		crate::diag::wifi_ip(client_ip);
+		crate::events::publish(events, Event::IpAssigned(client_ip));
+	}
+	crate::events::publish(events, Event::LinkChanged { up: true });
+	if let Some(t) = &resume_timer {
+		t.milestone("got an address");
+	}
 
-	let mut server_ip = None; // We don't know the server ip yet.
+	// We don't know the server ip yet.
+	let mut server_ip: Option<IpAddress> = None;
+	// Raw data streams can also be requested by developer tools besides the server,
+	// like the overlay's plots. The samples then go to them instead.
+	let mut raw_requester: Option<(IpAddress, u16)> = None;
 
-	// Buffer size of 1536 matches modern MTU sizes and is more than enough for the SlimeVR protocol
-	// Unfortunately esp-wifi won't let us access the underlying tx/rx buffer. Unecessary copy here
-	let mut buffer = [0; 1536];
-	let mut rx_buffer = [0u8; 1536];
-	let mut tx_buffer = [0u8; 1536];
+	// Packets are (de)serialized here, and copied to and from the buffers of the
+	// sockets.
+	let mut buffer = [0; BUFFER_SIZE];
+	let mut rx_buffer = [0u8; BUFFER_SIZE];
+	let mut tx_buffer = [0u8; BUFFER_SIZE];
 	let mut rx_meta = [UdpPacketMetadata::EMPTY];
 	let mut tx_meta = [UdpPacketMetadata::EMPTY];
-	let mut socket = network.get_udp_socket(
-		&mut rx_meta,
-		&mut rx_buffer,
-		&mut tx_meta,
-		&mut tx_buffer,
-	);
+	let mut socket =
+		network.udp_socket(&mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
 
 	// Server will send broadcasts to this port
 	socket.bind(PORT).unwrap();
 
+	#[cfg(feature = "log-udp")]
+	let (mut log_rx_meta, mut log_rx_buffer) = ([UdpPacketMetadata::EMPTY], [0u8; 0]);
+	#[cfg(feature = "log-udp")]
+	let (mut log_tx_meta, mut log_tx_buffer) = ([UdpPacketMetadata::EMPTY; 4], [0u8; 2048]);
+	#[cfg(feature = "log-udp")]
+	let mut log_socket = {
+		let mut s = network.udp_socket(
+			&mut log_rx_meta,
+			&mut log_rx_buffer,
+			&mut log_tx_meta,
+			&mut log_tx_buffer,
+		);
+		s.bind(LOG_PORT).unwrap();
+		s
+	};
+	#[cfg(feature = "log-udp")]
+	let mut log_bridge = LogBridge::new();
+
 	// Sequence numbers are monotonically increasing. This is done to reject out-of-order packets
 	// This along with serialization should maybe be done in Packets
-	let mut tx_seq = 0;
 	let mut rx_seq = 0;
 
+	let mut last_rx = Instant::now();
+	let mut slots = Slots::new();
+	let mut checksums = Checksums::new();
+
 	// TODO: Implement with proper async select. So far there is no async counterpart of recv
 	loop {
+		#[cfg(feature = "log-udp")]
+		log_bridge.flush(|chunk| {
+			let host = IpAddress::Ipv4(smoltcp::wire::Ipv4Address(log_bridge::HOST));
+			log_socket.send(host, log_bridge::PORT, chunk).is_ok()
+		});
+
+		if let Some(report) = checksums.report(Instant::now()) {
+			// Not worth waiting for, there will be another report soon if the link
+			// stays bad.
+			let _ = packets.serverbound.try_send(report);
+		}
+
 		// Either start sending or receive, if either is available
-		let net = select(
+		let net = select3(
 			recv_bytes(&mut socket, &mut buffer),
 			packets.serverbound.recv(),
+			Timer::after(IDLE_INTERVAL),
 		)
 		.await;
 
 		match (net, server_ip) {
 			// There is inbound bytes that should be parsed and processed
-			(Either::First((len, addr, _port)), _) => {
+			(Either3::First((len, addr, port)), _) => {
+				last_rx = Instant::now();
+
 				// Try to optimistically parse all packets that come off the network
-				let Ok(packet) = Packet::deserialize_from(&buffer[..len]) else { trace!("Discarding {}", &buffer[..len]); continue };
-				let (seq, msg) = packet.split();
+				let Some((seq, msg)) = checksums.deserialize(&buffer[..len]) else { trace!("Discarding {}", &buffer[..len]); continue };
+
+				// A tool other than the server must not take over as our host, and
+				// doesn't count sequence numbers along with it.
+				if let CbPacket::StreamRawData { .. } = msg {
+					if server_ip == Some(addr) {
+						raw_requester = None;
+					} else {
+						info!("Raw data stream requested by {}:{}", addr, port);
+						raw_requester = Some((addr, port));
+						packets.clientbound.send(msg).await;
+						continue;
+					}
+				}
+
+				// A handshake response has no sequence number, those bytes are part of
+				// its greeting. It starts a new session, in which the server counts
+				// from the start again.
+				let seq = match msg {
+					CbPacket::HandshakeResponse { .. } => {
+						rx_seq = 0;
+						0
+					}
+					_ => seq,
+				};
 
 				// Cancel if sequence number is less than last seen. As of writing, SlimeVR server does not properly
 				// count sequence numbers for clientbound packets, so it always sends 0. This still works, because we
@@ -94,6 +265,38 @@ pub async fn network_task(packets: &Packets) -> ! {
 					continue;
 				}
 
+				// Downloading needs the network, so we take offers of firmware here
+				// instead of the control task.
+				#[cfg(feature = "ota")]
+				if let CbPacket::FirmwareOffer { .. } = msg {
+					let host = addr;
+					let report = |msg| {
+						let Ok(len) = checksums.serialize(Packet::new(*tx_seq, msg), &mut buffer) else { return };
+						*tx_seq += 1;
+						if let Err(e) = socket.send(host, PORT, &buffer[..len]) {
+							warn!(
+								"Failed to send #{}: {}",
+								tx_seq,
+								defmt::Debug2Format(&e)
+							);
+						}
+					};
+					download::run(&network, host, msg, report).await;
+					// Nothing was received while downloading.
+					last_rx = Instant::now();
+					continue;
+				}
+
+				match msg {
+					CbPacket::Heartbeat => slots.sync(),
+					CbPacket::TxSlots { count, period_ms } => {
+						slots.configure(count, period_ms)
+					}
+					// Applies from the next packet on, in both directions.
+					CbPacket::EnableCapabilities { flags } => checksums.enable(flags),
+					_ => (),
+				}
+
 				// Hand the packet to rest of the system
 				packets.clientbound.send(msg).await;
 				rx_seq = seq;
@@ -105,19 +308,58 @@ pub async fn network_task(packets: &Packets) -> ! {
 						addr, server_ip
 					);
 					server_ip = Some(addr);
+					if let Some(t) = &resume_timer {
+						t.milestone("found server");
+					}
 				}
 			}
+			// A server without our extensions ignores them anyway.
+			(Either3::Second(msg), _) if !packets.allows(&msg) => continue,
 			// There is pending outbound packet that should be sent
-			(Either::Second(msg), Some(server_ip)) => {
+			(Either3::Second(msg), server_ip) => {
+				// Handshakes are broadcast, so that any server that is up answers,
+				// even one that we don't know about yet, over IPv4 or IPv6.
+				let destination = match (&msg, server_ip) {
+					(SbPacket::Handshake { .. }, _) => None,
+					(_, Some(server_ip)) => Some(server_ip),
+					// Nobody to send it to yet.
+					(_, None) => continue,
+				};
+				let is_diagnostic = matches!(
+					msg,
+					SbPacket::Diagnostic { .. }
+						| SbPacket::ThermalState { .. }
+						| SbPacket::Sleeping
+				);
+				let raw_requester = raw_requester
+					.filter(|_| matches!(msg, SbPacket::RawImuData { .. }));
 				// Serialize the packet based on our send sequence number
-				let Ok(len) = Packet::new(tx_seq, msg).serialize_into(&mut buffer) else { warn!("Failed to serialize outgoing packet"); continue };
-				tx_seq += 1;
+				let Ok(len) = checksums.serialize(Packet::new(*tx_seq, msg), &mut buffer) else { warn!("Failed to serialize outgoing packet"); continue };
+				*tx_seq += 1;
 
-				if let Err(e) =
-					socket.send(Ipv4Address(server_ip), PORT, &buffer[..len])
-				{
+				if slots.is_enabled() {
+					Timer::at(slots.next_send()).await;
+				}
+				let data = &buffer[..len];
+				let result = match (raw_requester, destination) {
+					(Some((ip, port)), _) => socket.send(ip, port, data),
+					(None, Some(ip)) => socket.send(ip, PORT, data),
+					(None, None) => socket.broadcast(PORT, data),
+				};
+				if let Err(e) = result {
 					warn!("Failed to send #{}: {}", tx_seq, defmt::Debug2Format(&e));
+				} else if let Some(t) = resume_timer.take() {
+					t.finish();
 				}
+				if is_diagnostic {
+					let _ = socket.broadcast(DIAGNOSTICS_PORT, data);
+				}
+			}
+			// Only check for stalls once we know of a server, it might not be up yet.
+			// The control task already noticed that the server went quiet, and
+			// searches for it again once we are back.
+			(Either3::Third(()), Some(_)) if last_rx.elapsed() > STALL_TIMEOUT => {
+				return;
 			}
 			_ => (),
 		}
@@ -125,17 +367,16 @@ pub async fn network_task(packets: &Packets) -> ! {
 }
 
 /// Asynchronously receive bytes from the network. This is a wrapper around UdpSocket::receive
-/// Returns number of bytes read, receiving Ipv4 address and receiving port
+/// Returns number of bytes read, receiving IP address and receiving port
 async fn recv_bytes<'s, 'n>(
 	socket: &mut UdpSocket<'s, 'n>,
 	buffer: &mut [u8],
-) -> (usize, [u8; 4], u16) {
+) -> (usize, IpAddress, u16) {
 	loop {
 		match socket.receive(buffer) {
 			Ok(v) => return v,
-			Err(WifiError::Other(smoltcp::Error::Exhausted)) => {}
-			Err(WifiError::Other(e)) => error!("smoltcp error {}", e),
-			Err(e) => error!("esp-wifi error {}", defmt::Debug2Format(&e)),
+			Err(smoltcp::Error::Exhausted) => {}
+			Err(e) => error!("smoltcp error {}", e),
 		}
 		yield_now().await
 	}
@@ -0,0 +1,86 @@
+//! Mirrors the log output over UDP, so that the logs of a tracker that is strapped
+//! to a leg across the room can be watched without a cable. Configured with
+//! `LOG_UDP` in `.env`, which `build.rs` validates.
+//!
+//! The datagrams contain the raw defmt stream, so they can be decoded with
+//! something like `nc -ul 6971 | defmt-print -e target/.../firmware`. Mirroring
+//! starts disabled unless `LOG_UDP_AT_BOOT` is set, and can be toggled by sending
+//! `log udp on` or `log udp off` over the USB serial port, see `console`.
+
+use crate::utils::parse_u32;
+
+use defmt::warn;
+pub use defmt_esp_println::tee;
+use embassy_time::{Duration, Instant};
+
+pub const HOST: [u8; 4] = [
+	parse_u32(env!("LOG_UDP_IP0")) as u8,
+	parse_u32(env!("LOG_UDP_IP1")) as u8,
+	parse_u32(env!("LOG_UDP_IP2")) as u8,
+	parse_u32(env!("LOG_UDP_IP3")) as u8,
+];
+pub const PORT: u16 = parse_u32(env!("LOG_UDP_PORT")) as u16;
+/// The most bytes per second we send.
+const RATE: u32 = parse_u32(env!("LOG_UDP_RATE"));
+const AT_BOOT: bool = parse_u32(env!("LOG_UDP_AT_BOOT")) != 0;
+
+/// The largest datagram we send, comfortably below the MTU.
+const CHUNK: usize = 512;
+/// How often to flush when there is no other network activity.
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(50);
+/// How often we may warn about dropped output, since each warning is output too.
+const DROPPED_WARN_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Sends the mirrored log output, limited to [`RATE`] bytes per second.
+pub struct LogBridge {
+	/// How many bytes we may send right now, refilled over time.
+	budget: u32,
+	last_refill: Instant,
+	dropped: u32,
+	last_dropped_warn: Instant,
+}
+impl LogBridge {
+	pub fn new() -> Self {
+		if AT_BOOT {
+			tee::set_enabled(true);
+		}
+		Self {
+			budget: 0,
+			last_refill: Instant::now(),
+			dropped: 0,
+			last_dropped_warn: Instant::now(),
+		}
+	}
+
+	/// Sends as much of the log output as the rate limit allows, in datagrams of at
+	/// most [`CHUNK`] bytes. Stops early if `send` fails.
+	pub fn flush(&mut self, mut send: impl FnMut(&[u8]) -> bool) {
+		if !tee::is_enabled() {
+			return;
+		}
+		let now = Instant::now();
+		let refill = (now - self.last_refill).as_micros() * u64::from(RATE) / 1_000_000;
+		if refill > 0 {
+			// Never save up more than a second, so bursts stay bounded.
+			self.budget = (self.budget as u64 + refill).min(u64::from(RATE)) as u32;
+			self.last_refill = now;
+		}
+
+		self.dropped += tee::take_dropped();
+		if self.dropped > 0 && now - self.last_dropped_warn > DROPPED_WARN_INTERVAL {
+			warn!("log bridge dropped {} bytes", self.dropped);
+			self.dropped = 0;
+			self.last_dropped_warn = now;
+		}
+
+		let mut buf = [0; CHUNK];
+		while self.budget > 0 {
+			let max = buf.len().min(self.budget as usize);
+			let n = tee::read(&mut buf[..max]);
+			if n == 0 || !send(&buf[..n]) {
+				break;
+			}
+			self.budget -= n as u32;
+		}
+	}
+}
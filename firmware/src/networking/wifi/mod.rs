@@ -1,58 +1,170 @@
-use defmt::{debug, info};
+pub mod antenna;
+#[cfg(feature = "net-wifi")]
+mod checksum;
+#[cfg(feature = "net-wifi")]
+pub mod credentials;
+#[cfg(feature = "ota")]
+mod download;
+#[cfg(feature = "log-udp")]
+pub mod log_bridge;
+mod slots;
+#[cfg(feature = "net-wifi")]
+mod stack;
+mod timing;
+
+use defmt::{debug, info, warn};
 use embassy_futures::yield_now;
-use embedded_svc::wifi::{ClientConfiguration, Configuration, Wifi};
+use embassy_time::{Duration, Instant};
+use embedded_svc::wifi::{AccessPointInfo, ClientConfiguration, Configuration, Wifi};
+
+use self::antenna::{Antenna, AntennaSwitch};
+#[cfg(feature = "net-wifi")]
+use self::credentials::Credentials;
+use crate::events::{self, BootStage, Event, EventBus};
 
 #[cfg(feature = "net-wifi")]
 #[path = "esp.rs"]
 pub mod ඞ;
 
-const SSID: &str = env!("SSID");
-const PASSWORD: &str = env!("PASSWORD");
 const EXPECTED_NEIGHBOURS: usize = 10;
 const WIFI_FIND_RETRIES: usize = 10;
+/// How long to wait when connecting with cached parameters, before giving up and
+/// scanning for the access point again.
+const CACHED_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
 
-pub async fn connect_wifi<W: Wifi>(wifi: &mut W) -> Result<(), W::Error> {
+/// Connects to the network of `credentials`. `cached` holds the parameters of the
+/// last successful connection, which lets us skip scanning, the slowest part of
+/// reconnecting. Keeps scanning until the network shows up.
+pub async fn connect_wifi<W: Wifi>(
+	wifi: &mut W,
+	credentials: &Credentials,
+	cached: &mut Option<ClientConfiguration>,
+	antenna: &mut AntennaSwitch,
+	events: &EventBus,
+) -> Result<(), W::Error> {
 	if !wifi.is_started()? {
-		wifi.start()?
+		wifi.start()?;
+		crate::networking::tx_power::apply();
+	}
+	// We might be reconnecting after a stall.
+	if wifi.is_connected()? {
+		wifi.disconnect()?;
 	}
 
+	if let Some(client_config) = cached.clone() {
+		debug!("wifi connecting with cached parameters");
+		if try_connect(wifi, client_config, Some(CACHED_CONNECT_TIMEOUT)).await? {
+			return Ok(());
+		}
+		info!("couldn't connect with cached parameters, scanning instead");
+		let _ = wifi.disconnect();
+		*cached = None;
+	}
+
+	let client_config = scan(wifi, credentials, antenna, events).await?;
+	try_connect(wifi, client_config.clone(), None).await?;
+	*cached = Some(client_config);
+	Ok(())
+}
+
+/// Scans for the configured SSID, and returns the configuration to connect to it.
+/// Not finding it for [`WIFI_FIND_RETRIES`] scans fails the network stage of
+/// booting, but the scans go on, in case the access point is only starting up.
+async fn scan<W: Wifi>(
+	wifi: &mut W,
+	credentials: &Credentials,
+	antenna: &mut AntennaSwitch,
+	events: &EventBus,
+) -> Result<ClientConfiguration, W::Error> {
+	let ssid = credentials.ssid();
 	let mut i = 0;
 	let ap = loop {
 		i += 1;
 		debug!("wifi scanning, retry {}...", i);
-		let (mut scan_list, count) = wifi.scan_n::<EXPECTED_NEIGHBOURS>()?;
-		debug!("found {} APs", count);
+		let found = if antenna.is_auto() {
+			scan_antennas(wifi, ssid, antenna)?
+		} else {
+			find_ssid(wifi, ssid)?
+		};
 
-		let pos = scan_list.iter().position(|ap| ap.ssid == SSID);
-
-		if let Some(ap) = pos {
-			break scan_list.swap_remove(ap);
+		if let Some(ap) = found {
+			break ap;
 		} else if i == WIFI_FIND_RETRIES {
-			panic!("Couldn't find SSID {}", SSID);
+			warn!("Couldn't find SSID {}, still looking", ssid);
+			events::publish(events, Event::BootFailed(BootStage::Network));
 		}
 		// TODO: this also should require a ticker
 		yield_now().await;
 	};
-	info!("found SSID {}", SSID);
-	let client_config = Configuration::Client(ClientConfiguration {
-		ssid: SSID.into(),
-		password: PASSWORD.into(),
+	info!("found SSID {}", ssid);
+	Ok(ClientConfiguration {
+		ssid: ssid.into(),
+		password: credentials.password().into(),
 		bssid: Some(ap.bssid),
 		auth_method: ap.auth_method,
 		channel: Some(ap.channel),
-	});
-	wifi.set_configuration(&client_config)?;
+	})
+}
+
+/// Scans once, and returns the access point with `ssid` if we saw it.
+fn find_ssid<W: Wifi>(
+	wifi: &mut W,
+	ssid: &str,
+) -> Result<Option<AccessPointInfo>, W::Error> {
+	let (mut scan_list, count) = wifi.scan_n::<EXPECTED_NEIGHBOURS>()?;
+	debug!("found {} APs", count);
+	let pos = scan_list.iter().position(|ap| ap.ssid == ssid);
+	let found = pos.map(|i| (scan_list[i].signal_strength, scan_list[i].channel));
+	crate::diag::wifi_scan(count, found);
+	Ok(pos.map(|ap| scan_list.swap_remove(ap)))
+}
+
+/// Scans with each antenna, and stays on the one that receives `ssid` with the
+/// strongest signal.
+fn scan_antennas<W: Wifi>(
+	wifi: &mut W,
+	ssid: &str,
+	switch: &mut AntennaSwitch,
+) -> Result<Option<AccessPointInfo>, W::Error> {
+	let mut best: Option<(Antenna, AccessPointInfo)> = None;
+	for antenna in [Antenna::Internal, Antenna::External] {
+		switch.select(antenna);
+		let Some(ap) = find_ssid(wifi, ssid)? else { continue };
+		debug!("{} antenna RSSI: {}", antenna, ap.signal_strength);
+		let better = best.as_ref().map_or(true, |(_, b)| {
+			i32::from(ap.signal_strength) > i32::from(b.signal_strength)
+		});
+		if better {
+			best = Some((antenna, ap));
+		}
+	}
+	let Some((antenna, ap)) = best else { return Ok(None) };
+	info!("using {} antenna", antenna);
+	switch.select(antenna);
+	Ok(Some(ap))
+}
+
+/// Connects with `client_config`. Returns `false` if that didn't succeed within
+/// `timeout`, or waits forever if there is no timeout.
+async fn try_connect<W: Wifi>(
+	wifi: &mut W,
+	client_config: ClientConfiguration,
+	timeout: Option<Duration>,
+) -> Result<bool, W::Error> {
+	wifi.set_configuration(&Configuration::Client(client_config))?;
 
 	debug!("{:?}", defmt::Debug2Format(&wifi.get_capabilities()?));
 	wifi.connect()?;
 
+	let start = Instant::now();
 	loop {
 		let res = wifi.is_connected();
 		if matches!(res, Ok(true)) {
-			break; // connected successfully
+			return Ok(true); // connected successfully
+		}
+		if timeout.map_or(false, |t| start.elapsed() > t) {
+			return Ok(false);
 		}
 		yield_now().await;
 	}
-
-	Ok(())
 }
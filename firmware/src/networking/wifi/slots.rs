@@ -0,0 +1,99 @@
+//! Transmit slots, so that lots of trackers on the same channel don't all send their
+//! rotations at the same time and collide. The server turns them on with
+//! `CbPacket::TxSlots`, which says how many slots each period has and how long it
+//! is. Each tracker takes the slot that its MAC address falls into, so that they
+//! don't have to be told apart by hand.
+//!
+//! There is no real clock sync with the server. Instead, the period restarts
+//! whenever we get a heartbeat, since the server sends those to every tracker in
+//! one go. That is only accurate to a millisecond or so, which is why the slots
+//! should be a good deal longer than a packet takes to send.
+
+use defmt::{debug, warn};
+use embassy_time::{Duration, Instant};
+
+pub struct Slots {
+	/// The start of a period, as far as we know.
+	epoch: Instant,
+	period: Duration,
+	/// How many slots the period is split into. `0` while they are off.
+	count: u32,
+	/// Our slot, in the range `0..count`.
+	index: u32,
+}
+impl Slots {
+	pub fn new() -> Self {
+		Self {
+			epoch: Instant::now(),
+			period: Duration::from_ticks(0),
+			count: 0,
+			index: 0,
+		}
+	}
+
+	/// Whether the server turned transmit slots on.
+	pub fn is_enabled(&self) -> bool {
+		self.count != 0
+	}
+
+	/// Splits every `period_ms` into `count` slots, as the server asked, or turns
+	/// them off with a `count` of `0`.
+	pub fn configure(&mut self, count: u16, period_ms: u16) {
+		let period = Duration::from_millis(period_ms.into());
+		if period.as_micros() < u64::from(count) {
+			warn!(
+				"Ignoring {} slots in {}ms, that is less than 1us each",
+				count, period_ms
+			);
+			return;
+		}
+		self.period = period;
+		self.count = count.into();
+		self.index = index(crate::peripherals::chip_id(), self.count);
+		debug!(
+			"Sending in slot {} of {} every {}ms",
+			self.index, count, period_ms
+		);
+	}
+
+	/// Lines the periods up with a heartbeat from the server that just arrived.
+	pub fn sync(&mut self) {
+		self.epoch = Instant::now();
+	}
+
+	/// When we may send next. That is right away while we are in our slot, so that
+	/// the packets of one sample go out together.
+	pub fn next_send(&self) -> Instant {
+		if !self.is_enabled() {
+			return Instant::now();
+		}
+		let period = self.period.as_micros();
+		let width = period / u64::from(self.count);
+		let offset = width * u64::from(self.index);
+
+		let now = Instant::now();
+		let since_epoch = (now - self.epoch).as_micros();
+		let in_period = since_epoch % period;
+		if (offset..offset + width).contains(&in_period) {
+			return now;
+		}
+		let period_start = since_epoch - in_period;
+		let next = if in_period < offset {
+			period_start + offset
+		} else {
+			period_start + period + offset
+		};
+		self.epoch + Duration::from_micros(next)
+	}
+}
+
+/// The slot of the tracker with the MAC address `mac`, out of `count`. That is the
+/// part of the address that the vendor numbers its chips with, so that trackers
+/// out of one batch, which usually have consecutive addresses, get different slots.
+/// Others can still end up in the same one.
+fn index(mac: [u8; 6], count: u32) -> u32 {
+	let [.., d, e, f] = mac;
+	u32::from_be_bytes([0, d, e, f])
+		.checked_rem(count)
+		.unwrap_or(0)
+}
@@ -0,0 +1,439 @@
+//! A dual-stack replacement for esp-wifi's `Network`, whose sockets only send to
+//! IPv4 addresses. This one drives the smoltcp interface of `Wifi` directly, so that
+//! the server, the tools and the broadcasts can be on IPv4 or IPv6.
+//!
+//! IPv4 comes from DHCP, which esp-wifi still does for us. For IPv6 the tracker
+//! always has a link-local address made from its MAC, and makes a global one from
+//! the prefix that the router advertises (SLAAC), since smoltcp 0.8 does neither.
+//! There is no duplicate address detection, the MAC is unique anyway, and the
+//! lifetime of the prefix is ignored, the next session asks for it again. IPv6 isn't
+//! routed, the server has to be on the same link, as it is for the discovery
+//! broadcasts to reach it.
+
+use core::cell::{Cell, RefCell};
+
+use defmt::info;
+use esp_wifi::{current_millis, wifi_interface::Wifi};
+use smoltcp::{
+	iface::SocketHandle,
+	phy::ChecksumCapabilities,
+	socket::{
+		RawPacketMetadata, RawSocket, RawSocketBuffer, TcpSocket as SmolTcpSocket,
+		TcpSocketBuffer, TcpState, UdpPacketMetadata, UdpSocket as SmolUdpSocket,
+		UdpSocketBuffer,
+	},
+	time::{Duration, Instant},
+	wire::{
+		EthernetAddress, HardwareAddress, Icmpv6Message, Icmpv6Packet, Icmpv6Repr,
+		IpAddress, IpCidr, IpProtocol, IpVersion, Ipv4Address, Ipv6Address, Ipv6Packet,
+		Ipv6Repr, NdiscOption, NdiscOptionType, NdiscPrefixInfoFlags, NdiscRepr,
+	},
+	Error,
+};
+
+/// The IPv4 address from DHCP, then the global IPv6 address and the link-local one.
+/// smoltcp sends from the first address of the family, so the global one goes in
+/// front of the link-local one once we have it.
+const ADDRESSES: usize = 3;
+/// Room for a router advertisement, including the options that we skip, like DNS
+/// servers.
+const ICMP_RX_LEN: usize = 512;
+/// Only router solicitations go out.
+const ICMP_TX_LEN: usize = 64;
+/// SLAAC only works with this prefix length, the other half of the address is made
+/// from the MAC. Also the prefix of the link-local addresses.
+const PREFIX_LEN: u8 = 64;
+/// Neighbour discovery packets from off the link can't have this hop limit left.
+const NDISC_HOP_LIMIT: u8 = 255;
+/// How long a TCP connection may go without hearing from the other side.
+const TCP_TIMEOUT: Duration = Duration::from_secs(10);
+/// The local ports of TCP connections come from the dynamic range.
+const FIRST_LOCAL_PORT: u16 = 49152;
+
+/// What the interface borrows from the stack, which has to outlive it.
+pub struct Storage {
+	addresses: [IpCidr; ADDRESSES],
+	icmp_rx_meta: [RawPacketMetadata; 1],
+	icmp_rx: [u8; ICMP_RX_LEN],
+	icmp_tx_meta: [RawPacketMetadata; 1],
+	icmp_tx: [u8; ICMP_TX_LEN],
+}
+impl Storage {
+	pub fn new() -> Self {
+		// Not `0.0.0.0/0`, that would put every IPv4 address on our link.
+		let unspecified = IpCidr::new(IpAddress::Ipv6(Ipv6Address::UNSPECIFIED), 128);
+		Self {
+			addresses: [unspecified; ADDRESSES],
+			icmp_rx_meta: [RawPacketMetadata::EMPTY],
+			icmp_rx: [0; ICMP_RX_LEN],
+			icmp_tx_meta: [RawPacketMetadata::EMPTY],
+			icmp_tx: [0; ICMP_TX_LEN],
+		}
+	}
+}
+
+pub struct Stack<'a> {
+	wifi: RefCell<Wifi<'a>>,
+	mac: EthernetAddress,
+	/// The raw socket that router advertisements arrive on.
+	icmp: SocketHandle,
+	link_local: Ipv6Address,
+	global: Cell<Option<Ipv6Address>>,
+	next_local_port: Cell<u16>,
+}
+impl<'a> Stack<'a> {
+	/// Takes over the interface of `wifi`, which should be connected, and asks the
+	/// router for an IPv6 prefix.
+	pub fn new(mut wifi: Wifi<'a>, storage: &'a mut Storage) -> Self {
+		let Storage {
+			addresses,
+			icmp_rx_meta,
+			icmp_rx,
+			icmp_tx_meta,
+			icmp_tx,
+		} = storage;
+		let iface = wifi.network_interface();
+		let HardwareAddress::Ethernet(mac) = iface.hardware_addr();
+		let link_local = with_mac(Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), mac);
+		iface.update_ip_addrs(|current| {
+			// The address esp-wifi started with, or got from DHCP already.
+			let ipv4 = current
+				.iter()
+				.find(|c| matches!(c.address(), IpAddress::Ipv4(_)));
+			if let Some(&ipv4) = ipv4 {
+				addresses[0] = ipv4;
+			}
+			addresses[1] = IpCidr::new(IpAddress::Ipv6(link_local), PREFIX_LEN);
+			*current = (&mut addresses[..]).into();
+		});
+		let icmp = iface.add_socket(RawSocket::new(
+			IpVersion::Ipv6,
+			IpProtocol::Icmpv6,
+			RawSocketBuffer::new(&mut icmp_rx_meta[..], &mut icmp_rx[..]),
+			RawSocketBuffer::new(&mut icmp_tx_meta[..], &mut icmp_tx[..]),
+		));
+		info!("IPv6 link-local: {}", link_local);
+
+		let stack = Self {
+			wifi: RefCell::new(wifi),
+			mac,
+			icmp,
+			link_local,
+			global: Cell::new(None),
+			next_local_port: Cell::new(FIRST_LOCAL_PORT),
+		};
+		stack.solicit_router();
+		stack
+	}
+
+	/// Sends and receives what is queued, and keeps the addresses up to date. Has to
+	/// be called regularly, the sockets call it for themselves too.
+	pub fn work(&self) {
+		let mut wifi = self.wifi.borrow_mut();
+		let _ = wifi.poll_dhcp();
+		let iface = wifi.network_interface();
+		// Fails for every packet that isn't for us, nothing to act on.
+		let _ = iface.poll(Instant::from_millis(current_millis() as i64));
+
+		let icmp = iface.get_socket::<RawSocket>(self.icmp);
+		let Some(prefix) = icmp.recv().ok().and_then(slaac_prefix) else { return };
+		let global = with_mac(prefix, self.mac);
+		if self.global.replace(Some(global)) == Some(global) {
+			return;
+		}
+		info!("IPv6 from SLAAC: {}", global);
+		let link_local = self.link_local;
+		iface.update_ip_addrs(|addresses| {
+			addresses[1] = IpCidr::new(IpAddress::Ipv6(global), PREFIX_LEN);
+			addresses[2] = IpCidr::new(IpAddress::Ipv6(link_local), PREFIX_LEN);
+		});
+	}
+
+	/// The address from DHCP, once there is one.
+	pub fn ipv4(&self) -> Option<Ipv4Address> {
+		let mut wifi = self.wifi.borrow_mut();
+		let ip = wifi.network_interface().ipv4_address();
+		ip.filter(|ip| !ip.is_unspecified())
+	}
+
+	/// The address from SLAAC, once the router advertised a prefix.
+	pub fn ipv6(&self) -> Option<Ipv6Address> {
+		self.global.get()
+	}
+
+	pub fn udp_socket<'s>(
+		&'s self,
+		rx_meta: &'a mut [UdpPacketMetadata],
+		rx_buffer: &'a mut [u8],
+		tx_meta: &'a mut [UdpPacketMetadata],
+		tx_buffer: &'a mut [u8],
+	) -> UdpSocket<'s, 'a> {
+		let socket = SmolUdpSocket::new(
+			UdpSocketBuffer::new(rx_meta, rx_buffer),
+			UdpSocketBuffer::new(tx_meta, tx_buffer),
+		);
+		let handle = self
+			.wifi
+			.borrow_mut()
+			.network_interface()
+			.add_socket(socket);
+		UdpSocket {
+			stack: self,
+			handle,
+		}
+	}
+
+	pub fn tcp_socket<'s>(
+		&'s self,
+		rx_buffer: &'a mut [u8],
+		tx_buffer: &'a mut [u8],
+	) -> TcpSocket<'s, 'a> {
+		let mut socket = SmolTcpSocket::new(
+			TcpSocketBuffer::new(rx_buffer),
+			TcpSocketBuffer::new(tx_buffer),
+		);
+		socket.set_timeout(Some(TCP_TIMEOUT));
+		let handle = self
+			.wifi
+			.borrow_mut()
+			.network_interface()
+			.add_socket(socket);
+		TcpSocket {
+			stack: self,
+			handle,
+		}
+	}
+
+	/// Asks the routers to advertise their prefixes now, instead of whenever they
+	/// do that on their own, which can be minutes away.
+	fn solicit_router(&self) {
+		let solicit = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit {
+			lladdr: Some(self.mac.into()),
+		});
+		let header = Ipv6Repr {
+			src_addr: self.link_local,
+			dst_addr: Ipv6Address::LINK_LOCAL_ALL_ROUTERS,
+			next_header: IpProtocol::Icmpv6,
+			payload_len: solicit.buffer_len(),
+			hop_limit: NDISC_HOP_LIMIT,
+		};
+		let mut wifi = self.wifi.borrow_mut();
+		let icmp = wifi.network_interface().get_socket::<RawSocket>(self.icmp);
+		let len = header.buffer_len() + solicit.buffer_len();
+		let Ok(buffer) = icmp.send(len) else { return };
+		let mut packet = Ipv6Packet::new_unchecked(buffer);
+		header.emit(&mut packet);
+		solicit.emit(
+			&IpAddress::Ipv6(header.src_addr),
+			&IpAddress::Ipv6(header.dst_addr),
+			&mut Icmpv6Packet::new_unchecked(packet.payload_mut()),
+			&ChecksumCapabilities::default(),
+		);
+	}
+
+	fn local_port(&self) -> u16 {
+		let port = self.next_local_port.get();
+		self.next_local_port
+			.set(port.checked_add(1).unwrap_or(FIRST_LOCAL_PORT));
+		port
+	}
+}
+
+/// The address with the first half of `prefix`, and the modified EUI-64 of `mac`
+/// as the second half.
+fn with_mac(prefix: Ipv6Address, mac: EthernetAddress) -> Ipv6Address {
+	let mut address = prefix;
+	let mac = mac.as_bytes();
+	address.0[8..].copy_from_slice(&[
+		mac[0] ^ 0x02,
+		mac[1],
+		mac[2],
+		0xff,
+		0xfe,
+		mac[3],
+		mac[4],
+		mac[5],
+	]);
+	address
+}
+
+/// The prefix to make an address from, if `packet` is a router advertisement that
+/// has one.
+fn slaac_prefix(packet: &[u8]) -> Option<Ipv6Address> {
+	let ip = Ipv6Packet::new_checked(packet).ok()?;
+	let icmp = Icmpv6Packet::new_checked(ip.payload()).ok()?;
+	let (src, dst) = (
+		IpAddress::Ipv6(ip.src_addr()),
+		IpAddress::Ipv6(ip.dst_addr()),
+	);
+	if icmp.msg_type() != Icmpv6Message::RouterAdvert
+		|| ip.hop_limit() != NDISC_HOP_LIMIT
+		|| !icmp.verify_checksum(&src, &dst)
+	{
+		return None;
+	}
+	// Not `NdiscRepr::parse`, that rejects options it doesn't know.
+	let mut options = icmp.payload();
+	while let Ok(option) = NdiscOption::new_checked(options) {
+		let len = usize::from(option.data_len()) * 8;
+		if len == 0 {
+			break;
+		}
+		if option.option_type() == NdiscOptionType::PrefixInformation
+			&& option
+				.prefix_flags()
+				.contains(NdiscPrefixInfoFlags::ADDRCONF)
+			&& option.prefix_len() == PREFIX_LEN
+			&& option.valid_lifetime() > Duration::ZERO
+			&& !option.prefix().is_link_local()
+		{
+			return Some(option.prefix());
+		}
+		options = options.get(len..)?;
+	}
+	None
+}
+
+/// A UDP socket of the [`Stack`], for both IPv4 and IPv6.
+pub struct UdpSocket<'s, 'a> {
+	stack: &'s Stack<'a>,
+	handle: SocketHandle,
+}
+impl<'s, 'a> UdpSocket<'s, 'a> {
+	pub fn bind(&mut self, port: u16) -> Result<(), Error> {
+		self.with_socket(|s| s.bind(port))
+	}
+
+	pub fn send(
+		&mut self,
+		addr: IpAddress,
+		port: u16,
+		data: &[u8],
+	) -> Result<(), Error> {
+		self.with_socket(|s| s.send_slice(data, (addr, port).into()))?;
+		self.stack.work();
+		Ok(())
+	}
+
+	/// Sends to everyone on the link, over IPv4 once we have an address for it, and
+	/// over IPv6. Only fails if neither went out.
+	pub fn broadcast(&mut self, port: u16, data: &[u8]) -> Result<(), Error> {
+		let ipv4 = match self.stack.ipv4() {
+			Some(_) => self.send(Ipv4Address::BROADCAST.into(), port, data),
+			None => Err(Error::Unaddressable),
+		};
+		let ipv6 = self.send(Ipv6Address::LINK_LOCAL_ALL_NODES.into(), port, data);
+		ipv4.or(ipv6)
+	}
+
+	/// Returns how many bytes were received, and who from. `Error::Exhausted` means
+	/// that nothing was.
+	pub fn receive(
+		&mut self,
+		buffer: &mut [u8],
+	) -> Result<(usize, IpAddress, u16), Error> {
+		self.stack.work();
+		let (len, from) = self.with_socket(|s| s.recv_slice(buffer))?;
+		Ok((len, from.addr, from.port))
+	}
+
+	fn with_socket<R>(&mut self, f: impl FnOnce(&mut SmolUdpSocket<'a>) -> R) -> R {
+		let mut wifi = self.stack.wifi.borrow_mut();
+		f(wifi.network_interface().get_socket(self.handle))
+	}
+}
+impl Drop for UdpSocket<'_, '_> {
+	fn drop(&mut self) {
+		let mut wifi = self.stack.wifi.borrow_mut();
+		wifi.network_interface().remove_socket(self.handle);
+	}
+}
+
+/// A TCP socket of the [`Stack`]. Everything blocks until it is done, or the other
+/// side went quiet for [`TCP_TIMEOUT`].
+pub struct TcpSocket<'s, 'a> {
+	stack: &'s Stack<'a>,
+	handle: SocketHandle,
+}
+impl<'s, 'a> TcpSocket<'s, 'a> {
+	pub fn open(&mut self, addr: IpAddress, port: u16) -> Result<(), Error> {
+		let local_port = self.stack.local_port();
+		let mut wifi = self.stack.wifi.borrow_mut();
+		let (socket, cx) = wifi
+			.network_interface()
+			.get_socket_and_context::<SmolTcpSocket>(self.handle);
+		socket.connect(cx, (addr, port), local_port)?;
+		drop(wifi);
+		loop {
+			self.stack.work();
+			match self.with_socket(|s| s.state()) {
+				TcpState::Established => return Ok(()),
+				TcpState::Closed => return Err(Error::Illegal),
+				_ => (),
+			}
+		}
+	}
+
+	pub fn write_all(&mut self, mut data: &[u8]) -> Result<(), Error> {
+		while !data.is_empty() {
+			let sent = self.with_socket(|s| match s.may_send() {
+				true => s.send_slice(data),
+				false => Err(Error::Finished),
+			})?;
+			data = &data[sent..];
+			self.stack.work();
+		}
+		Ok(())
+	}
+
+	/// Waits until the other side received everything.
+	pub fn flush(&mut self) -> Result<(), Error> {
+		loop {
+			self.stack.work();
+			match self.with_socket(|s| (s.send_queue(), s.may_send())) {
+				(0, _) => return Ok(()),
+				(_, false) => return Err(Error::Finished),
+				_ => (),
+			}
+		}
+	}
+
+	/// Waits for data. Returns `0` once the other side closed the connection and
+	/// everything was read.
+	pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, Error> {
+		loop {
+			self.stack.work();
+			let read = self.with_socket(|s| match (s.can_recv(), s.may_recv()) {
+				(true, _) => s.recv_slice(buffer).map(Some),
+				(false, false) => Ok(Some(0)),
+				(false, true) => Ok(None),
+			})?;
+			if let Some(len) = read {
+				return Ok(len);
+			}
+		}
+	}
+
+	/// Closes our side, and waits until the other side knows.
+	pub fn disconnect(&mut self) {
+		self.with_socket(|s| s.close());
+		loop {
+			self.stack.work();
+			let state = self.with_socket(|s| s.state());
+			if matches!(state, TcpState::Closed | TcpState::TimeWait) {
+				return;
+			}
+		}
+	}
+
+	fn with_socket<R>(&mut self, f: impl FnOnce(&mut SmolTcpSocket<'a>) -> R) -> R {
+		let mut wifi = self.stack.wifi.borrow_mut();
+		f(wifi.network_interface().get_socket(self.handle))
+	}
+}
+impl Drop for TcpSocket<'_, '_> {
+	fn drop(&mut self) {
+		let mut wifi = self.stack.wifi.borrow_mut();
+		wifi.network_interface().remove_socket(self.handle);
+	}
+}
@@ -0,0 +1,43 @@
+//! Instrumentation for how long it takes to get rotations flowing to the server
+//! after (re)connecting, so that we can tell when we miss the resume budget.
+
+use defmt::{info, warn};
+use embassy_time::{Duration, Instant};
+
+/// How quickly we aim to resume streaming rotations after a reconnect.
+pub const RESUME_BUDGET: Duration = Duration::from_secs(2);
+
+/// Measures the time since the start of a connection attempt.
+pub struct ResumeTimer {
+	start: Instant,
+}
+impl ResumeTimer {
+	pub fn start() -> Self {
+		Self {
+			start: Instant::now(),
+		}
+	}
+
+	/// Logs how long it took to reach `milestone`.
+	pub fn milestone(&self, milestone: &str) {
+		info!(
+			"resume: {} after {}ms",
+			milestone,
+			self.start.elapsed().as_millis()
+		);
+	}
+
+	/// Call once the first packet was sent to the server.
+	pub fn finish(self) {
+		let elapsed = self.start.elapsed();
+		if elapsed > RESUME_BUDGET {
+			warn!(
+				"resume: took {}ms, over the budget of {}ms",
+				elapsed.as_millis(),
+				RESUME_BUDGET.as_millis()
+			);
+		} else {
+			info!("resume: streaming after {}ms", elapsed.as_millis());
+		}
+	}
+}
@@ -0,0 +1,522 @@
+//! Updates the firmware over the air on the ESP32 family, with the `ota` feature.
+//!
+//! This needs the partition table in `partitions_ota.csv`, which has two app slots.
+//! The new image is written to the slot we aren't running from, a sector at a time,
+//! and checked against its CRC by reading it back. Then the bootloader is told to
+//! boot the new slot, through the two entries of the `otadata` partition, which
+//! are laid out like ESP-IDF's `esp_ota_select_entry_t`.
+//!
+//! The new firmware starts out unconfirmed, and confirms itself once it connected
+//! to the server. Until then it counts its boots in the label of its entry, which
+//! the bootloader ignores, see [`check_boot`]. Once it booted [`MAX_BOOTS`] times
+//! without confirming itself, it marks its entry as aborted and reboots, so that
+//! the bootloader goes back to the old slot. That doesn't need a bootloader built
+//! with `CONFIG_BOOTLOADER_APP_ROLLBACK_ENABLE`, which the one of `espflash` isn't.
+//! Firmware that hangs instead of resetting reboots itself after
+//! [`CONFIRM_TIMEOUT`], see [`confirm_task`].
+//!
+//! The server either pushes the image in chunks, or offers it for the Wi-Fi network
+//! task to download, see [`check`].
+//!
+//! Built with an `OTA_PUBLIC_KEY`, we only switch to images that are signed with
+//! its private key, so that nobody else on the network can install firmware. The
+//! signature comes after the image, see `OTA_SIGNATURE_LEN`, and is kept in RAM
+//! instead of being written to the slot, so that the slot holds the image exactly as
+//! it was built. That is what ESP secure boot checks its own signature on.
+
+// The sector that is being written is buffered on the heap, which is smaller than a
+// sector with `constrained`.
+#[cfg(feature = "constrained")]
+compile_error!("`ota` needs more RAM than `constrained` leaves");
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use core::cell::RefCell;
+use defmt::{debug, info, warn, Debug2Format, Format};
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embassy_time::{Duration, Timer};
+use embedded_storage::{ReadStorage, Storage};
+use esp_storage::FlashStorage;
+use firmware_protocol::{
+	crc32_continue, BoardType, McuType, SbPacket, OTA_BAD_IMAGE, OTA_FAILED,
+	OTA_INCOMPATIBLE, OTA_OK, OTA_SIGNATURE_LEN, OTA_UP_TO_DATE,
+};
+#[cfg(ota_signed)]
+use sha2::{Digest, Sha256};
+
+use crate::networking::protocol::{BOARD, MCU};
+
+/// How long to wait after a finished update before rebooting into it, so that the
+/// status still goes out.
+pub const REBOOT_DELAY: Duration = Duration::from_millis(500);
+
+/// How often unconfirmed firmware may boot before we go back to the old one. More
+/// than once, so that a reset from a sagging battery doesn't throw an update away.
+pub const MAX_BOOTS: u8 = 2;
+/// How long unconfirmed firmware may run without reaching the server, before it
+/// reboots, which counts as a failed boot.
+pub const CONFIRM_TIMEOUT: Duration = Duration::from_secs(180);
+
+/// Where `partitions_ota.csv` puts the `otadata` partition, which holds one entry in
+/// each of its two sectors.
+const OTADATA: u32 = 0x10000;
+const SECTOR_SIZE: usize = 0x1000;
+/// Where the `ota_0` and `ota_1` partitions start.
+const SLOTS: [u32; 2] = [0x20000, 0x200000];
+const SLOT_SIZE: u32 = 0x1E0000;
+/// Every app image starts with this byte.
+const IMAGE_MAGIC: u8 = 0xE9;
+/// Read back in pieces of this size, to check the CRC.
+const VERIFY_CHUNK: usize = 256;
+/// What follows the image in the update, and isn't written to the slot.
+const TRAILER_LEN: usize = if cfg!(ota_signed) {
+	OTA_SIGNATURE_LEN
+} else {
+	0
+};
+
+/// The states of an otadata entry, from ESP-IDF's `esp_ota_img_states_t`.
+mod state {
+	/// Written along with the entry, the bootloader hasn't booted it yet.
+	pub const NEW: u32 = 0;
+	/// Booted once, and not confirmed yet.
+	pub const PENDING_VERIFY: u32 = 1;
+	pub const VALID: u32 = 2;
+	pub const INVALID: u32 = 3;
+	/// Rolled back, because it reset before it was confirmed.
+	pub const ABORTED: u32 = 4;
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+enum Error {
+	Flash,
+	/// Not an app image, or it didn't match its size or CRC.
+	BadImage,
+	/// Not signed with our key.
+	#[cfg_attr(not(ota_signed), allow(dead_code))]
+	Unsigned,
+}
+impl Error {
+	fn status(self) -> u8 {
+		match self {
+			Self::Flash => OTA_FAILED,
+			Self::BadImage => OTA_BAD_IMAGE,
+			Self::Unsigned => firmware_protocol::OTA_UNSIGNED,
+		}
+	}
+}
+
+/// One of the two entries in otadata. The bootloader boots the slot of the valid
+/// entry with the highest sequence number, or the first slot if there is none.
+#[derive(Clone, Copy)]
+struct Entry {
+	seq: u32,
+	state: u32,
+	/// How often it booted without being confirmed, in the first byte of the label.
+	/// Counted down from `0xFF`, so that an empty label is no boots.
+	boots: u8,
+}
+impl Entry {
+	/// The sequence number, a label that only holds `boots`, the state, and the CRC
+	/// of the sequence number.
+	const LEN: usize = 4 + 20 + 4 + 4;
+
+	/// Returns `None` if the entry is empty or corrupt.
+	fn read(index: usize) -> Option<Self> {
+		let mut bytes = [0; Self::LEN];
+		FlashStorage::new()
+			.read(Self::address(index), &mut bytes)
+			.ok()?;
+		let word = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+		let (seq, state, crc) = (word(0), word(24), word(28));
+		let boots = 0xFF - bytes[4];
+		(seq != u32::MAX && crc == Self::crc(seq)).then_some(Self { seq, state, boots })
+	}
+
+	fn write(self, index: usize) -> Result<(), Error> {
+		let mut bytes = [0xFF; Self::LEN];
+		bytes[0..4].copy_from_slice(&self.seq.to_le_bytes());
+		bytes[4] = 0xFF - self.boots;
+		bytes[24..28].copy_from_slice(&self.state.to_le_bytes());
+		bytes[28..32].copy_from_slice(&Self::crc(self.seq).to_le_bytes());
+		// Erases the rest of the sector, which is unused.
+		FlashStorage::new()
+			.write(Self::address(index), &bytes)
+			.map_err(|_| Error::Flash)
+	}
+
+	fn address(index: usize) -> u32 {
+		OTADATA + (index * SECTOR_SIZE) as u32
+	}
+
+	/// What the bootloader computes with `esp_rom_crc32_le(UINT32_MAX, ...)`.
+	fn crc(seq: u32) -> u32 {
+		crc32_continue(u32::MAX, &seq.to_le_bytes())
+	}
+
+	fn slot(self) -> usize {
+		(self.seq.wrapping_sub(1) % 2) as usize
+	}
+
+	fn bootable(self) -> bool {
+		!matches!(self.state, state::INVALID | state::ABORTED)
+	}
+
+	fn confirmed(self) -> bool {
+		!matches!(self.state, state::NEW | state::PENDING_VERIFY)
+	}
+}
+
+/// The entry that the bootloader boots from, and its index.
+fn active_entry() -> Option<(usize, Entry)> {
+	(0..2)
+		.filter_map(|i| Some((i, Entry::read(i)?)))
+		.filter(|(_, e)| e.bootable())
+		.max_by_key(|(_, e)| e.seq)
+}
+
+struct Update {
+	/// Of the image and the trailer.
+	size: u32,
+	crc32: u32,
+	slot: usize,
+	/// How much of the update was received.
+	received: u32,
+	/// The sector being received, which is written once it is full.
+	sector: Box<[u8]>,
+	/// The signature after the image.
+	trailer: [u8; TRAILER_LEN],
+}
+impl Update {
+	/// How much of the update goes into the slot.
+	fn image_len(&self) -> u32 {
+		self.size - TRAILER_LEN as u32
+	}
+
+	fn push(&mut self, mut data: &[u8]) -> Result<(), Error> {
+		let image_len = self.image_len();
+		while !data.is_empty() {
+			if self.received >= image_len {
+				// `write` checked that the update isn't longer than announced.
+				let start = (self.received - image_len) as usize;
+				self.trailer[start..start + data.len()].copy_from_slice(data);
+				self.received += data.len() as u32;
+				return Ok(());
+			}
+			let start = self.received as usize % SECTOR_SIZE;
+			let len = data
+				.len()
+				.min(SECTOR_SIZE - start)
+				.min((image_len - self.received) as usize);
+			self.sector[start..start + len].copy_from_slice(&data[..len]);
+			self.received += len as u32;
+			data = &data[len..];
+			if self.received as usize % SECTOR_SIZE == 0 || self.received == image_len {
+				self.flush()?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Writes the sector being received, up to what was received of it.
+	fn flush(&mut self) -> Result<(), Error> {
+		let len = match self.received as usize % SECTOR_SIZE {
+			0 => SECTOR_SIZE,
+			len => len,
+		};
+		let start = self.received - len as u32;
+		FlashStorage::new()
+			.write(SLOTS[self.slot] + start, &self.sector[..len])
+			.map_err(|_| Error::Flash)
+	}
+
+	/// Reads the image back, to check that it made it into flash intact, and that
+	/// it is signed.
+	fn verify(&self) -> Result<(), Error> {
+		let mut flash = FlashStorage::new();
+		let mut buf = [0; VERIFY_CHUNK];
+		let mut crc = 0;
+		#[cfg(ota_signed)]
+		let mut hash = Sha256::new();
+		let image_len = self.image_len();
+		for offset in (0..image_len).step_by(VERIFY_CHUNK) {
+			let len = (image_len - offset).min(VERIFY_CHUNK as u32) as usize;
+			flash
+				.read(SLOTS[self.slot] + offset, &mut buf[..len])
+				.map_err(|_| Error::Flash)?;
+			crc = crc32_continue(crc, &buf[..len]);
+			#[cfg(ota_signed)]
+			hash.update(&buf[..len]);
+		}
+		let crc = crc32_continue(crc, &self.trailer);
+		if crc != self.crc32 {
+			warn!("OTA image has CRC {:#x}, expected {:#x}", crc, self.crc32);
+			return Err(Error::BadImage);
+		}
+		#[cfg(ota_signed)]
+		signature::verify(&self.trailer, &hash.finalize().into())?;
+		Ok(())
+	}
+
+	/// Tells the bootloader to boot the new slot from now on.
+	fn select(&self) -> Result<(), Error> {
+		let active = active_entry();
+		let mut seq = active.map_or(0, |(_, e)| e.seq) + 1;
+		let entry = loop {
+			let entry = Entry {
+				seq,
+				state: state::NEW,
+				boots: 0,
+			};
+			if entry.slot() == self.slot {
+				break entry;
+			}
+			seq += 1;
+		};
+		// Keeps the entry of the running firmware, in case the new one rolls back.
+		let index = active.map_or(0, |(i, _)| 1 - i);
+		entry.write(index)
+	}
+}
+
+/// Only taken out while it is used, so that writing flash doesn't happen inside of
+/// a critical section.
+static UPDATE: Mutex<CriticalSectionRawMutex, RefCell<Option<Update>>> =
+	Mutex::new(RefCell::new(None));
+
+fn take() -> Option<Update> {
+	UPDATE.lock(|u| u.borrow_mut().take())
+}
+
+fn keep(update: Update) {
+	UPDATE.lock(|u| *u.borrow_mut() = Some(update));
+}
+
+fn status(offset: u32, status: u8) -> SbPacket {
+	SbPacket::OtaStatus { offset, status }
+}
+
+/// Whether to take the firmware `version` that the server offered for `mcu` and
+/// `board`. Otherwise returns the status to answer with. Older versions are taken
+/// too, the server knows best when to roll back.
+pub fn check(mcu: &McuType, board: &BoardType, version: &str) -> Result<(), u8> {
+	if *mcu != MCU || *board != BOARD {
+		warn!(
+			"Offered firmware for {}, {}",
+			Debug2Format(mcu),
+			Debug2Format(board)
+		);
+		return Err(OTA_INCOMPATIBLE);
+	}
+	if version == env!("CARGO_PKG_VERSION") {
+		info!("Offered firmware {}, which we already run", version);
+		return Err(OTA_UP_TO_DATE);
+	}
+	Ok(())
+}
+
+/// Starts receiving an image of `size` bytes into the slot we aren't running from.
+pub fn begin(size: u32, crc32: u32) -> SbPacket {
+	drop(take());
+	if size <= TRAILER_LEN as u32 || size - TRAILER_LEN as u32 > SLOT_SIZE {
+		warn!("OTA image of {} bytes doesn't fit into a slot", size);
+		return status(0, OTA_BAD_IMAGE);
+	}
+	let running = active_entry().map_or(0, |(_, e)| e.slot());
+	let slot = 1 - running;
+	info!("Starting OTA update of {} bytes into slot {}", size, slot);
+	keep(Update {
+		size,
+		crc32,
+		slot,
+		received: 0,
+		sector: alloc::vec![0xFF; SECTOR_SIZE].into_boxed_slice(),
+		trailer: [0; TRAILER_LEN],
+	});
+	status(0, OTA_OK)
+}
+
+/// Takes the chunk of the image at `offset`.
+pub fn write(offset: u32, data: &[u8]) -> SbPacket {
+	let Some(mut update) = take() else {
+		warn!("Got OTA data without an update in progress");
+		return status(0, OTA_FAILED);
+	};
+	let received = update.received;
+	// A chunk that we already have, because its status got lost, or one after a
+	// chunk that got lost. Either way, the sender continues from what we have.
+	if offset != received {
+		debug!("Got OTA chunk at {}, expected {}", offset, received);
+		keep(update);
+		return status(received, OTA_OK);
+	}
+	if offset == 0 && data.first() != Some(&IMAGE_MAGIC) {
+		warn!("OTA image isn't an app image");
+		return status(0, OTA_BAD_IMAGE);
+	}
+	if received as usize + data.len() > update.size as usize {
+		warn!("OTA image is longer than announced");
+		return status(received, OTA_BAD_IMAGE);
+	}
+	match update.push(data) {
+		Ok(()) => {
+			let received = update.received;
+			keep(update);
+			status(received, OTA_OK)
+		}
+		Err(e) => {
+			warn!("Failed to write OTA image: {}", e);
+			status(received, e.status())
+		}
+	}
+}
+
+/// Checks the image and switches to its slot. Returns the status, and whether to
+/// reboot into the new firmware once it was sent.
+pub fn finish() -> (SbPacket, bool) {
+	let Some(mut update) = take() else {
+		warn!("Got the end of an OTA update without one in progress");
+		return (status(0, OTA_FAILED), false);
+	};
+	let result = if update.received != update.size {
+		warn!(
+			"OTA image ended after {} of {} bytes",
+			update.received, update.size
+		);
+		Err(Error::BadImage)
+	} else {
+		// The last sector was written once the image was complete.
+		update.verify().and_then(|()| update.select())
+	};
+	match result {
+		Ok(()) => {
+			info!("Finished OTA update into slot {}", update.slot);
+			(status(update.received, OTA_OK), true)
+		}
+		Err(e) => {
+			warn!("OTA update failed: {}", e);
+			(status(update.received, e.status()), false)
+		}
+	}
+}
+
+/// Confirms that the running firmware works, if it was just updated, so that
+/// [`check_boot`] doesn't roll it back.
+pub fn confirm() {
+	let Some((index, entry)) = active_entry() else {
+		return;
+	};
+	if entry.confirmed() {
+		return;
+	}
+	let entry = Entry {
+		state: state::VALID,
+		..entry
+	};
+	match entry.write(index) {
+		Ok(()) => info!("Confirmed the updated firmware"),
+		Err(e) => warn!("Failed to confirm the updated firmware: {}", e),
+	}
+}
+
+/// Counts the boot if the running firmware wasn't confirmed yet, and goes back to
+/// the old firmware once it booted too often. Returns whether it is unconfirmed, so
+/// that [`confirm_task`] has to watch over it.
+pub fn check_boot() -> bool {
+	let Some((index, entry)) = active_entry() else {
+		return false;
+	};
+	if entry.confirmed() {
+		return false;
+	}
+	if entry.boots < MAX_BOOTS {
+		let entry = Entry {
+			boots: entry.boots + 1,
+			..entry
+		};
+		info!(
+			"Booting updated firmware, {} of {} tries",
+			entry.boots, MAX_BOOTS
+		);
+		if let Err(e) = entry.write(index) {
+			warn!("Failed to count the boot of the updated firmware: {}", e);
+		}
+		return true;
+	}
+	// The bootloader boots the first slot if no entry is left, which might be this
+	// one, so we would only lose the count.
+	let Some(old) = Entry::read(1 - index).filter(|e| e.bootable()) else {
+		warn!("Updated firmware never reached the server, and there is no old one");
+		return true;
+	};
+	let entry = Entry {
+		state: state::ABORTED,
+		..entry
+	};
+	match entry.write(index) {
+		Ok(()) => {
+			warn!(
+				"Updated firmware never reached the server, going back to slot {}",
+				old.slot()
+			);
+			crate::peripherals::reboot()
+		}
+		Err(e) => {
+			warn!("Failed to roll back the updated firmware: {}", e);
+			true
+		}
+	}
+}
+
+/// Reboots unconfirmed firmware that didn't reach the server in time, in case it
+/// hangs somewhere that doesn't reset, like while connecting.
+#[embassy_executor::task]
+pub async fn confirm_task() {
+	Timer::after(CONFIRM_TIMEOUT).await;
+	if active_entry().map_or(true, |(_, e)| e.confirmed()) {
+		return;
+	}
+	warn!("Updated firmware didn't reach the server in time, rebooting");
+	crate::peripherals::reboot()
+}
+
+/// Checks the signature after updates, with the public key that we were built with.
+#[cfg(ota_signed)]
+mod signature {
+	use super::Error;
+	use defmt::warn;
+	use ed25519_dalek::{Signature, VerifyingKey};
+	use firmware_protocol::{OTA_SIGNATURE_LEN, OTA_SIGNATURE_MAGIC};
+
+	/// Checked by the build script to be 32 bytes in hex.
+	fn public_key() -> [u8; 32] {
+		let hex = env!("OTA_PUBLIC_KEY").as_bytes();
+		let digit = |i: usize| (hex[i] as char).to_digit(16).unwrap() as u8;
+		core::array::from_fn(|i| (digit(2 * i) << 4) | digit(2 * i + 1))
+	}
+
+	/// Checks that `trailer` signs the image with the SHA-256 `hash`.
+	pub fn verify(
+		trailer: &[u8; OTA_SIGNATURE_LEN],
+		hash: &[u8; 32],
+	) -> Result<(), Error> {
+		let (magic, signature) = trailer.split_at(OTA_SIGNATURE_MAGIC.len());
+		if magic != OTA_SIGNATURE_MAGIC {
+			warn!("OTA image isn't signed");
+			return Err(Error::Unsigned);
+		}
+		let key =
+			VerifyingKey::from_bytes(&public_key()).map_err(|_| Error::Unsigned)?;
+		let signature = Signature::from_bytes(signature.try_into().unwrap());
+		key.verify_strict(hash, &signature).map_err(|_| {
+			warn!("OTA image isn't signed with our key");
+			Error::Unsigned
+		})
+	}
+}
+
+pub fn reboot() -> ! {
+	info!("Rebooting into the new firmware");
+	crate::peripherals::reboot()
+}
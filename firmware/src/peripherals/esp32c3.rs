@@ -1,6 +1,16 @@
-use super::Peripherals;
+#[cfg(any(
+	battery,
+	button,
+	led,
+	spi_imu,
+	all(antenna_switch, feature = "net-wifi")
+))]
+extern crate alloc;
+
+use super::{BatteryConcrete, ButtonConcrete, LedConcrete, Peripherals};
+use crate::aliases::ImuBusConcrete;
 use crate::aliases::ඞ::DelayConcrete;
-use crate::aliases::ඞ::I2cConcrete;
+use crate::networking::Radio;
 
 use fugit::RateExtU32;
 use paste::paste;
@@ -20,7 +30,16 @@ macro_rules! map_pin {
 	};
 }
 
-pub fn get_peripherals() -> Peripherals<I2cConcrete<'static>, DelayConcrete> {
+pub fn get_peripherals() -> Peripherals<
+	ImuBusConcrete<'static>,
+	DelayConcrete,
+	(),
+	(),
+	ButtonConcrete,
+	BatteryConcrete,
+	Radio,
+	LedConcrete,
+> {
 	let p = esp32c3_hal::pac::Peripherals::take().unwrap();
 
 	let mut system = p.SYSTEM.split();
@@ -63,15 +82,91 @@ pub fn get_peripherals() -> Peripherals<I2cConcrete<'static>, DelayConcrete> {
 	}
 
 	let io = esp32c3_hal::IO::new(p.GPIO, p.IO_MUX);
-	let i2c = esp32c3_hal::i2c::I2C::new(
+	#[cfg(feature = "net-wifi")]
+	let radio = {
+		#[cfg(antenna_switch)]
+		let switch: Option<crate::networking::wifi::antenna::Switch> =
+			Some(alloc::boxed::Box::new(
+				map_pin!(io, env!("PIN_ANT_SWITCH")).into_push_pull_output(),
+			));
+		#[cfg(not(antenna_switch))]
+		let switch = None;
+		Radio::new(switch)
+	};
+	#[cfg(not(feature = "net-wifi"))]
+	let radio = Radio::new();
+	#[cfg(button)]
+	let button: ButtonConcrete =
+		alloc::boxed::Box::new(map_pin!(io, env!("PIN_BUTTON")).into_pull_up_input());
+	#[cfg(battery)]
+	let battery: BatteryConcrete = {
+		use esp32c3_hal::adc::{AdcConfig, Attenuation, ADC, ADC1};
+		let analog = p.APB_SARADC.split();
+		let mut config = AdcConfig::new();
+		let mut pin = config.enable_pin(
+			map_pin!(io, env!("PIN_BATTERY")).into_analog(),
+			Attenuation::Attenuation11dB,
+		);
+		let mut adc =
+			ADC::<ADC1>::adc(&mut system.peripheral_clock_control, analog.adc1, config)
+				.unwrap();
+		alloc::boxed::Box::new(move || nb::block!(adc.read(&mut pin)).ok())
+	};
+	#[cfg(all(led, not(led_ws2812)))]
+	let led: LedConcrete = crate::led::Led::new(alloc::boxed::Box::new(
+		map_pin!(io, env!("PIN_LED")).into_push_pull_output(),
+	));
+	#[cfg(led_ws2812)]
+	let led: LedConcrete = {
+		use embedded_hal::blocking::spi::Write;
+		use esp32c3_hal::spi::{Spi, SpiMode};
+		let mut spi = Spi::new_mosi_only(
+			p.SPI2,
+			map_pin!(io, env!("PIN_LED")),
+			crate::led::SPI_FREQ_KHZ.kHz(),
+			SpiMode::Mode0,
+			&mut system.peripheral_clock_control,
+			&clocks,
+		);
+		// Nothing to do about a failed write, the next color comes soon.
+		crate::led::Led::new(alloc::boxed::Box::new(move |bytes: &[u8]| {
+			let _ = spi.write(bytes);
+		}))
+	};
+	#[cfg(not(spi_imu))]
+	let imu_bus = esp32c3_hal::i2c::I2C::new(
 		p.I2C0,
 		map_pin!(io, env!("PIN_SDA")),
 		map_pin!(io, env!("PIN_SCL")),
-		400u32.kHz(),
+		super::I2C_FREQ_KHZ.kHz(),
 		&mut system.peripheral_clock_control,
 		&clocks,
 	);
+	#[cfg(spi_imu)]
+	let imu_bus = {
+		use esp32c3_hal::spi::{Spi, SpiMode};
+		let spi = Spi::new_no_cs(
+			p.SPI2,
+			map_pin!(io, env!("PIN_SPI_SCK")),
+			map_pin!(io, env!("PIN_SPI_MOSI")),
+			map_pin!(io, env!("PIN_SPI_MISO")),
+			super::SPI_FREQ_KHZ.kHz(),
+			SpiMode::Mode0,
+			&mut system.peripheral_clock_control,
+			&clocks,
+		);
+		// Driven by hand, since each register access takes more than one transfer.
+		let cs = map_pin!(io, env!("PIN_SPI_CS")).into_push_pull_output();
+		crate::imu::regs::SpiRegisters::new(spi, alloc::boxed::Box::new(cs))
+	};
 
 	let delay = esp32c3_hal::Delay::new(&clocks);
-	Peripherals::new().i2c(i2c).delay(delay)
+	let p = Peripherals::new().imu_bus(imu_bus).delay(delay);
+	#[cfg(button)]
+	let p = p.button(button);
+	#[cfg(battery)]
+	let p = p.battery(battery);
+	#[cfg(led)]
+	let p = p.led(led);
+	p.radio(radio)
 }
@@ -0,0 +1,161 @@
+#[cfg(any(
+	battery,
+	button,
+	led,
+	spi_imu,
+	all(antenna_switch, feature = "net-wifi")
+))]
+extern crate alloc;
+
+use super::{BatteryConcrete, ButtonConcrete, LedConcrete, Peripherals};
+use crate::aliases::ImuBusConcrete;
+use crate::aliases::ඞ::DelayConcrete;
+use crate::networking::Radio;
+
+use fugit::RateExtU32;
+use paste::paste;
+
+use esp32s3_hal::{
+	clock::{ClockControl, CpuClock},
+	pac,
+	prelude::*,
+	timer::TimerGroup,
+	Rtc,
+};
+
+macro_rules! map_pin {
+	($io: ident, $pin: expr) => {
+		paste! {
+			$io.pins.[<gpio $pin>]
+		}
+	};
+}
+
+pub fn get_peripherals() -> Peripherals<
+	ImuBusConcrete<'static>,
+	DelayConcrete,
+	(),
+	(),
+	ButtonConcrete,
+	BatteryConcrete,
+	Radio,
+	LedConcrete,
+> {
+	let p = pac::Peripherals::take().unwrap();
+
+	let mut system = p.SYSTEM.split();
+	// The ESP-Wifi module requires 240MHz for cpu clock speeed
+	let clocks =
+		ClockControl::configure(system.clock_control, CpuClock::Clock240MHz).freeze();
+
+	// Disable the RTC and TIMG watchdog timers
+	let timer0 = {
+		let mut rtc = Rtc::new(p.RTC_CNTL);
+		let timer_group0 = TimerGroup::new(p.TIMG0, &clocks);
+		let mut wdt0 = timer_group0.wdt;
+
+		rtc.rwdt.disable();
+		wdt0.disable();
+
+		timer_group0.timer0
+	};
+
+	// Initialize embassy
+	esp32s3_hal::embassy::init(&clocks, timer0);
+
+	// Initialize esp-wifi stuff
+	#[cfg(feature = "esp-wifi")]
+	{
+		esp_wifi::init_heap();
+		let timerg = TimerGroup::new(p.TIMG1, &clocks);
+		esp_wifi::initialize(timerg.timer0, p.RNG, &clocks)
+			.expect("failed to initialize esp-wifi");
+	}
+
+	let io = esp32s3_hal::IO::new(p.GPIO, p.IO_MUX);
+	#[cfg(feature = "net-wifi")]
+	let radio = {
+		#[cfg(antenna_switch)]
+		let switch: Option<crate::networking::wifi::antenna::Switch> =
+			Some(alloc::boxed::Box::new(
+				map_pin!(io, env!("PIN_ANT_SWITCH")).into_push_pull_output(),
+			));
+		#[cfg(not(antenna_switch))]
+		let switch = None;
+		Radio::new(switch)
+	};
+	#[cfg(not(feature = "net-wifi"))]
+	let radio = Radio::new();
+	#[cfg(button)]
+	let button: ButtonConcrete =
+		alloc::boxed::Box::new(map_pin!(io, env!("PIN_BUTTON")).into_pull_up_input());
+	#[cfg(battery)]
+	let battery: BatteryConcrete = {
+		use esp32s3_hal::adc::{AdcConfig, Attenuation, ADC, ADC1};
+		let analog = p.SENS.split();
+		let mut config = AdcConfig::new();
+		let mut pin = config.enable_pin(
+			map_pin!(io, env!("PIN_BATTERY")).into_analog(),
+			Attenuation::Attenuation11dB,
+		);
+		let mut adc = ADC::<ADC1>::adc(analog.adc1, config).unwrap();
+		alloc::boxed::Box::new(move || nb::block!(adc.read(&mut pin)).ok())
+	};
+	#[cfg(all(led, not(led_ws2812)))]
+	let led: LedConcrete = crate::led::Led::new(alloc::boxed::Box::new(
+		map_pin!(io, env!("PIN_LED")).into_push_pull_output(),
+	));
+	#[cfg(led_ws2812)]
+	let led: LedConcrete = {
+		use embedded_hal::blocking::spi::Write;
+		use esp32s3_hal::spi::{Spi, SpiMode};
+		let mut spi = Spi::new_mosi_only(
+			p.SPI2,
+			map_pin!(io, env!("PIN_LED")),
+			crate::led::SPI_FREQ_KHZ.kHz(),
+			SpiMode::Mode0,
+			&mut system.peripheral_clock_control,
+			&clocks,
+		);
+		// Nothing to do about a failed write, the next color comes soon.
+		crate::led::Led::new(alloc::boxed::Box::new(move |bytes: &[u8]| {
+			let _ = spi.write(bytes);
+		}))
+	};
+	#[cfg(not(spi_imu))]
+	let imu_bus = esp32s3_hal::i2c::I2C::new(
+		p.I2C0,
+		map_pin!(io, env!("PIN_SDA")),
+		map_pin!(io, env!("PIN_SCL")),
+		super::I2C_FREQ_KHZ.kHz(),
+		&mut system.peripheral_clock_control,
+		&clocks,
+	);
+	#[cfg(spi_imu)]
+	let imu_bus = {
+		use esp32s3_hal::spi::{Spi, SpiMode};
+		let spi = Spi::new_no_cs(
+			p.SPI2,
+			map_pin!(io, env!("PIN_SPI_SCK")),
+			map_pin!(io, env!("PIN_SPI_MOSI")),
+			map_pin!(io, env!("PIN_SPI_MISO")),
+			super::SPI_FREQ_KHZ.kHz(),
+			SpiMode::Mode0,
+			&mut system.peripheral_clock_control,
+			&clocks,
+		);
+		// Driven by hand, since each register access takes more than one transfer.
+		let cs = map_pin!(io, env!("PIN_SPI_CS")).into_push_pull_output();
+		crate::imu::regs::SpiRegisters::new(spi, alloc::boxed::Box::new(cs))
+	};
+
+	let delay = esp32s3_hal::Delay::new(&clocks);
+	let p = Peripherals::new().imu_bus(imu_bus).delay(delay);
+	#[cfg(button)]
+	let p = p.button(button);
+	#[cfg(battery)]
+	let p = p.battery(battery);
+	#[cfg(led)]
+	let p = p.led(led);
+	p.radio(radio)
+}
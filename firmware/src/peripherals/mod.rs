@@ -8,97 +8,354 @@ pub mod ඞ;
 #[path = "esp32c3.rs"]
 pub mod ඞ;
 
+#[cfg(feature = "mcu-esp32s3")]
+#[path = "esp32s3.rs"]
+pub mod ඞ;
+
 #[cfg(mcu_f_nrf52)]
 #[path = "nrf52.rs"]
 pub mod ඞ;
 
+/// The I2C bus frequency in kHz. The BNO055 stretches the clock for longer than fast
+/// mode tolerates, so it gets standard mode.
+#[allow(dead_code)]
+pub const I2C_FREQ_KHZ: u32 = if cfg!(feature = "imu-bno055") {
+	100
+} else {
+	400
+};
+
+/// The SPI clock in kHz, on boards with the imu on SPI. Set with `[spi]` in the
+/// board config.
+#[cfg(spi_imu)]
+pub const SPI_FREQ_KHZ: u32 = crate::utils::parse_u32(env!("SPI_FREQ_KHZ"));
+
+/// The button, on boards that have one.
+#[cfg(button)]
+pub type ButtonConcrete = crate::button::Button;
+#[cfg(not(button))]
+pub type ButtonConcrete = ();
+
+/// The ADC that measures the battery, on boards that have one.
+#[cfg(battery)]
+pub type BatteryConcrete = crate::battery::Adc;
+#[cfg(not(battery))]
+pub type BatteryConcrete = ();
+
+/// The status LED, on boards that have one.
+#[cfg(led)]
+pub type LedConcrete = crate::led::Led;
+#[cfg(not(led))]
+pub type LedConcrete = ();
+
+/// The temperature sensor of the MCU, on the chips that we can read it on.
+pub type TempConcrete = crate::thermal::Thermometer;
+
+/// Sets this chip apart from every other one, and survives reflashing: the MAC
+/// address that the factory burned into the eFuses of the ESPs, or the device
+/// address in the FICR of the nRFs.
+pub fn chip_id() -> [u8; 6] {
+	#[cfg(feature = "mcu-esp32")]
+	return esp32_hal::efuse::Efuse::get_mac_address();
+	#[cfg(feature = "mcu-esp32c3")]
+	return esp32c3_hal::efuse::Efuse::get_mac_address();
+	#[cfg(feature = "mcu-esp32s3")]
+	return esp32s3_hal::efuse::Efuse::get_mac_address();
+	#[cfg(mcu_f_nrf52)]
+	{
+		#[cfg(feature = "mcu-nrf52832")]
+		use nrf52832_pac as pac;
+		#[cfg(feature = "mcu-nrf52840")]
+		use nrf52840_pac as pac;
+		// Safety: The FICR is read-only.
+		let ficr = unsafe { &*pac::FICR::ptr() };
+		let [a, b, c, d] = ficr.deviceaddr[0].read().bits().to_le_bytes();
+		let [e, f, ..] = ficr.deviceaddr[1].read().bits().to_le_bytes();
+		[a, b, c, d, e, f]
+	}
+}
+
+/// Resets the whole chip, like the reset button does.
+#[cfg(mcu_f_esp32)]
+#[allow(dead_code)]
+pub fn reboot() -> ! {
+	#[cfg(feature = "mcu-esp32")]
+	use esp32_hal::pac;
+	#[cfg(feature = "mcu-esp32c3")]
+	use esp32c3_hal::pac;
+	#[cfg(feature = "mcu-esp32s3")]
+	use esp32s3_hal::pac;
+	// Safety: `Rtc` owns the RTC_CNTL peripheral, but only used it to disable the
+	// watchdogs at boot.
+	unsafe {
+		(*pac::RTC_CNTL::ptr())
+			.options0
+			.modify(|_, w| w.sw_sys_rst().set_bit())
+	};
+	// The reset takes a moment to kick in.
+	loop {
+		core::hint::spin_loop();
+	}
+}
+
 /// Holds the peripherals. This merely exists to allow a way to pass around platform
 /// specific peripherals, some of which may not even exist, in a platform-agnostic way.
-pub struct Peripherals<I2c = (), Delay = (), Uart = (), UsbDriver = ()> {
-	pub i2c: I2c,
+///
+/// Each of them is moved out into the one task that uses it when it is spawned, so
+/// nothing else can get at them. Only the flash controller is shared, since every
+/// task can store things, see `storage`.
+pub struct Peripherals<
+	ImuBus = (),
+	Delay = (),
+	Uart = (),
+	UsbDriver = (),
+	Button = (),
+	Battery = (),
+	Radio = (),
+	Led = (),
+	Temp = (),
+> {
+	/// I2C, or SPI for boards with an `[spi]` table.
+	pub imu_bus: ImuBus,
 	pub delay: Delay,
 	pub uart: Uart,
 	pub usb_driver: UsbDriver,
+	pub button: Button,
+	pub battery: Battery,
+	pub radio: Radio,
+	pub led: Led,
+	pub temp: Temp,
 }
 impl Peripherals {
 	pub fn new() -> Self {
 		Self {
-			i2c: (),
+			imu_bus: (),
 			delay: (),
 			uart: (),
 			usb_driver: (),
+			button: (),
+			battery: (),
+			radio: (),
+			led: (),
+			temp: (),
 		}
 	}
 }
 /// Type-level builder for `Peripherals`, which transforms each field from () to the
 /// peripheral type.
-impl<I2c, Delay, Uart, UsbDriver> Peripherals<I2c, Delay, Uart, UsbDriver> {
+impl<ImuBus, Delay, Uart, UsbDriver, Button, Battery, Radio, Led, Temp>
+	Peripherals<ImuBus, Delay, Uart, UsbDriver, Button, Battery, Radio, Led, Temp>
+{
 	#[allow(dead_code)]
-	pub fn i2c<T>(self, p: T) -> Peripherals<T, Delay, Uart, UsbDriver> {
+	pub fn imu_bus<T>(
+		self,
+		p: T,
+	) -> Peripherals<T, Delay, Uart, UsbDriver, Button, Battery, Radio, Led, Temp> {
 		Peripherals {
-			i2c: p,
+			imu_bus: p,
 			delay: self.delay,
 			uart: self.uart,
 			usb_driver: self.usb_driver,
+			button: self.button,
+			battery: self.battery,
+			radio: self.radio,
+			led: self.led,
+			temp: self.temp,
 		}
 	}
 	#[allow(dead_code)]
-	pub fn delay<T>(self, p: T) -> Peripherals<I2c, T, Uart, UsbDriver> {
+	pub fn delay<T>(
+		self,
+		p: T,
+	) -> Peripherals<ImuBus, T, Uart, UsbDriver, Button, Battery, Radio, Led, Temp> {
 		Peripherals {
-			i2c: self.i2c,
+			imu_bus: self.imu_bus,
 			delay: p,
 			uart: self.uart,
 			usb_driver: self.usb_driver,
+			button: self.button,
+			battery: self.battery,
+			radio: self.radio,
+			led: self.led,
+			temp: self.temp,
 		}
 	}
 	#[allow(dead_code)]
-	pub fn uart<T>(self, p: T) -> Peripherals<I2c, Delay, T, UsbDriver> {
+	pub fn uart<T>(
+		self,
+		p: T,
+	) -> Peripherals<ImuBus, Delay, T, UsbDriver, Button, Battery, Radio, Led, Temp> {
 		Peripherals {
-			i2c: self.i2c,
+			imu_bus: self.imu_bus,
 			delay: self.delay,
 			uart: p,
 			usb_driver: self.usb_driver,
+			button: self.button,
+			battery: self.battery,
+			radio: self.radio,
+			led: self.led,
+			temp: self.temp,
 		}
 	}
 	#[allow(dead_code)]
-	pub fn usb_driver<T>(self, p: T) -> Peripherals<I2c, Delay, Uart, T> {
+	pub fn usb_driver<T>(
+		self,
+		p: T,
+	) -> Peripherals<ImuBus, Delay, Uart, T, Button, Battery, Radio, Led, Temp> {
 		Peripherals {
-			i2c: self.i2c,
+			imu_bus: self.imu_bus,
 			delay: self.delay,
 			uart: self.uart,
 			usb_driver: p,
+			button: self.button,
+			battery: self.battery,
+			radio: self.radio,
+			led: self.led,
+			temp: self.temp,
+		}
+	}
+	#[allow(dead_code)]
+	pub fn button<T>(
+		self,
+		p: T,
+	) -> Peripherals<ImuBus, Delay, Uart, UsbDriver, T, Battery, Radio, Led, Temp> {
+		Peripherals {
+			imu_bus: self.imu_bus,
+			delay: self.delay,
+			uart: self.uart,
+			usb_driver: self.usb_driver,
+			button: p,
+			battery: self.battery,
+			radio: self.radio,
+			led: self.led,
+			temp: self.temp,
+		}
+	}
+	#[allow(dead_code)]
+	pub fn battery<T>(
+		self,
+		p: T,
+	) -> Peripherals<ImuBus, Delay, Uart, UsbDriver, Button, T, Radio, Led, Temp> {
+		Peripherals {
+			imu_bus: self.imu_bus,
+			delay: self.delay,
+			uart: self.uart,
+			usb_driver: self.usb_driver,
+			button: self.button,
+			battery: p,
+			radio: self.radio,
+			led: self.led,
+			temp: self.temp,
+		}
+	}
+	#[allow(dead_code)]
+	pub fn radio<T>(
+		self,
+		p: T,
+	) -> Peripherals<ImuBus, Delay, Uart, UsbDriver, Button, Battery, T, Led, Temp> {
+		Peripherals {
+			imu_bus: self.imu_bus,
+			delay: self.delay,
+			uart: self.uart,
+			usb_driver: self.usb_driver,
+			button: self.button,
+			battery: self.battery,
+			radio: p,
+			led: self.led,
+			temp: self.temp,
+		}
+	}
+	#[allow(dead_code)]
+	pub fn led<T>(
+		self,
+		p: T,
+	) -> Peripherals<ImuBus, Delay, Uart, UsbDriver, Button, Battery, Radio, T, Temp> {
+		Peripherals {
+			imu_bus: self.imu_bus,
+			delay: self.delay,
+			uart: self.uart,
+			usb_driver: self.usb_driver,
+			button: self.button,
+			battery: self.battery,
+			radio: self.radio,
+			led: p,
+			temp: self.temp,
+		}
+	}
+	#[allow(dead_code)]
+	pub fn temp<T>(
+		self,
+		p: T,
+	) -> Peripherals<ImuBus, Delay, Uart, UsbDriver, Button, Battery, Radio, Led, T> {
+		Peripherals {
+			imu_bus: self.imu_bus,
+			delay: self.delay,
+			uart: self.uart,
+			usb_driver: self.usb_driver,
+			button: self.button,
+			battery: self.battery,
+			radio: self.radio,
+			led: self.led,
+			temp: p,
 		}
 	}
 }
 
 /// Type-level destructors for `Peripherals` which turn peripheral type into ().
-impl<I2c, Delay, Uart, UsbDriver> Peripherals<I2c, Delay, Uart, UsbDriver> {
+impl<ImuBus, Delay, Uart, UsbDriver, Button, Battery, Radio, Led, Temp>
+	Peripherals<ImuBus, Delay, Uart, UsbDriver, Button, Battery, Radio, Led, Temp>
+{
 	#[cfg(all(bbq, feature = "log-usb-serial"))]
-	pub fn bbq_peripheral(self) -> (UsbDriver, Peripherals<I2c, Delay, Uart, ()>) {
+	pub fn bbq_peripheral(
+		self,
+	) -> (
+		UsbDriver,
+		Peripherals<ImuBus, Delay, Uart, (), Button, Battery, Radio, Led, Temp>,
+	) {
 		(
 			self.usb_driver,
 			Peripherals {
-				i2c: self.i2c,
+				imu_bus: self.imu_bus,
 				delay: self.delay,
 				uart: self.uart,
 				usb_driver: (),
+				button: self.button,
+				battery: self.battery,
+				radio: self.radio,
+				led: self.led,
+				temp: self.temp,
 			},
 		)
 	}
 	#[cfg(all(bbq, feature = "log-uart"))]
-	pub fn bbq_peripheral(self) -> (Uart, Peripherals<I2c, Delay, (), UsbDriver>) {
+	pub fn bbq_peripheral(
+		self,
+	) -> (
+		Uart,
+		Peripherals<ImuBus, Delay, (), UsbDriver, Button, Battery, Radio, Led, Temp>,
+	) {
 		(
 			self.uart,
 			Peripherals {
-				i2c: self.i2c,
+				imu_bus: self.imu_bus,
 				delay: self.delay,
 				uart: (),
 				usb_driver: self.usb_driver,
+				button: self.button,
+				battery: self.battery,
+				radio: self.radio,
+				led: self.led,
+				temp: self.temp,
 			},
 		)
 	}
 	#[cfg(not(bbq))]
-	pub fn bbq_peripheral(self) -> ((), Peripherals<I2c, Delay, Uart, UsbDriver>) {
+	pub fn bbq_peripheral(
+		self,
+	) -> (
+		(),
+		Peripherals<ImuBus, Delay, Uart, UsbDriver, Button, Battery, Radio, Led, Temp>,
+	) {
 		((), self)
 	}
 }
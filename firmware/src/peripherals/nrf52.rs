@@ -1,11 +1,15 @@
-use super::Peripherals;
+use super::{BatteryConcrete, ButtonConcrete, LedConcrete, Peripherals, TempConcrete};
+use crate::aliases::ImuBusConcrete;
 use crate::aliases::ඞ::DelayConcrete;
-use crate::aliases::ඞ::I2cConcrete;
 use crate::aliases::ඞ::UartConcrete;
 use crate::aliases::ඞ::UsbDriverConcrete;
+use crate::networking::Radio;
 
 use defmt::debug;
 use embassy_nrf::interrupt;
+#[cfg(softdevice)]
+use embassy_nrf::interrupt::{InterruptExt, Priority};
+#[cfg(not(spi_imu))]
 use embassy_nrf::twim::{self, Twim};
 use embassy_nrf::uarte::{self, Uarte};
 use paste::paste;
@@ -19,12 +23,25 @@ macro_rules! map_pin {
 }
 
 pub fn get_peripherals() -> Peripherals<
-	I2cConcrete<'static>,
+	ImuBusConcrete<'static>,
 	DelayConcrete,
 	UartConcrete<'static>,
 	UsbDriverConcrete<'static>,
+	ButtonConcrete,
+	BatteryConcrete,
+	Radio,
+	LedConcrete,
+	TempConcrete,
 > {
-	let p = embassy_nrf::init(Default::default());
+	#[allow(unused_mut)]
+	let mut config = embassy_nrf::config::Config::default();
+	// The softdevice keeps priorities 0, 1 and 4 to itself.
+	#[cfg(softdevice)]
+	{
+		config.gpiote_interrupt_priority = Priority::P2;
+		config.time_interrupt_priority = Priority::P2;
+	}
+	let p = embassy_nrf::init(config);
 
 	// Fix issue on rev 3 boards where AP is protected, preventing debugging/rtt.
 	#[cfg(feature = "mcu-nrf52840")] // TODO: Add nrf52832 support
@@ -104,13 +121,15 @@ pub fn get_peripherals() -> Peripherals<
 		}
 	}
 
-	debug!("Initializing TWIM (I2C controller)");
-
 	// IDK how this works, code is from here:
 	// https://github.com/embassy-rs/embassy/blob/f109e73c6d7ef2ad93102b7c8223f5cef30ef36f/examples/nrf/src/bin/twim.rs
-	let twim = {
+	#[cfg(not(spi_imu))]
+	let imu_bus = {
+		debug!("Initializing TWIM (I2C controller)");
 		let config = twim::Config::default();
 		let irq = interrupt::take!(SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0);
+		#[cfg(softdevice)]
+		irq.set_priority(Priority::P3);
 		Twim::new(
 			p.TWISPI0,
 			irq,
@@ -119,13 +138,78 @@ pub fn get_peripherals() -> Peripherals<
 			config,
 		)
 	};
-	debug!("Initialized twim");
+	#[cfg(spi_imu)]
+	let imu_bus = {
+		extern crate alloc;
+		use embassy_nrf::gpio::{Level, Output, OutputDrive};
+		use embassy_nrf::spim::{self, Spim};
+		debug!("Initializing SPIM (SPI controller)");
+		let mut config = spim::Config::default();
+		config.frequency = spim_frequency();
+		config.mode = spim::MODE_0;
+		let irq = interrupt::take!(SPIM2_SPIS2_SPI2);
+		#[cfg(softdevice)]
+		irq.set_priority(Priority::P3);
+		let spim = Spim::new(
+			p.SPI2,
+			irq,
+			map_pin!(p, env!("PIN_SPI_SCK")),
+			map_pin!(p, env!("PIN_SPI_MISO")),
+			map_pin!(p, env!("PIN_SPI_MOSI")),
+			config,
+		);
+		// Driven by hand, since each register access takes more than one transfer.
+		let cs = map_pin!(p, env!("PIN_SPI_CS"));
+		let cs = Output::new(cs, Level::High, OutputDrive::Standard);
+		crate::imu::regs::SpiRegisters::new(spim, alloc::boxed::Box::new(cs))
+	};
+	debug!("Initialized the IMU bus");
 
 	let delay = embassy_time::Delay;
 	debug!("Initialized delay");
 
+	// The softdevice owns the flash controller once BLE enables it, and writing to it
+	// behind its back faults. BLE builds store through the softdevice instead.
+	#[cfg(not(softdevice))]
+	{
+		crate::storage::init(embassy_nrf::nvmc::Nvmc::new(p.NVMC));
+		debug!("Initialized storage");
+	}
+
+	#[cfg(button)]
+	let button: ButtonConcrete = {
+		extern crate alloc;
+		use embassy_nrf::gpio::{Input, Pull};
+		let button = Input::new(map_pin!(p, env!("PIN_BUTTON")), Pull::Up);
+		debug!("Initialized button");
+		alloc::boxed::Box::new(button)
+	};
+
+	#[cfg(battery)]
+	let battery: BatteryConcrete = {
+		use embassy_nrf::saadc::{ChannelConfig, Config, Saadc};
+		let irq = interrupt::take!(SAADC);
+		#[cfg(softdevice)]
+		irq.set_priority(Priority::P3);
+		let channel = ChannelConfig::single_ended(map_pin!(p, env!("PIN_BATTERY")));
+		debug!("Initialized battery ADC");
+		Saadc::new(p.SAADC, irq, Config::default(), [channel])
+	};
+
+	#[cfg(led)]
+	let led: LedConcrete = {
+		extern crate alloc;
+		use embassy_nrf::gpio::{Level, Output, OutputDrive};
+		let pin = map_pin!(p, env!("PIN_LED"));
+		let led = Output::new(pin, Level::Low, OutputDrive::Standard);
+		debug!("Initialized LED");
+		crate::led::Led::new(alloc::boxed::Box::new(led))
+	};
+
 	let uarte = {
 		let irq = interrupt::take!(UARTE0_UART0);
+		#[cfg(softdevice)]
+		irq.set_priority(Priority::P3);
 		let mut config = uarte::Config::default();
 		config.parity = uarte::Parity::EXCLUDED;
 		config.baudrate = uarte::Baudrate::BAUD115200;
@@ -138,7 +222,8 @@ pub fn get_peripherals() -> Peripherals<
 
 	#[allow(unused_variables)]
 	let usb_driver = ();
-	#[cfg(feature = "mcu-nrf52840")]
+	// The softdevice takes the power events that USB needs.
+	#[cfg(all(feature = "mcu-nrf52840", not(softdevice)))]
 	let usb_driver = {
 		use embassy_nrf::usb::{self, Driver};
 		let irq = interrupt::take!(USBD);
@@ -148,6 +233,95 @@ pub fn get_peripherals() -> Peripherals<
 		d
 	};
 
+	// The softdevice owns the TEMP peripheral, we ask it for the temperature instead.
+	#[cfg(softdevice)]
+	let temp = ();
+	#[cfg(not(softdevice))]
+	let temp = {
+		let irq = interrupt::take!(TEMP);
+		embassy_nrf::temp::Temp::new(p.TEMP, irq)
+	};
+
 	let p = Peripherals::new();
-	p.i2c(twim).delay(delay).uart(uarte).usb_driver(usb_driver)
+	let p = p
+		.imu_bus(imu_bus)
+		.delay(delay)
+		.uart(uarte)
+		.usb_driver(usb_driver);
+	#[cfg(button)]
+	let p = p.button(button);
+	#[cfg(battery)]
+	let p = p.battery(battery);
+	#[cfg(led)]
+	let p = p.led(led);
+	p.radio(Radio::new()).temp(temp)
+}
+
+/// The fastest clock of the SPIM that isn't above `SPI_FREQ_KHZ`.
+#[cfg(spi_imu)]
+fn spim_frequency() -> embassy_nrf::spim::Frequency {
+	use embassy_nrf::spim::Frequency;
+	match super::SPI_FREQ_KHZ {
+		8000.. => Frequency::M8,
+		4000.. => Frequency::M4,
+		2000.. => Frequency::M2,
+		1000.. => Frequency::M1,
+		500.. => Frequency::K500,
+		250.. => Frequency::K250,
+		_ => Frequency::K125,
+	}
+}
+
+/// The interrupt pin of the imu, which wakes us up from deep sleep. Pins are
+/// written like `0_10`, for pin 10 of port 0.
+#[cfg(deep_sleep)]
+const WAKE_PIN: (u8, usize) = {
+	let pin = env!("PIN_INT0").as_bytes();
+	let mut number = 0;
+	let mut i = 2;
+	while i < pin.len() {
+		number = number * 10 + (pin[i] - b'0') as usize;
+		i += 1;
+	}
+	(pin[0] - b'0', number)
+};
+
+/// Turns the chip off until the imu raises its interrupt pin, which resets it. RAM
+/// isn't kept, so waking up is like booting.
+#[cfg(deep_sleep)]
+pub fn system_off() -> ! {
+	#[cfg(feature = "mcu-nrf52832")]
+	use nrf52832_pac as pac;
+	#[cfg(feature = "mcu-nrf52840")]
+	use nrf52840_pac as pac;
+
+	let (port, pin) = WAKE_PIN;
+	// Safety: Nothing else uses the pin, and nothing runs after this.
+	unsafe {
+		let block = match port {
+			#[cfg(feature = "mcu-nrf52840")]
+			1 => &*pac::P1::ptr(),
+			_ => &*pac::P0::ptr(),
+		};
+		block.pin_cnf[pin].write(|w| {
+			w.dir().input();
+			w.input().connect();
+			w.pull().disabled();
+			w.sense().high()
+		});
+	}
+	// The softdevice owns the POWER peripheral once it is enabled.
+	#[cfg(softdevice)]
+	unsafe {
+		nrf_softdevice::raw::sd_power_system_off();
+	}
+	#[cfg(not(softdevice))]
+	unsafe {
+		(*pac::POWER::ptr())
+			.systemoff
+			.write(|w| w.systemoff().enter());
+	}
+	loop {
+		cortex_m::asm::wfe();
+	}
 }
@@ -0,0 +1,129 @@
+//! Saves power while the tracker lies still, like on a desk between sessions.
+//!
+//! Once the main sensor hasn't moved for [`IDLE_AFTER`], the tracker idles: the imu
+//! measures less often where it can, and is read less often, which also sends fewer
+//! rotations to the server and leaves the MCU asleep in between. Moving it again
+//! brings it back right away.
+//!
+//! Only the nRF52 with `imu-mpu6050-raw` goes into deep sleep, after
+//! [`SLEEP_AFTER`], since that is the only driver that sets up its chip to wake the
+//! MCU on motion. Waking up reboots the tracker, so it starts over like it was just
+//! turned on. Everywhere else the tracker only ever idles, however long it lies
+//! still, and there is nothing to wake up from.
+//!
+//! Both come from the `[power]` table of the board, see `boards/README.md`.
+
+use defmt::{info, Format};
+use embassy_time::{Duration, Instant};
+
+#[cfg(deep_sleep)]
+use crate::events::{self, Event, EventBus};
+use crate::imu::Quat;
+use crate::utils::parse_u32;
+
+/// How long the tracker has to lie still before it idles, if it idles at all.
+const IDLE_AFTER: Option<Duration> = secs(parse_u32(env!("POWER_IDLE_AFTER_S")));
+/// How long the tracker has to lie still before it goes into deep sleep, on the
+/// boards that can be woken up by their imu.
+const SLEEP_AFTER: Option<Duration> = secs(parse_u32(env!("POWER_SLEEP_AFTER_S")));
+
+/// How far in radians the main sensor has to turn to count as moving. Well above
+/// what the fusion drifts while lying still.
+const MOTION_ANGLE: f32 = 5. * core::f32::consts::PI / 180.;
+
+/// Gives the logs and the network a moment before the MCU turns off, to let the
+/// server know with `SbPacket::Sleeping`.
+#[cfg(deep_sleep)]
+const SLEEP_DELAY: Duration = Duration::from_millis(200);
+
+const fn secs(secs: u32) -> Option<Duration> {
+	if secs == 0 {
+		None
+	} else {
+		Some(Duration::from_secs(secs as u64))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Format)]
+pub enum PowerState {
+	Active,
+	/// Lying still, sampling less often.
+	Idle,
+	/// About to go into deep sleep.
+	Sleep,
+}
+impl PowerState {
+	/// How long the imu should wait between samples in this state, if it should be
+	/// slowed down at all.
+	pub fn sample_interval(self) -> Option<Duration> {
+		match self {
+			Self::Active => None,
+			Self::Idle | Self::Sleep => Some(Duration::from_millis(100)),
+		}
+	}
+}
+
+/// Decides from the orientation of the main sensor how long the tracker has been
+/// lying still.
+pub struct Stillness {
+	state: PowerState,
+	/// The orientation when the tracker last moved.
+	anchor: Option<Quat>,
+	/// When the tracker last moved.
+	since: Instant,
+}
+impl Stillness {
+	pub fn new(now: Instant) -> Self {
+		Self {
+			state: PowerState::Active,
+			anchor: None,
+			since: now,
+		}
+	}
+
+	/// Takes the latest orientation of the main sensor, and returns the new state if
+	/// it changed.
+	pub fn update(&mut self, q: &Quat, now: Instant) -> Option<PowerState> {
+		let moved = self.anchor.map_or(true, |a| a.angle_to(q) > MOTION_ANGLE);
+		if moved {
+			self.anchor = Some(*q);
+			self.since = now;
+		}
+		let still_for = now - self.since;
+		let reached = |after: Option<Duration>| after.map_or(false, |a| still_for >= a);
+		let state = if reached(SLEEP_AFTER) {
+			PowerState::Sleep
+		} else if reached(IDLE_AFTER) {
+			PowerState::Idle
+		} else {
+			PowerState::Active
+		};
+		self.set(state)
+	}
+
+	/// Starts over as if the tracker just moved, for when something needs every
+	/// sample, like a raw stream or a calibration.
+	pub fn wake(&mut self, now: Instant) -> Option<PowerState> {
+		self.anchor = None;
+		self.since = now;
+		self.set(PowerState::Active)
+	}
+
+	fn set(&mut self, state: PowerState) -> Option<PowerState> {
+		if state == self.state {
+			return None;
+		}
+		info!("power: {} -> {}", self.state, state);
+		self.state = state;
+		Some(state)
+	}
+}
+
+/// Turns the MCU off, once the imu was told to wake it up by motion.
+#[cfg(deep_sleep)]
+pub async fn deep_sleep(events: &EventBus) -> ! {
+	info!("Going into deep sleep, move the tracker to wake it up");
+	events::publish(events, Event::Sleeping);
+	embassy_time::Timer::after(SLEEP_DELAY).await;
+	crate::peripherals::ඞ::system_off()
+}
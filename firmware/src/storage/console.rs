@@ -0,0 +1,126 @@
+//! The `STORAGE` commands of the serial console, which let `tools/tracker_storage`
+//! back up and restore the records on the host. The slots are given by their index,
+//! and records as the hex of their payload:
+//!
+//! - `STORAGE READ <slot>` answers `SLMV STORAGE <slot> DATA <hex>`, or
+//!   `SLMV STORAGE <slot> EMPTY` if nothing is stored.
+//! - `STORAGE WRITE <slot> <hex>` replaces the record, and `STORAGE CLEAR <slot>`
+//!   empties the slot. Both answer `SLMV STORAGE <slot> OK`.
+//!
+//! Anything that fails answers `SLMV STORAGE <slot> ERROR <why>`, with `?` as the
+//! slot if it wasn't one. The answers go around the defmt output, see
+//! `defmt_esp_println::raw`, so that the tool doesn't need the ELF to read them.
+//!
+//! The tasks only load their records at boot, and some write their own copy back
+//! later, like the control task does with the config. So whatever is written only
+//! takes effect after a `REBOOT`, which the tool sends once it is done.
+
+use defmt::{info, warn};
+use defmt_esp_println::raw;
+
+use super::{Error, Slot, MAX_PAYLOAD_LEN};
+
+/// The longest command, `STORAGE WRITE` with the largest record.
+pub const MAX_COMMAND_LEN: usize = WRITE.len() + 2 + 2 * MAX_PAYLOAD_LEN;
+const READ: &[u8] = b"READ ";
+const WRITE: &[u8] = b"WRITE ";
+const CLEAR: &[u8] = b"CLEAR ";
+const PREFIX: &[u8] = b"SLMV STORAGE ";
+/// The prefix, a slot, `DATA`, and the largest record.
+const MAX_ANSWER_LEN: usize = PREFIX.len() + 2 + 5 + 2 * MAX_PAYLOAD_LEN;
+const HEX: &[u8; 16] = b"0123456789abcdef";
+
+enum Command<'a> {
+	Read,
+	/// With the hex of the payload.
+	Write(&'a [u8]),
+	Clear,
+}
+
+/// Runs the arguments of a `STORAGE` command.
+pub fn command(args: &[u8]) {
+	let parsed = if let Some(a) = args.strip_prefix(READ) {
+		Some((Command::Read, a))
+	} else if let Some(a) = args.strip_prefix(CLEAR) {
+		Some((Command::Clear, a))
+	} else if let Some(a) = args.strip_prefix(WRITE) {
+		let i = a.iter().position(|&b| b == b' ').unwrap_or(a.len());
+		Some((Command::Write(a.get(i + 1..).unwrap_or_default()), &a[..i]))
+	} else {
+		None
+	};
+	let Some((command, slot)) = parsed else {
+		warn!("usage: STORAGE READ|WRITE|CLEAR <slot> [<hex>]");
+		return answer(None, b"ERROR usage", &[]);
+	};
+	let Some(slot) = parse_slot(slot) else {
+		return answer(None, b"ERROR slot", &[]);
+	};
+	let mut payload = [0; MAX_PAYLOAD_LEN];
+	let result = match command {
+		Command::Read => match super::load(slot, &mut payload) {
+			Ok(len) => return answer(Some(slot), b"DATA ", &payload[..len]),
+			Err(e) => Err(e),
+		},
+		Command::Write(hex) => {
+			let Some(len) = unhex(hex, &mut payload) else {
+				return answer(Some(slot), b"ERROR hex", &[]);
+			};
+			info!("Storing {} bytes in {} from the console", len, slot);
+			super::store(slot, &payload[..len])
+		}
+		Command::Clear => {
+			info!("Clearing {} from the console", slot);
+			super::clear(slot)
+		}
+	};
+	match result {
+		Ok(()) => answer(Some(slot), b"OK", &[]),
+		Err(Error::Empty) => answer(Some(slot), b"EMPTY", &[]),
+		Err(Error::Corrupt) => answer(Some(slot), b"ERROR corrupt", &[]),
+		Err(Error::TooLarge) => answer(Some(slot), b"ERROR too-large", &[]),
+		Err(Error::Flash) => answer(Some(slot), b"ERROR flash", &[]),
+		Err(Error::NoPartition) => answer(Some(slot), b"ERROR no-partition", &[]),
+		Err(Error::Busy) => answer(Some(slot), b"ERROR busy", &[]),
+	}
+}
+
+fn parse_slot(arg: &[u8]) -> Option<Slot> {
+	match arg {
+		[digit @ b'0'..=b'9'] => Slot::from_index(digit - b'0'),
+		_ => None,
+	}
+}
+
+/// Decodes `hex` into `payload`, and returns how many bytes it was. `None` if it
+/// isn't hex, or is too long.
+fn unhex(hex: &[u8], payload: &mut [u8]) -> Option<usize> {
+	if hex.len() % 2 != 0 || hex.len() / 2 > payload.len() {
+		return None;
+	}
+	let nibble = |b: u8| (b as char).to_digit(16).map(|d| d as u8);
+	for (byte, pair) in payload.iter_mut().zip(hex.chunks_exact(2)) {
+		*byte = nibble(pair[0])? << 4 | nibble(pair[1])?;
+	}
+	Some(hex.len() / 2)
+}
+
+/// Answers with `what`, and `payload` in hex after it.
+fn answer(slot: Option<Slot>, what: &[u8], payload: &[u8]) {
+	let mut line = [0; MAX_ANSWER_LEN];
+	let mut len = 0;
+	let mut push = |bytes: &[u8]| {
+		line[len..][..bytes.len()].copy_from_slice(bytes);
+		len += bytes.len();
+	};
+	push(PREFIX);
+	match slot {
+		Some(slot) => push(&[b'0' + slot as u8, b' ']),
+		None => push(b"? "),
+	}
+	push(what);
+	for &b in payload {
+		push(&[HEX[usize::from(b >> 4)], HEX[usize::from(b & 0xF)]]);
+	}
+	raw::write_line(&line[..len]);
+}
@@ -0,0 +1,78 @@
+use core::cell::Cell;
+use defmt::warn;
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+use embedded_storage::{ReadStorage, Storage};
+use esp_storage::FlashStorage;
+
+use super::Error;
+
+/// Where the `slimevr` partition of `partitions.csv` and `partitions_ota.csv` starts,
+/// at the end of 4 MB of flash.
+const BASE: u32 = 0x3F_0000;
+pub const SECTOR_SIZE: u32 = 0x1000;
+/// The partition is `0x10000` bytes long.
+pub const SECTORS: u32 = 16;
+/// The subtype of the partition, out of the ones that are left to apps.
+const SUBTYPE: u8 = 0x40;
+/// Where the bootloader looks for the partition table.
+const PARTITION_TABLE: u32 = 0x8000;
+/// The most entries that fit into the partition table.
+const MAX_PARTITIONS: u32 = 95;
+const PARTITION_LEN: usize = 32;
+const PARTITION_MAGIC: [u8; 2] = [0xAA, 0x50];
+
+/// Whether the partition is there, once it was looked for.
+static HAS_PARTITION: Mutex<CriticalSectionRawMutex, Cell<Option<bool>>> =
+	Mutex::new(Cell::new(None));
+
+pub fn read(offset: u32, buf: &mut [u8]) -> Result<(), Error> {
+	check_partition()?;
+	FlashStorage::new()
+		.read(BASE + offset, buf)
+		.map_err(|_| Error::Flash)
+}
+
+/// Erases the sector at `offset`, and writes `data` at its start.
+pub fn write(offset: u32, data: &[u8]) -> Result<(), Error> {
+	check_partition()?;
+	// Writing erases whichever sectors it touches, and keeps the rest of their bytes.
+	// Those are stale anyway, since a record is never followed by anything.
+	FlashStorage::new()
+		.write(BASE + offset, data)
+		.map_err(|_| Error::Flash)
+}
+
+/// Fails unless the partition table has the `slimevr` partition. Without it, the
+/// sectors could belong to the firmware, or to whatever was flashed before.
+fn check_partition() -> Result<(), Error> {
+	let found = HAS_PARTITION.lock(|h| h.get()).unwrap_or_else(|| {
+		let found = find_partition();
+		if !found {
+			warn!(
+				"There is no partition for the storage, flash the firmware with \
+				`--partition-table partitions.csv`"
+			);
+		}
+		HAS_PARTITION.lock(|h| h.set(Some(found)));
+		found
+	});
+	found.then_some(()).ok_or(Error::NoPartition)
+}
+
+fn find_partition() -> bool {
+	let mut flash = FlashStorage::new();
+	let mut entry = [0; PARTITION_LEN];
+	for i in 0..MAX_PARTITIONS {
+		let at = PARTITION_TABLE + i * PARTITION_LEN as u32;
+		if flash.read(at, &mut entry).is_err() || entry[..2] != PARTITION_MAGIC {
+			return false;
+		}
+		// The magic, the type, the subtype, the offset, and the size.
+		let u32_at = |i: usize| u32::from_le_bytes(entry[i..i + 4].try_into().unwrap());
+		let is_data = entry[2] == 0x01 && entry[3] == SUBTYPE;
+		if is_data && u32_at(4) == BASE && u32_at(8) >= SECTORS * SECTOR_SIZE {
+			return true;
+		}
+	}
+	false
+}
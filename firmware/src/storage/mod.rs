@@ -0,0 +1,147 @@
+//! Keeps small records in flash across reboots and reflashing, like the results of
+//! calibrating the IMU and the magnetometer, the Wi-Fi credentials, the ESP-NOW
+//! receiver, or the settings from the server.
+//!
+//! Each [`Slot`] owns a whole flash sector, so writing one record never has to
+//! preserve another. A record starts with a header holding its length and a CRC-32,
+//! so that a sector that was never written or only partly written (because power
+//! was lost) reads as empty instead of as garbage.
+//!
+//! Where the sectors are depends on the MCU:
+//! - On the ESP32 family they are the `slimevr` partition at the end of the flash,
+//!   which `partitions.csv` and `partitions_ota.csv` make room for. Without it in
+//!   the partition table, like after flashing with the default table of espflash,
+//!   nothing is stored.
+//! - On the nRF52 they are the pages that the Adafruit bootloader keeps for user
+//!   data by default, which a firmware update over DFU doesn't erase. Boards with
+//!   another bootloader move them with `storage.address` in their toml, and
+//!   `memory.x` keeps the firmware out of them. With `net-ble` the softdevice owns
+//!   the flash controller, so writes wait in a queue for [`run`], which does them
+//!   through the softdevice. Until then, loading still returns the old record.
+
+#[cfg(mcu_f_esp32)]
+#[path = "esp.rs"]
+mod ඞ;
+
+#[cfg(mcu_f_nrf52)]
+#[path = "nrf.rs"]
+mod ඞ;
+
+#[cfg(all(mcu_f_nrf52, not(softdevice)))]
+pub use self::ඞ::init;
+#[cfg(softdevice)]
+pub use self::ඞ::run;
+
+#[cfg(console)]
+pub mod console;
+
+use defmt::Format;
+use firmware_protocol::crc32;
+
+const MAGIC: [u8; 4] = *b"SLMV";
+/// The magic, the length, and the CRC-32 of the payload.
+const HEADER_LEN: usize = 4 + 4 + 4;
+/// The largest record, with its header. Plenty for what is stored so far, and small
+/// enough to go on the stack.
+const MAX_RECORD_LEN: usize = 256;
+pub const MAX_PAYLOAD_LEN: usize = MAX_RECORD_LEN - HEADER_LEN;
+/// Flash on the nRF52 can only be written in whole words.
+const WRITE_ALIGN: usize = 4;
+
+/// What a sector holds. The numbers are the index of the sector, and must not change
+/// once released, or stored records end up being read as something else.
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+	Calibration = 0,
+	WifiCredentials = 1,
+	EspNowReceiver = 2,
+	Config = 3,
+	MagCalibration = 4,
+}
+const _: () = assert!((Slot::MagCalibration as u32) < ඞ::SECTORS);
+impl Slot {
+	#[cfg_attr(not(console), allow(dead_code))]
+	fn from_index(index: u8) -> Option<Self> {
+		match index {
+			0 => Some(Self::Calibration),
+			1 => Some(Self::WifiCredentials),
+			2 => Some(Self::EspNowReceiver),
+			3 => Some(Self::Config),
+			4 => Some(Self::MagCalibration),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+	/// Nothing was stored in the slot yet.
+	Empty,
+	/// The record didn't match its CRC, probably because writing it was cut off.
+	Corrupt,
+	/// The payload doesn't fit into a record, or the buffer to read it into.
+	TooLarge,
+	/// The flash driver failed.
+	Flash,
+	/// The partition table has no partition for the storage.
+	#[cfg_attr(not(mcu_f_esp32), allow(dead_code))]
+	NoPartition,
+	/// Too many writes are waiting for the softdevice already.
+	#[cfg_attr(not(softdevice), allow(dead_code))]
+	Busy,
+}
+
+fn offset(slot: Slot) -> u32 {
+	slot as u32 * ඞ::SECTOR_SIZE
+}
+
+/// Reads the record in `slot` into `payload`, and returns its length.
+pub fn load(slot: Slot, payload: &mut [u8]) -> Result<usize, Error> {
+	let mut header = [0; HEADER_LEN];
+	ඞ::read(offset(slot), &mut header)?;
+	if header[..4] != MAGIC {
+		return Err(Error::Empty);
+	}
+	let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+	let crc = u32::from_le_bytes(header[8..12].try_into().unwrap());
+	if len > MAX_PAYLOAD_LEN {
+		return Err(Error::Corrupt);
+	}
+	let payload = payload.get_mut(..len).ok_or(Error::TooLarge)?;
+	ඞ::read(offset(slot) + HEADER_LEN as u32, payload)?;
+	if crc32(payload) != crc {
+		return Err(Error::Corrupt);
+	}
+	Ok(len)
+}
+
+/// Replaces the record in `slot` with `payload`. This erases the whole sector first,
+/// which takes tens of milliseconds during which the caller is blocked.
+pub fn store(slot: Slot, payload: &[u8]) -> Result<(), Error> {
+	if payload.len() > MAX_PAYLOAD_LEN {
+		return Err(Error::TooLarge);
+	}
+	let mut record = [0xFF; MAX_RECORD_LEN];
+	record[..4].copy_from_slice(&MAGIC);
+	record[4..8].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+	record[8..12].copy_from_slice(&crc32(payload).to_le_bytes());
+	record[HEADER_LEN..][..payload.len()].copy_from_slice(payload);
+	let len = HEADER_LEN + payload.len();
+	let len = (len + WRITE_ALIGN - 1) / WRITE_ALIGN * WRITE_ALIGN;
+	ඞ::write(offset(slot), &record[..len])
+}
+
+/// Waits until the records that were stored so far are in flash, which is right
+/// away unless they wait for the softdevice.
+#[cfg_attr(not(console), allow(dead_code))]
+pub async fn flush() {
+	#[cfg(softdevice)]
+	ඞ::flush().await;
+}
+
+/// Empties `slot`, so that loading it returns [`Error::Empty`].
+#[allow(dead_code)]
+pub fn clear(slot: Slot) -> Result<(), Error> {
+	// Erasing leaves the magic as all ones.
+	ඞ::write(offset(slot), &[0xFF; WRITE_ALIGN])
+}
@@ -0,0 +1,145 @@
+#[cfg(softdevice)]
+use core::cell::Cell;
+#[cfg(not(softdevice))]
+use core::cell::RefCell;
+#[cfg(softdevice)]
+use defmt::warn;
+#[cfg(not(softdevice))]
+use embassy_nrf::nvmc::Nvmc;
+use embassy_nrf::nvmc::PAGE_SIZE;
+use embassy_sync::blocking_mutex::{raw::CriticalSectionRawMutex, Mutex};
+#[cfg(softdevice)]
+use embassy_sync::channel::Channel;
+#[cfg(softdevice)]
+use embassy_time::{Duration, Timer};
+#[cfg(not(softdevice))]
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+#[cfg(softdevice)]
+use embedded_storage_async::nor_flash::NorFlash;
+#[cfg(softdevice)]
+use nrf_softdevice::{Flash, Softdevice};
+
+use super::Error;
+use crate::utils::parse_u32;
+
+/// The first page, from `storage.address` in the board toml. `memory.x` ends the
+/// firmware here.
+const BASE: u32 = parse_u32(env!("STORAGE_BASE"));
+pub const SECTOR_SIZE: u32 = PAGE_SIZE as u32;
+pub const SECTORS: u32 = 7;
+
+/// Only taken out while it is used, so that erasing doesn't happen inside of a
+/// critical section.
+#[cfg(not(softdevice))]
+static NVMC: Mutex<CriticalSectionRawMutex, RefCell<Option<Nvmc<'static>>>> =
+	Mutex::new(RefCell::new(None));
+
+/// Hands the flash controller to the storage. Until then, all reads and writes fail.
+/// Panics if it already has one, since there is only one flash controller.
+#[cfg(not(softdevice))]
+pub fn init(nvmc: Nvmc<'static>) {
+	let previous = NVMC.lock(|n| n.borrow_mut().replace(nvmc));
+	assert!(previous.is_none(), "storage was initialized twice");
+}
+
+#[cfg(not(softdevice))]
+fn with_nvmc<T>(f: impl FnOnce(&mut Nvmc<'static>) -> T) -> Result<T, Error> {
+	let mut nvmc = NVMC.lock(|n| n.borrow_mut().take()).ok_or(Error::Flash)?;
+	let result = f(&mut nvmc);
+	NVMC.lock(|n| *n.borrow_mut() = Some(nvmc));
+	Ok(result)
+}
+
+#[cfg(not(softdevice))]
+pub fn read(offset: u32, buf: &mut [u8]) -> Result<(), Error> {
+	with_nvmc(|nvmc| nvmc.read(BASE + offset, buf))?.map_err(|_| Error::Flash)
+}
+
+/// Erases the page at `offset`, and writes `data` at its start.
+#[cfg(not(softdevice))]
+pub fn write(offset: u32, data: &[u8]) -> Result<(), Error> {
+	let start = BASE + offset;
+	with_nvmc(|nvmc| {
+		nvmc.erase(start, start + SECTOR_SIZE)?;
+		nvmc.write(start, data)
+	})?
+	.map_err(|_| Error::Flash)
+}
+
+/// How many writes can wait for the softdevice. More than one, since the button
+/// and the server can each change a record at the same time.
+#[cfg(softdevice)]
+const QUEUE_LEN: usize = 2;
+
+/// A page to write, by [`run`]. The softdevice only writes from word aligned RAM.
+#[cfg(softdevice)]
+#[repr(align(4))]
+struct Write {
+	data: [u8; super::MAX_RECORD_LEN],
+	len: usize,
+	offset: u32,
+}
+
+#[cfg(softdevice)]
+static WRITES: Channel<CriticalSectionRawMutex, Write, QUEUE_LEN> = Channel::new();
+/// How many writes were queued, but not done yet.
+#[cfg(softdevice)]
+static PENDING: Mutex<CriticalSectionRawMutex, Cell<usize>> = Mutex::new(Cell::new(0));
+
+/// Reads flash directly, which doesn't get in the way of the softdevice.
+#[cfg(softdevice)]
+pub fn read(offset: u32, buf: &mut [u8]) -> Result<(), Error> {
+	let start = (BASE + offset) as *const u8;
+	// Safety: The pages of the storage are flash that `memory.x` keeps the firmware
+	// out of, and flash is always mapped and readable.
+	unsafe { core::ptr::copy_nonoverlapping(start, buf.as_mut_ptr(), buf.len()) };
+	Ok(())
+}
+
+/// Queues erasing the page at `offset` and writing `data` at its start, for [`run`].
+/// Until that is done, [`read`] still returns what was there before.
+#[cfg(softdevice)]
+pub fn write(offset: u32, data: &[u8]) -> Result<(), Error> {
+	let mut write = Write {
+		data: [0xFF; super::MAX_RECORD_LEN],
+		len: data.len(),
+		offset,
+	};
+	write
+		.data
+		.get_mut(..data.len())
+		.ok_or(Error::TooLarge)?
+		.copy_from_slice(data);
+	PENDING.lock(|p| p.set(p.get() + 1));
+	WRITES.try_send(write).map_err(|_| {
+		PENDING.lock(|p| p.set(p.get() - 1));
+		Error::Busy
+	})
+}
+
+/// Waits until the writes that were queued so far are done.
+#[cfg(softdevice)]
+pub async fn flush() {
+	while PENDING.lock(|p| p.get()) > 0 {
+		Timer::after(Duration::from_millis(10)).await;
+	}
+}
+
+/// Does the queued writes through the softdevice, which owns the flash controller
+/// while it is enabled. Writing to it directly would fault.
+#[cfg(softdevice)]
+pub async fn run(sd: &Softdevice) -> ! {
+	let mut flash = Flash::take(sd);
+	loop {
+		let write = WRITES.recv().await;
+		let start = BASE + write.offset;
+		let result = match flash.erase(start, start + SECTOR_SIZE).await {
+			Ok(()) => flash.write(start, &write.data[..write.len]).await,
+			Err(e) => Err(e),
+		};
+		if result.is_err() {
+			warn!("Failed to write a record through the softdevice");
+		}
+		PENDING.lock(|p| p.set(p.get() - 1));
+	}
+}
@@ -0,0 +1,136 @@
+//! Watches the MCU's temperature, so that an overheating tracker (like one in an
+//! enclosure sitting in the sun) can back off gracefully instead of browning out or
+//! getting clock-throttled unpredictably mid-session. While it is hot, the imu is
+//! read less often and the radio transmits with less power, see
+//! [`crate::networking::tx_power`].
+//!
+//! Only the nRF52 reads its temperature so far, with the TEMP peripheral, or through
+//! the softdevice once BLE enabled it. The HALs of the ESP32s don't have a driver for
+//! their sensor yet, so they never throttle.
+
+use defmt::{debug, info, warn};
+use embassy_executor::task;
+use embassy_time::{Duration, Timer};
+
+use crate::events::{self, Event, EventBus};
+use crate::networking::tx_power::{self, Reason};
+
+/// Temperature in °C above which we start throttling.
+const HOT_THRESHOLD: f32 = 75.;
+/// Temperature in °C above which we throttle as much as possible.
+const CRITICAL_THRESHOLD: f32 = 90.;
+/// How far in °C the temperature has to drop below a threshold before we stop
+/// throttling, so that we don't flip back and forth around it.
+const HYSTERESIS: f32 = 5.;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// The TX power while hot, in quarter dBm. While critical it goes to
+/// [`tx_power::MIN`].
+const HOT_TX_POWER: i8 = 52;
+
+/// The temperature sensor of the MCU. The softdevice owns it while it is enabled,
+/// so then there is nothing to hold on to.
+#[cfg(all(mcu_f_nrf52, not(softdevice)))]
+pub type Thermometer = embassy_nrf::temp::Temp<'static>;
+#[cfg(not(all(mcu_f_nrf52, not(softdevice))))]
+pub type Thermometer = ();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+#[repr(u8)]
+pub enum ThermalState {
+	Normal = 0,
+	/// Reduce the work we do, to cool down.
+	Hot = 1,
+	/// Reduce the work we do as much as possible, we are close to shutting down.
+	Critical = 2,
+}
+impl ThermalState {
+	/// How long the imu should wait between samples in this state, if it should be
+	/// throttled at all.
+	pub fn sample_interval(self) -> Option<Duration> {
+		match self {
+			Self::Normal => None,
+			Self::Hot => Some(Duration::from_millis(20)),
+			Self::Critical => Some(Duration::from_millis(100)),
+		}
+	}
+
+	/// The TX power in this state, in quarter dBm, if it should be limited at all.
+	fn tx_power(self) -> Option<i8> {
+		match self {
+			Self::Normal => None,
+			Self::Hot => Some(HOT_TX_POWER),
+			Self::Critical => Some(tx_power::MIN),
+		}
+	}
+
+	/// The state at `temperature` °C, if we were in `self` before.
+	fn next(self, temperature: f32) -> Self {
+		// Going up is immediate, going down needs to pass the hysteresis.
+		let threshold = |t: f32, state| if self >= state { t - HYSTERESIS } else { t };
+		if temperature >= threshold(CRITICAL_THRESHOLD, Self::Critical) {
+			Self::Critical
+		} else if temperature >= threshold(HOT_THRESHOLD, Self::Hot) {
+			Self::Hot
+		} else {
+			Self::Normal
+		}
+	}
+}
+
+#[task]
+pub async fn thermal_task(
+	events: &'static EventBus,
+	mut thermometer: Thermometer,
+) -> ! {
+	debug!("Thermal task");
+	let mut state = ThermalState::Normal;
+	loop {
+		Timer::after(POLL_INTERVAL).await;
+		let Some(temperature) = read_temperature(&mut thermometer).await else {
+			continue;
+		};
+		crate::diag::temperature(temperature);
+		let next = state.next(temperature);
+		if next == state {
+			continue;
+		}
+		if next > state {
+			warn!("MCU is at {}C, throttling: {}", temperature, next);
+		} else {
+			info!("MCU cooled down to {}C, now {}", temperature, next);
+		}
+		state = next;
+		tx_power::limit(Reason::Thermal, state.tx_power());
+		events::publish(events, Event::Thermal(state));
+	}
+}
+
+/// Reads the MCU's internal temperature sensor in °C.
+#[cfg(all(mcu_f_nrf52, not(softdevice)))]
+async fn read_temperature(thermometer: &mut Thermometer) -> Option<f32> {
+	Some(thermometer.read().await.to_num())
+}
+
+/// Reads the MCU's internal temperature sensor in °C, once BLE enabled the
+/// softdevice.
+#[cfg(softdevice)]
+async fn read_temperature(_thermometer: &mut Thermometer) -> Option<f32> {
+	let sd = crate::networking::ble::ඞ::softdevice()?;
+	match nrf_softdevice::temperature_celsius(sd) {
+		Ok(temperature) => Some(temperature.to_num()),
+		Err(err) => {
+			warn!(
+				"Failed to read the temperature: {}",
+				defmt::Debug2Format(&err)
+			);
+			None
+		}
+	}
+}
+
+/// Reads the MCU's internal temperature sensor in °C.
+#[cfg(mcu_f_esp32)]
+async fn read_temperature(_thermometer: &mut Thermometer) -> Option<f32> {
+	// TODO: esp-hal doesn't have a driver for the TSENS of the esp32c3/esp32s3 yet.
+	None
+}
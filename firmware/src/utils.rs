@@ -56,3 +56,16 @@ pub async fn nb2a<T, E>(mut f: impl FnMut() -> nb::Result<T, E>) -> Result<T, E>
 		}
 	}
 }
+
+/// Parses a decimal number at compile time. Meant for numbers from env vars that
+/// `build.rs` already validated, so it doesn't check for invalid digits.
+pub const fn parse_u32(s: &str) -> u32 {
+	let bytes = s.as_bytes();
+	let mut result = 0;
+	let mut i = 0;
+	while i < bytes.len() {
+		result = result * 10 + (bytes[i] - b'0') as u32;
+		i += 1;
+	}
+	result
+}
@@ -0,0 +1,89 @@
+//! The interface between the firmware and a sensor fusion algorithm that lives
+//! outside of its tree. Implement [`ExternalFuser`] in your own crate, export it
+//! as `Fuser`, and build the firmware with the `fusion-external` feature, see
+//! `firmware/docs/Building.md`. `fuser_template` is a crate to start from.
+//!
+//! This crate is all that your fuser has to depend on. It is licensed under MIT or
+//! Apache 2.0, so your fuser can keep its own license, and doesn't have to be
+//! published along with the firmware.
+//!
+//! The fuser only does math. It gets the readings after the firmware calibrated
+//! them, and the firmware fills in what the fuser can't tell, like the linear
+//! acceleration and taps.
+
+#![no_std]
+
+/// The version of nalgebra that the firmware uses, so that fusers don't have to
+/// match it by hand.
+pub use nalgebra;
+
+use nalgebra::{UnitQuaternion, Vector3};
+
+/// One sample of the imu. All of it is in the frame of the sensor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reading {
+	/// In m/s^2, including gravity.
+	pub accel: Vector3<f32>,
+	/// In rad/s, with the bias that the firmware calibrated removed.
+	pub gyro: Vector3<f32>,
+	/// In microtesla, if the imu has a magnetometer. Only set with the sample after
+	/// each new measurement, which is much less often than the other two.
+	pub mag: Option<Vector3<f32>>,
+	/// The seconds since the last reading, or since the fuser was created or reset.
+	pub dt: f32,
+}
+
+/// The orientation that a fuser estimated from a [`Reading`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+	/// From the sensor frame to the world frame, in which z points up.
+	pub q: UnitQuaternion<f32>,
+	/// In rad/s, if the fuser knows better than the gyro reading, like by estimating
+	/// its bias.
+	pub angular_velocity: Option<Vector3<f32>>,
+	/// In m/s^2 without gravity, in the sensor frame, if the fuser knows better than
+	/// removing gravity at `q`.
+	pub linear_accel: Option<Vector3<f32>>,
+	/// How well calibrated the fusion is, from `0` (not at all) to `3` (fully), if
+	/// the fuser can tell.
+	pub accuracy: Option<u8>,
+}
+impl Estimate {
+	/// Just an orientation, leaving the rest to the firmware.
+	pub fn new(q: UnitQuaternion<f32>) -> Self {
+		Self {
+			q,
+			angular_velocity: None,
+			linear_accel: None,
+			accuracy: None,
+		}
+	}
+}
+
+/// A sensor fusion algorithm, like the `Fuser`s of the firmware.
+pub trait ExternalFuser {
+	/// Whether `set_accel_gain` is supported, so that the server can be told before
+	/// it tries.
+	const ACCEL_GAIN: bool = false;
+
+	/// Called once for each imu.
+	fn new() -> Self
+	where
+		Self: Sized;
+
+	/// Called for every sample. Intentionally not async, this should only be doing
+	/// math, not io or any internal awaiting.
+	fn process(&mut self, reading: &Reading) -> Estimate;
+
+	/// Forgets everything learned so far, as if the fuser was just created. Keeps the
+	/// gain from `set_accel_gain`.
+	fn reset(&mut self);
+
+	/// Scales how strongly the accelerometer corrects the orientation, by `gain`
+	/// times the default. Returns whether the fuser supports it, which has to match
+	/// [`ACCEL_GAIN`](ExternalFuser::ACCEL_GAIN).
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		let _ = gain;
+		false
+	}
+}
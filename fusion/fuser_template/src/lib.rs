@@ -0,0 +1,104 @@
+//! A template for a fuser that lives outside of the firmware tree, see
+//! [`fuser_api`]. Copy this crate somewhere else, replace the math in
+//! [`Template::process`], and point the `external_fuser` dependency of the
+//! firmware at it.
+//!
+//! As an example, it is a plain complementary filter: it integrates the gyro, and
+//! pulls pitch and roll towards gravity. It ignores the magnetometer, so yaw
+//! drifts.
+
+#![no_std]
+
+use fuser_api::nalgebra::{UnitQuaternion, Vector3};
+use fuser_api::{Estimate, ExternalFuser, Reading};
+
+/// The firmware looks for a type with this name.
+pub type Fuser = Template;
+
+/// How strongly the accelerometer pulls the orientation towards gravity, in rad/s
+/// per radian that it is off.
+const GRAVITY_GAIN: f32 = 0.5;
+
+pub struct Template {
+	q: UnitQuaternion<f32>,
+	/// Scales [`GRAVITY_GAIN`].
+	accel_gain: f32,
+}
+
+impl ExternalFuser for Template {
+	const ACCEL_GAIN: bool = true;
+
+	fn new() -> Self {
+		Self {
+			q: UnitQuaternion::identity(),
+			accel_gain: 1.,
+		}
+	}
+
+	fn process(&mut self, reading: &Reading) -> Estimate {
+		let mut w = reading.gyro;
+		// Rotate towards the measured gravity, around the axis between it and where we
+		// expect it to be.
+		if let Some(measured) = reading.accel.try_normalize(f32::EPSILON) {
+			let expected = self.q.inverse_transform_vector(&Vector3::z());
+			w += measured.cross(&expected) * GRAVITY_GAIN * self.accel_gain;
+		}
+		// `w` is in the sensor frame, so the step comes after the orientation.
+		self.q *= UnitQuaternion::from_scaled_axis(w * reading.dt);
+		Estimate::new(self.q)
+	}
+
+	fn reset(&mut self) {
+		self.q = UnitQuaternion::identity();
+	}
+
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		self.accel_gain = gain;
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const G: f32 = 9.80665;
+
+	fn reading(accel: Vector3<f32>, gyro: Vector3<f32>) -> Reading {
+		Reading {
+			accel,
+			gyro,
+			mag: None,
+			dt: 0.01,
+		}
+	}
+
+	#[test]
+	fn integrates_the_gyro() {
+		let mut fuser = Fuser::new();
+		let turning = reading(Vector3::zeros(), Vector3::new(0., 0., 1.));
+		let mut estimate = fuser.process(&turning);
+		for _ in 1..100 {
+			estimate = fuser.process(&turning);
+		}
+		// One second at 1 rad/s.
+		assert!((estimate.q.angle() - 1.).abs() < 1e-4);
+
+		fuser.reset();
+		let still = reading(Vector3::zeros(), Vector3::zeros());
+		assert!(fuser.process(&still).q.angle() < 1e-6);
+	}
+
+	#[test]
+	fn levels_out_with_gravity() {
+		let mut fuser = Fuser::new();
+		// Lying on its side, so gravity pulls along y.
+		let on_its_side = reading(Vector3::new(0., G, 0.), Vector3::zeros());
+		let mut estimate = fuser.process(&on_its_side);
+		for _ in 1..2000 {
+			estimate = fuser.process(&on_its_side);
+		}
+		let up = estimate.q.inverse_transform_vector(&Vector3::z());
+		assert!((up - Vector3::y()).norm() < 1e-3);
+	}
+}
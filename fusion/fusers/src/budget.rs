@@ -0,0 +1,144 @@
+use core::time::Duration;
+
+use nalgebra::{ComplexField, Vector3};
+
+use crate::{integrate, Clock, Estimate, Fuser, Gyro, Quat, UnfusedData};
+
+const GRAVITY: f32 = 9.81;
+
+/// Below this angular speed in rad/s (after removing the bias) we might be at rest.
+const REST_GYRO: f32 = 0.05;
+/// How far in m/s^2 the acceleration may be from gravity while at rest.
+const REST_ACCEL: f32 = 0.3;
+/// How long both have to hold before we consider ourselves at rest.
+const REST_TIME: Duration = Duration::from_millis(300);
+
+/// How strongly the accelerometer pulls the orientation back towards gravity while
+/// moving, and while at rest.
+const MOVING_GAIN: f32 = 0.5;
+const REST_GAIN: f32 = 5.;
+/// How fast the gyro bias estimate converges while at rest, per sample.
+const BIAS_ALPHA: f32 = 0.01;
+
+/// A fuser for cheap 6-DoF imus like the MPU-6050, which have no magnetometer and
+/// a gyro that drifts a lot.
+///
+/// Whenever the tracker sits still it learns the gyro bias and stops integrating
+/// the gyro entirely, so that yaw doesn't drift away while nothing is moving.
+/// Otherwise it is a complementary filter, correcting pitch and roll with gravity.
+pub struct Budget<C: Clock> {
+	clock: C,
+	q: Quat,
+	bias: Gyro,
+	/// When we started to look like we are at rest.
+	still_since: Option<Duration>,
+	last: Duration,
+	/// Scales [`MOVING_GAIN`] and [`REST_GAIN`].
+	accel_gain: f32,
+}
+
+impl<C: Clock> Budget<C> {
+	pub fn new(clock: C) -> Self {
+		Self {
+			q: Quat::identity(),
+			bias: Gyro::zeros(),
+			still_since: None,
+			last: clock.now(),
+			accel_gain: 1.,
+			clock,
+		}
+	}
+}
+
+impl<C: Clock> Fuser for Budget<C> {
+	fn process(&mut self, unfused: &UnfusedData) -> Estimate {
+		let now = self.clock.now();
+		let dt = (now - self.last).as_secs_f32();
+		self.last = now;
+
+		let UnfusedData { accel, gyro, .. } = unfused;
+		let mut w = gyro - self.bias;
+
+		let looks_still = w.norm() < REST_GYRO
+			&& ComplexField::abs(accel.norm() - GRAVITY) < REST_ACCEL;
+		let still_since = if looks_still {
+			*self.still_since.get_or_insert(now)
+		} else {
+			self.still_since = None;
+			now
+		};
+		let is_rest = now - still_since >= REST_TIME;
+		if is_rest {
+			// All that is left of the gyro reading at rest is its bias.
+			self.bias += (gyro - self.bias) * BIAS_ALPHA;
+			w = Gyro::zeros();
+		}
+
+		// Rotate towards the measured gravity, around the axis between it and where we
+		// expect it to be.
+		let gain = self.accel_gain * if is_rest { REST_GAIN } else { MOVING_GAIN };
+		if let Some(measured) = accel.try_normalize(f32::EPSILON) {
+			let expected = self.q.inverse_transform_vector(&Vector3::z());
+			w += measured.cross(&expected) * gain;
+		}
+
+		self.q = integrate(&self.q, &w, dt);
+
+		Estimate {
+			angular_velocity: Some(gyro - self.bias),
+			..Estimate::new(self.q)
+		}
+	}
+
+	fn reset(&mut self) {
+		*self = Self {
+			accel_gain: self.accel_gain,
+			..Self::new(self.clock.clone())
+		};
+	}
+
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		self.accel_gain = gain;
+		true
+	}
+
+	fn gyro_bias(&self) -> Option<Gyro> {
+		Some(self.bias)
+	}
+
+	fn seed(&mut self, q: &Quat, bias: Option<Gyro>) -> bool {
+		self.q = *q;
+		if let Some(bias) = bias {
+			self.bias = bias;
+		}
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ManualClock;
+	use nalgebra::vector;
+
+	#[test]
+	fn learns_the_bias_at_rest() {
+		let clock = ManualClock::new();
+		let mut budget = Budget::new(&clock);
+		let bias = vector![0.01, 0., 0.];
+		let still = UnfusedData {
+			accel: vector![0., 0., GRAVITY],
+			gyro: bias,
+			mag: None,
+		};
+		let mut fused = budget.process(&still);
+		// Two seconds at 200 Hz.
+		for _ in 0..400 {
+			clock.advance(Duration::from_millis(5));
+			fused = budget.process(&still);
+		}
+		let w = fused.angular_velocity.unwrap();
+		assert!(w.norm() < 0.001, "{w:?}");
+		assert!(fused.q.angle() < 0.01, "{:?}", fused.q);
+	}
+}
@@ -0,0 +1,31 @@
+use core::time::Duration;
+
+/// Where the fusers get the time of each sample from, as the time since some fixed
+/// point like boot. They measure the time between samples themselves, so tests swap
+/// this out to feed them exact steps, instead of however long the test happened to
+/// take. The firmware's `SystemClock` reads the one of embassy.
+pub trait Clock: Clone {
+	fn now(&self) -> Duration;
+}
+
+/// A clock that only moves when told to. Fusers take it by reference, so the test
+/// keeps a hold of it to advance it between samples.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct ManualClock(core::cell::Cell<Duration>);
+#[cfg(test)]
+impl ManualClock {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn advance(&self, by: Duration) {
+		self.0.set(self.0.get() + by);
+	}
+}
+#[cfg(test)]
+impl Clock for &ManualClock {
+	fn now(&self) -> Duration {
+		self.0.get()
+	}
+}
@@ -0,0 +1,45 @@
+use core::time::Duration;
+
+use dcmimu::DCMIMU;
+
+use crate::{Clock, Estimate, Fuser, Quat, UnfusedData};
+
+/// Extended Kalman filtering in direction cosine matrix formation
+pub struct Dcm<C: Clock> {
+	dcm: DCMIMU,
+	clock: C,
+	last: Duration,
+}
+
+impl<C: Clock> Dcm<C> {
+	pub fn new(clock: C) -> Self {
+		Self {
+			dcm: DCMIMU::new(),
+			last: clock.now(),
+			clock,
+		}
+	}
+}
+
+impl<C: Clock> Fuser for Dcm<C> {
+	fn process(&mut self, unfused: &UnfusedData) -> Estimate {
+		let last = self.last;
+		self.last = self.clock.now();
+		let elapsed = (self.last - last).as_secs_f32();
+
+		let UnfusedData { accel, gyro, .. } = unfused;
+
+		// TODO: Check that these euler angle convention matches
+		let (euler, _) = self.dcm.update(
+			(gyro.x, gyro.y, gyro.z),
+			(accel.x, accel.y, accel.z),
+			elapsed,
+		);
+
+		Estimate::new(Quat::from_euler_angles(euler.roll, euler.pitch, euler.yaw))
+	}
+
+	fn reset(&mut self) {
+		*self = Self::new(self.clock.clone());
+	}
+}
@@ -0,0 +1,95 @@
+use core::time::Duration;
+
+use fuser_api::{Estimate, ExternalFuser, Reading};
+
+use crate::{Clock, Fuser, UnfusedData};
+
+/// Longer gaps between samples, like the one before the first, are clamped to this
+/// so that the fuser doesn't integrate the gyro over them.
+const MAX_DT: f32 = 0.1;
+
+/// A fuser from outside of the firmware tree, the `Fuser` of the `external_fuser`
+/// crate, see `fuser_api`. It is handed the time between samples, so it doesn't
+/// need a clock of its own.
+pub struct External<F: ExternalFuser, C: Clock> {
+	clock: C,
+	fuser: F,
+	last: Duration,
+}
+
+impl<F: ExternalFuser, C: Clock> External<F, C> {
+	pub fn new(clock: C) -> Self {
+		Self {
+			fuser: F::new(),
+			last: clock.now(),
+			clock,
+		}
+	}
+}
+
+impl<F: ExternalFuser, C: Clock> Fuser for External<F, C> {
+	fn process(&mut self, unfused: &UnfusedData) -> Estimate {
+		let now = self.clock.now();
+		let dt = (now - self.last).as_secs_f32();
+		self.last = now;
+
+		let UnfusedData { accel, gyro, mag } = *unfused;
+		self.fuser.process(&Reading {
+			accel,
+			gyro,
+			mag,
+			dt: dt.min(MAX_DT),
+		})
+	}
+
+	fn reset(&mut self) {
+		self.fuser.reset();
+		self.last = self.clock.now();
+	}
+
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		self.fuser.set_accel_gain(gain)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Gyro, ManualClock, Quat};
+
+	/// Remembers the time steps it was handed, as the angle around x.
+	struct Steps(f32);
+	impl ExternalFuser for Steps {
+		fn new() -> Self {
+			Self(0.)
+		}
+
+		fn process(&mut self, reading: &Reading) -> Estimate {
+			self.0 += reading.dt;
+			Estimate::new(Quat::from_axis_angle(&Gyro::x_axis(), self.0))
+		}
+
+		fn reset(&mut self) {
+			self.0 = 0.;
+		}
+	}
+
+	#[test]
+	fn hands_over_the_time_between_samples() {
+		let clock = ManualClock::new();
+		let mut external = External::<Steps, _>::new(&clock);
+		let still = UnfusedData {
+			accel: Gyro::zeros(),
+			gyro: Gyro::zeros(),
+			mag: None,
+		};
+		clock.advance(Duration::from_millis(5));
+		assert!((external.process(&still).q.angle() - 0.005).abs() < 1e-6);
+		// Gaps are clamped.
+		clock.advance(Duration::from_secs(10));
+		let fused = external.process(&still);
+		assert!((fused.q.angle() - (0.005 + MAX_DT)).abs() < 1e-6);
+		assert!(fused.angular_velocity.is_none());
+		assert!(!external.set_accel_gain(2.));
+	}
+}
@@ -0,0 +1,318 @@
+use core::time::Duration;
+
+use nalgebra::Quaternion;
+
+use crate::{Clock, Estimate, Fuser, Gyro, Quat, UnfusedData};
+
+/// The fixed point formats, by how many of the bits are the fraction. Accelerations
+/// are in m/s^2 with 16, angular velocities in rad/s with 24, and everything that is
+/// at most 1, like the quaternion and unit vectors, with 30.
+const ACCEL: u32 = 16;
+const GYRO: u32 = 24;
+const UNIT: u32 = 30;
+/// The gains are in the same format as the accelerations.
+const GAIN: u32 = ACCEL;
+
+/// Like [`fixed`], for constants.
+macro_rules! fixed {
+	($x:expr, $bits:expr) => {
+		($x * (1u64 << $bits) as f32) as i32
+	};
+}
+
+const GRAVITY: i32 = fixed!(9.81, ACCEL);
+/// The same as those of [`Budget`](crate::Budget), squared where it saves a root.
+const REST_GYRO_SQ: i64 = (fixed!(0.05, ACCEL) as i64).pow(2);
+const REST_ACCEL: i32 = fixed!(0.3, ACCEL);
+const REST_TIME: Duration = Duration::from_millis(300);
+const MOVING_GAIN: i32 = fixed!(0.5, GAIN);
+const REST_GAIN: i32 = fixed!(5., GAIN);
+const BIAS_ALPHA: i32 = fixed!(0.01, GAIN);
+/// The longest step that we integrate in one go. Longer gaps between samples, like
+/// after the imu stalled, would overflow the format of the step.
+const MAX_STEP_US: i64 = 20_000;
+
+/// [`Budget`](crate::Budget) without floating point, for microcontrollers that have
+/// no FPU. It is the same filter, but its state and all of its math are integers,
+/// so that only converting the readings and the result costs soft floats. Its
+/// orientation keeps up with that of `Budget` to about a tenth of a degree.
+pub struct Fixed<C: Clock> {
+	clock: C,
+	/// `w, i, j, k`, always normalized.
+	q: [i32; 4],
+	bias: [i32; 3],
+	/// When we started to look like we are at rest.
+	still_since: Option<Duration>,
+	last: Duration,
+	/// Scales [`MOVING_GAIN`] and [`REST_GAIN`].
+	accel_gain: i32,
+}
+
+impl<C: Clock> Fixed<C> {
+	pub fn new(clock: C) -> Self {
+		Self {
+			q: [1 << UNIT, 0, 0, 0],
+			bias: [0; 3],
+			still_since: None,
+			last: clock.now(),
+			accel_gain: 1 << GAIN,
+			clock,
+		}
+	}
+}
+
+impl<C: Clock> Fuser for Fixed<C> {
+	fn process(&mut self, unfused: &UnfusedData) -> Estimate {
+		let now = self.clock.now();
+		let dt_us = ((now - self.last).as_micros() as i64).min(MAX_STEP_US);
+		self.last = now;
+
+		let accel: [i32; 3] = unfused.accel.map(|a| fixed(a, ACCEL)).into();
+		let gyro: [i32; 3] = unfused.gyro.map(|g| fixed(g, GYRO)).into();
+		let mut w = gyro;
+		for (w, bias) in w.iter_mut().zip(self.bias) {
+			*w = w.saturating_sub(bias);
+		}
+
+		let accel_norm = isqrt(accel.iter().map(|&a| square(a)).sum()) as i32;
+		let w_sq: i64 = w.iter().map(|&w| square(w >> (GYRO - ACCEL)) as i64).sum();
+		let looks_still =
+			w_sq < REST_GYRO_SQ && (accel_norm - GRAVITY).abs() < REST_ACCEL;
+		let still_since = if looks_still {
+			*self.still_since.get_or_insert(now)
+		} else {
+			self.still_since = None;
+			now
+		};
+		let is_rest = now - still_since >= REST_TIME;
+		if is_rest {
+			// All that is left of the gyro reading at rest is its bias.
+			for (bias, gyro) in self.bias.iter_mut().zip(gyro) {
+				*bias += mul(gyro - *bias, BIAS_ALPHA, GAIN);
+			}
+			w = [0; 3];
+		}
+
+		// Rotate towards the measured gravity, around the axis between it and where we
+		// expect it to be.
+		let gain = mul(
+			self.accel_gain,
+			if is_rest { REST_GAIN } else { MOVING_GAIN },
+			GAIN,
+		);
+		if accel_norm > 0 {
+			let norm = i64::from(accel_norm);
+			let measured = accel.map(|a| ((i64::from(a) << UNIT) / norm) as i32);
+			let correction = cross(&measured, &expected_gravity(&self.q));
+			for (w, correction) in w.iter_mut().zip(correction) {
+				// From a unit vector to an angular velocity.
+				*w = w.saturating_add(mul(correction, gain, GAIN + UNIT - GYRO));
+			}
+		}
+
+		self.q = integrate(&self.q, &w, dt_us);
+
+		let bias = self.gyro_bias().unwrap();
+		Estimate {
+			angular_velocity: Some(unfused.gyro - bias),
+			..Estimate::new(self.orientation())
+		}
+	}
+
+	fn reset(&mut self) {
+		*self = Self {
+			accel_gain: self.accel_gain,
+			..Self::new(self.clock.clone())
+		};
+	}
+
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		self.accel_gain = fixed(gain, GAIN);
+		true
+	}
+
+	fn gyro_bias(&self) -> Option<Gyro> {
+		Some(self.bias.map(|b| float(b, GYRO)).into())
+	}
+
+	fn seed(&mut self, q: &Quat, bias: Option<Gyro>) -> bool {
+		let q = q.quaternion();
+		self.q = [q.w, q.i, q.j, q.k].map(|c| fixed(c, UNIT));
+		self.q = normalize(&self.q);
+		if let Some(bias) = bias {
+			self.bias = [bias.x, bias.y, bias.z].map(|b| fixed(b, GYRO));
+		}
+		true
+	}
+}
+
+impl<C: Clock> Fixed<C> {
+	fn orientation(&self) -> Quat {
+		let [w, i, j, k] = self.q.map(|c| float(c, UNIT));
+		// Normalized already, as far as the format allows.
+		Quat::new_unchecked(Quaternion::new(w, i, j, k))
+	}
+}
+
+/// `x` with `bits` bits of fraction, saturating at the ends of an `i32`.
+fn fixed(x: f32, bits: u32) -> i32 {
+	fixed!(x, bits)
+}
+
+fn float(x: i32, bits: u32) -> f32 {
+	x as f32 / (1u64 << bits) as f32
+}
+
+/// `a * b`, where `b` has `bits` bits of fraction, so that the result has those of
+/// `a`.
+fn mul(a: i32, b: i32, bits: u32) -> i32 {
+	((i64::from(a) * i64::from(b)) >> bits) as i32
+}
+
+fn square(x: i32) -> u64 {
+	let x = u64::from(x.unsigned_abs());
+	x * x
+}
+
+/// The square root, rounded down.
+fn isqrt(n: u64) -> u64 {
+	if n < 2 {
+		return n;
+	}
+	// Newton's method, from a power of two above the root, only ever goes down.
+	let mut x = 1 << ((64 - n.leading_zeros() + 1) / 2);
+	loop {
+		let y = (x + n / x) / 2;
+		if y >= x {
+			return x;
+		}
+		x = y;
+	}
+}
+
+/// Where we expect gravity in the body frame, the z axis rotated by the inverse of
+/// `q`.
+fn expected_gravity(q: &[i32; 4]) -> [i32; 3] {
+	let [w, x, y, z] = *q;
+	let m = |a, b| mul(a, b, UNIT);
+	[
+		2 * (m(x, z) - m(w, y)),
+		2 * (m(y, z) + m(w, x)),
+		(1 << UNIT) - 2 * (m(x, x) + m(y, y)),
+	]
+}
+
+fn cross(a: &[i32; 3], b: &[i32; 3]) -> [i32; 3] {
+	let m = |a, b| mul(a, b, UNIT);
+	[
+		m(a[1], b[2]) - m(a[2], b[1]),
+		m(a[2], b[0]) - m(a[0], b[2]),
+		m(a[0], b[1]) - m(a[1], b[0]),
+	]
+}
+
+/// Rotates `q` by the angular velocity `w` in the body frame, over `dt_us`
+/// microseconds. To first order, which is close enough for the short steps between
+/// samples, and normalizes the result.
+fn integrate(q: &[i32; 4], w: &[i32; 3], dt_us: i64) -> [i32; 4] {
+	// Half of the rotation, from an angular velocity to a unit quaternion part.
+	let half =
+		w.map(|w| (i64::from(w) * dt_us * (1 << (UNIT - GYRO)) / 2_000_000) as i32);
+	let [w0, x0, y0, z0] = *q;
+	let [x1, y1, z1] = half;
+	let m = |a, b| mul(a, b, UNIT);
+	normalize(&[
+		w0 - m(x0, x1) - m(y0, y1) - m(z0, z1),
+		x0 + m(w0, x1) + m(y0, z1) - m(z0, y1),
+		y0 + m(w0, y1) - m(x0, z1) + m(z0, x1),
+		z0 + m(w0, z1) + m(x0, y1) - m(y0, x1),
+	])
+}
+
+fn normalize(q: &[i32; 4]) -> [i32; 4] {
+	let norm = isqrt(q.iter().map(|&c| square(c)).sum()) as i64;
+	if norm == 0 {
+		return [1 << UNIT, 0, 0, 0];
+	}
+	q.map(|c| ((i64::from(c) << UNIT) / norm) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Budget, ManualClock};
+	use nalgebra::vector;
+
+	const STEP: Duration = Duration::from_millis(5);
+
+	#[test]
+	fn learns_the_bias_at_rest() {
+		let clock = ManualClock::new();
+		let mut fixed = Fixed::new(&clock);
+		let bias = vector![0.01, 0., 0.];
+		let still = UnfusedData {
+			accel: vector![0., 0., 9.81],
+			gyro: bias,
+			mag: None,
+		};
+		let mut fused = fixed.process(&still);
+		// Two seconds at 200 Hz.
+		for _ in 0..400 {
+			clock.advance(STEP);
+			fused = fixed.process(&still);
+		}
+		let w = fused.angular_velocity.unwrap();
+		assert!(w.norm() < 0.001, "{w:?}");
+		assert!(fused.q.angle() < 0.01, "{:?}", fused.q);
+	}
+
+	#[test]
+	fn keeps_up_with_budget() {
+		let clock = ManualClock::new();
+		let mut fixed = Fixed::new(&clock);
+		let mut budget = Budget::new(&clock);
+		// Turning and tilting, with gravity tilted a bit against both.
+		let moving = UnfusedData {
+			accel: vector![1., -0.5, 9.7],
+			gyro: vector![0.3, -0.2, 1.],
+			mag: None,
+		};
+		for _ in 0..600 {
+			clock.advance(STEP);
+			let (f, b) = (fixed.process(&moving).q, budget.process(&moving).q);
+			assert!(f.angle_to(&b) < 0.002, "{f:?} {b:?}");
+		}
+	}
+
+	#[test]
+	fn levels_out_from_a_tilt() {
+		let clock = ManualClock::new();
+		let mut fixed = Fixed::new(&clock);
+		let tilted = Quat::from_axis_angle(&nalgebra::Vector3::x_axis(), 0.5);
+		assert!(fixed.seed(&tilted, None));
+		let level = UnfusedData {
+			accel: vector![0., 0., 9.81],
+			gyro: Gyro::zeros(),
+			mag: None,
+		};
+		let mut q = fixed.process(&level).q;
+		for _ in 0..2000 {
+			clock.advance(STEP);
+			q = fixed.process(&level).q;
+		}
+		// Only pitch and roll come back, so this is all of it around x.
+		assert!(q.angle() < 0.01, "{q:?}");
+	}
+
+	#[test]
+	fn square_roots() {
+		for n in [0, 1, 2, 3, 4, 99, 100, 101, u64::from(u32::MAX), u64::MAX] {
+			let root = isqrt(n);
+			assert!(root * root <= n, "{n}");
+			assert!(
+				(root + 1).checked_mul(root + 1).map_or(true, |r| r > n),
+				"{n}"
+			);
+		}
+	}
+}
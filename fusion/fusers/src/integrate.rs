@@ -0,0 +1,30 @@
+//! Integrates angular velocity into an orientation with the quaternion exponential
+//! map. Unlike adding up euler angles, this is exact for a constant angular
+//! velocity no matter how fast the rotation, and has no gimbal lock.
+
+use nalgebra::{ComplexField, Quaternion};
+
+use crate::{Gyro, Quat};
+
+/// Below this half angle in radians, `cos` and `sin(x) / x` are replaced with
+/// their Taylor series. The error is then below `f32` precision, and we save the
+/// square root and trigonometry on the common case of slow rotations.
+const SMALL_HALF_ANGLE: f32 = 0.01;
+
+/// Rotates `q` by the angular velocity `w` in rad/s, in the body frame, over `dt`
+/// seconds.
+pub fn integrate(q: &Quat, w: &Gyro, dt: f32) -> Quat {
+	let half = w * (dt / 2.);
+	let half_angle_sq = half.norm_squared();
+	let (cos, sinc) = if half_angle_sq < SMALL_HALF_ANGLE * SMALL_HALF_ANGLE {
+		(1. - half_angle_sq / 2., 1. - half_angle_sq / 6.)
+	} else {
+		let half_angle = ComplexField::sqrt(half_angle_sq);
+		let (sin, cos) = ComplexField::sin_cos(half_angle);
+		(cos, sin / half_angle)
+	};
+	let dq = Quaternion::from_parts(cos, half * sinc);
+	// The step is a unit quaternion already, this only removes accumulated rounding
+	// errors.
+	Quat::new_normalize(q.quaternion() * dq)
+}
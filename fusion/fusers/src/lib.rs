@@ -0,0 +1,92 @@
+//! The sensor fusion algorithms of the firmware, for the imus that don't fuse
+//! on-chip. They only do math on the calibrated readings, and get the time from a
+//! [`Clock`], so this crate builds and tests on the host, unlike the firmware:
+//!
+//! ```sh
+//! cargo test -p fusers --all-features
+//! ```
+//!
+//! The firmware picks one with a `fusion-*` feature, and wraps it in [`Swappable`]
+//! so the server can switch to the others at runtime.
+
+#![no_std]
+
+mod budget;
+mod clock;
+mod dcm;
+mod external;
+mod fixed;
+mod integrate;
+mod stubbed;
+mod swap;
+#[cfg(feature = "vqf")]
+mod vqf;
+
+pub use self::budget::Budget;
+pub use self::clock::Clock;
+#[cfg(test)]
+use self::clock::ManualClock;
+pub use self::dcm::Dcm;
+pub use self::external::External;
+pub use self::fixed::Fixed;
+pub use self::integrate::integrate;
+pub use self::stubbed::Stubbed;
+pub use self::swap::{Algorithm, Swappable};
+#[cfg(feature = "vqf")]
+pub use self::vqf::Vqf;
+pub use fuser_api::Estimate;
+
+pub type Quat = nalgebra::UnitQuaternion<f32>;
+pub type Accel = nalgebra::Vector3<f32>;
+pub type Gyro = nalgebra::Vector3<f32>;
+pub type Mag = nalgebra::Vector3<f32>;
+
+#[derive(Debug, Clone, Copy)]
+pub struct UnfusedData {
+	pub accel: Accel,
+	pub gyro: Gyro,
+	/// The magnetic field in microtesla, in the same frame as the other two, if the
+	/// imu has a magnetometer. Drivers report it uncalibrated, and only with the
+	/// sample after each new measurement, which is much less often than the others.
+	pub mag: Option<Mag>,
+}
+
+/// Represents a sensor fusion algorithm that will take an imu's `UnfusedData` and
+/// do math to turn it into an [`Estimate`], suitable for use as orientation.
+pub trait Fuser {
+	// Note: Intentionally not async, this should only be doing math, not io or
+	// any internal awaiting.
+	fn process(&mut self, unfused: &UnfusedData) -> Estimate;
+
+	/// Forgets everything learned so far, as if the fuser was just created. Keeps the
+	/// gain from `set_accel_gain`.
+	fn reset(&mut self);
+
+	/// Scales how strongly the accelerometer corrects the orientation, by `gain`
+	/// times the default. Returns whether the fuser supports it.
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		let _ = gain;
+		false
+	}
+
+	/// The gyro bias learned so far, if the fuser learns one, to hand it over to
+	/// another fuser.
+	fn gyro_bias(&self) -> Option<Gyro> {
+		None
+	}
+
+	/// Continues from the orientation `q`, and from `bias` if it learns one, instead
+	/// of the identity, to take over from another fuser. Returns whether the fuser
+	/// supports it.
+	fn seed(&mut self, q: &Quat, bias: Option<Gyro>) -> bool {
+		let _ = (q, bias);
+		false
+	}
+
+	/// Switches to another fusion algorithm, and returns whether the fuser supports
+	/// it. Only [`Swappable`] does.
+	fn switch(&mut self, algorithm: Algorithm) -> bool {
+		let _ = algorithm;
+		false
+	}
+}
@@ -0,0 +1,59 @@
+use core::f32::consts::PI;
+
+use core::time::Duration;
+
+use crate::{Clock, Estimate, Fuser, Quat, UnfusedData};
+
+/// A fake fuser that just rotates around the x axis.
+pub struct Stubbed<C: Clock> {
+	clock: C,
+	start: Duration,
+}
+impl<C: Clock> Stubbed<C> {
+	pub fn new(clock: C) -> Self {
+		Self {
+			start: clock.now(),
+			clock,
+		}
+	}
+}
+impl<C: Clock> Fuser for Stubbed<C> {
+	fn process(&mut self, _unfused: &UnfusedData) -> Estimate {
+		let dt = (self.clock.now() - self.start).as_secs_f32();
+
+		const ROT_RATE: f32 = PI / 2.; // 90 degrees per second
+		Estimate {
+			angular_velocity: Some(nalgebra::Vector3::x() * ROT_RATE),
+			..Estimate::new(Quat::from_axis_angle(
+				&nalgebra::Vector3::x_axis(),
+				dt * ROT_RATE,
+			))
+		}
+	}
+
+	fn reset(&mut self) {
+		self.start = self.clock.now();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Gyro, ManualClock};
+
+	#[test]
+	fn turns_a_quarter_per_second() {
+		let clock = ManualClock::new();
+		let mut stubbed = Stubbed::new(&clock);
+		let still = UnfusedData {
+			accel: Gyro::zeros(),
+			gyro: Gyro::zeros(),
+			mag: None,
+		};
+		clock.advance(Duration::from_secs(1));
+		let q = stubbed.process(&still).q;
+		assert!((q.angle() - PI / 2.).abs() < 1e-5);
+		stubbed.reset();
+		assert!(stubbed.process(&still).q.angle() < 1e-5);
+	}
+}
@@ -0,0 +1,246 @@
+use core::time::Duration;
+
+use firmware_protocol::{FUSION_BUDGET, FUSION_BUILT_IN, FUSION_DCM};
+
+use crate::{Budget, Clock, Dcm, Estimate, Fuser, Gyro, Quat, UnfusedData};
+
+/// How long the orientation takes to go over to that of the new fuser, if they
+/// disagree after switching.
+const BLEND: Duration = Duration::from_millis(500);
+
+/// The fusers that [`Swappable`] switches between. Besides the one of the build,
+/// those that are always built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Algorithm {
+	BuiltIn,
+	Budget,
+	Dcm,
+}
+impl Algorithm {
+	/// From one of the `FUSION_*` constants of the protocol.
+	pub fn from_protocol(value: u32) -> Option<Self> {
+		match value {
+			FUSION_BUILT_IN => Some(Self::BuiltIn),
+			FUSION_BUDGET => Some(Self::Budget),
+			FUSION_DCM => Some(Self::Dcm),
+			_ => None,
+		}
+	}
+
+	pub fn to_protocol(self) -> u32 {
+		match self {
+			Self::BuiltIn => FUSION_BUILT_IN,
+			Self::Budget => FUSION_BUDGET,
+			Self::Dcm => FUSION_DCM,
+		}
+	}
+}
+
+/// A fuser that is used instead of the build's.
+enum Other<C: Clock> {
+	Budget(Budget<C>),
+	Dcm(Dcm<C>),
+}
+
+/// Fades out the difference between the old fuser's orientation and the new one.
+#[derive(Clone, Copy)]
+struct Blend {
+	/// The first sample of the new fuser.
+	since: Duration,
+	/// Rotates the new fuser's orientation into the old one's, as of then.
+	offset: Quat,
+}
+
+/// The fuser of the build, or another one that the server switched to at runtime,
+/// to compare them while wearing the tracker.
+///
+/// The new fuser starts from the orientation and gyro bias of the old one, if it
+/// can. The orientation might still jump, like when the new one can't start from
+/// it or settles somewhere else, so the difference is blended out over [`BLEND`].
+pub struct Swappable<F: Fuser, C: Clock> {
+	clock: C,
+	/// Kept while another one is used, to switch back to.
+	built_in: F,
+	other: Option<Other<C>>,
+	/// From `set_accel_gain`, for the fusers that are switched to.
+	accel_gain: f32,
+	/// The orientation of the last sample, which the next fuser starts from.
+	last: Option<Quat>,
+	/// The last gyro bias that was learned, for the next fuser that learns one.
+	bias: Option<Gyro>,
+	/// Whether the next sample is the first of a new fuser.
+	switched: bool,
+	blend: Option<Blend>,
+}
+impl<F: Fuser, C: Clock> Swappable<F, C> {
+	pub fn new(built_in: F, clock: C) -> Self {
+		Self {
+			clock,
+			built_in,
+			other: None,
+			accel_gain: 1.,
+			last: None,
+			bias: None,
+			switched: false,
+			blend: None,
+		}
+	}
+
+	fn fuser(&mut self) -> &mut dyn Fuser {
+		match &mut self.other {
+			None => &mut self.built_in,
+			Some(Other::Budget(f)) => f,
+			Some(Other::Dcm(f)) => f,
+		}
+	}
+
+	fn algorithm(&self) -> Algorithm {
+		match self.other {
+			None => Algorithm::BuiltIn,
+			Some(Other::Budget(_)) => Algorithm::Budget,
+			Some(Other::Dcm(_)) => Algorithm::Dcm,
+		}
+	}
+}
+impl<F: Fuser, C: Clock> Fuser for Swappable<F, C> {
+	fn process(&mut self, unfused: &UnfusedData) -> Estimate {
+		let mut fused = self.fuser().process(unfused);
+		let now = self.clock.now();
+		if core::mem::take(&mut self.switched) {
+			self.blend = self.last.map(|last| Blend {
+				since: now,
+				offset: last * fused.q.inverse(),
+			});
+		}
+		if let Some(Blend { since, offset }) = self.blend {
+			let elapsed = now - since;
+			if elapsed >= BLEND {
+				self.blend = None;
+			} else {
+				let t = 1. - elapsed.as_secs_f32() / BLEND.as_secs_f32();
+				let identity = Quat::identity();
+				let offset = identity.try_slerp(&offset, t, 1e-6).unwrap_or(offset);
+				fused.q = offset * fused.q;
+			}
+		}
+		self.last = Some(fused.q);
+		fused
+	}
+
+	fn reset(&mut self) {
+		self.fuser().reset();
+		self.last = None;
+		self.switched = false;
+		self.blend = None;
+	}
+
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		self.accel_gain = gain;
+		self.fuser().set_accel_gain(gain)
+	}
+
+	/// The one of the fuser in use, or the last one that was learned.
+	fn gyro_bias(&self) -> Option<Gyro> {
+		let bias = match &self.other {
+			None => self.built_in.gyro_bias(),
+			Some(Other::Budget(f)) => f.gyro_bias(),
+			Some(Other::Dcm(f)) => f.gyro_bias(),
+		};
+		bias.or(self.bias)
+	}
+
+	fn seed(&mut self, q: &Quat, bias: Option<Gyro>) -> bool {
+		self.fuser().seed(q, bias)
+	}
+
+	fn switch(&mut self, algorithm: Algorithm) -> bool {
+		if algorithm == self.algorithm() {
+			return true;
+		}
+		self.bias = self.gyro_bias();
+		let clock = self.clock.clone();
+		self.other = match algorithm {
+			Algorithm::BuiltIn => {
+				self.built_in.reset();
+				None
+			}
+			Algorithm::Budget => Some(Other::Budget(Budget::new(clock))),
+			Algorithm::Dcm => Some(Other::Dcm(Dcm::new(clock))),
+		};
+		let (gain, last, bias) = (self.accel_gain, self.last, self.bias);
+		let fuser = self.fuser();
+		fuser.set_accel_gain(gain);
+		if let Some(q) = last {
+			fuser.seed(&q, bias);
+		}
+		self.switched = true;
+		true
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{ManualClock, Stubbed};
+	use nalgebra::vector;
+
+	const STEP: Duration = Duration::from_millis(5);
+
+	fn still() -> UnfusedData {
+		UnfusedData {
+			accel: vector![0., 0., 9.81],
+			gyro: vector![0.01, 0., 0.],
+			mag: None,
+		}
+	}
+
+	#[test]
+	fn carries_the_orientation_and_bias_over() {
+		let clock = ManualClock::new();
+		let mut fuser = Swappable::new(Budget::new(&clock), &clock);
+		let mut before = fuser.process(&still()).q;
+		for _ in 0..400 {
+			clock.advance(STEP);
+			before = fuser.process(&still()).q;
+		}
+		let bias = fuser.gyro_bias().unwrap();
+		assert!((bias.x - 0.01).abs() < 0.001, "{bias:?}");
+
+		assert!(fuser.switch(Algorithm::Dcm));
+		clock.advance(STEP);
+		let after = fuser.process(&still()).q;
+		assert!(before.angle_to(&after) < 1e-3, "{before:?} {after:?}");
+
+		// DCM doesn't learn a bias, so the budget fuser gets back the one from before.
+		clock.advance(BLEND);
+		fuser.process(&still());
+		assert!(fuser.switch(Algorithm::BuiltIn));
+		assert_eq!(fuser.gyro_bias(), Some(bias));
+	}
+
+	#[test]
+	fn blends_out_the_difference() {
+		let clock = ManualClock::new();
+		let mut fuser = Swappable::new(Stubbed::new(&clock), &clock);
+		clock.advance(Duration::from_millis(400));
+		let stubbed = fuser.process(&still()).q;
+
+		// DCM can't start from another orientation, so it starts over.
+		assert!(fuser.switch(Algorithm::Dcm));
+		let mut dcm = Dcm::new(&clock);
+		let mut step = |by| {
+			clock.advance(by);
+			(fuser.process(&still()).q, dcm.process(&still()).q)
+		};
+		let (first, own) = step(STEP);
+		assert!(stubbed.angle_to(&first) < 1e-3);
+		let difference = first.angle_to(&own);
+		assert!(difference > 0.1);
+
+		let (halfway, own) = step(BLEND / 2);
+		assert!((halfway.angle_to(&own) - difference / 2.).abs() < 0.01);
+		let (done, own) = step(BLEND / 2);
+		assert!(done.angle_to(&own) < 1e-3);
+	}
+}
@@ -0,0 +1,125 @@
+use core::time::Duration;
+
+use nalgebra::ComplexField;
+
+use crate::{Clock, Estimate, Fuser, Gyro, UnfusedData};
+
+/// What we assume the sample period in seconds is, until we have measured it.
+const INITIAL_TS: f32 = 0.01;
+/// Longer gaps between samples, like the one before the first, are clamped to this
+/// so they don't throw off the measured sample period.
+const MAX_DT: f32 = 0.1;
+/// The same for the magnetometer, which measures much less often. Its period is
+/// assumed to be that of the rate it was configured for at first.
+const MAX_MAG_DT: f32 = 1.;
+/// How long yaw keeps being corrected after the last magnetometer reading. If the
+/// readings stop, say because the magnetometer failed, we fall back to 6-DoF.
+const MAG_TIMEOUT: Duration = Duration::from_secs(2);
+/// How quickly the measured sample period follows changes, per sample.
+const DT_ALPHA: f32 = 0.05;
+/// How far the measured sample period may drift from the one the filters are tuned
+/// for, as a fraction of it, before they are tuned again.
+const RETUNE_FRACTION: f32 = 0.2;
+
+/// [VQF](https://github.com/dlaidig/vqf), which corrects pitch and roll with
+/// low pass filtered gravity, and estimates the gyro bias all the time. At rest the
+/// bias is learned from the gyro directly, and while moving from how far it would
+/// tilt the orientation away from gravity.
+///
+/// With a magnetometer it also corrects yaw, unless the field looks disturbed by
+/// nearby metal. Without one, yaw only stays put thanks to the bias estimate. The
+/// magnetometer is tuned for its own, lower rate, so each yaw correction is spread
+/// over the samples until its next reading.
+///
+/// VQF expects a fixed sample rate. Ours changes when the rate is scaled down under
+/// load, so the filters get tuned again whenever the measured rate has moved away.
+pub struct Vqf<C: Clock> {
+	clock: C,
+	/// How often the magnetometer measures, for the initial `mag_ts`.
+	mag_rate_hz: u32,
+	vqf: ::vqf::Vqf,
+	/// The sample period in seconds that the filters are tuned for.
+	ts: f32,
+	/// The measured sample period in seconds.
+	dt: f32,
+	last: Duration,
+	mag_ts: f32,
+	mag_dt: f32,
+	last_mag: Option<Duration>,
+	/// Divides the time constant of the accelerometer filter.
+	accel_gain: f32,
+}
+
+impl<C: Clock> Vqf<C> {
+	/// For a magnetometer that measures `mag_rate_hz` times per second.
+	pub fn new(clock: C, mag_rate_hz: u32) -> Self {
+		let mag_ts = 1. / mag_rate_hz as f32;
+		Self {
+			vqf: ::vqf::Vqf::new(INITIAL_TS, INITIAL_TS, mag_ts, Default::default()),
+			mag_rate_hz,
+			ts: INITIAL_TS,
+			dt: INITIAL_TS,
+			last: clock.now(),
+			mag_ts,
+			mag_dt: mag_ts,
+			last_mag: None,
+			accel_gain: 1.,
+			clock,
+		}
+	}
+}
+
+impl<C: Clock> Fuser for Vqf<C> {
+	fn process(&mut self, unfused: &UnfusedData) -> Estimate {
+		let now = self.clock.now();
+		let dt = (now - self.last).as_secs_f32();
+		self.last = now;
+		self.dt += (dt.min(MAX_DT) - self.dt) * DT_ALPHA;
+		let UnfusedData { accel, gyro, mag } = unfused;
+		if mag.is_some() {
+			if let Some(last) = self.last_mag {
+				let dt = (now - last).as_secs_f32();
+				self.mag_dt += (dt.min(MAX_MAG_DT) - self.mag_dt) * DT_ALPHA;
+			}
+			self.last_mag = Some(now);
+		}
+		let drifted =
+			|dt: f32, ts: f32| ComplexField::abs(dt - ts) > ts * RETUNE_FRACTION;
+		if drifted(self.dt, self.ts) || drifted(self.mag_dt, self.mag_ts) {
+			self.ts = self.dt;
+			self.mag_ts = self.mag_dt;
+			self.vqf.setTs(self.ts, self.ts, self.mag_ts);
+		}
+
+		self.vqf.update(*gyro, *accel, *mag);
+		let has_mag = self.last_mag.map_or(false, |t| now - t < MAG_TIMEOUT);
+		let q = if has_mag {
+			self.vqf.getQuat9D()
+		} else {
+			self.vqf.getQuat6D()
+		};
+
+		Estimate {
+			angular_velocity: Some(gyro - self.vqf.getBiasEstimate()),
+			..Estimate::new(q)
+		}
+	}
+
+	fn reset(&mut self) {
+		let gain = self.accel_gain;
+		*self = Self::new(self.clock.clone(), self.mag_rate_hz);
+		self.set_accel_gain(gain);
+	}
+
+	/// A higher gain makes pitch and roll follow the accelerometer more quickly.
+	fn set_accel_gain(&mut self, gain: f32) -> bool {
+		self.accel_gain = gain;
+		let tau_acc = ::vqf::VqfParameters::default().tauAcc / gain;
+		self.vqf.setTauAcc(tau_acc);
+		true
+	}
+
+	fn gyro_bias(&self) -> Option<Gyro> {
+		Some(self.vqf.getBiasEstimate())
+	}
+}
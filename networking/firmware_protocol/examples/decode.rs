@@ -0,0 +1,69 @@
+//! Decodes tracker packets on the PC, to see what a tracker and the server are
+//! telling each other. Reads one packet per line from stdin, as hex like Wireshark's
+//! "Copy as Hex Stream", and prints what it contains:
+//! ```bash
+//! echo 0000000a000000000000000701020304 | cargo run --example decode
+//! ```
+//! Pass `--checked` for packets that end in a CRC-32, once the tracker turned on
+//! `CAPABILITY_CRC32`.
+
+use firmware_protocol::deku::ctx::Endian;
+use firmware_protocol::deku::{DekuEnumExt, DekuRead, DekuWrite};
+use firmware_protocol::{CbPacket, DeserializeError, Packet, SbPacket};
+use std::io::BufRead;
+
+fn main() {
+	let checked = std::env::args().any(|arg| arg == "--checked");
+	for (i, line) in std::io::stdin().lock().lines().enumerate() {
+		let line = line.expect("Failed to read stdin");
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let Some(bytes) = parse_hex(line) else {
+			println!("{}: not hex", i + 1);
+			continue;
+		};
+		println!("{}: {}", i + 1, decode(&bytes, checked));
+	}
+}
+
+/// Tries both directions, since most packets only make sense in one of them.
+fn decode(bytes: &[u8], checked: bool) -> String {
+	let sb = parse::<SbPacket>(bytes, checked);
+	let cb = parse::<CbPacket>(bytes, checked);
+	match (sb, cb) {
+		(Ok((seq, sb)), _) => format!("to server, seq {seq}: {sb:?}"),
+		(_, Ok((seq, cb))) => format!("to tracker, seq {seq}: {cb:?}"),
+		(Err(sb), Err(cb)) => format!("not a packet ({sb:?}, or {cb:?})"),
+	}
+}
+
+fn parse<D>(bytes: &[u8], checked: bool) -> Result<(u64, D), DeserializeError>
+where
+	for<'a> D: DekuRead<'a, (Endian, u32)>
+		+ DekuWrite<(Endian, u32)>
+		+ DekuEnumExt<'static, u32>,
+{
+	let packet = if checked {
+		Packet::<D>::deserialize_from_checked(bytes)?
+	} else {
+		Packet::<D>::deserialize_from(bytes)?
+	};
+	Ok(packet.split())
+}
+
+/// Ignores whitespace and colons between bytes.
+fn parse_hex(line: &str) -> Option<Vec<u8>> {
+	let digits: Vec<u8> = line
+		.bytes()
+		.filter(|b| !b.is_ascii_whitespace() && *b != b':')
+		.collect();
+	if digits.len() % 2 != 0 {
+		return None;
+	}
+	digits
+		.chunks(2)
+		.map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+		.collect()
+}
@@ -0,0 +1,11 @@
+#![no_main]
+
+use firmware_protocol::{CbPacket, Packet, SbPacket};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+	// Both ends of the connection parse whatever shows up on the socket, so neither
+	// may panic, no matter the input.
+	let _ = Packet::<SbPacket>::deserialize_from(data);
+	let _ = Packet::<CbPacket>::deserialize_from(data);
+});
@@ -1,6 +1,9 @@
 use alloc::format;
+use alloc::vec::Vec;
 use deku::prelude::*;
 
+use crate::{BoardType, McuType, SlimeString};
+
 #[derive(Debug, PartialEq, Eq, DekuRead, DekuWrite)]
 #[deku(ctx = "_: deku::ctx::Endian, tag: u32", id = "tag", endian = "big")]
 #[non_exhaustive]
@@ -20,8 +23,157 @@ pub enum CbPacket {
 		/// Char. SlimeVR Server sends '5' = 53
 		version: u8,
 	},
+	// Packet ids starting at 1000 are extensions specific to SlimeVR-Rust, see
+	// `SbPacket`.
+	/// Pauses or resumes a sensor, without needing to reboot the tracker.
+	#[deku(id = "1000")]
+	SetSensorEnabled { sensor_id: u8, enabled: bool },
+	/// Turns on the capabilities in `flags` that the tracker advertised with
+	/// `SbPacket::Capabilities`, and turns off all others.
+	#[deku(id = "1001")]
+	EnableCapabilities { flags: u32 },
+	/// Streams a sensor's raw samples with `SbPacket::RawImuData` for `duration_ms`,
+	/// for calibration and filter tuning tools. `0` stops an ongoing stream.
+	#[deku(id = "1002")]
+	StreamRawData { sensor_id: u8, duration_ms: u32 },
+	/// Starts one of the tracker's calibration routines for a sensor, which stores its
+	/// result on the tracker. See [`CALIBRATE_GYRO_BIAS`], [`CALIBRATE_ACCEL`] and
+	/// [`CALIBRATE_MAG`].
+	#[deku(id = "1003")]
+	Calibrate { sensor_id: u8, routine: u8 },
+	/// Reinitializes a sensor and its fusion, to recover from a glitch without
+	/// rebooting the tracker. The connection to the server stays up meanwhile.
+	#[deku(id = "1004")]
+	RestartSensor { sensor_id: u8 },
+	/// Relays a reset that the user started on the server to a sensor, so that the
+	/// orientation it reports starts over the same way whether it is fused on the
+	/// tracker or on the server. See [`RESET_YAW`] and [`RESET_FULL`].
+	#[deku(id = "1005")]
+	ResetOrientation { sensor_id: u8, kind: u8 },
+	/// Starts updating the firmware over the air with an image of `size` bytes, whose
+	/// [`crc32`](crate::crc32) is `crc32`. Replaces an update that was in progress.
+	///
+	/// The image is then sent in order with `OtaData`, waiting for the
+	/// `SbPacket::OtaStatus` of each chunk before sending the next. `OtaEnd` finishes
+	/// the update, after which the tracker reboots into the new firmware.
+	#[deku(id = "1006")]
+	OtaBegin { size: u32, crc32: u32 },
+	/// A chunk of the image at `offset`, of at most [`OTA_MAX_CHUNK`] bytes.
+	#[deku(id = "1007")]
+	OtaData {
+		offset: u32,
+		/// Has to match the length of `data`.
+		len: u16,
+		#[deku(count = "len")]
+		data: Vec<u8>,
+	},
+	#[deku(id = "1008")]
+	OtaEnd,
+	/// Offers firmware `version` for trackers with `mcu` and `board`. Instead of
+	/// having it pushed with `OtaBegin`, the tracker downloads it from the server
+	/// over HTTP, from `port` at `path`. Like with `OtaBegin`, the image has `size`
+	/// bytes and the [`crc32`](crate::crc32) `crc32`.
+	///
+	/// While it downloads, the tracker reports how far it got with
+	/// `SbPacket::OtaStatus`, and reboots into the new firmware once it is done.
+	/// One that doesn't take the offer answers with
+	/// [`OTA_INCOMPATIBLE`](crate::OTA_INCOMPATIBLE) or
+	/// [`OTA_UP_TO_DATE`](crate::OTA_UP_TO_DATE) instead.
+	#[deku(id = "1009")]
+	FirmwareOffer {
+		mcu: McuType,
+		board: BoardType,
+		/// Like `0.1.0`, what follows `SlimeVR-Rust ` in the firmware string of the
+		/// handshake.
+		version: SlimeString,
+		port: u16,
+		/// Starts with a `/`.
+		path: SlimeString,
+		size: u32,
+		crc32: u32,
+	},
+	/// Changes one of the tracker's settings, and stores it so that it survives
+	/// reboots. `key` is one of the `CONFIG_*` constants, which also say what
+	/// `value` means. The tracker answers with `SbPacket::ConfigStatus`.
+	#[deku(id = "1010")]
+	SetConfig { key: u8, value: u32 },
+	/// Splits every `period_ms` into `count` transmit slots, so that lots of trackers
+	/// on one channel don't all send at the same time. Each tracker picks its slot
+	/// from its MAC address, and starts the periods over with every `Heartbeat`.
+	/// A `count` of `0` turns the slots off again.
+	#[deku(id = "1011")]
+	TxSlots { count: u16, period_ms: u16 },
+}
+impl CbPacket {
+	/// Whether this is one of the extensions of SlimeVR-Rust, with an id from 1000.
+	/// Servers that send these also take ours.
+	pub fn is_extension(&self) -> bool {
+		matches!(
+			self,
+			Self::SetSensorEnabled { .. }
+				| Self::EnableCapabilities { .. }
+				| Self::StreamRawData { .. }
+				| Self::Calibrate { .. }
+				| Self::RestartSensor { .. }
+				| Self::ResetOrientation { .. }
+				| Self::OtaBegin { .. }
+				| Self::OtaData { .. }
+				| Self::OtaEnd | Self::FirmwareOffer { .. }
+				| Self::SetConfig { .. }
+				| Self::TxSlots { .. }
+		)
+	}
 }
 
+/// Measures the gyro bias once the tracker lies still.
+pub const CALIBRATE_GYRO_BIAS: u8 = 0;
+/// Measures the accelerometer offsets and scales, from the tracker lying still on
+/// each of its six sides in turn.
+pub const CALIBRATE_ACCEL: u8 = 1;
+/// Measures the hard and soft iron distortion of the magnetometer, while the tracker
+/// is turned around in every direction.
+pub const CALIBRATE_MAG: u8 = 2;
+
+/// Makes the current heading the new zero of yaw.
+pub const RESET_YAW: u8 = 0;
+/// Like [`RESET_YAW`], and also estimates which way is down again from the
+/// accelerometer.
+pub const RESET_FULL: u8 = 1;
+
+/// The most rotations per second that are sent for each sensor, or `0` for as many
+/// as it measures. Raw streams aren't limited.
+pub const CONFIG_ROTATION_RATE: u8 = 0;
+/// The most power that Wi-Fi transmits with, in quarters of a dBm, from 8 to 84.
+pub const CONFIG_TX_POWER: u8 = 1;
+/// How strongly the fusion corrects the orientation with the accelerometer, in
+/// thousandths of its default. Higher values drift less, but are noisier.
+pub const CONFIG_FUSION_ACCEL_GAIN: u8 = 2;
+/// Whether the status LED is used, `0` or `1`.
+pub const CONFIG_LED: u8 = 3;
+/// Which fusion algorithm the tracker uses, one of the `FUSION_*` constants. The
+/// orientation carries over to the new one, so this can be switched back and forth
+/// to compare them. Trackers that fuse on-chip don't support it.
+pub const CONFIG_FUSION: u8 = 4;
+
+/// The fusion the firmware was built with.
+pub const FUSION_BUILT_IN: u32 = 0;
+/// A complementary filter that stops integrating the gyro while the tracker lies
+/// still, for cheap 6-DoF imus.
+pub const FUSION_BUDGET: u32 = 1;
+/// An extended Kalman filter in direction cosine matrix form.
+pub const FUSION_DCM: u32 = 2;
+
+/// The most bytes that one `CbPacket::OtaData` can carry.
+pub const OTA_MAX_CHUNK: usize = 1024;
+
+/// Marks the end of a signed image, see [`OTA_SIGNATURE_LEN`].
+pub const OTA_SIGNATURE_MAGIC: [u8; 8] = *b"SLVR-SIG";
+/// Trackers built with a key for updates only take images signed with it. Those
+/// end in [`OTA_SIGNATURE_MAGIC`] and then the ed25519 signature of the SHA-256 of
+/// the image before both, which make up the last this many bytes. `size` and
+/// `crc32` of `CbPacket::OtaBegin` and `CbPacket::FirmwareOffer` cover all of it.
+pub const OTA_SIGNATURE_LEN: usize = OTA_SIGNATURE_MAGIC.len() + 64;
+
 #[cfg(test)]
 mod tests {
 	use crate::*;
@@ -53,6 +205,171 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn set_sensor_enabled() {
+		test(
+			CbPacket::SetSensorEnabled {
+				sensor_id: 1,
+				enabled: false,
+			},
+			&[
+				1, // ID
+				0, // Enabled
+			],
+		);
+	}
+
+	#[test]
+	fn enable_capabilities() {
+		test(
+			CbPacket::EnableCapabilities {
+				flags: CAPABILITY_CRC32,
+			},
+			&[
+				0, 0, 0, 1, // Flags
+			],
+		);
+	}
+
+	#[test]
+	fn stream_raw_data() {
+		test(
+			CbPacket::StreamRawData {
+				sensor_id: 1,
+				duration_ms: 10_000,
+			},
+			&[
+				1, // ID
+				0, 0, 0x27, 0x10, // Duration
+			],
+		);
+	}
+
+	#[test]
+	fn calibrate() {
+		test(
+			CbPacket::Calibrate {
+				sensor_id: 1,
+				routine: CALIBRATE_ACCEL,
+			},
+			&[
+				1, // ID
+				1, // Routine
+			],
+		);
+	}
+
+	#[test]
+	fn restart_sensor() {
+		test(
+			CbPacket::RestartSensor { sensor_id: 1 },
+			&[
+				1, // ID
+			],
+		);
+	}
+
+	#[test]
+	fn reset_orientation() {
+		test(
+			CbPacket::ResetOrientation {
+				sensor_id: 1,
+				kind: RESET_FULL,
+			},
+			&[
+				1, // ID
+				1, // Kind
+			],
+		);
+	}
+
+	#[test]
+	fn ota_begin() {
+		test(
+			CbPacket::OtaBegin {
+				size: 0x01020304,
+				crc32: 0x05060708,
+			},
+			&[
+				1, 2, 3, 4, // Size
+				5, 6, 7, 8, // CRC
+			],
+		);
+	}
+
+	#[test]
+	fn ota_data() {
+		test(
+			CbPacket::OtaData {
+				offset: 0x1000,
+				len: 3,
+				data: alloc::vec![7, 8, 9],
+			},
+			&[
+				0, 0, 0x10, 0, // Offset
+				0, 3, // Length
+				7, 8, 9, // Data
+			],
+		);
+	}
+
+	#[test]
+	fn ota_end() {
+		test(CbPacket::OtaEnd, &[]);
+	}
+
+	#[test]
+	fn firmware_offer() {
+		test(
+			CbPacket::FirmwareOffer {
+				mcu: McuType::Esp32,
+				board: BoardType::Custom,
+				version: SlimeString::from("0.1"),
+				port: 8080,
+				path: SlimeString::from("/fw"),
+				size: 0x01020304,
+				crc32: 0x05060708,
+			},
+			&[
+				0, 0, 0, 2, // MCU
+				0, 0, 0, 4, // Board
+				3, b'0', b'.', b'1', // Version
+				0x1f, 0x90, // Port
+				3, b'/', b'f', b'w', // Path
+				1, 2, 3, 4, // Size
+				5, 6, 7, 8, // CRC
+			],
+		);
+	}
+
+	#[test]
+	fn set_config() {
+		test(
+			CbPacket::SetConfig {
+				key: CONFIG_FUSION_ACCEL_GAIN,
+				value: 1500,
+			},
+			&[
+				2, // Key
+				0, 0, 0x05, 0xdc, // Value
+			],
+		);
+	}
+
+	#[test]
+	fn tx_slots() {
+		test(
+			CbPacket::TxSlots {
+				count: 12,
+				period_ms: 10,
+			},
+			&[
+				0, 12, // Count
+				0, 10, // Period
+			],
+		);
+	}
+
 	#[test]
 	fn handshake_response() {
 		// 3"Hey" -> [3, 72, 101, 121] -> 55076217
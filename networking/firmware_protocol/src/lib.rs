@@ -94,12 +94,49 @@ impl From<String> for SlimeString {
 }
 
 impl SlimeString {
-	#[allow(dead_code)]
-	fn to_string(&self) -> Result<String, FromUtf8Error> {
+	pub fn to_string(&self) -> Result<String, FromUtf8Error> {
 		String::from_utf8(self.data.clone())
 	}
 }
 
+/// The protocol version that trackers send as `build` in `SbPacket::Handshake`.
+/// Servers before version 9 don't understand our packets, and the java server only
+/// checks for this number. Bump it together with the server when the wire format
+/// changes in a way that older servers can't parse.
+pub const PROTOCOL_BUILD: i32 = 10;
+
+/// The server version that the packets here are written against, the digit in
+/// `CbPacket::HandshakeResponse`. Newer servers still take them, but older ones
+/// can't parse them.
+pub const SERVER_VERSION: u8 = b'5';
+
+/// Every packet in both directions is followed by a big endian CRC-32 of the packet,
+/// to catch corruption that the UDP checksum missed on a flaky link. See
+/// [`Packet::serialize_into_checked`].
+pub const CAPABILITY_CRC32: u32 = 1 << 0;
+/// How many bytes [`CAPABILITY_CRC32`] adds to each packet.
+pub const CRC32_LEN: usize = 4;
+
+/// The CRC-32 used by ethernet and zlib. Computed bit by bit, since packets are
+/// small and a table would cost 1K of flash.
+pub fn crc32(bytes: &[u8]) -> u32 {
+	crc32_continue(0, bytes)
+}
+
+/// Continues `crc`, the [`crc32`] of the bytes so far, over `bytes`. Lets the CRC of
+/// something that doesn't fit into memory be computed piece by piece.
+pub fn crc32_continue(crc: u32, bytes: &[u8]) -> u32 {
+	let mut crc = !crc;
+	for &byte in bytes {
+		crc ^= u32::from(byte);
+		for _ in 0..8 {
+			let mask = (crc & 1).wrapping_neg();
+			crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+		}
+	}
+	!crc
+}
+
 #[derive(Debug, PartialEq, Eq, DekuRead, DekuWrite)]
 #[deku(endian = "big")]
 pub struct Packet<D>
@@ -155,6 +192,32 @@ where
 		}
 	}
 
+	/// Like [`Self::serialize_into`], but followed by the CRC-32 of the packet, for
+	/// when [`CAPABILITY_CRC32`] is enabled.
+	pub fn serialize_into_checked(
+		&self,
+		buf: &mut [u8],
+	) -> Result<usize, SerializeError> {
+		let len = buf.len().saturating_sub(CRC32_LEN);
+		let len = self.serialize_into(&mut buf[..len])?;
+		let crc = crc32(&buf[..len]);
+		buf[len..len + CRC32_LEN].copy_from_slice(&crc.to_be_bytes());
+		Ok(len + CRC32_LEN)
+	}
+
+	/// Like [`Self::deserialize_from`], but expects the packet to be followed by its
+	/// CRC-32, for when [`CAPABILITY_CRC32`] is enabled.
+	pub fn deserialize_from_checked(buf: &[u8]) -> Result<Self, DeserializeError> {
+		let Some(len) = buf.len().checked_sub(CRC32_LEN) else {
+			return Err(DeserializeError::BadChecksum);
+		};
+		let (packet, crc) = buf.split_at(len);
+		if crc32(packet).to_be_bytes() != crc {
+			return Err(DeserializeError::BadChecksum);
+		}
+		Self::deserialize_from(packet)
+	}
+
 	/// Returns a tuple of the sequence number, and the `PacketData`.
 	pub fn split(self) -> (u64, D) {
 		(self.seq, self.data)
@@ -177,6 +240,8 @@ pub enum DeserializeError {
 	Deku(::deku::DekuError),
 	/// Unexpectedly had bytes remaining after deserialization.
 	BytesRemaining,
+	/// The CRC-32 at the end of the packet didn't match, or was missing.
+	BadChecksum,
 }
 impl From<::deku::DekuError> for DeserializeError {
 	fn from(deku: ::deku::DekuError) -> Self {
@@ -259,4 +324,216 @@ mod tests {
 			);
 		}
 	}
+
+	#[test]
+	fn crc32_check_value() {
+		// The standard check value for this CRC
+		assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+		assert_eq!(crc32(b""), 0);
+	}
+
+	#[test]
+	fn crc32_in_pieces() {
+		let crc = crc32_continue(crc32(b"1234"), b"56789");
+		assert_eq!(crc, crc32(b"123456789"));
+	}
+
+	#[test]
+	fn checked_round_trip() {
+		let packet = Packet::new(7, Dummy::D2 { val: 42 });
+		let mut buf = [0; 32];
+		let len = packet.serialize_into_checked(&mut buf).unwrap();
+		assert_eq!(len, 16 + CRC32_LEN);
+		assert_eq!(
+			Packet::<Dummy>::deserialize_from_checked(&buf[..len]),
+			Ok(packet)
+		);
+
+		// A packet without a checksum is rejected
+		assert_eq!(
+			Packet::<Dummy>::deserialize_from_checked(&buf[..len - CRC32_LEN]),
+			Err(DeserializeError::BadChecksum)
+		);
+		// As is a flipped bit anywhere
+		for i in 0..len * 8 {
+			let mut corrupted = buf;
+			corrupted[i / 8] ^= 1 << (i % 8);
+			assert_eq!(
+				Packet::<Dummy>::deserialize_from_checked(&corrupted[..len]),
+				Err(DeserializeError::BadChecksum)
+			);
+		}
+	}
+
+	#[test]
+	fn checked_buffer_too_small() {
+		let packet = Packet::new(7, Dummy::D2 { val: 42 });
+		let mut buf = [0; 16 + CRC32_LEN - 1];
+		assert_eq!(
+			packet.serialize_into_checked(&mut buf),
+			Err(SerializeError::BufferTooSmall)
+		);
+	}
+}
+
+/// Property tests for parsing untrusted input. Anything can show up on the UDP port,
+/// so no input may make the parser panic.
+#[cfg(test)]
+mod proptests {
+	use super::*;
+
+	use proptest::collection::vec;
+	use proptest::prelude::*;
+
+	/// A tag and sequence number, followed by `data`.
+	fn with_header(tag: u32, seq: u64, data: &[u8]) -> Vec<u8> {
+		let mut bytes = Vec::new();
+		bytes.extend(tag.to_be_bytes());
+		bytes.extend(seq.to_be_bytes());
+		bytes.extend(data);
+		bytes
+	}
+
+	fn handshake(firmware: &str) -> SbPacket {
+		SbPacket::Handshake {
+			board: BoardType::Custom,
+			imu: ImuType::Bno085,
+			mcu: McuType::Esp32,
+			imu_info: (0, 0, 0),
+			build: 10,
+			firmware: SlimeString::from(firmware),
+			mac_address: [1, 2, 3, 4, 5, 6],
+		}
+	}
+
+	fn sb_packet() -> impl Strategy<Value = SbPacket> {
+		prop_oneof![
+			Just(SbPacket::Heartbeat),
+			Just(SbPacket::Sleeping),
+			any::<[u8; 4]>().prop_map(|challenge| SbPacket::Ping { challenge }),
+			any::<[f32; 2]>()
+				.prop_map(|[voltage, level]| SbPacket::BatteryLevel { voltage, level }),
+			(any::<u8>(), any::<[f32; 4]>(), any::<u8>()).prop_map(
+				|(sensor_id, [i, j, k, w], calibration_info)| SbPacket::RotationData {
+					sensor_id,
+					data_type: SensorDataType::Normal,
+					quat: SlimeQuaternion { i, j, k, w },
+					calibration_info,
+				}
+			),
+			(any::<u8>(), any::<f32>()).prop_map(|(sensor_id, temperature)| {
+				SbPacket::Temperature {
+					sensor_id,
+					temperature,
+				}
+			}),
+			(any::<u8>(), any::<u8>()).prop_map(|(sensor_id, error)| {
+				SbPacket::SensorError { sensor_id, error }
+			}),
+			// `SlimeString` only has a single byte for its length
+			"[a-zA-Z0-9.-]{0,255}".prop_map(|firmware| handshake(&firmware)),
+			(any::<u8>(), any::<u8>(), "[ -~]{0,255}").prop_map(
+				|(sensor_id, code, message)| SbPacket::Diagnostic {
+					sensor_id,
+					code,
+					message: message.into(),
+				}
+			),
+			any::<u32>().prop_map(|flags| SbPacket::Capabilities { flags }),
+			(any::<u8>(), any::<u32>(), any::<u8>()).prop_map(
+				|(key, value, status)| {
+					SbPacket::ConfigStatus { key, value, status }
+				}
+			),
+			(any::<u32>(), any::<u8>())
+				.prop_map(|(offset, status)| SbPacket::OtaStatus { offset, status }),
+			(any::<u8>(), any::<u64>()).prop_map(|(sensor_id, unique_id)| {
+				SbPacket::SensorUniqueId {
+					sensor_id,
+					unique_id,
+				}
+			}),
+			(any::<u8>(), any::<u32>(), any::<[f32; 6]>()).prop_map(
+				|(sensor_id, timestamp_us, [ax, ay, az, gx, gy, gz])| {
+					SbPacket::RawImuData {
+						sensor_id,
+						timestamp_us,
+						accel: (ax, ay, az),
+						gyro: (gx, gy, gz),
+					}
+				}
+			),
+		]
+	}
+
+	proptest! {
+		#[test]
+		fn garbage_never_panics(bytes in vec(any::<u8>(), 0..512)) {
+			let _ = Packet::<SbPacket>::deserialize_from(&bytes);
+			let _ = Packet::<CbPacket>::deserialize_from(&bytes);
+		}
+
+		#[test]
+		fn garbage_after_known_tag_never_panics(
+			tag in prop_oneof![0u32..32, 1000u32..1010],
+			seq: u64,
+			data in vec(any::<u8>(), 0..128),
+		) {
+			let bytes = with_header(tag, seq, &data);
+			let _ = Packet::<SbPacket>::deserialize_from(&bytes);
+			let _ = Packet::<CbPacket>::deserialize_from(&bytes);
+		}
+
+		#[test]
+		fn unknown_tags_are_rejected(tag in 22u32..1000, seq: u64) {
+			let bytes = with_header(tag, seq, &[]);
+			prop_assert!(Packet::<SbPacket>::deserialize_from(&bytes).is_err());
+		}
+
+		#[test]
+		fn round_trip(packet in sb_packet(), seq: u64) {
+			let bytes = Packet::new(seq, packet).to_bytes().unwrap();
+			let parsed = Packet::<SbPacket>::deserialize_from(&bytes).unwrap();
+			// Compare bytes instead of packets, since NaNs aren't equal to themselves
+			prop_assert_eq!(parsed.to_bytes().unwrap(), bytes);
+		}
+
+		#[test]
+		fn extensions_have_their_own_ids(packet in sb_packet(), seq: u64) {
+			let is_extension = packet.is_extension();
+			let bytes = Packet::new(seq, packet).to_bytes().unwrap();
+			let tag = u32::from_be_bytes(bytes[..4].try_into().unwrap());
+			prop_assert_eq!(is_extension, tag >= 1000);
+		}
+
+		#[test]
+		fn checked_round_trip(packet in sb_packet(), seq: u64) {
+			let packet = Packet::new(seq, packet);
+			let mut buf = [0; 512];
+			let len = packet.serialize_into_checked(&mut buf).unwrap();
+			let parsed = Packet::<SbPacket>::deserialize_from_checked(&buf[..len]).unwrap();
+			prop_assert_eq!(parsed.to_bytes().unwrap(), packet.to_bytes().unwrap());
+		}
+
+		#[test]
+		fn truncated_packets_are_rejected(packet in sb_packet(), seq: u64, cut: usize) {
+			let bytes = Packet::new(seq, packet).to_bytes().unwrap();
+			let len = cut % bytes.len();
+			prop_assert!(Packet::<SbPacket>::deserialize_from(&bytes[..len]).is_err());
+		}
+
+		#[test]
+		fn string_length_past_end_is_rejected(
+			count in 1u8..,
+			data in vec(any::<u8>(), 0..255),
+		) {
+			prop_assume!(data.len() < count as usize);
+			let mut bytes = Packet::new(0, handshake("")).to_bytes().unwrap();
+			// Chop off the empty string and the mac address
+			bytes.truncate(bytes.len() - 7);
+			bytes.push(count);
+			bytes.extend(data);
+			prop_assert!(Packet::<SbPacket>::deserialize_from(&bytes).is_err());
+		}
+	}
 }
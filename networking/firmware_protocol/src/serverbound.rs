@@ -21,6 +21,7 @@ pub enum SbPacket {
 		firmware: SlimeString,
 		mac_address: [u8; 6],
 	},
+	/// Linear acceleration in m/s², without gravity and in the frame of the sensor.
 	#[deku(id = "4")]
 	Acceleration {
 		vector: (f32, f32, f32),
@@ -28,6 +29,16 @@ pub enum SbPacket {
 	},
 	#[deku(id = "10")]
 	Ping { challenge: [u8; 4] },
+	/// The battery voltage in volts, and how full it is from `0` to `1`.
+	#[deku(id = "12")]
+	BatteryLevel { voltage: f32, level: f32 },
+	/// The sensor was tapped, `tap` has [`TAP_DOUBLE`] set for the second tap of a
+	/// double tap. The server uses taps for reset gestures.
+	#[deku(id = "13")]
+	Tap { sensor_id: u8, tap: u8 },
+	/// Reports that a sensor stopped working, with a firmware-specific error code.
+	#[deku(id = "14")]
+	SensorError { sensor_id: u8, error: u8 },
 	#[deku(id = "15")]
 	SensorInfo {
 		sensor_id: u8,
@@ -41,9 +52,127 @@ pub enum SbPacket {
 		quat: SlimeQuaternion,
 		calibration_info: u8,
 	},
+	/// The die temperature of a sensor in degrees Celsius, sent every few seconds by
+	/// sensors that measure it. Drift correlates with it.
+	#[deku(id = "20")]
+	Temperature { sensor_id: u8, temperature: f32 },
 	#[deku(id = "21")]
 	UserAction { action: ActionType },
+	// Packet ids starting at 1000 are extensions specific to SlimeVR-Rust. The
+	// official server ignores packets it doesn't know, so these are always safe to
+	// send.
+	/// Angular velocity in rad/s, sent along with some of the `RotationData` so that
+	/// the receiver can extrapolate the rotation to compensate for latency.
+	#[deku(id = "1000")]
+	AngularVelocity {
+		sensor_id: u8,
+		vector: (f32, f32, f32),
+	},
+	/// Sent when the tracker starts or stops throttling itself due to overheating.
+	/// `0` is normal, `1` is hot, and `2` is critical.
+	#[deku(id = "1001")]
+	ThermalState { state: u8 },
+	/// A human readable description of a problem on the tracker, to show to the
+	/// user. `code` is the same as in `SensorError` when the problem is with a
+	/// sensor, and `sensor_id` is `0xFF` when it isn't.
+	#[deku(id = "1002")]
+	Diagnostic {
+		sensor_id: u8,
+		code: u8,
+		message: SlimeString,
+	},
+	/// The optional features that the tracker supports, as a bitset of the
+	/// `CAPABILITY_*` constants. Sent once after the handshake, and the receiver can
+	/// turn them on with `CbPacket::EnableCapabilities`. A receiver that takes the
+	/// extensions answers with that even if it turns nothing on, trackers stop
+	/// sending them to one that doesn't answer.
+	#[deku(id = "1003")]
+	Capabilities { flags: u32 },
+	/// An uncalibrated sample straight from the sensor, sent at its full rate while
+	/// `CbPacket::StreamRawData` asked for it. Acceleration is in m/s^2, angular
+	/// velocity in rad/s, and the timestamp is in microseconds since boot, wrapping
+	/// around. Samples are dropped rather than queued when the network can't keep
+	/// up, which shows up as gaps in the timestamps.
+	#[deku(id = "1004")]
+	RawImuData {
+		sensor_id: u8,
+		timestamp_us: u32,
+		accel: (f32, f32, f32),
+		gyro: (f32, f32, f32),
+	},
+	/// Answers each packet of an update over the air. `offset` is how much of the
+	/// image the tracker has, so it is also the offset of the next chunk to send.
+	/// `status` is one of the `OTA_*` constants.
+	#[deku(id = "1005")]
+	OtaStatus { offset: u32, status: u8 },
+	/// Sent right before the tracker goes into deep sleep on purpose, because it lay
+	/// still for long enough, so that the receiver can tell it apart from a tracker
+	/// that lost its connection. Moving it wakes it up, and it handshakes again.
+	#[deku(id = "1006")]
+	Sleeping,
+	/// Answers `CbPacket::SetConfig`. `value` is what the setting is now, which is
+	/// the old value unless `status` is [`CONFIG_OK`] or [`CONFIG_NOT_STORED`].
+	/// `status` is one of the `CONFIG_*` constants.
+	#[deku(id = "1007")]
+	ConfigStatus { key: u8, value: u32, status: u8 },
+	/// Sent right after each `SensorInfo`, which can't grow itself since the
+	/// official protocol already gave its later bytes other meanings. `unique_id`
+	/// tells the sensor apart from the sensors of every other tracker, and stays the
+	/// same across reflashes, so that the receiver can keep settings like the
+	/// mounting per sensor. It is the MAC address of the tracker in the upper six
+	/// bytes, and `sensor_id` in the lowest.
+	#[deku(id = "1008")]
+	SensorUniqueId { sensor_id: u8, unique_id: u64 },
 }
+impl SbPacket {
+	/// Whether this is one of the extensions of SlimeVR-Rust, with an id from 1000,
+	/// which the official server doesn't know.
+	pub fn is_extension(&self) -> bool {
+		matches!(
+			self,
+			Self::AngularVelocity { .. }
+				| Self::ThermalState { .. }
+				| Self::Diagnostic { .. }
+				| Self::Capabilities { .. }
+				| Self::RawImuData { .. }
+				| Self::OtaStatus { .. }
+				| Self::Sleeping | Self::ConfigStatus { .. }
+				| Self::SensorUniqueId { .. }
+		)
+	}
+}
+
+/// The update is going fine, send the chunk at `offset` next.
+pub const OTA_OK: u8 = 0;
+/// Writing the update failed, and it has to be started over.
+pub const OTA_FAILED: u8 = 1;
+/// The image doesn't look like firmware, or doesn't match its CRC.
+pub const OTA_BAD_IMAGE: u8 = 2;
+/// The tracker was built without support for updates over the air.
+pub const OTA_UNSUPPORTED: u8 = 3;
+/// The firmware of a `CbPacket::FirmwareOffer` is for another MCU or board.
+pub const OTA_INCOMPATIBLE: u8 = 4;
+/// The tracker already runs the version of a `CbPacket::FirmwareOffer`.
+pub const OTA_UP_TO_DATE: u8 = 5;
+/// The image isn't signed with the key that the tracker was built with, see
+/// [`OTA_SIGNATURE_LEN`](crate::OTA_SIGNATURE_LEN).
+pub const OTA_UNSIGNED: u8 = 6;
+
+/// The flag of `SbPacket::Tap` for a double tap, like the tap detector of the
+/// BNO08x reports it. The other bits are the axes of the tap, if the imu knows them.
+pub const TAP_DOUBLE: u8 = 1 << 6;
+
+/// The setting was changed and stored.
+pub const CONFIG_OK: u8 = 0;
+/// The tracker doesn't know the key, maybe because its firmware is older.
+pub const CONFIG_UNKNOWN_KEY: u8 = 1;
+/// The value is outside of what the setting allows.
+pub const CONFIG_OUT_OF_RANGE: u8 = 2;
+/// The tracker knows the setting, but can't change it, like the fusion on a tracker
+/// whose imu fuses on-chip.
+pub const CONFIG_UNSUPPORTED: u8 = 3;
+/// The setting was changed, but storing it failed, so it is lost on reboot.
+pub const CONFIG_NOT_STORED: u8 = 4;
 
 #[derive(Debug, PartialEq, Eq, DekuRead, DekuWrite)]
 #[deku(type = "u32", ctx = "_: deku::ctx::Endian", endian = "big")]
@@ -78,7 +207,7 @@ pub enum BoardType {
 	Unknown(u32),
 }
 
-#[derive(Debug, PartialEq, Eq, DekuRead, DekuWrite)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DekuRead, DekuWrite)]
 #[deku(type = "u8", ctx = "_: deku::ctx::Endian", endian = "big")]
 #[non_exhaustive]
 /// The intertial measurement unit in use
@@ -118,7 +247,7 @@ pub enum McuType {
 	Unknown(u32),
 }
 
-#[derive(Debug, PartialEq, Eq, DekuRead, DekuWrite)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, DekuRead, DekuWrite)]
 #[deku(type = "u8", ctx = "_: deku::ctx::Endian", endian = "big")]
 /// Current status of the sensor
 pub enum SensorStatus {
@@ -234,6 +363,62 @@ mod tests {
 		);
 	}
 
+	#[test]
+	fn battery_level() {
+		test(
+			SbPacket::BatteryLevel {
+				voltage: f32::from_be_bytes([1, 2, 3, 4]),
+				level: f32::from_be_bytes([5, 6, 7, 8]),
+			},
+			&[
+				1, 2, 3, 4, // Voltage
+				5, 6, 7, 8, // Level
+			],
+		);
+	}
+
+	#[test]
+	fn temperature() {
+		test(
+			SbPacket::Temperature {
+				sensor_id: 1,
+				temperature: f32::from_be_bytes([2, 3, 4, 5]),
+			},
+			&[
+				1, // Sensor id
+				2, 3, 4, 5, // Temperature
+			],
+		);
+	}
+
+	#[test]
+	fn sensor_error() {
+		test(
+			SbPacket::SensorError {
+				sensor_id: 40,
+				error: 2,
+			},
+			&[
+				40, // ID
+				2,  // Error
+			],
+		);
+	}
+
+	#[test]
+	fn tap() {
+		test(
+			SbPacket::Tap {
+				sensor_id: 40,
+				tap: TAP_DOUBLE,
+			},
+			&[
+				40,   // ID
+				0x40, // Tap
+			],
+		);
+	}
+
 	#[test]
 	fn sensor_info() {
 		test(
@@ -276,6 +461,145 @@ mod tests {
 			],
 		);
 	}
+
+	#[test]
+	fn angular_velocity() {
+		test(
+			SbPacket::AngularVelocity {
+				sensor_id: 13,
+				vector: (
+					f32::from_be_bytes([1, 2, 3, 4]),
+					f32::from_be_bytes([5, 6, 7, 8]),
+					f32::from_be_bytes([9, 10, 11, 12]),
+				),
+			},
+			&[
+				13, // ID
+				1, 2, 3, 4, // X
+				5, 6, 7, 8, // Y
+				9, 10, 11, 12, // Z
+			],
+		);
+	}
+
+	#[test]
+	fn thermal_state() {
+		test(
+			SbPacket::ThermalState { state: 2 },
+			&[
+				2, // State
+			],
+		);
+	}
+
+	#[test]
+	fn diagnostic() {
+		test(
+			SbPacket::Diagnostic {
+				sensor_id: 1,
+				code: 2,
+				message: "hi".into(),
+			},
+			&[
+				1, // Sensor id
+				2, // Code
+				2, // Message length
+				b'h', b'i', // Message
+			],
+		);
+	}
+
+	#[test]
+	fn capabilities() {
+		test(
+			SbPacket::Capabilities {
+				flags: CAPABILITY_CRC32,
+			},
+			&[
+				0, 0, 0, 1, // Flags
+			],
+		);
+	}
+
+	#[test]
+	fn raw_imu_data() {
+		test(
+			SbPacket::RawImuData {
+				sensor_id: 1,
+				timestamp_us: 0x01020304,
+				accel: (
+					f32::from_be_bytes([5, 6, 7, 8]),
+					f32::from_be_bytes([9, 10, 11, 12]),
+					f32::from_be_bytes([13, 14, 15, 16]),
+				),
+				gyro: (
+					f32::from_be_bytes([17, 18, 19, 20]),
+					f32::from_be_bytes([21, 22, 23, 24]),
+					f32::from_be_bytes([25, 26, 27, 28]),
+				),
+			},
+			&[
+				1, // ID
+				1, 2, 3, 4, // Timestamp
+				5, 6, 7, 8, // Accel X
+				9, 10, 11, 12, // Accel Y
+				13, 14, 15, 16, // Accel Z
+				17, 18, 19, 20, // Gyro X
+				21, 22, 23, 24, // Gyro Y
+				25, 26, 27, 28, // Gyro Z
+			],
+		);
+	}
+
+	#[test]
+	fn ota_status() {
+		test(
+			SbPacket::OtaStatus {
+				offset: 0x01020304,
+				status: OTA_BAD_IMAGE,
+			},
+			&[
+				1, 2, 3, 4, // Offset
+				2, // Status
+			],
+		);
+	}
+
+	#[test]
+	fn sleeping() {
+		test(SbPacket::Sleeping, &[]);
+	}
+
+	#[test]
+	fn config_status() {
+		test(
+			SbPacket::ConfigStatus {
+				key: 1,
+				value: 80,
+				status: CONFIG_UNSUPPORTED,
+			},
+			&[
+				1, // Key
+				0, 0, 0, 80, // Value
+				3,  // Status
+			],
+		);
+	}
+
+	#[test]
+	fn sensor_unique_id() {
+		test(
+			SbPacket::SensorUniqueId {
+				sensor_id: 1,
+				unique_id: 0x0203_0405_0607_0001,
+			},
+			&[
+				1, // Sensor id
+				2, 3, 4, 5, 6, 7, 0, 1, // Unique id
+			],
+		);
+	}
+
 	#[test]
 	fn user_action() {
 		test(
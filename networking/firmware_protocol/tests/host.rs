@@ -0,0 +1,123 @@
+//! Uses the crate the way PC tooling does, from outside of it and with std: talking
+//! to a tracker as the server would, one packet at a time.
+
+use firmware_protocol::{
+	BoardType, CbPacket, DeserializeError, ImuType, McuType, Packet, SbPacket,
+	SensorStatus, SlimeString, CAPABILITY_CRC32, PROTOCOL_BUILD,
+};
+
+fn sb(seq: u64, data: SbPacket) -> Vec<u8> {
+	let mut buf = [0; 512];
+	let len = Packet::new(seq, data).serialize_into(&mut buf).unwrap();
+	buf[..len].to_vec()
+}
+
+fn cb(seq: u64, data: CbPacket) -> Vec<u8> {
+	let mut buf = [0; 512];
+	let len = Packet::new(seq, data).serialize_into(&mut buf).unwrap();
+	buf[..len].to_vec()
+}
+
+fn handshake() -> SbPacket {
+	SbPacket::Handshake {
+		board: BoardType::Custom,
+		imu: ImuType::Bno085,
+		mcu: McuType::Esp32,
+		imu_info: (0, 0, 0),
+		build: PROTOCOL_BUILD,
+		firmware: SlimeString::from("SlimeVR-Rust"),
+		mac_address: [0; 6],
+	}
+}
+
+#[test]
+fn handshake_on_the_wire() {
+	#[rustfmt::skip]
+	let expected = [
+		0, 0, 0, 3, // Tag
+		0, 0, 0, 0, 0, 0, 0, 1, // Sequence
+		0, 0, 0, 4, // Board
+		0, 0, 0, // Pad
+		4, // IMU
+		0, 0, 0, 2, // MCU
+		0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // Info
+		0, 0, 0, 10, // Build
+		12, b'S', b'l', b'i', b'm', b'e', b'V', b'R', b'-', b'R', b'u', b's', b't',
+		0, 0, 0, 0, 0, 0, // MAC
+	];
+	assert_eq!(sb(1, handshake()), expected);
+}
+
+#[test]
+fn session() {
+	// The tracker introduces itself, and the server answers
+	let bytes = sb(1, handshake());
+	let (seq, packet) = Packet::<SbPacket>::deserialize_from(&bytes)
+		.unwrap()
+		.split();
+	assert_eq!((seq, packet), (1, handshake()));
+	let bytes = cb(0, CbPacket::HandshakeResponse { version: b'5' });
+	assert_eq!(
+		Packet::<CbPacket>::deserialize_from(&bytes)
+			.unwrap()
+			.split(),
+		(0, CbPacket::HandshakeResponse { version: b'5' })
+	);
+
+	// Then it registers its sensors
+	let info = SbPacket::SensorInfo {
+		sensor_id: 0,
+		sensor_status: SensorStatus::Ok,
+		sensor_type: ImuType::Bno085,
+	};
+	let bytes = sb(2, info);
+	let (seq, packet) = Packet::<SbPacket>::deserialize_from(&bytes)
+		.unwrap()
+		.split();
+	assert_eq!(seq, 2);
+	assert!(matches!(packet, SbPacket::SensorInfo { sensor_id: 0, .. }));
+}
+
+#[test]
+fn checked_packets_after_capabilities() {
+	let capabilities = sb(
+		3,
+		SbPacket::Capabilities {
+			flags: CAPABILITY_CRC32,
+		},
+	);
+	let parsed = Packet::<SbPacket>::deserialize_from(&capabilities).unwrap();
+	match parsed.split() {
+		(3, SbPacket::Capabilities { flags }) => {
+			assert_ne!(flags & CAPABILITY_CRC32, 0)
+		}
+		other => panic!("Expected capabilities, got {other:?}"),
+	}
+
+	// From then on, every packet carries a checksum
+	let mut buf = [0; 64];
+	let len = Packet::new(4, SbPacket::Heartbeat)
+		.serialize_into_checked(&mut buf)
+		.unwrap();
+	assert_eq!(
+		Packet::<SbPacket>::deserialize_from_checked(&buf[..len])
+			.unwrap()
+			.split(),
+		(4, SbPacket::Heartbeat)
+	);
+	// And ones without are rejected
+	assert_eq!(
+		Packet::<SbPacket>::deserialize_from_checked(&capabilities),
+		Err(DeserializeError::BadChecksum)
+	);
+}
+
+#[test]
+fn trailing_bytes_are_rejected() {
+	let mut bytes = sb(5, SbPacket::Heartbeat);
+	bytes.push(0);
+	assert_eq!(
+		Packet::<SbPacket>::deserialize_from(&bytes),
+		Err(DeserializeError::BytesRemaining)
+	);
+}
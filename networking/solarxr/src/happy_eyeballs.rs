@@ -0,0 +1,71 @@
+//! Connects to hosts that may resolve to both IPv4 and IPv6 addresses, racing the
+//! connection attempts as described in [RFC 8305] ("Happy Eyeballs"). This way
+//! v6-only, v4-only, and dual-stack networks all connect quickly.
+//!
+//! [RFC 8305]: https://www.rfc-editor.org/rfc/rfc8305
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// How long to wait on a connection attempt before starting the next one in
+/// parallel. This is the value recommended by the RFC.
+const ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Connects to `host`, which can be a hostname or an IP address.
+pub async fn connect(host: &str, port: u16) -> io::Result<TcpStream> {
+	// IPv6 literals in urls are wrapped in brackets.
+	let host = host.trim_start_matches('[').trim_end_matches(']');
+	let addrs = tokio::net::lookup_host((host, port)).await?;
+	let mut addrs = interleave(addrs.collect()).into_iter();
+
+	let mut attempts = FuturesUnordered::new();
+	let mut last_err = None;
+	loop {
+		if attempts.is_empty() {
+			let Some(addr) = addrs.next() else {
+				break;
+			};
+			attempts.push(TcpStream::connect(addr));
+		}
+
+		tokio::select! {
+			Some(result) = attempts.next() => match result {
+				Ok(stream) => return Ok(stream),
+				Err(err) => {
+					log::debug!("Connection attempt failed: {err}");
+					last_err = Some(err);
+					if let Some(addr) = addrs.next() {
+						attempts.push(TcpStream::connect(addr));
+					}
+				}
+			},
+			_ = tokio::time::sleep(ATTEMPT_DELAY), if addrs.len() > 0 => {
+				attempts.push(TcpStream::connect(addrs.next().unwrap()));
+			}
+		}
+	}
+	Err(last_err.unwrap_or_else(|| {
+		io::Error::new(
+			io::ErrorKind::NotFound,
+			format!("{host} did not resolve to any addresses"),
+		)
+	}))
+}
+
+/// Orders addresses by alternating between IPv6 and IPv4, starting with IPv6.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+	let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|a| a.is_ipv6());
+	let mut v6 = v6.into_iter();
+	let mut v4 = v4.into_iter();
+	let mut result = Vec::with_capacity(v6.len() + v4.len());
+	loop {
+		match (v6.next(), v4.next()) {
+			(None, None) => break,
+			(a, b) => result.extend(a.into_iter().chain(b)),
+		}
+	}
+	result
+}
@@ -1,4 +1,5 @@
 mod data;
+mod happy_eyeballs;
 pub mod settings;
 mod state_machine;
 pub mod topic;
@@ -9,13 +10,48 @@ pub use crate::data::{Data, DecodeError, FeedUpdate};
 use crate::state_machine::{ClientStateMachine, DeserializeError, RecvError};
 
 use core::future::Future;
+use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio_tungstenite::MaybeTlsStream;
 use tokio_tungstenite::WebSocketStream;
 
 type Wss = WebSocketStream<MaybeTlsStream<TcpStream>>;
 
-/// Returns a future that will run forever, continually callin the callbacks as necessary
+/// How long to wait before the first reconnect. Connecting to a server that isn't
+/// running fails right away, so without waiting we would spin.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+/// The wait doubles with every failed attempt, up to this. Short enough that a
+/// restarted server gets picked up again quickly.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Spaces out reconnects, see [`MIN_RECONNECT_DELAY`].
+#[derive(Debug, Default)]
+struct Backoff {
+	/// `None` until an attempt failed.
+	next: Option<Duration>,
+}
+impl Backoff {
+	async fn wait(&mut self) {
+		if let Some(delay) = self.next {
+			log::debug!("Reconnecting in {delay:?}");
+			tokio::time::sleep(delay).await;
+		}
+		let next = match self.next {
+			None => MIN_RECONNECT_DELAY,
+			Some(delay) => (delay * 2).min(MAX_RECONNECT_DELAY),
+		};
+		self.next = Some(next);
+	}
+
+	/// Connects right away next time, once we got data from the server.
+	fn reset(&mut self) {
+		self.next = None;
+	}
+}
+
+/// Returns a future that will run forever, continually callin the callbacks as
+/// necessary. Reconnects whenever the connection fails, like when the server
+/// restarts.
 pub async fn run<Fut>(
 	connect_to: String,
 	mut data_feed_callback: impl FnMut(FeedUpdate) -> Fut,
@@ -24,7 +60,9 @@ where
 	Fut: Future<Output = ()>,
 {
 	let mut disconnected = Some(ClientStateMachine::new(connect_to));
+	let mut backoff = Backoff::default();
 	loop {
+		backoff.wait().await;
 		let ready = match disconnected.take().unwrap().connect().await {
 			Ok(ready) => ready,
 			Err((d, err)) => {
@@ -60,6 +98,7 @@ where
 				Ok((a, update)) => {
 					log::trace!("Sending data to watchers: {:#?}", update);
 					active = Some(a);
+					backoff.reset();
 					data_feed_callback(update).await;
 				}
 				Err(err) => {
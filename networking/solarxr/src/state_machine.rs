@@ -10,8 +10,9 @@ use solarxr_protocol::MessageBundle;
 use std::fmt::Debug;
 use std::future;
 use std::pin::Pin;
-use tokio_tungstenite::{connect_async, tungstenite};
-use tungstenite::error::Error as WsError;
+use tokio_tungstenite::{client_async_tls, tungstenite};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::error::{Error as WsError, UrlError};
 use tungstenite::Message;
 
 type DeserializeFn = fn(Result<Message, WsError>) -> Result<Data, DeserializeError>;
@@ -71,8 +72,8 @@ pub enum DeserializeError {
 pub struct Disconnected;
 impl M<Disconnected> {
 	pub async fn connect(self) -> Result<M<Connected>, (Self, WsError)> {
-		match connect_async(&self.common.connect_to).await {
-			Ok((socket, _)) => {
+		match connect_ws(&self.common.connect_to).await {
+			Ok(socket) => {
 				let (sink, stream) = socket.split();
 
 				// We never actually error, but this signature satisfies `sink.with()`
@@ -111,6 +112,23 @@ impl M<Disconnected> {
 	}
 }
 
+/// Opens a websocket to `url`, over either IPv4 or IPv6. `wss://` goes over TLS.
+async fn connect_ws(url: &str) -> Result<Wss, WsError> {
+	let request = url.into_client_request()?;
+	let uri = request.uri();
+	let host = uri
+		.host()
+		.ok_or(WsError::Url(UrlError::NoHostName))?
+		.to_owned();
+	let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+		Some("wss") => 443,
+		_ => 80,
+	});
+	let stream = crate::happy_eyeballs::connect(&host, port).await?;
+	let (socket, _response) = client_async_tls(request, stream).await?;
+	Ok(socket)
+}
+
 /// Client is connected over websocket
 #[derive(Debug)]
 pub struct Connected {
@@ -127,6 +145,9 @@ impl M<Connected> {
 		#[allow(clippy::needless_update)]
 		let data = {
 			let data_feed_header = {
+				use solarxr_protocol::data_feed::device_data::{
+					DeviceDataMask, DeviceDataMaskArgs,
+				};
 				use solarxr_protocol::data_feed::tracker::{
 					TrackerDataMask, TrackerDataMaskArgs,
 				};
@@ -136,22 +157,33 @@ impl M<Connected> {
 					StartDataFeedArgs,
 				};
 
-				let _tracker_mask = TrackerDataMask::create(
+				// Used to tell when trackers disconnect, to draw where they are, to
+				// notice two of them on the same body part, and to extrapolate
+				let tracker_mask = TrackerDataMask::create(
 					fbb,
 					&TrackerDataMaskArgs {
-						// TODO: We only need the body part here, not the whole TrackerInfo
 						info: true,
-						rotation: true,
+						status: true,
 						position: true,
+						rotation: true,
+						raw_angular_velocity: true,
 						..Default::default()
 					},
 				);
+				// Only used for the battery level of devices
+				let device_mask = DeviceDataMask::create(
+					fbb,
+					&DeviceDataMaskArgs {
+						tracker_data: Some(tracker_mask),
+						device_data: true,
+					},
+				);
 
 				let data_feed_config = DataFeedConfig::create(
 					fbb,
 					&DataFeedConfigArgs {
 						minimum_time_since_last: 10,
-						// We don't care about anything but bones
+						data_mask: Some(device_mask),
 						bone_mask: true,
 						..Default::default()
 					},
@@ -0,0 +1,165 @@
+#!/usr/bin/env python3
+"""Generates `avatar.gltf`, the low-poly humanoid bundled with the overlay.
+
+Every bone is a tapered prism, rigidly skinned to the joint of the same name. The
+joints are named after `BoneKind`, start at the head of their bone, and have no
+rotation in the bind pose, so that each bone points down along -Y just like the
+bones in `src/model`.
+
+Run it from this directory: `python3 make_avatar.py`
+"""
+
+import base64
+import json
+import math
+import struct
+
+SIDES = 6
+
+# name: (parent, head position, length, rings)
+# Each ring is (fraction along the bone, radius in x, radius in z). Left is -x,
+# since the avatar faces -z like everything else in OpenVR.
+BONES = {
+    "Head": (None, (0.0, 1.70, 0.0), 0.20, [(0.0, 0.05, 0.05), (0.4, 0.09, 0.10), (1.0, 0.05, 0.06)]),
+    "Neck": ("Head", (0.0, 1.50, 0.0), 0.05, [(0.0, 0.05, 0.05), (1.0, 0.06, 0.06)]),
+    "Chest": ("Neck", (0.0, 1.45, 0.0), 0.25, [(0.0, 0.16, 0.09), (1.0, 0.14, 0.10)]),
+    "Waist": ("Chest", (0.0, 1.20, 0.0), 0.20, [(0.0, 0.14, 0.10), (1.0, 0.13, 0.09)]),
+    "Hip": ("Waist", (0.0, 1.00, 0.0), 0.10, [(0.0, 0.13, 0.09), (1.0, 0.16, 0.10)]),
+    "ThighL": ("Hip", (-0.09, 0.90, 0.0), 0.45, [(0.0, 0.08, 0.08), (1.0, 0.05, 0.05)]),
+    "ThighR": ("Hip", (0.09, 0.90, 0.0), 0.45, [(0.0, 0.08, 0.08), (1.0, 0.05, 0.05)]),
+    "AnkleL": ("ThighL", (-0.09, 0.45, 0.0), 0.40, [(0.0, 0.05, 0.05), (1.0, 0.035, 0.035)]),
+    "AnkleR": ("ThighR", (0.09, 0.45, 0.0), 0.40, [(0.0, 0.05, 0.05), (1.0, 0.035, 0.035)]),
+    "FootL": ("AnkleL", (-0.09, 0.05, 0.0), 0.15, [(0.0, 0.04, 0.04), (1.0, 0.035, 0.02)]),
+    "FootR": ("AnkleR", (0.09, 0.05, 0.0), 0.15, [(0.0, 0.04, 0.04), (1.0, 0.035, 0.02)]),
+    "UpperArmL": ("Neck", (-0.20, 1.45, 0.0), 0.30, [(0.0, 0.05, 0.05), (1.0, 0.04, 0.04)]),
+    "UpperArmR": ("Neck", (0.20, 1.45, 0.0), 0.30, [(0.0, 0.05, 0.05), (1.0, 0.04, 0.04)]),
+    "ForearmL": ("UpperArmL", (-0.20, 1.15, 0.0), 0.27, [(0.0, 0.04, 0.04), (1.0, 0.03, 0.03)]),
+    "ForearmR": ("UpperArmR", (0.20, 1.15, 0.0), 0.27, [(0.0, 0.04, 0.04), (1.0, 0.03, 0.03)]),
+    "WristL": ("ForearmL", (-0.20, 0.88, 0.0), 0.10, [(0.0, 0.03, 0.02), (1.0, 0.04, 0.015)]),
+    "WristR": ("ForearmR", (0.20, 0.88, 0.0), 0.10, [(0.0, 0.03, 0.02), (1.0, 0.04, 0.015)]),
+}
+
+
+def sub(a, b):
+    return tuple(x - y for x, y in zip(a, b))
+
+
+def normal(a, b, c):
+    u, v = sub(b, a), sub(c, a)
+    n = (u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0])
+    length = math.sqrt(sum(x * x for x in n)) or 1.0
+    return tuple(x / length for x in n)
+
+
+def prism(head, length, rings):
+    """Flat shaded triangles of a prism through `rings`, closed at both ends."""
+    points = []
+    for frac, rx, rz in rings:
+        y = head[1] - frac * length
+        points.append([
+            (head[0] + rx * math.cos(a), y, head[2] + rz * math.sin(a))
+            for a in (2 * math.pi * i / SIDES for i in range(SIDES))
+        ])
+    tris = []
+    for top, bottom in zip(points, points[1:]):
+        for i in range(SIDES):
+            j = (i + 1) % SIDES
+            tris.append((top[i], top[j], bottom[i]))
+            tris.append((bottom[i], top[j], bottom[j]))
+    top_center = (head[0], head[1], head[2])
+    bottom_center = (head[0], head[1] - length, head[2])
+    for i in range(SIDES):
+        j = (i + 1) % SIDES
+        tris.append((top_center, points[0][j], points[0][i]))
+        tris.append((bottom_center, points[-1][i], points[-1][j]))
+    return tris
+
+
+def main():
+    names = list(BONES)
+    positions, normals, joints, indices = [], [], [], []
+    for joint, (_parent, head, length, rings) in enumerate(BONES.values()):
+        for a, b, c in prism(head, length, rings):
+            # Pick the winding that faces away from the bone's axis.
+            n = normal(a, b, c)
+            middle = (head[0], head[1] - length / 2, head[2])
+            center = sub(tuple((x + y + z) / 3 for x, y, z in zip(a, b, c)), middle)
+            if sum(x * y for x, y in zip(n, center)) < 0:
+                b, c = c, b
+                n = tuple(-x for x in n)
+            for p in (a, b, c):
+                indices.append(len(positions))
+                positions.append(p)
+                normals.append(n)
+                joints.append(joint)
+
+    buffer = bytearray()
+    buffer_views, accessors = [], []
+
+    def add(data, accessor):
+        while len(buffer) % 4:
+            buffer.append(0)
+        buffer_views.append({"buffer": 0, "byteOffset": len(buffer), "byteLength": len(data)})
+        buffer.extend(data)
+        accessor["bufferView"] = len(buffer_views) - 1
+        accessors.append(accessor)
+        return len(accessors) - 1
+
+    def vec3(values):
+        return b"".join(struct.pack("<3f", *v) for v in values)
+
+    position = add(vec3(positions), {
+        "componentType": 5126, "count": len(positions), "type": "VEC3",
+        "min": [min(p[i] for p in positions) for i in range(3)],
+        "max": [max(p[i] for p in positions) for i in range(3)],
+    })
+    normal_ = add(vec3(normals), {"componentType": 5126, "count": len(normals), "type": "VEC3"})
+    joints_ = add(b"".join(struct.pack("<4H", j, 0, 0, 0) for j in joints),
+                  {"componentType": 5123, "count": len(joints), "type": "VEC4"})
+    weights = add(b"".join(struct.pack("<4f", 1, 0, 0, 0) for _ in joints),
+                  {"componentType": 5126, "count": len(joints), "type": "VEC4"})
+    index = add(b"".join(struct.pack("<H", i) for i in indices),
+                {"componentType": 5123, "count": len(indices), "type": "SCALAR"})
+    # Column major, undoing the translation to the head of each bone.
+    inverse_binds = add(b"".join(
+        struct.pack("<16f", 1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, -h[0], -h[1], -h[2], 1)
+        for _parent, h, _length, _rings in BONES.values()
+    ), {"componentType": 5126, "count": len(BONES), "type": "MAT4"})
+
+    nodes = []
+    for name, (parent, head, _length, _rings) in BONES.items():
+        origin = BONES[parent][1] if parent else (0, 0, 0)
+        nodes.append({
+            "name": name,
+            "translation": list(sub(head, origin)),
+            "children": [i for i, (p, *_rest) in enumerate(BONES.values()) if p == name],
+        })
+    for node in nodes:
+        if not node["children"]:
+            del node["children"]
+    nodes.append({"name": "Avatar", "mesh": 0, "skin": 0})
+
+    gltf = {
+        "asset": {"version": "2.0", "generator": "make_avatar.py"},
+        "scene": 0,
+        "scenes": [{"nodes": [names.index("Head"), len(nodes) - 1]}],
+        "nodes": nodes,
+        "meshes": [{"name": "Avatar", "primitives": [{
+            "attributes": {"POSITION": position, "NORMAL": normal_, "JOINTS_0": joints_, "WEIGHTS_0": weights},
+            "indices": index,
+        }]}],
+        "skins": [{"joints": list(range(len(BONES))), "inverseBindMatrices": inverse_binds, "skeleton": 0}],
+        "accessors": accessors,
+        "bufferViews": buffer_views,
+        "buffers": [{
+            "byteLength": len(buffer),
+            "uri": "data:application/octet-stream;base64," + base64.b64encode(buffer).decode(),
+        }],
+    }
+    with open("avatar.gltf", "w") as f:
+        json.dump(gltf, f, indent=1)
+        f.write("\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,295 @@
+//! Audio cues for tracking events, since a warning that is only visible in the
+//! SlimeVR GUI is easy to miss while in VR.
+//!
+//! Sounds play on the default output device, from a thread of their own, because
+//! the audio stream can't be moved between the threads of the async runtime.
+
+use crate::config::{Alert, Alerts};
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::model::BoneKind;
+use crate::timebase::Stamped;
+use crate::update_rate::UpdateRates;
+
+use eyre::Result;
+use nalgebra::UnitQuaternion;
+use solarxr::protocol::data_feed::DataFeedUpdate;
+use solarxr::protocol::datatypes::TrackerStatus;
+use solarxr::FeedUpdate;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// How far the battery has to recover above the threshold before we alert again,
+/// so that a noisy estimate doesn't alert over and over.
+const BATTERY_HYSTERESIS: u8 = 5;
+/// How often to check whether the reset reminder is due.
+const REMINDER_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+	TrackerDisconnected,
+	BatteryLow,
+	TrackerError,
+	ResetReminder,
+	BoneRateLow,
+}
+impl Event {
+	fn alert(self, config: &Alerts) -> &Alert {
+		match self {
+			Event::TrackerDisconnected => &config.tracker_disconnected,
+			Event::BatteryLow => &config.battery_low,
+			Event::TrackerError => &config.tracker_error,
+			Event::ResetReminder => &config.reset_reminder,
+			Event::BoneRateLow => &config.bone_rate_low,
+		}
+	}
+
+	/// The pitch in Hz of the built-in beep.
+	#[cfg_attr(not(feature = "sounds"), allow(dead_code))]
+	fn beep_hz(self) -> f32 {
+		match self {
+			Event::TrackerDisconnected => 440.,
+			Event::BatteryLow => 660.,
+			Event::TrackerError => 550.,
+			Event::ResetReminder => 880.,
+			Event::BoneRateLow => 330.,
+		}
+	}
+}
+
+/// A sound to play, and how loud.
+#[cfg_attr(not(feature = "sounds"), allow(dead_code))]
+struct Request {
+	event: Event,
+	sound: Option<PathBuf>,
+	volume: f32,
+}
+
+/// Turns feed updates into alerts, by remembering what the trackers were like
+/// before.
+#[derive(Debug, Default)]
+struct Watcher {
+	/// Whether each tracker was connected in the last update, keyed by device id
+	/// and tracker index.
+	connected: HashMap<(u8, usize), bool>,
+	/// Devices we already alerted about a low battery for.
+	low_battery: HashSet<u8>,
+	rates: UpdateRates,
+	/// Bones we already alerted about updating too slowly.
+	slow_bones: HashSet<BoneKind>,
+}
+impl Watcher {
+	/// Returns the events in `update`, along with the name of the device they
+	/// happened to.
+	fn update(&mut self, config: &Alerts, update: &FeedUpdate) -> Vec<(Event, String)> {
+		let mut events = Vec::new();
+		let table = update.0.table();
+		let Some(msgs) = table.data_feed_msgs() else {
+			return events;
+		};
+		for m in msgs {
+			let Some(m) = m.message_as_data_feed_update() else {
+				continue;
+			};
+			if config.bone_rate_low.enabled {
+				self.update_rates(config, m, &mut events);
+			}
+			for device in m.devices().into_iter().flatten() {
+				let Some(id) = device.id().map(|id| id.id()) else {
+					continue;
+				};
+				let name = device
+					.custom_name()
+					.map_or_else(|| format!("Device {id}"), String::from);
+
+				for (i, tracker) in device.trackers().into_iter().flatten().enumerate()
+				{
+					let is_connected = !matches!(
+						tracker.status(),
+						TrackerStatus::DISCONNECTED | TrackerStatus::TIMED_OUT
+					);
+					let was_connected = self
+						.connected
+						.insert((id, i), is_connected)
+						.unwrap_or(false);
+					if was_connected && !is_connected {
+						events.push((Event::TrackerDisconnected, name.clone()));
+					}
+				}
+
+				let battery = device
+					.hardware_status()
+					.and_then(|h| h.battery_pct_estimate());
+				if let Some(battery) = battery {
+					if battery <= config.battery_low_percent {
+						if self.low_battery.insert(id) {
+							events.push((Event::BatteryLow, name));
+						}
+					} else if battery
+						> config
+							.battery_low_percent
+							.saturating_add(BATTERY_HYSTERESIS)
+					{
+						self.low_battery.remove(&id);
+					}
+				}
+			}
+		}
+		events
+	}
+
+	fn update_rates(
+		&mut self,
+		config: &Alerts,
+		update: DataFeedUpdate<'_>,
+		events: &mut Vec<(Event, String)>,
+	) {
+		let now = Instant::now();
+		for bone in update.bones().into_iter().flatten() {
+			let kind = BoneKind::try_from(bone.body_part());
+			let (Ok(kind), Some(r)) = (kind, bone.rotation_g()) else {
+				continue;
+			};
+			let rot =
+				UnitQuaternion::from_quaternion([r.x(), r.y(), r.z(), r.w()].into());
+			self.rates.update(kind, rot, now);
+		}
+		let slow = self.rates.slow(config.bone_rate_low_hz, now);
+		for &kind in &slow {
+			if self.slow_bones.insert(kind) {
+				events.push((Event::BoneRateLow, kind.to_string()));
+			}
+		}
+		self.slow_bones.retain(|kind| slow.contains(kind));
+	}
+}
+
+pub async fn run(
+	subsys: SubsystemHandle,
+	config: Alerts,
+	mut feed: watch::Receiver<Option<Stamped<FeedUpdate>>>,
+	mut diagnostics: broadcast::Receiver<Diagnostic>,
+	mut standby: watch::Receiver<bool>,
+) -> Result<()> {
+	let player = spawn_player();
+	let play = |event: Event| {
+		let alert = event.alert(&config);
+		if alert.enabled {
+			let _ = player.send(Request {
+				event,
+				sound: alert.sound.clone(),
+				volume: config.volume,
+			});
+		}
+	};
+
+	let mut watcher = Watcher::default();
+	let reminder_period = Duration::from_secs(config.reset_reminder_minutes * 60);
+	let mut last_reminder = Instant::now();
+	let mut reminder_check = tokio::time::interval(REMINDER_CHECK_INTERVAL);
+	loop {
+		tokio::select! {
+			_ = subsys.on_shutdown_requested() => return Ok(()),
+			r = feed.changed() => {
+				if r.is_err() {
+					return Ok(());
+				}
+				let events = match feed.borrow_and_update().as_ref() {
+					Some(update) => watcher.update(&config, &update.value),
+					None => continue,
+				};
+				for (event, device) in events {
+					log::warn!("{event:?}: {device}");
+					play(event);
+				}
+			}
+			r = diagnostics.recv() => match r {
+				// Already logged by the diagnostics subsystem.
+				Ok(d) if d.severity == Severity::Error => play(Event::TrackerError),
+				Ok(_) => (),
+				Err(broadcast::error::RecvError::Lagged(n)) => {
+					log::warn!("Missed alerts for {n} tracker diagnostics");
+				}
+				Err(broadcast::error::RecvError::Closed) => return Ok(()),
+			},
+			Ok(()) = standby.changed() => {
+				// Nobody is wearing the headset, so the reminder starts over.
+				last_reminder = Instant::now();
+			}
+			_ = reminder_check.tick() => {
+				if config.reset_reminder.enabled
+					&& !*standby.borrow()
+					&& !reminder_period.is_zero()
+					&& last_reminder.elapsed() >= reminder_period
+				{
+					log::info!("Reminding to reset");
+					play(Event::ResetReminder);
+					last_reminder = Instant::now();
+				}
+			}
+		}
+	}
+}
+
+/// Starts the thread that plays sounds, and returns a way to send it requests. The
+/// thread exits once the sender is dropped.
+fn spawn_player() -> mpsc::UnboundedSender<Request> {
+	let (sender, receiver) = mpsc::unbounded_channel();
+	std::thread::spawn(move || {
+		if let Err(e) = player(receiver) {
+			log::error!("Can't play alert sounds: {e:?}");
+		}
+	});
+	sender
+}
+
+#[cfg(feature = "sounds")]
+fn player(mut requests: mpsc::UnboundedReceiver<Request>) -> Result<()> {
+	use eyre::WrapErr;
+	use rodio::source::{SineWave, Source};
+	use rodio::{Decoder, OutputStream};
+	use std::fs::File;
+	use std::io::BufReader;
+
+	/// How long the built-in beep lasts.
+	const BEEP_LENGTH: Duration = Duration::from_millis(200);
+	/// A full volume sine wave is a lot louder than most sound files.
+	const BEEP_VOLUME: f32 = 0.2;
+
+	let (_stream, handle) =
+		OutputStream::try_default().wrap_err("Failed to open the audio device")?;
+	while let Some(request) = requests.blocking_recv() {
+		let result = match &request.sound {
+			Some(path) => File::open(path)
+				.wrap_err_with(|| format!("Failed to open {path:?}"))
+				.and_then(|f| {
+					Decoder::new(BufReader::new(f))
+						.wrap_err_with(|| format!("Failed to decode {path:?}"))
+				})
+				.and_then(|source| {
+					let source = source.convert_samples().amplify(request.volume);
+					Ok(handle.play_raw(source)?)
+				}),
+			None => {
+				let beep = SineWave::new(request.event.beep_hz())
+					.take_duration(BEEP_LENGTH)
+					.amplify(request.volume * BEEP_VOLUME);
+				handle.play_raw(beep).map_err(Into::into)
+			}
+		};
+		if let Err(e) = result {
+			log::error!("Failed to play sound for {:?}: {e:?}", request.event);
+		}
+	}
+	Ok(())
+}
+
+#[cfg(not(feature = "sounds"))]
+fn player(mut requests: mpsc::UnboundedReceiver<Request>) -> Result<()> {
+	if requests.blocking_recv().is_some() {
+		log::warn!("Not playing alert sounds, the `sounds` feature is disabled");
+	}
+	Ok(())
+}
@@ -0,0 +1,209 @@
+//! Looks for signs of a misconfigured setup in the feed, which the server happily
+//! tracks anyway: two trackers assigned to the same body part, and bone lengths
+//! that jump around from one update to the next. Each one is logged once when it
+//! shows up, along with how to fix it, and listed on the warning panel while the
+//! SteamVR dashboard is open.
+
+use crate::model::BoneKind;
+
+use solarxr::protocol::data_feed::DataFeedUpdate;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Length changes smaller than this, in meters, are adjustments, not jumps.
+const MIN_JUMP: f32 = 0.05;
+/// Or smaller than this fraction of the length before.
+const MIN_JUMP_FRACTION: f32 = 0.25;
+/// A jump is only listed for this long, since it is over once it happened.
+const JUMP_SHOWN_FOR: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Anomaly {
+	/// More than one tracker is assigned to `part`, so they fight over it.
+	SharedBodyPart {
+		part: BoneKind,
+		trackers: Vec<String>,
+	},
+	/// The length of `bone` changed from `from` to `to` meters in one update.
+	LengthJump { bone: BoneKind, from: f32, to: f32 },
+}
+impl Anomaly {
+	/// How to fix it, for the log and the panel.
+	pub fn suggestion(&self) -> &'static str {
+		match self {
+			Self::SharedBodyPart { .. } => {
+				"Assign all but one of them to another body part, or to none, in the \
+				 tracker settings of the SlimeVR GUI"
+			}
+			Self::LengthJump { .. } => {
+				"If you didn't just change the body proportions, check them in the \
+				 SlimeVR GUI, or run automatic proportions again"
+			}
+		}
+	}
+}
+impl fmt::Display for Anomaly {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::SharedBodyPart { part, trackers } => {
+				write!(f, "{part:?} is assigned to {}", trackers.join(" and "))
+			}
+			Self::LengthJump { bone, from, to } => {
+				write!(
+					f,
+					"The length of {bone:?} jumped from {from:.2} m to {to:.2} m"
+				)
+			}
+		}
+	}
+}
+
+/// Remembers what the feed looked like before, to tell what is new.
+#[derive(Debug, Default)]
+pub struct AnomalyDetector {
+	lengths: HashMap<BoneKind, f32>,
+	shared: Vec<Anomaly>,
+	/// With when they happened.
+	jumps: Vec<(Instant, Anomaly)>,
+}
+impl AnomalyDetector {
+	/// Looks at the trackers and bones of `update`, received at `now`. Looking at the
+	/// same update again finds nothing new.
+	pub fn update(&mut self, update: DataFeedUpdate<'_>, now: Instant) {
+		let trackers = update.devices().into_iter().flatten().flat_map(|device| {
+			let id = device.id().map_or(0, |id| id.id());
+			let device_name = device.custom_name().map(String::from);
+			let trackers = device.trackers().into_iter().flatten().enumerate();
+			trackers.filter_map(move |(i, tracker)| {
+				let info = tracker.info()?;
+				// Like the headset, which the server puts on the head on its own.
+				if info.is_computed() {
+					return None;
+				}
+				let part = BoneKind::try_from(info.body_part()).ok()?;
+				let name = info
+					.custom_name()
+					.or(info.display_name())
+					.map(String::from)
+					.or_else(|| device_name.clone())
+					.unwrap_or_else(|| format!("Device {id}"));
+				Some((part, format!("{name} ({id}.{i})")))
+			})
+		});
+		let shared = shared_body_parts(trackers);
+		for anomaly in shared.iter().filter(|a| !self.shared.contains(a)) {
+			log(anomaly);
+		}
+		self.shared = shared;
+
+		let bones = update.bones().into_iter().flatten();
+		let lengths = bones.filter_map(|bone| {
+			let kind = BoneKind::try_from(bone.body_part()).ok()?;
+			Some((kind, bone.bone_length()))
+		});
+		self.update_lengths(lengths, now);
+	}
+
+	fn update_lengths(
+		&mut self,
+		lengths: impl IntoIterator<Item = (BoneKind, f32)>,
+		now: Instant,
+	) {
+		self.jumps.retain(|(t, _)| now - *t < JUMP_SHOWN_FOR);
+		for (bone, to) in lengths {
+			let Some(from) = self.lengths.insert(bone, to) else {
+				continue;
+			};
+			let change = (to - from).abs();
+			if change > MIN_JUMP && change > from * MIN_JUMP_FRACTION {
+				let anomaly = Anomaly::LengthJump { bone, from, to };
+				log(&anomaly);
+				// Only the latest jump of each bone is listed.
+				let same_bone = |a: &Anomaly| match a {
+					Anomaly::LengthJump { bone: b, .. } => *b == bone,
+					Anomaly::SharedBodyPart { .. } => false,
+				};
+				self.jumps.retain(|(_, a)| !same_bone(a));
+				self.jumps.push((now, anomaly));
+			}
+		}
+	}
+
+	/// What is wrong right now, and what went wrong recently.
+	pub fn current(&self) -> impl Iterator<Item = &Anomaly> {
+		self.shared.iter().chain(self.jumps.iter().map(|(_, a)| a))
+	}
+}
+
+fn log(anomaly: &Anomaly) {
+	log::warn!("{anomaly}. {}.", anomaly.suggestion());
+}
+
+/// The body parts that more than one of `trackers` is assigned to, in the order of
+/// [`BoneKind`].
+fn shared_body_parts(
+	trackers: impl IntoIterator<Item = (BoneKind, String)>,
+) -> Vec<Anomaly> {
+	let mut by_part: BTreeMap<usize, (BoneKind, Vec<String>)> = BTreeMap::new();
+	for (part, name) in trackers {
+		let entry = by_part
+			.entry(usize::from(part))
+			.or_insert_with(|| (part, Vec::new()));
+		entry.1.push(name);
+	}
+	by_part
+		.into_values()
+		.filter(|(_, trackers)| trackers.len() > 1)
+		.map(|(part, trackers)| Anomaly::SharedBodyPart { part, trackers })
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn trackers_on_the_same_part() {
+		let trackers = [
+			(BoneKind::FootL, "Left ankle".to_owned()),
+			(BoneKind::FootR, "Right ankle".to_owned()),
+			(BoneKind::FootL, "Spare".to_owned()),
+		];
+		let shared = shared_body_parts(trackers);
+		assert_eq!(
+			shared,
+			[Anomaly::SharedBodyPart {
+				part: BoneKind::FootL,
+				trackers: vec!["Left ankle".to_owned(), "Spare".to_owned()],
+			}]
+		);
+		assert_eq!(
+			shared[0].to_string(),
+			"FootL is assigned to Left ankle and Spare"
+		);
+	}
+
+	#[test]
+	fn length_jumps() {
+		let mut detector = AnomalyDetector::default();
+		let start = Instant::now();
+		detector.update_lengths([(BoneKind::Chest, 0.3)], start);
+		// Small adjustments, like while tweaking the proportions, are fine.
+		detector.update_lengths([(BoneKind::Chest, 0.32)], start);
+		assert_eq!(detector.current().count(), 0);
+
+		detector.update_lengths([(BoneKind::Chest, 0.6)], start);
+		let jumps: Vec<&Anomaly> = detector.current().collect();
+		assert!(matches!(
+			jumps[..],
+			[Anomaly::LengthJump {
+				bone: BoneKind::Chest,
+				..
+			}]
+		));
+
+		detector.update_lengths([], start + JUMP_SHOWN_FOR);
+		assert_eq!(detector.current().count(), 0);
+	}
+}
@@ -0,0 +1,52 @@
+//! Detects which VR application is currently running in SteamVR.
+
+use ovr_overlay as ovr;
+use std::time::{Duration, Instant};
+
+/// How often to ask OpenVR which application is running.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Gets the application key (like `steam.app.620980`) of the scene application
+/// that currently has focus, if any.
+pub fn current_app_key(context: &ovr::Context) -> Option<String> {
+	let pid = context.compositor_mngr().get_current_scene_focus_process();
+	if pid == 0 {
+		return None;
+	}
+	context
+		.applications_mngr()
+		.get_application_key_by_process_id(pid)
+		.ok()
+}
+
+/// Keeps track of the current application, and reports when it changes.
+#[derive(Debug, Default)]
+pub struct AppDetector {
+	current: Option<String>,
+	last_poll: Option<Instant>,
+}
+impl AppDetector {
+	/// Polls OpenVR at most once every [`POLL_INTERVAL`]. Returns `true` if the
+	/// application changed.
+	pub fn poll(&mut self, context: &ovr::Context) -> bool {
+		let now = Instant::now();
+		if self
+			.last_poll
+			.map_or(false, |last| now - last < POLL_INTERVAL)
+		{
+			return false;
+		}
+		self.last_poll = Some(now);
+
+		let app_key = current_app_key(context);
+		if app_key == self.current {
+			return false;
+		}
+		self.current = app_key;
+		true
+	}
+
+	pub fn current(&self) -> Option<&str> {
+		self.current.as_deref()
+	}
+}
@@ -1,3 +1,6 @@
+use serde::de::{self, Deserialize, Deserializer};
+use std::str::FromStr;
+
 macro_rules! def_color {
 	($name:ident, $r:literal, $g:literal, $b: literal, $a: literal) => {
 		pub const $name: RGBA = RGBA::new($r, $g, $b, $a);
@@ -35,3 +38,45 @@ impl RGBA {
 	def_color!(FUCHSIA, 255, 0, 255);
 	def_color!(PURPLE, 128, 0, 128);
 }
+
+/// Parses `#rrggbb`, or `#rrggbbaa` for a color that isn't opaque, like in the
+/// config.
+impl FromStr for RGBA {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let invalid = || format!("`{s}` is not a color like `#ff8000`");
+		let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+		if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+			return Err(invalid());
+		}
+		let channel = |i: usize| {
+			hex.get(i..i + 2)
+				.map_or(Ok(255), |c| u8::from_str_radix(c, 16))
+				.map_err(|_| invalid())
+		};
+		let [r, g, b, a] = [0, 2, 4, 6].map(channel);
+		Ok(Self::new(r?, g?, b?, a?))
+	}
+}
+impl<'de> Deserialize<'de> for RGBA {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		String::deserialize(deserializer)?
+			.parse()
+			.map_err(de::Error::custom)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse() {
+		assert_eq!("#ff8000".parse(), Ok(RGBA::new(255, 128, 0, 255)));
+		assert_eq!("#ff800040".parse(), Ok(RGBA::new(255, 128, 0, 64)));
+		assert!("ff8000".parse::<RGBA>().is_err());
+		assert!("#ff80".parse::<RGBA>().is_err());
+		assert!("#gg8000".parse::<RGBA>().is_err());
+	}
+}
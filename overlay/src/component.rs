@@ -0,0 +1,178 @@
+//! Optional features that only need to react to what the overlay sees, like
+//! exporting the skeleton. Each one is a [`Component`] on its own subsystem, which
+//! receives every [`Event`] published on the [`Bus`]. Adding one means registering
+//! it in `main`, instead of threading another channel through the render loop.
+
+use crate::model::{BoneKind, Isometry};
+use crate::timebase::Timebase;
+
+use eyre::Result;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_graceful_shutdown::{SubsystemHandle, Toplevel};
+
+/// How many events a component can fall behind before it misses some.
+const BUS_CAPACITY: usize = 64;
+
+/// All the bones from a single feed update.
+#[derive(Debug)]
+pub struct Frame {
+	/// When the feed update arrived. Never goes backwards, see [`crate::timebase`].
+	pub timestamp: SystemTime,
+	pub bones: Vec<(BoneKind, Isometry)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Event {
+	/// The server sent a feed update.
+	Frame(Arc<Frame>),
+	/// The headset went into standby, or woke up.
+	Standby(bool),
+	/// Recording was started or stopped from the HTTP API.
+	Recording(bool),
+	/// How many trackers the server reports as connected, whenever that changes.
+	Trackers(usize),
+}
+
+/// Publishing never blocks, a component that falls behind misses the oldest events
+/// instead.
+pub type Bus = broadcast::Sender<Event>;
+
+pub fn bus() -> Bus {
+	broadcast::channel(BUS_CAPACITY).0
+}
+
+/// Whether publishing is worth it, because any component is listening.
+pub fn has_subscribers(bus: &Bus) -> bool {
+	bus.receiver_count() > 0
+}
+
+/// What the sessions publish on the bus, each thing once. They also loop without
+/// new feed updates, and the tracker count rarely changes.
+pub struct Publisher {
+	bus: Bus,
+	timebase: Timebase,
+	/// When the feed update of the last frame arrived.
+	last_frame: Option<Instant>,
+	last_trackers: Option<usize>,
+}
+impl Publisher {
+	pub fn new(bus: Bus, timebase: Timebase) -> Self {
+		Self {
+			bus,
+			timebase,
+			last_frame: None,
+			last_trackers: None,
+		}
+	}
+
+	/// Publishes the bones of the feed update that arrived at `received`, unless
+	/// they already were, or nobody would get them.
+	pub fn frame(
+		&mut self,
+		received: Instant,
+		bones: impl IntoIterator<Item = (BoneKind, Isometry)>,
+	) {
+		if !has_subscribers(&self.bus) || self.last_frame == Some(received) {
+			return;
+		}
+		self.last_frame = Some(received);
+		let frame = Frame {
+			timestamp: self.timebase.wall_time(received),
+			bones: bones.into_iter().collect(),
+		};
+		// Only fails if nobody is listening.
+		let _ = self.bus.send(Event::Frame(Arc::new(frame)));
+	}
+
+	/// Publishes how many trackers are connected, if that changed.
+	pub fn trackers(&mut self, connected: usize) {
+		if self.last_trackers != Some(connected) {
+			self.last_trackers = Some(connected);
+			let _ = self.bus.send(Event::Trackers(connected));
+		}
+	}
+
+	pub fn standby(&self, is_standby: bool) {
+		let _ = self.bus.send(Event::Standby(is_standby));
+	}
+}
+
+pub trait Component: Send {
+	/// The name of the subsystem the component runs on.
+	fn name(&self) -> &'static str;
+
+	/// Called once before the first event.
+	fn init(&mut self) -> Result<()> {
+		Ok(())
+	}
+
+	/// Called for every event on the bus. An error shuts down the overlay.
+	fn update(&mut self, event: &Event) -> Result<()>;
+
+	/// Called once when the overlay shuts down, if `init` succeeded.
+	fn shutdown(&mut self) -> Result<()> {
+		Ok(())
+	}
+}
+
+/// Starts a subsystem for each of `components`. They are subscribed to `bus`
+/// before returning, so they don't miss any events.
+pub fn start_all(
+	mut toplevel: Toplevel,
+	bus: &Bus,
+	components: Vec<Box<dyn Component>>,
+) -> Toplevel {
+	for component in components {
+		let events = bus.subscribe();
+		toplevel = toplevel.start(component.name(), move |s| run(s, component, events));
+	}
+	toplevel
+}
+
+async fn run(
+	subsys: SubsystemHandle,
+	mut component: Box<dyn Component>,
+	mut events: broadcast::Receiver<Event>,
+) -> Result<()> {
+	let name = component.name();
+	component.init()?;
+	loop {
+		tokio::select! {
+			_ = subsys.on_shutdown_requested() => {
+				log::debug!("{name} shutdown requested");
+				break;
+			}
+			event = events.recv() => match event {
+				Ok(event) => component.update(&event)?,
+				Err(RecvError::Lagged(n)) => {
+					log::warn!("{name} is falling behind, skipped {n} events");
+				}
+				Err(RecvError::Closed) => break,
+			},
+		}
+	}
+	component.shutdown()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn publishes_each_thing_once() {
+		let bus = bus();
+		let mut events = bus.subscribe();
+		let mut publisher = Publisher::new(bus, Timebase::new());
+		let received = Instant::now();
+		let bones = [(BoneKind::Hip, Isometry::identity())];
+		publisher.frame(received, bones);
+		publisher.frame(received, bones);
+		publisher.trackers(3);
+		publisher.trackers(3);
+		assert!(matches!(events.try_recv(), Ok(Event::Frame(f)) if f.bones.len() == 1));
+		assert!(matches!(events.try_recv(), Ok(Event::Trackers(3))));
+		assert!(events.try_recv().is_err());
+	}
+}
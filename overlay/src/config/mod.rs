@@ -0,0 +1,698 @@
+//! User configuration of the overlay, loaded from a TOML file. The `[skeleton]`
+//! and `[smoothing]` tables apply as soon as the file is saved, see [`reload`], the
+//! rest once the overlay restarts.
+//!
+//! ```toml
+//! # Always show the skeleton in Dance Dash
+//! [profiles."steam.app.1283460"]
+//! visible = true
+//!
+//! # Never show it in Phasmophobia
+//! [profiles."steam.app.739630"]
+//! visible = false
+//!
+//! # Play my own sound when a tracker disconnects
+//! [alerts.tracker_disconnected]
+//! sound = "sounds/disconnected.ogg"
+//!
+//! # Show on Discord how many trackers are connected
+//! [presence]
+//! discord = true
+//! discord_client_id = "1234567890"
+//!
+//! # Draw a trail behind the left foot
+//! [trails.FootL]
+//! seconds = 2.0
+//!
+//! # Show a small copy of the skeleton in the bottom right of the view
+//! [hud]
+//! enabled = true
+//!
+//! # Log how far the hands and feet reached when the overlay closes
+//! [reach]
+//! enabled = true
+//!
+//! # Make up for the time that bone updates take to get to the headset
+//! [latency]
+//! compensate = true
+//!
+//! # Smooth the skeleton between feed updates
+//! [interpolation]
+//! enabled = true
+//!
+//! # Hold the skeleton still while standing, except for the hands
+//! [deadband]
+//! enabled = true
+//! bones = { HandL = { position_mm = 0 }, HandR = { position_mm = 0 } }
+//!
+//! # Make up the neck and head from the headset, without trackers on them
+//! [neck]
+//! enabled = true
+//!
+//! # Smooth the feet more than the rest of the skeleton
+//! [smoothing]
+//! enabled = true
+//! bones = { FootL = 80, FootR = 80 }
+//!
+//! # Draw where each tracker is, and which way it faces
+//! [tracker_markers]
+//! enabled = true
+//!
+//! # Show the last warnings and errors above the headset's view
+//! [log_console]
+//! enabled = true
+//!
+//! # Keep the bones on their own overlays, even while the game drops frames
+//! [adaptive_quality]
+//! merge = false
+//!
+//! # Thicker bones, without the head, and with orange feet
+//! [skeleton]
+//! radius = 0.004
+//! hidden = ["Head"]
+//! colors = { FootL = "#ff8000", FootR = "#ff8000" }
+//!
+//! # Two people sharing the trackers, see `crate::users`
+//! [users.alex]
+//! theme = "stream"
+//! headset_height = 1.62
+//!
+//! [users.sam]
+//! server = "ws://192.168.1.20:21110"
+//! headset_height = 1.85
+//!
+//! # Send the bones to TouchDesigner
+//! [osc]
+//! enabled = true
+//! address = "127.0.0.1:7000"
+//!
+//! # Circle around the skeleton when rendering a CSV export
+//! [replay_camera]
+//! orbit_seconds = 10.0
+//! ```
+//!
+//! Unknown keys and out of range values are errors, see [`validate`].
+
+pub mod reload;
+mod validate;
+
+use self::validate::Problems;
+
+use crate::model::skeleton::BONE_RADIUS;
+use crate::model::BoneKind;
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_CONFIG_PATH: &str = "overlay.toml";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+	/// Per-application profiles, keyed by the SteamVR application key, like
+	/// `steam.app.620980`.
+	pub profiles: HashMap<String, Profile>,
+	/// How far ahead in milliseconds to extrapolate bone rotations, to compensate
+	/// for latency. `0` disables extrapolation.
+	pub extrapolation_ms: u64,
+	/// Also extrapolate to when the next frame will be displayed on the headset,
+	/// based on OpenVR's frame timing.
+	pub predict_photon_time: bool,
+	pub latency: Latency,
+	pub interpolation: Interpolation,
+	pub smoothing: Smoothing,
+	pub deadband: Deadband,
+	pub neck: Neck,
+	/// Draw the chest, waist, and hip as straight bones, instead of as one curved
+	/// spine.
+	pub straight_spine: bool,
+	/// Only show the skeleton while the SteamVR dashboard is open, so that it is
+	/// there to check on, but out of the way while playing.
+	pub only_with_dashboard: bool,
+	pub alerts: Alerts,
+	/// The port to listen on for the problems that trackers broadcast about
+	/// themselves. `0` disables listening.
+	pub diagnostics_port: u16,
+	/// The port of the HTTP API on localhost, see [`crate::http_api`]. `0` disables
+	/// it.
+	pub api_port: u16,
+	pub presence: Presence,
+	pub osc: Osc,
+	/// Motion trails behind the ends of bones, keyed by the name of the bone, like
+	/// `FootL`.
+	pub trails: HashMap<String, Trail>,
+	pub hud: Hud,
+	pub reach: Reach,
+	pub tracker_markers: TrackerMarkers,
+	pub log_console: LogConsole,
+	pub adaptive_quality: AdaptiveQuality,
+	pub skeleton: SkeletonStyle,
+	/// Skeleton styles that the HTTP API can switch to, keyed by name.
+	pub themes: HashMap<String, SkeletonStyle>,
+	/// The people who use the overlay on this PC, keyed by name, see
+	/// [`crate::users`].
+	pub users: HashMap<String, User>,
+	pub replay_camera: ReplayCamera,
+}
+impl Default for Config {
+	fn default() -> Self {
+		Self {
+			profiles: HashMap::new(),
+			extrapolation_ms: 0,
+			predict_photon_time: false,
+			latency: Latency::default(),
+			interpolation: Interpolation::default(),
+			smoothing: Smoothing::default(),
+			deadband: Deadband::default(),
+			neck: Neck::default(),
+			straight_spine: false,
+			only_with_dashboard: false,
+			alerts: Alerts::default(),
+			diagnostics_port: 6970,
+			api_port: 0,
+			presence: Presence::default(),
+			osc: Osc::default(),
+			trails: HashMap::new(),
+			hud: Hud::default(),
+			reach: Reach::default(),
+			tracker_markers: TrackerMarkers::default(),
+			log_console: LogConsole::default(),
+			adaptive_quality: AdaptiveQuality::default(),
+			skeleton: SkeletonStyle::default(),
+			themes: HashMap::new(),
+			users: HashMap::new(),
+			replay_camera: ReplayCamera::default(),
+		}
+	}
+}
+impl Config {
+	/// Loads the config from `path`. If the file doesn't exist, the default config
+	/// is used instead.
+	pub fn load(path: &Path) -> Result<Self> {
+		let contents = match std::fs::read_to_string(path) {
+			Ok(c) => c,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+				log::info!("No config found at {path:?}, using defaults");
+				return Ok(Self::default());
+			}
+			Err(e) => {
+				return Err(e).wrap_err_with(|| format!("Failed to read {path:?}"))
+			}
+		};
+		Self::parse(&contents).wrap_err_with(|| format!("Invalid config in {path:?}"))
+	}
+
+	/// Parses and validates the contents of a config file.
+	pub fn parse(contents: &str) -> Result<Self, Problems> {
+		let config: Self = toml::from_str(contents)
+			.map_err(|e| Problems(vec![validate::parse_error(&e)]))?;
+		let problems = validate::check(&config, contents);
+		if problems.is_empty() {
+			Ok(config)
+		} else {
+			Err(Problems(problems))
+		}
+	}
+
+	/// The profile to use for the application with key `app_key`, if any.
+	pub fn profile(&self, app_key: &str) -> Option<&Profile> {
+		self.profiles.get(app_key)
+	}
+
+	/// The settings of the user named `name`, if anyone is using the overlay.
+	pub fn user(&self, name: Option<&str>) -> Option<&User> {
+		self.users.get(name?)
+	}
+}
+
+/// Draws the skeleton every frame of the headset, between the last two feed
+/// updates, see [`crate::interpolation`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Interpolation {
+	pub enabled: bool,
+	/// How far behind the feed to draw, in units of the time between updates.
+	pub smoothing: f32,
+	/// The furthest to keep moving past the last update, in milliseconds.
+	pub max_extrapolation_ms: u64,
+}
+impl Default for Interpolation {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			smoothing: 1.0,
+			max_extrapolation_ms: 20,
+		}
+	}
+}
+
+/// Smooths each bone on its own, see [`crate::smoothing`]. Applies as soon as the
+/// file is saved, like the `[skeleton]`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Smoothing {
+	pub enabled: bool,
+	/// The time constant of the bones that aren't in `bones`, in milliseconds. `0`
+	/// doesn't smooth them.
+	pub time_constant_ms: f32,
+	/// Time constants in milliseconds, keyed by the name of the bone, like `FootL`.
+	pub bones: HashMap<String, f32>,
+	/// Also draw the unsmoothed skeleton from the start, see the tray.
+	pub preview: bool,
+	/// The color of the unsmoothed skeleton in the preview.
+	pub raw_color: RGBA,
+}
+impl Smoothing {
+	pub fn time_constant_ms(&self, kind: BoneKind) -> f32 {
+		let ms = self.bones.get(&format!("{kind:?}")).copied();
+		ms.unwrap_or(self.time_constant_ms)
+	}
+}
+impl Default for Smoothing {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			time_constant_ms: 30.,
+			bones: HashMap::new(),
+			preview: false,
+			raw_color: RGBA::FUCHSIA,
+		}
+	}
+}
+
+/// Publishes the tracking status to Discord or a webhook, see [`crate::presence`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Presence {
+	/// Show the status as the Rich Presence of the Discord app.
+	pub discord: bool,
+	/// The application id of the Discord application to show the status as.
+	pub discord_client_id: String,
+	/// A URL to POST the status to as JSON, whenever it changes.
+	pub webhook: Option<String>,
+	/// The least time between two updates, in seconds. Discord only takes a few a
+	/// minute.
+	pub min_interval_s: u64,
+}
+impl Default for Presence {
+	fn default() -> Self {
+		Self {
+			discord: false,
+			discord_client_id: String::new(),
+			webhook: None,
+			min_interval_s: 15,
+		}
+	}
+}
+
+/// Sends the skeleton to an OSC receiver, see [`crate::osc`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Osc {
+	pub enabled: bool,
+	/// The IP address and port of the receiver.
+	pub address: String,
+	/// What the addresses of the messages start with.
+	pub prefix: String,
+}
+impl Default for Osc {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			address: "127.0.0.1:9000".to_owned(),
+			prefix: "/slimevr".to_owned(),
+		}
+	}
+}
+
+/// Measures how long bone updates take to get to the headset, see
+/// [`crate::latency`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Latency {
+	/// How often to log the percentiles, in seconds. `0` disables logging them.
+	pub log_interval_s: u64,
+	/// Draw further ahead by the measured latency, on top of `extrapolation_ms`.
+	pub compensate: bool,
+	/// The most to draw further ahead by, in milliseconds.
+	pub max_compensation_ms: u64,
+}
+impl Default for Latency {
+	fn default() -> Self {
+		Self {
+			log_interval_s: 60,
+			compensate: false,
+			max_compensation_ms: 30,
+		}
+	}
+}
+
+/// Holds bones still while they only jitter, see [`crate::deadband`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Deadband {
+	pub enabled: bool,
+	/// How far the bones that aren't in `bones` have to move before they are drawn
+	/// somewhere else, in millimeters.
+	pub position_mm: f32,
+	/// And how far they have to turn, in degrees.
+	pub rotation_degrees: f32,
+	/// Thresholds keyed by the name of the bone, like `FootL`.
+	pub bones: HashMap<String, Threshold>,
+}
+impl Deadband {
+	/// How far `kind` has to move in millimeters, and turn in degrees.
+	pub fn threshold(&self, kind: BoneKind) -> (f32, f32) {
+		let bone = self.bones.get(&format!("{kind:?}"));
+		(
+			bone.and_then(|t| t.position_mm).unwrap_or(self.position_mm),
+			bone.and_then(|t| t.rotation_degrees)
+				.unwrap_or(self.rotation_degrees),
+		)
+	}
+}
+impl Default for Deadband {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			position_mm: 0.5,
+			rotation_degrees: 0.2,
+			bones: HashMap::new(),
+		}
+	}
+}
+
+/// The deadband of one bone. What is left out is taken from the rest of the
+/// `[deadband]`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Threshold {
+	pub position_mm: Option<f32>,
+	pub rotation_degrees: Option<f32>,
+}
+
+/// Draws the neck and head from the headset while the feed lacks them, see
+/// [`crate::neck`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Neck {
+	pub enabled: bool,
+	/// Where the neck pivots in meters, relative to the headset. `x` points right,
+	/// `y` up, and `z` backwards.
+	pub pivot_offset: [f32; 3],
+	/// How long the neck is in meters, while there is no chest to reach down to.
+	pub neck_length: f32,
+}
+impl Default for Neck {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			pivot_offset: [0., -0.1, 0.1],
+			neck_length: 0.1,
+		}
+	}
+}
+
+/// Settings that get applied while a particular application is running.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+	/// Forces the skeleton to be shown or hidden, regardless of the
+	/// `DisplaySettings` from the server.
+	pub visible: Option<bool>,
+}
+
+/// What differs between the people who use the overlay on this PC. Whatever isn't
+/// set is the same for everyone.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct User {
+	/// Where to remember their bone lengths, by default next to `--bone-lengths`
+	/// with their name in it.
+	pub bone_lengths: Option<PathBuf>,
+	/// One of the `[themes]` to draw the skeleton with.
+	pub theme: Option<String>,
+	/// The SolarXR address of their server, like `ws://192.168.1.20:21110`.
+	pub server: Option<String>,
+	/// How high their headset is while they stand, in meters, to suggest switching
+	/// to them when someone that tall puts it on.
+	pub headset_height: Option<f32>,
+}
+
+/// Audio cues for tracking events.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Alerts {
+	/// Volume of all alerts, where `1.0` plays sound files as they are.
+	pub volume: f32,
+	/// When a tracker that was connected stops reporting.
+	pub tracker_disconnected: Alert,
+	/// When the battery of a tracker drops to `battery_low_percent`.
+	pub battery_low: Alert,
+	pub battery_low_percent: u8,
+	/// When a tracker reports a problem, like a sensor that stopped responding.
+	pub tracker_error: Alert,
+	/// A reminder every `reset_reminder_minutes` to reset, before drift adds up.
+	/// Disabled by default, since we can't see when the user last reset.
+	pub reset_reminder: Alert,
+	pub reset_reminder_minutes: u64,
+	/// When a bone that was updating at least `bone_rate_low_hz` times per second
+	/// slows down below it, which usually means its tracker is dropping out. Slow
+	/// bones are also tinted red.
+	pub bone_rate_low: Alert,
+	pub bone_rate_low_hz: f32,
+}
+impl Default for Alerts {
+	fn default() -> Self {
+		Self {
+			volume: 1.0,
+			tracker_disconnected: Alert::default(),
+			battery_low: Alert::default(),
+			battery_low_percent: 15,
+			tracker_error: Alert::default(),
+			reset_reminder: Alert {
+				enabled: false,
+				sound: None,
+			},
+			reset_reminder_minutes: 30,
+			bone_rate_low: Alert::default(),
+			bone_rate_low_hz: 10.0,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Alert {
+	pub enabled: bool,
+	/// A `.wav`, `.ogg`, `.flac`, or `.mp3` file to play, instead of the built-in
+	/// beep.
+	pub sound: Option<PathBuf>,
+}
+impl Default for Alert {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			sound: None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Trail {
+	/// How far back the trail goes.
+	pub seconds: f32,
+	/// How many straight pieces the trail is made of. More look smoother, but each
+	/// one costs two overlays.
+	pub segments: usize,
+}
+impl Default for Trail {
+	fn default() -> Self {
+		Self {
+			seconds: 1.0,
+			segments: 16,
+		}
+	}
+}
+
+/// A small copy of the skeleton that is locked to the headset's view.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Hud {
+	pub enabled: bool,
+	/// How big the copy is relative to the skeleton.
+	pub scale: f32,
+	/// Where the copy's hip goes in meters, relative to the headset. `x` points
+	/// right, `y` up, and `z` backwards.
+	pub offset: [f32; 3],
+}
+impl Default for Hud {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			scale: 0.15,
+			offset: [0.2, -0.15, -0.6],
+		}
+	}
+}
+
+/// Keeps track of how far the skeleton reaches over a session, and logs it when the
+/// session ends.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Reach {
+	pub enabled: bool,
+	/// Also draw the box around everywhere the skeleton has been.
+	pub show_box: bool,
+}
+impl Default for Reach {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			show_box: true,
+		}
+	}
+}
+
+/// A small gizmo at each tracker, colored by whether it is fine, stale, or
+/// disconnected, to check how the trackers are mounted and whether they drift.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackerMarkers {
+	pub enabled: bool,
+	/// The length of the axes of the gizmo, in meters.
+	pub size: f32,
+}
+impl Default for TrackerMarkers {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			size: 0.05,
+		}
+	}
+}
+
+/// A panel above the headset's view with the last lines that were logged, for when
+/// the console window and log file are out of reach, like while streaming to a
+/// standalone headset. The tray toggles it as well.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConsole {
+	/// Whether it is shown from the start.
+	pub enabled: bool,
+	/// How many lines it shows.
+	pub lines: usize,
+	/// The least severe lines that it shows.
+	pub level: LogLevel,
+}
+impl Default for LogConsole {
+	fn default() -> Self {
+		Self {
+			enabled: false,
+			lines: 10,
+			level: LogLevel::Warn,
+		}
+	}
+}
+
+/// Draws less while the game drops frames, see [`crate::quality`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveQuality {
+	pub enabled: bool,
+	/// Also draw the bones on one overlay meanwhile, which is less work for SteamVR
+	/// but looks flat and choppy.
+	pub merge: bool,
+}
+impl Default for AdaptiveQuality {
+	fn default() -> Self {
+		Self {
+			enabled: true,
+			merge: true,
+		}
+	}
+}
+
+/// How the bones of the skeleton are drawn.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SkeletonStyle {
+	/// The radius of the bones, in meters.
+	pub radius: f32,
+	/// Colors like `#ff8000`, keyed by the name of the bone. Bones without one keep
+	/// their default color.
+	pub colors: HashMap<String, RGBA>,
+	/// The names of the bones that are never drawn, unless a script shows them.
+	pub hidden: Vec<String>,
+}
+impl SkeletonStyle {
+	pub fn color(&self, kind: BoneKind) -> Option<RGBA> {
+		self.colors.get(&format!("{kind:?}")).copied()
+	}
+
+	pub fn hidden(&self) -> impl Iterator<Item = BoneKind> + '_ {
+		self.hidden.iter().filter_map(|name| name.parse().ok())
+	}
+}
+impl Default for SkeletonStyle {
+	fn default() -> Self {
+		Self {
+			radius: BONE_RADIUS,
+			colors: HashMap::new(),
+			hidden: Vec::new(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+	Error,
+	Warn,
+	Info,
+}
+impl From<LogLevel> for log::Level {
+	fn from(level: LogLevel) -> Self {
+		match level {
+			LogLevel::Error => Self::Error,
+			LogLevel::Warn => Self::Warn,
+			LogLevel::Info => Self::Info,
+		}
+	}
+}
+
+/// The virtual camera that `--render-replay` films a CSV export with, see
+/// [`crate::replay`].
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReplayCamera {
+	/// Where the camera is, in meters.
+	pub position: [f32; 3],
+	/// What it looks at, in meters.
+	pub target: [f32; 3],
+	/// The vertical field of view, in degrees.
+	pub fov_degrees: f32,
+	pub width: usize,
+	pub height: usize,
+	pub fps: f32,
+	/// How long the camera takes to circle around the target once. `0` keeps it in
+	/// place.
+	pub orbit_seconds: f32,
+}
+impl Default for ReplayCamera {
+	fn default() -> Self {
+		Self {
+			position: [0., 1.2, 3.],
+			target: [0., 1., 0.],
+			fov_degrees: 60.,
+			width: 1280,
+			height: 720,
+			fps: 30.,
+			orbit_seconds: 0.,
+		}
+	}
+}
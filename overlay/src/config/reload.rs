@@ -0,0 +1,72 @@
+//! Watches the config file, so that changes to the [`SkeletonStyle`] and the
+//! [`Smoothing`] show up as soon as it is saved. The rest of the config is only read
+//! on startup, since it decides which overlays get created.
+
+use super::{Config, SkeletonStyle, Smoothing};
+
+use eyre::Result;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// How often to look at when the file was last modified. Cheap, and quick enough to
+/// feel instant while trying out colors.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reloads the config at `path` whenever it changes until shutdown, and sends its
+/// style to `style` and its smoothing to `smoothing`. Mistakes are logged, and keep
+/// the style and smoothing from before them.
+pub async fn run(
+	subsys: SubsystemHandle,
+	path: PathBuf,
+	style: Arc<watch::Sender<SkeletonStyle>>,
+	smoothing: watch::Sender<Smoothing>,
+) -> Result<()> {
+	let mut last_modified = modified(&path);
+	loop {
+		tokio::select! {
+			_ = subsys.on_shutdown_requested() => {
+				log::debug!("config reload shutdown requested");
+				return Ok(());
+			}
+			_ = tokio::time::sleep(POLL_INTERVAL) => (),
+		}
+		let modified = modified(&path);
+		if modified == last_modified {
+			continue;
+		}
+		last_modified = modified;
+
+		let config = match Config::load(&path) {
+			Ok(config) => config,
+			Err(e) => {
+				log::error!("{e:?}");
+				log::warn!("Keeping the style and smoothing from before the mistake");
+				continue;
+			}
+		};
+		let restyled = *style.borrow() != config.skeleton;
+		let resmoothed = *smoothing.borrow() != config.smoothing;
+		if !restyled && !resmoothed {
+			log::info!(
+				"Reloaded {path:?}, changes outside of `[skeleton]` and `[smoothing]` \
+				 apply once the overlay restarts"
+			);
+		}
+		if restyled {
+			log::info!("Reloaded {path:?}, applying the new skeleton style");
+			style.send_replace(config.skeleton);
+		}
+		if resmoothed {
+			log::info!("Reloaded {path:?}, applying the new smoothing");
+			smoothing.send_replace(config.smoothing);
+		}
+	}
+}
+
+/// `None` while the file doesn't exist, which makes creating it count as a change.
+fn modified(path: &Path) -> Option<SystemTime> {
+	std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
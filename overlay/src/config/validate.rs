@@ -0,0 +1,608 @@
+//! Checks the config for mistakes, so that a typo doesn't silently fall back to a
+//! default, or a value that makes no sense doesn't bring the overlay down later.
+//! Each mistake is reported with the line it is on, and a fix if we can guess one.
+
+use crate::model::BoneKind;
+
+use super::{Config, SkeletonStyle};
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+
+/// One mistake in the config.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Problem {
+	/// Starting at 1, if we could tell.
+	pub line: Option<usize>,
+	pub message: String,
+	pub hint: Option<String>,
+}
+impl fmt::Display for Problem {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if let Some(line) = self.line {
+			write!(f, "line {line}: ")?;
+		}
+		f.write_str(&self.message)?;
+		if let Some(hint) = &self.hint {
+			write!(f, ". {hint}")?;
+		}
+		Ok(())
+	}
+}
+
+/// All the mistakes in the config, so that they can be fixed in one go.
+#[derive(Debug)]
+pub struct Problems(pub Vec<Problem>);
+impl fmt::Display for Problems {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		for (i, problem) in self.0.iter().enumerate() {
+			if i > 0 {
+				writeln!(f)?;
+			}
+			write!(f, "{problem}")?;
+		}
+		Ok(())
+	}
+}
+impl std::error::Error for Problems {}
+
+/// Explains why `contents` couldn't be parsed. This covers syntax errors, unknown
+/// keys, and values of the wrong type.
+pub fn parse_error(err: &toml::de::Error) -> Problem {
+	let line = err.line_col().map(|(line, _col)| line + 1);
+	let text = err.to_string();
+	// toml appends the location, which we show at the start instead.
+	let message = match text.rfind(" at line ") {
+		Some(i) => text[..i].to_owned(),
+		None => text,
+	};
+	let hint = if message.starts_with("invalid type") {
+		Some(
+			"Text needs quotes around it, numbers and `true` or `false` don't"
+				.to_owned(),
+		)
+	} else {
+		unknown_field_hint(&message)
+	};
+	Problem {
+		line,
+		message,
+		hint,
+	}
+}
+
+/// serde reports unknown keys as ``unknown field `volum`, expected one of
+/// `volume`, ...``, and toml adds `` for key `alerts` `` to that.
+fn unknown_field_hint(message: &str) -> Option<String> {
+	let rest = message.strip_prefix("unknown field `")?;
+	let (field, rest) = rest.split_once('`')?;
+	let expected = rest.split(" for key ").next().unwrap_or(rest);
+	let candidates = expected.split('`').skip(1).step_by(2);
+	suggest(field, candidates)
+}
+
+fn suggest<'a>(
+	wrong: &str,
+	candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<String> {
+	closest(wrong, candidates).map(|c| format!("Did you mean `{c}`?"))
+}
+
+/// The candidate that takes the fewest edits to turn into `wrong`, if it is close
+/// enough to plausibly be a typo of it.
+fn closest<'a>(
+	wrong: &str,
+	candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+	let wrong = wrong.to_lowercase();
+	candidates
+		.into_iter()
+		.map(|c| (edit_distance(&wrong, &c.to_lowercase()), c))
+		.filter(|&(distance, c)| distance <= (c.len() / 3).max(1))
+		.min_by_key(|&(distance, _)| distance)
+		.map(|(_, c)| c)
+}
+
+/// The Levenshtein distance, counting inserted, removed, and replaced characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+	let b: Vec<char> = b.chars().collect();
+	let mut row: Vec<usize> = (0..=b.len()).collect();
+	for a in a.chars() {
+		let mut diagonal = row[0];
+		row[0] += 1;
+		for (j, &b) in b.iter().enumerate() {
+			let replaced = diagonal + usize::from(a != b);
+			diagonal = row[j + 1];
+			row[j + 1] = replaced.min(row[j] + 1).min(diagonal + 1);
+		}
+	}
+	row[b.len()]
+}
+
+/// Finds the values that parsed, but are out of range.
+pub fn check(config: &Config, contents: &str) -> Vec<Problem> {
+	let mut c = Checker {
+		contents,
+		problems: Vec::new(),
+	};
+	c.range(&["extrapolation_ms"], config.extrapolation_ms, 0..=1000);
+	c.range(
+		&["latency", "max_compensation_ms"],
+		config.latency.max_compensation_ms,
+		0..=100,
+	);
+	let interpolation = &config.interpolation;
+	c.range(
+		&["interpolation", "smoothing"],
+		interpolation.smoothing,
+		0.0..=2.0,
+	);
+	c.range(
+		&["interpolation", "max_extrapolation_ms"],
+		interpolation.max_extrapolation_ms,
+		0..=200,
+	);
+	let smoothing = &config.smoothing;
+	c.range(
+		&["smoothing", "time_constant_ms"],
+		smoothing.time_constant_ms,
+		0.0..=1000.0,
+	);
+	for (name, ms) in &smoothing.bones {
+		c.bone(&["smoothing", "bones", name], "smoothing.bones", name);
+		c.range(&["smoothing", "bones", name], *ms, 0.0..=1000.0);
+	}
+	let deadband = &config.deadband;
+	c.range(
+		&["deadband", "position_mm"],
+		deadband.position_mm,
+		0.0..=10.0,
+	);
+	c.range(
+		&["deadband", "rotation_degrees"],
+		deadband.rotation_degrees,
+		0.0..=5.0,
+	);
+	for (name, threshold) in &deadband.bones {
+		c.bone(&["deadband", "bones", name], "deadband.bones", name);
+		if let Some(mm) = threshold.position_mm {
+			c.range(&["deadband", "bones", name, "position_mm"], mm, 0.0..=10.0);
+		}
+		if let Some(degrees) = threshold.rotation_degrees {
+			let path = ["deadband", "bones", name, "rotation_degrees"];
+			c.range(&path, degrees, 0.0..=5.0);
+		}
+	}
+	c.position(&["neck", "pivot_offset"], config.neck.pivot_offset);
+	c.range(&["neck", "neck_length"], config.neck.neck_length, 0.0..=0.5);
+	let alerts = &config.alerts;
+	c.range(&["alerts", "volume"], alerts.volume, 0.0..=4.0);
+	c.range(
+		&["alerts", "battery_low_percent"],
+		alerts.battery_low_percent,
+		0..=100,
+	);
+	c.range(
+		&["alerts", "reset_reminder_minutes"],
+		alerts.reset_reminder_minutes,
+		1..=24 * 60,
+	);
+	c.range(
+		&["alerts", "bone_rate_low_hz"],
+		alerts.bone_rate_low_hz,
+		0.1..=100.0,
+	);
+	let presence = &config.presence;
+	c.range(
+		&["presence", "min_interval_s"],
+		presence.min_interval_s,
+		5..=3600,
+	);
+	if presence.discord && presence.discord_client_id.is_empty() {
+		c.problems.push(Problem {
+			line: c.locate(&["presence", "discord"]),
+			message: "`presence.discord` needs a `discord_client_id`".to_owned(),
+			hint: Some(
+				"Use the application id of an application from \
+				 https://discord.com/developers/applications"
+					.to_owned(),
+			),
+		});
+	}
+	let osc = &config.osc;
+	if osc.address.parse::<SocketAddr>().is_err() {
+		c.problems.push(Problem {
+			line: c.locate(&["osc", "address"]),
+			message: format!(
+				"`osc.address` is {:?}, which isn't an IP address and port",
+				osc.address
+			),
+			hint: Some("Use an address like `127.0.0.1:9000`".to_owned()),
+		});
+	}
+	if !osc.prefix.starts_with('/') || osc.prefix.ends_with('/') {
+		c.problems.push(Problem {
+			line: c.locate(&["osc", "prefix"]),
+			message: format!(
+				"`osc.prefix` is {:?}, which isn't an OSC address",
+				osc.prefix
+			),
+			hint: Some("Use a prefix like `/slimevr`".to_owned()),
+		});
+	}
+	for (name, trail) in &config.trails {
+		c.bone(&["trails", name], "trails", name);
+		c.range(&["trails", name, "seconds"], trail.seconds, 0.01..=60.0);
+		c.range(&["trails", name, "segments"], trail.segments, 1..=64);
+	}
+	c.range(&["hud", "scale"], config.hud.scale, 0.01..=1.0);
+	c.position(&["hud", "offset"], config.hud.offset);
+	c.range(
+		&["tracker_markers", "size"],
+		config.tracker_markers.size,
+		0.01..=0.5,
+	);
+	c.range(&["log_console", "lines"], config.log_console.lines, 1..=40);
+	c.style(&["skeleton"], &config.skeleton);
+	for (name, theme) in &config.themes {
+		c.style(&["themes", name], theme);
+	}
+	for (name, user) in &config.users {
+		if let Some(theme) = &user.theme {
+			if !config.themes.contains_key(theme) {
+				let themes = config.themes.keys().map(String::as_str);
+				c.problems.push(Problem {
+					line: c.locate(&["users", name, "theme"]),
+					message: format!(
+						"`users.{name}.theme` is {theme:?}, which isn't \
+						 one of the `[themes]`"
+					),
+					hint: suggest(theme, themes),
+				});
+			}
+		}
+		if let Some(server) = &user.server {
+			if !server.starts_with("ws://") && !server.starts_with("wss://") {
+				c.problems.push(Problem {
+					line: c.locate(&["users", name, "server"]),
+					message: format!(
+						"`users.{name}.server` is {server:?}, which \
+						 isn't a SolarXR address"
+					),
+					hint: Some("Use an address like `ws://localhost:21110`".to_owned()),
+				});
+			}
+		}
+		if let Some(height) = user.headset_height {
+			c.range(&["users", name, "headset_height"], height, 0.5..=2.5);
+		}
+	}
+	let camera = &config.replay_camera;
+	c.range(
+		&["replay_camera", "fov_degrees"],
+		camera.fov_degrees,
+		1.0..=170.0,
+	);
+	c.range(&["replay_camera", "width"], camera.width, 16..=7680);
+	c.range(&["replay_camera", "height"], camera.height, 16..=4320);
+	c.range(&["replay_camera", "fps"], camera.fps, 1.0..=240.0);
+	c.range(
+		&["replay_camera", "orbit_seconds"],
+		camera.orbit_seconds,
+		0.0..=3600.0,
+	);
+	c.position(&["replay_camera", "position"], camera.position);
+	c.position(&["replay_camera", "target"], camera.target);
+	c.problems
+}
+
+struct Checker<'a> {
+	contents: &'a str,
+	problems: Vec<Problem>,
+}
+impl Checker<'_> {
+	fn range<T: PartialOrd + fmt::Display>(
+		&mut self,
+		path: &[&str],
+		value: T,
+		range: RangeInclusive<T>,
+	) {
+		// Written so that NaN is out of range too.
+		if range.contains(&value) {
+			return;
+		}
+		self.problems.push(Problem {
+			line: self.locate(path),
+			message: format!("`{}` is {value}, which is out of range", path.join(".")),
+			hint: Some(format!(
+				"Use a value from {} to {}",
+				range.start(),
+				range.end()
+			)),
+		});
+	}
+
+	/// Reports each axis of `value` at `path` that isn't a number.
+	fn position(&mut self, path: &[&str], value: [f32; 3]) {
+		for (axis, value) in ["x", "y", "z"].into_iter().zip(value) {
+			if !value.is_finite() {
+				self.problems.push(Problem {
+					line: self.locate(path),
+					message: format!("The {axis} of `{}` is {value}", path.join(".")),
+					hint: Some("Use a distance in meters".to_owned()),
+				});
+			}
+		}
+	}
+
+	/// Checks a skeleton style in the table at `path`.
+	fn style(&mut self, path: &[&str], style: &SkeletonStyle) {
+		let place = |key: &str| format!("{}.{key}", path.join("."));
+		self.range(
+			&[path, &["radius"][..]].concat(),
+			style.radius,
+			0.0005..=0.05,
+		);
+		for name in style.colors.keys() {
+			let at = [path, &["colors", name.as_str()][..]].concat();
+			self.bone(&at, &place("colors"), name);
+		}
+		let at = [path, &["hidden"][..]].concat();
+		for name in &style.hidden {
+			self.bone(&at, &place("hidden"), name);
+		}
+	}
+
+	/// Reports `name` at `path` if it isn't the name of a bone. `place` is where it
+	/// is, for the message.
+	fn bone(&mut self, path: &[&str], place: &str, name: &str) {
+		if name.parse::<BoneKind>().is_ok() {
+			return;
+		}
+		let names: Vec<String> =
+			BoneKind::iter().map(|kind| format!("{kind:?}")).collect();
+		self.problems.push(Problem {
+			line: self.locate(path),
+			message: format!("`{name}` in `{place}` is not a bone"),
+			hint: Some(
+				suggest(name, names.iter().map(String::as_str))
+					.unwrap_or_else(|| format!("The bones are {}", names.join(", "))),
+			),
+		});
+	}
+
+	/// The line that `path` is set on, either as a `key = value` in the table of the
+	/// rest of the path, or as a `[table]` header. Doesn't understand dotted keys or
+	/// inline tables, which are rare in hand written files.
+	fn locate(&self, path: &[&str]) -> Option<usize> {
+		let (key, table) = path.split_last()?;
+		let mut current: Vec<String> = Vec::new();
+		for (i, line) in self.contents.lines().enumerate() {
+			let line = line.trim();
+			if let Some(header) = line.strip_prefix('[') {
+				let header = header.split(']').next().unwrap_or(header);
+				current = header.split('.').map(unquote).collect();
+				if current == path {
+					return Some(i + 1);
+				}
+			} else if let Some((k, _value)) = line.split_once('=') {
+				if current == table && unquote(k) == *key {
+					return Some(i + 1);
+				}
+			}
+		}
+		None
+	}
+}
+
+fn unquote(key: &str) -> String {
+	key.trim()
+		.trim_matches(|c| c == '"' || c == '\'')
+		.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::RGBA;
+
+	fn problems(contents: &str) -> Vec<Problem> {
+		Config::parse(contents).unwrap_err().0
+	}
+
+	#[test]
+	fn default_is_valid() {
+		assert!(check(&Config::default(), "").is_empty());
+		assert!(Config::parse("").is_ok());
+	}
+
+	#[test]
+	fn edit_distances() {
+		assert_eq!(edit_distance("volume", "volume"), 0);
+		assert_eq!(edit_distance("volum", "volume"), 1);
+		assert_eq!(edit_distance("vlume", "volume"), 1);
+		assert_eq!(edit_distance("", "hud"), 3);
+		assert_eq!(closest("footl", ["FootL", "FootR"]), Some("FootL"));
+		assert_eq!(closest("banana", ["FootL", "FootR"]), None);
+	}
+
+	#[test]
+	fn unknown_key() {
+		let problems = problems("[alerts]\nvolum = 0.5\n");
+		assert_eq!(problems.len(), 1);
+		assert!(problems[0].message.contains("volum"));
+		assert!(problems[0].line.is_some());
+		assert_eq!(problems[0].hint.as_deref(), Some("Did you mean `volume`?"));
+	}
+
+	#[test]
+	fn out_of_range() {
+		let contents = "\
+[alerts]
+volume = 0.5
+battery_low_percent = 150
+
+[trails.FootR]
+segments = 0
+";
+		assert_eq!(
+			problems(contents),
+			[
+				Problem {
+					line: Some(3),
+					message:
+						"`alerts.battery_low_percent` is 150, which is out of range"
+							.to_owned(),
+					hint: Some("Use a value from 0 to 100".to_owned()),
+				},
+				Problem {
+					line: Some(6),
+					message: "`trails.FootR.segments` is 0, which is out of range"
+						.to_owned(),
+					hint: Some("Use a value from 1 to 64".to_owned()),
+				},
+			]
+		);
+	}
+
+	#[test]
+	fn unknown_bone() {
+		let problems = problems("[trails.Footl]\n");
+		assert_eq!(problems.len(), 1);
+		assert_eq!(problems[0].line, Some(1));
+		assert_eq!(problems[0].hint.as_deref(), Some("Did you mean `FootL`?"));
+	}
+
+	#[test]
+	fn skeleton_style() {
+		let contents = "\
+[skeleton]
+hidden = [\"Head\", \"Nek\"]
+
+[skeleton.colors]
+FootL = \"#ff8000\"
+";
+		let config = Config::parse(&contents.replace("Nek", "Neck")).unwrap();
+		assert_eq!(
+			config.skeleton.color(BoneKind::FootL),
+			Some(RGBA::new(255, 128, 0, 255))
+		);
+		assert_eq!(
+			config.skeleton.hidden().collect::<Vec<_>>(),
+			[BoneKind::Head, BoneKind::Neck]
+		);
+
+		let problems = problems(contents);
+		assert_eq!(problems.len(), 1);
+		assert_eq!(problems[0].line, Some(2));
+		assert_eq!(problems[0].hint.as_deref(), Some("Did you mean `Neck`?"));
+	}
+
+	#[test]
+	fn invalid_color() {
+		let problems = problems("[skeleton.colors]\nFootL = \"orange\"\n");
+		assert_eq!(problems.len(), 1);
+		assert_eq!(problems[0].line, Some(2));
+		assert!(problems[0].message.contains("is not a color"));
+	}
+
+	#[test]
+	fn themes() {
+		let contents = "\
+[themes.stream]
+radius = 0.01
+hidden = [\"Head\"]
+
+[themes.stream.colors]
+Footl = \"#ff8000\"
+";
+		let problems = problems(contents);
+		assert_eq!(problems.len(), 1);
+		assert_eq!(problems[0].line, Some(6));
+		assert_eq!(
+			problems[0].message,
+			"`Footl` in `themes.stream.colors` is not a bone"
+		);
+	}
+
+	#[test]
+	fn users() {
+		let contents = "\
+[themes.stream]
+radius = 0.01
+
+[users.alex]
+theme = \"strem\"
+headset_height = 1.62
+
+[users.sam]
+server = \"192.168.1.20:21110\"
+headset_height = 18.5
+";
+		let mut problems = problems(contents);
+		problems.sort_by_key(|p| p.line);
+		assert_eq!(problems.len(), 3);
+		assert_eq!(problems[0].line, Some(5));
+		assert_eq!(problems[0].hint.as_deref(), Some("Did you mean `stream`?"));
+		assert_eq!(problems[1].line, Some(9));
+		assert_eq!(problems[2].line, Some(10));
+
+		let fixed = contents
+			.replace("strem", "stream")
+			.replace("\"192", "\"ws://192")
+			.replace("18.5", "1.85");
+		let config = Config::parse(&fixed).unwrap();
+		assert_eq!(config.users["sam"].headset_height, Some(1.85));
+	}
+
+	#[test]
+	fn deadband() {
+		let contents = "\
+[deadband.bones.FootL]
+position_mm = 2
+
+[deadband.bones.HandL]
+rotation_degrees = 90
+";
+		let config = Config::parse(&contents.replace("90", "0")).unwrap();
+		assert_eq!(config.deadband.threshold(BoneKind::FootL), (2., 0.2));
+		assert_eq!(config.deadband.threshold(BoneKind::HandL), (0.5, 0.));
+
+		let problems = problems(contents);
+		assert_eq!(problems.len(), 1);
+		assert_eq!(problems[0].line, Some(5));
+		assert_eq!(problems[0].hint.as_deref(), Some("Use a value from 0 to 5"));
+	}
+
+	#[test]
+	fn replay_camera() {
+		let contents = "[replay_camera]\nfps = 0\ntarget = [0, nan, 0]\n";
+		let problems = problems(contents);
+		assert_eq!(problems.len(), 2);
+		assert_eq!(problems[0].line, Some(2));
+		assert_eq!(
+			problems[1].message,
+			"The y of `replay_camera.target` is NaN"
+		);
+		assert_eq!(problems[1].line, Some(3));
+	}
+
+	#[test]
+	fn discord_client_id() {
+		let problems = problems("[presence]\ndiscord = true\n");
+		assert_eq!(problems.len(), 1);
+		assert_eq!(problems[0].line, Some(2));
+		let contents = "[presence]\ndiscord = true\ndiscord_client_id = \"123\"\n";
+		assert!(Config::parse(contents).is_ok());
+	}
+
+	#[test]
+	fn osc() {
+		let address = problems("[osc]\nenabled = true\naddress = \"localhost\"\n");
+		assert_eq!(address.len(), 1);
+		assert_eq!(address[0].line, Some(3));
+		let prefix = problems("[osc]\nprefix = \"slimevr/\"\n");
+		assert!(prefix[0].message.starts_with("`osc.prefix`"));
+	}
+}
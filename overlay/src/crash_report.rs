@@ -0,0 +1,49 @@
+//! Writes a report to [`DIR`] whenever the overlay panics, so that there is
+//! something to attach to an issue even when the console was hidden or the log
+//! rolled over since. `--collect-debug-bundle` picks up the most recent ones.
+
+use std::backtrace::Backtrace;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const DIR: &str = "crash_reports";
+
+/// Writes reports of panics, and then hands them on to the hook that was
+/// installed before, like the one of `color_eyre`.
+pub fn install(version: &'static str) {
+	let previous = std::panic::take_hook();
+	std::panic::set_hook(Box::new(move |info| {
+		let report = format!(
+			"Overlay version: {version}\n{info}\n\n{}\n",
+			Backtrace::force_capture()
+		);
+		if let Err(e) = write(&report) {
+			eprintln!("Failed to write crash report: {e}");
+		}
+		previous(info);
+	}));
+}
+
+fn write(report: &str) -> std::io::Result<()> {
+	let since_epoch = SystemTime::now()
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.unwrap_or_default();
+	let dir = Path::new(DIR);
+	fs::create_dir_all(dir)?;
+	fs::write(
+		dir.join(format!("crash_{}.txt", since_epoch.as_secs())),
+		report,
+	)
+}
+
+/// The `count` most recent reports, most recent first.
+pub fn recent(count: usize) -> Vec<PathBuf> {
+	let Ok(entries) = fs::read_dir(DIR) else { return Vec::new() };
+	let mut reports: Vec<(SystemTime, PathBuf)> = entries
+		.flatten()
+		.filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.path())))
+		.collect();
+	reports.sort_by(|a, b| b.0.cmp(&a.0));
+	reports.into_iter().take(count).map(|(_, p)| p).collect()
+}
@@ -0,0 +1,189 @@
+//! Exports the skeleton stream to CSV files, so that the tracking data can be
+//! analyzed with other tools without writing a custom SolarXR client.
+//!
+//! Every bone in every feed update becomes one row:
+//! ```csv
+//! timestamp,bone,x,y,z,qx,qy,qz,qw
+//! 1681234567.890,Chest,0.01,1.32,0.05,0,0,0,1
+//! ```
+//! `timestamp` is in seconds since the unix epoch, and never goes backwards, even
+//! when the system clock is adjusted while exporting. Once a file gets larger than
+//! the size limit, a new one is started with the next number, like
+//! `export_0001.csv`.
+//!
+//! For sessions of hours, `--record` takes much less space, see
+//! [`crate::recording`].
+//!
+//! The HTTP API can stop and start the export, see [`crate::http_api`]. Each start
+//! carries on with the next number, so that earlier recordings are kept.
+
+use crate::component::{Component, Event, Frame};
+use crate::model::{BoneKind, Isometry};
+use crate::replay;
+
+use eyre::{Result, WrapErr};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const HEADER: &str = "timestamp,bone,x,y,z,qx,qy,qz,qw\n";
+
+/// Writes the frames on the bus to CSV files at `path`.
+pub struct CsvExport {
+	path: PathBuf,
+	max_bytes: u64,
+	/// Whether to write from the start, or wait for [`Event::Recording`].
+	recording: bool,
+	/// The number of the next file to create.
+	next_file: u32,
+	writer: Option<RotatingWriter>,
+}
+impl CsvExport {
+	/// Starts a new file whenever the current one exceeds `max_bytes`.
+	pub fn new(path: PathBuf, max_bytes: u64, recording: bool) -> Self {
+		Self {
+			path,
+			max_bytes,
+			recording,
+			next_file: 0,
+			writer: None,
+		}
+	}
+
+	fn start(&mut self) -> Result<()> {
+		log::info!("Exporting skeleton to {:?}", self.path);
+		let writer =
+			RotatingWriter::new(self.path.clone(), self.max_bytes, self.next_file)?;
+		self.writer = Some(writer);
+		Ok(())
+	}
+
+	fn stop(&mut self) -> Result<()> {
+		let Some(mut writer) = self.writer.take() else {
+			return Ok(());
+		};
+		log::info!("Stopped exporting skeleton");
+		self.next_file = writer.file_number + 1;
+		writer.writer.flush().wrap_err("Failed to flush CSV file")
+	}
+}
+impl Component for CsvExport {
+	fn name(&self) -> &'static str {
+		"CsvExport"
+	}
+
+	fn init(&mut self) -> Result<()> {
+		if self.recording {
+			self.start()?;
+		}
+		Ok(())
+	}
+
+	fn update(&mut self, event: &Event) -> Result<()> {
+		match (event, self.writer.as_mut()) {
+			(Event::Frame(frame), Some(writer)) => writer.write_frame(frame),
+			(Event::Recording(true), None) => self.start(),
+			(Event::Recording(false), Some(_)) => self.stop(),
+			_ => Ok(()),
+		}
+	}
+
+	fn shutdown(&mut self) -> Result<()> {
+		self.stop()
+	}
+}
+
+/// Writes frames to numbered files based on `path`, starting a new file whenever
+/// the current one exceeds `max_bytes`.
+struct RotatingWriter {
+	path: PathBuf,
+	max_bytes: u64,
+	file_number: u32,
+	written: u64,
+	writer: BufWriter<File>,
+}
+impl RotatingWriter {
+	/// Starts with the file numbered `file_number`.
+	fn new(path: PathBuf, max_bytes: u64, file_number: u32) -> Result<Self> {
+		let writer = create_file(&numbered_path(&path, file_number))?;
+		Ok(Self {
+			path,
+			max_bytes,
+			file_number,
+			written: HEADER.len() as u64,
+			writer,
+		})
+	}
+
+	fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+		let timestamp = frame
+			.timestamp
+			.duration_since(SystemTime::UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_secs_f64();
+		for (kind, iso) in frame.bones.iter() {
+			let row = row(timestamp, *kind, iso);
+			self.writer
+				.write_all(row.as_bytes())
+				.wrap_err("Failed to write CSV row")?;
+			self.written += row.len() as u64;
+		}
+		if self.written >= self.max_bytes {
+			self.rotate()?;
+		}
+		Ok(())
+	}
+
+	fn rotate(&mut self) -> Result<()> {
+		self.writer.flush().wrap_err("Failed to flush CSV file")?;
+		self.file_number += 1;
+		let path = numbered_path(&self.path, self.file_number);
+		log::info!("Starting new CSV export file {path:?}");
+		self.writer = create_file(&path)?;
+		self.written = HEADER.len() as u64;
+		Ok(())
+	}
+}
+
+/// Writes `frames`, like the ones of a recording, to a single file at `path`.
+pub fn write_frames(path: &Path, frames: &[replay::Frame]) -> Result<()> {
+	let mut writer = create_file(path)?;
+	for frame in frames {
+		for (kind, iso) in &frame.bones {
+			writer
+				.write_all(row(frame.timestamp, *kind, iso).as_bytes())
+				.wrap_err("Failed to write CSV row")?;
+		}
+	}
+	writer.flush().wrap_err("Failed to flush CSV file")
+}
+
+fn row(timestamp: f64, kind: BoneKind, iso: &Isometry) -> String {
+	let t = iso.translation.vector;
+	let r = iso.rotation;
+	format!(
+		"{timestamp:.3},{kind:?},{},{},{},{},{},{},{}\n",
+		t.x, t.y, t.z, r.i, r.j, r.k, r.w
+	)
+}
+
+fn create_file(path: &Path) -> Result<BufWriter<File>> {
+	let file = File::create(path)
+		.wrap_err_with(|| format!("Failed to create CSV file {path:?}"))?;
+	let mut writer = BufWriter::new(file);
+	writer
+		.write_all(HEADER.as_bytes())
+		.wrap_err("Failed to write CSV header")?;
+	Ok(writer)
+}
+
+/// Turns `foo/export.csv` into `foo/export_0001.csv`.
+fn numbered_path(path: &Path, number: u32) -> PathBuf {
+	let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+	let name = match path.extension() {
+		Some(ext) => format!("{stem}_{number:04}.{}", ext.to_string_lossy()),
+		None => format!("{stem}_{number:04}"),
+	};
+	path.with_file_name(name)
+}
@@ -0,0 +1,185 @@
+//! A page with the tracking status at a glance, for a second screen next to the
+//! game, at `GET /dashboard` of the HTTP API, see [`crate::http_api`]. It shows
+//! how many trackers are connected, how often feed updates arrive, whether the
+//! headset is in standby and the skeleton recorded, and where each bone is.
+//!
+//! The page reloads itself every second, so it needs no scripts, and the browser
+//! doesn't send an `Origin` that the API would refuse.
+
+use crate::component::{Component, Event, Frame};
+
+use eyre::Result;
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+
+/// How far back the rate of feed updates is measured.
+const RATE_WINDOW: Duration = Duration::from_secs(2);
+const REFRESH_S: u32 = 1;
+
+/// What the page shows.
+#[derive(Debug, Clone)]
+pub struct State {
+	trackers: usize,
+	standby: bool,
+	recording: bool,
+	/// Feed updates per second.
+	rate_hz: f32,
+	last_frame: Option<Arc<Frame>>,
+}
+impl State {
+	/// The page, as of `now`.
+	pub fn render(&self, now: SystemTime) -> String {
+		let mut html = String::new();
+		let _ = write!(
+			html,
+			"<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+			 <meta http-equiv=\"refresh\" content=\"{REFRESH_S}\">\
+			 <title>SlimeVR overlay</title><style>\
+			 body {{ font-family: sans-serif; background: #18181c; color: #eee }}\
+			 td, th {{ padding: 0 1em 0 0; text-align: right }}\
+			 </style></head><body>\n"
+		);
+		let tracking = self.trackers > 0 && !self.standby;
+		let heading = if tracking { "Tracking" } else { "Not tracking" };
+		let _ = writeln!(html, "<h1>{heading}</h1>");
+		let _ = write!(
+			html,
+			"<p>{} trackers connected, {:.0} feed updates per second",
+			self.trackers, self.rate_hz
+		);
+		if let Some(frame) = &self.last_frame {
+			let age = now.duration_since(frame.timestamp).unwrap_or_default();
+			let _ = write!(html, ", the last {:.1} s ago", age.as_secs_f32());
+		}
+		let _ = writeln!(html, "</p>");
+		if self.standby {
+			let _ = writeln!(html, "<p>The headset is in standby</p>");
+		}
+		if self.recording {
+			let _ = writeln!(html, "<p>Recording the skeleton</p>");
+		}
+		let bones = self.last_frame.as_ref().map_or(&[][..], |f| &f.bones[..]);
+		if !bones.is_empty() {
+			let _ = writeln!(
+				html,
+				"<table><tr><th>Bone</th><th>x</th><th>y</th><th>z</th></tr>"
+			);
+			for (kind, iso) in bones {
+				let t = iso.translation.vector;
+				let _ = writeln!(
+					html,
+					"<tr><td>{kind:?}</td><td>{:.3}</td><td>{:.3}</td>\
+					 <td>{:.3}</td></tr>",
+					t.x, t.y, t.z
+				);
+			}
+			let _ = writeln!(html, "</table>");
+		}
+		html.push_str("</body></html>\n");
+		html
+	}
+}
+
+/// Keeps the state of the page for the HTTP API.
+pub struct Dashboard {
+	state: State,
+	/// When the feed updates of the rate window arrived.
+	recent: VecDeque<SystemTime>,
+	sender: watch::Sender<State>,
+}
+impl Dashboard {
+	/// `recording` is whether the skeleton is recorded from the start.
+	pub fn new(recording: bool) -> (Self, watch::Receiver<State>) {
+		let state = State {
+			trackers: 0,
+			standby: false,
+			recording,
+			rate_hz: 0.,
+			last_frame: None,
+		};
+		let (sender, receiver) = watch::channel(state.clone());
+		let dashboard = Self {
+			state,
+			recent: VecDeque::new(),
+			sender,
+		};
+		(dashboard, receiver)
+	}
+
+	/// Feed updates per second, over the window up to the last one.
+	fn rate(&mut self, timestamp: SystemTime) -> f32 {
+		self.recent.push_back(timestamp);
+		while let Some(&first) = self.recent.front() {
+			match timestamp.duration_since(first) {
+				Ok(age) if age > RATE_WINDOW => self.recent.pop_front(),
+				_ => break,
+			};
+		}
+		let first = self.recent.front().copied().unwrap_or(timestamp);
+		let span = timestamp.duration_since(first).unwrap_or_default();
+		if span.is_zero() {
+			return 0.;
+		}
+		(self.recent.len() - 1) as f32 / span.as_secs_f32()
+	}
+}
+
+impl Component for Dashboard {
+	fn name(&self) -> &'static str {
+		"Dashboard"
+	}
+
+	fn update(&mut self, event: &Event) -> Result<()> {
+		match event {
+			Event::Frame(frame) => {
+				self.state.rate_hz = self.rate(frame.timestamp);
+				self.state.last_frame = Some(frame.clone());
+			}
+			Event::Standby(standby) => self.state.standby = *standby,
+			Event::Recording(recording) => self.state.recording = *recording,
+			Event::Trackers(trackers) => self.state.trackers = *trackers,
+		}
+		self.sender.send_replace(self.state.clone());
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::model::{BoneKind, Isometry};
+
+	fn frame(ms: u64) -> Event {
+		let frame = Frame {
+			timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(ms),
+			bones: vec![(BoneKind::Hip, Isometry::translation(0., 0.9, 0.))],
+		};
+		Event::Frame(Arc::new(frame))
+	}
+
+	#[test]
+	fn shows_the_bus() {
+		let (mut dashboard, state) = Dashboard::new(false);
+		// At 50 Hz, for longer than the window.
+		for i in 0..200 {
+			dashboard.update(&frame(i * 20)).unwrap();
+		}
+		dashboard.update(&Event::Trackers(6)).unwrap();
+		let state = state.borrow().clone();
+		assert!((state.rate_hz - 50.).abs() < 0.1, "{}", state.rate_hz);
+
+		let now = SystemTime::UNIX_EPOCH + Duration::from_millis(4480);
+		let html = state.render(now);
+		assert!(html.contains("<h1>Tracking</h1>"));
+		assert!(html.contains("6 trackers connected, 50 feed updates per second"));
+		assert!(html.contains("the last 0.5 s ago"));
+		assert!(html.contains("<td>Hip</td><td>0.000</td><td>0.900</td>"));
+
+		dashboard.update(&Event::Standby(true)).unwrap();
+		let html = dashboard.state.render(now);
+		assert!(html.contains("<h1>Not tracking</h1>"));
+	}
+}
@@ -0,0 +1,113 @@
+//! Holds bones still while they only jitter. Standing still, the feed still moves
+//! each bone by fractions of a millimeter every update, which shimmers and keeps
+//! SteamVR busy moving overlays. A bone stays where it was drawn until it moved or
+//! turned further than its deadband from there, and then jumps to where it is.
+//! Bones that don't move aren't sent to SteamVR again, see [`crate::model::Bone`].
+//!
+//! Deadbands are small enough that the jump can't be seen. Each bone can have its
+//! own, since the feet jitter the most, and the hands need to stay precise.
+
+use crate::config;
+use crate::model::{BoneKind, BoneMap, Isometry};
+
+#[derive(Debug)]
+pub struct Deadband {
+	/// How far each bone has to move in meters, and turn in radians, before it is
+	/// drawn somewhere else. `(0, 0)` for bones without a deadband.
+	thresholds: BoneMap<(f32, f32)>,
+	/// Where each bone was drawn last.
+	held: BoneMap<Option<Isometry>>,
+}
+impl Deadband {
+	pub fn new(config: &config::Deadband) -> Self {
+		let mut thresholds = BoneMap::default();
+		if config.enabled {
+			for kind in BoneKind::iter() {
+				let (mm, degrees) = config.threshold(kind);
+				thresholds[kind] = (mm / 1000., degrees.to_radians());
+			}
+		}
+		Self {
+			thresholds,
+			held: BoneMap::default(),
+		}
+	}
+
+	/// Where to draw `bone` if the feed puts it at `iso`.
+	pub fn update(&mut self, bone: BoneKind, iso: Isometry) -> Isometry {
+		let (distance, angle) = self.thresholds[bone];
+		if distance <= 0. && angle <= 0. {
+			return iso;
+		}
+		let held = &mut self.held[bone];
+		if let Some(last) = *held {
+			let moved = (last.translation.vector - iso.translation.vector).norm();
+			if moved <= distance && last.rotation.angle_to(&iso.rotation) <= angle {
+				return last;
+			}
+		}
+		*held = Some(iso);
+		iso
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::{Translation3, UnitQuaternion, Vector3};
+	use std::collections::HashMap;
+
+	fn at(x: f32, angle: f32) -> Isometry {
+		Isometry::from_parts(
+			Translation3::new(x, 1., 0.),
+			UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle),
+		)
+	}
+
+	fn deadband() -> Deadband {
+		Deadband::new(&config::Deadband {
+			enabled: true,
+			position_mm: 1.,
+			rotation_degrees: 0.5,
+			bones: HashMap::from([(
+				"FootL".to_owned(),
+				config::Threshold {
+					position_mm: Some(0.),
+					rotation_degrees: Some(0.),
+				},
+			)]),
+		})
+	}
+
+	#[test]
+	fn jitter_is_held() {
+		let mut deadband = deadband();
+		let start = at(0., 0.);
+		assert_eq!(deadband.update(BoneKind::Chest, start), start);
+		let jitter = at(0.0005, 0.2f32.to_radians());
+		assert_eq!(deadband.update(BoneKind::Chest, jitter), start);
+		// Still measured from where it is held, not from the last update.
+		let drifted = at(0.0015, 0.);
+		assert_eq!(deadband.update(BoneKind::Chest, drifted), drifted);
+	}
+
+	#[test]
+	fn turning_releases() {
+		let mut deadband = deadband();
+		deadband.update(BoneKind::Chest, at(0., 0.));
+		let turned = at(0., 1f32.to_radians());
+		assert_eq!(deadband.update(BoneKind::Chest, turned), turned);
+	}
+
+	#[test]
+	fn bones_without_a_deadband_follow_the_feed() {
+		let mut deadband = deadband();
+		deadband.update(BoneKind::FootL, at(0., 0.));
+		let jitter = at(0.0001, 0.);
+		assert_eq!(deadband.update(BoneKind::FootL, jitter), jitter);
+
+		let mut disabled = Deadband::new(&config::Deadband::default());
+		disabled.update(BoneKind::Chest, at(0., 0.));
+		assert_eq!(disabled.update(BoneKind::Chest, jitter), jitter);
+	}
+}
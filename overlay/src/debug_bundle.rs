@@ -0,0 +1,284 @@
+//! Gathers what it takes to look into a bug into one zip, for attaching to an
+//! issue: the logs, the config, the most recent crash reports, a summary of the
+//! sessions in the logs, and what the overlay runs on, like the versions of the OS
+//! and SteamVR and the graphics cards.
+//!
+//! Issues are public, so IPv4 and MAC addresses, Wi-Fi credentials and the home
+//! directory are redacted from everything that goes in. The last part of each
+//! address is kept, so that the trackers can still be told apart.
+
+use crate::config::Config;
+use crate::{crash_report, platform, GIT_VERSION, LOG_FILE, LOG_ROLLED, LOG_WINDOW};
+
+use eyre::{Result, WrapErr};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::Write as _;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use zip::ZipWriter;
+
+pub const DEFAULT_PATH: &str = "debug_bundle.zip";
+/// How many crash reports to include, the most recent first.
+const CRASH_REPORTS: usize = 5;
+/// Lines that mention these are about Wi-Fi credentials, compared in lower case.
+const SECRETS: [&str; 4] = ["ssid", "password", "passphrase", "psk"];
+
+/// Writes the bundle to `out`, with the config at `config` and the bone lengths at
+/// `bone_lengths`. Returns how many files went in.
+pub fn collect(out: &Path, config: &Path, bone_lengths: &Path) -> Result<usize> {
+	let file =
+		File::create(out).wrap_err_with(|| format!("Failed to create {out:?}"))?;
+	let mut bundle = Bundle {
+		zip: ZipWriter::new(file),
+		home: home_dir(),
+		files: 0,
+	};
+
+	let logs = log_files();
+	let mut log_contents = String::new();
+	for path in &logs {
+		if let Some(contents) = read(path) {
+			bundle.add(&format!("logs/{}", file_name(path)), &contents)?;
+			log_contents += &contents;
+		}
+	}
+	let config_error = match read(config) {
+		Some(contents) => {
+			bundle.add("overlay.toml", &contents)?;
+			Config::parse(&contents).err().map(|e| e.to_string())
+		}
+		None => Some("Not found, so the defaults are used".to_owned()),
+	};
+	if let Some(contents) = read(bone_lengths) {
+		bundle.add(&file_name(bone_lengths), &contents)?;
+	}
+	for path in crash_report::recent(CRASH_REPORTS) {
+		if let Some(contents) = read(&path) {
+			let name = format!("{}/{}", crash_report::DIR, file_name(&path));
+			bundle.add(&name, &contents)?;
+		}
+	}
+
+	let mut environment = environment();
+	match config_error {
+		Some(e) => writeln!(environment, "Config: {config:?}: {e}"),
+		None => writeln!(environment, "Config: {config:?}"),
+	}
+	.unwrap();
+	environment += &summarize(&log_contents);
+	bundle.add("environment.txt", &environment)?;
+
+	bundle
+		.zip
+		.finish()
+		.wrap_err_with(|| format!("Failed to write {out:?}"))?;
+	Ok(bundle.files)
+}
+
+struct Bundle {
+	zip: ZipWriter<File>,
+	home: Option<String>,
+	files: usize,
+}
+impl Bundle {
+	/// Adds a file called `name` to the zip, with `contents` redacted.
+	fn add(&mut self, name: &str, contents: &str) -> Result<()> {
+		let contents = redact(contents, self.home.as_deref());
+		self.zip
+			.start_file(name, FileOptions::default())
+			.and_then(|()| {
+				self.zip
+					.write_all(contents.as_bytes())
+					.map_err(ZipError::from)
+			})
+			.wrap_err_with(|| format!("Failed to add {name} to the bundle"))?;
+		self.files += 1;
+		Ok(())
+	}
+}
+
+/// The log of this run, and the ones it rolled over from, oldest first.
+fn log_files() -> Vec<PathBuf> {
+	let rolled = (0..LOG_WINDOW)
+		.rev()
+		.map(|i| PathBuf::from(LOG_ROLLED.replace("{}", &i.to_string())));
+	rolled.chain([PathBuf::from(LOG_FILE)]).collect()
+}
+
+fn read(path: &Path) -> Option<String> {
+	let contents = std::fs::read(path).ok()?;
+	Some(String::from_utf8_lossy(&contents).into_owned())
+}
+
+fn file_name(path: &Path) -> String {
+	path.file_name()
+		.map_or_else(|| "unnamed".into(), |n| n.to_string_lossy().into_owned())
+}
+
+fn home_dir() -> Option<String> {
+	let var = if cfg!(windows) { "USERPROFILE" } else { "HOME" };
+	std::env::var(var).ok().filter(|home| home.len() > 1)
+}
+
+/// What the overlay runs on.
+fn environment() -> String {
+	let mut out = String::new();
+	let args: Vec<String> = std::env::args().collect();
+	writeln!(out, "Overlay version: {GIT_VERSION}").unwrap();
+	writeln!(out, "Command line: {}", args.join(" ")).unwrap();
+	writeln!(out, "OS: {}", platform::os_version()).unwrap();
+	let gpus = platform::gpus();
+	if gpus.is_empty() {
+		writeln!(out, "GPU: unknown").unwrap();
+	}
+	for gpu in gpus {
+		writeln!(out, "GPU: {gpu}").unwrap();
+	}
+	match platform::find_openvr_runtime() {
+		Ok(runtime) => {
+			let build = platform::steamvr_build(&runtime);
+			let build = build.as_deref().unwrap_or("unknown");
+			writeln!(out, "SteamVR: {runtime:?}, build {build}").unwrap();
+		}
+		Err(e) => writeln!(out, "SteamVR: {e}").unwrap(),
+	}
+	match platform::find_slimevr_driver() {
+		Some(driver) => writeln!(out, "SlimeVR driver: {driver:?}").unwrap(),
+		None => writeln!(out, "SlimeVR driver: not found").unwrap(),
+	}
+	out
+}
+
+/// How the sessions in `logs` went, as far as the logs tell.
+fn summarize(logs: &str) -> String {
+	let count = |f: fn(&str) -> bool| logs.lines().filter(|l| f(l)).count();
+	let runs = count(|l| l.contains("Overlay version: "));
+	let restarts = count(|l| l.contains("Restarting overlay session"));
+	let warnings = count(|l| l.starts_with("WARN"));
+	let errors = count(|l| l.starts_with("ERROR"));
+	format!(
+		"Runs in the logs: {runs}\nOverlay restarts: {restarts}\nWarnings: \
+		 {warnings}\nErrors: {errors}\n"
+	)
+}
+
+/// Redacts addresses and Wi-Fi credentials from `text`, and replaces `home` with
+/// `~`.
+fn redact(text: &str, home: Option<&str>) -> String {
+	let mut text = text.to_owned();
+	if let Some(home) = home {
+		// Paths are often logged with `{:?}`, which escapes backslashes.
+		text = text
+			.replace(&home.replace('\\', "\\\\"), "~")
+			.replace(home, "~");
+	}
+	let mut out = String::with_capacity(text.len());
+	for line in text.split_inclusive('\n') {
+		let lower = line.to_lowercase();
+		if SECRETS.iter().any(|s| lower.contains(s)) {
+			if let Some(i) = line.find(|c| c == '=' || c == ':') {
+				out += &line[..=i];
+			}
+			out += " [redacted]";
+			if line.ends_with('\n') {
+				out.push('\n');
+			}
+			continue;
+		}
+		out += &redact_addresses(line);
+	}
+	out
+}
+
+fn is_address_char(c: char) -> bool {
+	c.is_ascii_hexdigit() || c == '.' || c == ':'
+}
+
+fn redact_addresses(line: &str) -> String {
+	let mut out = String::with_capacity(line.len());
+	let mut rest = line;
+	while let Some(start) = rest.find(is_address_char) {
+		out += &rest[..start];
+		rest = &rest[start..];
+		let end = rest.find(|c| !is_address_char(c)).unwrap_or(rest.len());
+		out += &redact_address(&rest[..end]);
+		rest = &rest[end..];
+	}
+	out + rest
+}
+
+/// Redacts `token` if it is a MAC address, or contains IPv4 addresses, like with a
+/// port.
+fn redact_address(token: &str) -> String {
+	let parts: Vec<&str> = token.split(':').collect();
+	let is_byte = |p: &&str| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit());
+	if parts.len() == 6 && parts.iter().all(is_byte) {
+		return format!("xx:xx:xx:xx:xx:{}", parts[5]);
+	}
+	let parts: Vec<String> = parts
+		.into_iter()
+		.map(|part| {
+			// Like the end of a sentence.
+			let address = part.trim_end_matches('.');
+			match address.parse::<Ipv4Addr>() {
+				Ok(ip) if !ip.is_loopback() && !ip.is_unspecified() => {
+					let last = ip.octets()[3];
+					format!("x.x.x.{last}{}", &part[address.len()..])
+				}
+				_ => part.to_owned(),
+			}
+		})
+		.collect();
+	parts.join(":")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn redacts_addresses() {
+		let log = "INFO - Tracker 192.168.1.23 sensor 0: overheating.\n\
+		           INFO - Listening on 0.0.0.0:6969, connected to 10.0.0.5:6969.\n";
+		assert_eq!(
+			redact(log, None),
+			"INFO - Tracker x.x.x.23 sensor 0: overheating.\n\
+			 INFO - Listening on 0.0.0.0:6969, connected to x.x.x.5:6969.\n"
+		);
+		assert_eq!(
+			redact("mac a4:cf:12:0b:ee:01 at 127.0.0.1", None),
+			"mac xx:xx:xx:xx:xx:01 at 127.0.0.1"
+		);
+		// Versions and times aren't addresses.
+		let line = "INFO - 2026-10-14 12:30:05 - wgpu 0.15.1";
+		assert_eq!(redact(line, None), line);
+	}
+
+	#[test]
+	fn redacts_credentials_and_home() {
+		let text = "SSID = \"home\"\nPassword: hunter2\n\
+		            Config at \"/home/me/a.toml\"\n";
+		assert_eq!(
+			redact(text, Some("/home/me")),
+			"SSID = [redacted]\nPassword: [redacted]\nConfig at \"~/a.toml\"\n"
+		);
+		let windows = r#"Loaded "C:\\Users\\me\\overlay.toml""#;
+		assert_eq!(
+			redact(windows, Some(r"C:\Users\me")),
+			r#"Loaded "~\\overlay.toml""#
+		);
+	}
+
+	#[test]
+	fn summarizes_the_logs() {
+		let logs = "INFO - d - Overlay version: v1\nWARN - d - Slow\nINFO - d - \
+		            Restarting overlay session (restart #1)\nERROR - d - Stuck\n";
+		assert_eq!(
+			summarize(logs),
+			"Runs in the logs: 1\nOverlay restarts: 1\nWarnings: 1\nErrors: 1\n"
+		);
+	}
+}
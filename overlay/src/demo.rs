@@ -0,0 +1,382 @@
+//! Animates the skeleton through canned movements, for `--demo`. It stands in for the
+//! server, so that rendering, themes, and performance can be checked without any
+//! trackers.
+//!
+//! The movements take turns, each for [`MOVEMENT_DURATION`]: walking in place,
+//! squatting, and waving both arms. The skeleton stands on the floor at SteamVR's
+//! origin, facing -Z.
+
+use crate::model::{BoneKind, Isometry};
+use crate::timebase::Stamped;
+
+use nalgebra::{Translation3, UnitQuaternion, Vector3};
+use solarxr::protocol::datatypes::BodyPart;
+use solarxr::protocol::flatbuffers::FlatBufferBuilder;
+use solarxr::{Data, FeedUpdate};
+use std::f32::consts::{FRAC_PI_2, TAU};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// About as often as the server sends feed updates.
+const FRAME_INTERVAL: Duration = Duration::from_millis(1000 / 60);
+/// How long each movement lasts, before the next one takes over.
+const MOVEMENT_DURATION: Duration = Duration::from_secs(6);
+/// How far apart the thighs and upper arms are from the middle of the body, in
+/// meters.
+const HIP_WIDTH: f32 = 0.1;
+const SHOULDER_WIDTH: f32 = 0.18;
+
+/// The length of each bone that is animated, in meters. The server doesn't send
+/// the head, so neither do we.
+const LENGTHS: [(BoneKind, f32); 16] = {
+	use BoneKind::*;
+	[
+		(Neck, 0.1),
+		(Chest, 0.25),
+		(Waist, 0.2),
+		(Hip, 0.1),
+		(ThighL, 0.45),
+		(ThighR, 0.45),
+		(AnkleL, 0.45),
+		(AnkleR, 0.45),
+		(FootL, 0.1),
+		(FootR, 0.1),
+		(UpperArmL, 0.28),
+		(UpperArmR, 0.28),
+		(ForearmL, 0.26),
+		(ForearmR, 0.26),
+		(WristL, 0.08),
+		(WristR, 0.08),
+	]
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Movement {
+	Walk,
+	Squat,
+	ArmWaves,
+}
+impl Movement {
+	const ALL: [Self; 3] = [Self::Walk, Self::Squat, Self::ArmWaves];
+
+	/// How long one repetition takes.
+	fn period(self) -> Duration {
+		match self {
+			Self::Walk => Duration::from_millis(1200),
+			Self::Squat => Duration::from_millis(2500),
+			Self::ArmWaves => Duration::from_millis(1500),
+		}
+	}
+
+	/// The movement that plays `elapsed` after the start, and the angle of its
+	/// current repetition, in radians.
+	fn at(elapsed: Duration) -> (Self, f32) {
+		let movement = elapsed.as_millis() / MOVEMENT_DURATION.as_millis();
+		let movement = Self::ALL[movement as usize % Self::ALL.len()];
+		let period = movement.period().as_secs_f32();
+		let phase = (elapsed.as_secs_f32() % period) / period * TAU;
+		(movement, phase)
+	}
+
+	fn angles(self, phase: f32) -> Angles {
+		let mut a = Angles::default();
+		match self {
+			Self::Walk => {
+				let swing = phase.sin();
+				for (i, side) in [1., -1.].into_iter().enumerate() {
+					a.thigh[i] = 0.4 * side * swing;
+					// The knee only bends while the leg swings forward.
+					a.knee[i] = -0.8 * (side * phase.cos()).max(0.);
+					a.arm_swing[i] = -0.35 * side * swing;
+					a.elbow[i] = 0.3;
+				}
+			}
+			Self::Squat => {
+				let depth = (1. - phase.cos()) / 2.;
+				a.lean = 0.5 * depth;
+				a.thigh = [1.4 * depth; 2];
+				a.knee = [-2.1 * depth; 2];
+				a.arm_swing = [1.3 * depth; 2];
+			}
+			Self::ArmWaves => {
+				let wave = phase.sin();
+				a.arm_raise = [2.5 + 0.3 * wave; 2];
+				a.elbow = [0.4 - 0.4 * wave, 0.4 + 0.4 * wave];
+			}
+		}
+		a
+	}
+}
+
+/// Joint angles in radians, left side first. Legs are relative to standing upright,
+/// so that the feet stay flat.
+#[derive(Debug, Default)]
+struct Angles {
+	/// How far the torso leans forward.
+	lean: f32,
+	/// Forward is positive.
+	thigh: [f32; 2],
+	/// Relative to the thigh, bending is negative.
+	knee: [f32; 2],
+	/// Forward is positive.
+	arm_swing: [f32; 2],
+	/// Away from the body is positive.
+	arm_raise: [f32; 2],
+	/// Relative to the upper arm, bending outwards is positive.
+	elbow: [f32; 2],
+}
+
+/// Bones point down their -Y axis when not rotated, so rotating around X by a
+/// positive angle swings them forward, and around Z to the right.
+fn forward(angle: f32) -> UnitQuaternion<f32> {
+	UnitQuaternion::from_axis_angle(&Vector3::x_axis(), angle)
+}
+fn sideways(angle: f32) -> UnitQuaternion<f32> {
+	UnitQuaternion::from_axis_angle(&Vector3::z_axis(), angle)
+}
+
+fn length(kind: BoneKind) -> f32 {
+	LENGTHS.iter().find(|(k, _)| *k == kind).unwrap().1
+}
+
+/// The pose `elapsed` after the start, as the global isometry and length of each bone
+/// in [`LENGTHS`].
+fn pose(elapsed: Duration) -> Vec<(BoneKind, Isometry, f32)> {
+	use BoneKind::*;
+
+	let (movement, phase) = Movement::at(elapsed);
+	let a = movement.angles(phase);
+	let mut bones: Vec<(BoneKind, Isometry, f32)> = Vec::new();
+	let tail = |bones: &[(BoneKind, Isometry, f32)], kind: BoneKind| {
+		let (_, iso, length) = bones.iter().find(|(k, _, _)| *k == kind).unwrap();
+		iso.translation.vector + iso.rotation * Vector3::new(0., -length, 0.)
+	};
+	let push = |bones: &mut Vec<_>, kind, head: Vector3<f32>, rot| {
+		let iso = Isometry::from_parts(Translation3::from(head), rot);
+		bones.push((kind, iso, length(kind)));
+	};
+
+	// The torso, from the top down. Leaning forward moves the tails back.
+	let torso = forward(-a.lean);
+	let mut head = Vector3::zeros();
+	for kind in [Neck, Chest, Waist, Hip] {
+		push(&mut bones, kind, head, torso);
+		head = tail(&bones, kind);
+	}
+	let hip = head;
+	let shoulders = tail(&bones, Neck);
+	for (i, [thigh, ankle, foot], side) in [
+		(0, [ThighL, AnkleL, FootL], -1.),
+		(1, [ThighR, AnkleR, FootR], 1.),
+	] {
+		push(
+			&mut bones,
+			thigh,
+			hip + torso * Vector3::x() * side * HIP_WIDTH,
+			forward(a.thigh[i]),
+		);
+		let knee = tail(&bones, thigh);
+		push(&mut bones, ankle, knee, forward(a.thigh[i] + a.knee[i]));
+		let heel = tail(&bones, ankle);
+		push(&mut bones, foot, heel, forward(FRAC_PI_2));
+	}
+	for (i, [upper, fore, wrist], side) in [
+		(0, [UpperArmL, ForearmL, WristL], -1.),
+		(1, [UpperArmR, ForearmR, WristR], 1.),
+	] {
+		let shoulder = shoulders + torso * Vector3::x() * side * SHOULDER_WIDTH;
+		let upper_rot =
+			torso * sideways(side * a.arm_raise[i]) * forward(a.arm_swing[i]);
+		push(&mut bones, upper, shoulder, upper_rot);
+		let elbow = tail(&bones, upper);
+		let fore_rot = upper_rot * sideways(side * a.elbow[i]);
+		push(&mut bones, fore, elbow, fore_rot);
+		let hand = tail(&bones, fore);
+		push(&mut bones, wrist, hand, fore_rot);
+	}
+
+	// Stand on the floor, with whichever end of a bone is lowest.
+	let lowest = bones
+		.iter()
+		.flat_map(|(kind, iso, _)| [iso.translation.y, tail(&bones, *kind).y])
+		.fold(f32::INFINITY, f32::min);
+	for (_, iso, _) in &mut bones {
+		iso.translation.y -= lowest;
+	}
+	bones
+}
+
+/// The inverse of `BoneKind::try_from(BodyPart)`.
+fn body_part(kind: BoneKind) -> BodyPart {
+	use BoneKind::*;
+	match kind {
+		Head => BodyPart::HEAD,
+		Neck => BodyPart::NECK,
+		Chest => BodyPart::CHEST,
+		Waist => BodyPart::WAIST,
+		Hip => BodyPart::HIP,
+		ThighL => BodyPart::LEFT_UPPER_LEG,
+		ThighR => BodyPart::RIGHT_UPPER_LEG,
+		AnkleL => BodyPart::LEFT_LOWER_LEG,
+		AnkleR => BodyPart::RIGHT_LOWER_LEG,
+		FootL => BodyPart::LEFT_FOOT,
+		FootR => BodyPart::RIGHT_FOOT,
+		UpperArmL => BodyPart::LEFT_UPPER_ARM,
+		UpperArmR => BodyPart::RIGHT_UPPER_ARM,
+		ForearmL => BodyPart::LEFT_LOWER_ARM,
+		ForearmR => BodyPart::RIGHT_LOWER_ARM,
+		WristL => BodyPart::LEFT_HAND,
+		WristR => BodyPart::RIGHT_HAND,
+	}
+}
+
+/// Encodes `bones` like the server would, as a data feed update with only bones.
+#[allow(clippy::needless_update)]
+fn encode(
+	fbb: &mut FlatBufferBuilder<'static>,
+	bones: &[(BoneKind, Isometry, f32)],
+) -> FeedUpdate {
+	use solarxr::protocol::data_feed::{
+		Bone, BoneArgs, DataFeedMessage, DataFeedMessageHeader,
+		DataFeedMessageHeaderArgs, DataFeedUpdate, DataFeedUpdateArgs,
+	};
+	use solarxr::protocol::datatypes::math::{Quat, Vec3f};
+	use solarxr::protocol::{MessageBundle, MessageBundleArgs};
+
+	fbb.reset();
+	let bones: Vec<_> = bones
+		.iter()
+		.map(|(kind, iso, length)| {
+			let (t, r) = (iso.translation, iso.rotation);
+			Bone::create(
+				fbb,
+				&BoneArgs {
+					body_part: body_part(*kind),
+					rotation_g: Some(&Quat::new(r.i, r.j, r.k, r.w)),
+					bone_length: *length,
+					head_position_g: Some(&Vec3f::new(t.x, t.y, t.z)),
+					..Default::default()
+				},
+			)
+		})
+		.collect();
+	let bones = fbb.create_vector(&bones);
+	let update = DataFeedUpdate::create(
+		fbb,
+		&DataFeedUpdateArgs {
+			bones: Some(bones),
+			..Default::default()
+		},
+	);
+	let header = DataFeedMessageHeader::create(
+		fbb,
+		&DataFeedMessageHeaderArgs {
+			message_type: DataFeedMessage::DataFeedUpdate,
+			message: Some(update.as_union_value()),
+			..Default::default()
+		},
+	);
+	let data_feed_msgs = fbb.create_vector(&[header]);
+	let bundle = MessageBundle::create(
+		fbb,
+		&MessageBundleArgs {
+			data_feed_msgs: Some(data_feed_msgs),
+			..Default::default()
+		},
+	);
+	fbb.finish(bundle, None);
+	let data = Data::from_vec(fbb.finished_data().to_vec())
+		.expect("we just built a valid message bundle");
+	FeedUpdate(data)
+}
+
+/// Sends the animation to `sender`, like [`solarxr::run`] sends what the server does.
+pub async fn run(sender: &watch::Sender<Option<Stamped<FeedUpdate>>>) -> ! {
+	let start = Instant::now();
+	let mut fbb = FlatBufferBuilder::new();
+	let mut interval = tokio::time::interval(FRAME_INTERVAL);
+	loop {
+		interval.tick().await;
+		let update = encode(&mut fbb, &pose(start.elapsed()));
+		sender.send_replace(Some(Stamped::now(update)));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A few frames of every movement.
+	fn frames() -> impl Iterator<Item = Duration> {
+		let total = MOVEMENT_DURATION * Movement::ALL.len() as u32;
+		(0..total.as_millis() as u64)
+			.step_by(97)
+			.map(Duration::from_millis)
+	}
+
+	#[test]
+	fn plays_every_movement_in_turn() {
+		let movements: Vec<Movement> = (0..4)
+			.map(|i| Movement::at(MOVEMENT_DURATION * i).0)
+			.collect();
+		assert_eq!(
+			movements,
+			[
+				Movement::Walk,
+				Movement::Squat,
+				Movement::ArmWaves,
+				Movement::Walk
+			]
+		);
+	}
+
+	#[test]
+	fn stands_on_the_floor() {
+		for t in frames() {
+			let bones = pose(t);
+			let lowest = bones
+				.iter()
+				.map(|(_, iso, _)| iso.translation.y)
+				.fold(f32::INFINITY, f32::min);
+			assert!(lowest >= -1e-4, "below the floor at {t:?}");
+			assert!(lowest < 0.2, "floating at {t:?}");
+		}
+	}
+
+	#[test]
+	fn bones_are_attached_to_their_parents() {
+		for t in frames() {
+			let bones = pose(t);
+			let find = |kind| bones.iter().find(|(k, _, _)| *k == kind).unwrap();
+			for &(kind, iso, _) in &bones {
+				// Thighs and upper arms sit beside the tails of their parents, and the
+				// head sits on top of the neck instead.
+				if matches!(kind, BoneKind::Neck | BoneKind::Head) {
+					continue;
+				}
+				let (_, parent, length) = find(kind.parent().unwrap());
+				let tail = parent * nalgebra::Point3::new(0., -length, 0.);
+				let gap = (iso.translation.vector - tail.coords).norm();
+				let allowed = SHOULDER_WIDTH.max(HIP_WIDTH) + 1e-4;
+				assert!(gap <= allowed, "{kind:?} is {gap} m away at {t:?}");
+			}
+		}
+	}
+
+	#[test]
+	fn encodes_every_bone() {
+		let mut fbb = FlatBufferBuilder::new();
+		let update = encode(&mut fbb, &pose(Duration::ZERO));
+		let table = update.0.table();
+		let msg = table.data_feed_msgs().unwrap().get(0);
+		let bones = msg.message_as_data_feed_update().unwrap().bones().unwrap();
+		let kinds: Vec<BoneKind> = bones
+			.iter()
+			.map(|b| BoneKind::try_from(b.body_part()).unwrap())
+			.collect();
+		let expected: Vec<BoneKind> = LENGTHS.iter().map(|(k, _)| *k).collect();
+		assert_eq!(kinds.len(), expected.len());
+		assert!(expected.iter().all(|k| kinds.contains(k)));
+		assert!(bones.iter().all(|b| b.rotation_g().is_some()));
+	}
+}
@@ -0,0 +1,135 @@
+//! Receives the problems that trackers report about themselves, like a sensor that
+//! stopped responding or overheating. The SlimeVR server doesn't forward these, so
+//! the firmware broadcasts a copy of them on the LAN, which we listen for here.
+
+use eyre::Result;
+use firmware_protocol::{Packet, SbPacket};
+use std::net::{Ipv4Addr, SocketAddr};
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// Sent in `sensor_id` when the problem isn't with a particular sensor.
+const NO_SENSOR: u8 = 0xFF;
+/// How many diagnostics can be waiting for a slow subscriber before the oldest are
+/// dropped.
+pub const CHANNEL_CAPACITY: usize = 16;
+
+/// A problem that a tracker reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+	/// The tracker is only known by its address, since the server doesn't tell us
+	/// which device that is.
+	pub tracker: SocketAddr,
+	pub sensor_id: Option<u8>,
+	pub severity: Severity,
+	pub message: String,
+}
+impl std::fmt::Display for Diagnostic {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "Tracker {}", self.tracker.ip())?;
+		if let Some(id) = self.sensor_id {
+			write!(f, " sensor {id}")?;
+		}
+		write!(f, ": {}", self.message)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+	/// The problem went away again.
+	Info,
+	Error,
+}
+
+/// Turns a packet into a diagnostic, if it is one.
+fn to_diagnostic(tracker: SocketAddr, packet: SbPacket) -> Option<Diagnostic> {
+	let (sensor_id, severity, message) = match packet {
+		SbPacket::Diagnostic {
+			sensor_id,
+			code,
+			message,
+		} => {
+			let message = message
+				.to_string()
+				.unwrap_or_else(|_| format!("Unknown problem (code {code})"));
+			(sensor_id, Severity::Error, message)
+		}
+		SbPacket::ThermalState { state } => {
+			let (severity, message) = match state {
+				0 => (Severity::Info, "Cooled down".to_string()),
+				1 => (Severity::Error, "Overheating, throttling".to_string()),
+				_ => (
+					Severity::Error,
+					"Critically hot, throttling heavily".to_string(),
+				),
+			};
+			(NO_SENSOR, severity, message)
+		}
+		SbPacket::Sleeping => (
+			NO_SENSOR,
+			Severity::Info,
+			"Lay still, went to sleep until it is moved".to_string(),
+		),
+		_ => return None,
+	};
+	Some(Diagnostic {
+		tracker,
+		sensor_id: (sensor_id != NO_SENSOR).then_some(sensor_id),
+		severity,
+		message,
+	})
+}
+
+/// Listens on `port` until shutdown, logging the diagnostics and sending them to
+/// `sender`. Not being able to listen is only logged, since the rest of the overlay
+/// works fine without diagnostics.
+pub async fn run(
+	subsys: SubsystemHandle,
+	port: u16,
+	sender: broadcast::Sender<Diagnostic>,
+) -> Result<()> {
+	let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).await {
+		Ok(s) => s,
+		Err(e) => {
+			let e = eyre::Report::new(e)
+				.wrap_err(format!("Failed to listen for diagnostics on port {port}"));
+			log::error!("{e:?}");
+			return Ok(());
+		}
+	};
+	log::info!("Listening for tracker diagnostics on port {port}");
+
+	let mut buf = [0; 1536];
+	loop {
+		let r = tokio::select! {
+			_ = subsys.on_shutdown_requested() => return Ok(()),
+			r = socket.recv_from(&mut buf) => r,
+		};
+		// Windows reports ICMP errors from earlier sends here, which don't matter.
+		let (len, tracker) = match r {
+			Ok(r) => r,
+			Err(e) => {
+				log::debug!("Failed to receive diagnostics: {e}");
+				continue;
+			}
+		};
+		// Trackers that the server turned checksums on for also send them here.
+		let bytes = &buf[..len];
+		let packet = Packet::<SbPacket>::deserialize_from(bytes)
+			.or_else(|_| Packet::deserialize_from_checked(bytes));
+		let Ok(packet) = packet else {
+			log::trace!("Ignoring malformed packet from {tracker}");
+			continue;
+		};
+		let Some(diagnostic) = to_diagnostic(tracker, packet.split().1) else {
+			continue;
+		};
+		match diagnostic.severity {
+			Severity::Info => log::info!("{diagnostic}"),
+			Severity::Error => log::warn!("{diagnostic}"),
+		}
+		// Nobody might be subscribed, which is fine.
+		let _ = sender.send(diagnostic);
+	}
+}
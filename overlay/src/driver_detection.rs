@@ -0,0 +1,125 @@
+//! Detects whether the SlimeVR driver for SteamVR is there, from the trackers that
+//! it adds to SteamVR. Without it SteamVR never sees the trackers, which first-time
+//! users tend to mistake for the overlay not working.
+
+use crate::platform;
+
+use ovr::sys::ETrackedDeviceProperty as Prop;
+use ovr::TrackedDeviceIndex;
+use ovr_overlay as ovr;
+use std::ffi::{CStr, CString};
+use std::time::{Duration, Instant};
+
+/// How often to look through the tracked devices. Trackers only come and go when
+/// the server starts or stops, so this can be slow.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// The tracking system of the trackers that the driver adds.
+const TRACKING_SYSTEM: &str = "slimevr";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DriverStatus {
+	/// SteamVR has trackers from the driver.
+	Active,
+	/// The driver is installed, but hasn't added any trackers yet. Usually the
+	/// server isn't running, or the driver is disabled in the settings of SteamVR.
+	Installed,
+	/// The driver isn't installed, so the server can't send trackers to SteamVR.
+	Missing,
+}
+impl DriverStatus {
+	/// Explains the status to the user, with what to do about it.
+	pub fn log(self) {
+		match self {
+			Self::Active => log::info!("The SlimeVR driver is active in SteamVR"),
+			Self::Installed => log::warn!(
+				"The SlimeVR driver is installed, but SteamVR has no trackers from it \
+				 yet. Make sure that the server is running, and that the driver is \
+				 enabled under Startup / Shutdown > Manage Add-Ons in SteamVR."
+			),
+			Self::Missing => log::warn!(
+				"The SlimeVR driver for SteamVR isn't installed, so the trackers won't \
+				 show up in SteamVR. Run the SlimeVR installer to install it."
+			),
+		}
+	}
+}
+
+/// Whether any tracked device comes from the driver.
+fn has_trackers(context: &ovr::Context) -> bool {
+	let system = context.system_mngr();
+	(0..ovr::sys::k_unMaxTrackedDeviceCount)
+		.map(TrackedDeviceIndex::new)
+		.any(|idx| {
+			system
+				.get_tracked_device_property::<CString>(
+					idx,
+					Prop::Prop_TrackingSystemName_String,
+				)
+				.map_or(false, |name| is_driver_tracking_system(&name))
+		})
+}
+
+/// Whether a device with the tracking system `name` comes from the driver.
+pub fn is_driver_tracking_system(name: &CStr) -> bool {
+	name.to_string_lossy().eq_ignore_ascii_case(TRACKING_SYSTEM)
+}
+
+/// Keeps track of the driver, and reports when its status changes.
+#[derive(Debug)]
+pub struct DriverDetector {
+	/// Whether the driver is on disk. Only checked once, installing it takes a
+	/// restart of SteamVR anyway.
+	installed: bool,
+	status: Option<DriverStatus>,
+	last_poll: Option<Instant>,
+}
+impl DriverDetector {
+	pub fn new() -> Self {
+		let driver = platform::find_slimevr_driver();
+		if let Some(driver) = &driver {
+			log::info!("Found the SlimeVR driver at {driver:?}");
+		}
+		Self {
+			installed: driver.is_some(),
+			status: None,
+			last_poll: None,
+		}
+	}
+
+	/// Polls OpenVR at most once every [`POLL_INTERVAL`]. Returns the status of the
+	/// driver if it changed, which it always does on the first poll.
+	pub fn poll(&mut self, context: &ovr::Context) -> Option<DriverStatus> {
+		self.poll_with(|| has_trackers(context))
+	}
+
+	/// Like [`Self::poll`], for a context that `ovr_overlay` doesn't have, with
+	/// `has_trackers` telling whether any tracked device comes from the driver.
+	pub fn poll_with(
+		&mut self,
+		has_trackers: impl FnOnce() -> bool,
+	) -> Option<DriverStatus> {
+		let now = Instant::now();
+		if self
+			.last_poll
+			.map_or(false, |last| now - last < POLL_INTERVAL)
+		{
+			return None;
+		}
+		self.last_poll = Some(now);
+
+		// The trackers win over the files, since the driver may have been
+		// installed somewhere that we don't look.
+		let status = if has_trackers() {
+			DriverStatus::Active
+		} else if self.installed {
+			DriverStatus::Installed
+		} else {
+			DriverStatus::Missing
+		};
+		if Some(status) == self.status {
+			return None;
+		}
+		self.status = Some(status);
+		Some(status)
+	}
+}
@@ -0,0 +1,151 @@
+//! Extrapolates bone rotations forward in time, to compensate for latency between
+//! the trackers and the rendered skeleton. When the server knows the angular
+//! velocity of a tracker, like the one that the trackers of this firmware report,
+//! it is in the feed as the raw angular velocity of the tracker, and used for the
+//! bone of the tracker. Otherwise it is estimated from consecutive rotations.
+
+use crate::model::{BoneKind, BoneMap};
+
+use nalgebra::{UnitQuaternion, Vector3};
+use std::time::{Duration, Instant};
+
+/// Updates closer together than this are ignored when estimating velocity, as they
+/// are too noisy.
+const MIN_SAMPLE_INTERVAL: Duration = Duration::from_millis(2);
+/// How long the angular velocity that a tracker reported is used for. Trackers send
+/// it less often than their rotation.
+const REPORTED_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Rotates `rot` by `angular_velocity` (in rad/s, in the global frame) for `dt`.
+pub fn extrapolate(
+	rot: UnitQuaternion<f32>,
+	angular_velocity: Vector3<f32>,
+	dt: Duration,
+) -> UnitQuaternion<f32> {
+	UnitQuaternion::from_scaled_axis(angular_velocity * dt.as_secs_f32()) * rot
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+	time: Instant,
+	rot: UnitQuaternion<f32>,
+}
+
+/// Keeps the angular velocity of each bone, as reported by its tracker, or else
+/// estimated from consecutive rotations.
+#[derive(Debug)]
+pub struct Extrapolator {
+	/// The latest rotation of each bone, which gets extrapolated.
+	last: BoneMap<Option<Sample>>,
+	/// The rotation that the next estimate of the velocity starts from, at least
+	/// [`MIN_SAMPLE_INTERVAL`] before it.
+	reference: BoneMap<Option<Sample>>,
+	angular_velocity: BoneMap<Vector3<f32>>,
+	/// When the tracker of each bone last reported its angular velocity.
+	reported: BoneMap<Option<Instant>>,
+}
+impl Default for Extrapolator {
+	fn default() -> Self {
+		Self {
+			last: BoneMap::default(),
+			reference: BoneMap::default(),
+			angular_velocity: BoneMap::new([Vector3::zeros(); BoneKind::num_types()]),
+			reported: BoneMap::default(),
+		}
+	}
+}
+impl Extrapolator {
+	/// Records the latest rotation of `bone`, received at `time`.
+	pub fn update(&mut self, bone: BoneKind, rot: UnitQuaternion<f32>, time: Instant) {
+		let sample = Sample { time, rot };
+		self.last[bone] = Some(sample);
+		let is_reported = self.reported[bone].map_or(false, |at| {
+			time.saturating_duration_since(at) < REPORTED_TIMEOUT
+		});
+		if is_reported {
+			self.reference[bone] = Some(sample);
+			return;
+		}
+		if let Some(reference) = self.reference[bone] {
+			let dt = time.saturating_duration_since(reference.time);
+			if dt < MIN_SAMPLE_INTERVAL {
+				return;
+			}
+			let delta = rot * reference.rot.inverse();
+			self.angular_velocity[bone] = delta.scaled_axis() / dt.as_secs_f32();
+		}
+		self.reference[bone] = Some(sample);
+	}
+
+	/// Records the angular velocity that the tracker of `bone` reported at `time`,
+	/// in rad/s in the global frame, which is used instead of the estimate.
+	pub fn report(
+		&mut self,
+		bone: BoneKind,
+		angular_velocity: Vector3<f32>,
+		time: Instant,
+	) {
+		self.angular_velocity[bone] = angular_velocity;
+		self.reported[bone] = Some(time);
+	}
+
+	/// The angular velocity of `bone`, in rad/s in the global frame.
+	pub fn angular_velocity(&self, bone: BoneKind) -> Vector3<f32> {
+		self.angular_velocity[bone]
+	}
+
+	/// Extrapolates the latest rotation of `bone` by `dt`.
+	pub fn extrapolate(
+		&self,
+		bone: BoneKind,
+		dt: Duration,
+	) -> Option<UnitQuaternion<f32>> {
+		let last = self.last[bone]?;
+		Some(extrapolate(last.rot, self.angular_velocity(bone), dt))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const BONE: BoneKind = BoneKind::Chest;
+
+	fn yaw(angle: f32) -> UnitQuaternion<f32> {
+		UnitQuaternion::from_axis_angle(&Vector3::z_axis(), angle)
+	}
+
+	#[test]
+	fn extrapolates_from_the_latest_rotation() {
+		let mut extrapolator = Extrapolator::default();
+		let start = Instant::now();
+		extrapolator.update(BONE, yaw(0.), start);
+		extrapolator.update(BONE, yaw(0.1), start + Duration::from_millis(10));
+		assert!((extrapolator.angular_velocity(BONE).z - 10.).abs() < 1e-3);
+
+		// Too close to estimate from, but still the rotation to extrapolate.
+		extrapolator.update(BONE, yaw(0.11), start + Duration::from_millis(11));
+		let rot = extrapolator.extrapolate(BONE, Duration::ZERO).unwrap();
+		assert!(rot.angle_to(&yaw(0.11)) < 1e-5);
+	}
+
+	#[test]
+	fn prefers_the_reported_velocity() {
+		let mut extrapolator = Extrapolator::default();
+		let start = Instant::now();
+		extrapolator.report(BONE, Vector3::new(0., 0., 2.), start);
+		extrapolator.update(BONE, yaw(0.), start);
+		extrapolator.update(BONE, yaw(0.1), start + Duration::from_millis(10));
+		assert_eq!(
+			extrapolator.angular_velocity(BONE),
+			Vector3::new(0., 0., 2.)
+		);
+
+		// Estimated again, once the tracker stopped reporting it.
+		let later = start + REPORTED_TIMEOUT + Duration::from_millis(10);
+		extrapolator.update(BONE, yaw(0.2), later);
+		let later = later + Duration::from_millis(10);
+		extrapolator.update(BONE, yaw(0.3), later);
+		assert!((extrapolator.angular_velocity(BONE).z - 10.).abs() < 1e-3);
+	}
+}
@@ -0,0 +1,96 @@
+//! Moves the whole skeleton up or down, to make up for a floor that the server got
+//! slightly wrong. The tray and gamepad nudge it, or snap it so that its lowest
+//! point touches the floor of SteamVR.
+//!
+//! SolarXR has no request for the floor height yet, so the server doesn't learn
+//! about the offset, and it only moves what the overlay draws.
+
+use crate::model::Isometry;
+
+use nalgebra::{Point3, Vector3};
+
+/// How far one nudge moves the skeleton, in meters.
+const NUDGE: f32 = 0.01;
+/// Offsets beyond this are more than a small correction, and probably a mistake.
+const MAX_OFFSET: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloorCommand {
+	Raise,
+	Lower,
+	/// Moves the skeleton so that its lowest point is on the floor. This assumes
+	/// that the user is standing, so that their feet are on the floor too.
+	Snap,
+	Reset,
+}
+
+/// Applies `cmd` to `offset`, the height in meters that the skeleton is moved up
+/// by. `bones` are where the bones would be without it, with their lengths.
+pub fn adjust(
+	offset: f32,
+	cmd: FloorCommand,
+	bones: impl IntoIterator<Item = (Isometry, f32)>,
+) -> f32 {
+	let offset = match cmd {
+		FloorCommand::Raise => offset + NUDGE,
+		FloorCommand::Lower => offset - NUDGE,
+		FloorCommand::Snap => match lowest_point(bones) {
+			Some(lowest) => -lowest,
+			// Nothing to snap to before the first feed update.
+			None => offset,
+		},
+		FloorCommand::Reset => 0.,
+	};
+	offset.clamp(-MAX_OFFSET, MAX_OFFSET)
+}
+
+/// Moves `iso` up by `offset`.
+pub fn apply(iso: &mut Isometry, offset: f32) {
+	iso.translation.vector += Vector3::y() * offset;
+}
+
+/// The height of the lowest head or tail of `bones`.
+fn lowest_point(bones: impl IntoIterator<Item = (Isometry, f32)>) -> Option<f32> {
+	bones
+		.into_iter()
+		.flat_map(|(iso, length)| {
+			let head = iso * Point3::origin();
+			let tail = iso * Point3::new(0., -length, 0.);
+			[head.y, tail.y]
+		})
+		.reduce(f32::min)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::{Translation3, UnitQuaternion};
+
+	fn bone(y: f32, length: f32) -> (Isometry, f32) {
+		let iso = Isometry::from_parts(
+			Translation3::new(0., y, 0.),
+			UnitQuaternion::identity(),
+		);
+		(iso, length)
+	}
+
+	#[test]
+	fn snap_puts_the_lowest_tail_on_the_floor() {
+		// Feet that end 3 cm below the floor.
+		let bones = [bone(1.0, 0.5), bone(0.5, 0.53)];
+		let offset = adjust(0., FloorCommand::Snap, bones);
+		assert!((offset - 0.03).abs() < 1e-6);
+	}
+
+	#[test]
+	fn snap_without_bones_keeps_the_offset() {
+		assert_eq!(adjust(0.02, FloorCommand::Snap, []), 0.02);
+	}
+
+	#[test]
+	fn nudges_are_clamped() {
+		let offset = (0..100).fold(0., |o, _| adjust(o, FloorCommand::Raise, []));
+		assert_eq!(offset, MAX_OFFSET);
+		assert_eq!(adjust(offset, FloorCommand::Reset, []), 0.);
+	}
+}
@@ -0,0 +1,60 @@
+//! Queries OpenVR for when the frame currently being rendered will actually be
+//! shown on the headset's display, and how the last ones went.
+
+use ovr::sys::ETrackedDeviceProperty as Prop;
+use ovr::TrackedDeviceIndex;
+use ovr_overlay as ovr;
+use std::time::Duration;
+
+/// How long each frame of the headset's display is shown for.
+pub fn frame_interval(context: &ovr::Context) -> Option<Duration> {
+	let frequency: f32 = context
+		.system_mngr()
+		.get_tracked_device_property(
+			TrackedDeviceIndex::HMD,
+			Prop::Prop_DisplayFrequency_Float,
+		)
+		.ok()?;
+	(frequency > 0.).then(|| Duration::from_secs_f32(1. / frequency))
+}
+
+/// How long until the photons of the next frame leave the headset's display.
+pub fn time_to_photons(context: &ovr::Context) -> Option<Duration> {
+	let system = context.system_mngr();
+	let hmd = TrackedDeviceIndex::HMD;
+	let frequency: f32 = system
+		.get_tracked_device_property(hmd, Prop::Prop_DisplayFrequency_Float)
+		.ok()?;
+	let vsync_to_photons: f32 = system
+		.get_tracked_device_property(hmd, Prop::Prop_SecondsFromVsyncToPhotons_Float)
+		.ok()?;
+	let (since_vsync, _frame_counter) = system.get_time_since_last_vsync()?;
+	if frequency <= 0. {
+		return None;
+	}
+
+	let secs = 1. / frequency - since_vsync + vsync_to_photons;
+	Some(Duration::from_secs_f32(secs.max(0.)))
+}
+
+/// How the last frame that the compositor showed went.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+	/// Counts up with every frame, to tell a new one from the last.
+	pub index: u32,
+	/// How long the GPU took to render it, for the application and the compositor.
+	pub gpu: Duration,
+	/// Whether it missed its vsync, so that the last frame was shown again or
+	/// reprojected in its place.
+	pub dropped: bool,
+}
+
+/// The last frame that the compositor showed, see [`FrameStats`].
+pub fn last_frame(context: &ovr::Context) -> Option<FrameStats> {
+	let timing = context.compositor_mngr().get_frame_timing(0)?;
+	Some(FrameStats {
+		index: timing.m_nFrameIndex,
+		gpu: Duration::from_secs_f32(timing.m_flTotalRenderGpuMs.max(0.) / 1000.),
+		dropped: timing.m_nNumDroppedFrames > 0 || timing.m_nNumMisPresented > 0,
+	})
+}
@@ -0,0 +1,98 @@
+//! Controls the bone selection with a gamepad, with `--gamepad` and the `gamepad`
+//! feature, for people who find pointing a VR controller at a bone hard.
+//!
+//! The D-pad moves the selection to the next or previous bone, the south button
+//! (A on Xbox controllers) identifies the selected bone, and the east button (B)
+//! deselects it. The right and left triggers raise and lower the skeleton by a
+//! centimeter, and the north button (Y) snaps it to the floor. Every gamepad that is
+//! connected is listened to, even while another app has focus, so this is off by
+//! default.
+
+use crate::floor::FloorCommand;
+use crate::picking::Navigate;
+
+use eyre::{eyre, Result, WrapErr};
+use gilrs::{Button, EventType, Gilrs};
+use std::sync::Arc;
+use std::thread;
+use tokio::sync::{broadcast, Notify};
+use tokio_graceful_shutdown::SubsystemHandle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+	Navigate(Navigate),
+	Identify,
+	Floor(FloorCommand),
+}
+
+fn action(button: Button) -> Option<Action> {
+	Some(match button {
+		Button::DPadRight | Button::DPadDown => Action::Navigate(Navigate::Next),
+		Button::DPadLeft | Button::DPadUp => Action::Navigate(Navigate::Previous),
+		Button::East => Action::Navigate(Navigate::Deselect),
+		Button::South => Action::Identify,
+		Button::RightTrigger => Action::Floor(FloorCommand::Raise),
+		Button::LeftTrigger => Action::Floor(FloorCommand::Lower),
+		Button::North => Action::Floor(FloorCommand::Snap),
+		_ => return None,
+	})
+}
+
+/// Listens to gamepads until shutdown, like the tray does for its menu.
+pub async fn run(
+	subsys: SubsystemHandle,
+	identify: Arc<Notify>,
+	navigate: broadcast::Sender<Navigate>,
+	floor: broadcast::Sender<FloorCommand>,
+) -> Result<()> {
+	thread::Builder::new()
+		.name("gamepad".to_string())
+		.spawn(move || {
+			if let Err(e) = gamepad_thread(&identify, &navigate, &floor) {
+				log::error!("{:?}", e.wrap_err("Gamepad input failed"));
+			}
+		})
+		.wrap_err("Failed to spawn gamepad thread")?;
+
+	subsys.on_shutdown_requested().await;
+	log::debug!("gamepad shutdown requested");
+	Ok(())
+}
+
+/// Waits for button presses. Never returns unless there was an error.
+fn gamepad_thread(
+	identify: &Notify,
+	navigate: &broadcast::Sender<Navigate>,
+	floor: &broadcast::Sender<FloorCommand>,
+) -> Result<()> {
+	// Not `Send` on every platform, so it is created on the thread that uses it.
+	let mut gilrs =
+		Gilrs::new().map_err(|e| eyre!("Failed to open the gamepads: {e}"))?;
+	for (_id, gamepad) in gilrs.gamepads() {
+		log::info!("Found gamepad {:?}", gamepad.name());
+	}
+	loop {
+		let Some(event) = gilrs.next_event_blocking(None) else {
+			continue;
+		};
+		let button = match event.event {
+			EventType::ButtonPressed(button, _) => button,
+			EventType::Connected => {
+				log::info!("Connected gamepad {:?}", gilrs.gamepad(event.id).name());
+				continue;
+			}
+			_ => continue,
+		};
+		match action(button) {
+			Some(Action::Navigate(n)) => {
+				// Only fails while there is no overlay session to move it in.
+				let _ = navigate.send(n);
+			}
+			Some(Action::Identify) => identify.notify_one(),
+			Some(Action::Floor(cmd)) => {
+				let _ = floor.send(cmd);
+			}
+			None => (),
+		}
+	}
+}
@@ -0,0 +1,437 @@
+//! A small HTTP API on localhost, so that stream decks, OBS scripts, and home
+//! automation can control the overlay without a SolarXR client. It listens on
+//! `127.0.0.1` at the `api_port` of the config:
+//!
+//! - `GET /status`: whether the skeleton and the log console are shown, whether
+//!   the skeleton is recorded, which theme it is drawn with, and who is using it.
+//! - `POST /visible/on`, `/visible/off`, and `/visible/toggle`: like the tray.
+//! - `POST /theme/<name>`: draws the skeleton with one of the `[themes]` of the
+//!   config, and `POST /theme` goes back to the style before. So does saving the
+//!   config, which applies its `[skeleton]` again.
+//! - `POST /recording/start` and `/recording/stop`: exports the skeleton to CSV,
+//!   see [`crate::csv_export`].
+//! - `POST /user/<name>`: switches to one of the `[users]` of the config, and to
+//!   their theme, see [`crate::users`]. `POST /user` switches to nobody in
+//!   particular.
+//! - `GET /metrics`: counters for Prometheus, see [`crate::metrics`].
+//! - `GET /dashboard`: a page with the tracking status for browsers, see
+//!   [`crate::dashboard`].
+//!
+//! Changes answer with the status after them, and everything but the last two is
+//! answered with JSON. Only the request line and headers
+//! are read, so requests don't need a body.
+//!
+//! Browsers send simple requests to any address, even from other sites, so that
+//! any web page could toggle the skeleton. Requests with an `Origin` header, which
+//! browsers always send cross-origin and tools like `curl` don't, are refused.
+
+use crate::component::{self, Bus};
+use crate::config::{SkeletonStyle, User};
+use crate::{dashboard, metrics};
+
+use eyre::{Result, WrapErr};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// The request line and headers of our requests are tiny, anything larger isn't
+/// meant for us.
+const MAX_REQUEST: usize = 8 * 1024;
+/// Clients are served one at a time, so one that doesn't finish its request in
+/// time is dropped, instead of holding up the others.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// What the API controls.
+pub struct Controls {
+	/// Shared with the tray, the controller binding, and `SIGUSR1`.
+	pub visible: Arc<watch::Sender<bool>>,
+	pub log_visible: watch::Receiver<bool>,
+	/// Shared with the config reload.
+	pub style: Arc<watch::Sender<SkeletonStyle>>,
+	pub themes: HashMap<String, SkeletonStyle>,
+	/// The theme of the user from `--user`, and the style from before it.
+	pub theme: Option<(String, SkeletonStyle)>,
+	/// Shared with the overlay and networking, which switch to their bone lengths
+	/// and server.
+	pub user: Arc<watch::Sender<Option<String>>>,
+	pub users: HashMap<String, User>,
+	/// Who the overlay thinks put on the headset.
+	pub suggested_user: watch::Receiver<Option<String>>,
+	/// Starts and stops the CSV export, with [`component::Event::Recording`].
+	pub bus: Bus,
+	/// Whether `--export-csv` records from the start.
+	pub recording: bool,
+	pub dashboard: watch::Receiver<dashboard::State>,
+	pub metrics: watch::Receiver<metrics::Counters>,
+}
+
+struct Api {
+	controls: Controls,
+	/// The theme that was picked last, and the style from before the first pick.
+	theme: Option<(String, SkeletonStyle)>,
+}
+impl Api {
+	/// Answers a request for `target` with a status code and a body.
+	fn handle(&mut self, method: &str, target: &str) -> (u16, Value) {
+		let path = target.split('?').next().unwrap_or(target);
+		let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+		let c = &mut self.controls;
+		match (method, segments.as_slice()) {
+			("GET", ["status"]) => (),
+			("POST", ["visible", action]) => {
+				let visible = match *action {
+					"on" => true,
+					"off" => false,
+					"toggle" => !*c.visible.borrow(),
+					_ => return error(404, "Use `on`, `off`, or `toggle`"),
+				};
+				log::info!("Setting visibility from the HTTP API: {visible}");
+				c.visible.send_replace(visible);
+			}
+			("POST", ["theme"]) => self.leave_theme(),
+			("POST", ["theme", name]) => {
+				if !c.themes.contains_key(*name) {
+					return error(404, &format!("There is no theme named {name:?}"));
+				}
+				self.pick_theme(name);
+			}
+			("POST", ["recording", action @ ("start" | "stop")]) => {
+				let recording = *action == "start";
+				log::info!("Setting recording from the HTTP API: {recording}");
+				// Only fails while there is no CSV export to start, which `main`
+				// always adds along with the API.
+				let _ = c.bus.send(component::Event::Recording(recording));
+				c.recording = recording;
+			}
+			("POST", ["user"]) => self.switch_user(None),
+			("POST", ["user", name]) => {
+				if !c.users.contains_key(*name) {
+					return error(404, &format!("There is no user named {name:?}"));
+				}
+				self.switch_user(Some(name));
+			}
+			(_, ["metrics" | "dashboard", ..]) => return error(405, "Use `GET`"),
+			(_, ["status" | "visible" | "theme" | "recording" | "user", ..]) => {
+				return error(405, "Use `GET /status`, and `POST` for everything else")
+			}
+			_ => return error(404, "Not found, see `src/http_api.rs` for the API"),
+		}
+		(200, self.status())
+	}
+
+	/// The content type and body of the page at `target`, for the requests that
+	/// aren't answered with JSON.
+	fn page(&self, target: &str) -> Option<(&'static str, String)> {
+		let path = target.split('?').next().unwrap_or(target);
+		let now = SystemTime::now();
+		match path.trim_end_matches('/') {
+			"/metrics" => Some((
+				"text/plain; version=0.0.4",
+				self.controls.metrics.borrow().render(now),
+			)),
+			"/dashboard" => Some((
+				"text/html; charset=utf-8",
+				self.controls.dashboard.borrow().render(now),
+			)),
+			_ => None,
+		}
+	}
+
+	fn pick_theme(&mut self, name: &str) {
+		let c = &mut self.controls;
+		log::info!("Switching to theme {name:?} from the HTTP API");
+		let before = c.style.send_replace(c.themes[name].clone());
+		let before = match self.theme.take() {
+			Some((_, first)) => first,
+			None => before,
+		};
+		self.theme = Some((name.to_string(), before));
+	}
+
+	fn leave_theme(&mut self) {
+		if let Some((name, before)) = self.theme.take() {
+			log::info!("Leaving theme {name:?} from the HTTP API");
+			self.controls.style.send_replace(before);
+		}
+	}
+
+	/// Switches to the user named `name`, and to their theme or out of the last
+	/// one's.
+	fn switch_user(&mut self, name: Option<&str>) {
+		let c = &mut self.controls;
+		log::info!("Switching to user {name:?} from the HTTP API");
+		c.user.send_replace(name.map(str::to_owned));
+		let theme = name.and_then(|n| c.users[n].theme.clone());
+		match theme {
+			Some(theme) => self.pick_theme(&theme),
+			None => self.leave_theme(),
+		}
+	}
+
+	fn status(&self) -> Value {
+		let c = &self.controls;
+		// Saving the config replaces the theme with its `[skeleton]`.
+		let theme = self
+			.theme
+			.as_ref()
+			.map(|(name, _)| name)
+			.filter(|name| c.themes.get(*name) == Some(&*c.style.borrow()));
+		let mut themes: Vec<&String> = c.themes.keys().collect();
+		themes.sort();
+		let mut users: Vec<&String> = c.users.keys().collect();
+		users.sort();
+		json!({
+			"version": crate::GIT_VERSION,
+			"visible": *c.visible.borrow(),
+			"log_console": *c.log_visible.borrow(),
+			"recording": c.recording,
+			"theme": theme,
+			"themes": themes,
+			"user": *c.user.borrow(),
+			"users": users,
+			"suggested_user": *c.suggested_user.borrow(),
+		})
+	}
+
+	async fn serve(&mut self, mut stream: TcpStream) -> Result<()> {
+		let mut head = Vec::new();
+		while !head.ends_with(b"\r\n\r\n") {
+			if head.len() >= MAX_REQUEST {
+				return respond(&mut stream, error(400, "Request too large")).await;
+			}
+			let mut byte = [0];
+			let read = stream
+				.read(&mut byte)
+				.await
+				.wrap_err("Failed to read HTTP request")?;
+			if read == 0 {
+				return Ok(());
+			}
+			head.push(byte[0]);
+		}
+		let head = String::from_utf8_lossy(&head);
+		let response = match parse_request(&head) {
+			None => error(400, "Not an HTTP request"),
+			Some(_) if has_origin(&head) => error(403, "Browsers may not use the API"),
+			Some(("GET", target)) => match self.page(target) {
+				Some((content_type, body)) => {
+					return send(&mut stream, 200, content_type, &body).await
+				}
+				None => self.handle("GET", target),
+			},
+			Some((method, target)) => self.handle(method, target),
+		};
+		respond(&mut stream, response).await
+	}
+}
+
+/// Serves the API on `port` until shutdown.
+pub async fn run(
+	subsys: SubsystemHandle,
+	port: u16,
+	mut controls: Controls,
+) -> Result<()> {
+	let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+		.await
+		.wrap_err_with(|| {
+			format!("Failed to listen for the HTTP API on port {port}")
+		})?;
+	log::info!("HTTP API listening on http://127.0.0.1:{port}");
+	let theme = controls.theme.take();
+	let mut api = Api { controls, theme };
+	loop {
+		let stream = tokio::select! {
+			_ = subsys.on_shutdown_requested() => {
+				log::debug!("HTTP API shutdown requested");
+				return Ok(());
+			}
+			accepted = listener.accept() => match accepted {
+				Ok((stream, _)) => stream,
+				Err(e) => {
+					log::warn!("Failed to accept HTTP API connection: {e}");
+					continue;
+				}
+			},
+		};
+		match tokio::time::timeout(REQUEST_TIMEOUT, api.serve(stream)).await {
+			Ok(Ok(())) => (),
+			Ok(Err(e)) => log::warn!("{e:?}"),
+			Err(_) => log::debug!("HTTP API client took too long, dropped it"),
+		}
+	}
+}
+
+/// The method and target of the request line, like `POST` and `/visible/on`.
+fn parse_request(head: &str) -> Option<(&str, &str)> {
+	let mut parts = head.lines().next()?.split(' ');
+	let method = parts.next()?;
+	let target = parts.next()?;
+	let version = parts.next()?;
+	version.starts_with("HTTP/").then_some((method, target))
+}
+
+fn has_origin(head: &str) -> bool {
+	head.lines().skip(1).any(|line| {
+		let name = line.split(':').next().unwrap_or_default();
+		name.trim().eq_ignore_ascii_case("origin")
+	})
+}
+
+fn error(status: u16, message: &str) -> (u16, Value) {
+	(status, json!({ "error": message }))
+}
+
+async fn respond(stream: &mut TcpStream, (status, body): (u16, Value)) -> Result<()> {
+	send(stream, status, "application/json", &body.to_string()).await
+}
+
+async fn send(
+	stream: &mut TcpStream,
+	status: u16,
+	content_type: &str,
+	body: &str,
+) -> Result<()> {
+	let reason = match status {
+		200 => "OK",
+		400 => "Bad Request",
+		403 => "Forbidden",
+		404 => "Not Found",
+		405 => "Method Not Allowed",
+		_ => "Error",
+	};
+	let response = format!(
+		"HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\n\
+		 Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+		body.len()
+	);
+	stream
+		.write_all(response.as_bytes())
+		.await
+		.wrap_err("Failed to send HTTP response")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn api() -> Api {
+		let themes = HashMap::from([(
+			"stream".to_owned(),
+			SkeletonStyle {
+				radius: 0.01,
+				..SkeletonStyle::default()
+			},
+		)]);
+		Api {
+			controls: Controls {
+				visible: Arc::new(watch::channel(true).0),
+				log_visible: watch::channel(false).1,
+				style: Arc::new(watch::channel(SkeletonStyle::default()).0),
+				themes,
+				theme: None,
+				user: Arc::new(watch::channel(None).0),
+				users: HashMap::from([
+					(
+						"alex".to_owned(),
+						User {
+							theme: Some("stream".to_owned()),
+							..User::default()
+						},
+					),
+					("sam".to_owned(), User::default()),
+				]),
+				suggested_user: watch::channel(None).1,
+				bus: component::bus(),
+				recording: false,
+				dashboard: dashboard::Dashboard::new(false).1,
+				metrics: metrics::Metrics::new(false).1,
+			},
+			theme: None,
+		}
+	}
+
+	#[test]
+	fn request_lines() {
+		let head = "POST /visible/on HTTP/1.1\r\nHost: localhost\r\n\r\n";
+		assert_eq!(parse_request(head), Some(("POST", "/visible/on")));
+		assert!(!has_origin(head));
+		assert!(has_origin(
+			"GET / HTTP/1.1\r\norigin: https://example.com\r\n"
+		));
+		assert_eq!(parse_request("hello\r\n\r\n"), None);
+	}
+
+	#[test]
+	fn visibility() {
+		let mut api = api();
+		let (status, body) = api.handle("POST", "/visible/toggle");
+		assert_eq!(status, 200);
+		assert_eq!(body["visible"], false);
+		assert!(!*api.controls.visible.borrow());
+		assert_eq!(api.handle("GET", "/visible/on").0, 405);
+		assert_eq!(api.handle("POST", "/visible/maybe").0, 404);
+	}
+
+	#[test]
+	fn themes() {
+		let mut api = api();
+		assert_eq!(api.handle("POST", "/theme/nope").0, 404);
+		let (_, body) = api.handle("POST", "/theme/stream");
+		assert_eq!(body["theme"], "stream");
+		assert_eq!(api.controls.style.borrow().radius, 0.01);
+
+		// Picking it again still goes back to the style from before.
+		api.handle("POST", "/theme/stream");
+		let (_, body) = api.handle("POST", "/theme");
+		assert_eq!(body["theme"], Value::Null);
+		assert_eq!(*api.controls.style.borrow(), SkeletonStyle::default());
+	}
+
+	#[test]
+	fn users() {
+		let mut api = api();
+		assert_eq!(api.handle("POST", "/user/kim").0, 404);
+		let (_, body) = api.handle("POST", "/user/alex");
+		assert_eq!(body["user"], "alex");
+		assert_eq!(body["theme"], "stream");
+		assert_eq!(body["users"], json!(["alex", "sam"]));
+
+		// Sam has no theme, so it goes back to the style from before Alex's.
+		let (_, body) = api.handle("POST", "/user/sam");
+		assert_eq!(body["user"], "sam");
+		assert_eq!(body["theme"], Value::Null);
+		assert_eq!(*api.controls.style.borrow(), SkeletonStyle::default());
+		let (_, body) = api.handle("POST", "/user");
+		assert_eq!(body["user"], Value::Null);
+	}
+
+	#[test]
+	fn recording() {
+		let mut api = api();
+		let mut events = api.controls.bus.subscribe();
+		let (_, body) = api.handle("POST", "/recording/start");
+		assert_eq!(body["recording"], true);
+		assert!(matches!(
+			events.try_recv(),
+			Ok(component::Event::Recording(true))
+		));
+	}
+
+	#[test]
+	fn pages() {
+		let api = api();
+		let (content_type, body) = api.page("/metrics").unwrap();
+		assert!(content_type.starts_with("text/plain"));
+		assert!(body.contains("slimevr_overlay_feed_updates_total 0\n"));
+		let (content_type, body) = api.page("/dashboard/?refresh").unwrap();
+		assert!(content_type.starts_with("text/html"));
+		assert!(body.contains("<h1>Not tracking</h1>"));
+		assert_eq!(api.page("/status"), None);
+	}
+}
@@ -0,0 +1,44 @@
+//! Makes the selected bone blink for a while, so that the user can tell which
+//! physical tracker drives it. Triggered from the tray, or with `SIGUSR2` on Linux.
+//!
+//! Ideally the tracker itself would blink or vibrate at the same time, but SolarXR
+//! has no request for that yet, so only the bone does.
+
+use crate::model::skeleton::Skeleton;
+use crate::model::BoneKind;
+
+use std::time::{Duration, Instant};
+
+/// How long a bone keeps blinking.
+const DURATION: Duration = Duration::from_secs(5);
+/// How long one on and off cycle takes.
+const BLINK_PERIOD: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Default)]
+pub struct Identifier {
+	active: Option<(BoneKind, Instant)>,
+}
+impl Identifier {
+	/// Starts blinking `bone`, replacing whichever bone was blinking before.
+	pub fn start(&mut self, bone: BoneKind, now: Instant) {
+		log::info!("Identifying {bone:?}");
+		self.active = Some((bone, now));
+	}
+
+	/// Hides the blinking bone while it is in the off part of a cycle. Call after
+	/// the visibility of the bones has been decided.
+	pub fn update(&mut self, skeleton: &mut Skeleton, now: Instant) {
+		let Some((bone, since)) = self.active else {
+			return;
+		};
+		let elapsed = now - since;
+		if elapsed >= DURATION {
+			self.active = None;
+			return;
+		}
+		let phase = elapsed.as_millis() % BLINK_PERIOD.as_millis();
+		if phase >= BLINK_PERIOD.as_millis() / 2 {
+			skeleton.set_visibility(bone, false);
+		}
+	}
+}
@@ -0,0 +1,121 @@
+//! Shows and hides the skeleton with a controller binding, through SteamVR Input,
+//! along with the quick actions for the selected bone, see
+//! [`crate::model::ContextPanel`]. There are no default bindings, since any button
+//! that we picked would also be one that some game uses. They are bound under
+//! "Manage Controller Bindings" in the settings of SteamVR instead. The controllers
+//! vibrate briefly when a bone gets selected or deselected.
+
+use eyre::{eyre, Result, WrapErr};
+use ovr::input::{ActionHandle, ActionSetHandle, InputValueHandle};
+use ovr::sys::VRActiveActionSet_t;
+use ovr_overlay as ovr;
+use std::path::PathBuf;
+
+/// Lists the actions, SteamVR reads it from disk.
+const MANIFEST: &str = include_str!("../assets/actions.json");
+const ACTION_SET: &str = "/actions/overlay";
+const TOGGLE_ACTION: &str = "/actions/overlay/in/toggle_skeleton";
+const HIDE_ACTION: &str = "/actions/overlay/in/hide_selected";
+const RECOLOR_ACTION: &str = "/actions/overlay/in/recolor_selected";
+const IDENTIFY_ACTION: &str = "/actions/overlay/in/identify_selected";
+const HAPTIC_ACTION: &str = "/actions/overlay/out/haptic";
+/// How long the controllers vibrate for, in seconds.
+const PULSE_DURATION: f32 = 0.05;
+const PULSE_FREQUENCY: f32 = 160.;
+const PULSE_AMPLITUDE: f32 = 0.5;
+
+/// Which actions were pressed since the last poll.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Pressed {
+	pub toggle: bool,
+	pub hide: bool,
+	pub recolor: bool,
+	pub identify: bool,
+}
+
+pub struct ControllerInput {
+	action_set: ActionSetHandle,
+	toggle: ActionHandle,
+	hide: ActionHandle,
+	recolor: ActionHandle,
+	identify: ActionHandle,
+	haptic: ActionHandle,
+}
+impl ControllerInput {
+	/// Registers the actions with SteamVR.
+	pub fn new(context: &ovr::Context) -> Result<Self> {
+		// SteamVR wants an absolute path, and the working directory might not be
+		// where the assets are.
+		let path = manifest_path();
+		std::fs::write(&path, MANIFEST)
+			.wrap_err_with(|| format!("Failed to write {path:?}"))?;
+
+		let mut input = context.input_mngr();
+		input
+			.set_action_manifest(&path)
+			.map_err(|e| eyre!("Failed to set the action manifest: {e:?}"))?;
+		let action_set = input
+			.get_action_set_handle(ACTION_SET)
+			.map_err(|e| eyre!("Failed to get the action set: {e:?}"))?;
+		let mut action = |name| {
+			input
+				.get_action_handle(name)
+				.map_err(|e| eyre!("Failed to get the action {name}: {e:?}"))
+		};
+		Ok(Self {
+			action_set,
+			toggle: action(TOGGLE_ACTION)?,
+			hide: action(HIDE_ACTION)?,
+			recolor: action(RECOLOR_ACTION)?,
+			identify: action(IDENTIFY_ACTION)?,
+			haptic: action(HAPTIC_ACTION)?,
+		})
+	}
+
+	/// Updates the state of the actions, and returns which were pressed since the
+	/// last poll.
+	pub fn poll(&mut self, context: &ovr::Context) -> Pressed {
+		let mut input = context.input_mngr();
+		let mut sets = [VRActiveActionSet_t {
+			ulActionSet: self.action_set.0,
+			ulRestrictedToDevice: InputValueHandle::default().0,
+			ulSecondaryActionSet: 0,
+			unPadding: 0,
+			nPriority: 0,
+		}];
+		if let Err(e) = input.update_actions(&mut sets) {
+			log::trace!("Failed to update the actions: {e:?}");
+			return Pressed::default();
+		}
+		let mut pressed = |action| {
+			input
+				.get_digital_action_data(action, InputValueHandle::default())
+				.map_or(false, |data| data.bChanged && data.bState)
+		};
+		Pressed {
+			toggle: pressed(self.toggle),
+			hide: pressed(self.hide),
+			recolor: pressed(self.recolor),
+			identify: pressed(self.identify),
+		}
+	}
+
+	/// Vibrates the controllers briefly, as feedback for the selection.
+	pub fn vibrate(&mut self, context: &ovr::Context) {
+		let result = context.input_mngr().trigger_haptic_vibration_action(
+			self.haptic,
+			0.,
+			PULSE_DURATION,
+			PULSE_FREQUENCY,
+			PULSE_AMPLITUDE,
+			InputValueHandle::default(),
+		);
+		if let Err(e) = result {
+			log::trace!("Failed to vibrate the controllers: {e:?}");
+		}
+	}
+}
+
+fn manifest_path() -> PathBuf {
+	std::env::temp_dir().join("slimevr_overlay_actions.json")
+}
@@ -0,0 +1,151 @@
+//! Smooths the skeleton between feed updates. The server sends them at its own
+//! rate, which is usually below the refresh rate of the headset, so drawing each
+//! update as it comes looks jittery. Instead, every frame is drawn somewhere between
+//! the last two updates of each bone, a little behind the feed.
+//!
+//! How far behind is the `smoothing`, in units of the time between those two
+//! updates. At `1.0`, the skeleton reaches the last update right as the next one is
+//! due, which is smooth but one update late. Lower values are closer to the feed,
+//! and keep going past the last update in the direction the bone was moving, for at
+//! most the extrapolation cap.
+
+use crate::model::{BoneKind, BoneMap, Isometry};
+
+use nalgebra::{Translation3, UnitQuaternion};
+use std::time::{Duration, Instant};
+
+/// Updates further apart than this are a gap in the feed, not motion to smooth out,
+/// so the bone jumps to the last one instead.
+const MAX_GAP: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+	time: Instant,
+	iso: Isometry,
+}
+
+/// Keeps the last two updates of each bone, to draw the frames between them.
+#[derive(Debug)]
+pub struct Interpolator {
+	smoothing: f32,
+	max_extrapolation: Duration,
+	/// Older first.
+	samples: BoneMap<(Option<Sample>, Option<Sample>)>,
+}
+impl Interpolator {
+	pub fn new(smoothing: f32, max_extrapolation: Duration) -> Self {
+		Self {
+			smoothing,
+			max_extrapolation,
+			samples: BoneMap::default(),
+		}
+	}
+
+	/// Records where `bone` was according to the update received at `time`. The loop
+	/// also runs without new updates, so ones that aren't newer are ignored.
+	pub fn update(&mut self, bone: BoneKind, iso: Isometry, time: Instant) {
+		let (prev, last) = &mut self.samples[bone];
+		if last.map_or(false, |l| l.time >= time) {
+			return;
+		}
+		*prev = *last;
+		*last = Some(Sample { time, iso });
+	}
+
+	/// Where to draw `bone` in the frame that is shown at `at`, or `None` before its
+	/// first update.
+	pub fn pose(&self, bone: BoneKind, at: Instant) -> Option<Isometry> {
+		let (prev, last) = self.samples[bone];
+		let last = last?;
+		let Some(prev) = prev else {
+			return Some(last.iso);
+		};
+		let interval = last.time - prev.time;
+		if interval > MAX_GAP {
+			return Some(last.iso);
+		}
+		let interval = interval.as_secs_f32();
+		let behind = self.smoothing * interval;
+		let max = 1. + self.max_extrapolation.as_secs_f32() / interval;
+		let t = ((signed_secs(at, prev.time) - behind) / interval).clamp(0., max);
+		Some(between(&prev.iso, &last.iso, t))
+	}
+}
+
+/// `a - b` in seconds, which is negative if `a` is earlier.
+fn signed_secs(a: Instant, b: Instant) -> f32 {
+	match a.checked_duration_since(b) {
+		Some(d) => d.as_secs_f32(),
+		None => -(b - a).as_secs_f32(),
+	}
+}
+
+/// The isometry `t` of the way from `a` to `b`. Keeps going past `b` for `t` above
+/// one, which slerping doesn't.
+fn between(a: &Isometry, b: &Isometry, t: f32) -> Isometry {
+	let translation = a.translation.vector.lerp(&b.translation.vector, t);
+	let delta = b.rotation * a.rotation.inverse();
+	let rotation =
+		UnitQuaternion::from_scaled_axis(delta.scaled_axis() * t) * a.rotation;
+	Isometry::from_parts(Translation3::from(translation), rotation)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::Vector3;
+
+	fn at_height(y: f32) -> Isometry {
+		Isometry::from_parts(Translation3::new(0., y, 0.), UnitQuaternion::identity())
+	}
+
+	fn height(interpolator: &Interpolator, at: Instant) -> f32 {
+		let pose = interpolator.pose(BoneKind::Chest, at).unwrap();
+		pose.translation.vector.y
+	}
+
+	/// Two updates 10 ms apart, moving the chest up by 1 m.
+	fn moving(smoothing: f32, max_extrapolation_ms: u64) -> (Interpolator, Instant) {
+		let max_extrapolation = Duration::from_millis(max_extrapolation_ms);
+		let mut interpolator = Interpolator::new(smoothing, max_extrapolation);
+		let start = Instant::now();
+		let last = start + Duration::from_millis(10);
+		interpolator.update(BoneKind::Chest, at_height(0.), start);
+		interpolator.update(BoneKind::Chest, at_height(1.), last);
+		(interpolator, last)
+	}
+
+	#[test]
+	fn smoothing_draws_behind_the_feed() {
+		let (interpolator, last) = moving(1., 0);
+		assert!(height(&interpolator, last).abs() < 1e-5);
+		let halfway = last + Duration::from_millis(5);
+		assert!((height(&interpolator, halfway) - 0.5).abs() < 1e-5);
+		let later = last + Duration::from_secs(1);
+		assert!((height(&interpolator, later) - 1.).abs() < 1e-5);
+	}
+
+	#[test]
+	fn extrapolation_is_capped() {
+		let (interpolator, last) = moving(0., 5);
+		let at = last + Duration::from_secs(1);
+		// 5 ms past the last update, at 100 m/s.
+		assert!((height(&interpolator, at) - 1.5).abs() < 1e-4);
+	}
+
+	#[test]
+	fn old_updates_are_ignored() {
+		let (mut interpolator, last) = moving(1., 0);
+		interpolator.update(BoneKind::Chest, at_height(5.), last);
+		assert!(height(&interpolator, last).abs() < 1e-5);
+	}
+
+	#[test]
+	fn rotations_keep_turning_past_the_last_update() {
+		let a = Isometry::identity();
+		let turn = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), 0.1);
+		let b = Isometry::from_parts(Translation3::identity(), turn);
+		let angle = between(&a, &b, 2.).rotation.angle();
+		assert!((angle - 0.2).abs() < 1e-5);
+	}
+}
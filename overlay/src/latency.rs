@@ -0,0 +1,211 @@
+//! Measures how long bone updates take to get to the headset: from when a feed
+//! update arrived, to when the overlays it moved were submitted to SteamVR, and to
+//! when the frame that shows them is displayed, as far as OpenVR's frame timing
+//! tells. The percentiles are logged every so often.
+//!
+//! Without interpolation, a bone is drawn at its last update, which was already old
+//! when the loop got to it, and gets older until it is on the display. The median of
+//! that is how far ahead to extrapolate to draw the bone where it is by then. With
+//! interpolation, the bone is drawn at the time the loop started, so only the time
+//! from there until submitting needs making up for.
+
+use crate::config;
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How many feed updates the percentiles are over.
+const SAMPLES: usize = 512;
+/// How many feed updates to measure before compensating for them.
+const MIN_SAMPLES: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+	/// From the feed update arriving to the loop starting on it.
+	queued: Duration,
+	/// From the loop starting, to the overlays being submitted.
+	submitting: Duration,
+	/// From the overlays being submitted, to the frame showing them.
+	to_photons: Option<Duration>,
+}
+
+#[derive(Debug)]
+pub struct LatencyMeter {
+	compensate: bool,
+	max_compensation: Duration,
+	log_interval: Option<Duration>,
+	samples: VecDeque<Sample>,
+	/// When the last feed update that was measured arrived.
+	last_received: Option<Instant>,
+	last_log: Option<Instant>,
+}
+impl LatencyMeter {
+	pub fn new(config: &config::Latency) -> Self {
+		Self {
+			compensate: config.compensate,
+			max_compensation: Duration::from_millis(config.max_compensation_ms),
+			log_interval: (config.log_interval_s > 0)
+				.then(|| Duration::from_secs(config.log_interval_s)),
+			samples: VecDeque::with_capacity(SAMPLES),
+			last_received: None,
+			last_log: None,
+		}
+	}
+
+	/// Records that the feed update that arrived at `received` was submitted at
+	/// `submitted`, by the loop that started at `started`. `to_photons` is how long
+	/// after `submitted` it is displayed. Only the first time each update is
+	/// submitted counts, as the loop also runs without new updates.
+	pub fn record(
+		&mut self,
+		received: Instant,
+		started: Instant,
+		submitted: Instant,
+		to_photons: Option<Duration>,
+	) {
+		if self.last_received.map_or(false, |last| received <= last) {
+			return;
+		}
+		self.last_received = Some(received);
+		if self.samples.len() == SAMPLES {
+			self.samples.pop_front();
+		}
+		self.samples.push_back(Sample {
+			queued: started.saturating_duration_since(received),
+			submitting: submitted.saturating_duration_since(started),
+			to_photons,
+		});
+	}
+
+	/// How much further ahead to draw, on top of the configured prediction. Without
+	/// interpolation, that includes how long updates wait for the loop.
+	pub fn compensation(&self, interpolating: bool) -> Duration {
+		if !self.compensate || self.samples.len() < MIN_SAMPLES {
+			return Duration::ZERO;
+		}
+		let latency = if interpolating {
+			self.percentile(0.5, |s| s.submitting)
+		} else {
+			self.percentile(0.5, |s| s.queued + s.submitting)
+		};
+		latency.unwrap_or_default().min(self.max_compensation)
+	}
+
+	/// How long the median update takes until it is displayed, or until it is
+	/// submitted if OpenVR doesn't tell. `None` until enough updates were measured.
+	pub fn median(&self) -> Option<Duration> {
+		if self.samples.len() < MIN_SAMPLES {
+			return None;
+		}
+		let displayed =
+			|s: &Sample| s.queued + s.submitting + s.to_photons.unwrap_or_default();
+		self.percentile(0.5, displayed)
+	}
+
+	/// Logs the percentiles once every log interval.
+	pub fn log(&mut self, now: Instant) {
+		let Some(interval) = self.log_interval else { return };
+		let last_log = *self.last_log.get_or_insert(now);
+		if now - last_log < interval || self.samples.is_empty() {
+			return;
+		}
+		self.last_log = Some(now);
+		let submitted = |s: &Sample| s.queued + s.submitting;
+		log::info!(
+			"Latency over the last {} updates, until submitted: {}",
+			self.samples.len(),
+			self.percentiles(submitted)
+		);
+		if self.samples.iter().any(|s| s.to_photons.is_some()) {
+			let displayed =
+				|s: &Sample| submitted(s) + s.to_photons.unwrap_or_default();
+			log::info!("Until displayed: {}", self.percentiles(displayed));
+		}
+	}
+
+	fn percentiles(&self, f: impl Fn(&Sample) -> Duration + Copy) -> String {
+		let ms = |p| self.percentile(p, f).unwrap_or_default().as_secs_f32() * 1000.;
+		format!(
+			"p50 {:.1} ms, p90 {:.1} ms, p99 {:.1} ms",
+			ms(0.5),
+			ms(0.9),
+			ms(0.99)
+		)
+	}
+
+	/// The `p`th quantile of `f` over the samples, from 0 to 1.
+	fn percentile(&self, p: f32, f: impl Fn(&Sample) -> Duration) -> Option<Duration> {
+		let mut values: Vec<Duration> = self.samples.iter().map(f).collect();
+		if values.is_empty() {
+			return None;
+		}
+		values.sort_unstable();
+		let i = (p * (values.len() - 1) as f32).round() as usize;
+		Some(values[i.min(values.len() - 1)])
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn meter() -> LatencyMeter {
+		LatencyMeter::new(&config::Latency {
+			compensate: true,
+			..Default::default()
+		})
+	}
+
+	/// Records `count` updates that waited `queued_ms` and took `submitting_ms`.
+	fn record(
+		meter: &mut LatencyMeter,
+		count: u64,
+		queued_ms: u64,
+		submitting_ms: u64,
+	) {
+		let start = meter.last_received.unwrap_or_else(Instant::now);
+		for i in 1..=count {
+			let received = start + Duration::from_millis(i * 10);
+			let started = received + Duration::from_millis(queued_ms);
+			let submitted = started + Duration::from_millis(submitting_ms);
+			meter.record(received, started, submitted, None);
+		}
+	}
+
+	#[test]
+	fn compensates_with_the_median() {
+		let mut meter = meter();
+		record(&mut meter, 10, 4, 2);
+		// Not enough to go by yet.
+		assert_eq!(meter.compensation(false), Duration::ZERO);
+		record(&mut meter, 40, 4, 2);
+		record(&mut meter, 10, 30, 30);
+		assert_eq!(meter.compensation(false), Duration::from_millis(6));
+		assert_eq!(meter.compensation(true), Duration::from_millis(2));
+	}
+
+	#[test]
+	fn counts_each_update_once() {
+		let mut meter = meter();
+		let received = Instant::now();
+		let later = received + Duration::from_millis(5);
+		meter.record(received, received, received, None);
+		meter.record(received, later, later, None);
+		assert_eq!(meter.samples.len(), 1);
+		assert_eq!(meter.percentile(0.5, |s| s.queued), Some(Duration::ZERO));
+	}
+
+	#[test]
+	fn caps_the_compensation() {
+		let mut meter = meter();
+		record(&mut meter, 100, 200, 0);
+		assert_eq!(
+			meter.compensation(false),
+			Duration::from_millis(config::Latency::default().max_compensation_ms)
+		);
+
+		let mut disabled = LatencyMeter::new(&config::Latency::default());
+		record(&mut disabled, 100, 5, 0);
+		assert_eq!(disabled.compensation(false), Duration::ZERO);
+	}
+}
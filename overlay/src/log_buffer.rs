@@ -0,0 +1,116 @@
+//! Keeps the last lines that were logged, so that the log console can show them in
+//! the headset. Without it, users that stream to a standalone headset have no way to
+//! see errors like failing to connect to the server.
+
+use log::{Level, Record};
+use log4rs::append::Append;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// How many lines are kept, which is more than the console ever shows.
+const CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+	pub level: Level,
+	pub text: String,
+}
+
+#[derive(Debug, Default)]
+struct Lines {
+	/// Oldest first.
+	lines: VecDeque<LogLine>,
+	/// Counts the lines that were ever pushed, so that readers can tell whether
+	/// anything changed since they last looked.
+	generation: u64,
+}
+
+/// The last [`CAPACITY`] lines. Clones share the same lines.
+#[derive(Debug, Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<Lines>>);
+impl LogBuffer {
+	/// Messages that span several lines, like error reports, are split up.
+	pub fn push(&self, level: Level, message: &str) {
+		let mut lines = self.0.lock().unwrap();
+		for text in message.lines().filter(|l| !l.trim().is_empty()) {
+			if lines.lines.len() == CAPACITY {
+				lines.lines.pop_front();
+			}
+			lines.lines.push_back(LogLine {
+				level,
+				text: text.to_owned(),
+			});
+			lines.generation += 1;
+		}
+	}
+
+	pub fn generation(&self) -> u64 {
+		self.0.lock().unwrap().generation
+	}
+
+	/// The newest `count` lines that are at least as severe as `level`, oldest
+	/// first.
+	pub fn last(&self, count: usize, level: Level) -> Vec<LogLine> {
+		let lines = self.0.lock().unwrap();
+		let mut last: Vec<LogLine> = lines
+			.lines
+			.iter()
+			.rev()
+			.filter(|l| l.level <= level)
+			.take(count)
+			.cloned()
+			.collect();
+		last.reverse();
+		last
+	}
+}
+
+/// Feeds everything that log4rs logs into a [`LogBuffer`].
+#[derive(Debug)]
+pub struct BufferAppender(pub LogBuffer);
+impl Append for BufferAppender {
+	fn append(&self, record: &Record) -> anyhow::Result<()> {
+		self.0.push(record.level(), &record.args().to_string());
+		Ok(())
+	}
+
+	fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn keeps_the_newest_lines() {
+		let buffer = LogBuffer::default();
+		for i in 0..CAPACITY + 5 {
+			buffer.push(Level::Info, &format!("line {i}"));
+		}
+		let last = buffer.last(CAPACITY * 2, Level::Info);
+		assert_eq!(last.len(), CAPACITY);
+		assert_eq!(last[0].text, "line 5");
+		assert_eq!(buffer.generation(), (CAPACITY + 5) as u64);
+	}
+
+	#[test]
+	fn filters_by_severity() {
+		let buffer = LogBuffer::default();
+		buffer.push(Level::Error, "Failed to connect\n\nCaused by: refused");
+		buffer.push(Level::Info, "Connected");
+		buffer.push(Level::Warn, "No position");
+		let texts = |level| {
+			buffer
+				.last(2, level)
+				.into_iter()
+				.map(|l| l.text)
+				.collect::<Vec<_>>()
+		};
+		assert_eq!(texts(Level::Warn), ["Caused by: refused", "No position"]);
+		assert_eq!(
+			texts(Level::Error),
+			["Failed to connect", "Caused by: refused"]
+		);
+		assert_eq!(texts(Level::Info), ["Connected", "No position"]);
+	}
+}
@@ -1,12 +1,91 @@
+mod alerts;
+mod anomalies;
+mod app_detection;
 mod color;
+mod component;
+mod config;
+mod crash_report;
+mod csv_export;
+mod dashboard;
+mod deadband;
+mod debug_bundle;
+mod demo;
+mod diagnostics;
+mod driver_detection;
+mod extrapolation;
+mod floor;
+mod frame_timing;
+#[cfg(feature = "gamepad")]
+mod gamepad;
+mod http_api;
+mod identify;
+mod input;
+mod interpolation;
+mod latency;
+mod log_buffer;
+mod metrics;
 mod model;
+mod neck;
+mod osc;
+mod picking;
+mod platform;
+mod presence;
+mod quality;
+mod raw_stream;
+mod recording;
+mod replay;
+#[cfg(feature = "scene")]
+mod scene;
+mod scripting;
+mod smoothing;
+mod standby;
+mod timebase;
+#[cfg(any(target_os = "windows", target_os = "linux"))]
+mod tray;
+mod update_rate;
+mod users;
+mod watchdog;
 
 pub use self::color::RGBA;
 
-use crate::model::skeleton::SkeletonBuilder;
-use crate::model::{BoneKind, Isometry};
+use crate::anomalies::AnomalyDetector;
+use crate::app_detection::AppDetector;
+use crate::component::{Bus, Component, Publisher};
+use crate::config::{
+	Config, Profile, ReplayCamera, SkeletonStyle, Smoothing, DEFAULT_CONFIG_PATH,
+};
+use crate::csv_export::CsvExport;
+use crate::dashboard::Dashboard;
+use crate::deadband::Deadband;
+use crate::driver_detection::{DriverDetector, DriverStatus};
+use crate::extrapolation::Extrapolator;
+use crate::floor::FloorCommand;
+use crate::identify::Identifier;
+use crate::input::{ControllerInput, Pressed};
+use crate::interpolation::Interpolator;
+use crate::latency::LatencyMeter;
+use crate::log_buffer::{BufferAppender, LogBuffer};
+use crate::metrics::Metrics;
+use crate::model::skeleton::{default_color, Skeleton, SkeletonBuilder};
+use crate::model::{
+	budget, hmd_forward, hmd_position, BoneDetails, BoneKind, ContextPanel, Isometry,
+	LogPanel, MiniSkeleton, RawPlot, Reach, TrackerMarkers, TrackerPose, TrackerState,
+	Trails, WarningPanel,
+};
+use crate::osc::OscOutput;
+use crate::picking::{Navigate, Picker};
+use crate::presence::Presence;
+use crate::quality::{Quality, QualityGovernor};
+use crate::raw_stream::RawSample;
+use crate::scripting::{ScriptEvent, ScriptHost};
+use crate::smoothing::{Smoother, SmoothingCommand, PREVIEW_BONE_RADIUS};
+use crate::standby::StandbyDetector;
+use crate::timebase::{Stamped, Timebase};
+use crate::update_rate::{SlowBoneTint, UpdateRates};
+use crate::users::HeightMeter;
+use crate::watchdog::{Watchdog, CALL_TIMEOUT};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use eyre::{Result, WrapErr};
 use git_version::git_version;
 use log4rs::append::rolling_file::policy::compound::roll::fixed_window::FixedWindowRoller;
@@ -14,21 +93,44 @@ use log4rs::append::rolling_file::policy::compound::trigger::size::SizeTrigger;
 use log4rs::append::rolling_file::policy::compound::CompoundPolicy;
 use log4rs::append::rolling_file::RollingFileAppender;
 use log4rs::encode::pattern::PatternEncoder;
-use nalgebra::{Translation3, UnitQuaternion};
+use nalgebra::{Translation3, UnitQuaternion, Vector3};
 use ovr_overlay as ovr;
+use slimevr_skeleton::neck::NeckSynthesis;
+use slimevr_skeleton::proportions::{self, Proportions};
+use solarxr::protocol::data_feed::DataFeedUpdate;
+use solarxr::protocol::datatypes::TrackerStatus;
 use solarxr::settings::DisplaySettings;
 use solarxr::FeedUpdate;
-use std::collections::HashSet;
-use std::time::Duration;
-use tokio::sync::watch;
+use std::collections::{HashMap, HashSet};
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, oneshot, watch, Notify};
 use tokio_graceful_shutdown::{SubsystemHandle, Toplevel};
 
 use log::LevelFilter;
 use log4rs::append::console::ConsoleAppender;
-use log4rs::config::{Appender, Config, Root};
+use log4rs::config::{Appender, Config as LogConfig, Root};
 
 const CONNECT_STR: &str = "ws://localhost:21110";
 const GIT_VERSION: &str = git_version!();
+/// How many selection moves can queue up before the overlay handles them.
+const NAVIGATE_CAPACITY: usize = 8;
+/// Same for moving the skeleton up and down.
+const FLOOR_CAPACITY: usize = 8;
+/// And for tuning the smoothing.
+const SMOOTHING_CAPACITY: usize = 8;
+/// How often to draw while interpolating, if the headset doesn't say how often it
+/// refreshes.
+const DEFAULT_FRAME_INTERVAL: Duration = Duration::from_micros(11_111);
+/// Where the HTTP API records to, without `--export-csv`.
+const DEFAULT_RECORDING_PATH: &str = "recording.csv";
+/// The log of this run, which rolls over to `LOG_ROLLED` with the numbers up to
+/// `LOG_WINDOW` once it grows too large.
+const LOG_FILE: &str = "logfile.log";
+const LOG_ROLLED: &str = "log_last_{}.log";
+const LOG_WINDOW: u32 = 2;
 
 #[derive(Parser, Debug)]
 #[command(version = GIT_VERSION)]
@@ -37,6 +139,135 @@ struct Args {
 	show_console: bool,
 	#[arg(long, default_value_t = true)]
 	show_log: bool,
+	#[arg(long, default_value_t = false)]
+	hide_tray: bool,
+	/// A Rhai script to customize the overlay's behavior
+	#[arg(long)]
+	script: Option<PathBuf>,
+	/// The config file to use
+	#[arg(long, default_value = DEFAULT_CONFIG_PATH)]
+	config: PathBuf,
+	/// Start as this one of the `[users]` of the config
+	#[arg(long)]
+	user: Option<String>,
+	/// Export the skeleton stream to CSV files, numbered based on this path
+	#[arg(long)]
+	export_csv: Option<PathBuf>,
+	/// Start a new CSV file once the current one is larger than this many megabytes
+	#[arg(long, default_value_t = 100)]
+	export_max_mb: u64,
+	/// Record the skeleton stream to this file in a compact binary format, which
+	/// suits sessions of hours better than CSV
+	#[arg(long)]
+	record: Option<PathBuf>,
+	/// Convert a CSV export to a recording, or a recording to CSV, instead of
+	/// running the overlay
+	#[arg(long)]
+	convert: Option<PathBuf>,
+	/// Where to write the conversion to, by default next to the input
+	#[arg(long, requires = "convert")]
+	convert_out: Option<PathBuf>,
+	/// Render a CSV export or a recording to an image sequence from the camera in
+	/// the config, instead of running the overlay
+	#[arg(long)]
+	render_replay: Option<PathBuf>,
+	/// The folder to render the replay to
+	#[arg(long, default_value = "replay")]
+	render_out: PathBuf,
+	/// How to show the skeleton in the headset
+	#[arg(long, value_enum, default_value_t = Backend::Overlay)]
+	backend: Backend,
+	/// Draw a humanoid avatar instead of the bones, with `--backend scene` or
+	/// `--render-replay`. Requires the `scene` feature.
+	#[arg(long, default_value_t = false)]
+	avatar: bool,
+	/// A VRM or glTF avatar to draw instead of the bundled one. Implies `--avatar`.
+	#[arg(long)]
+	avatar_file: Option<PathBuf>,
+	/// Stream the raw IMU samples of the tracker at this IP address, and plot them
+	#[arg(long)]
+	raw_stream: Option<Ipv4Addr>,
+	/// Where to remember the bone lengths between runs
+	#[arg(long, default_value = proportions::DEFAULT_PATH)]
+	bone_lengths: PathBuf,
+	/// Move the bone selection with a gamepad. Requires the `gamepad` feature.
+	#[arg(long, default_value_t = false)]
+	gamepad: bool,
+	/// Where the skeleton comes from
+	#[arg(long, value_enum, default_value_t = Source::Auto)]
+	source: Source,
+	/// Animate the skeleton through canned movements, instead of connecting to the
+	/// server. Same as `--source demo`.
+	#[arg(long, default_value_t = false, conflicts_with = "source")]
+	demo: bool,
+	/// Gather the logs, config, crash reports and system info into a zip for a bug
+	/// report, with addresses and Wi-Fi credentials redacted, instead of running
+	#[arg(long, num_args = 0..=1, default_missing_value = debug_bundle::DEFAULT_PATH)]
+	collect_debug_bundle: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Source {
+	/// Connect to the server, unless the SlimeVR driver for SteamVR isn't installed.
+	/// Then there is most likely no server either, so play the demo.
+	Auto,
+	/// Connect to the server
+	Server,
+	/// Animate the skeleton through canned movements
+	Demo,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+	/// Draw the skeleton with overlays, on top of whatever else is running
+	Overlay,
+	/// Render the skeleton with depth as a scene application, in place of a game.
+	/// Only draws the bones or the avatar. Requires the `scene` feature.
+	Scene,
+}
+
+/// Everything the overlay subsystem needs, besides its channels.
+#[derive(Clone)]
+pub struct OverlayOptions {
+	config: Config,
+	script: Option<PathBuf>,
+	bus: Bus,
+	/// Gives the frames on the bus their wall clock time.
+	timebase: Timebase,
+	/// Where the samples of `--raw-stream` come from, if it was given.
+	raw_samples: Option<broadcast::Sender<RawSample>>,
+	bone_lengths: PathBuf,
+	/// Moves the selection from the tray or a gamepad, without pointing at bones.
+	navigate: broadcast::Sender<Navigate>,
+	/// Moves the skeleton up and down from the tray or a gamepad.
+	floor: broadcast::Sender<FloorCommand>,
+	/// How far the skeleton is moved up, in meters. Outlives the overlay session, so
+	/// that it survives restarts of SteamVR.
+	floor_offset: Arc<watch::Sender<f32>>,
+	/// What the log console shows, and whether it is shown.
+	log_buffer: LogBuffer,
+	log_visible: watch::Receiver<bool>,
+	/// How the skeleton is drawn, which changes whenever the config file is saved.
+	style: watch::Receiver<SkeletonStyle>,
+	/// Same for how the bones are smoothed.
+	smoothing: watch::Receiver<Smoothing>,
+	/// Tunes the smoothing, and toggles its preview, from the tray.
+	smoothing_commands: broadcast::Sender<SmoothingCommand>,
+	/// Toggled by the controller binding, like the tray and `SIGUSR1` do.
+	visible: Arc<watch::Sender<bool>>,
+	/// Whether the feed is the animation of the demo, rather than the server. Only
+	/// known once networking picked the [`Source`].
+	demo: watch::Receiver<bool>,
+	/// Which of the `[users]` is using the overlay, if any, see [`users`].
+	user: Arc<watch::Sender<Option<String>>>,
+	/// Which of them seems to have put on the headset, going by its height.
+	suggested_user: Arc<watch::Sender<Option<String>>>,
+	/// Which session `overlay` runs.
+	backend: Backend,
+	/// What `--backend scene` draws instead of the bones, see
+	/// `scene::load_avatar`.
+	avatar: bool,
+	avatar_file: Option<PathBuf>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -67,14 +298,11 @@ fn hide_console_window() {
 	}
 }
 
-fn init_log(show_log: bool) -> Result<()> {
-	let logfile_name = "logfile.log";
-
+fn init_log(show_log: bool, buffer: LogBuffer) -> Result<()> {
 	let log_pattern = "{h({l})} - {d(%Y-%m-%d %H:%M:%S)} - {m}{n}";
 
-	let window_size = 2;
 	let fixed_window_roller = FixedWindowRoller::builder()
-		.build("log_last_{}.log", window_size)
+		.build(LOG_ROLLED, LOG_WINDOW)
 		.unwrap();
 	let size_limit = 1 * 1000 * 1024; // 1MB
 	let size_trigger = SizeTrigger::new(size_limit);
@@ -84,15 +312,20 @@ fn init_log(show_log: bool) -> Result<()> {
 
 	let rolling_file_appender = RollingFileAppender::builder()
 		.encoder(Box::new(PatternEncoder::new(log_pattern)))
-		.build(logfile_name, Box::new(compound_policy))?;
+		.build(LOG_FILE, Box::new(compound_policy))?;
 
-	let mut config_builder = Config::builder();
+	let mut config_builder = LogConfig::builder();
 	let mut root_builder = Root::builder();
 
 	root_builder = root_builder.appender("logfile");
 	config_builder = config_builder.appender(
 		Appender::builder().build("logfile", Box::new(rolling_file_appender)),
 	);
+	// The log console filters by itself, since the config isn't loaded yet.
+	root_builder = root_builder.appender("headset");
+	config_builder = config_builder.appender(
+		Appender::builder().build("headset", Box::new(BufferAppender(buffer))),
+	);
 
 	if show_log {
 		let stdout = ConsoleAppender::builder()
@@ -118,8 +351,10 @@ pub async fn main() -> Result<()> {
 
 	let args = Args::parse();
 
-	init_log(args.show_log)?;
+	let log_buffer = LogBuffer::default();
+	init_log(args.show_log, log_buffer.clone())?;
 	color_eyre::install()?;
+	crash_report::install(GIT_VERSION);
 
 	#[cfg(target_os = "windows")]
 	{
@@ -129,37 +364,771 @@ pub async fn main() -> Result<()> {
 	}
 
 	log::info!("Overlay version: {GIT_VERSION}");
+	#[cfg(not(feature = "gamepad"))]
+	if args.gamepad {
+		eyre::bail!("`--gamepad` requires the `gamepad` feature");
+	}
+	#[cfg(not(feature = "scene"))]
+	if args.backend == Backend::Scene {
+		eyre::bail!("`--backend scene` requires the `scene` feature");
+	}
+	let draws_avatar = args.backend == Backend::Scene || args.render_replay.is_some();
+	if (args.avatar || args.avatar_file.is_some()) && !draws_avatar {
+		eyre::bail!(
+			"`--avatar` and `--avatar-file` require `--backend scene` or \
+			`--render-replay`"
+		);
+	}
+
+	let config_path = platform::resolve_path(&args.config);
+	// Before loading the config, which might be what the bug is about.
+	if let Some(out) = args.collect_debug_bundle {
+		let bone_lengths = platform::resolve_path(&args.bone_lengths);
+		let count = debug_bundle::collect(&out, &config_path, &bone_lengths)?;
+		log::info!("Wrote {count} files to {out:?}, attach it to the issue");
+		return Ok(());
+	}
+	let config = Config::load(&config_path)?;
+	if let Some(from) = args.convert {
+		let to = args
+			.convert_out
+			.unwrap_or_else(|| recording::converted_path(&from));
+		let count = recording::convert(&from, &to)?;
+		log::info!("Converted {count} updates from {from:?} to {to:?}");
+		return Ok(());
+	}
+	if let Some(csv) = args.render_replay {
+		let lengths = Proportions::load(&platform::resolve_path(&args.bone_lengths));
+		let camera = &config.replay_camera;
+		let avatar = args.avatar_file.as_deref().map(platform::resolve_path);
+		let bones = replay_bones(camera, args.avatar, avatar).await?;
+		let count = replay::export(
+			&csv,
+			&args.render_out,
+			camera,
+			&config.skeleton,
+			lengths.lengths(),
+			bones,
+		)?;
+		log::info!("Rendered {count} frames to {:?}", args.render_out);
+		return Ok(());
+	}
+
+	if let Some(name) = &args.user {
+		if !config.users.contains_key(name) {
+			eyre::bail!(
+				"There is no user named {name:?} in the `[users]` of the config"
+			);
+		}
+	}
+	// The HTTP API goes back to the `[skeleton]` from their theme.
+	let user_theme = config
+		.user(args.user.as_deref())
+		.and_then(|u| u.theme.clone())
+		.map(|name| (name, config.skeleton.clone()));
+	let style = match &user_theme {
+		Some((name, _)) => &config.themes[name],
+		None => &config.skeleton,
+	};
+	let (style_sender, style) = watch::channel(style.clone());
+	let style_sender = Arc::new(style_sender);
+	let (smoothing_sender, smoothing) = watch::channel(config.smoothing.clone());
+	let (smoothing_commands, _) = broadcast::channel(SMOOTHING_CAPACITY);
+	#[allow(unused_variables)]
+	let (tray_visible_sender, tray_visible) = watch::channel(true);
+	let tray_visible_sender = Arc::new(tray_visible_sender);
+	let identify = Arc::new(Notify::new());
+	let (navigate, _) = broadcast::channel(NAVIGATE_CAPACITY);
+	let (floor, _) = broadcast::channel(FLOOR_CAPACITY);
+	#[allow(unused_variables)]
+	let (log_visible_sender, log_visible) = watch::channel(config.log_console.enabled);
 
-	Toplevel::new()
-		.start("Networking", networking)
+	let source = if args.demo { Source::Demo } else { args.source };
+	let (demo_sender, demo) = watch::channel(source == Source::Demo);
+	let user = Arc::new(watch::channel(args.user).0);
+	let suggested_user = Arc::new(watch::channel(None).0);
+
+	let bus = component::bus();
+	let mut components: Vec<Box<dyn Component>> = Vec::new();
+	// The tray and the HTTP API can start recording later.
+	let recording = args.export_csv.is_some();
+	let path = args
+		.export_csv
+		.unwrap_or_else(|| PathBuf::from(DEFAULT_RECORDING_PATH));
+	let max_bytes = args.export_max_mb * 1000 * 1000;
+	components.push(Box::new(CsvExport::new(path, max_bytes, recording)));
+	if let Some(path) = args.record {
+		components.push(Box::new(recording::Recorder::new(path)));
+	}
+	if config.presence.discord || config.presence.webhook.is_some() {
+		components.push(Box::new(Presence::new(config.presence.clone())));
+	}
+	if config.osc.enabled {
+		components.push(Box::new(OscOutput::new(&config.osc)?));
+	}
+	// Only shown by the HTTP API.
+	let (dashboard, dashboard_state) = Dashboard::new(recording);
+	let (metrics, counters) = Metrics::new(recording);
+	if config.api_port != 0 {
+		components.push(Box::new(dashboard));
+		components.push(Box::new(metrics));
+	}
+	let toplevel = component::start_all(Toplevel::new(), &bus, components);
+	let toplevel = {
+		let style = style_sender.clone();
+		toplevel.start("ConfigReload", |s| {
+			config::reload::run(s, config_path, style, smoothing_sender)
+		})
+	};
+	let toplevel = match config.api_port {
+		0 => toplevel,
+		port => {
+			let controls = http_api::Controls {
+				visible: tray_visible_sender.clone(),
+				log_visible: log_visible.clone(),
+				style: style_sender.clone(),
+				themes: config.themes.clone(),
+				theme: user_theme,
+				user: user.clone(),
+				users: config.users.clone(),
+				suggested_user: suggested_user.subscribe(),
+				bus: bus.clone(),
+				recording,
+				dashboard: dashboard_state,
+				metrics: counters,
+			};
+			toplevel.start("HttpApi", move |s| http_api::run(s, port, controls))
+		}
+	};
+	let (toplevel, raw_samples) = match args.raw_stream {
+		Some(tracker) => {
+			let (sender, _) = broadcast::channel(raw_stream::CHANNEL_CAPACITY);
+			let s = sender.clone();
+			let toplevel =
+				toplevel.start("RawStream", move |h| raw_stream::run(h, tracker, s));
+			(toplevel, Some(sender))
+		}
+		None => (toplevel, None),
+	};
+	#[cfg(any(target_os = "windows", target_os = "linux"))]
+	let toplevel = if args.hide_tray {
+		toplevel
+	} else {
+		let controls = tray::Controls {
+			visible: tray_visible_sender.clone(),
+			identify: identify.clone(),
+			navigate: navigate.clone(),
+			floor: floor.clone(),
+			log_visible: log_visible_sender,
+			smoothing: smoothing_commands.clone(),
+			preview: config.smoothing.preview,
+			bus: bus.clone(),
+			recording,
+			style: style_sender.clone(),
+			themes: config.themes.clone(),
+		};
+		toplevel.start("Tray", move |s| tray::run(s, controls))
+	};
+	#[cfg(feature = "gamepad")]
+	let toplevel = if args.gamepad {
+		let identify = identify.clone();
+		let navigate = navigate.clone();
+		let floor = floor.clone();
+		toplevel.start("Gamepad", |s| gamepad::run(s, identify, navigate, floor))
+	} else {
+		toplevel
+	};
+	#[cfg(target_os = "linux")]
+	let toplevel = {
+		let visible = tray_visible_sender.clone();
+		let identify = identify.clone();
+		toplevel.start("Signals", |s| async move {
+			tokio::select! {
+				_ = s.on_shutdown_requested() => Ok(()),
+				r = platform::toggle_on_signal(&visible) => r,
+				r = platform::identify_on_signal(&identify) => r,
+			}
+		})
+	};
+	let options = OverlayOptions {
+		config,
+		script: args.script.as_deref().map(platform::resolve_path),
+		bus,
+		timebase: Timebase::new(),
+		raw_samples,
+		bone_lengths: platform::resolve_path(&args.bone_lengths),
+		navigate,
+		floor,
+		floor_offset: Arc::new(watch::channel(0.).0),
+		log_buffer,
+		log_visible,
+		style,
+		smoothing,
+		smoothing_commands,
+		visible: tray_visible_sender,
+		demo,
+		user,
+		suggested_user,
+		backend: args.backend,
+		avatar: args.avatar,
+		avatar_file: args.avatar_file.as_deref().map(platform::resolve_path),
+	};
+	toplevel
+		.start("Networking", |s| {
+			networking(s, options, source, demo_sender, tray_visible, identify)
+		})
 		.catch_signals()
 		.handle_shutdown_requests(Duration::from_millis(1000))
 		.await
 		.wrap_err("system shutdown")
 }
 
+/// How `--render-replay` draws the bones, with the avatar of `avatar_file` or the
+/// bundled one if `avatar` is set.
+#[cfg(feature = "scene")]
+async fn replay_bones(
+	camera: &ReplayCamera,
+	avatar: bool,
+	avatar_file: Option<PathBuf>,
+) -> Result<replay::Bones> {
+	let avatar = scene::load_avatar(avatar, avatar_file.as_deref())?;
+	let (width, height) = (camera.width as u32, camera.height as u32);
+	let scene = scene::SceneRenderer::new(avatar).await?;
+	let target = scene::Target::readable(&scene, width, height);
+	Ok(replay::Bones::Scene(scene, target))
+}
+
+#[cfg(not(feature = "scene"))]
+async fn replay_bones(
+	_camera: &ReplayCamera,
+	avatar: bool,
+	avatar_file: Option<PathBuf>,
+) -> Result<replay::Bones> {
+	if avatar || avatar_file.is_some() {
+		eyre::bail!("`--avatar` and `--avatar-file` require the `scene` feature");
+	}
+	Ok(replay::Bones::Lines)
+}
+
+/// How often to check whether OpenVR is stuck.
+const WATCHDOG_INTERVAL: Duration = Duration::from_millis(500);
+/// How long to wait before re-initializing OpenVR after it got stuck.
+const RESTART_DELAY: Duration = Duration::from_secs(2);
+
+/// Runs overlay sessions, and restarts them with a fresh OpenVR context whenever
+/// one gets stuck in an OpenVR call.
 async fn overlay(
-	mut recv: watch::Receiver<Option<FeedUpdate>>,
+	recv: watch::Receiver<Option<Stamped<FeedUpdate>>>,
 	display_settings: watch::Receiver<DisplaySettings>,
+	options: OverlayOptions,
+	tray_visible: watch::Receiver<bool>,
+	standby: Arc<watch::Sender<bool>>,
+	driver_status: Arc<watch::Sender<Option<DriverStatus>>>,
+	identify: Arc<Notify>,
 	subsys: SubsystemHandle,
 ) -> Result<()> {
+	let mut restarts = 0;
+	loop {
+		let watchdog = Watchdog::default();
+		let (stop_sender, stop) = watch::channel(());
+		let (done_sender, mut done) = oneshot::channel();
+		// The session runs on a thread of its own, with a runtime of its own, so that
+		// a call that never returns only blocks that thread, and not us or the other
+		// subsystems.
+		let session = {
+			let recv = recv.clone();
+			let display_settings = display_settings.clone();
+			let options = options.clone();
+			let tray_visible = tray_visible.clone();
+			let standby = standby.clone();
+			let driver_status = driver_status.clone();
+			let identify = identify.clone();
+			let watchdog = watchdog.clone();
+			move || {
+				let r = match options.backend {
+					Backend::Overlay => tokio::runtime::Builder::new_current_thread()
+						.enable_all()
+						.build()
+						.wrap_err("Failed to start the runtime of the overlay session")
+						.and_then(|runtime| {
+							runtime.block_on(overlay_session(
+								recv,
+								display_settings,
+								options,
+								tray_visible,
+								standby,
+								driver_status,
+								identify,
+								watchdog,
+								stop,
+							))
+						}),
+					// Blocks on the compositor instead of a runtime.
+					#[cfg(feature = "scene")]
+					Backend::Scene => scene::session::run(
+						recv,
+						display_settings,
+						options,
+						tray_visible,
+						driver_status,
+						watchdog,
+						stop,
+					),
+					#[cfg(not(feature = "scene"))]
+					Backend::Scene => unreachable!("checked in `main`"),
+				};
+				let _ = done_sender.send(r);
+			}
+		};
+		std::thread::Builder::new()
+			.name("overlay session".to_owned())
+			.spawn(session)
+			.wrap_err("Failed to start the overlay session")?;
+		let mut interval = tokio::time::interval(WATCHDOG_INTERVAL);
+		let stuck = loop {
+			tokio::select! {
+				_ = subsys.on_shutdown_requested() => {
+					log::debug!("overlay shutdown requested");
+					stop_sender.send_replace(());
+					return done.await.wrap_err("Overlay session panicked")?;
+				}
+				r = &mut done => {
+					match r.wrap_err("Overlay session panicked")? {
+						// Failing to initialize again is expected while SteamVR
+						// restarts, so just keep trying.
+						Err(e) if restarts > 0 => {
+							log::warn!("Failed to restart overlay session: {e:?}");
+							break None;
+						}
+						r => return r,
+					}
+				}
+				_ = interval.tick() => {
+					if let Some(stuck) = watchdog.stuck(CALL_TIMEOUT) {
+						break Some(stuck);
+					}
+				}
+			}
+		};
+		if let Some((call, elapsed)) = stuck {
+			log::error!(
+				"OpenVR call `{call}` has not returned for {elapsed:?}, SteamVR \
+				probably crashed. Restarting the OpenVR context."
+			);
+			stop_sender.send_replace(());
+			// The session can't shut its context down while it is stuck in it, and
+			// the next one can't initialize OpenVR while it is still up.
+			let shutdown = watchdog.clone();
+			tokio::task::spawn_blocking(move || shutdown.shutdown());
+			if tokio::time::timeout(CALL_TIMEOUT, &mut done).await.is_err() {
+				log::warn!(
+					"The stuck overlay session didn't stop, leaving its thread behind"
+				);
+			}
+		}
+		restarts += 1;
+		tokio::select! {
+			_ = subsys.on_shutdown_requested() => return Ok(()),
+			_ = tokio::time::sleep(RESTART_DELAY) => (),
+		}
+		log::info!("Restarting overlay session (restart #{restarts})");
+	}
+}
+
+/// Runs the overlay with its own OpenVR context, until `stop` changes.
+async fn overlay_session(
+	mut recv: watch::Receiver<Option<Stamped<FeedUpdate>>>,
+	display_settings: watch::Receiver<DisplaySettings>,
+	options: OverlayOptions,
+	mut tray_visible: watch::Receiver<bool>,
+	standby: Arc<watch::Sender<bool>>,
+	driver_status: Arc<watch::Sender<Option<DriverStatus>>>,
+	identify: Arc<Notify>,
+	watchdog: Watchdog,
+	mut stop: watch::Receiver<()>,
+) -> Result<()> {
+	let OverlayOptions {
+		config,
+		script,
+		bus,
+		timebase,
+		raw_samples,
+		bone_lengths,
+		navigate,
+		floor,
+		floor_offset,
+		log_buffer,
+		mut log_visible,
+		mut style,
+		mut smoothing,
+		smoothing_commands,
+		visible,
+		demo,
+		user,
+		suggested_user,
+		// Only for the scene session.
+		backend: _,
+		avatar: _,
+		avatar_file: _,
+	} = options;
+
+	match platform::find_openvr_runtime() {
+		Ok(runtime) => log::info!("Using OpenVR runtime at {runtime:?}"),
+		Err(e) => log::warn!("{e}"),
+	}
 	log::info!("Initializing OpenVR context");
-	let context = ovr::Context::init().wrap_err("Failed to initialize OpenVR")?;
+	let context = watchdog
+		.call("VR_Init", ovr::Context::init)
+		.wrap_err("Failed to initialize OpenVR")?;
+	// Also when the session fails, so that the next one can initialize it again.
+	let _shutdown = watchdog.shutdown_on_drop();
 	let mngr = &mut context.overlay_mngr();
+	// The overlays of the last session went away with its context.
+	budget::reset();
+	// The overlay works without it, just without the binding.
+	let mut controller_input = watchdog
+		.call("SetActionManifestPath", || ControllerInput::new(&context))
+		.map_err(|e| log::warn!("{e:?}"))
+		.ok();
 
-	let mut skeleton = SkeletonBuilder::default()
-		.build(mngr)
+	// Read again for every session, since the last one saved its lengths on the way
+	// out.
+	let mut user = user.subscribe();
+	let lengths_of = |name: Option<&str>| match config.user(name) {
+		Some(u) => platform::resolve_path(&users::bone_lengths(
+			&bone_lengths,
+			name.unwrap_or_default(),
+			u,
+		)),
+		None => bone_lengths.clone(),
+	};
+	let mut proportions = Proportions::load(&lengths_of(user.borrow().as_deref()));
+	let mut skeleton = watchdog
+		.call("CreateOverlay", || {
+			SkeletonBuilder::default()
+				.bone_lengths(proportions.lengths())
+				.build(mngr)
+		})
 		.wrap_err("Could not create skeleton")?;
+	let trail_bones = config
+		.trails
+		.iter()
+		.map(|(name, trail)| {
+			let bone: BoneKind = name
+				.parse()
+				.map_err(|()| eyre::eyre!("Unknown bone {name:?} in `trails`"))?;
+			let seconds = trail.seconds.clamp(0.01, 60.);
+			Ok((bone, Duration::from_secs_f32(seconds), trail.segments))
+		})
+		.collect::<Result<Vec<_>>>()?;
+	let mut trails = watchdog
+		.call("CreateOverlay", || {
+			Trails::new(mngr, "slimevr", trail_bones)
+		})
+		.wrap_err("Could not create trails")?;
+	let mut hud = config
+		.hud
+		.enabled
+		.then(|| {
+			let [x, y, z] = config.hud.offset;
+			let offset = Vector3::new(x, y, z);
+			watchdog.call("CreateOverlay", || {
+				MiniSkeleton::new(mngr, offset, config.hud.scale)
+			})
+		})
+		.transpose()
+		.wrap_err("Could not create HUD skeleton")?;
+	let mut reach = config
+		.reach
+		.enabled
+		.then(|| {
+			watchdog.call("CreateOverlay", || {
+				Reach::new(mngr, "slimevr", config.reach.show_box)
+			})
+		})
+		.transpose()
+		.wrap_err("Could not create reach box")?;
+	let mut context_panel = watchdog
+		.call("CreateOverlay", || ContextPanel::new(mngr, "slimevr"))
+		.wrap_err("Could not create context panel")?;
+	let mut warning_panel = watchdog
+		.call("CreateOverlay", || WarningPanel::new(mngr, "slimevr"))
+		.wrap_err("Could not create warning panel")?;
+	let mut anomalies = AnomalyDetector::default();
+	// Created as the trackers show up, since there is no telling how many there are.
+	let mut tracker_markers = config
+		.tracker_markers
+		.enabled
+		.then(|| TrackerMarkers::new("slimevr", config.tracker_markers.size));
+	let mut raw_plot = raw_samples
+		.map(|sender| {
+			watchdog.call("CreateOverlay", || {
+				RawPlot::new(mngr, "slimevr", sender.subscribe())
+			})
+		})
+		.transpose()
+		.wrap_err("Could not create raw data plot")?;
+	let mut log_panel = watchdog
+		.call("CreateOverlay", || {
+			LogPanel::new(mngr, "slimevr", log_buffer, &config.log_console)
+		})
+		.wrap_err("Could not create log console")?;
+
+	let mut script = script
+		.map(|path| {
+			log::info!("Loading script {path:?}");
+			ScriptHost::load(&path)
+		})
+		.transpose()?;
+	if let Some(script) = script.as_mut() {
+		script.on_start(&mut skeleton)?;
+	}
+	// What happened since the last `on_update`, for the `on_event` hook.
+	let mut script_events = script.is_some().then(|| bus.subscribe());
+	let mut publisher = Publisher::new(bus, timebase);
 
 	log::info!("Overlay Loop");
 
 	let loop_ = async {
 		let mut hidden_bones: HashSet<BoneKind> = HashSet::new();
+		let mut app_detector = AppDetector::default();
+		let mut driver_detector = DriverDetector::new();
+		let mut profile: Option<Profile> = None;
+		let mut extrapolator = Extrapolator::default();
+		let mut picker = Picker::default();
+		let mut standby_detector = StandbyDetector::new(*standby.borrow());
+		// Only measured if there is someone to suggest.
+		let mut height_meter = config
+			.users
+			.values()
+			.any(|u| u.headset_height.is_some())
+			.then(HeightMeter::default);
+		let mut identifier = Identifier::default();
+		let mut identify_requested = false;
+		// Hidden with the quick action of the context panel.
+		let mut hidden_by_user: HashSet<BoneKind> = HashSet::new();
+		let mut navigate = navigate.subscribe();
+		let mut navigation = Vec::new();
+		let mut floor = floor.subscribe();
+		let mut floor_commands = Vec::new();
+		let mut smoother = Smoother::new(&smoothing.borrow());
+		let mut deadband = Deadband::new(&config.deadband);
+		let mut latency = LatencyMeter::new(&config.latency);
+		let mut smoothing_commands = smoothing_commands.subscribe();
+		let mut smoothing_pending = Vec::new();
+		let mut preview = config.smoothing.preview;
+		// The unsmoothed skeleton, created once the preview is first shown.
+		let mut raw_skeleton: Option<Skeleton> = None;
+		let mut raw_poses = Vec::new();
+		let mut recolor_raw = true;
+		let mut hidden_by_style: HashSet<BoneKind> = HashSet::new();
+		// Also for the first frame, since the skeleton is built with the defaults.
+		let mut restyle = true;
+		let mut update_rates = UpdateRates::default();
+		let mut slow_tint = config
+			.alerts
+			.bone_rate_low
+			.enabled
+			.then(|| SlowBoneTint::new(config.alerts.bone_rate_low_hz));
+		let extrapolation = Duration::from_millis(config.extrapolation_ms);
+		let mut interpolator = config.interpolation.enabled.then(|| {
+			let max_extrapolation =
+				Duration::from_millis(config.interpolation.max_extrapolation_ms);
+			Interpolator::new(config.interpolation.smoothing, max_extrapolation)
+		});
+		let neck_synthesis = config.neck.enabled.then(|| {
+			NeckSynthesis::new(config.neck.pivot_offset, config.neck.neck_length)
+		});
+		let frame_interval = watchdog
+			.call("GetFloatTrackedDeviceProperty", || {
+				frame_timing::frame_interval(&context)
+			})
+			.unwrap_or(DEFAULT_FRAME_INTERVAL);
+		let mut governor = config
+			.adaptive_quality
+			.enabled
+			.then(QualityGovernor::default);
+		// Whether the skeletons are merged because of it.
+		let mut merge = false;
+		let reduced_interval = Duration::from_secs_f32(1. / quality::REDUCED_RATE);
+		// When the skeleton was last drawn, and whether it skipped a feed update
+		// since, while the quality is reduced.
+		let mut last_draw: Option<Instant> = None;
+		let mut is_behind = false;
 		loop {
-			recv.changed()
-				.await
-				.wrap_err("Error while attempting to watch for feed update")?;
-			let is_skeleton_visible = display_settings.borrow().is_visible;
+			// Before waiting for the feed, since networking waits for the first status
+			// to pick where the feed comes from.
+			let changed = watchdog.call("GetStringTrackedDeviceProperty", || {
+				driver_detector.poll(&context)
+			});
+			if let Some(status) = changed {
+				status.log();
+				driver_status.send_replace(Some(status));
+			}
+			tokio::select! {
+				r = recv.changed() => {
+					r.wrap_err("Error while attempting to watch for feed update")?
+				}
+				// Re-render with the last feed update when toggled from the tray.
+				Ok(()) = tray_visible.changed() => (),
+				_ = identify.notified() => identify_requested = true,
+				Ok(n) = navigate.recv() => navigation.push(n),
+				Ok(cmd) = floor.recv() => floor_commands.push(cmd),
+				Ok(cmd) = smoothing_commands.recv() => smoothing_pending.push(cmd),
+				Ok(()) = smoothing.changed() => {
+					smoother.configure(&smoothing.borrow_and_update());
+					recolor_raw = true;
+				}
+				Ok(()) = log_visible.changed() => (),
+				Ok(()) = style.changed() => restyle = true,
+				Ok(()) = user.changed() => {
+					let name = user.borrow_and_update().clone();
+					log::info!("Switched to user {name:?}");
+					proportions.save();
+					proportions = Proportions::load(&lengths_of(name.as_deref()));
+					for (kind, length) in proportions.lengths() {
+						if let Some(length) = length {
+							skeleton.set_length(kind, length);
+						}
+					}
+				}
+				// New lines have to show up even without feed updates, like when
+				// the server can't be reached.
+				_ = tokio::time::sleep(LogPanel::POLL_INTERVAL),
+					if *log_visible.borrow() => (),
+				// There are no feed updates in standby, but we still need to notice
+				// when the headset wakes up.
+				_ = tokio::time::sleep(standby::POLL_INTERVAL),
+					if standby_detector.is_standby() => (),
+				// Interpolating draws every frame of the headset, not just updates.
+				_ = tokio::time::sleep(frame_interval),
+					if interpolator.is_some() && !standby_detector.is_standby() => (),
+				// Draws the update that was skipped, if no other comes in.
+				_ = tokio::time::sleep(reduced_interval), if is_behind => (),
+			}
+			let now = Instant::now();
+
+			let was_standby = standby_detector.is_standby();
+			let changed = watchdog.call("GetTrackedDeviceActivityLevel", || {
+				standby_detector.poll(&context)
+			});
+			if let Some(is_standby) = changed {
+				if is_standby {
+					log::info!("Headset went into standby, pausing");
+				} else {
+					log::info!("Headset woke up, resuming");
+				}
+				standby.send_replace(is_standby);
+				publisher.standby(is_standby);
+			}
+			// Render one more time after going into standby, to hide the skeleton.
+			if was_standby && standby_detector.is_standby() {
+				continue;
+			}
+			if let Some(meter) = height_meter.as_mut() {
+				if standby_detector.is_standby() {
+					// Whoever puts it on next might be someone else.
+					meter.reset();
+					suggested_user.send_replace(None);
+				} else if let Some(hmd) = watchdog
+					.call("GetDeviceToAbsoluteTrackingPose", || hmd_position(&context))
+				{
+					meter.update(hmd.y, now);
+				}
+				let height = meter.height();
+				let suggestion = height.and_then(|h| users::suggest(&config.users, h));
+				let is_new = suggestion != suggested_user.borrow().as_deref();
+				let is_other = suggestion != user.borrow().as_deref();
+				if is_new {
+					if let (Some(name), Some(h), true) = (suggestion, height, is_other)
+					{
+						log::info!(
+							"The headset is {h:.2} m high while standing, like \
+							 {name}'s. Switch to them with `POST /user/{name}`"
+						);
+					}
+					suggested_user.send_replace(suggestion.map(str::to_owned));
+				}
+			}
+			if let Some(governor) = governor.as_mut() {
+				let frame = watchdog
+					.call("GetFrameTiming", || frame_timing::last_frame(&context));
+				if let Some(quality) =
+					frame.and_then(|f| governor.update(f, frame_interval, now))
+				{
+					match quality {
+						Quality::Reduced => log::info!(
+							"The GPU is busy for {:.0}% of each frame, and {:.0}% of \
+							 frames are dropped. Drawing less until there is headroom",
+							100. * governor.load(),
+							100. * governor.dropped()
+						),
+						Quality::Full => {
+							log::info!("Headroom is back, drawing everything")
+						}
+					}
+					merge =
+						config.adaptive_quality.merge && quality == Quality::Reduced;
+					let _guard = watchdog.enter("CreateOverlay");
+					let skeletons =
+						std::iter::once(&mut skeleton).chain(&mut raw_skeleton);
+					for s in skeletons {
+						if let Err(e) = s.set_merged(mngr, merge) {
+							log::error!("{e:?}");
+						}
+					}
+				}
+			}
+			let quality = governor
+				.as_ref()
+				.map_or(Quality::Full, QualityGovernor::quality);
+			let prediction = if config.predict_photon_time {
+				let to_photons = watchdog.call("GetTimeSinceLastVsync", || {
+					frame_timing::time_to_photons(&context)
+				});
+				extrapolation + to_photons.unwrap_or_default()
+			} else {
+				extrapolation
+			};
+			let prediction = prediction + latency.compensation(interpolator.is_some());
+
+			if watchdog.call("GetSceneProcessId", || app_detector.poll(&context)) {
+				let app_key = app_detector.current();
+				profile = app_key.and_then(|k| config.profile(k)).cloned();
+				log::info!(
+					"Detected application {app_key:?}, using profile {profile:?}"
+				);
+			}
+			let mut pressed = Pressed::default();
+			if let Some(input) = controller_input.as_mut() {
+				pressed = watchdog.call("UpdateActionState", || input.poll(&context));
+				if pressed.toggle {
+					visible.send_modify(|v| *v = !*v);
+					log::info!(
+						"Toggled visibility from the controller: {}",
+						*visible.borrow()
+					);
+				}
+			}
+			let is_dashboard_open =
+				watchdog.call("IsDashboardVisible", || mngr.is_dashboard_visible());
+			let is_dashboard_shown = !config.only_with_dashboard || is_dashboard_open;
+			let is_skeleton_visible = !standby_detector.is_standby()
+				&& *tray_visible.borrow()
+				&& is_dashboard_shown
+				&& profile
+					.as_ref()
+					.and_then(|p| p.visible)
+					.unwrap_or_else(|| display_settings.borrow().is_visible);
+
+			// Before the feed update is looked at, since there might not be one.
+			{
+				let _guard = watchdog.enter("SetOverlay");
+				let visible = !standby_detector.is_standby() && *log_visible.borrow();
+				if let Err(e) = log_panel.update_render(mngr, visible, now) {
+					log::error!("Error updating log console: {e:?}");
+				}
+			}
 
 			log::trace!("Got a feed update");
 
@@ -172,11 +1141,20 @@ async fn overlay(
 				pos: Translation3<f32>,
 				rot: UnitQuaternion<f32>,
 				length: f32,
+				/// Made up from the headset, instead of from the feed.
+				synthesized: bool,
 			}
 			// Extract relevant data about bones from flatbuffers
-			let bones: Vec<BoneInfo> = {
+			let (received, mut bones, mut trackers, velocities, assigned): (
+				Instant,
+				Vec<BoneInfo>,
+				_,
+				_,
+				_,
+			) = {
 				let guard = recv.borrow_and_update();
-				let table = guard.as_ref().unwrap().0.table();
+				let feed = unwrap_or_continue!(guard.as_ref());
+				let table = feed.value.0.table();
 				log::trace!("update: {:#?}", table);
 
 				let m = unwrap_or_continue!(table.data_feed_msgs());
@@ -184,10 +1162,14 @@ async fn overlay(
 				// TODO: handle multiple updates?
 				let m = m.get(0);
 				let m = unwrap_or_continue!(m.message_as_data_feed_update());
+				let trackers = tracker_poses(m);
+				let velocities = reported_velocities(m);
+				let assigned = assigned_trackers(m);
+				anomalies.update(m, now);
 				let bones = unwrap_or_continue!(m.bones());
 				log::debug!("Got {} bones before filtering", bones.len());
 
-				bones
+				let bones = bones
 					.iter()
 					.filter_map(|b| {
 						let part = b.body_part();
@@ -224,9 +1206,11 @@ async fn overlay(
 							pos,
 							rot,
 							length,
+							synthesized: false,
 						})
 					})
-					.collect()
+					.collect();
+				(feed.time, bones, trackers, velocities, assigned)
 			};
 
 			log::debug!(
@@ -235,66 +1219,695 @@ async fn overlay(
 			);
 			log::trace!("Bone data: {bones:?}");
 
+			publisher.frame(
+				received,
+				bones
+					.iter()
+					.map(|b| (b.kind, Isometry::from_parts(b.pos, b.rot))),
+			);
+			publisher.trackers(
+				trackers
+					.iter()
+					.filter(|t| t.state != TrackerState::Disconnected)
+					.count(),
+			);
+			{
+				let _guard = watchdog.enter("SetOverlay");
+				let visible = is_dashboard_open && !standby_detector.is_standby();
+				let current = anomalies.current();
+				if let Err(e) = warning_panel.update_render(mngr, visible, current) {
+					log::error!("Error updating warning panel: {e:?}");
+				}
+			}
+
+			// Snapping needs the bones where the server put them, not where the
+			// offset moved them to.
+			for cmd in floor_commands.drain(..) {
+				let offset = floor::adjust(
+					*floor_offset.borrow(),
+					cmd,
+					bones
+						.iter()
+						.map(|b| (Isometry::from_parts(b.pos, b.rot), b.length)),
+				);
+				log::info!("{cmd:?}: moving the skeleton up by {offset:.2} m");
+				floor_offset.send_replace(offset);
+			}
+			let offset = *floor_offset.borrow();
+
+			// After the frame and the floor, which are about the feed.
+			if let Some(neck_synthesis) = neck_synthesis.as_ref() {
+				let hmd = watchdog.call("GetDeviceToAbsoluteTrackingPose", || {
+					neck::hmd_pose(&context, prediction)
+				});
+				if let Some(hmd) = hmd {
+					let feed = |kind: BoneKind| {
+						let b = bones.iter().find(|b| b.kind == kind)?;
+						Some((Isometry::from_parts(b.pos, b.rot), b.length))
+					};
+					for bone in neck_synthesis.synthesize(&hmd, feed) {
+						if is_skeleton_visible {
+							hidden_bones.remove(&bone.kind);
+						}
+						bones.push(BoneInfo {
+							kind: bone.kind,
+							pos: bone.iso.translation,
+							rot: bone.iso.rotation,
+							length: bone.length,
+							synthesized: true,
+						});
+					}
+				}
+			}
+
 			// Update all bones in datafeed
 			for BoneInfo {
 				kind,
 				pos,
 				rot,
 				length,
+				synthesized,
 			} in bones
 			{
-				let iso = Isometry {
-					rotation: rot,
-					translation: pos,
+				if synthesized {
+					// The headset is already where it will be, there is nothing to
+					// smooth, and the lengths aren't the user's.
+					let mut iso = Isometry::from_parts(pos, rot);
+					floor::apply(&mut iso, offset);
+					skeleton.set_isometry(kind, iso);
+					skeleton.set_length(kind, length);
+					continue;
+				}
+				update_rates.update(kind, rot, received);
+				if let Some(&(_, w)) = velocities.iter().find(|(k, _)| *k == kind) {
+					extrapolator.report(kind, w, received);
+				}
+				extrapolator.update(kind, rot, received);
+				let mut iso = match interpolator.as_mut() {
+					// Extrapolates by drawing that much further ahead.
+					Some(interpolator) => {
+						let iso = Isometry::from_parts(pos, rot);
+						interpolator.update(kind, iso, received);
+						interpolator.pose(kind, now + prediction).unwrap_or(iso)
+					}
+					None => Isometry {
+						rotation: extrapolator
+							.extrapolate(kind, prediction)
+							.unwrap_or(rot),
+						translation: pos,
+					},
 				};
+				let mut raw = iso;
+				iso = smoother.update(kind, iso, now);
+				iso = deadband.update(kind, iso);
+				floor::apply(&mut iso, offset);
+				if preview {
+					floor::apply(&mut raw, offset);
+					raw_poses.push((kind, raw, length));
+				}
 				skeleton.set_isometry(kind, iso);
 				skeleton.set_length(kind, length);
+				// The demo's lengths aren't the user's, so they aren't remembered.
+				if !*demo.borrow() {
+					proportions.update(kind, length);
+				}
+			}
+			proportions.save_if_due(now);
+
+			let was_selected = picker.selected();
+			watchdog.call("GetDeviceToAbsoluteTrackingPose", || {
+				picker.update(&context, &mut skeleton)
+			});
+			for n in navigation.drain(..) {
+				picker.navigate(n, &mut skeleton);
+			}
+			if picker.selected() != was_selected {
+				if let Some(input) = controller_input.as_mut() {
+					watchdog.call("TriggerHapticVibrationAction", || {
+						input.vibrate(&context)
+					});
+				}
+			}
+			if let Some(bone) = picker.selected().filter(|_| pressed.hide) {
+				let is_hidden = !hidden_by_user.remove(&bone);
+				if is_hidden {
+					hidden_by_user.insert(bone);
+				}
+				log::info!("{} {bone:?}", if is_hidden { "Hid" } else { "Showed" });
+			}
+			if pressed.recolor {
+				if let Some((bone, color)) = picker.recolor() {
+					log::info!("Recolored {bone:?} to {color:?}");
+				}
+			}
+			if std::mem::take(&mut identify_requested) || pressed.identify {
+				match picker.selected() {
+					Some(bone) => identifier.start(bone, now),
+					None => log::warn!(
+						"Can't identify a bone, select one first by pointing at it"
+					),
+				}
+			}
+
+			for cmd in smoothing_pending.drain(..) {
+				let stronger = match cmd {
+					SmoothingCommand::Preview(enabled) => {
+						preview = enabled;
+						continue;
+					}
+					SmoothingCommand::Stronger => true,
+					SmoothingCommand::Weaker => false,
+				};
+				let Some(bone) = picker.selected() else {
+					log::warn!(
+						"Can't change the smoothing of a bone, select one first by \
+						 pointing at it"
+					);
+					continue;
+				};
+				let ms = smoother.adjust(bone, stronger);
+				log::info!(
+					"Smoothing {bone:?} over {ms:.0} ms, add `{bone:?} = {ms:.0}` to \
+					 `bones` under `[smoothing]` to keep it"
+				);
+			}
+
+			if std::mem::take(&mut restyle) {
+				let style = style.borrow_and_update();
+				apply_style(&style, &mut skeleton, &mut picker, slow_tint.as_mut());
+				hidden_by_style = style.hidden().collect();
+			}
+
+			if let Some(script) = script.as_mut() {
+				let selected = picker.selected();
+				let mut events = Vec::new();
+				if let Some(rx) = script_events.as_mut() {
+					loop {
+						match rx.try_recv() {
+							Ok(event) => events.extend(ScriptEvent::from_bus(&event)),
+							Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+							Err(_) => break,
+						}
+					}
+				}
+				for event in &events {
+					if let Err(e) = script.on_event(&mut skeleton, selected, event) {
+						log::error!("{e:?}");
+					}
+				}
+				if let Err(e) = script.on_trackers(&mut skeleton, selected, &trackers) {
+					log::error!("{e:?}");
+				}
+				if let Err(e) = script.on_update(&mut skeleton, selected) {
+					log::error!("{e:?}");
+				}
 			}
 
+			let show_raw = preview && smoother.is_enabled();
+			if show_raw && raw_skeleton.is_none() {
+				raw_skeleton = watchdog
+					.call("CreateOverlay", || {
+						SkeletonBuilder::default()
+							.key("slimevr-raw")
+							.bone_radius(PREVIEW_BONE_RADIUS)
+							.bone_lengths(proportions.lengths())
+							.build(mngr)
+							.and_then(|mut raw| {
+								raw.set_merged(mngr, merge)?;
+								Ok(raw)
+							})
+					})
+					.map_err(|e| {
+						log::error!("Could not create smoothing preview: {e:?}");
+						// Instead of trying again every frame.
+						preview = false;
+					})
+					.ok();
+			}
+			if let Some(raw_skeleton) = raw_skeleton.as_mut() {
+				if std::mem::take(&mut recolor_raw) {
+					let color = smoothing.borrow().raw_color;
+					for kind in BoneKind::iter() {
+						raw_skeleton.set_color(kind, color);
+					}
+				}
+				for (kind, iso, length) in raw_poses.drain(..) {
+					raw_skeleton.set_isometry(kind, iso);
+					raw_skeleton.set_length(kind, length);
+				}
+			}
+			raw_poses.clear();
+
 			// Update rendering state
 			for kind in BoneKind::iter() {
-				skeleton.set_visibility(kind, !hidden_bones.contains(&kind));
+				let is_hidden = hidden_bones.contains(&kind)
+					|| hidden_by_style.contains(&kind)
+					|| hidden_by_user.contains(&kind);
+				let is_visible = script
+					.as_ref()
+					.and_then(|s| s.visibility_override(kind))
+					.unwrap_or(!is_hidden);
+				skeleton.set_visibility(kind, is_visible);
+				if let Some(raw_skeleton) = raw_skeleton.as_mut() {
+					raw_skeleton.set_visibility(kind, is_visible && show_raw);
+				}
+			}
+			identifier.update(&mut skeleton, now);
+			{
+				let _guard = watchdog.enter("SetOverlay");
+				let details = picker.selected().map(|bone| {
+					let (tracker, battery) = match assigned.get(&bone) {
+						Some((name, battery)) => (Some(name.clone()), *battery),
+						None => (None, None),
+					};
+					BoneDetails {
+						bone,
+						tracker,
+						battery,
+						rate_hz: update_rates.rate(bone),
+						latency: latency.median(),
+						is_hidden: hidden_by_user.contains(&bone),
+					}
+				});
+				let visible = !standby_detector.is_standby();
+				let details = details.as_ref();
+				if let Err(e) = context_panel.update_render(mngr, visible, details) {
+					log::error!("Error updating context panel: {e:?}");
+				}
+			}
+			if let Some(slow_tint) = slow_tint.as_mut() {
+				slow_tint.update(&mut update_rates, &mut skeleton, now);
+			}
+
+			// Drawn even while the skeleton is behind, since it isn't part of it.
+			if let Some(raw_plot) = raw_plot.as_mut() {
+				let _guard = watchdog.enter("SetOverlay");
+				if let Err(e) = raw_plot.update_render(mngr, now) {
+					log::error!("{e:?}");
+				}
+			}
+
+			// Only every so often while the quality is reduced, with whatever the last
+			// feed update was by then.
+			is_behind = quality == Quality::Reduced
+				&& last_draw.map_or(false, |t| now - t < reduced_interval);
+			if is_behind {
+				continue;
+			}
+			last_draw = Some(now);
+
+			if quality == Quality::Full {
+				trails.update(&skeleton, now);
+			} else {
+				trails.hide();
+			}
+			if let Some(markers) = tracker_markers.as_mut() {
+				for pose in &mut trackers {
+					floor::apply(&mut pose.iso, offset);
+				}
+				let _guard = watchdog.enter("CreateOverlay");
+				let visible = is_skeleton_visible && quality == Quality::Full;
+				if let Err(e) = markers.update(mngr, &trackers, visible, now) {
+					log::error!("{e:?}");
+				}
+			}
+			if let Some(reach) = reach.as_mut() {
+				reach.update(&skeleton);
+			}
+			if let Some(hud) = hud.as_mut() {
+				let forward = watchdog
+					.call("GetDeviceToAbsoluteTrackingPose", || hmd_forward(&context));
+				hud.update(&skeleton, forward, config.straight_spine);
+			}
+			skeleton.update_spine(!config.straight_spine);
+			// Only skeletons merged into one overlay need to know where the headset is.
+			let merged = skeleton.is_merged()
+				|| raw_skeleton.as_ref().map_or(false, Skeleton::is_merged);
+			let eye = merged
+				.then(|| {
+					watchdog.call("GetDeviceToAbsoluteTrackingPose", || {
+						hmd_position(&context)
+					})
+				})
+				.flatten();
+			let _guard = watchdog.enter("SetOverlay");
+			for kind in BoneKind::iter() {
 				if let Err(e) = skeleton.update_render(kind, mngr) {
 					log::error!("Error updating render for bone {kind:?}: {:?}", e);
 				}
 			}
+			if let Err(e) = skeleton.update_spine_render(mngr) {
+				log::error!("{e:?}");
+			}
+			if let Err(e) = skeleton.update_merged_render(mngr, eye) {
+				log::error!("{e:?}");
+			}
+			if let Some(raw_skeleton) = raw_skeleton.as_mut() {
+				raw_skeleton.update_spine(!config.straight_spine);
+				for kind in BoneKind::iter() {
+					if let Err(e) = raw_skeleton.update_render(kind, mngr) {
+						log::error!("Error updating render for raw {kind:?}: {e:?}");
+					}
+				}
+				if let Err(e) = raw_skeleton.update_spine_render(mngr) {
+					log::error!("{e:?}");
+				}
+				if let Err(e) = raw_skeleton.update_merged_render(mngr, eye) {
+					log::error!("{e:?}");
+				}
+			}
+			if let Err(e) = trails.update_render(mngr) {
+				log::error!("{e:?}");
+			}
+			if let Some(markers) = tracker_markers.as_mut() {
+				if let Err(e) = markers.update_render(mngr) {
+					log::error!("{e:?}");
+				}
+			}
+			if let Some(hud) = hud.as_mut() {
+				if let Err(e) = hud.update_render(mngr) {
+					log::error!("Error updating render for HUD: {e:?}");
+				}
+			}
+			if let Some(reach) = reach.as_mut() {
+				if let Err(e) = reach.update_render(mngr) {
+					log::error!("{e:?}");
+				}
+			}
+			drop(_guard);
+			let to_photons = watchdog.call("GetTimeSinceLastVsync", || {
+				frame_timing::time_to_photons(&context)
+			});
+			latency.record(received, now, Instant::now(), to_photons);
+			latency.log(now);
 		}
 	};
 	tokio::select! {
-		_ = subsys.on_shutdown_requested() => {
-			log::debug!("overlay shutdown requested");
-			Ok::<_, eyre::Report>(())
-		},
+		_ = stop.changed() => Ok::<_, eyre::Report>(()),
 		r = loop_ => r,
 	}?;
 
-	log::info!("Shutting down OpenVR context");
-	unsafe { context.shutdown() };
+	if let Some(reach) = &reach {
+		reach.envelope().log_summary();
+	}
+	proportions.save();
 	Ok(())
 }
 
-async fn networking(subsys: SubsystemHandle) -> Result<()> {
+/// Applies the radius and colors of `style`. Bones that are highlighted right now,
+/// because they are selected or slow, get their new color once they aren't anymore.
+fn apply_style(
+	style: &SkeletonStyle,
+	skeleton: &mut Skeleton,
+	picker: &mut Picker,
+	mut slow_tint: Option<&mut SlowBoneTint>,
+) {
+	skeleton.set_radius(style.radius);
+	for kind in BoneKind::iter() {
+		let color = style.color(kind).unwrap_or_else(|| default_color(kind));
+		let is_selected = picker.set_original_color(kind, color);
+		let is_slow = slow_tint
+			.as_mut()
+			.map_or(false, |t| t.set_untinted_color(kind, color));
+		if !is_selected && !is_slow {
+			skeleton.set_color(kind, color);
+		}
+	}
+}
+
+async fn networking(
+	subsys: SubsystemHandle,
+	options: OverlayOptions,
+	source: Source,
+	demo: watch::Sender<bool>,
+	tray_visible: watch::Receiver<bool>,
+	identify: Arc<Notify>,
+) -> Result<()> {
 	let (data_sender, data_reciever) = watch::channel(None);
 	let (settings_sender, settings_receiver) =
 		watch::channel(DisplaySettings::default());
 
-	subsys.start("Overlay", |s| overlay(data_reciever, settings_receiver, s));
+	// Kept here rather than handed to the subsystem, so that subscribers still work
+	// when diagnostics are disabled or the port is taken.
+	let (diagnostics_sender, _) = broadcast::channel(diagnostics::CHANNEL_CAPACITY);
+	let port = options.config.diagnostics_port;
+	if port != 0 {
+		let sender = diagnostics_sender.clone();
+		subsys.start("Diagnostics", move |s| diagnostics::run(s, port, sender));
+	}
+
+	// We hold on to a sender as well, so that the channel stays open while the
+	// overlay restarts.
+	let (standby_sender, mut standby) = watch::channel(false);
+	let standby_sender = Arc::new(standby_sender);
+	let overlay_standby = standby_sender.clone();
+	// Also held on to, for the same reason.
+	let (driver_status_sender, mut driver_status) = watch::channel(None);
+	let driver_status_sender = Arc::new(driver_status_sender);
 
-	let run_future = solarxr::run(CONNECT_STR.to_string(), |update| async {
-		let ds = get_display_settings(&update).await;
-		if let Some(ds) = ds {
-			log::info!("Updating settings: {:?}", ds);
-			settings_sender.send_replace(ds);
-		}
-		data_sender.send_replace(Some(update));
+	let alerts = options.config.alerts.clone();
+	let mut user = options.user.subscribe();
+	let users = options.config.users.clone();
+	let server_of = |name: Option<&str>| {
+		let server = name.and_then(|n| users.get(n)?.server.clone());
+		server.unwrap_or_else(|| CONNECT_STR.to_string())
+	};
+	let feed = data_sender.subscribe();
+	let diagnostics = diagnostics_sender.subscribe();
+	let alerts_standby = standby_sender.subscribe();
+	subsys.start("Alerts", |s| {
+		alerts::run(s, alerts, feed, diagnostics, alerts_standby)
 	});
-	tokio::select! {
-		_ = run_future => { unreachable!("This future never returns") },
-		_ = subsys.on_shutdown_requested() => {
-			log::debug!("networking shutdown requested");
-			Ok(())
+
+	subsys.start("Overlay", |s| {
+		overlay(
+			data_reciever,
+			settings_receiver,
+			options,
+			tray_visible,
+			overlay_standby,
+			driver_status_sender,
+			identify,
+			s,
+		)
+	});
+
+	let play_demo = match source {
+		Source::Demo => true,
+		Source::Server => false,
+		Source::Auto => {
+			log::info!("Waiting for SteamVR, to look for the SlimeVR driver");
+			let status = tokio::select! {
+				status = wait_for_driver_status(&mut driver_status) => status,
+				_ = subsys.on_shutdown_requested() => {
+					log::debug!("networking shutdown requested");
+					return Ok(());
+				}
+			};
+			status == DriverStatus::Missing
+		}
+	};
+	demo.send_replace(play_demo);
+	if play_demo {
+		if source == Source::Auto {
+			log::warn!(
+				"Playing the demo animation, since there is probably no server \
+				 without the SlimeVR driver. Pass `--source server` to connect to it \
+				 anyway."
+			);
+		}
+		log::info!("Playing the demo animation, instead of connecting to the server");
+		tokio::select! {
+			_ = demo::run(&data_sender) => unreachable!("This future never returns"),
+			_ = subsys.on_shutdown_requested() => {
+				log::debug!("networking shutdown requested");
+				return Ok(());
+			}
+		}
+	}
+
+	loop {
+		// Disconnect from the server while the headset is in standby, so that we
+		// don't keep receiving data that nobody looks at.
+		tokio::select! {
+			_ = wait_for_standby(&mut standby, false) => (),
+			_ = subsys.on_shutdown_requested() => {
+				log::debug!("networking shutdown requested");
+				return Ok(());
+			}
+		}
+
+		let server = server_of(user.borrow_and_update().as_deref());
+		log::info!("Connecting to the server at {server}");
+		let run_future = solarxr::run(server.clone(), |update| async {
+			let ds = get_display_settings(&update).await;
+			if let Some(ds) = ds {
+				log::info!("Updating settings: {:?}", ds);
+				settings_sender.send_replace(ds);
+			}
+			data_sender.send_replace(Some(Stamped::now(update)));
+		});
+		tokio::select! {
+			_ = run_future => { unreachable!("This future never returns") },
+			_ = wait_for_standby(&mut standby, true) => {
+				log::info!("Disconnecting from the server during standby");
+			}
+			_ = wait_for_server_change(&mut user, &server_of, &server) => {
+				log::info!("Disconnecting, {:?} uses another server", *user.borrow());
+			}
+			_ = subsys.on_shutdown_requested() => {
+				log::debug!("networking shutdown requested");
+				return Ok(());
+			}
+		}
+	}
+}
+
+/// Waits until the overlay found out whether the SlimeVR driver is there.
+async fn wait_for_driver_status(
+	status: &mut watch::Receiver<Option<DriverStatus>>,
+) -> DriverStatus {
+	loop {
+		if let Some(status) = *status.borrow_and_update() {
+			return status;
+		}
+		// We hold on to a sender, so this never fails.
+		let _ = status.changed().await;
+	}
+}
+
+/// Waits until whether the headset is in standby matches `is_standby`.
+async fn wait_for_standby(standby: &mut watch::Receiver<bool>, is_standby: bool) {
+	while *standby.borrow_and_update() != is_standby {
+		// We hold on to a sender, so this never fails.
+		let _ = standby.changed().await;
+	}
+}
+
+/// Waits until the user switches to someone whose server isn't `server`.
+async fn wait_for_server_change(
+	user: &mut watch::Receiver<Option<String>>,
+	server_of: impl Fn(Option<&str>) -> String,
+	server: &str,
+) {
+	loop {
+		// Without anyone left to switch users, nobody will.
+		if user.changed().await.is_err() {
+			std::future::pending::<()>().await;
+		}
+		if server_of(user.borrow_and_update().as_deref()) != server {
+			return;
+		}
+	}
+}
+
+/// The bones of `feed`, with their lengths, for the backends that don't need
+/// anything else from it. Skips the ones without a position or rotation, like the
+/// overlay session does.
+#[cfg(feature = "scene")]
+fn feed_bones(feed: &FeedUpdate) -> Vec<(BoneKind, Isometry, f32)> {
+	let update = feed
+		.0
+		.table()
+		.data_feed_msgs()
+		.and_then(|m| m.iter().next())
+		.and_then(|m| m.message_as_data_feed_update());
+	let Some(bones) = update.and_then(|u| u.bones()) else {
+		return Vec::new();
+	};
+	bones
+		.iter()
+		.filter_map(|b| {
+			let kind = BoneKind::try_from(b.body_part()).ok()?;
+			let (p, r) = (b.head_position_g()?, b.rotation_g()?);
+			let rot =
+				UnitQuaternion::from_quaternion([r.x(), r.y(), r.z(), r.w()].into());
+			let iso = Isometry::from_parts(Translation3::new(p.x(), p.y(), p.z()), rot);
+			Some((kind, iso, b.bone_length()))
+		})
+		.collect()
+}
+
+/// Where the trackers in `update` are, skipping the ones without a position or
+/// rotation.
+fn tracker_poses(update: DataFeedUpdate<'_>) -> Vec<TrackerPose> {
+	let mut poses = Vec::new();
+	for device in update.devices().into_iter().flatten() {
+		let Some(id) = device.id().map(|id| id.id()) else {
+			continue;
+		};
+		for (i, tracker) in device.trackers().into_iter().flatten().enumerate() {
+			let (Some(p), Some(r)) = (tracker.position(), tracker.rotation()) else {
+				continue;
+			};
+			let state = match tracker.status() {
+				TrackerStatus::OK => TrackerState::Ok,
+				TrackerStatus::DISCONNECTED | TrackerStatus::TIMED_OUT => {
+					TrackerState::Disconnected
+				}
+				_ => TrackerState::Stale,
+			};
+			let rot =
+				UnitQuaternion::from_quaternion([r.x(), r.y(), r.z(), r.w()].into());
+			poses.push(TrackerPose {
+				id: (id, i),
+				iso: Isometry::from_parts(Translation3::new(p.x(), p.y(), p.z()), rot),
+				state,
+			});
+		}
+	}
+	poses
+}
+
+/// The name of the tracker on each bone in `update`, and the battery of its device.
+fn assigned_trackers(
+	update: DataFeedUpdate<'_>,
+) -> HashMap<BoneKind, (String, Option<u8>)> {
+	let mut assigned = HashMap::new();
+	for device in update.devices().into_iter().flatten() {
+		let id = device.id().map_or(0, |id| id.id());
+		let battery = device
+			.hardware_status()
+			.and_then(|h| h.battery_pct_estimate());
+		for (i, tracker) in device.trackers().into_iter().flatten().enumerate() {
+			let Some(info) = tracker.info() else {
+				continue;
+			};
+			let Ok(kind) = BoneKind::try_from(info.body_part()) else {
+				continue;
+			};
+			let name = info
+				.custom_name()
+				.or(info.display_name())
+				.or(device.custom_name())
+				.map_or_else(|| format!("Device {id}"), String::from);
+			assigned.insert(kind, (format!("{name} ({id}.{i})"), battery));
+		}
+	}
+	assigned
+}
+
+/// The angular velocities that the trackers in `update` reported, in the global
+/// frame, by the bone that each tracker is on.
+fn reported_velocities(update: DataFeedUpdate<'_>) -> Vec<(BoneKind, Vector3<f32>)> {
+	let mut velocities = Vec::new();
+	for device in update.devices().into_iter().flatten() {
+		for tracker in device.trackers().into_iter().flatten() {
+			let part = tracker.info().map(|i| i.body_part());
+			let Some(Ok(kind)) = part.map(BoneKind::try_from) else {
+				continue;
+			};
+			let w = tracker.raw_angular_velocity();
+			let (Some(w), Some(r)) = (w, tracker.rotation()) else {
+				continue;
+			};
+			// The velocity is in the frame of the tracker.
+			let rot =
+				UnitQuaternion::from_quaternion([r.x(), r.y(), r.z(), r.w()].into());
+			velocities.push((kind, rot * Vector3::new(w.x(), w.y(), w.z())));
 		}
 	}
+	velocities
 }
 
 async fn get_display_settings<'a>(update: &FeedUpdate) -> Option<DisplaySettings> {
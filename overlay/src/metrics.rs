@@ -0,0 +1,185 @@
+//! Counts what goes over the bus, so that long sessions can be watched with
+//! Prometheus, or anything else that reads its text format. The HTTP API serves
+//! them at `GET /metrics`, see [`crate::http_api`]:
+//!
+//! ```text
+//! slimevr_overlay_uptime_seconds 3600.0
+//! slimevr_overlay_feed_updates_total 180211
+//! slimevr_overlay_bones 17
+//! slimevr_overlay_trackers 6
+//! slimevr_overlay_standby 0
+//! slimevr_overlay_standby_seconds_total 312.5
+//! slimevr_overlay_recording 0
+//! ```
+//!
+//! `bones` is how many were in the last feed update. The totals are also logged
+//! when the overlay closes.
+
+use crate::component::{Component, Event};
+
+use eyre::Result;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+use tokio::sync::watch;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Counters {
+	started: SystemTime,
+	feed_updates: u64,
+	bones: usize,
+	trackers: usize,
+	/// Since when the headset is in standby, if it is.
+	standby_since: Option<SystemTime>,
+	/// How long the headset was in standby before that.
+	standby: Duration,
+	recording: bool,
+}
+impl Counters {
+	fn new(now: SystemTime, recording: bool) -> Self {
+		Self {
+			started: now,
+			feed_updates: 0,
+			bones: 0,
+			trackers: 0,
+			standby_since: None,
+			standby: Duration::ZERO,
+			recording,
+		}
+	}
+
+	fn update(&mut self, event: &Event, now: SystemTime) {
+		match event {
+			Event::Frame(frame) => {
+				self.feed_updates += 1;
+				self.bones = frame.bones.len();
+			}
+			Event::Standby(true) => {
+				self.standby_since.get_or_insert(now);
+			}
+			Event::Standby(false) => {
+				self.standby = self.standby_at(now);
+				self.standby_since = None;
+			}
+			Event::Recording(recording) => self.recording = *recording,
+			Event::Trackers(trackers) => self.trackers = *trackers,
+		}
+	}
+
+	/// How long the headset was in standby until `now`.
+	fn standby_at(&self, now: SystemTime) -> Duration {
+		let since = self.standby_since.and_then(|s| now.duration_since(s).ok());
+		self.standby + since.unwrap_or_default()
+	}
+
+	/// In the text format of Prometheus.
+	pub fn render(&self, now: SystemTime) -> String {
+		let uptime = now.duration_since(self.started).unwrap_or_default();
+		let metrics: [(&str, &str, String); 7] = [
+			(
+				"uptime_seconds",
+				"gauge",
+				format!("{:.1}", uptime.as_secs_f32()),
+			),
+			(
+				"feed_updates_total",
+				"counter",
+				self.feed_updates.to_string(),
+			),
+			("bones", "gauge", self.bones.to_string()),
+			("trackers", "gauge", self.trackers.to_string()),
+			(
+				"standby",
+				"gauge",
+				u8::from(self.standby_since.is_some()).to_string(),
+			),
+			(
+				"standby_seconds_total",
+				"counter",
+				format!("{:.1}", self.standby_at(now).as_secs_f32()),
+			),
+			("recording", "gauge", u8::from(self.recording).to_string()),
+		];
+		let mut text = String::new();
+		for (name, kind, value) in metrics {
+			let _ = writeln!(text, "# TYPE slimevr_overlay_{name} {kind}");
+			let _ = writeln!(text, "slimevr_overlay_{name} {value}");
+		}
+		text
+	}
+}
+
+/// Keeps the counters for the HTTP API.
+pub struct Metrics {
+	counters: Counters,
+	sender: watch::Sender<Counters>,
+}
+impl Metrics {
+	/// `recording` is whether the skeleton is recorded from the start.
+	pub fn new(recording: bool) -> (Self, watch::Receiver<Counters>) {
+		let counters = Counters::new(SystemTime::now(), recording);
+		let (sender, receiver) = watch::channel(counters);
+		(Self { counters, sender }, receiver)
+	}
+}
+
+impl Component for Metrics {
+	fn name(&self) -> &'static str {
+		"Metrics"
+	}
+
+	fn update(&mut self, event: &Event) -> Result<()> {
+		self.counters.update(event, SystemTime::now());
+		self.sender.send_replace(self.counters);
+		Ok(())
+	}
+
+	fn shutdown(&mut self) -> Result<()> {
+		let c = &self.counters;
+		let now = SystemTime::now();
+		let uptime = now.duration_since(c.started).unwrap_or_default();
+		log::info!(
+			"Got {} feed updates in {:.0} s, with the headset in standby for {:.0} s",
+			c.feed_updates,
+			uptime.as_secs_f32(),
+			c.standby_at(now).as_secs_f32()
+		);
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::component::Frame;
+	use crate::model::{BoneKind, Isometry};
+
+	use std::sync::Arc;
+
+	#[test]
+	fn counts_the_bus() {
+		let start = SystemTime::UNIX_EPOCH;
+		let at = |s| start + Duration::from_secs(s);
+		let mut c = Counters::new(start, false);
+		let frame = Frame {
+			timestamp: start,
+			bones: vec![(BoneKind::Hip, Isometry::identity())],
+		};
+		let frame = Event::Frame(Arc::new(frame));
+		c.update(&frame, at(1));
+		c.update(&frame, at(2));
+		c.update(&Event::Trackers(6), at(2));
+		c.update(&Event::Standby(true), at(10));
+		c.update(&Event::Standby(true), at(20));
+		c.update(&Event::Standby(false), at(30));
+		c.update(&Event::Standby(true), at(40));
+
+		let text = c.render(at(45));
+		assert!(text.contains("slimevr_overlay_uptime_seconds 45.0\n"));
+		assert!(text.contains("slimevr_overlay_feed_updates_total 2\n"));
+		assert!(text.contains("slimevr_overlay_bones 1\n"));
+		assert!(text.contains("slimevr_overlay_trackers 6\n"));
+		assert!(text.contains("slimevr_overlay_standby 1\n"));
+		assert!(text.contains("slimevr_overlay_standby_seconds_total 25.0\n"));
+		assert!(text.contains("# TYPE slimevr_overlay_recording gauge\n"));
+	}
+}
@@ -0,0 +1,466 @@
+//! A humanoid mesh that gets posed like the bones of the skeleton, as a more
+//! intuitive preview of the tracked body than a stick figure.
+//!
+//! Besides the bundled avatar, this loads VRM avatars and any glTF skin whose joints
+//! are named after a [`BoneKind`]. Avatars are usually modeled in a T-pose, with
+//! their joints oriented however the modeling tool liked, so we don't use the joint
+//! orientations at all. Instead each joint that is driven by a bone gets rotated so
+//! that the segment to its next joint lines up with that bone, and moved to where
+//! that end of the bone is. Joints that aren't driven by a bone, like fingers, just
+//! follow their parent.
+
+use crate::model::{BoneKind, BoneMap, Isometry, Mesh, Vertex};
+
+use eyre::{eyre, Result, WrapErr};
+use gltf::json::Value;
+use nalgebra::{Matrix3, Matrix4, Point3, Translation3, Unit, UnitQuaternion, Vector3};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The low-poly avatar that ships with the overlay, generated by
+/// `assets/make_avatar.py`.
+const BUNDLED: &[u8] = include_bytes!("../../assets/avatar.gltf");
+
+/// Which end of a bone a joint sits at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Anchor {
+	/// The joint is at the head of the bone, so its segment points along the bone.
+	Head,
+	/// The joint is at the tail of the bone, so its segment points back up the
+	/// bone. This is the case for the torso, since SlimeVR's bones point down from
+	/// the head, while rigs point up from the hips.
+	Tail,
+}
+
+/// The VRM humanoid bones that get driven by a [`BoneKind`], and the humanoid bone
+/// that their segment points to.
+#[rustfmt::skip]
+const VRM_BONES: &[(&str, BoneKind, Anchor, Option<&str>)] = {
+	// Named, so that `Head` is the anchor, and the bone of that name is spelled out.
+	use Anchor::{Head, Tail};
+	use BoneKind::*;
+	&[
+		("hips", Hip, Tail, Some("spine")),
+		("spine", Waist, Tail, Some("chest")),
+		("chest", Chest, Tail, Some("neck")),
+		("neck", Neck, Tail, Some("head")),
+		("head", BoneKind::Head, Tail, None),
+		("leftUpperLeg", ThighL, Head, Some("leftLowerLeg")),
+		("rightUpperLeg", ThighR, Head, Some("rightLowerLeg")),
+		("leftLowerLeg", AnkleL, Head, Some("leftFoot")),
+		("rightLowerLeg", AnkleR, Head, Some("rightFoot")),
+		("leftFoot", FootL, Head, Some("leftToes")),
+		("rightFoot", FootR, Head, Some("rightToes")),
+		("leftUpperArm", UpperArmL, Head, Some("leftLowerArm")),
+		("rightUpperArm", UpperArmR, Head, Some("rightLowerArm")),
+		("leftLowerArm", ForearmL, Head, Some("leftHand")),
+		("rightLowerArm", ForearmR, Head, Some("rightHand")),
+		("leftHand", WristL, Head, Some("leftMiddleProximal")),
+		("rightHand", WristR, Head, Some("rightMiddleProximal")),
+	]
+};
+
+/// A joint of the avatar's skin.
+#[derive(Debug)]
+struct Joint {
+	/// The bone that drives this joint, if any.
+	driver: Option<(BoneKind, Anchor)>,
+	/// Index of the parent joint.
+	parent: Option<usize>,
+	/// Where the joint is in the bind pose.
+	bind_position: Point3<f32>,
+	/// Direction and length of the segment from this joint to its next joint in the
+	/// bind pose, if it has one.
+	segment: Option<(Unit<Vector3<f32>>, f32)>,
+}
+
+#[derive(Debug)]
+struct SkinnedVertex {
+	position: Point3<f32>,
+	normal: Vector3<f32>,
+	joints: [u16; 4],
+	weights: [f32; 4],
+}
+
+pub struct Avatar {
+	/// Ordered so that parents always come before their children.
+	joints: Vec<Joint>,
+	vertices: Vec<SkinnedVertex>,
+	indices: Vec<u32>,
+}
+impl Avatar {
+	pub fn bundled() -> Result<Self> {
+		Self::from_slice(BUNDLED).wrap_err("Failed to load the bundled avatar")
+	}
+
+	/// Loads a `.vrm`, `.glb`, or `.gltf` file.
+	pub fn load(path: &Path) -> Result<Self> {
+		let bytes =
+			std::fs::read(path).wrap_err_with(|| format!("Failed to read {path:?}"))?;
+		Self::from_slice(&bytes).wrap_err_with(|| format!("Failed to load {path:?}"))
+	}
+
+	/// Loads the first skinned mesh in a glTF or VRM file.
+	pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+		let (document, buffers, _images) = gltf::import_slice(bytes)?;
+		let node = document
+			.nodes()
+			.find(|n| n.skin().is_some() && n.mesh().is_some())
+			.ok_or_else(|| eyre!("No skinned mesh"))?;
+		let (skin, mesh) = (node.skin().unwrap(), node.mesh().unwrap());
+		let get_buffer = |b: gltf::Buffer| Some(&*buffers[b.index()]);
+
+		let humanoid = vrm_humanoid(&document);
+		// OpenVR's forward is -z, while glTF's (and VRM 1.0's) is +z.
+		let flip = match humanoid {
+			Some((_, true)) => {
+				Matrix4::new_rotation(Vector3::y() * std::f32::consts::PI)
+			}
+			_ => Matrix4::identity(),
+		};
+
+		let inverse_binds: Vec<Matrix4<f32>> = skin
+			.reader(get_buffer)
+			.read_inverse_bind_matrices()
+			.map(|m| m.map(Matrix4::from).collect())
+			.unwrap_or_else(|| vec![Matrix4::identity(); skin.joints().count()]);
+		let skin_joints: Vec<gltf::Node> = skin.joints().collect();
+		let joint_of_node =
+			|node: usize| skin_joints.iter().position(|j| j.index() == node);
+		let bind_positions: Vec<Point3<f32>> = inverse_binds
+			.iter()
+			.map(|m| {
+				let bind = m.try_inverse().unwrap_or_else(Matrix4::identity);
+				(flip * bind).transform_point(&Point3::origin())
+			})
+			.collect();
+
+		let mut parents = vec![None; skin_joints.len()];
+		for (i, joint) in skin_joints.iter().enumerate() {
+			for child in joint.children() {
+				if let Some(c) = joint_of_node(child.index()) {
+					parents[c] = Some(i);
+				}
+			}
+		}
+
+		let mut joints = Vec::new();
+		for (i, joint) in skin_joints.iter().enumerate() {
+			let (driver, segment) = match &humanoid {
+				Some((humanoid, _)) => {
+					let (driver, tip) = vrm_driver(humanoid, joint.index());
+					let segment = tip.and_then(joint_of_node).and_then(|tip| {
+						let offset = bind_positions[tip] - bind_positions[i];
+						Unit::try_new_and_get(offset, f32::EPSILON)
+					});
+					(driver, segment)
+				}
+				// Named joints point along -y, towards their first child.
+				None => {
+					let driver = joint
+						.name()
+						.and_then(|n| n.parse().ok())
+						.map(|kind| (kind, Anchor::Head));
+					let segment = joint
+						.children()
+						.find_map(|c| joint_of_node(c.index()))
+						.map(|c| bind_positions[i].y - bind_positions[c].y)
+						.filter(|&l| l > f32::EPSILON)
+						.map(|l| (-Vector3::y_axis(), l));
+					(driver, segment)
+				}
+			};
+			joints.push(Joint {
+				driver,
+				parent: parents[i],
+				bind_position: bind_positions[i],
+				segment,
+			});
+		}
+		if joints.iter().all(|j| j.driver.is_none()) {
+			return Err(eyre!("None of the joints belong to a humanoid bone"));
+		}
+
+		let mut vertices = Vec::new();
+		let mut indices = Vec::new();
+		for primitive in mesh.primitives() {
+			let reader = primitive.reader(get_buffer);
+			let offset = vertices.len() as u32;
+			let positions = reader
+				.read_positions()
+				.ok_or_else(|| eyre!("Mesh has no positions"))?;
+			let normals = reader
+				.read_normals()
+				.ok_or_else(|| eyre!("Mesh has no normals"))?;
+			let skin_joints = reader
+				.read_joints(0)
+				.ok_or_else(|| eyre!("Mesh has no joints"))?
+				.into_u16();
+			let weights = reader
+				.read_weights(0)
+				.ok_or_else(|| eyre!("Mesh has no weights"))?
+				.into_f32();
+			for (((position, normal), joints), weights) in
+				positions.zip(normals).zip(skin_joints).zip(weights)
+			{
+				vertices.push(SkinnedVertex {
+					position: flip.transform_point(&position.into()),
+					normal: flip.transform_vector(&normal.into()),
+					joints,
+					weights,
+				});
+			}
+			match reader.read_indices() {
+				Some(i) => indices.extend(i.into_u32().map(|i| i + offset)),
+				None => indices.extend(offset..vertices.len() as u32),
+			}
+		}
+		if indices.iter().any(|&i| i as usize >= vertices.len())
+			|| vertices
+				.iter()
+				.any(|v| v.joints.iter().any(|&j| j as usize >= joints.len()))
+		{
+			return Err(eyre!("Mesh has out of bounds indices"));
+		}
+
+		let mut avatar = Self {
+			joints,
+			vertices,
+			indices,
+		};
+		avatar.sort_joints();
+		Ok(avatar)
+	}
+
+	/// Reorders the joints so that parents come before their children.
+	fn sort_joints(&mut self) {
+		let depth = |mut i: usize| {
+			let mut depth = 0;
+			while let Some(parent) = self.joints[i].parent {
+				i = parent;
+				depth += 1;
+			}
+			depth
+		};
+		let mut order: Vec<usize> = (0..self.joints.len()).collect();
+		order.sort_by_key(|&i| depth(i));
+		let mut new_index = vec![0; order.len()];
+		for (new, &old) in order.iter().enumerate() {
+			new_index[old] = new;
+		}
+
+		let mut joints: Vec<Option<Joint>> = self.joints.drain(..).map(Some).collect();
+		self.joints = order
+			.iter()
+			.map(|&old| {
+				let mut joint = joints[old].take().unwrap();
+				joint.parent = joint.parent.map(|p| new_index[p]);
+				joint
+			})
+			.collect();
+		for v in self.vertices.iter_mut() {
+			for j in v.joints.iter_mut() {
+				*j = new_index[*j as usize] as u16;
+			}
+		}
+	}
+
+	/// Poses the avatar like `bones`. Parts of the mesh that belong to bones that
+	/// aren't visible collapse to a point, so they don't get drawn.
+	pub fn skin(&self, bones: &BoneMap<slimevr_skeleton::Bone>) -> Mesh {
+		let matrices: Vec<Matrix4<f32>> = self
+			.joints
+			.iter()
+			.zip(self.pose(bones))
+			.map(|(joint, pose)| match pose {
+				Some(pose) => pose.matrix(joint),
+				None => Matrix4::zeros(),
+			})
+			.collect();
+
+		let vertices = self
+			.vertices
+			.iter()
+			.map(|v| {
+				let mut position = Vector3::zeros();
+				let mut normal = Vector3::zeros();
+				for (&joint, &weight) in v.joints.iter().zip(v.weights.iter()) {
+					let m = &matrices[joint as usize];
+					position += weight * (m * v.position.to_homogeneous()).xyz();
+					normal += weight * m.transform_vector(&v.normal);
+				}
+				Vertex {
+					position: position.into(),
+					normal: normal
+						.try_normalize(f32::EPSILON)
+						.unwrap_or_else(Vector3::zeros),
+				}
+			})
+			.collect();
+
+		Mesh {
+			vertices,
+			indices: self.indices.clone(),
+		}
+	}
+
+	/// Where each joint should be, or `None` if it should be hidden.
+	fn pose(&self, bones: &BoneMap<slimevr_skeleton::Bone>) -> Vec<Option<JointPose>> {
+		let mut poses: Vec<Option<JointPose>> = Vec::with_capacity(self.joints.len());
+		for joint in self.joints.iter() {
+			let pose = match (joint.driver, joint.parent) {
+				(Some((kind, anchor)), _) => {
+					let bone = &bones[kind];
+					bone.is_visible.then(|| {
+						JointPose::driven(joint, &bone.iso, bone.length, anchor)
+					})
+				}
+				// Follow the parent, keeping our offset from the bind pose.
+				(None, Some(p)) => poses[p].map(|parent| {
+					let offset = joint.bind_position - self.joints[p].bind_position;
+					JointPose {
+						position: parent.position + parent.rotation * offset,
+						rotation: parent.rotation,
+						stretch: 1.,
+					}
+				}),
+				(None, None) => None,
+			};
+			poses.push(pose);
+		}
+		poses
+	}
+}
+
+/// The pose of a joint, relative to the bind pose.
+#[derive(Debug, Clone, Copy)]
+struct JointPose {
+	position: Point3<f32>,
+	rotation: UnitQuaternion<f32>,
+	/// How much to stretch the joint's segment to match the length of its bone.
+	stretch: f32,
+}
+impl JointPose {
+	fn driven(joint: &Joint, iso: &Isometry, length: f32, anchor: Anchor) -> Self {
+		// With an identity rotation, bones point along -y.
+		let (position, rest_direction) = match anchor {
+			Anchor::Head => (iso * Point3::origin(), -Vector3::y_axis()),
+			Anchor::Tail => (iso * Point3::new(0., -length, 0.), Vector3::y_axis()),
+		};
+		let (align, stretch) = match joint.segment {
+			Some((direction, rest_length)) => (
+				UnitQuaternion::rotation_between_axis(&direction, &rest_direction)
+					// Only fails when they point in opposite directions, where any
+					// perpendicular axis will do.
+					.unwrap_or_else(|| {
+						UnitQuaternion::from_axis_angle(
+							&Vector3::z_axis(),
+							std::f32::consts::PI,
+						)
+					}),
+				length / rest_length,
+			),
+			None => (UnitQuaternion::identity(), 1.),
+		};
+		Self {
+			position,
+			rotation: iso.rotation * align,
+			stretch,
+		}
+	}
+
+	/// Transforms from the bind pose to this pose.
+	fn matrix(&self, joint: &Joint) -> Matrix4<f32> {
+		let stretch = match joint.segment {
+			Some((direction, _)) => {
+				let d = direction.into_inner();
+				Matrix3::identity() + (self.stretch - 1.) * d * d.transpose()
+			}
+			None => Matrix3::identity(),
+		};
+		Translation3::from(self.position.coords).to_homogeneous()
+			* self.rotation.to_homogeneous()
+			* stretch.to_homogeneous()
+			* Translation3::from(-joint.bind_position.coords).to_homogeneous()
+	}
+}
+
+/// The humanoid bone names by glTF node index from a VRM 0.x or 1.0 extension, and
+/// whether the avatar faces +z.
+fn vrm_humanoid(document: &gltf::Document) -> Option<(HashMap<usize, String>, bool)> {
+	let extensions = document.extensions()?;
+	let node_index = |v: &Value| v.get("node")?.as_u64().map(|n| n as usize);
+	if let Some(humanoid) = extensions.get("VRMC_vrm").and_then(|v| v.get("humanoid")) {
+		let bones = humanoid
+			.get("humanBones")?
+			.as_object()?
+			.iter()
+			.filter_map(|(name, bone)| Some((node_index(bone)?, name.clone())))
+			.collect();
+		return Some((bones, true));
+	}
+	let bones = extensions
+		.get("VRM")?
+		.get("humanoid")?
+		.get("humanBones")?
+		.as_array()?
+		.iter()
+		.filter_map(|bone| {
+			let name = bone.get("bone")?.as_str()?;
+			Some((node_index(bone)?, name.to_owned()))
+		})
+		.collect();
+	// VRM 0.x avatars face -z, like OpenVR.
+	Some((bones, false))
+}
+
+/// The bone driving the joint at glTF node `node`, and the node that the joint's
+/// segment points to.
+fn vrm_driver(
+	humanoid: &HashMap<usize, String>,
+	node: usize,
+) -> (Option<(BoneKind, Anchor)>, Option<usize>) {
+	let Some(name) = humanoid.get(&node) else {
+		return (None, None);
+	};
+	let Some(&(_, kind, anchor, tip)) = VRM_BONES.iter().find(|(vrm, ..)| vrm == name) else {
+		return (None, None);
+	};
+	let tip = tip.and_then(|tip| {
+		humanoid
+			.iter()
+			.find_map(|(&node, name)| (name == tip).then_some(node))
+	});
+	(Some((kind, anchor)), tip)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn bundled_avatar_has_every_bone() {
+		let avatar = Avatar::bundled().unwrap();
+		for kind in BoneKind::iter() {
+			assert!(
+				avatar
+					.joints
+					.iter()
+					.any(|j| j.driver == Some((kind, Anchor::Head))),
+				"{kind:?} is missing"
+			);
+		}
+		assert!(!avatar.vertices.is_empty());
+		assert_eq!(avatar.indices.len() % 3, 0);
+	}
+
+	#[test]
+	fn joints_are_sorted() {
+		let avatar = Avatar::bundled().unwrap();
+		for (i, joint) in avatar.joints.iter().enumerate() {
+			if let Some(parent) = joint.parent {
+				assert!(parent < i);
+			}
+		}
+	}
+}
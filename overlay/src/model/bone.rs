@@ -1,21 +1,32 @@
+use crate::model::{budget, ovr_transform, Capsule, Isometry};
 use crate::RGBA;
 
 use eyre::{Result, WrapErr};
 use nalgebra::{Isometry3, UnitQuaternion, Vector3};
 use ovr_overlay::overlay::{OverlayHandle, OverlayManager};
-use ovr_overlay::pose::{Matrix3x4, TrackingUniverseOrigin};
-use ovr_overlay::ColorTint;
-
-pub type Isometry = nalgebra::Isometry3<f32>;
+use ovr_overlay::pose::TrackingUniverseOrigin;
+use ovr_overlay::{ColorTint, TrackedDeviceIndex};
+use std::cell::Cell;
 
+/// A [`slimevr_skeleton::Bone`], drawn as two overlays that face away from each
+/// other, so that it can be seen from both sides.
 #[derive(Debug)]
 pub struct Bone {
-	overlays: (OverlayHandle, OverlayHandle),
-	iso: Isometry,
+	/// `None` if the bone is drawn along with the others of a
+	/// [`Merged`](super::merged::Merged) skeleton instead.
+	overlays: Option<(OverlayHandle, OverlayHandle)>,
+	pose: slimevr_skeleton::Bone,
 	color: RGBA,
-	radius: f32,
-	length: f32,
-	is_visible: bool,
+	/// The device that `iso` is relative to, or `None` if it is in the standing
+	/// tracking space.
+	anchor: Option<TrackedDeviceIndex>,
+	/// Whether anything changed since the overlays were last updated. Unchanged
+	/// bones aren't sent to SteamVR again, which is most of them while standing
+	/// still.
+	dirty: Cell<bool>,
+	/// Whether the overlays are hidden, since a merged skeleton draws the bone for
+	/// now, see [`Bone::set_merged`].
+	merged: bool,
 }
 impl Bone {
 	pub fn new(
@@ -29,9 +40,7 @@ impl Bone {
 		let keys = (format!("{key}_0"), format!("{key}_1"));
 
 		let mut init_overlay = |key: &str| -> Result<OverlayHandle> {
-			let overlay = mngr
-				.create_overlay(key, key)
-				.wrap_err("Failed to create overlay")?;
+			let overlay = budget::create_overlay(mngr, key)?;
 			mngr.set_curvature(overlay, 1.)
 				.wrap_err("Failed to set curvature")?;
 			mngr.set_raw_data(overlay, &[255u8; 4], 1, 1, 4)
@@ -42,17 +51,39 @@ impl Bone {
 
 		let overlays = (init_overlay(&keys.0)?, init_overlay(&keys.1)?);
 
-		Ok(Self {
-			overlays,
-			iso: isometry,
-			radius,
-			length,
+		let mut bone = Self::without_overlays(color, isometry, radius, length);
+		bone.overlays = Some(overlays);
+		Ok(bone)
+	}
+
+	/// A bone that isn't drawn on its own, for a [`Merged`](super::merged::Merged)
+	/// skeleton.
+	pub fn without_overlays(
+		color: RGBA,
+		isometry: Isometry,
+		radius: f32, // meters
+		length: f32, // meters
+	) -> Self {
+		let mut pose = slimevr_skeleton::Bone::new(length, radius);
+		pose.iso = isometry;
+		Self {
+			overlays: None,
+			pose,
 			color,
-			is_visible: false,
-		})
+			anchor: None,
+			dirty: Cell::new(true),
+			merged: false,
+		}
 	}
 
 	pub fn update_render(&self, mngr: &mut OverlayManager<'_>) -> Result<()> {
+		if self.merged {
+			return Ok(());
+		}
+		let Some(overlays) = self.overlays.filter(|_| self.dirty.get()) else {
+			return Ok(());
+		};
+
 		// Set Color
 		{
 			fn f(color: u8) -> f32 {
@@ -64,29 +95,29 @@ impl Bone {
 				b: f(self.color.b),
 				a: f(self.color.a),
 			};
-			mngr.set_tint(self.overlays.0, tint)
-				.and_then(|_| mngr.set_tint(self.overlays.1, tint))
+			mngr.set_tint(overlays.0, tint)
+				.and_then(|_| mngr.set_tint(overlays.1, tint))
 				.wrap_err("Failed to set color")?;
 		}
 
 		// Set width and height
 		{
 			let mut f = |overlay| -> Result<()> {
-				mngr.set_width(overlay, self.circumference())
+				mngr.set_width(overlay, self.pose.circumference())
 					.wrap_err("Failed to set radius")?;
-				let aspect = self.circumference() / self.length;
+				let aspect = self.pose.circumference() / self.pose.length;
 				mngr.set_texel_aspect(overlay, aspect)
 					.wrap_err("Failed to set texture aspect ratio")?;
 
 				Ok(())
 			};
 
-			f(self.overlays.0)?;
-			f(self.overlays.1)?;
+			f(overlays.0)?;
+			f(overlays.1)?;
 		}
 
-		mngr.set_visibility(self.overlays.0, self.is_visible)
-			.and_then(|_| mngr.set_visibility(self.overlays.1, self.is_visible))
+		mngr.set_visibility(overlays.0, self.pose.is_visible)
+			.and_then(|_| mngr.set_visibility(overlays.1, self.pose.is_visible))
 			.wrap_err("Failed to show overlay")?;
 
 		// Set transform
@@ -106,7 +137,7 @@ impl Bone {
 				{
 					// just use the existing rotation, there won't be any distortion
 					iso.translation.vector += iso.rotation.transform_vector(
-						&Vector3::new(0., -self.length / 2.0, -self.radius),
+						&Vector3::new(0., -self.pose.length / 2.0, -self.pose.radius),
 					);
 					iso.to_homogeneous().remove_fixed_rows::<1>(3)
 				} else {
@@ -127,18 +158,27 @@ impl Bone {
 
 					// Fixes the "center of tube" issue and the "center of overlay"
 					// issue
-					iso.translation.vector += z_direction * -self.radius;
-					iso.translation.vector -= y_direction * self.length / 2.0;
+					iso.translation.vector += z_direction * -self.pose.radius;
+					iso.translation.vector -= y_direction * self.pose.length / 2.0;
 
 					iso.to_homogeneous().remove_fixed_rows::<1>(3)
 				};
 
-				let col_major_3x4 = Matrix3x4::from(&transform);
-				mngr.set_transform_absolute(
-					overlay,
-					TrackingUniverseOrigin::TrackingUniverseStanding,
-					&col_major_3x4,
-				)
+				let col_major_3x4 = ovr_transform(&transform);
+				// SteamVR moves anchored overlays along with the device itself, so
+				// they don't lag behind like they would if we did it every frame.
+				match self.anchor {
+					Some(device) => mngr.set_transform_tracked_device_relative(
+						overlay,
+						device,
+						&col_major_3x4,
+					),
+					None => mngr.set_transform_absolute(
+						overlay,
+						TrackingUniverseOrigin::TrackingUniverseStanding,
+						&col_major_3x4,
+					),
+				}
 				.wrap_err("Failed to set transform")?;
 				Ok(())
 			};
@@ -148,39 +188,101 @@ impl Bone {
 					&Vector3::y_axis(),
 					std::f32::consts::PI,
 				);
-				rotation = self.iso.rotation * rotation;
+				rotation = self.pose.iso.rotation * rotation;
 				Isometry3 {
 					rotation,
-					translation: self.iso.translation,
+					translation: self.pose.iso.translation,
 				}
 			};
 
-			f(self.overlays.0, self.iso, 1.0)?;
-			f(self.overlays.1, flipped, -1.0)?;
+			f(overlays.0, self.pose.iso, 1.0)?;
+			f(overlays.1, flipped, -1.0)?;
 		}
 
+		self.dirty.set(false);
+		Ok(())
+	}
+
+	/// Hides the overlays while `merged`, for when a
+	/// [`Merged`](super::merged::Merged) skeleton draws the bone for a while
+	/// instead.
+	pub fn set_merged(
+		&mut self,
+		mngr: &mut OverlayManager,
+		merged: bool,
+	) -> Result<()> {
+		if let (Some(overlays), true) = (self.overlays, merged) {
+			mngr.set_visibility(overlays.0, false)
+				.and_then(|_| mngr.set_visibility(overlays.1, false))
+				.wrap_err("Failed to hide overlay")?;
+		}
+		self.merged = merged;
+		// Whatever changed in the meantime wasn't sent.
+		self.dirty.set(true);
 		Ok(())
 	}
 
 	pub fn set_isometry(&mut self, isometry: Isometry) {
-		self.iso = isometry;
+		self.dirty
+			.set(self.dirty.get() || self.pose.iso != isometry);
+		self.pose.iso = isometry;
+	}
+
+	/// Makes the isometry relative to `device`, instead of the standing tracking
+	/// space.
+	pub fn set_anchor(&mut self, device: Option<TrackedDeviceIndex>) {
+		self.dirty.set(true);
+		self.anchor = device;
+	}
+
+	pub fn isometry(&self) -> &Isometry {
+		&self.pose.iso
 	}
 
 	pub fn set_length(&mut self, length: f32) {
 		assert!(length >= 0., "Length must be positive");
-		self.length = length;
+		self.dirty
+			.set(self.dirty.get() || self.pose.length != length);
+		self.pose.length = length;
 	}
 
-	#[allow(unused)]
 	pub fn set_radius(&mut self, radius: f32) {
-		self.radius = radius;
+		self.dirty
+			.set(self.dirty.get() || self.pose.radius != radius);
+		self.pose.radius = radius;
 	}
 
-	pub fn circumference(&self) -> f32 {
-		2. * std::f32::consts::PI * self.radius
+	pub fn set_visibility(&mut self, is_visible: bool) {
+		self.dirty
+			.set(self.dirty.get() || self.pose.is_visible != is_visible);
+		self.pose.is_visible = is_visible;
 	}
 
-	pub fn set_visibility(&mut self, is_visible: bool) {
-		self.is_visible = is_visible;
+	pub fn is_visible(&self) -> bool {
+		self.pose.is_visible
+	}
+
+	pub fn set_color(&mut self, color: RGBA) {
+		self.dirty.set(self.dirty.get() || self.color != color);
+		self.color = color;
+	}
+
+	pub fn color(&self) -> RGBA {
+		self.color
+	}
+
+	pub fn length(&self) -> f32 {
+		self.pose.length
+	}
+
+	/// Where the bone is and how it is shaped, without the overlays.
+	pub fn pose(&self) -> &slimevr_skeleton::Bone {
+		&self.pose
+	}
+
+	/// The shape of the bone, which extends from the head of the bone along its
+	/// negative y axis.
+	pub fn capsule(&self) -> Capsule {
+		self.pose.capsule()
 	}
 }
@@ -0,0 +1,55 @@
+//! SteamVR only lets each process have [`MAX_OVERLAYS`] overlays, and each bone of
+//! a skeleton takes two of them. With the HUD, the trails and the smoothing preview
+//! on top of the skeleton, they run out quickly, and creating more fails. So the
+//! overlays are counted as they are created, and skeletons that wouldn't fit
+//! anymore are drawn on a single overlay instead, see [`Merged`].
+//!
+//! [`Merged`]: super::merged::Merged
+
+use crate::model::spine;
+use crate::model::BoneKind;
+
+use eyre::{Result, WrapErr};
+use ovr_overlay::overlay::{OverlayHandle, OverlayManager};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// `k_unMaxOverlayCount` of OpenVR.
+pub const MAX_OVERLAYS: usize = 128;
+/// How many are left for the panels and markers that are created after the
+/// skeletons. Running into it is warned about.
+pub const RESERVE: usize = 16;
+/// How many overlays a skeleton takes, with its spine.
+pub const SKELETON: usize = 2 * (BoneKind::num_types() + spine::NUM_SEGMENTS);
+
+/// How many overlays were created in this session.
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Starts counting from zero, for a new session. The overlays of the last one went
+/// away with its context.
+pub fn reset() {
+	CREATED.store(0, Ordering::Relaxed);
+}
+
+/// How many more overlays SteamVR lets us create.
+pub fn remaining() -> usize {
+	MAX_OVERLAYS.saturating_sub(CREATED.load(Ordering::Relaxed))
+}
+
+/// Creates an overlay with `key` as its key and name, and counts it.
+pub fn create_overlay(mngr: &mut OverlayManager, key: &str) -> Result<OverlayHandle> {
+	let overlay = mngr.create_overlay(key, key).wrap_err_with(|| {
+		format!(
+			"Failed to create overlay, with {} of SteamVR's {MAX_OVERLAYS} left",
+			remaining()
+		)
+	})?;
+	let created = CREATED.fetch_add(1, Ordering::Relaxed) + 1;
+	if created == MAX_OVERLAYS - RESERVE {
+		log::warn!(
+			"{created} of SteamVR's {MAX_OVERLAYS} overlays are in use, so what is \
+			 created from now on might not fit. Turning off the HUD, the trails or \
+			 the smoothing preview leaves more of them for the rest"
+		);
+	}
+	Ok(overlay)
+}
@@ -0,0 +1,158 @@
+//! The context panel, which shows the details of the bone selected with a
+//! controller, see [`crate::picking`]: the tracker on it, its battery, how often the
+//! bone updates, and how late it is drawn. Below that are the quick actions, which
+//! are bound to controller buttons in SteamVR, see [`crate::input`].
+
+use crate::model::image::{Image, GLYPH_SIZE};
+use crate::model::BoneKind;
+use crate::model::{budget, ovr_transform};
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use nalgebra::Translation3;
+use ovr_overlay::overlay::{OverlayHandle, OverlayManager};
+use ovr_overlay::TrackedDeviceIndex;
+use std::time::Duration;
+
+const WIDTH: usize = 512;
+const LINE_HEIGHT: usize = GLYPH_SIZE + 2;
+const MARGIN: usize = 4;
+const LINES: usize = 8;
+/// The width of the panel in meters.
+const PANEL_WIDTH: f32 = 0.4;
+/// Where the panel goes, relative to the headset. Below where the user looks, so
+/// that it doesn't cover the bone.
+const PANEL_OFFSET: [f32; 3] = [0., -0.35, -0.8];
+const BACKGROUND: RGBA = RGBA::new(0, 0, 0, 192);
+
+/// What the panel shows about the selected bone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoneDetails {
+	pub bone: BoneKind,
+	/// The name of the tracker on the bone, if there is one.
+	pub tracker: Option<String>,
+	/// The battery of its device in percent, if it reports one.
+	pub battery: Option<u8>,
+	/// How often the bone updates.
+	pub rate_hz: f32,
+	/// How late bones are drawn, once enough updates were measured.
+	pub latency: Option<Duration>,
+	pub is_hidden: bool,
+}
+
+fn lines(details: &BoneDetails) -> Vec<(String, RGBA)> {
+	let tracker = match &details.tracker {
+		Some(name) => format!("Tracker: {name}"),
+		None => "No tracker, the server computes it".to_owned(),
+	};
+	let battery = details
+		.battery
+		.map_or_else(|| "unknown".to_owned(), |b| format!("{b}%"));
+	let latency = details.latency.map_or_else(
+		|| "measuring".to_owned(),
+		|l| format!("{:.0} ms", l.as_secs_f32() * 1000.),
+	);
+	let hide = if details.is_hidden { "Show" } else { "Hide" };
+	vec![
+		(format!("{:?}", details.bone), RGBA::YELLOW),
+		(tracker, RGBA::WHITE),
+		(format!("Battery: {battery}"), RGBA::WHITE),
+		(format!("Updates: {:.0} Hz", details.rate_hz), RGBA::WHITE),
+		(format!("Latency: {latency}"), RGBA::WHITE),
+		(String::new(), RGBA::WHITE),
+		(
+			format!("{hide}, Recolor and Identify with the buttons bound in SteamVR"),
+			RGBA::SILVER,
+		),
+	]
+}
+
+fn render(lines: &[(String, RGBA)], image: &mut Image) {
+	image.fill(BACKGROUND);
+	for (i, (text, color)) in lines.iter().take(LINES).enumerate() {
+		image.text(MARGIN, MARGIN + i * LINE_HEIGHT, text, *color);
+	}
+}
+
+/// Hidden while no bone is selected.
+pub struct ContextPanel {
+	overlay: OverlayHandle,
+	image: Image,
+	/// The lines as of the last draw.
+	drawn: Vec<(String, RGBA)>,
+	visible: bool,
+}
+impl ContextPanel {
+	pub fn new(mngr: &mut OverlayManager, key: &str) -> Result<Self> {
+		let key = format!("{key}: ContextPanel");
+		let overlay = budget::create_overlay(mngr, &key)?;
+		mngr.set_width(overlay, PANEL_WIDTH)
+			.wrap_err("Failed to set width")?;
+		let [x, y, z] = PANEL_OFFSET;
+		let transform = Translation3::new(x, y, z)
+			.to_homogeneous()
+			.remove_fixed_rows::<1>(3);
+		mngr.set_transform_tracked_device_relative(
+			overlay,
+			TrackedDeviceIndex::HMD,
+			&ovr_transform(&transform),
+		)
+		.wrap_err("Failed to set transform")?;
+		Ok(Self {
+			overlay,
+			image: Image::new(WIDTH, LINES * LINE_HEIGHT + 2 * MARGIN),
+			drawn: Vec::new(),
+			visible: false,
+		})
+	}
+
+	/// Shows `details` if `visible`, redrawing them if they changed.
+	pub fn update_render(
+		&mut self,
+		mngr: &mut OverlayManager,
+		visible: bool,
+		details: Option<&BoneDetails>,
+	) -> Result<()> {
+		let visible = visible && details.is_some();
+		if let Some(details) = details.filter(|_| visible) {
+			let lines = lines(details);
+			if lines != self.drawn {
+				render(&lines, &mut self.image);
+				let Image { width, height, .. } = self.image;
+				mngr.set_raw_data(self.overlay, &self.image.pixels, width, height, 4)
+					.wrap_err("Failed to draw context panel")?;
+				self.drawn = lines;
+			}
+		}
+		if visible != self.visible {
+			self.visible = visible;
+			mngr.set_visibility(self.overlay, visible)
+				.wrap_err("Failed to show or hide context panel")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fits_the_panel() {
+		let details = BoneDetails {
+			bone: BoneKind::UpperArmL,
+			tracker: Some("Left upper arm (3.0)".to_owned()),
+			battery: Some(81),
+			rate_hz: 99.6,
+			latency: Some(Duration::from_millis(23)),
+			is_hidden: false,
+		};
+		let lines = lines(&details);
+		assert!(lines.len() <= LINES);
+		assert_eq!(lines[2].0, "Battery: 81%");
+		assert_eq!(lines[3].0, "Updates: 100 Hz");
+		for (text, _) in &lines {
+			assert!(text.len() * GLYPH_SIZE + 2 * MARGIN <= WIDTH, "{text}");
+		}
+	}
+}
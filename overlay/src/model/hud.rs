@@ -0,0 +1,121 @@
+//! A scaled down copy of the skeleton, anchored to a corner of the headset's view,
+//! so that the whole pose can be checked at a glance without looking down.
+//!
+//! The copy is centered on the hip, and turned so that the direction the headset
+//! faces points into the screen. The user sees themselves from behind, and turning
+//! the body relative to the head shows up as the copy turning. SteamVR keeps the
+//! overlays locked to the headset, so they don't lag behind head motion.
+
+use crate::model::skeleton::{Skeleton, SkeletonBuilder};
+use crate::model::{BoneKind, Isometry};
+
+use eyre::Result;
+use nalgebra::{Point3, Translation3, UnitQuaternion, Vector3};
+use ovr::pose::TrackingUniverseOrigin;
+use ovr::TrackedDeviceIndex;
+use ovr_overlay as ovr;
+use ovr_overlay::overlay::OverlayManager;
+
+/// Thinner than the full size bones, so the copy doesn't cover more of the view
+/// than needed.
+const HUD_BONE_RADIUS: f32 = 0.001;
+
+/// The transform of the headset, in the standing tracking space.
+fn hmd_pose(context: &ovr::Context) -> Option<[[f32; 4]; 3]> {
+	let poses = context.system_mngr().get_device_to_absolute_tracking_pose(
+		TrackingUniverseOrigin::TrackingUniverseStanding,
+		0.,
+	);
+	let pose = poses.get(0).filter(|p| p.bPoseIsValid)?;
+	Some(pose.mDeviceToAbsoluteTracking.m)
+}
+
+/// The direction the headset faces, in the standing tracking space.
+pub fn hmd_forward(context: &ovr::Context) -> Option<Vector3<f32>> {
+	let m = hmd_pose(context)?;
+	// The headset looks along its negative z axis.
+	Some(Vector3::new(-m[0][2], -m[1][2], -m[2][2]))
+}
+
+/// Where the headset is, in the standing tracking space.
+pub fn hmd_position(context: &ovr::Context) -> Option<Point3<f32>> {
+	let m = hmd_pose(context)?;
+	Some(Point3::new(m[0][3], m[1][3], m[2][3]))
+}
+
+pub struct MiniSkeleton {
+	skeleton: Skeleton,
+	/// Where the hip goes, relative to the headset.
+	offset: Vector3<f32>,
+	scale: f32,
+}
+impl MiniSkeleton {
+	pub fn new(
+		mngr: &mut OverlayManager,
+		offset: Vector3<f32>,
+		scale: f32,
+	) -> Result<Self> {
+		let mut skeleton = SkeletonBuilder::default()
+			.key("slimevr-hud")
+			.bone_radius(HUD_BONE_RADIUS)
+			.build(mngr)?;
+		skeleton.set_anchor(Some(TrackedDeviceIndex::HMD));
+		Ok(Self {
+			skeleton,
+			offset,
+			scale,
+		})
+	}
+
+	/// Copies the pose of `source`. Call after the visibility of its bones has been
+	/// decided, but before its spine is updated, which hides some of them.
+	pub fn update(
+		&mut self,
+		source: &Skeleton,
+		hmd_forward: Option<Vector3<f32>>,
+		straight_spine: bool,
+	) {
+		let center = [BoneKind::Hip, BoneKind::Head]
+			.into_iter()
+			.map(|kind| &source.bones[kind])
+			.find(|bone| bone.is_visible())
+			.map(|bone| bone.isometry().translation);
+		let (Some(center), Some(forward)) = (center, hmd_forward) else {
+			for kind in BoneKind::iter() {
+				self.skeleton.set_visibility(kind, false);
+			}
+			self.skeleton.update_spine(false);
+			return;
+		};
+		// Only the yaw of the headset, the copy shouldn't tip over when looking down.
+		let yaw = f32::atan2(-forward.x, -forward.z);
+		let to_hud = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), -yaw);
+
+		for (kind, bone) in source.bones.iter() {
+			let iso = bone.isometry();
+			let relative =
+				to_hud * (iso.translation.vector - center.vector) * self.scale;
+			self.skeleton.set_isometry(
+				kind,
+				Isometry::from_parts(
+					Translation3::from(self.offset + relative),
+					to_hud * iso.rotation,
+				),
+			);
+			self.skeleton.set_length(kind, bone.length() * self.scale);
+			self.skeleton.set_color(kind, bone.color());
+			self.skeleton.set_visibility(kind, bone.is_visible());
+		}
+		self.skeleton.update_spine(!straight_spine);
+	}
+
+	pub fn update_render(&mut self, mngr: &mut OverlayManager) -> Result<()> {
+		for kind in BoneKind::iter() {
+			self.skeleton.update_render(kind, mngr)?;
+		}
+		self.skeleton.update_spine_render(mngr)?;
+		// The copy is anchored to the headset.
+		self.skeleton
+			.update_merged_render(mngr, Some(Point3::origin()))
+	}
+}
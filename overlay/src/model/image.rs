@@ -0,0 +1,87 @@
+//! Images that are drawn on the CPU, for the panels that are uploaded to SteamVR as
+//! raw pixels.
+
+use crate::RGBA;
+
+use font8x8::{UnicodeFonts, BASIC_FONTS};
+
+/// The size of a character of [`Image::text`], in pixels.
+pub const GLYPH_SIZE: usize = 8;
+
+/// An RGBA image, row by row.
+pub struct Image {
+	pub width: usize,
+	pub height: usize,
+	pub pixels: Vec<u8>,
+}
+impl Image {
+	pub fn new(width: usize, height: usize) -> Self {
+		Self {
+			width,
+			height,
+			pixels: vec![0; width * height * 4],
+		}
+	}
+
+	pub fn fill(&mut self, color: RGBA) {
+		for pixel in self.pixels.chunks_exact_mut(4) {
+			pixel.copy_from_slice(&[color.r, color.g, color.b, color.a]);
+		}
+	}
+
+	/// Pixels outside of the image are ignored.
+	pub fn set(&mut self, x: f32, y: f32, color: RGBA) {
+		let (x, y) = (x.round(), y.round());
+		if x < 0. || y < 0. || x >= self.width as f32 || y >= self.height as f32 {
+			return;
+		}
+		let i = (y as usize * self.width + x as usize) * 4;
+		self.pixels[i..i + 4].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+	}
+
+	pub fn line(&mut self, from: (f32, f32), to: (f32, f32), color: RGBA) {
+		let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+		let steps = dx.abs().max(dy.abs()).ceil().max(1.);
+		for i in 0..=steps as usize {
+			let t = i as f32 / steps;
+			self.set(from.0 + dx * t, from.1 + dy * t, color);
+		}
+	}
+
+	/// Draws `pixels`, an RGBA image of the same size, on top, by their alpha.
+	#[cfg(feature = "scene")]
+	pub fn blend(&mut self, pixels: &[u8]) {
+		for (dst, src) in self.pixels.chunks_exact_mut(4).zip(pixels.chunks_exact(4)) {
+			let alpha = u16::from(src[3]);
+			for (d, &s) in dst[..3].iter_mut().zip(&src[..3]) {
+				*d = ((u16::from(s) * alpha + u16::from(*d) * (255 - alpha)) / 255)
+					as u8;
+			}
+		}
+	}
+
+	pub fn hline(&mut self, y: usize, color: RGBA) {
+		self.line((0., y as f32), ((self.width - 1) as f32, y as f32), color);
+	}
+
+	/// Draws one line of text with its top left corner at `(x, y)`, in an 8x8 pixel
+	/// font. Characters that the font doesn't have show up as `?`, and whatever
+	/// doesn't fit is cut off.
+	pub fn text(&mut self, x: usize, y: usize, text: &str, color: RGBA) {
+		let fits = self.width.saturating_sub(x) / GLYPH_SIZE;
+		for (i, c) in text.chars().take(fits).enumerate() {
+			let glyph = BASIC_FONTS.get(c).or_else(|| BASIC_FONTS.get('?'));
+			let Some(glyph) = glyph else {
+				continue;
+			};
+			let left = x + i * GLYPH_SIZE;
+			for (row, bits) in glyph.into_iter().enumerate() {
+				for column in (0..GLYPH_SIZE).filter(|column| bits & (1 << column) != 0)
+				{
+					let (px, py) = (left + column, y + row);
+					self.set(px as f32, py as f32, color);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,171 @@
+//! The log console, a panel above the headset's view with the last lines that were
+//! logged. Errors are drawn in red and warnings in yellow, newest at the bottom.
+
+use crate::config;
+use crate::log_buffer::{LogBuffer, LogLine};
+use crate::model::image::{Image, GLYPH_SIZE};
+use crate::model::{budget, ovr_transform};
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use log::Level;
+use nalgebra::Translation3;
+use ovr_overlay::overlay::{OverlayHandle, OverlayManager};
+use ovr_overlay::TrackedDeviceIndex;
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 768;
+const LINE_HEIGHT: usize = GLYPH_SIZE + 2;
+const MARGIN: usize = 4;
+/// The width of the panel in meters.
+const PANEL_WIDTH: f32 = 0.6;
+/// Where the panel goes, relative to the headset.
+const PANEL_OFFSET: [f32; 3] = [0., 0.25, -0.9];
+/// Uploading the image is slow, so it is redrawn at most this often.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+const BACKGROUND: RGBA = RGBA::new(0, 0, 0, 192);
+
+fn color(level: Level) -> RGBA {
+	match level {
+		Level::Error => RGBA::RED,
+		Level::Warn => RGBA::YELLOW,
+		Level::Info => RGBA::WHITE,
+		Level::Debug | Level::Trace => RGBA::SILVER,
+	}
+}
+
+/// Draws `lines` from the top, so that the newest is at the bottom once the panel is
+/// full.
+fn render(lines: &[LogLine], image: &mut Image) {
+	image.fill(BACKGROUND);
+	for (i, line) in lines.iter().enumerate() {
+		let y = MARGIN + i * LINE_HEIGHT;
+		image.text(MARGIN, y, &line.text, color(line.level));
+	}
+}
+
+/// Hidden unless it is toggled on, from the config or the tray.
+pub struct LogPanel {
+	overlay: OverlayHandle,
+	buffer: LogBuffer,
+	lines: usize,
+	level: Level,
+	image: Image,
+	/// The generation of the buffer as of the last draw.
+	drawn: Option<u64>,
+	last_draw: Option<Instant>,
+	visible: bool,
+}
+impl LogPanel {
+	/// How often to look for new lines while there are no feed updates, like when
+	/// the server can't be reached.
+	pub const POLL_INTERVAL: Duration = REDRAW_INTERVAL;
+
+	pub fn new(
+		mngr: &mut OverlayManager,
+		key: &str,
+		buffer: LogBuffer,
+		config: &config::LogConsole,
+	) -> Result<Self> {
+		let key = format!("{key}: LogPanel");
+		let overlay = budget::create_overlay(mngr, &key)?;
+		mngr.set_width(overlay, PANEL_WIDTH)
+			.wrap_err("Failed to set width")?;
+		let [x, y, z] = PANEL_OFFSET;
+		let transform = Translation3::new(x, y, z)
+			.to_homogeneous()
+			.remove_fixed_rows::<1>(3);
+		mngr.set_transform_tracked_device_relative(
+			overlay,
+			TrackedDeviceIndex::HMD,
+			&ovr_transform(&transform),
+		)
+		.wrap_err("Failed to set transform")?;
+		let height = config.lines * LINE_HEIGHT + 2 * MARGIN;
+		Ok(Self {
+			overlay,
+			buffer,
+			lines: config.lines,
+			level: config.level.into(),
+			image: Image::new(WIDTH, height),
+			drawn: None,
+			last_draw: None,
+			visible: false,
+		})
+	}
+
+	/// Shows or hides the panel, and redraws it if something was logged since the
+	/// last draw and it is time to.
+	pub fn update_render(
+		&mut self,
+		mngr: &mut OverlayManager,
+		visible: bool,
+		now: Instant,
+	) -> Result<()> {
+		if !visible {
+			if self.visible {
+				self.visible = false;
+				mngr.set_visibility(self.overlay, false)
+					.wrap_err("Failed to hide log console")?;
+			}
+			return Ok(());
+		}
+		let generation = self.buffer.generation();
+		let due = self.last_draw.map_or(true, |t| now - t >= REDRAW_INTERVAL);
+		if self.drawn != Some(generation) && due {
+			self.drawn = Some(generation);
+			self.last_draw = Some(now);
+			render(&self.buffer.last(self.lines, self.level), &mut self.image);
+			let Image { width, height, .. } = self.image;
+			mngr.set_raw_data(self.overlay, &self.image.pixels, width, height, 4)
+				.wrap_err("Failed to draw log console")?;
+		}
+		if !self.visible {
+			self.visible = true;
+			mngr.set_visibility(self.overlay, true)
+				.wrap_err("Failed to show log console")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn line(level: Level, text: &str) -> LogLine {
+		LogLine {
+			level,
+			text: text.to_owned(),
+		}
+	}
+
+	/// The colors that occur in row `y`.
+	fn colors(image: &Image, y: usize) -> Vec<RGBA> {
+		let row = &image.pixels[y * image.width * 4..][..image.width * 4];
+		let mut colors: Vec<RGBA> = Vec::new();
+		for p in row.chunks_exact(4) {
+			let color = RGBA::new(p[0], p[1], p[2], p[3]);
+			if !colors.contains(&color) {
+				colors.push(color);
+			}
+		}
+		colors
+	}
+
+	#[test]
+	fn lines_are_colored_by_severity() {
+		let lines = [line(Level::Error, "Failed"), line(Level::Warn, "Slow")];
+		let mut image = Image::new(WIDTH, 2 * LINE_HEIGHT + 2 * MARGIN);
+		render(&lines, &mut image);
+		// The middle of the first and second line.
+		let first = MARGIN + GLYPH_SIZE / 2;
+		assert_eq!(colors(&image, first), [BACKGROUND, RGBA::RED]);
+		assert_eq!(
+			colors(&image, first + LINE_HEIGHT),
+			[BACKGROUND, RGBA::YELLOW]
+		);
+		// The gap between them.
+		assert_eq!(colors(&image, MARGIN + GLYPH_SIZE), [BACKGROUND]);
+	}
+}
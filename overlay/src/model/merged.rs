@@ -0,0 +1,278 @@
+//! A skeleton drawn on a single overlay, for when SteamVR is running out of them,
+//! see [`super::budget`]. The overlay is a panel through the middle of the bones
+//! that faces the headset, with the bones drawn on it the way they look from
+//! there. Since it is flat, the bones further away are drawn first, and those in
+//! front of them on top.
+//!
+//! Uploading the image is slow, so it's only redrawn every so often, which makes it
+//! look choppier than the bones on their own overlays.
+
+use crate::model::image::Image;
+use crate::model::Bone;
+use crate::model::{budget, ovr_transform};
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use nalgebra::{Isometry3, Point3, UnitQuaternion, Vector3};
+use ovr_overlay::overlay::{OverlayHandle, OverlayManager};
+use ovr_overlay::pose::TrackingUniverseOrigin;
+use ovr_overlay::TrackedDeviceIndex;
+use std::time::{Duration, Instant};
+
+/// The width and height of the image, in pixels.
+const SIZE: usize = 256;
+/// Uploading the image is slow, so it is only redrawn this often.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(33);
+/// The smallest width of the panel in meters, so that a lone bone isn't blown up.
+const MIN_WIDTH: f32 = 0.5;
+/// The room around the bones, in meters.
+const MARGIN: f32 = 0.05;
+/// How thick bones are drawn at least, in pixels, so that they don't disappear.
+const MIN_THICKNESS: f32 = 2.;
+const BACKGROUND: RGBA = RGBA::new(0, 0, 0, 0);
+
+/// A bone, as the line from its head to its tail.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+	head: Point3<f32>,
+	tail: Point3<f32>,
+	radius: f32,
+	color: RGBA,
+}
+impl Segment {
+	fn of(bone: &Bone) -> Self {
+		let iso = bone.isometry();
+		Self {
+			head: iso.translation.vector.into(),
+			tail: iso * Point3::new(0., -bone.length(), 0.),
+			radius: bone.pose().radius,
+			color: bone.color(),
+		}
+	}
+}
+
+/// Where the panel goes, and how wide it is.
+#[derive(Debug, Clone, Copy)]
+struct View {
+	/// The middle of the panel, with its z axis pointing at the eye.
+	iso: Isometry3<f32>,
+	width: f32,
+}
+impl View {
+	/// The panel through the middle of `segments` that faces `eye`, and fits all of
+	/// them. `None` without any segments, or with the eye right in the middle.
+	fn new(segments: &[Segment], eye: &Point3<f32>) -> Option<Self> {
+		let points = || segments.iter().flat_map(|s| [(s.head, s), (s.tail, s)]);
+		let count = 2 * segments.len();
+		if count == 0 {
+			return None;
+		}
+		let sum: Vector3<f32> = points().map(|(p, _)| p.coords).sum();
+		let center = Point3::from(sum / count as f32);
+		let to_eye = eye - center;
+		if to_eye.norm() < MARGIN {
+			return None;
+		}
+		// Any up will do when looking straight up or down at the bones.
+		let up = if to_eye.normalize().y.abs() < 0.999 {
+			Vector3::y()
+		} else {
+			Vector3::z()
+		};
+		let iso = Isometry3::from_parts(
+			center.coords.into(),
+			UnitQuaternion::face_towards(&to_eye, &up),
+		);
+		let half_width = points()
+			.map(|(p, s)| {
+				let local = iso.inverse_transform_point(&p);
+				local.x.abs().max(local.y.abs()) + s.radius
+			})
+			.fold(0., f32::max);
+		Some(Self {
+			iso,
+			width: (2. * (half_width + MARGIN)).max(MIN_WIDTH),
+		})
+	}
+
+	/// Where `point` is on the panel, in meters from its middle, and how far in
+	/// front of it.
+	fn local(&self, point: &Point3<f32>) -> Point3<f32> {
+		self.iso.inverse_transform_point(point)
+	}
+}
+
+fn render(segments: &[Segment], view: &View, image: &mut Image) {
+	image.fill(BACKGROUND);
+	let scale = image.width as f32 / view.width;
+	let half = image.width as f32 / 2.;
+	let pixel = |local: Point3<f32>| (half + local.x * scale, half - local.y * scale);
+
+	let mut segments: Vec<(Point3<f32>, Point3<f32>, &Segment)> = segments
+		.iter()
+		.map(|s| (view.local(&s.head), view.local(&s.tail), s))
+		.collect();
+	// Furthest from the eye first, which is along the negative z axis.
+	let depth = |(head, tail, _): &(Point3<f32>, Point3<f32>, _)| head.z + tail.z;
+	segments.sort_by(|a, b| depth(a).total_cmp(&depth(b)));
+	for (head, tail, segment) in segments {
+		let (from, to) = (pixel(head), pixel(tail));
+		let thickness = (2. * segment.radius * scale).max(MIN_THICKNESS);
+		let brush = (thickness / 2.).round() as i32;
+		for dx in -brush..=brush {
+			for dy in -brush..=brush {
+				let (dx, dy) = (dx as f32, dy as f32);
+				image.line(
+					(from.0 + dx, from.1 + dy),
+					(to.0 + dx, to.1 + dy),
+					segment.color,
+				);
+			}
+		}
+	}
+}
+
+/// The bones of a skeleton on one overlay, hidden while none of them are visible.
+pub struct Merged {
+	overlay: OverlayHandle,
+	image: Image,
+	/// The device that the bones and the eye are relative to, or `None` if they are
+	/// in the standing tracking space.
+	anchor: Option<TrackedDeviceIndex>,
+	last_draw: Option<Instant>,
+	visible: bool,
+}
+impl Merged {
+	pub fn new(mngr: &mut OverlayManager, key: &str) -> Result<Self> {
+		let key = format!("{key}: Merged");
+		let overlay = budget::create_overlay(mngr, &key)?;
+		Ok(Self {
+			overlay,
+			image: Image::new(SIZE, SIZE),
+			anchor: None,
+			last_draw: None,
+			visible: false,
+		})
+	}
+
+	pub fn set_anchor(&mut self, device: Option<TrackedDeviceIndex>) {
+		self.anchor = device;
+	}
+
+	/// Draws the visible ones of `bones` as seen from `eye`, if it is time to.
+	pub fn update_render<'a>(
+		&mut self,
+		mngr: &mut OverlayManager,
+		bones: impl IntoIterator<Item = &'a Bone>,
+		eye: Option<Point3<f32>>,
+	) -> Result<()> {
+		let now = Instant::now();
+		if self.last_draw.map_or(false, |t| now - t < REDRAW_INTERVAL) {
+			return Ok(());
+		}
+		self.last_draw = Some(now);
+
+		let segments: Vec<Segment> = bones
+			.into_iter()
+			.filter(|b| b.is_visible())
+			.map(Segment::of)
+			.collect();
+		let Some(view) = eye.and_then(|eye| View::new(&segments, &eye)) else {
+			return self.set_visibility(mngr, false);
+		};
+		render(&segments, &view, &mut self.image);
+		mngr.set_raw_data(self.overlay, &self.image.pixels, SIZE, SIZE, 4)
+			.wrap_err("Failed to draw merged skeleton")?;
+		mngr.set_width(self.overlay, view.width)
+			.wrap_err("Failed to set width")?;
+		let transform = view.iso.to_homogeneous().remove_fixed_rows::<1>(3);
+		let transform = ovr_transform(&transform);
+		match self.anchor {
+			Some(device) => mngr.set_transform_tracked_device_relative(
+				self.overlay,
+				device,
+				&transform,
+			),
+			None => mngr.set_transform_absolute(
+				self.overlay,
+				TrackingUniverseOrigin::TrackingUniverseStanding,
+				&transform,
+			),
+		}
+		.wrap_err("Failed to set transform")?;
+		self.set_visibility(mngr, true)
+	}
+
+	/// Until it is drawn again.
+	pub fn hide(&mut self, mngr: &mut OverlayManager) -> Result<()> {
+		self.last_draw = None;
+		self.set_visibility(mngr, false)
+	}
+
+	fn set_visibility(
+		&mut self,
+		mngr: &mut OverlayManager,
+		visible: bool,
+	) -> Result<()> {
+		if self.visible != visible {
+			mngr.set_visibility(self.overlay, visible)
+				.wrap_err("Failed to show merged skeleton")?;
+			self.visible = visible;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn segment(head: [f32; 3], tail: [f32; 3], color: RGBA) -> Segment {
+		Segment {
+			head: head.into(),
+			tail: tail.into(),
+			radius: 0.002,
+			color,
+		}
+	}
+
+	fn pixel(image: &Image, x: usize, y: usize) -> RGBA {
+		let i = (y * image.width + x) * 4;
+		let p = &image.pixels[i..i + 4];
+		RGBA::new(p[0], p[1], p[2], p[3])
+	}
+
+	#[test]
+	fn faces_the_eye_and_fits_the_bones() {
+		let segments = [segment([0., 1.5, 0.], [0., 0.5, 0.], RGBA::RED)];
+		let eye = Point3::new(0., 1., 2.);
+		let view = View::new(&segments, &eye).unwrap();
+		assert!((view.iso.translation.vector - Vector3::new(0., 1., 0.)).norm() < 1e-5);
+		let z = view.iso.rotation * Vector3::z();
+		assert!((z - Vector3::z()).norm() < 1e-5);
+		assert!((view.width - 2. * (0.5 + 0.002 + MARGIN)).abs() < 1e-5);
+
+		// From straight above.
+		let view = View::new(&segments, &Point3::new(0., 3., 0.)).unwrap();
+		let z = view.iso.rotation * Vector3::z();
+		assert!((z - Vector3::y()).norm() < 1e-5);
+		assert!(View::new(&[], &eye).is_none());
+	}
+
+	#[test]
+	fn draws_the_closest_bone_on_top() {
+		let eye = Point3::new(0., 0., 2.);
+		let segments = [
+			segment([0., 0.5, 0.1], [0., -0.5, 0.1], RGBA::RED),
+			segment([-0.5, 0., -0.1], [0.5, 0., -0.1], RGBA::BLUE),
+		];
+		let view = View::new(&segments, &eye).unwrap();
+		let mut image = Image::new(SIZE, SIZE);
+		render(&segments, &view, &mut image);
+		let middle = SIZE / 2;
+		assert_eq!(pixel(&image, middle, middle), RGBA::RED);
+		assert_eq!(pixel(&image, middle + SIZE / 4, middle), RGBA::BLUE);
+		assert_eq!(pixel(&image, middle, middle + SIZE / 4), RGBA::RED);
+		assert_eq!(pixel(&image, 5, 5), BACKGROUND);
+	}
+}
@@ -0,0 +1,90 @@
+use crate::model::Capsule;
+
+use nalgebra::{Point3, Vector3};
+use std::f32::consts::{FRAC_PI_2, TAU};
+
+/// How many vertices go around the capsule.
+const SEGMENTS: u32 = 12;
+/// How many rings of vertices each end cap has, including its equator.
+const RINGS: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vertex {
+	pub position: Point3<f32>,
+	pub normal: Vector3<f32>,
+}
+
+/// A triangle mesh, for renderers that draw actual geometry instead of overlays.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+	pub vertices: Vec<Vertex>,
+	/// Every three indices make up one triangle, wound counter-clockwise.
+	pub indices: Vec<u32>,
+}
+impl Mesh {
+	/// Triangulates `capsule`. The mesh is a grid of rings going from the pole at
+	/// `a`, through both end caps, to the pole at `b`. The quads between the two
+	/// equators make up the cylinder.
+	pub fn capsule(capsule: &Capsule) -> Self {
+		let axis = capsule.b - capsule.a;
+		let up = axis.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::y);
+		// Any two vectors perpendicular to the axis and each other.
+		let u = up
+			.cross(&Vector3::x())
+			.try_normalize(f32::EPSILON)
+			.unwrap_or_else(|| up.cross(&Vector3::z()).normalize());
+		let v = up.cross(&u);
+
+		let mut vertices = Vec::new();
+		for row in 0..2 * (RINGS + 1) {
+			let (center, phi) = if row <= RINGS {
+				let t = row as f32 / RINGS as f32;
+				(capsule.a, -FRAC_PI_2 * (1. - t))
+			} else {
+				let t = (row - RINGS - 1) as f32 / RINGS as f32;
+				(capsule.b, FRAC_PI_2 * t)
+			};
+			for col in 0..=SEGMENTS {
+				let theta = TAU * col as f32 / SEGMENTS as f32;
+				let around = u * theta.cos() + v * theta.sin();
+				let normal = around * phi.cos() + up * phi.sin();
+				vertices.push(Vertex {
+					position: center + normal * capsule.radius,
+					normal,
+				});
+			}
+		}
+
+		let row_len = SEGMENTS + 1;
+		let mut indices = Vec::new();
+		for row in 0..2 * (RINGS + 1) - 1 {
+			for col in 0..SEGMENTS {
+				let i = row * row_len + col;
+				let above = i + row_len;
+				indices.extend([i, i + 1, above, above, i + 1, above + 1]);
+			}
+		}
+
+		Self { vertices, indices }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn capsule_vertices_are_on_surface() {
+		let capsule =
+			Capsule::new(Point3::new(1., 2., 3.), Point3::new(1.5, 1., 3.), 0.1);
+		let mesh = Mesh::capsule(&capsule);
+		assert_eq!(mesh.vertices.len() as u32, 2 * (RINGS + 1) * (SEGMENTS + 1));
+		for vertex in mesh.vertices.iter() {
+			let dist = capsule.signed_distance(&vertex.position);
+			assert!(dist.abs() < 1e-5, "{vertex:?} is {dist} from the surface");
+			assert!((vertex.normal.norm() - 1.).abs() < 1e-5);
+		}
+		assert_eq!(mesh.indices.len() % 3, 0);
+		assert!(mesh.indices.iter().all(|&i| i < mesh.vertices.len() as u32));
+	}
+}
@@ -1,8 +1,45 @@
+//! What the overlay draws, with SteamVR overlays. The skeleton underneath, and its
+//! math, is the `slimevr_skeleton` crate.
+
+#[cfg(feature = "scene")]
+mod avatar;
 mod bone;
-mod bone_kind;
-mod bone_map;
+pub mod budget;
+mod context_panel;
+mod hud;
+mod image;
+mod log_panel;
+mod merged;
+#[cfg(feature = "scene")]
+mod mesh;
+mod plot;
+mod reach;
 pub mod skeleton;
+mod spine;
+mod tracker_marker;
+mod trail;
+mod warning_panel;
+
+#[cfg(feature = "scene")]
+pub use self::avatar::Avatar;
+pub use self::bone::Bone;
+pub use self::context_panel::{BoneDetails, ContextPanel};
+pub use self::hud::{hmd_forward, hmd_position, MiniSkeleton};
+pub use self::image::Image;
+pub use self::log_panel::LogPanel;
+#[cfg(feature = "scene")]
+pub use self::mesh::{Mesh, Vertex};
+pub use self::plot::RawPlot;
+pub use self::reach::Reach;
+pub use self::spine::Spine;
+pub use self::tracker_marker::{TrackerMarkers, TrackerPose, TrackerState};
+pub use self::trail::Trails;
+pub use self::warning_panel::WarningPanel;
+pub use slimevr_skeleton::{BoneKind, BoneMap, Capsule, Isometry};
+
+use ovr_overlay::pose::Matrix3x4;
 
-pub use self::bone::{Bone, Isometry};
-pub use self::bone_kind::BoneKind;
-pub use self::bone_map::BoneMap;
+/// `transform` as the row-major matrix that OpenVR takes, for placing overlays.
+pub fn ovr_transform(transform: &nalgebra::Matrix3x4<f32>) -> Matrix3x4 {
+	Matrix3x4(transform.transpose().data.0)
+}
@@ -0,0 +1,274 @@
+//! Live plots of a tracker's raw IMU samples, on a panel below the headset's view,
+//! for inspecting filter and drift problems without leaving VR. The acceleration
+//! goes on top and the angular velocity below, with the x, y and z axes drawn in
+//! red, green and blue. Time runs from left to right, newest on the right.
+//!
+//! There is no room for labels, so each plot scales to fit its samples in steps of
+//! powers of two, and the range is logged whenever it changes. Samples that the
+//! tracker dropped show up as gaps.
+
+use crate::model::image::Image;
+use crate::model::{budget, ovr_transform};
+use crate::raw_stream::RawSample;
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use nalgebra::{Translation3, Vector3};
+use ovr_overlay::overlay::{OverlayHandle, OverlayManager};
+use ovr_overlay::TrackedDeviceIndex;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::TryRecvError};
+
+/// How much history is shown.
+const WINDOW: Duration = Duration::from_secs(5);
+/// Consecutive samples further apart than this aren't connected.
+const MAX_GAP: Duration = Duration::from_millis(100);
+const WIDTH: usize = 512;
+const HEIGHT: usize = 256;
+/// The width of the panel in meters.
+const PANEL_WIDTH: f32 = 0.5;
+/// Where the panel goes, relative to the headset.
+const PANEL_OFFSET: [f32; 3] = [0., -0.25, -0.8];
+/// Uploading the image is slow, so it is only redrawn this often.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(33);
+const BACKGROUND: RGBA = RGBA::new(0, 0, 0, 160);
+const GRID: RGBA = RGBA::new(128, 128, 128, 255);
+const AXIS_COLORS: [RGBA; 3] = [RGBA::RED, RGBA::LIME, RGBA::BLUE];
+
+struct Plot {
+	name: &'static str,
+	unit: &'static str,
+	value: fn(&RawSample) -> Vector3<f32>,
+	/// The smallest range, so that a still sensor doesn't blow up its noise.
+	min_scale: f32,
+}
+fn accel(s: &RawSample) -> Vector3<f32> {
+	s.accel
+}
+fn gyro(s: &RawSample) -> Vector3<f32> {
+	s.gyro
+}
+const PLOTS: [Plot; 2] = [
+	Plot {
+		name: "acceleration",
+		unit: "m/s^2",
+		value: accel,
+		min_scale: 0.5,
+	},
+	Plot {
+		name: "angular velocity",
+		unit: "rad/s",
+		value: gyro,
+		min_scale: 0.0625,
+	},
+];
+
+/// The samples of the last [`WINDOW`], and how to draw them.
+#[derive(Debug, Default)]
+struct Graph {
+	/// Oldest first.
+	samples: VecDeque<RawSample>,
+}
+impl Graph {
+	fn push(&mut self, sample: RawSample) {
+		self.samples.push_back(sample);
+		while self.samples.front().map_or(false, |s| self.age(s) > WINDOW) {
+			self.samples.pop_front();
+		}
+	}
+
+	/// How long before the newest sample `sample` was taken.
+	fn age(&self, sample: &RawSample) -> Duration {
+		let newest = self.samples.back().map_or(sample.time, |s| s.time);
+		newest.saturating_duration_since(sample.time)
+	}
+
+	/// The largest absolute value that `plot` shows, rounded up to a power of two.
+	fn scale(&self, plot: &Plot) -> f32 {
+		let max = self
+			.samples
+			.iter()
+			.map(|s| (plot.value)(s).amax())
+			.fold(plot.min_scale, f32::max);
+		max.log2().ceil().exp2()
+	}
+
+	fn render(&self, image: &mut Image) {
+		image.fill(BACKGROUND);
+		let plot_height = image.height / PLOTS.len();
+		for (i, plot) in PLOTS.iter().enumerate() {
+			let top = i * plot_height;
+			if i > 0 {
+				image.hline(top, GRID);
+			}
+			let middle = top + plot_height / 2;
+			image.hline(middle, GRID);
+
+			let scale = self.scale(plot);
+			let half_height = (plot_height / 2 - 1) as f32;
+			let right = (image.width - 1) as f32;
+			let point = |s: &RawSample, axis: usize| {
+				let age = self.age(s).as_secs_f32() / WINDOW.as_secs_f32();
+				let x = right * (1. - age);
+				let y = middle as f32 - (plot.value)(s)[axis] / scale * half_height;
+				(x, y)
+			};
+			for (a, b) in self.samples.iter().zip(self.samples.iter().skip(1)) {
+				if b.time.saturating_duration_since(a.time) > MAX_GAP {
+					continue;
+				}
+				for (axis, color) in AXIS_COLORS.into_iter().enumerate() {
+					image.line(point(a, axis), point(b, axis), color);
+				}
+			}
+		}
+	}
+}
+
+/// The panel with the plots, anchored to the headset. It stays hidden until the
+/// first sample arrives.
+pub struct RawPlot {
+	overlay: OverlayHandle,
+	samples: broadcast::Receiver<RawSample>,
+	graph: Graph,
+	image: Image,
+	last_draw: Option<Instant>,
+	/// The scale of each of [`PLOTS`] as of the last draw.
+	scales: [f32; PLOTS.len()],
+}
+impl RawPlot {
+	pub fn new(
+		mngr: &mut OverlayManager,
+		key: &str,
+		samples: broadcast::Receiver<RawSample>,
+	) -> Result<Self> {
+		let key = format!("{key}: RawPlot");
+		let overlay = budget::create_overlay(mngr, &key)?;
+		mngr.set_width(overlay, PANEL_WIDTH)
+			.wrap_err("Failed to set width")?;
+		let [x, y, z] = PANEL_OFFSET;
+		let transform = Translation3::new(x, y, z)
+			.to_homogeneous()
+			.remove_fixed_rows::<1>(3);
+		mngr.set_transform_tracked_device_relative(
+			overlay,
+			TrackedDeviceIndex::HMD,
+			&ovr_transform(&transform),
+		)
+		.wrap_err("Failed to set transform")?;
+		Ok(Self {
+			overlay,
+			samples,
+			graph: Graph::default(),
+			image: Image::new(WIDTH, HEIGHT),
+			last_draw: None,
+			scales: [0.; PLOTS.len()],
+		})
+	}
+
+	/// Takes the samples that arrived since the last call, and redraws the plots if
+	/// it is time to.
+	pub fn update_render(
+		&mut self,
+		mngr: &mut OverlayManager,
+		now: Instant,
+	) -> Result<()> {
+		loop {
+			match self.samples.try_recv() {
+				Ok(sample) => self.graph.push(sample),
+				Err(TryRecvError::Lagged(n)) => {
+					log::debug!("Raw plot skipped {n} samples to catch up")
+				}
+				Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+			}
+		}
+		if self.graph.samples.is_empty()
+			|| self.last_draw.map_or(false, |t| now - t < REDRAW_INTERVAL)
+		{
+			return Ok(());
+		}
+		self.last_draw = Some(now);
+
+		for (plot, scale) in PLOTS.iter().zip(&mut self.scales) {
+			let new = self.graph.scale(plot);
+			if new != *scale {
+				log::info!("Raw plot of {} now spans ±{new} {}", plot.name, plot.unit);
+				*scale = new;
+			}
+		}
+		self.graph.render(&mut self.image);
+		mngr.set_raw_data(self.overlay, &self.image.pixels, WIDTH, HEIGHT, 4)
+			.wrap_err("Failed to draw raw plot")?;
+		mngr.set_visibility(self.overlay, true)
+			.wrap_err("Failed to show raw plot")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const WINDOW_US: u64 = WINDOW.as_micros() as u64;
+	const MAX_GAP_US: u64 = MAX_GAP.as_micros() as u64;
+
+	fn sample(start: Instant, us: u64, accel_x: f32) -> RawSample {
+		RawSample {
+			time: start + Duration::from_micros(us),
+			accel: Vector3::new(accel_x, 0., 0.),
+			gyro: Vector3::zeros(),
+		}
+	}
+
+	fn pixel(image: &Image, x: usize, y: usize) -> RGBA {
+		let i = (y * image.width + x) * 4;
+		let p = &image.pixels[i..i + 4];
+		RGBA::new(p[0], p[1], p[2], p[3])
+	}
+
+	#[test]
+	fn old_samples_fall_out_of_the_window() {
+		let start = Instant::now();
+		let mut graph = Graph::default();
+		for i in 0..=10 {
+			graph.push(sample(start, i * WINDOW_US / 5, 0.));
+		}
+		assert_eq!(graph.samples.len(), 6);
+		assert_eq!(graph.samples[0].time, start + WINDOW);
+	}
+
+	#[test]
+	fn scale_is_a_power_of_two() {
+		let mut graph = Graph::default();
+		assert_eq!(graph.scale(&PLOTS[0]), 0.5);
+		graph.push(sample(Instant::now(), 0, -9.81));
+		assert_eq!(graph.scale(&PLOTS[0]), 16.);
+		assert_eq!(graph.scale(&PLOTS[1]), 0.0625);
+	}
+
+	#[test]
+	fn draws_at_the_value_and_skips_gaps() {
+		let start = Instant::now();
+		let mut graph = Graph::default();
+		let step = MAX_GAP_US / 2;
+		for t in (0..=WINDOW_US / 4).step_by(step as usize) {
+			graph.push(sample(start, t, 0.5));
+		}
+		// Too far from the last one to be connected.
+		for t in (WINDOW_US / 2..=WINDOW_US).step_by(step as usize) {
+			graph.push(sample(start, t, 0.5));
+		}
+		let mut image = Image::new(101, 2 * 42);
+		graph.render(&mut image);
+		// The top of the acceleration plot, since its scale is 0.5.
+		let y = 1;
+		assert_eq!(pixel(&image, 0, y), RGBA::RED);
+		assert_eq!(pixel(&image, 25, y), RGBA::RED);
+		assert_eq!(pixel(&image, 40, y), BACKGROUND);
+		assert_eq!(pixel(&image, 75, y), RGBA::RED);
+		assert_eq!(pixel(&image, 100, y), RGBA::RED);
+		// Zero on the other axes.
+		assert_eq!(pixel(&image, 75, 21), RGBA::BLUE);
+		assert_eq!(pixel(&image, 75, 42 + 21), RGBA::BLUE);
+	}
+}
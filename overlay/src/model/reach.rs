@@ -0,0 +1,252 @@
+//! Keeps track of how far the skeleton reached over a session: the box around every
+//! place a bone has been, and how far each hand and foot got from the hip. The box
+//! helps with setting up the play space, and the reach with checking that the body
+//! proportions are realistic, since a hand reaching much further than the arm
+//! length means they are off.
+//!
+//! Both are logged when the session ends. The box can also be drawn, which is handy
+//! when walking around the edges of the play space.
+
+use crate::model::skeleton::Skeleton;
+use crate::model::{Bone, BoneKind, Isometry};
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use nalgebra::{Point3, Translation3, UnitQuaternion, Vector3};
+use ovr_overlay::overlay::OverlayManager;
+
+/// The ends of the limbs whose reach is measured.
+const LIMBS: [BoneKind; 4] = [
+	BoneKind::WristL,
+	BoneKind::WristR,
+	BoneKind::FootL,
+	BoneKind::FootR,
+];
+const EDGE_RADIUS: f32 = 0.002;
+const EDGE_COLOR: RGBA = RGBA::new(0, 255, 255, 128);
+
+/// An axis aligned box that grows to contain every point added to it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+	pub min: Point3<f32>,
+	pub max: Point3<f32>,
+}
+impl Bounds {
+	pub fn new(p: Point3<f32>) -> Self {
+		Self { min: p, max: p }
+	}
+
+	pub fn add(&mut self, p: &Point3<f32>) {
+		self.min = self.min.inf(p);
+		self.max = self.max.sup(p);
+	}
+
+	pub fn size(&self) -> Vector3<f32> {
+		self.max - self.min
+	}
+
+	/// The corner with the coordinates of `max` on the axes whose bit is set in `i`.
+	fn corner(&self, i: usize) -> Point3<f32> {
+		Point3::from(Vector3::from_fn(|axis, _| {
+			if i & (1 << axis) != 0 {
+				self.max[axis]
+			} else {
+				self.min[axis]
+			}
+		}))
+	}
+
+	/// The 12 edges of the box.
+	pub fn edges(&self) -> impl Iterator<Item = (Point3<f32>, Point3<f32>)> + '_ {
+		// Each edge connects a corner to the one that only differs on one axis.
+		(0..8).flat_map(move |i| {
+			(0..3)
+				.filter(move |axis| i & (1 << axis) == 0)
+				.map(move |axis| (self.corner(i), self.corner(i | (1 << axis))))
+		})
+	}
+}
+
+/// The bounding box and the reach of the limbs, without any rendering.
+#[derive(Debug, Default, Clone)]
+pub struct Envelope {
+	pub bounds: Option<Bounds>,
+	/// The furthest distance in meters from the hip to the end of each of
+	/// [`LIMBS`], if it was seen yet.
+	pub reach: [Option<f32>; LIMBS.len()],
+}
+impl Envelope {
+	pub fn add_point(&mut self, p: &Point3<f32>) {
+		match &mut self.bounds {
+			Some(bounds) => bounds.add(p),
+			None => self.bounds = Some(Bounds::new(*p)),
+		}
+	}
+
+	/// Records that the end of `limb` was at `end`, while the hip was at `hip`. Bones
+	/// that aren't one of [`LIMBS`] are ignored.
+	pub fn add_reach(&mut self, limb: BoneKind, hip: &Point3<f32>, end: &Point3<f32>) {
+		let Some(i) = LIMBS.iter().position(|l| *l == limb) else {
+			return;
+		};
+		let distance = (end - hip).norm();
+		let reach = &mut self.reach[i];
+		*reach = Some(reach.map_or(distance, |r| r.max(distance)));
+	}
+
+	/// Adds the current pose of the visible bones of `skeleton`.
+	pub fn update(&mut self, skeleton: &Skeleton) {
+		let hip = &skeleton.bones[BoneKind::Hip];
+		let hip = hip.is_visible().then(|| hip.capsule().a);
+		for (kind, bone) in skeleton.bones.iter() {
+			if !bone.is_visible() {
+				continue;
+			}
+			let capsule = bone.capsule();
+			self.add_point(&capsule.a);
+			self.add_point(&capsule.b);
+			if let Some(hip) = &hip {
+				self.add_reach(kind, hip, &capsule.b);
+			}
+		}
+	}
+
+	pub fn log_summary(&self) {
+		let Some(bounds) = &self.bounds else {
+			log::info!("No bones were seen this session, so there is no reach");
+			return;
+		};
+		let size = bounds.size();
+		log::info!(
+			"Bounding box of this session: {:.2}m wide, {:.2}m high, {:.2}m deep",
+			size.x,
+			size.y,
+			size.z
+		);
+		for (limb, reach) in LIMBS.iter().zip(self.reach) {
+			match reach {
+				Some(reach) => {
+					log::info!("Reach of {limb:?} from the hip: {reach:.2}m")
+				}
+				None => log::info!("Reach of {limb:?} from the hip: never seen"),
+			}
+		}
+	}
+}
+
+/// An [`Envelope`] that draws its bounding box, if it was created with
+/// `visible`.
+pub struct Reach {
+	envelope: Envelope,
+	edges: Vec<Bone>,
+}
+impl Reach {
+	pub fn new(mngr: &mut OverlayManager, key: &str, visible: bool) -> Result<Self> {
+		let edges = if visible {
+			(0..12)
+				.map(|i| {
+					Bone::new(
+						mngr,
+						EDGE_COLOR,
+						Isometry::identity(),
+						format!("{key}: ReachEdge{i}"),
+						EDGE_RADIUS,
+						0.1,
+					)
+				})
+				.collect::<Result<_>>()
+				.wrap_err("Failed to create reach box")?
+		} else {
+			Vec::new()
+		};
+		Ok(Self {
+			envelope: Envelope::default(),
+			edges,
+		})
+	}
+
+	/// Call after the visibility of the bones has been decided.
+	pub fn update(&mut self, skeleton: &Skeleton) {
+		self.envelope.update(skeleton);
+		let Some(bounds) = &self.envelope.bounds else {
+			return;
+		};
+		for (edge, (from, to)) in self.edges.iter_mut().zip(bounds.edges()) {
+			let dir = to - from;
+			let length = dir.norm();
+			if length == 0. {
+				// A flat box, which only happens until something moves.
+				edge.set_visibility(false);
+				continue;
+			}
+			// Bones point down their y axis, so the vertical edges need a half turn.
+			let rot = UnitQuaternion::rotation_between(&-Vector3::y(), &dir)
+				.unwrap_or_else(|| {
+					UnitQuaternion::from_axis_angle(
+						&Vector3::x_axis(),
+						std::f32::consts::PI,
+					)
+				});
+			edge.set_isometry(Isometry::from_parts(
+				Translation3::from(from.coords),
+				rot,
+			));
+			edge.set_length(length);
+			edge.set_visibility(true);
+		}
+	}
+
+	pub fn update_render(&mut self, mngr: &mut OverlayManager) -> Result<()> {
+		for edge in &mut self.edges {
+			edge.update_render(mngr)
+				.wrap_err("Failed to render reach box")?;
+		}
+		Ok(())
+	}
+
+	pub fn envelope(&self) -> &Envelope {
+		&self.envelope
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn bounds_grow() {
+		let mut bounds = Bounds::new(Point3::new(0., 1., 0.));
+		bounds.add(&Point3::new(-1., 2., 0.5));
+		bounds.add(&Point3::new(0.5, 0., -0.5));
+		assert_eq!(bounds.min, Point3::new(-1., 0., -0.5));
+		assert_eq!(bounds.max, Point3::new(0.5, 2., 0.5));
+		assert_eq!(bounds.size(), Vector3::new(1.5, 2., 1.));
+	}
+
+	#[test]
+	fn edges_follow_one_axis() {
+		let mut bounds = Bounds::new(Point3::origin());
+		bounds.add(&Point3::new(1., 2., 3.));
+		let edges: Vec<_> = bounds.edges().collect();
+		assert_eq!(edges.len(), 12);
+		let total: f32 = edges.iter().map(|(a, b)| (b - a).norm()).sum();
+		assert_eq!(total, 4. * (1. + 2. + 3.));
+		for (a, b) in edges {
+			let dir = b - a;
+			assert_eq!(dir.iter().filter(|c| **c != 0.).count(), 1);
+			assert!(dir.iter().all(|c| *c >= 0.));
+		}
+	}
+
+	#[test]
+	fn reach_keeps_the_maximum() {
+		let mut envelope = Envelope::default();
+		let hip = Point3::origin();
+		envelope.add_reach(BoneKind::WristL, &hip, &Point3::new(0.5, 0., 0.));
+		envelope.add_reach(BoneKind::WristL, &hip, &Point3::new(0., 0.75, 0.));
+		envelope.add_reach(BoneKind::WristL, &hip, &Point3::new(0., 0., 0.25));
+		envelope.add_reach(BoneKind::Hip, &hip, &Point3::new(9., 9., 9.));
+		assert_eq!(envelope.reach[0], Some(0.75));
+		assert_eq!(envelope.reach[1..], [None; 3]);
+	}
+}
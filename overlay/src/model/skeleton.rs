@@ -1,18 +1,24 @@
 use std::collections::HashMap;
 
 use crate::model::bone::Bone;
+use crate::model::budget;
+use crate::model::merged::Merged;
+use crate::model::spine::SPINE_BONES;
 use crate::model::BoneKind;
 use crate::model::BoneMap;
+use crate::model::Capsule;
+use crate::model::Isometry;
+use crate::model::Spine;
 use crate::RGBA;
 
 use eyre::Context;
 use eyre::Result;
 use lazy_static::lazy_static;
+use nalgebra::{Point3, Unit, Vector3};
 use ovr_overlay::overlay::OverlayManager;
+use ovr_overlay::TrackedDeviceIndex;
 use stackvec::TryCollect;
 
-use super::bone::Isometry;
-
 pub type BoneArena = BoneMap<Bone>;
 
 lazy_static! {
@@ -42,49 +48,113 @@ lazy_static! {
 	};
 }
 
-const BONE_RADIUS: f32 = 0.002;
+pub const BONE_RADIUS: f32 = 0.002;
+/// The length of bones until the server tells us better.
+pub const DEFAULT_BONE_LENGTH: f32 = 0.1;
+
+/// The color of `kind` unless something else was asked for.
+pub fn default_color(kind: BoneKind) -> RGBA {
+	DEFAULT_COLORS[kind]
+}
 
 /// Builder for the [`Skeleton`].
 pub struct SkeletonBuilder {
 	colors: Option<BoneMap<Option<RGBA>>>,
 	key: String,
 	bone_radius: f32,
-	bone_lengths: Option<BoneMap<f32>>,
+	bone_lengths: Option<BoneMap<Option<f32>>>,
 }
 impl SkeletonBuilder {
+	/// Prefix of the overlay keys, which must be unique across skeletons.
+	pub fn key(mut self, key: impl Into<String>) -> Self {
+		self.key = key.into();
+		self
+	}
+
+	pub fn bone_radius(mut self, radius: f32) -> Self {
+		self.bone_radius = radius;
+		self
+	}
+
+	/// The lengths to start with, like the ones the server sent last time. Bones
+	/// without one get a short default.
+	pub fn bone_lengths(mut self, lengths: BoneMap<Option<f32>>) -> Self {
+		self.bone_lengths = Some(lengths);
+		self
+	}
+
+	/// Gives each bone two overlays, or merges all of them into one if SteamVR
+	/// doesn't have enough left, see [`budget`]. Only fails if not even that one
+	/// can be created.
 	#[allow(dead_code)]
 	pub fn build(self, overlay_manager: &mut OverlayManager) -> Result<Skeleton> {
-		let colors = if let Some(colors) = self.colors {
-			colors
-		} else {
-			Default::default()
-		};
-		let colors: BoneMap<RGBA> = colors
-			.into_iter()
-			.map(|(kind, maybe_color)| {
-				(kind, maybe_color.unwrap_or_else(|| DEFAULT_COLORS[kind]))
-			})
-			.try_collect()
-			.unwrap();
-
-		let bone_lengths = self
-			.bone_lengths
-			.unwrap_or_else(|| BoneMap::new([0.1; BoneKind::NUM_TYPES]));
+		let remaining = budget::remaining();
+		if remaining < budget::SKELETON + budget::RESERVE {
+			log::warn!(
+				"Only {remaining} of SteamVR's {} overlays are left, so the bones of \
+				 {:?} are merged into one of them, which is redrawn less often",
+				budget::MAX_OVERLAYS,
+				self.key
+			);
+			return self.build_merged(overlay_manager);
+		}
+		match self.build_overlays(overlay_manager) {
+			Ok(skeleton) => Ok(skeleton),
+			Err(e) => {
+				log::warn!(
+					"{e:?}\nMerging the bones of {:?} into one overlay instead",
+					self.key
+				);
+				self.build_merged(overlay_manager)
+			}
+		}
+	}
 
-		let mut bones = Vec::new();
-		for (kind, color) in colors {
-			let bone = Bone::new(
+	fn build_overlays(&self, overlay_manager: &mut OverlayManager) -> Result<Skeleton> {
+		let bones = self.bones(|kind, color, length| {
+			Bone::new(
 				overlay_manager,
 				color,
 				Default::default(),
 				format!("{}: {kind:?}", self.key),
 				self.bone_radius,
-				bone_lengths[kind],
+				length,
+			)
+		})?;
+		let spine = Spine::new(overlay_manager, &self.key, self.bone_radius)?;
+		Ok(Skeleton::new(bones, Overlays::Bones(spine), &self.key))
+	}
+
+	fn build_merged(&self, overlay_manager: &mut OverlayManager) -> Result<Skeleton> {
+		let bones = self.bones(|_kind, color, length| {
+			Ok(Bone::without_overlays(
+				color,
+				Default::default(),
+				self.bone_radius,
+				length,
+			))
+		})?;
+		let merged = Merged::new(overlay_manager, &self.key)?;
+		Ok(Skeleton::new(bones, Overlays::Merged(merged), &self.key))
+	}
+
+	/// Makes each bone with `new_bone`, from its kind, color and length.
+	fn bones(
+		&self,
+		mut new_bone: impl FnMut(BoneKind, RGBA, f32) -> Result<Bone>,
+	) -> Result<BoneArena> {
+		let mut bones = Vec::new();
+		for kind in BoneKind::iter() {
+			let color = self.colors.as_ref().and_then(|colors| colors[kind]);
+			let length = self.bone_lengths.as_ref().and_then(|lengths| lengths[kind]);
+			let bone = new_bone(
+				kind,
+				color.unwrap_or(DEFAULT_COLORS[kind]),
+				length.unwrap_or(DEFAULT_BONE_LENGTH),
 			)?;
 			bones.push((kind, bone));
 		}
-		let bones: BoneArena = bones.into_iter().try_collect().unwrap();
-		Ok(Skeleton::new(bones))
+		Ok(bones.into_iter().try_collect().unwrap())
 	}
 }
 impl Default for SkeletonBuilder {
@@ -98,13 +168,36 @@ impl Default for SkeletonBuilder {
 	}
 }
 
+/// How the bones are drawn.
+enum Overlays {
+	/// Each on its own overlays, with a curved spine.
+	Bones(Spine),
+	/// All of them on one overlay.
+	Merged(Merged),
+}
+
 pub struct Skeleton {
 	pub bones: BoneArena,
+	overlays: Overlays,
+	/// The prefix of the overlay keys.
+	key: String,
+	anchor: Option<TrackedDeviceIndex>,
+	/// Draws bones that have their own overlays while `is_merging`, see
+	/// [`Skeleton::set_merged`]. Created the first time.
+	merged: Option<Merged>,
+	is_merging: bool,
 }
 #[allow(dead_code)]
 impl Skeleton {
-	pub fn new(bones: BoneArena) -> Self {
-		let mut result = Self { bones };
+	fn new(bones: BoneArena, overlays: Overlays, key: &str) -> Self {
+		let mut result = Self {
+			bones,
+			overlays,
+			key: key.to_owned(),
+			anchor: None,
+			merged: None,
+			is_merging: false,
+		};
 		// We explicitly set all bones to invisible, to reduce code brittleness.
 		for b in BoneKind::iter() {
 			result.set_visibility(b, false);
@@ -117,11 +210,74 @@ impl Skeleton {
 		bone.set_isometry(iso);
 	}
 
+	/// Makes the isometries of all bones relative to `device`, instead of the
+	/// standing tracking space.
+	pub fn set_anchor(&mut self, device: Option<TrackedDeviceIndex>) {
+		for (_kind, bone) in self.bones.iter_mut() {
+			bone.set_anchor(device);
+		}
+		match &mut self.overlays {
+			Overlays::Bones(spine) => spine.set_anchor(device),
+			Overlays::Merged(merged) => merged.set_anchor(device),
+		}
+		if let Some(merged) = &mut self.merged {
+			merged.set_anchor(device);
+		}
+		self.anchor = device;
+	}
+
+	/// Whether the bones are merged into one overlay, which needs to know where the
+	/// headset is, see [`Skeleton::update_merged_render`].
+	pub fn is_merged(&self) -> bool {
+		matches!(self.overlays, Overlays::Merged(_)) || self.is_merging
+	}
+
+	/// Draws the bones on one overlay while `merged`, even though they have their
+	/// own, since that is less work for SteamVR, see [`crate::quality`]. Skeletons
+	/// that had to be merged from the start stay merged.
+	pub fn set_merged(
+		&mut self,
+		mngr: &mut OverlayManager,
+		merged: bool,
+	) -> Result<()> {
+		let Overlays::Bones(spine) = &mut self.overlays else {
+			return Ok(());
+		};
+		if merged == self.is_merging {
+			return Ok(());
+		}
+		if merged && self.merged.is_none() {
+			let mut overlay = Merged::new(mngr, &self.key)?;
+			overlay.set_anchor(self.anchor);
+			self.merged = Some(overlay);
+		}
+		for (_kind, bone) in self.bones.iter_mut() {
+			bone.set_merged(mngr, merged)?;
+		}
+		// Merged skeletons draw the torso as its bones.
+		spine.set_visibility(false);
+		if let (Some(overlay), false) = (&mut self.merged, merged) {
+			overlay.hide(mngr)?;
+		}
+		self.is_merging = merged;
+		Ok(())
+	}
+
 	pub fn set_length(&mut self, bone: BoneKind, len: f32) {
 		let bone = &mut self.bones[bone];
 		bone.set_length(len);
 	}
 
+	/// Changes the radius of all bones, and of the spine.
+	pub fn set_radius(&mut self, radius: f32) {
+		for (_kind, bone) in self.bones.iter_mut() {
+			bone.set_radius(radius);
+		}
+		if let Overlays::Bones(spine) = &mut self.overlays {
+			spine.set_radius(radius);
+		}
+	}
+
 	pub fn update_render(
 		&mut self,
 		bone: BoneKind,
@@ -132,8 +288,98 @@ impl Skeleton {
 			.wrap_err("could not update render for bone")
 	}
 
+	/// Replaces the chest, waist, and hip with a curved spine if they are all
+	/// visible and `is_enabled` is set. Call after the visibility of the bones has
+	/// been decided, and before rendering.
+	pub fn update_spine(&mut self, is_enabled: bool) {
+		// Merged skeletons draw the torso as its bones.
+		let Overlays::Bones(spine) = &mut self.overlays else {
+			return;
+		};
+		if self.is_merging {
+			return;
+		}
+		let use_spine =
+			is_enabled && SPINE_BONES.iter().all(|&b| self.bones[b].is_visible());
+		spine.set_visibility(use_spine);
+		if !use_spine {
+			return;
+		}
+		spine.update(SPINE_BONES.map(|b| {
+			let bone = &self.bones[b];
+			(bone.isometry(), bone.length(), bone.color())
+		}));
+		for b in SPINE_BONES {
+			self.bones[b].set_visibility(false);
+		}
+	}
+
+	pub fn update_spine_render(&mut self, mngr: &mut OverlayManager) -> Result<()> {
+		let Overlays::Bones(spine) = &mut self.overlays else {
+			return Ok(());
+		};
+		spine
+			.update_render(mngr)
+			.wrap_err("could not update render for spine")
+	}
+
+	/// Draws the bones of a merged skeleton, as seen from `eye`, which is where the
+	/// headset is relative to the anchor. Does nothing for the others.
+	pub fn update_merged_render(
+		&mut self,
+		mngr: &mut OverlayManager,
+		eye: Option<Point3<f32>>,
+	) -> Result<()> {
+		let merged = match (&mut self.overlays, &mut self.merged) {
+			(Overlays::Merged(merged), _) => merged,
+			(Overlays::Bones(_), Some(merged)) if self.is_merging => merged,
+			_ => return Ok(()),
+		};
+		merged
+			.update_render(mngr, self.bones.iter().map(|(_kind, bone)| bone), eye)
+			.wrap_err("could not update render for merged skeleton")
+	}
+
 	pub fn set_visibility(&mut self, bone: BoneKind, is_visible: bool) {
 		let bone = &mut self.bones[bone];
 		bone.set_visibility(is_visible);
 	}
+
+	pub fn set_color(&mut self, bone: BoneKind, color: RGBA) {
+		let bone = &mut self.bones[bone];
+		bone.set_color(color);
+	}
+
+	/// Where each bone is and how it is shaped, without the overlays.
+	pub fn poses(
+		&self,
+	) -> impl Iterator<Item = (BoneKind, &slimevr_skeleton::Bone)> + '_ {
+		self.bones.iter().map(|(kind, bone)| (kind, bone.pose()))
+	}
+
+	/// Finds the closest visible bone hit by a ray from `origin` along `dir`.
+	pub fn raycast(
+		&self,
+		origin: &Point3<f32>,
+		dir: &Unit<Vector3<f32>>,
+	) -> Option<(BoneKind, f32)> {
+		slimevr_skeleton::raycast(self.poses(), origin, dir)
+	}
+
+	/// Finds the visible bone whose surface is closest to `point`, along with the
+	/// closest point on it and the signed distance to it.
+	pub fn closest_bone(
+		&self,
+		point: &Point3<f32>,
+	) -> Option<(BoneKind, Point3<f32>, f32)> {
+		slimevr_skeleton::closest_bone(self.poses(), point)
+	}
+
+	/// All visible bones that overlap with `capsule`.
+	pub fn intersecting(
+		&self,
+		capsule: &Capsule,
+	) -> impl Iterator<Item = BoneKind> + '_ {
+		slimevr_skeleton::intersecting(self.poses(), capsule)
+	}
 }
@@ -0,0 +1,137 @@
+//! Renders the torso as a smooth curve instead of three straight bones.
+//!
+//! The server's skeletal model bends the spine at the chest, waist, and hip joints.
+//! Drawn as straight tubes, this looks like a broken line, so when all three torso
+//! bones are available we instead draw a Catmull-Rom spline through their joints,
+//! split into several short segments.
+
+use crate::model::{Bone, BoneKind, Isometry};
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use nalgebra::{Point3, Translation3, UnitQuaternion, Vector3};
+use ovr_overlay::overlay::OverlayManager;
+use ovr_overlay::TrackedDeviceIndex;
+
+/// The bones that make up the spine, from top to bottom.
+pub const SPINE_BONES: [BoneKind; 3] =
+	[BoneKind::Chest, BoneKind::Waist, BoneKind::Hip];
+/// How many segments each spine bone gets split into.
+const SEGMENTS_PER_BONE: usize = 4;
+pub const NUM_SEGMENTS: usize = SPINE_BONES.len() * SEGMENTS_PER_BONE;
+
+pub struct Spine {
+	segments: Vec<Bone>,
+	is_visible: bool,
+}
+impl Spine {
+	pub fn new(mngr: &mut OverlayManager, key: &str, radius: f32) -> Result<Self> {
+		let segments = (0..NUM_SEGMENTS)
+			.map(|i| {
+				Bone::new(
+					mngr,
+					RGBA::WHITE,
+					Isometry::identity(),
+					format!("{key}: Spine{i}"),
+					radius,
+					0.1,
+				)
+			})
+			.collect::<Result<_>>()
+			.wrap_err("Failed to create spine segments")?;
+		Ok(Self {
+			segments,
+			is_visible: false,
+		})
+	}
+
+	/// Fits the spine to `bones`, which are the isometries, lengths, and colors of
+	/// [`SPINE_BONES`] in order.
+	pub fn update(&mut self, bones: [(&Isometry, f32, RGBA); SPINE_BONES.len()]) {
+		// The joints that the curve passes through: the head of each bone, and the
+		// tail of the last one.
+		let mut joints = [Point3::origin(); SPINE_BONES.len() + 1];
+		for (joint, (iso, _length, _color)) in joints.iter_mut().zip(bones) {
+			*joint = Point3::from(iso.translation.vector);
+		}
+		let (last_iso, last_length, _) = bones[bones.len() - 1];
+		joints[joints.len() - 1] = last_iso * Point3::new(0., -last_length, 0.);
+
+		let point_at = |segment: usize| -> Point3<f32> {
+			let bone = segment / SEGMENTS_PER_BONE;
+			let t = (segment % SEGMENTS_PER_BONE) as f32 / SEGMENTS_PER_BONE as f32;
+			if bone >= SPINE_BONES.len() {
+				return joints[joints.len() - 1];
+			}
+			// Duplicate the end points so the curve reaches them.
+			let p0 = joints[bone.saturating_sub(1)];
+			let p1 = joints[bone];
+			let p2 = joints[bone + 1];
+			let p3 = joints[(bone + 2).min(joints.len() - 1)];
+			catmull_rom(&p0, &p1, &p2, &p3, t)
+		};
+
+		for (i, segment) in self.segments.iter_mut().enumerate() {
+			let bone = i / SEGMENTS_PER_BONE;
+			let (iso, _length, color) = bones[bone];
+			let head = point_at(i);
+			let tail = point_at(i + 1);
+			let dir = tail - head;
+
+			// Keep the roll of the bone the segment belongs to, and only bend it to
+			// follow the curve.
+			let down = iso.rotation * -Vector3::y();
+			let bend = UnitQuaternion::rotation_between(&down, &dir)
+				.unwrap_or_else(UnitQuaternion::identity);
+			segment.set_isometry(Isometry::from_parts(
+				Translation3::from(head.coords),
+				bend * iso.rotation,
+			));
+			segment.set_length(dir.norm());
+			segment.set_color(color);
+		}
+	}
+
+	pub fn set_visibility(&mut self, is_visible: bool) {
+		self.is_visible = is_visible;
+	}
+
+	pub fn set_radius(&mut self, radius: f32) {
+		for segment in self.segments.iter_mut() {
+			segment.set_radius(radius);
+		}
+	}
+
+	pub fn set_anchor(&mut self, device: Option<TrackedDeviceIndex>) {
+		for segment in self.segments.iter_mut() {
+			segment.set_anchor(device);
+		}
+	}
+
+	pub fn update_render(&mut self, mngr: &mut OverlayManager) -> Result<()> {
+		for segment in self.segments.iter_mut() {
+			segment.set_visibility(self.is_visible);
+			segment.update_render(mngr)?;
+		}
+		Ok(())
+	}
+}
+
+/// Evaluates the uniform Catmull-Rom spline between `p1` and `p2` at `t` in `0..=1`.
+fn catmull_rom(
+	p0: &Point3<f32>,
+	p1: &Point3<f32>,
+	p2: &Point3<f32>,
+	p3: &Point3<f32>,
+	t: f32,
+) -> Point3<f32> {
+	let (p0, p1, p2, p3) = (p0.coords, p1.coords, p2.coords, p3.coords);
+	let t2 = t * t;
+	let t3 = t2 * t;
+	let v = 0.5
+		* ((2. * p1)
+			+ (p2 - p0) * t
+			+ (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2
+			+ (3. * p1 - p0 - 3. * p2 + p3) * t3);
+	Point3::from(v)
+}
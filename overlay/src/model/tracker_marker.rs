@@ -0,0 +1,262 @@
+//! Draws a small gizmo where each tracker is, to check how the trackers are mounted
+//! and whether they drift, separately from the bones that the server computes from
+//! them.
+//!
+//! A gizmo is a short red, green, and blue line along the x, y, and z axes of the
+//! tracker, around a thicker core that is colored by the [`TrackerState`].
+
+use crate::model::{Bone, Isometry};
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use nalgebra::{UnitQuaternion, Vector3};
+use ovr_overlay::overlay::OverlayManager;
+use std::collections::hash_map::{Entry, HashMap};
+use std::time::{Duration, Instant};
+
+/// A tracker whose rotation stays the same for this long has probably stopped
+/// sending, even if the server still says that it is fine. Real ones never hold
+/// perfectly still.
+const STALE_AFTER: Duration = Duration::from_secs(1);
+/// Rotations closer than this, in radians, count as the same.
+const STILL_ANGLE: f32 = 1e-6;
+const AXIS_COLORS: [RGBA; 3] = [
+	RGBA::new(255, 64, 64, 255),
+	RGBA::new(64, 255, 64, 255),
+	RGBA::new(64, 64, 255, 255),
+];
+
+/// What the server says about a tracker, plus whether it is still moving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackerState {
+	Ok,
+	/// Connected, but busy, occluded, erroring, or not moving at all.
+	Stale,
+	Disconnected,
+}
+impl TrackerState {
+	fn color(self) -> RGBA {
+		match self {
+			Self::Ok => RGBA::new(0, 255, 128, 255),
+			Self::Stale => RGBA::new(255, 200, 0, 255),
+			Self::Disconnected => RGBA::new(128, 128, 128, 255),
+		}
+	}
+}
+
+/// Where one tracker is, for [`TrackerMarkers::update`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrackerPose {
+	/// The id of the device, and the index of the tracker on it.
+	pub id: (u8, usize),
+	pub iso: Isometry,
+	/// What the server says, before looking at whether it moves.
+	pub state: TrackerState,
+}
+
+/// The gizmo of one tracker.
+struct TrackerMarker {
+	core: Bone,
+	axes: [Bone; 3],
+	/// The last rotation that differed from the one before, and when it arrived.
+	last_moved: (UnitQuaternion<f32>, Instant),
+}
+impl TrackerMarker {
+	fn new(
+		mngr: &mut OverlayManager,
+		key: &str,
+		size: f32,
+		now: Instant,
+	) -> Result<Self> {
+		let mut bone = |name: &str, radius: f32, length: f32| {
+			Bone::new(
+				mngr,
+				RGBA::WHITE,
+				Isometry::identity(),
+				format!("{key}{name}"),
+				radius,
+				length,
+			)
+		};
+		let core = bone("Core", size * 0.15, size * 0.3)?;
+		let axes = [
+			bone("X", size * 0.03, size)?,
+			bone("Y", size * 0.03, size)?,
+			bone("Z", size * 0.03, size)?,
+		];
+		Ok(Self {
+			core,
+			axes,
+			last_moved: (UnitQuaternion::identity(), now),
+		})
+	}
+
+	fn update(&mut self, pose: &TrackerPose, now: Instant) {
+		let state = stale_state(&mut self.last_moved, pose, now);
+
+		// Centered on the tracker, and bones point down their y axis.
+		let length = self.core.length();
+		let up = pose.iso.rotation * Vector3::new(0., length / 2., 0.);
+		let mut core = pose.iso;
+		core.translation.vector += up;
+		self.core.set_isometry(core);
+		self.core.set_color(state.color());
+		self.core.set_visibility(true);
+
+		// Dimmed while the marker shows something wrong, so that the core stands out.
+		let alpha = if state == TrackerState::Ok { 255 } else { 96 };
+		let directions = [Vector3::x(), Vector3::y(), Vector3::z()];
+		for ((axis, direction), color) in
+			self.axes.iter_mut().zip(directions).zip(AXIS_COLORS)
+		{
+			let rotation = pose.iso.rotation * towards(direction);
+			axis.set_isometry(Isometry::from_parts(pose.iso.translation, rotation));
+			axis.set_color(RGBA { a: alpha, ..color });
+			axis.set_visibility(true);
+		}
+	}
+
+	fn bones_mut(&mut self) -> impl Iterator<Item = &mut Bone> {
+		[&mut self.core].into_iter().chain(self.axes.iter_mut())
+	}
+}
+
+/// The gizmos of all trackers in the feed. They are created the first time each
+/// tracker shows up, and hidden while it is missing from the feed.
+pub struct TrackerMarkers {
+	key: String,
+	size: f32,
+	markers: HashMap<(u8, usize), TrackerMarker>,
+}
+impl TrackerMarkers {
+	/// `size` is the length of the axes, in meters.
+	pub fn new(key: &str, size: f32) -> Self {
+		Self {
+			key: key.to_owned(),
+			size,
+			markers: HashMap::new(),
+		}
+	}
+
+	/// Moves the markers to `trackers`, creating the ones that are new. Hides all of
+	/// them unless `visible`.
+	pub fn update(
+		&mut self,
+		mngr: &mut OverlayManager,
+		trackers: &[TrackerPose],
+		visible: bool,
+		now: Instant,
+	) -> Result<()> {
+		for marker in self.markers.values_mut() {
+			marker.bones_mut().for_each(|b| b.set_visibility(false));
+		}
+		if !visible {
+			return Ok(());
+		}
+		for pose in trackers {
+			let marker = match self.markers.entry(pose.id) {
+				Entry::Occupied(e) => e.into_mut(),
+				Entry::Vacant(e) => {
+					let (device, index) = pose.id;
+					let key = format!("{}: Tracker{device}_{index}", self.key);
+					let marker = TrackerMarker::new(mngr, &key, self.size, now)
+						.wrap_err_with(|| format!("Failed to create marker {key:?}"))?;
+					e.insert(marker)
+				}
+			};
+			marker.update(pose, now);
+		}
+		Ok(())
+	}
+
+	pub fn update_render(&mut self, mngr: &mut OverlayManager) -> Result<()> {
+		for ((device, index), marker) in &mut self.markers {
+			for bone in marker.bones_mut() {
+				bone.update_render(mngr).wrap_err_with(|| {
+					format!("Failed to render marker of tracker {device}_{index}")
+				})?;
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Turns [`TrackerState::Ok`] into [`TrackerState::Stale`] once the rotation hasn't
+/// changed for [`STALE_AFTER`]. `last_moved` remembers when it last did.
+fn stale_state(
+	last_moved: &mut (UnitQuaternion<f32>, Instant),
+	pose: &TrackerPose,
+	now: Instant,
+) -> TrackerState {
+	if last_moved.0.angle_to(&pose.iso.rotation) > STILL_ANGLE {
+		*last_moved = (pose.iso.rotation, now);
+	}
+	match pose.state {
+		TrackerState::Ok if now - last_moved.1 >= STALE_AFTER => TrackerState::Stale,
+		state => state,
+	}
+}
+
+/// The rotation that turns the -y axis, which bones point down, towards `direction`.
+fn towards(direction: Vector3<f32>) -> UnitQuaternion<f32> {
+	UnitQuaternion::rotation_between(&-Vector3::y(), &direction).unwrap_or_else(|| {
+		UnitQuaternion::from_axis_angle(&Vector3::x_axis(), std::f32::consts::PI)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::Translation3;
+
+	fn pose(angle: f32, state: TrackerState) -> TrackerPose {
+		TrackerPose {
+			id: (0, 0),
+			iso: Isometry::from_parts(
+				Translation3::identity(),
+				UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle),
+			),
+			state,
+		}
+	}
+
+	#[test]
+	fn still_trackers_go_stale() {
+		let start = Instant::now();
+		let mut last_moved = (UnitQuaternion::identity(), start);
+		let moving = pose(0.1, TrackerState::Ok);
+		assert_eq!(
+			stale_state(&mut last_moved, &moving, start),
+			TrackerState::Ok
+		);
+		let later = start + STALE_AFTER;
+		assert_eq!(
+			stale_state(&mut last_moved, &moving, later),
+			TrackerState::Stale
+		);
+		let moved = pose(0.2, TrackerState::Ok);
+		assert_eq!(
+			stale_state(&mut last_moved, &moved, later),
+			TrackerState::Ok
+		);
+	}
+
+	#[test]
+	fn server_state_wins() {
+		let now = Instant::now();
+		let mut last_moved = (UnitQuaternion::identity(), now);
+		let gone = pose(0.1, TrackerState::Disconnected);
+		assert_eq!(
+			stale_state(&mut last_moved, &gone, now + STALE_AFTER),
+			TrackerState::Disconnected
+		);
+	}
+
+	#[test]
+	fn axes_point_the_right_way() {
+		for direction in [Vector3::x(), Vector3::y(), Vector3::z(), -Vector3::y()] {
+			let pointed = towards(direction) * -Vector3::y();
+			assert!((pointed - direction).norm() < 1e-5, "{direction:?}");
+		}
+	}
+}
@@ -0,0 +1,169 @@
+//! Draws fading trails behind the ends of selected bones, to make it easy to see
+//! whether their motion is smooth, or whether they jitter or jump around.
+//!
+//! Each trail is a fixed number of thin segments, since overlays can't be created
+//! and destroyed cheaply every frame. The end of the bone is sampled often enough to
+//! fill all segments over the length of the trail, and older segments fade out.
+
+use crate::model::skeleton::Skeleton;
+use crate::model::{Bone, BoneKind, Isometry};
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use nalgebra::{Point3, Translation3, UnitQuaternion, Vector3};
+use ovr_overlay::overlay::OverlayManager;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Thinner than the bones, so the trail doesn't hide them.
+const TRAIL_RADIUS: f32 = 0.001;
+/// Segments shorter than this aren't drawn, since the overlay math breaks down for
+/// zero length.
+const MIN_SEGMENT_LENGTH: f32 = 0.0005;
+
+/// The trail behind a single bone.
+struct Trail {
+	bone: BoneKind,
+	length: Duration,
+	/// Where the end of the bone was, oldest first.
+	samples: VecDeque<(Instant, Point3<f32>)>,
+	segments: Vec<Bone>,
+}
+impl Trail {
+	/// How long to wait between samples, so that the segments span the trail.
+	fn sample_interval(&self) -> Duration {
+		self.length / self.segments.len() as u32
+	}
+
+	fn update(&mut self, skeleton: &Skeleton, now: Instant) {
+		let bone = &skeleton.bones[self.bone];
+		if !bone.is_visible() {
+			// Don't connect the trail across the time the bone was gone.
+			self.samples.clear();
+			for segment in &mut self.segments {
+				segment.set_visibility(false);
+			}
+			return;
+		}
+		let tail = bone.isometry() * Point3::new(0., -bone.length(), 0.);
+
+		let is_due = self
+			.samples
+			.back()
+			.map_or(true, |(t, _)| now - *t >= self.sample_interval());
+		if is_due {
+			self.samples.push_back((now, tail));
+		}
+		while self.samples.len() > self.segments.len() {
+			self.samples.pop_front();
+		}
+
+		// The newest segment always ends at the current position, so the trail stays
+		// attached to the bone between samples.
+		let points = self.samples.iter().copied().chain([(now, tail)]);
+		let pairs = points.clone().zip(points.skip(1));
+		let color = bone.color();
+		let mut segments = self.segments.iter_mut();
+		for (segment, ((t, from), (_, to))) in segments.by_ref().zip(pairs) {
+			let dir = to - from;
+			let age = (now - t).as_secs_f32() / self.length.as_secs_f32();
+			let alpha = (1. - age).clamp(0., 1.);
+			let length = dir.norm();
+			if length < MIN_SEGMENT_LENGTH || alpha == 0. {
+				segment.set_visibility(false);
+				continue;
+			}
+			// Bones point down their y axis.
+			let rot = UnitQuaternion::rotation_between(&-Vector3::y(), &dir)
+				.unwrap_or_else(|| {
+					UnitQuaternion::from_axis_angle(
+						&Vector3::x_axis(),
+						std::f32::consts::PI,
+					)
+				});
+			segment.set_isometry(Isometry::from_parts(
+				Translation3::from(from.coords),
+				rot,
+			));
+			segment.set_length(length);
+			segment.set_color(RGBA {
+				a: (alpha * f32::from(color.a)) as u8,
+				..color
+			});
+			segment.set_visibility(true);
+		}
+		for segment in segments {
+			segment.set_visibility(false);
+		}
+	}
+}
+
+/// The trails of all the bones that have one.
+pub struct Trails {
+	trails: Vec<Trail>,
+}
+impl Trails {
+	/// Creates a trail for each of `bones`, lasting the given time and split into
+	/// the given number of segments.
+	pub fn new(
+		mngr: &mut OverlayManager,
+		key: &str,
+		bones: impl IntoIterator<Item = (BoneKind, Duration, usize)>,
+	) -> Result<Self> {
+		let trails = bones
+			.into_iter()
+			.map(|(bone, length, num_segments)| {
+				let segments = (0..num_segments.max(1))
+					.map(|i| {
+						Bone::new(
+							mngr,
+							RGBA::WHITE,
+							Isometry::identity(),
+							format!("{key}: Trail{bone:?}{i}"),
+							TRAIL_RADIUS,
+							0.1,
+						)
+					})
+					.collect::<Result<_>>()
+					.wrap_err_with(|| format!("Failed to create trail for {bone:?}"))?;
+				Ok(Trail {
+					bone,
+					length,
+					samples: VecDeque::new(),
+					segments,
+				})
+			})
+			.collect::<Result<_>>()?;
+		Ok(Self { trails })
+	}
+
+	/// Samples the ends of the bones. Call after the visibility of the bones has been
+	/// decided.
+	pub fn update(&mut self, skeleton: &Skeleton, now: Instant) {
+		for trail in &mut self.trails {
+			trail.update(skeleton, now);
+		}
+	}
+
+	/// Hides the trails instead of sampling the bones, until the next
+	/// [`Trails::update`] starts them over.
+	pub fn hide(&mut self) {
+		for trail in &mut self.trails {
+			trail.samples.clear();
+			for segment in &mut trail.segments {
+				segment.set_visibility(false);
+			}
+		}
+	}
+
+	pub fn update_render(&mut self, mngr: &mut OverlayManager) -> Result<()> {
+		for trail in &mut self.trails {
+			for segment in &mut trail.segments {
+				segment.update_render(mngr).wrap_err_with(|| {
+					format!("Failed to render trail of {:?}", trail.bone)
+				})?;
+			}
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,123 @@
+//! The warning panel, which lists what looks misconfigured about the setup while the
+//! SteamVR dashboard is open, see [`crate::anomalies`]. Each warning is drawn in
+//! yellow, with how to fix it below in white.
+
+use crate::anomalies::Anomaly;
+use crate::model::image::{Image, GLYPH_SIZE};
+use crate::model::{budget, ovr_transform};
+use crate::RGBA;
+
+use eyre::{Result, WrapErr};
+use nalgebra::Translation3;
+use ovr_overlay::overlay::{OverlayHandle, OverlayManager};
+use ovr_overlay::TrackedDeviceIndex;
+
+const WIDTH: usize = 1024;
+const LINE_HEIGHT: usize = GLYPH_SIZE + 2;
+const MARGIN: usize = 4;
+/// Warnings beyond this many lines are cut off.
+const MAX_LINES: usize = 12;
+/// The width of the panel in meters.
+const PANEL_WIDTH: f32 = 0.8;
+/// Where the panel goes, relative to the headset. Above the log console, and the
+/// dashboard.
+const PANEL_OFFSET: [f32; 3] = [0., 0.45, -1.0];
+const BACKGROUND: RGBA = RGBA::new(0, 0, 0, 192);
+
+/// The lines of `anomalies`, each followed by its suggestion.
+fn lines<'a>(anomalies: impl IntoIterator<Item = &'a Anomaly>) -> Vec<(String, RGBA)> {
+	anomalies
+		.into_iter()
+		.flat_map(|a| {
+			[
+				(a.to_string(), RGBA::YELLOW),
+				(format!("  {}", a.suggestion()), RGBA::WHITE),
+			]
+		})
+		.take(MAX_LINES)
+		.collect()
+}
+
+fn render(lines: &[(String, RGBA)], image: &mut Image) {
+	image.fill(BACKGROUND);
+	for (i, (text, color)) in lines.iter().enumerate() {
+		image.text(MARGIN, MARGIN + i * LINE_HEIGHT, text, *color);
+	}
+}
+
+/// Hidden while there is nothing to warn about.
+pub struct WarningPanel {
+	overlay: OverlayHandle,
+	image: Image,
+	/// The lines as of the last draw.
+	drawn: Vec<(String, RGBA)>,
+	visible: bool,
+}
+impl WarningPanel {
+	pub fn new(mngr: &mut OverlayManager, key: &str) -> Result<Self> {
+		let key = format!("{key}: WarningPanel");
+		let overlay = budget::create_overlay(mngr, &key)?;
+		mngr.set_width(overlay, PANEL_WIDTH)
+			.wrap_err("Failed to set width")?;
+		let [x, y, z] = PANEL_OFFSET;
+		let transform = Translation3::new(x, y, z)
+			.to_homogeneous()
+			.remove_fixed_rows::<1>(3);
+		mngr.set_transform_tracked_device_relative(
+			overlay,
+			TrackedDeviceIndex::HMD,
+			&ovr_transform(&transform),
+		)
+		.wrap_err("Failed to set transform")?;
+		Ok(Self {
+			overlay,
+			image: Image::new(WIDTH, MAX_LINES * LINE_HEIGHT + 2 * MARGIN),
+			drawn: Vec::new(),
+			visible: false,
+		})
+	}
+
+	/// Shows `anomalies` if `visible`, redrawing them if they changed.
+	pub fn update_render<'a>(
+		&mut self,
+		mngr: &mut OverlayManager,
+		visible: bool,
+		anomalies: impl IntoIterator<Item = &'a Anomaly>,
+	) -> Result<()> {
+		let lines = lines(anomalies);
+		let visible = visible && !lines.is_empty();
+		if visible && lines != self.drawn {
+			render(&lines, &mut self.image);
+			let Image { width, height, .. } = self.image;
+			mngr.set_raw_data(self.overlay, &self.image.pixels, width, height, 4)
+				.wrap_err("Failed to draw warning panel")?;
+			self.drawn = lines;
+		}
+		if visible != self.visible {
+			self.visible = visible;
+			mngr.set_visibility(self.overlay, visible)
+				.wrap_err("Failed to show or hide warning panel")?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::model::BoneKind;
+
+	#[test]
+	fn suggestions_follow_warnings() {
+		let jump = Anomaly::LengthJump {
+			bone: BoneKind::Chest,
+			from: 0.3,
+			to: 0.6,
+		};
+		let two = lines([&jump, &jump]);
+		assert_eq!(two.len(), 4);
+		assert_eq!(two[0].1, RGBA::YELLOW);
+		assert!(two[1].0.starts_with("  If you didn't"));
+		assert_eq!(lines(vec![&jump; MAX_LINES]).len(), MAX_LINES);
+	}
+}
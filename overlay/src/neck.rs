@@ -0,0 +1,28 @@
+//! Where the headset is, for making up the neck and head from, see
+//! [`slimevr_skeleton::neck`].
+
+use crate::model::Isometry;
+
+use nalgebra::{Matrix3, Rotation3, Translation3, UnitQuaternion};
+use ovr::pose::TrackingUniverseOrigin;
+use ovr_overlay as ovr;
+use std::time::Duration;
+
+/// Where the headset is, in the standing tracking space, `ahead` from now.
+pub fn hmd_pose(context: &ovr::Context, ahead: Duration) -> Option<Isometry> {
+	let poses = context.system_mngr().get_device_to_absolute_tracking_pose(
+		TrackingUniverseOrigin::TrackingUniverseStanding,
+		ahead.as_secs_f32(),
+	);
+	let pose = poses.get(0).filter(|p| p.bPoseIsValid)?;
+	Some(isometry(&pose.mDeviceToAbsoluteTracking.m))
+}
+
+/// The rigid transform of one of OpenVR's row-major 3x4 matrices.
+pub fn isometry(m: &[[f32; 4]; 3]) -> Isometry {
+	let rotation = Rotation3::from_matrix(&Matrix3::from_fn(|row, col| m[row][col]));
+	Isometry::from_parts(
+		Translation3::new(m[0][3], m[1][3], m[2][3]),
+		UnitQuaternion::from_rotation_matrix(&rotation),
+	)
+}
@@ -0,0 +1,180 @@
+//! Sends the skeleton over OSC, to TouchDesigner, Max, VTuber apps, or anything
+//! else that takes OSC, see `[osc]` of the config. Each feed update goes out as one
+//! bundle over UDP, with a message for each bone, of its position in meters and its
+//! rotation as a quaternion, in the same space as the CSV export:
+//!
+//! ```text
+//! /slimevr/bone/Chest ,fffffff 0.01 1.32 0.05 0.0 0.0 0.0 1.0
+//! ```
+//!
+//! How many trackers are connected goes to `/slimevr/trackers`, and whether the
+//! headset is in standby to `/slimevr/standby`, both as an int, whenever they
+//! change. `/slimevr` is the `prefix` of the config.
+
+use crate::component::{Component, Event, Frame};
+use crate::config;
+
+use eyre::{Result, WrapErr};
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+/// The time tag of bundles that apply as soon as they arrive.
+const IMMEDIATELY: u64 = 1;
+
+enum Arg {
+	Int(i32),
+	Float(f32),
+}
+
+pub struct OscOutput {
+	target: SocketAddr,
+	prefix: String,
+	socket: Option<UdpSocket>,
+	/// Whether the last send failed, so that failures are only logged once.
+	failing: bool,
+}
+impl OscOutput {
+	pub fn new(config: &config::Osc) -> Result<Self> {
+		let target = config
+			.address
+			.parse()
+			.wrap_err_with(|| format!("Invalid OSC address {:?}", config.address))?;
+		Ok(Self {
+			target,
+			prefix: config.prefix.clone(),
+			socket: None,
+			failing: false,
+		})
+	}
+
+	/// Doesn't fail when nothing is listening, since the receiver may well start
+	/// after the overlay.
+	fn send(&mut self, packet: &[u8]) {
+		let Some(socket) = &self.socket else { return };
+		match socket.send_to(packet, self.target) {
+			Ok(_) => self.failing = false,
+			// The socket doesn't block, a full buffer just loses the packet.
+			Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+			Err(e) if !self.failing => {
+				log::warn!("Failed to send OSC to {}: {e}", self.target);
+				self.failing = true;
+			}
+			Err(_) => (),
+		}
+	}
+}
+
+impl Component for OscOutput {
+	fn name(&self) -> &'static str {
+		"OscOutput"
+	}
+
+	fn init(&mut self) -> Result<()> {
+		let any: SocketAddr = match self.target {
+			SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+			SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+		};
+		let socket = UdpSocket::bind(any).wrap_err("Failed to open OSC socket")?;
+		socket
+			.set_nonblocking(true)
+			.wrap_err("Failed to open OSC socket")?;
+		log::info!("Sending the skeleton over OSC to {}", self.target);
+		self.socket = Some(socket);
+		Ok(())
+	}
+
+	fn update(&mut self, event: &Event) -> Result<()> {
+		let mut packet = Vec::new();
+		match event {
+			Event::Frame(frame) => bundle(&mut packet, &self.prefix, frame),
+			Event::Trackers(n) => {
+				let address = format!("{}/trackers", self.prefix);
+				message(&mut packet, &address, &[Arg::Int(*n as i32)]);
+			}
+			Event::Standby(standby) => {
+				let address = format!("{}/standby", self.prefix);
+				message(&mut packet, &address, &[Arg::Int(i32::from(*standby))]);
+			}
+			Event::Recording(_) => return Ok(()),
+		}
+		self.send(&packet);
+		Ok(())
+	}
+}
+
+/// Appends the bones of `frame` as a bundle.
+fn bundle(packet: &mut Vec<u8>, prefix: &str, frame: &Frame) {
+	string(packet, "#bundle");
+	packet.extend_from_slice(&IMMEDIATELY.to_be_bytes());
+	for (kind, iso) in &frame.bones {
+		let t = iso.translation.vector;
+		let r = iso.rotation;
+		let args = [t.x, t.y, t.z, r.i, r.j, r.k, r.w].map(Arg::Float);
+		// Prefixed with its size, once it is known.
+		let start = packet.len();
+		packet.extend_from_slice(&[0; 4]);
+		message(packet, &format!("{prefix}/bone/{kind:?}"), &args);
+		let size = (packet.len() - start - 4) as i32;
+		packet[start..start + 4].copy_from_slice(&size.to_be_bytes());
+	}
+}
+
+fn message(packet: &mut Vec<u8>, address: &str, args: &[Arg]) {
+	string(packet, address);
+	let tags: String = std::iter::once(',')
+		.chain(args.iter().map(|arg| match arg {
+			Arg::Int(_) => 'i',
+			Arg::Float(_) => 'f',
+		}))
+		.collect();
+	string(packet, &tags);
+	for arg in args {
+		match arg {
+			Arg::Int(i) => packet.extend_from_slice(&i.to_be_bytes()),
+			Arg::Float(f) => packet.extend_from_slice(&f.to_be_bytes()),
+		}
+	}
+}
+
+/// Terminated by a nul, and padded with more to a multiple of 4 bytes.
+fn string(packet: &mut Vec<u8>, s: &str) {
+	packet.extend_from_slice(s.as_bytes());
+	let padding = 4 - s.len() % 4;
+	packet.extend(std::iter::repeat(0).take(padding));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::model::{BoneKind, Isometry};
+
+	use std::time::SystemTime;
+
+	#[test]
+	fn messages() {
+		let mut packet = Vec::new();
+		message(&mut packet, "/slimevr/trackers", &[Arg::Int(6)]);
+		let mut expected = b"/slimevr/trackers\0\0\0,i\0\0".to_vec();
+		expected.extend_from_slice(&6i32.to_be_bytes());
+		assert_eq!(packet, expected);
+	}
+
+	#[test]
+	fn bundles() {
+		let frame = Frame {
+			timestamp: SystemTime::UNIX_EPOCH,
+			bones: vec![(BoneKind::Hip, Isometry::translation(0., 1., 0.))],
+		};
+		let mut packet = Vec::new();
+		bundle(&mut packet, "/slimevr", &frame);
+		assert_eq!(&packet[..8], b"#bundle\0");
+		assert_eq!(&packet[8..16], &IMMEDIATELY.to_be_bytes());
+		// The address, the tags, and seven floats.
+		let size = 20 + 12 + 7 * 4;
+		assert_eq!(&packet[16..20], &(size as i32).to_be_bytes());
+		assert_eq!(&packet[20..40], b"/slimevr/bone/Hip\0\0\0");
+		assert_eq!(&packet[40..52], b",fffffff\0\0\0\0");
+		assert_eq!(&packet[56..60], &1f32.to_be_bytes());
+		assert_eq!(packet.len(), 20 + size);
+	}
+}
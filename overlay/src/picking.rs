@@ -0,0 +1,260 @@
+//! Lets the user select a bone by pointing a controller at it.
+//!
+//! Pointing at a bone for [`DWELL_TIME`] selects it, which highlights it and shows
+//! its details in the [`crate::model::ContextPanel`]. Pointing at the selected bone
+//! again deselects it. The quick actions of the panel hide, recolor, or identify
+//! the selected bone, and scripts can query the selection to implement their own.
+//!
+//! The selection can also be moved without pointing, with [`Navigate`] commands
+//! from the tray or a gamepad, for people who can't aim a controller steadily. The
+//! highlight shows where the selection is either way.
+
+use crate::model::skeleton::Skeleton;
+use crate::model::BoneKind;
+use crate::RGBA;
+
+use nalgebra::{Matrix3x4, Point3, Unit, Vector3};
+use ovr::pose::TrackingUniverseOrigin;
+use ovr::sys::ETrackedDeviceClass;
+use ovr::TrackedDeviceIndex;
+use ovr_overlay as ovr;
+use std::time::{Duration, Instant};
+
+/// How long a bone must be pointed at before it gets (de)selected.
+const DWELL_TIME: Duration = Duration::from_millis(1000);
+const HIGHLIGHT_COLOR: RGBA = RGBA::WHITE;
+/// What the recolor action cycles through.
+const RECOLORS: [RGBA; 6] = [
+	RGBA::RED,
+	RGBA::YELLOW,
+	RGBA::LIME,
+	RGBA::AQUA,
+	RGBA::BLUE,
+	RGBA::FUCHSIA,
+];
+
+/// A ray pointing out of the front of a controller.
+struct Ray {
+	origin: Point3<f32>,
+	dir: Unit<Vector3<f32>>,
+}
+
+/// Gets the rays of all tracked controllers.
+fn controller_rays(context: &ovr::Context) -> Vec<Ray> {
+	let system = context.system_mngr();
+	let poses = system.get_device_to_absolute_tracking_pose(
+		TrackingUniverseOrigin::TrackingUniverseStanding,
+		0.,
+	);
+	poses
+		.iter()
+		.enumerate()
+		.filter(|(idx, pose)| {
+			let idx = TrackedDeviceIndex::new(*idx as u32);
+			pose.bPoseIsValid
+				&& system.get_tracked_device_class(idx)
+					== ETrackedDeviceClass::TrackedDeviceClass_Controller
+		})
+		.map(|(_idx, pose)| {
+			let m = pose.mDeviceToAbsoluteTracking.m;
+			let m = Matrix3x4::from_fn(|row, col| m[row][col]);
+			let origin = Point3::from(m.column(3).into_owned());
+			// Controllers point along their negative z axis.
+			let dir = Unit::new_normalize(-m.column(2).into_owned());
+			Ray { origin, dir }
+		})
+		.collect()
+}
+
+/// Moves the selection without pointing at a bone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Navigate {
+	/// Selects the next visible bone, in the order of [`BoneKind`].
+	Next,
+	Previous,
+	Deselect,
+}
+
+/// The bone after `current` among `candidates`, or before it if `forward` is false,
+/// wrapping around. `candidates` must be in the order of [`BoneKind`], and
+/// `current` doesn't need to be one of them.
+fn step(
+	candidates: &[BoneKind],
+	current: Option<BoneKind>,
+	forward: bool,
+) -> Option<BoneKind> {
+	let index = |b: &BoneKind| usize::from(*b);
+	match (current, forward) {
+		(None, true) => candidates.first().copied(),
+		(None, false) => candidates.last().copied(),
+		(Some(c), true) => candidates
+			.iter()
+			.find(|b| index(b) > index(&c))
+			.or_else(|| candidates.first())
+			.copied(),
+		(Some(c), false) => candidates
+			.iter()
+			.rev()
+			.find(|b| index(b) < index(&c))
+			.or_else(|| candidates.last())
+			.copied(),
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Selection {
+	bone: BoneKind,
+	original_color: RGBA,
+}
+
+/// The bone currently being pointed at.
+#[derive(Debug, Clone, Copy)]
+struct Hover {
+	bone: BoneKind,
+	since: Instant,
+	/// Whether we already (de)selected the bone. The user has to point away before
+	/// it can happen again.
+	is_done: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Picker {
+	hovered: Option<Hover>,
+	selected: Option<Selection>,
+	/// How often the recolor action was used, to pick the next color.
+	recolors: usize,
+}
+impl Picker {
+	pub fn selected(&self) -> Option<BoneKind> {
+		self.selected.map(|s| s.bone)
+	}
+
+	/// Changes the color that `bone` goes back to once it is deselected. Returns
+	/// whether it is selected right now, and so highlighted instead.
+	pub fn set_original_color(&mut self, bone: BoneKind, color: RGBA) -> bool {
+		match &mut self.selected {
+			Some(selection) if selection.bone == bone => {
+				selection.original_color = color;
+				true
+			}
+			_ => false,
+		}
+	}
+
+	/// Gives the selected bone the next color of [`RECOLORS`], once it is deselected.
+	/// Returns the bone and its new color, if a bone is selected.
+	pub fn recolor(&mut self) -> Option<(BoneKind, RGBA)> {
+		let selection = self.selected.as_mut()?;
+		let color = RECOLORS[self.recolors % RECOLORS.len()];
+		self.recolors += 1;
+		selection.original_color = color;
+		Some((selection.bone, color))
+	}
+
+	/// Checks which bone is being pointed at, and updates the selection.
+	pub fn update(&mut self, context: &ovr::Context, skeleton: &mut Skeleton) {
+		let hit = controller_rays(context)
+			.iter()
+			.filter_map(|ray| skeleton.raycast(&ray.origin, &ray.dir))
+			.min_by(|(_, a), (_, b)| a.total_cmp(b))
+			.map(|(bone, _dist)| bone);
+
+		let Some(hit) = hit else {
+			self.hovered = None;
+			return;
+		};
+		let hover = match &mut self.hovered {
+			Some(hover) if hover.bone == hit => hover,
+			_ => {
+				self.hovered = Some(Hover {
+					bone: hit,
+					since: Instant::now(),
+					is_done: false,
+				});
+				return;
+			}
+		};
+		if hover.is_done || hover.since.elapsed() < DWELL_TIME {
+			return;
+		}
+		hover.is_done = true;
+
+		let previous = self.deselect(skeleton);
+		if previous == Some(hit) {
+			return;
+		}
+		self.select(hit, skeleton);
+	}
+
+	/// Moves the selection among the visible bones, like pointing at them would.
+	pub fn navigate(&mut self, navigate: Navigate, skeleton: &mut Skeleton) {
+		let forward = match navigate {
+			Navigate::Next => true,
+			Navigate::Previous => false,
+			Navigate::Deselect => {
+				self.deselect(skeleton);
+				return;
+			}
+		};
+		let visible: Vec<BoneKind> = BoneKind::iter()
+			.filter(|&b| skeleton.bones[b].is_visible())
+			.collect();
+		let Some(next) = step(&visible, self.selected(), forward) else {
+			log::warn!("No visible bones to select");
+			return;
+		};
+		// Restores the color of the previous bone first, in case it is `next` again.
+		self.deselect(skeleton);
+		self.select(next, skeleton);
+	}
+
+	fn select(&mut self, bone: BoneKind, skeleton: &mut Skeleton) {
+		let b = &skeleton.bones[bone];
+		log::info!(
+			"Selected bone {bone:?}: length {:.3}m, color {:?}, visible {}",
+			b.length(),
+			b.color(),
+			b.is_visible(),
+		);
+		self.selected = Some(Selection {
+			bone,
+			original_color: b.color(),
+		});
+		skeleton.set_color(bone, HIGHLIGHT_COLOR);
+	}
+
+	/// Deselects the current selection, returning which bone it was.
+	fn deselect(&mut self, skeleton: &mut Skeleton) -> Option<BoneKind> {
+		let selection = self.selected.take()?;
+		log::info!("Deselected bone {:?}", selection.bone);
+		skeleton.set_color(selection.bone, selection.original_color);
+		Some(selection.bone)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn step_wraps_around() {
+		use BoneKind::*;
+		let bones = [Neck, Chest, Hip];
+		assert_eq!(step(&bones, None, true), Some(Neck));
+		assert_eq!(step(&bones, None, false), Some(Hip));
+		assert_eq!(step(&bones, Some(Neck), true), Some(Chest));
+		assert_eq!(step(&bones, Some(Hip), true), Some(Neck));
+		assert_eq!(step(&bones, Some(Neck), false), Some(Hip));
+		assert_eq!(step(&[], Some(Neck), true), None);
+	}
+
+	#[test]
+	fn step_from_hidden_bone() {
+		use BoneKind::*;
+		// Waist is between Chest and Hip, but hidden.
+		let bones = [Neck, Chest, Hip];
+		assert_eq!(step(&bones, Some(Waist), true), Some(Hip));
+		assert_eq!(step(&bones, Some(Waist), false), Some(Chest));
+		assert_eq!(step(&[Chest], Some(Chest), true), Some(Chest));
+	}
+}
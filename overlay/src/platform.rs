@@ -0,0 +1,264 @@
+//! The places where the overlay has to behave differently on Windows and Linux.
+
+use eyre::{eyre, Result, WrapErr};
+use serde::Deserialize;
+use std::path::{Component, Path, PathBuf};
+
+/// The file where SteamVR registers itself as the OpenVR runtime.
+const VRPATHS_FILE: &str = "openvrpaths.vrpath";
+/// The directory of the SlimeVR driver, in the `drivers` of SteamVR or wherever it
+/// was registered from.
+const SLIMEVR_DRIVER_DIR: &str = "slimevr";
+
+#[derive(Debug, Deserialize)]
+struct VrPaths {
+	#[serde(default)]
+	runtime: Vec<PathBuf>,
+	/// Drivers that live outside of SteamVR, registered with `vrpathreg`.
+	#[serde(default)]
+	external_drivers: Vec<PathBuf>,
+}
+
+/// Where `openvrpaths.vrpath` should be. On Linux that's in the config directory,
+/// but Steam installed through Flatpak keeps its own.
+fn vrpaths_candidates() -> Vec<PathBuf> {
+	let mut candidates = Vec::new();
+	#[cfg(windows)]
+	if let Some(local) = std::env::var_os("LOCALAPPDATA") {
+		candidates.push(PathBuf::from(local).join("openvr").join(VRPATHS_FILE));
+	}
+	#[cfg(target_os = "linux")]
+	{
+		let home = std::env::var_os("HOME").map(PathBuf::from);
+		let config = std::env::var_os("XDG_CONFIG_HOME")
+			.map(PathBuf::from)
+			.or_else(|| home.as_ref().map(|h| h.join(".config")));
+		if let Some(config) = config {
+			candidates.push(config.join("openvr").join(VRPATHS_FILE));
+		}
+		if let Some(home) = home {
+			candidates.push(
+				home.join(".var/app/com.valvesoftware.Steam/.config/openvr")
+					.join(VRPATHS_FILE),
+			);
+		}
+	}
+	candidates
+}
+
+/// The OpenVR client library inside of a runtime directory.
+fn vrclient(runtime: &Path) -> PathBuf {
+	if cfg!(windows) {
+		runtime.join("bin").join("vrclient_x64.dll")
+	} else {
+		runtime.join("bin").join("linux64").join("vrclient.so")
+	}
+}
+
+/// Finds the OpenVR runtime the same way `VR_Init` does, so that we can explain
+/// what is wrong when it fails. `VR_Init` itself only reports "installation not
+/// found", which isn't actionable.
+pub fn find_openvr_runtime() -> Result<PathBuf> {
+	let (vrpaths, parsed) = read_vrpaths()?;
+	for runtime in &parsed.runtime {
+		let runtime = resolve_path(runtime);
+		if vrclient(&runtime).exists() {
+			return Ok(runtime);
+		}
+		log::warn!("{runtime:?} in {vrpaths:?} doesn't contain the OpenVR client");
+	}
+	Err(eyre!(
+		"None of the runtimes in {vrpaths:?} exist, SteamVR was probably moved or \
+		 uninstalled. Run SteamVR once to register it again."
+	))
+}
+
+/// Finds the SlimeVR driver in the drivers that SteamVR would load. That it is
+/// installed doesn't mean that it is enabled, see `driver_detection` for that.
+pub fn find_slimevr_driver() -> Option<PathBuf> {
+	let (_, parsed) = read_vrpaths().ok()?;
+	let is_slimevr = |dir: &Path| {
+		dir.file_name()
+			.and_then(|n| n.to_str())
+			.map_or(false, |n| n.eq_ignore_ascii_case(SLIMEVR_DRIVER_DIR))
+	};
+	let external = parsed.external_drivers.iter().filter(|d| is_slimevr(d));
+	let bundled = parsed
+		.runtime
+		.iter()
+		.map(|r| r.join("drivers").join(SLIMEVR_DRIVER_DIR));
+	external
+		.cloned()
+		.chain(bundled)
+		.map(|d| resolve_path(&d))
+		.find(|d| d.exists())
+}
+
+/// Reads the first `openvrpaths.vrpath` that exists, and returns where it was.
+fn read_vrpaths() -> Result<(PathBuf, VrPaths)> {
+	let candidates = vrpaths_candidates();
+	let vrpaths = candidates.iter().find(|p| p.exists()).ok_or_else(|| {
+		eyre!(
+			"SteamVR isn't registered, none of {candidates:?} exist. Run SteamVR \
+			 once to register it."
+		)
+	})?;
+	let contents = std::fs::read_to_string(vrpaths)
+		.wrap_err_with(|| format!("Failed to read {vrpaths:?}"))?;
+	let parsed = serde_json::from_str(&contents)
+		.wrap_err_with(|| format!("Failed to parse {vrpaths:?}"))?;
+	Ok((vrpaths.clone(), parsed))
+}
+
+/// Returns `path`, or a path to the same file with different upper and lower case
+/// if `path` doesn't exist. Windows doesn't care about case, so configs and paths
+/// that were written there often don't match exactly on Linux.
+pub fn resolve_path(path: &Path) -> PathBuf {
+	if cfg!(windows) || path.exists() {
+		return path.to_owned();
+	}
+	match resolve_case_insensitive(path) {
+		Some(resolved) => {
+			log::info!("{path:?} doesn't exist, using {resolved:?} instead");
+			resolved
+		}
+		None => path.to_owned(),
+	}
+}
+
+fn resolve_case_insensitive(path: &Path) -> Option<PathBuf> {
+	let mut resolved = PathBuf::new();
+	for component in path.components() {
+		let next = resolved.join(component);
+		if next.exists() || !matches!(component, Component::Normal(_)) {
+			resolved = next;
+			continue;
+		}
+		let name = component.as_os_str().to_str()?.to_lowercase();
+		let dir = if resolved.as_os_str().is_empty() {
+			Path::new(".")
+		} else {
+			&resolved
+		};
+		let dir = std::fs::read_dir(dir).ok()?;
+		let entry = dir.flatten().find(|e| {
+			e.file_name()
+				.to_str()
+				.map_or(false, |n| n.to_lowercase() == name)
+		})?;
+		resolved.push(entry.file_name());
+	}
+	Some(resolved)
+}
+
+/// The Steam app id of SteamVR, which names its manifest.
+const STEAMVR_APP_ID: u32 = 250820;
+
+/// The build of SteamVR that Steam installed into `runtime`, from the app manifest
+/// next to it. Steam doesn't keep the version that SteamVR shows anywhere else.
+pub fn steamvr_build(runtime: &Path) -> Option<String> {
+	// `runtime` is `steamapps/common/SteamVR`.
+	let steamapps = runtime.parent()?.parent()?;
+	let manifest = steamapps.join(format!("appmanifest_{STEAMVR_APP_ID}.acf"));
+	let contents = std::fs::read_to_string(manifest).ok()?;
+	contents.lines().find_map(|line| {
+		let mut fields = line.split('"').filter(|f| !f.trim().is_empty());
+		match (fields.next(), fields.next()) {
+			(Some("buildid"), Some(build)) => Some(build.to_owned()),
+			_ => None,
+		}
+	})
+}
+
+/// The name and version of the operating system, as far as the system tells.
+pub fn os_version() -> String {
+	#[cfg(target_os = "linux")]
+	let version = std::fs::read_to_string("/etc/os-release")
+		.ok()
+		.and_then(|release| {
+			release.lines().find_map(|line| {
+				let name = line.strip_prefix("PRETTY_NAME=")?;
+				Some(name.trim_matches('"').to_owned())
+			})
+		});
+	#[cfg(windows)]
+	let version = command_output("cmd", &["/C", "ver"]);
+	#[cfg(not(any(target_os = "linux", windows)))]
+	let version = None;
+	let arch = std::env::consts::ARCH;
+	match version {
+		Some(version) => format!("{version} ({arch})"),
+		None => format!("{} ({arch})", std::env::consts::OS),
+	}
+}
+
+/// The names of the graphics cards.
+pub fn gpus() -> Vec<String> {
+	#[cfg(target_os = "linux")]
+	let gpus = command_output("lspci", &[]).map(|devices| {
+		devices
+			.lines()
+			.filter(|l| l.contains("VGA") || l.contains("3D controller"))
+			.filter_map(|l| Some(l.split_once(": ")?.1.to_owned()))
+			.collect()
+	});
+	#[cfg(windows)]
+	let gpus = command_output(
+		"powershell",
+		&[
+			"-NoProfile",
+			"-Command",
+			"(Get-CimInstance Win32_VideoController).Name",
+		],
+	)
+	.map(|names| names.lines().map(str::to_owned).collect());
+	#[cfg(not(any(target_os = "linux", windows)))]
+	let gpus: Option<Vec<String>> = None;
+	gpus.unwrap_or_default()
+}
+
+/// What `program` prints, if it ran and succeeded.
+#[cfg(any(target_os = "linux", windows))]
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+	let output = std::process::Command::new(program)
+		.args(args)
+		.output()
+		.ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	let output = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+	(!output.is_empty()).then_some(output)
+}
+
+/// Global hotkeys aren't available to apps under Wayland, so on Linux `SIGUSR1`
+/// toggles the skeleton instead. It can be bound to a key in the compositor, like
+/// `pkill -USR1 slimevr_overlay`. Only returns on errors.
+#[cfg(target_os = "linux")]
+pub async fn toggle_on_signal(
+	visible: &tokio::sync::watch::Sender<bool>,
+) -> Result<()> {
+	use tokio::signal::unix::{signal, SignalKind};
+
+	let mut signals =
+		signal(SignalKind::user_defined1()).wrap_err("Failed to listen for SIGUSR1")?;
+	while signals.recv().await.is_some() {
+		visible.send_modify(|v| *v = !*v);
+		log::info!("Toggled visibility from SIGUSR1: {}", *visible.borrow());
+	}
+	Ok(())
+}
+
+/// Like [`toggle_on_signal`], but `SIGUSR2` identifies the selected bone. Only
+/// returns on errors.
+#[cfg(target_os = "linux")]
+pub async fn identify_on_signal(identify: &tokio::sync::Notify) -> Result<()> {
+	use tokio::signal::unix::{signal, SignalKind};
+
+	let mut signals =
+		signal(SignalKind::user_defined2()).wrap_err("Failed to listen for SIGUSR2")?;
+	while signals.recv().await.is_some() {
+		identify.notify_one();
+	}
+	Ok(())
+}
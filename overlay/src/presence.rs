@@ -0,0 +1,336 @@
+//! Publishes whether full-body tracking is on, for streamers who want to show it
+//! without keeping it up to date by hand: how many trackers the server reports as
+//! connected, and since when. It goes to the Rich Presence of the Discord app, and
+//! as JSON to a webhook, like one of Streamer.bot or Node-RED:
+//!
+//! ```json
+//! {"tracking": true, "trackers": 6, "session_start": 1681234567, "session_s": 754}
+//! ```
+//!
+//! A session starts once a tracker connects, and ends once none are connected or
+//! the headset goes into standby. `session_start` is in seconds since the unix
+//! epoch. Outside of a session, it and `session_s` are `null`.
+//!
+//! Discord and webhooks can take their time to answer, so updates are sent from a
+//! thread of their own, at most once every `min_interval_s` of the config. Of the
+//! updates that came up meanwhile, only the latest is sent. Needs the overlay to be
+//! built with `--features presence`.
+
+use crate::component::{Component, Event};
+use crate::config;
+
+use eyre::Result;
+use serde_json::{json, Value};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+
+/// What is published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Status {
+	trackers: usize,
+	/// When the session started, while there is one.
+	session_start: Option<SystemTime>,
+}
+#[cfg_attr(not(feature = "presence"), allow(dead_code))]
+impl Status {
+	fn to_json(self, now: SystemTime) -> Value {
+		let session_s = self
+			.session_start
+			.map(|start| now.duration_since(start).unwrap_or_default().as_secs());
+		json!({
+			"tracking": self.session_start.is_some(),
+			"trackers": self.trackers,
+			"session_start": self.session_start.map(unix_secs),
+			"session_s": session_s,
+		})
+	}
+}
+
+#[cfg_attr(not(feature = "presence"), allow(dead_code))]
+fn unix_secs(time: SystemTime) -> u64 {
+	time.duration_since(SystemTime::UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs())
+}
+
+/// Publishes the status, whenever the trackers or standby change it.
+pub struct Presence {
+	config: config::Presence,
+	/// The last count from the server, which standby hides.
+	trackers: usize,
+	standby: bool,
+	status: Status,
+	/// To the publishing thread, once started.
+	sender: Option<mpsc::Sender<Status>>,
+}
+impl Presence {
+	pub fn new(config: config::Presence) -> Self {
+		Self {
+			config,
+			trackers: 0,
+			standby: false,
+			status: Status::default(),
+			sender: None,
+		}
+	}
+
+	/// The status at `now`, which keeps the session of the last one going.
+	fn status(&self, now: SystemTime) -> Status {
+		let trackers = if self.standby { 0 } else { self.trackers };
+		let session_start = match trackers {
+			0 => None,
+			_ => Some(self.status.session_start.unwrap_or(now)),
+		};
+		Status {
+			trackers,
+			session_start,
+		}
+	}
+}
+impl Component for Presence {
+	fn name(&self) -> &'static str {
+		"Presence"
+	}
+
+	fn init(&mut self) -> Result<()> {
+		let sinks = sinks(&self.config);
+		if sinks.is_empty() {
+			return Ok(());
+		}
+		let (sender, receiver) = mpsc::channel();
+		let interval = Duration::from_secs(self.config.min_interval_s);
+		std::thread::spawn(move || publish(sinks, receiver, interval));
+		self.sender = Some(sender);
+		Ok(())
+	}
+
+	fn update(&mut self, event: &Event) -> Result<()> {
+		match *event {
+			Event::Trackers(trackers) => self.trackers = trackers,
+			Event::Standby(standby) => self.standby = standby,
+			_ => return Ok(()),
+		}
+		let status = self.status(SystemTime::now());
+		if status != self.status {
+			log::debug!("Tracking status: {status:?}");
+			self.status = status;
+			if let Some(sender) = &self.sender {
+				// Only fails if the thread is gone, which it logged.
+				let _ = sender.send(status);
+			}
+		}
+		Ok(())
+	}
+
+	fn shutdown(&mut self) -> Result<()> {
+		// Which lets the thread clear the status.
+		self.sender = None;
+		Ok(())
+	}
+}
+
+/// Somewhere the status goes.
+trait Sink: Send {
+	fn name(&self) -> &'static str;
+
+	fn publish(&mut self, status: Status) -> Result<()>;
+}
+
+/// Sends each status from `statuses` to `sinks`, waiting `interval` after each.
+/// Once the overlay shuts down, clears it.
+fn publish(
+	mut sinks: Vec<Box<dyn Sink>>,
+	statuses: mpsc::Receiver<Status>,
+	interval: Duration,
+) {
+	let mut publish_all = |status: Status| {
+		for sink in &mut sinks {
+			if let Err(e) = sink.publish(status) {
+				log::warn!(
+					"Failed to publish the tracking status to {}: {e}",
+					sink.name()
+				);
+			}
+		}
+	};
+	while let Ok(status) = statuses.recv() {
+		publish_all(statuses.try_iter().last().unwrap_or(status));
+		std::thread::sleep(interval);
+	}
+	publish_all(Status::default());
+}
+
+#[cfg(not(feature = "presence"))]
+fn sinks(_config: &config::Presence) -> Vec<Box<dyn Sink>> {
+	log::warn!(
+		"Not publishing the tracking status, the `presence` feature is disabled"
+	);
+	Vec::new()
+}
+
+#[cfg(feature = "presence")]
+fn sinks(config: &config::Presence) -> Vec<Box<dyn Sink>> {
+	let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+	if config.discord {
+		sinks.push(Box::new(discord::Discord::new(&config.discord_client_id)));
+	}
+	if let Some(url) = &config.webhook {
+		sinks.push(Box::new(webhook::Webhook::new(url)));
+	}
+	sinks
+}
+
+#[cfg(feature = "presence")]
+mod discord {
+	use super::{unix_secs, Sink, Status};
+
+	use discord_rich_presence::activity::{Activity, Timestamps};
+	use discord_rich_presence::{DiscordIpc, DiscordIpcClient};
+	use eyre::{eyre, Result};
+
+	/// The Rich Presence of the Discord app, which shows how long the session has
+	/// been going on its own.
+	pub struct Discord {
+		client_id: String,
+		/// Connected on the first status, and again after Discord restarted.
+		client: Option<DiscordIpcClient>,
+	}
+	impl Discord {
+		pub fn new(client_id: &str) -> Self {
+			Self {
+				client_id: client_id.to_owned(),
+				client: None,
+			}
+		}
+
+		fn set(&mut self, status: Status) -> Result<()> {
+			// Its errors aren't `Send`, which eyre needs.
+			let err = |e: Box<dyn std::error::Error>| eyre!("{e}");
+			let client = match &mut self.client {
+				Some(client) => client,
+				None => {
+					let mut client =
+						DiscordIpcClient::new(&self.client_id).map_err(err)?;
+					client.connect().map_err(err)?;
+					self.client.insert(client)
+				}
+			};
+			let Some(start) = status.session_start else {
+				return client.clear_activity().map_err(err);
+			};
+			let state = match status.trackers {
+				1 => "1 tracker connected".to_owned(),
+				n => format!("{n} trackers connected"),
+			};
+			let timestamps = Timestamps::new().start(unix_secs(start) as i64);
+			let activity = Activity::new()
+				.details("Full-body tracking")
+				.state(&state)
+				.timestamps(timestamps);
+			client.set_activity(activity).map_err(err)
+		}
+	}
+	impl Sink for Discord {
+		fn name(&self) -> &'static str {
+			"Discord"
+		}
+
+		fn publish(&mut self, status: Status) -> Result<()> {
+			let result = self.set(status);
+			if result.is_err() {
+				self.client = None;
+			}
+			result
+		}
+	}
+}
+
+#[cfg(feature = "presence")]
+mod webhook {
+	use super::{Sink, Status};
+
+	use eyre::{Result, WrapErr};
+	use std::time::{Duration, SystemTime};
+
+	/// Webhooks that take longer than this to answer miss the update.
+	const TIMEOUT: Duration = Duration::from_secs(5);
+
+	/// POSTs the status as JSON to a URL.
+	pub struct Webhook {
+		url: String,
+		agent: ureq::Agent,
+	}
+	impl Webhook {
+		pub fn new(url: &str) -> Self {
+			Self {
+				url: url.to_owned(),
+				agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+			}
+		}
+	}
+	impl Sink for Webhook {
+		fn name(&self) -> &'static str {
+			"the webhook"
+		}
+
+		fn publish(&mut self, status: Status) -> Result<()> {
+			let body = status.to_json(SystemTime::now()).to_string();
+			self.agent
+				.post(&self.url)
+				.set("Content-Type", "application/json")
+				.send_string(&body)
+				.wrap_err_with(|| format!("Failed to POST to {}", self.url))?;
+			Ok(())
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sessions_follow_the_trackers() {
+		let mut presence = Presence::new(config::Presence::default());
+		presence.update(&Event::Trackers(3)).unwrap();
+		let start = presence.status.session_start.unwrap();
+		presence.update(&Event::Trackers(2)).unwrap();
+		assert_eq!(presence.status.trackers, 2);
+		assert_eq!(presence.status.session_start, Some(start));
+
+		presence.update(&Event::Standby(true)).unwrap();
+		assert_eq!(presence.status, Status::default());
+		presence.update(&Event::Standby(false)).unwrap();
+		assert_eq!(presence.status.trackers, 2);
+		assert!(presence.status.session_start.is_some());
+		presence.update(&Event::Trackers(0)).unwrap();
+		assert_eq!(presence.status, Status::default());
+	}
+
+	#[test]
+	fn json() {
+		let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1000);
+		let status = Status {
+			trackers: 6,
+			session_start: Some(start),
+		};
+		let now = start + Duration::from_secs(754);
+		assert_eq!(
+			status.to_json(now),
+			json!({
+				"tracking": true,
+				"trackers": 6,
+				"session_start": 1000,
+				"session_s": 754,
+			})
+		);
+		assert_eq!(
+			Status::default().to_json(now),
+			json!({
+				"tracking": false,
+				"trackers": 0,
+				"session_start": null,
+				"session_s": null,
+			})
+		);
+	}
+}
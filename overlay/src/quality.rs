@@ -0,0 +1,179 @@
+//! Draws less while the game is struggling to keep up with the headset, so that the
+//! overlay isn't what makes it drop frames. The compositor reports how long the
+//! GPU took for each frame, and whether it was dropped. Once the GPU is busy for
+//! most of the frame, or frames are dropped, for a second, the overlay switches to
+//! [`Quality::Reduced`]:
+//!
+//! - The skeleton is redrawn at most [`REDUCED_RATE`] times a second.
+//! - The trails and the tracker markers are hidden.
+//! - The bones are drawn on one overlay, like a skeleton that ran out of them, see
+//!   [`crate::model::budget`], unless `merge` is turned off in the config.
+//!
+//! It switches back to [`Quality::Full`] once there was headroom for a while, which
+//! takes longer than switching down, so that it doesn't keep flipping.
+
+use crate::frame_timing::FrameStats;
+
+use std::time::{Duration, Instant};
+
+/// How often the skeleton is redrawn at most, while the quality is reduced.
+pub const REDUCED_RATE: f32 = 30.;
+/// How much of each new frame goes into the averages.
+const SMOOTHING: f32 = 0.05;
+/// The share of the frame interval that the GPU may be busy for, on average.
+const HIGH_LOAD: f32 = 0.9;
+/// And below which there is headroom again.
+const LOW_LOAD: f32 = 0.7;
+/// The share of frames that may be dropped, on average.
+const HIGH_DROPPED: f32 = 0.05;
+/// And below which there is headroom again.
+const LOW_DROPPED: f32 = 0.01;
+/// How long the GPU has to be too busy before reducing the quality.
+const REDUCE_AFTER: Duration = Duration::from_secs(1);
+/// How long there has to be headroom before restoring it.
+const RESTORE_AFTER: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Quality {
+	#[default]
+	Full,
+	Reduced,
+}
+
+/// Keeps track of how busy the GPU is, and reports when the quality should change.
+#[derive(Debug, Default)]
+pub struct QualityGovernor {
+	quality: Quality,
+	/// The index of the last frame that was counted.
+	last_frame: Option<u32>,
+	/// The share of the frame interval that the GPU was busy for, on average.
+	load: f32,
+	/// The share of frames that were dropped, on average.
+	dropped: f32,
+	/// Since when the quality should be the other one.
+	since: Option<Instant>,
+}
+impl QualityGovernor {
+	/// Counts `frame` of the headset, whose display shows one every `interval`.
+	/// Returns the new quality, if it changed.
+	pub fn update(
+		&mut self,
+		frame: FrameStats,
+		interval: Duration,
+		now: Instant,
+	) -> Option<Quality> {
+		if self.last_frame == Some(frame.index) {
+			return None;
+		}
+		self.last_frame = Some(frame.index);
+		let load = frame.gpu.as_secs_f32() / interval.as_secs_f32();
+		self.load += SMOOTHING * (load - self.load);
+		let dropped = if frame.dropped { 1. } else { 0. };
+		self.dropped += SMOOTHING * (dropped - self.dropped);
+
+		let (wanted, wait) = match self.quality {
+			Quality::Full if self.load > HIGH_LOAD || self.dropped > HIGH_DROPPED => {
+				(Quality::Reduced, REDUCE_AFTER)
+			}
+			Quality::Reduced if self.load < LOW_LOAD && self.dropped < LOW_DROPPED => {
+				(Quality::Full, RESTORE_AFTER)
+			}
+			_ => {
+				self.since = None;
+				return None;
+			}
+		};
+		let since = *self.since.get_or_insert(now);
+		if now - since < wait {
+			return None;
+		}
+		self.since = None;
+		self.quality = wanted;
+		Some(wanted)
+	}
+
+	pub fn quality(&self) -> Quality {
+		self.quality
+	}
+
+	/// The share of the frame interval that the GPU is busy for, on average.
+	pub fn load(&self) -> f32 {
+		self.load
+	}
+
+	/// The share of frames that are dropped, on average.
+	pub fn dropped(&self) -> f32 {
+		self.dropped
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const INTERVAL: Duration = Duration::from_micros(11_111);
+
+	/// Feeds a frame that took `gpu_ms` every `INTERVAL` for `seconds`, counting up
+	/// from `*index`, and returns the last change.
+	fn run(
+		governor: &mut QualityGovernor,
+		index: &mut u32,
+		gpu_ms: f32,
+		dropped: bool,
+		seconds: f32,
+		start: Instant,
+	) -> Option<Quality> {
+		let mut changed = None;
+		let frames = (seconds / INTERVAL.as_secs_f32()) as u32;
+		for _ in 0..frames {
+			*index += 1;
+			let frame = FrameStats {
+				index: *index,
+				gpu: Duration::from_secs_f32(gpu_ms / 1000.),
+				dropped,
+			};
+			let now = start + INTERVAL * *index;
+			changed = governor.update(frame, INTERVAL, now).or(changed);
+		}
+		changed
+	}
+
+	#[test]
+	fn reduces_under_load_and_restores_with_headroom() {
+		let mut governor = QualityGovernor::default();
+		let (mut index, start) = (0, Instant::now());
+		assert_eq!(run(&mut governor, &mut index, 5., false, 5., start), None);
+
+		// A short spike doesn't count.
+		assert_eq!(run(&mut governor, &mut index, 11., false, 0.5, start), None);
+		assert_eq!(run(&mut governor, &mut index, 5., false, 2., start), None);
+
+		let changed = run(&mut governor, &mut index, 11., false, 2., start);
+		assert_eq!(changed, Some(Quality::Reduced));
+		assert_eq!(governor.quality(), Quality::Reduced);
+
+		// Not enough headroom to restore it yet.
+		assert_eq!(run(&mut governor, &mut index, 9., false, 20., start), None);
+		let changed = run(&mut governor, &mut index, 5., false, 12., start);
+		assert_eq!(changed, Some(Quality::Full));
+	}
+
+	#[test]
+	fn reduces_when_frames_are_dropped() {
+		let mut governor = QualityGovernor::default();
+		let (mut index, start) = (0, Instant::now());
+		let changed = run(&mut governor, &mut index, 5., true, 2., start);
+		assert_eq!(changed, Some(Quality::Reduced));
+		assert!(governor.dropped() > HIGH_DROPPED);
+
+		// The same frame again, since the overlay can be faster than the headset.
+		let frame = FrameStats {
+			index,
+			gpu: Duration::ZERO,
+			dropped: false,
+		};
+		let dropped = governor.dropped();
+		assert_eq!(governor.update(frame, INTERVAL, start), None);
+		assert_eq!(governor.dropped(), dropped);
+	}
+}
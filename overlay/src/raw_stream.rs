@@ -0,0 +1,143 @@
+//! Asks a tracker to stream the raw samples of its IMU, and receives them, so that
+//! they can be plotted. The firmware sends the samples to whoever asked for them,
+//! without treating us as its server, so this works while the tracker stays
+//! connected to the SlimeVR server.
+//!
+//! The timestamps of the tracker are mapped onto the overlay's clock, see
+//! [`crate::timebase`].
+//!
+//! Trackers that the server turned packet checksums on for ignore our requests,
+//! since we don't know about it and send them without one.
+
+use crate::timebase::SourceClock;
+
+use eyre::{Result, WrapErr};
+use firmware_protocol::{CbPacket, Packet, SbPacket};
+use nalgebra::Vector3;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tokio_graceful_shutdown::SubsystemHandle;
+
+/// The port that trackers listen on.
+const TRACKER_PORT: u16 = 6969;
+/// The sensor to stream. Trackers only have one so far.
+const SENSOR_ID: u8 = 0;
+/// How long each request streams for. Kept short, so that the tracker stops by
+/// itself soon after we go away without saying so.
+const REQUEST_DURATION: Duration = Duration::from_secs(10);
+/// How often the request is renewed, well before it runs out.
+const RENEW_INTERVAL: Duration = Duration::from_secs(4);
+/// How many samples can be waiting for a slow subscriber before the oldest are
+/// dropped. A few frames worth at the rates IMUs run at.
+pub const CHANNEL_CAPACITY: usize = 256;
+
+/// One raw sample of an IMU.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawSample {
+	/// When the tracker took the sample, on the overlay's clock.
+	pub time: Instant,
+	/// In m/s^2.
+	pub accel: Vector3<f32>,
+	/// In rad/s.
+	pub gyro: Vector3<f32>,
+}
+
+/// Turns a packet that arrived at `arrival` into a sample, if it is one.
+fn to_sample(
+	packet: SbPacket,
+	clock: &mut SourceClock,
+	arrival: Instant,
+) -> Option<RawSample> {
+	let SbPacket::RawImuData {
+		sensor_id: SENSOR_ID,
+		timestamp_us,
+		accel,
+		gyro,
+	} = packet
+	else {
+		return None;
+	};
+	Some(RawSample {
+		time: clock.map_wrapping_us(timestamp_us, arrival),
+		accel: Vector3::new(accel.0, accel.1, accel.2),
+		gyro: Vector3::new(gyro.0, gyro.1, gyro.2),
+	})
+}
+
+async fn request(socket: &UdpSocket, tracker: SocketAddr, duration: Duration) {
+	let packet = Packet::new(
+		0,
+		CbPacket::StreamRawData {
+			sensor_id: SENSOR_ID,
+			duration_ms: duration.as_millis() as u32,
+		},
+	);
+	let mut buf = [0; 64];
+	let result = match packet.serialize_into(&mut buf) {
+		Ok(len) => socket.send_to(&buf[..len], tracker).await.map(|_| ()),
+		Err(e) => {
+			log::error!("Failed to serialize raw data request: {e:?}");
+			return;
+		}
+	};
+	if let Err(e) = result {
+		log::warn!("Failed to request raw data from {tracker}: {e}");
+	}
+}
+
+/// Streams the raw samples of the tracker at `tracker` until shutdown, sending them
+/// to `sender`.
+pub async fn run(
+	subsys: SubsystemHandle,
+	tracker: Ipv4Addr,
+	sender: broadcast::Sender<RawSample>,
+) -> Result<()> {
+	let tracker = SocketAddr::from((tracker, TRACKER_PORT));
+	let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+		.await
+		.wrap_err("Failed to open a socket for raw data")?;
+	log::info!("Streaming raw IMU data from {}", tracker.ip());
+
+	let mut clock = SourceClock::default();
+	let mut renew = tokio::time::interval(RENEW_INTERVAL);
+	let mut buf = [0; 1536];
+	loop {
+		let r = tokio::select! {
+			_ = subsys.on_shutdown_requested() => break,
+			_ = renew.tick() => {
+				request(&socket, tracker, REQUEST_DURATION).await;
+				continue;
+			}
+			r = socket.recv_from(&mut buf) => r,
+		};
+		// Windows reports ICMP errors from earlier sends here, which don't matter.
+		let arrival = Instant::now();
+		let (len, from) = match r {
+			Ok(r) => r,
+			Err(e) => {
+				log::debug!("Failed to receive raw data: {e}");
+				continue;
+			}
+		};
+		if from.ip() != tracker.ip() {
+			continue;
+		}
+		// Trackers that the server turned checksums on for send them here too.
+		let bytes = &buf[..len];
+		let packet = Packet::<SbPacket>::deserialize_from(bytes)
+			.or_else(|_| Packet::deserialize_from_checked(bytes));
+		let Ok(packet) = packet else {
+			log::trace!("Ignoring malformed packet from {from}");
+			continue;
+		};
+		if let Some(sample) = to_sample(packet.split().1, &mut clock, arrival) {
+			// Nobody might be subscribed, which is fine.
+			let _ = sender.send(sample);
+		}
+	}
+	// A duration of zero stops the stream right away.
+	request(&socket, tracker, Duration::ZERO).await;
+	Ok(())
+}
@@ -0,0 +1,446 @@
+//! A compact binary format for long recordings of the skeleton stream, written with
+//! `--record`. CSV takes around 60 bytes per bone and update, which adds up to
+//! gigabytes over a session of a few hours. This takes a few bytes per bone and
+//! update.
+//!
+//! A recording starts with [`MAGIC`] and a version byte, followed by zstd frames
+//! that each hold a chunk of up to [`CHUNK_FRAMES`] updates. Within a chunk, every
+//! number is stored as the difference to the same number in the update before it,
+//! zigzag encoded as a LEB128 varint, so that a bone that barely moved takes one
+//! byte per number before compressing. Each chunk has:
+//! - the number of updates in it
+//! - for each update, the microseconds since the one before (since the unix epoch
+//!   for the first one of the chunk) and the number of bones
+//! - for each bone, its index in [`BoneKind`], its position in tenths of
+//!   millimeters, and its rotation as `x, y, z, w` times [`ROTATION_SCALE`]
+//!
+//! Chunks don't depend on each other, so a recording that was cut off, like when
+//! the overlay crashed, only misses its last chunk.
+//!
+//! `--convert` turns CSV exports into recordings and back, and `--render-replay`
+//! reads both.
+
+use crate::component::{Component, Event, Frame as FeedFrame};
+use crate::model::{BoneKind, BoneMap, Isometry};
+use crate::replay::{self, Frame};
+
+use eyre::{eyre, Result, WrapErr};
+use nalgebra::{Quaternion, Translation3, UnitQuaternion};
+use std::fs::File;
+use std::io::{BufWriter, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const MAGIC: &[u8; 6] = b"SLREC\0";
+/// Goes up whenever the layout changes, or the indices of [`BoneKind`] do.
+const VERSION: u8 = 1;
+/// What recordings are named, so that `--convert` can tell them from CSV.
+pub const EXTENSION: &str = "slrec";
+/// About ten seconds of updates, which are lost at most when the overlay crashes.
+const CHUNK_FRAMES: u32 = 1000;
+const ZSTD_LEVEL: i32 = 3;
+/// Units per meter of the positions.
+const POSITION_SCALE: f32 = 10_000.;
+/// Units per unit of the rotations, which is the most that fits in an `i16`.
+const ROTATION_SCALE: f32 = i16::MAX as f32;
+
+/// A bone in quantized units: the position, then the rotation.
+type Quantized = [i32; 7];
+
+/// Records the frames on the bus to a single file at `path`, for the whole run.
+pub struct Recorder {
+	path: PathBuf,
+	writer: Option<BufWriter<File>>,
+	encoder: Encoder,
+}
+impl Recorder {
+	pub fn new(path: PathBuf) -> Self {
+		Self {
+			path,
+			writer: None,
+			encoder: Encoder::new(),
+		}
+	}
+
+	/// Compresses the chunk so far and writes it out.
+	fn write_chunk(&mut self) -> Result<()> {
+		let Some(writer) = self.writer.as_mut() else {
+			return Ok(());
+		};
+		let Some(chunk) = self.encoder.finish()? else {
+			return Ok(());
+		};
+		writer
+			.write_all(&chunk)
+			.and_then(|_| writer.flush())
+			.wrap_err_with(|| format!("Failed to write to {:?}", self.path))
+	}
+}
+impl Component for Recorder {
+	fn name(&self) -> &'static str {
+		"Recorder"
+	}
+
+	fn init(&mut self) -> Result<()> {
+		log::info!("Recording skeleton to {:?}", self.path);
+		self.writer = Some(create_file(&self.path)?);
+		Ok(())
+	}
+
+	fn update(&mut self, event: &Event) -> Result<()> {
+		let Event::Frame(frame) = event else {
+			return Ok(());
+		};
+		self.encoder.push(micros_since_epoch(frame), &frame.bones);
+		if self.encoder.frames >= CHUNK_FRAMES {
+			self.write_chunk()?;
+		}
+		Ok(())
+	}
+
+	fn shutdown(&mut self) -> Result<()> {
+		self.write_chunk()
+	}
+}
+
+fn micros_since_epoch(frame: &FeedFrame) -> u64 {
+	let since = frame.timestamp.duration_since(SystemTime::UNIX_EPOCH);
+	since.unwrap_or_default().as_micros() as u64
+}
+
+fn create_file(path: &Path) -> Result<BufWriter<File>> {
+	let file = File::create(path)
+		.wrap_err_with(|| format!("Failed to create recording {path:?}"))?;
+	let mut writer = BufWriter::new(file);
+	writer
+		.write_all(MAGIC)
+		.and_then(|_| writer.write_all(&[VERSION]))
+		.wrap_err_with(|| format!("Failed to write to {path:?}"))?;
+	Ok(writer)
+}
+
+/// Collects updates into a chunk, and compresses it into a zstd frame once it is
+/// done.
+struct Encoder {
+	/// The updates of the chunk so far, before compressing.
+	body: Vec<u8>,
+	frames: u32,
+	last_micros: u64,
+	last: BoneMap<Quantized>,
+}
+impl Encoder {
+	fn new() -> Self {
+		Self {
+			body: Vec::new(),
+			frames: 0,
+			last_micros: 0,
+			last: BoneMap::default(),
+		}
+	}
+
+	fn push(&mut self, micros: u64, bones: &[(BoneKind, Isometry)]) {
+		let buf = &mut self.body;
+		// Timestamps never go backwards, see `crate::timebase`.
+		write_varint(buf, micros.saturating_sub(self.last_micros));
+		self.last_micros = micros;
+		write_varint(buf, bones.len() as u64);
+		for &(kind, iso) in bones {
+			buf.push(u8::from(kind));
+			let last = &mut self.last[kind];
+			let next = quantize(&iso, last);
+			for (next, last) in next.iter().zip(last.iter()) {
+				write_varint(buf, zigzag(i64::from(*next) - i64::from(*last)));
+			}
+			*last = next;
+		}
+		self.frames += 1;
+	}
+
+	/// The compressed chunk, if there were any updates since the last one. The next
+	/// chunk starts over, without differences to this one.
+	fn finish(&mut self) -> Result<Option<Vec<u8>>> {
+		if self.frames == 0 {
+			return Ok(None);
+		}
+		let mut chunk = Vec::with_capacity(self.body.len() + 5);
+		write_varint(&mut chunk, u64::from(self.frames));
+		chunk.append(&mut self.body);
+		*self = Self::new();
+		let compressed = zstd::bulk::compress(&chunk, ZSTD_LEVEL)
+			.wrap_err("Failed to compress recording")?;
+		Ok(Some(compressed))
+	}
+}
+
+/// `iso` in quantized units. `q` and `-q` are the same rotation, so the one closer
+/// to `last` is picked, which keeps the differences small.
+fn quantize(iso: &Isometry, last: &Quantized) -> Quantized {
+	let t = iso.translation.vector * POSITION_SCALE;
+	let mut q = iso.rotation.coords * ROTATION_SCALE;
+	let dot: f32 = (0..4).map(|i| q[i] * last[3 + i] as f32).sum();
+	if dot < 0. {
+		q = -q;
+	}
+	[t.x, t.y, t.z, q.x, q.y, q.z, q.w].map(|v| v.round() as i32)
+}
+
+fn dequantize(bone: &Quantized) -> Result<Isometry> {
+	let [x, y, z, i, j, k, w] = bone.map(|v| v as f32);
+	let rotation = UnitQuaternion::try_new(Quaternion::new(w, i, j, k), 0.)
+		.ok_or_else(|| eyre!("A bone has no rotation"))?;
+	let [x, y, z] = [x, y, z].map(|v| v / POSITION_SCALE);
+	Ok(Isometry::from_parts(Translation3::new(x, y, z), rotation))
+}
+
+/// Reads the recording at `path`. One that was cut off is read up to where it ends,
+/// with a warning.
+pub fn read(path: &Path) -> Result<Vec<Frame>> {
+	let mut file =
+		File::open(path).wrap_err_with(|| format!("Failed to open {path:?}"))?;
+	let mut header = [0; MAGIC.len() + 1];
+	file.read_exact(&mut header)
+		.wrap_err_with(|| format!("{path:?} is too short to be a recording"))?;
+	let (magic, version) = header.split_at(MAGIC.len());
+	if magic != MAGIC {
+		return Err(eyre!("{path:?} is not a recording"));
+	}
+	if version[0] != VERSION {
+		return Err(eyre!(
+			"{path:?} has version {} of the format, we only read {VERSION}",
+			version[0]
+		));
+	}
+
+	let mut frames = Vec::new();
+	let decoder = zstd::stream::read::Decoder::new(file)
+		.wrap_err("Failed to start decompressing")?;
+	match decode(decoder, &mut frames) {
+		Ok(()) => Ok(frames),
+		Err(e) if !frames.is_empty() => {
+			log::warn!("{path:?} ends early, after {} updates: {e}", frames.len());
+			Ok(frames)
+		}
+		Err(e) => Err(e.wrap_err(format!("Invalid recording {path:?}"))),
+	}
+}
+
+/// Decodes the chunks in `reader` to `frames`, until it ends.
+fn decode(mut reader: impl Read, frames: &mut Vec<Frame>) -> Result<()> {
+	loop {
+		let count = match read_varint(&mut reader) {
+			Ok(count) => count,
+			Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+			Err(e) => return Err(e.into()),
+		};
+		let mut micros = 0;
+		let mut last: BoneMap<Quantized> = BoneMap::default();
+		for _ in 0..count {
+			micros = read_varint(&mut reader)?.saturating_add(micros);
+			let bone_count = read_varint(&mut reader)?;
+			let mut bones = Vec::new();
+			for _ in 0..bone_count {
+				let mut index = [0];
+				reader.read_exact(&mut index)?;
+				let kind = BoneKind::try_from(index[0])
+					.map_err(|_| eyre!("{} is not a bone", index[0]))?;
+				let bone = &mut last[kind];
+				for v in bone.iter_mut() {
+					let delta = unzigzag(read_varint(&mut reader)?);
+					*v = i64::from(*v).wrapping_add(delta) as i32;
+				}
+				bones.push((kind, dequantize(bone)?));
+			}
+			frames.push(Frame {
+				timestamp: micros as f64 / 1_000_000.,
+				bones,
+			});
+		}
+	}
+}
+
+/// Converts the CSV export at `from` to a recording at `to`, or a recording back to
+/// CSV, depending on whether `from` has the [`EXTENSION`] of recordings. Returns how
+/// many updates were converted.
+pub fn convert(from: &Path, to: &Path) -> Result<usize> {
+	let frames = replay::load(from)?;
+	if is_recording(from) {
+		crate::csv_export::write_frames(to, &frames)?;
+	} else {
+		let mut writer = create_file(to)?;
+		for chunk in frames.chunks(CHUNK_FRAMES as usize) {
+			let mut encoder = Encoder::new();
+			for frame in chunk {
+				let micros = (frame.timestamp * 1_000_000.).round() as u64;
+				encoder.push(micros, &frame.bones);
+			}
+			let compressed = encoder.finish()?.unwrap_or_default();
+			writer
+				.write_all(&compressed)
+				.wrap_err_with(|| format!("Failed to write to {to:?}"))?;
+		}
+		writer
+			.flush()
+			.wrap_err_with(|| format!("Failed to write to {to:?}"))?;
+	}
+	Ok(frames.len())
+}
+
+/// Where `--convert` writes to by default: next to `from`, with the extension of
+/// the other format.
+pub fn converted_path(from: &Path) -> PathBuf {
+	let extension = if is_recording(from) { "csv" } else { EXTENSION };
+	from.with_extension(extension)
+}
+
+pub fn is_recording(path: &Path) -> bool {
+	path.extension().map_or(false, |ext| ext == EXTENSION)
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+	while value >= 0x80 {
+		buf.push(value as u8 | 0x80);
+		value >>= 7;
+	}
+	buf.push(value as u8);
+}
+
+fn read_varint(reader: &mut impl Read) -> std::io::Result<u64> {
+	let mut value = 0;
+	for shift in (0..64).step_by(7) {
+		let mut byte = [0];
+		reader.read_exact(&mut byte)?;
+		value |= u64::from(byte[0] & 0x7F) << shift;
+		if byte[0] & 0x80 == 0 {
+			return Ok(value);
+		}
+	}
+	Err(std::io::Error::new(
+		ErrorKind::InvalidData,
+		"Varint is too long",
+	))
+}
+
+/// Maps small negative numbers to small positive ones: `0, -1, 1, -2` to `0, 1, 2,
+/// 3`.
+fn zigzag(value: i64) -> u64 {
+	((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+	(value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::Vector3;
+
+	fn bone(y: f32, angle: f32) -> Isometry {
+		Isometry::from_parts(
+			Translation3::new(0.1, y, -0.2),
+			UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle),
+		)
+	}
+
+	fn encode(frames: &[Frame]) -> Vec<u8> {
+		let mut encoder = Encoder::new();
+		for frame in frames {
+			let micros = (frame.timestamp * 1_000_000.).round() as u64;
+			encoder.push(micros, &frame.bones);
+		}
+		encoder.finish().unwrap().unwrap()
+	}
+
+	fn walk(count: usize) -> Vec<Frame> {
+		(0..count)
+			.map(|i| {
+				let t = i as f32 / 100.;
+				Frame {
+					timestamp: 1681234567. + f64::from(t),
+					bones: vec![
+						(BoneKind::Chest, bone(1.3 + t.sin() * 0.01, t)),
+						(BoneKind::Hip, bone(0.9, -t)),
+					],
+				}
+			})
+			.collect()
+	}
+
+	#[test]
+	fn round_trips() {
+		let frames = walk(300);
+		let compressed = encode(&frames);
+		let mut decoded = Vec::new();
+		decode(
+			zstd::stream::read::Decoder::new(&compressed[..]).unwrap(),
+			&mut decoded,
+		)
+		.unwrap();
+		assert_eq!(decoded.len(), frames.len());
+		for (a, b) in frames.iter().zip(&decoded) {
+			assert!((a.timestamp - b.timestamp).abs() < 1e-5);
+			for ((kind_a, a), (kind_b, b)) in a.bones.iter().zip(&b.bones) {
+				assert_eq!(kind_a, kind_b);
+				let t = a.translation.vector - b.translation.vector;
+				assert!(t.norm() < 1e-4);
+				assert!(a.rotation.angle_to(&b.rotation) < 1e-3);
+			}
+		}
+	}
+
+	#[test]
+	fn much_smaller_than_csv() {
+		let frames = walk(1000);
+		// Each row of the CSV export is longer than this.
+		let csv_bytes = frames.len() * 2 * 60;
+		assert!(encode(&frames).len() * 5 < csv_bytes);
+	}
+
+	#[test]
+	fn flipped_quaternions_stay_close() {
+		let q = bone(1., 0.5);
+		let last = quantize(&q, &[0; 7]);
+		let flipped = Isometry::from_parts(
+			q.translation,
+			UnitQuaternion::new_unchecked(-q.rotation.into_inner()),
+		);
+		assert_eq!(quantize(&flipped, &last), last);
+	}
+
+	#[test]
+	fn chunks_follow_each_other() {
+		let frames = walk(3);
+		let mut both = encode(&frames[..2]);
+		both.extend(encode(&frames[2..]));
+		let mut decoded = Vec::new();
+		decode(
+			zstd::stream::read::Decoder::new(&both[..]).unwrap(),
+			&mut decoded,
+		)
+		.unwrap();
+		assert_eq!(decoded.len(), 3);
+		let y = decoded[2].bones[0].1.translation.vector.y;
+		assert!((y - frames[2].bones[0].1.translation.vector.y).abs() < 1e-4);
+	}
+
+	#[test]
+	fn varints() {
+		for value in [
+			0,
+			1,
+			-1,
+			63,
+			-64,
+			64,
+			i64::from(i32::MAX),
+			i64::from(i32::MIN),
+		] {
+			let mut buf = Vec::new();
+			write_varint(&mut buf, zigzag(value));
+			assert_eq!(unzigzag(read_varint(&mut &buf[..]).unwrap()), value);
+		}
+		let mut buf = Vec::new();
+		write_varint(&mut buf, zigzag(-1));
+		assert_eq!(buf, [1]);
+	}
+}
@@ -0,0 +1,363 @@
+//! Renders a recording made with `--export-csv` or `--record` from a virtual
+//! camera, to share what the tracking looked like in a bug report or a clip. Runs
+//! instead of the overlay, so SteamVR and the server aren't needed.
+//!
+//! Each frame becomes a binary PPM image, numbered like `frame_00042.ppm`, which
+//! most video tools read as an image sequence. For example, at the default 30 fps:
+//! ```sh
+//! ffmpeg -framerate 30 -i replay/frame_%05d.ppm -pix_fmt yuv420p replay.mp4
+//! ```
+//! The finished video can then be added to OBS as a media source. The camera is
+//! configured with the `[replay_camera]` table of the config, see
+//! [`ReplayCamera`].
+//!
+//! The recording only has where each bone starts, so their lengths come from the bone
+//! lengths the overlay remembered, see [`slimevr_skeleton::proportions`].
+//!
+//! The bones are drawn as lines, or with the `scene` feature as shaded capsules or
+//! an avatar, see [`Bones`].
+
+use crate::config::{ReplayCamera, SkeletonStyle};
+use crate::model::skeleton::{default_color, DEFAULT_BONE_LENGTH};
+use crate::model::{BoneKind, BoneMap, Image, Isometry};
+use crate::recording;
+use crate::RGBA;
+
+use eyre::{eyre, Result, WrapErr};
+use nalgebra::{Isometry3, Point3, Quaternion, Translation3, UnitQuaternion, Vector3};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+const BACKGROUND: RGBA = RGBA::new(24, 24, 28, 255);
+const GRID: RGBA = RGBA::new(64, 64, 72, 255);
+/// The floor grid goes this many meters in each direction from the origin.
+const GRID_EXTENT: i32 = 3;
+/// Points closer to the camera than this, in meters, aren't drawn.
+const Z_NEAR: f32 = 0.05;
+/// How far the lines of the bones reach to each side, in pixels.
+const BONE_THICKNESS: i32 = 1;
+
+/// How the bones are drawn.
+pub enum Bones {
+	/// As lines, which needs no GPU.
+	Lines,
+	/// With the radius of the style, or as its avatar, see [`crate::scene`]. The
+	/// target has to be readable, and of the size of the camera.
+	#[cfg(feature = "scene")]
+	Scene(crate::scene::SceneRenderer, crate::scene::Target),
+}
+
+/// The bones of one feed update in the recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+	/// In seconds since the unix epoch.
+	pub timestamp: f64,
+	pub bones: Vec<(BoneKind, Isometry)>,
+}
+
+/// Renders the recording at `csv` to numbered images in `out_dir`, and returns how
+/// many were written.
+pub fn export(
+	csv: &Path,
+	out_dir: &Path,
+	camera: &ReplayCamera,
+	style: &SkeletonStyle,
+	lengths: BoneMap<Option<f32>>,
+	mut bones: Bones,
+) -> Result<usize> {
+	let frames = load(csv)?;
+	let (Some(first), Some(last)) = (frames.first(), frames.last()) else {
+		return Err(eyre!("{csv:?} has no rows"));
+	};
+	std::fs::create_dir_all(out_dir)
+		.wrap_err_with(|| format!("Failed to create {out_dir:?}"))?;
+
+	let hidden: HashSet<BoneKind> = style.hidden().collect();
+	let mut colors = BoneMap::new([RGBA::WHITE; BoneKind::num_types()]);
+	for (kind, color) in &mut colors {
+		*color = style.color(kind).unwrap_or_else(|| default_color(kind));
+	}
+	let duration = last.timestamp - first.timestamp;
+	let count = (duration * f64::from(camera.fps)).floor() as usize + 1;
+	log::info!("Rendering {count} frames of {csv:?} to {out_dir:?}");
+
+	let mut image = Image::new(camera.width, camera.height);
+	let mut current = 0;
+	for i in 0..count {
+		let elapsed = i as f64 / f64::from(camera.fps);
+		current = frame_at(&frames, current, first.timestamp + elapsed);
+		let view = Camera::new(camera, elapsed as f32);
+
+		image.fill(BACKGROUND);
+		draw_grid(&mut image, &view);
+		let frame = frames[current]
+			.bones
+			.iter()
+			.filter(|(k, _)| !hidden.contains(k));
+		match &mut bones {
+			Bones::Lines => {
+				for &(kind, iso) in frame {
+					let length = lengths[kind].unwrap_or(DEFAULT_BONE_LENGTH);
+					let head = iso * Point3::origin();
+					let tail = iso * Point3::new(0., -length, 0.);
+					if let (Some(from), Some(to)) =
+						(view.project(head), view.project(tail))
+					{
+						thick_line(&mut image, from, to, colors[kind]);
+					}
+				}
+			}
+			#[cfg(feature = "scene")]
+			Bones::Scene(scene, target) => {
+				let mut poses = BoneMap::new(
+					[slimevr_skeleton::Bone::new(0., style.radius);
+						BoneKind::num_types()],
+				);
+				for &(kind, iso) in frame {
+					let pose = &mut poses[kind];
+					pose.iso = iso;
+					pose.length = lengths[kind].unwrap_or(DEFAULT_BONE_LENGTH);
+					pose.is_visible = true;
+				}
+				let aspect = camera.width as f32 / camera.height as f32;
+				let fov_y = camera.fov_degrees.to_radians();
+				let view_proj =
+					crate::scene::view_projection(&view.view, fov_y, aspect);
+				scene.draw(target, &view_proj, &poses, &colors);
+				let pixels = scene.read_back(target)?;
+				image.blend(&pixels);
+			}
+		}
+		image.text(8, 8, &format!("{elapsed:.2} s"), RGBA::WHITE);
+
+		let path = out_dir.join(format!("frame_{i:05}.ppm"));
+		write_ppm(&path, &image)?;
+	}
+	Ok(count)
+}
+
+/// Reads a CSV export, or a recording in the format of [`crate::recording`].
+pub fn load(path: &Path) -> Result<Vec<Frame>> {
+	if recording::is_recording(path) {
+		return recording::read(path);
+	}
+	let contents = std::fs::read_to_string(path)
+		.wrap_err_with(|| format!("Failed to read {path:?}"))?;
+	parse(&contents).wrap_err_with(|| format!("Invalid CSV export {path:?}"))
+}
+
+/// Reads the rows written by [`crate::csv_export`], grouping consecutive rows with
+/// the same timestamp into one frame.
+pub fn parse(contents: &str) -> Result<Vec<Frame>> {
+	let mut frames: Vec<Frame> = Vec::new();
+	for (i, line) in contents.lines().enumerate() {
+		if line.is_empty() || line.starts_with("timestamp,") {
+			continue;
+		}
+		let (timestamp, kind, iso) =
+			parse_row(line).wrap_err_with(|| format!("On line {}", i + 1))?;
+		match frames.last_mut() {
+			Some(frame) if frame.timestamp == timestamp => {
+				frame.bones.push((kind, iso))
+			}
+			Some(frame) if frame.timestamp > timestamp => {
+				return Err(eyre!("Line {} goes back in time", i + 1));
+			}
+			_ => frames.push(Frame {
+				timestamp,
+				bones: vec![(kind, iso)],
+			}),
+		}
+	}
+	Ok(frames)
+}
+
+fn parse_row(line: &str) -> Result<(f64, BoneKind, Isometry)> {
+	let fields: Vec<&str> = line.split(',').collect();
+	let [timestamp, kind, rest @ ..] = fields.as_slice() else {
+		return Err(eyre!("Expected 9 fields, found {}", fields.len()));
+	};
+	let timestamp: f64 = timestamp
+		.parse()
+		.wrap_err_with(|| format!("Invalid timestamp `{timestamp}`"))?;
+	let kind: BoneKind = kind.parse().map_err(|_| eyre!("`{kind}` is not a bone"))?;
+	let numbers = rest
+		.iter()
+		.map(|n| {
+			n.parse::<f32>()
+				.wrap_err_with(|| format!("Invalid number `{n}`"))
+		})
+		.collect::<Result<Vec<f32>>>()?;
+	let &[x, y, z, qx, qy, qz, qw] = numbers.as_slice() else {
+		return Err(eyre!("Expected 9 fields, found {}", fields.len()));
+	};
+	let rotation = UnitQuaternion::from_quaternion(Quaternion::new(qw, qx, qy, qz));
+	Ok((
+		timestamp,
+		kind,
+		Isometry::from_parts(Translation3::new(x, y, z), rotation),
+	))
+}
+
+/// The index of the last frame at or before `timestamp`, searching forward from
+/// `current`. Frames are held until the next one, like the overlay would.
+fn frame_at(frames: &[Frame], mut current: usize, timestamp: f64) -> usize {
+	while frames
+		.get(current + 1)
+		.map_or(false, |next| next.timestamp <= timestamp)
+	{
+		current += 1;
+	}
+	current
+}
+
+/// A pinhole camera at one moment of the replay.
+struct Camera {
+	view: Isometry3<f32>,
+	/// The distance from the camera to the image plane, in pixels.
+	focal: f32,
+	center: (f32, f32),
+}
+impl Camera {
+	/// Where `config` puts the camera, `elapsed` seconds into the replay.
+	fn new(config: &ReplayCamera, elapsed: f32) -> Self {
+		let target = Point3::from(config.target);
+		let mut eye = Point3::from(config.position);
+		if config.orbit_seconds > 0. {
+			let angle = elapsed / config.orbit_seconds * std::f32::consts::TAU;
+			let orbit = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle);
+			eye = target + orbit * (eye - target);
+		}
+		let (width, height) = (config.width as f32, config.height as f32);
+		let half_fov = config.fov_degrees.to_radians() / 2.;
+		Self {
+			view: Isometry3::look_at_rh(&eye, &target, &Vector3::y()),
+			focal: height / 2. / half_fov.tan(),
+			center: (width / 2., height / 2.),
+		}
+	}
+
+	/// Where `point` is on the image, in pixels from the top left. `None` if it is
+	/// behind the camera.
+	fn project(&self, point: Point3<f32>) -> Option<(f32, f32)> {
+		let p = self.view * point;
+		// The camera looks down -z.
+		let depth = -p.z;
+		if depth < Z_NEAR {
+			return None;
+		}
+		let x = self.center.0 + self.focal * p.x / depth;
+		let y = self.center.1 - self.focal * p.y / depth;
+		Some((x, y))
+	}
+}
+
+/// Draws a line every meter on the floor. They are drawn in short pieces, so that
+/// the parts in front of the camera show up even when the rest is behind it.
+fn draw_grid(image: &mut Image, camera: &Camera) {
+	const PIECES_PER_METER: i32 = 4;
+	for line in -GRID_EXTENT..=GRID_EXTENT {
+		let line = line as f32;
+		for piece in -GRID_EXTENT * PIECES_PER_METER..GRID_EXTENT * PIECES_PER_METER {
+			let from = piece as f32 / PIECES_PER_METER as f32;
+			let to = from + 1. / PIECES_PER_METER as f32;
+			for (a, b) in [
+				(Point3::new(line, 0., from), Point3::new(line, 0., to)),
+				(Point3::new(from, 0., line), Point3::new(to, 0., line)),
+			] {
+				if let (Some(a), Some(b)) = (camera.project(a), camera.project(b)) {
+					image.line(a, b, GRID);
+				}
+			}
+		}
+	}
+}
+
+fn thick_line(image: &mut Image, from: (f32, f32), to: (f32, f32), color: RGBA) {
+	for dx in -BONE_THICKNESS..=BONE_THICKNESS {
+		for dy in -BONE_THICKNESS..=BONE_THICKNESS {
+			let (dx, dy) = (dx as f32, dy as f32);
+			image.line((from.0 + dx, from.1 + dy), (to.0 + dx, to.1 + dy), color);
+		}
+	}
+}
+
+/// Writes `image` as a binary PPM, which has no alpha.
+fn write_ppm(path: &Path, image: &Image) -> Result<()> {
+	let file =
+		File::create(path).wrap_err_with(|| format!("Failed to create {path:?}"))?;
+	let mut writer = BufWriter::new(file);
+	write!(writer, "P6\n{} {}\n255\n", image.width, image.height)
+		.and_then(|_| {
+			image
+				.pixels
+				.chunks_exact(4)
+				.try_for_each(|pixel| writer.write_all(&pixel[..3]))
+		})
+		.and_then(|_| writer.flush())
+		.wrap_err_with(|| format!("Failed to write {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CSV: &str = "timestamp,bone,x,y,z,qx,qy,qz,qw
+1681234567.000,Chest,0,1.3,0,0,0,0,1
+1681234567.000,Hip,0,0.9,0,0,0,0,1
+1681234567.100,Chest,0,1.4,0,0,0,0,1
+";
+
+	#[test]
+	fn rows_are_grouped_into_frames() {
+		let frames = parse(CSV).unwrap();
+		assert_eq!(frames.len(), 2);
+		assert_eq!(frames[0].bones.len(), 2);
+		assert_eq!(frames[0].bones[1].0, BoneKind::Hip);
+		assert_eq!(frames[1].bones[0].1.translation.vector.y, 1.4);
+	}
+
+	#[test]
+	fn bad_rows_are_errors() {
+		assert!(parse("1681234567.000,Tail,0,0,0,0,0,0,1\n").is_err());
+		assert!(parse("1681234567.000,Chest,0,0,0,0,0,1\n").is_err());
+		let backwards = "2,Chest,0,0,0,0,0,0,1\n1,Chest,0,0,0,0,0,0,1\n";
+		assert!(parse(backwards).is_err());
+	}
+
+	#[test]
+	fn frames_are_held_until_the_next() {
+		let frames = parse(CSV).unwrap();
+		let start = frames[0].timestamp;
+		assert_eq!(frame_at(&frames, 0, start + 0.05), 0);
+		assert_eq!(frame_at(&frames, 0, start + 0.1), 1);
+		assert_eq!(frame_at(&frames, 0, start + 5.), 1);
+	}
+
+	#[test]
+	fn target_is_in_the_center() {
+		let config = ReplayCamera::default();
+		let camera = Camera::new(&config, 0.);
+		let (x, y) = camera.project(Point3::from(config.target)).unwrap();
+		assert!((x - 640.).abs() < 1e-3 && (y - 360.).abs() < 1e-3);
+		let behind = Point3::new(0., 1.2, 5.);
+		assert_eq!(camera.project(behind), None);
+	}
+
+	#[test]
+	fn orbit_comes_back_around() {
+		let config = ReplayCamera {
+			orbit_seconds: 4.,
+			..ReplayCamera::default()
+		};
+		let point = Point3::new(0.3, 1.5, 0.);
+		let start = Camera::new(&config, 0.).project(point).unwrap();
+		let half = Camera::new(&config, 2.).project(point).unwrap();
+		let end = Camera::new(&config, 4.).project(point).unwrap();
+		// Seen from the other side, right is left.
+		assert!((start.0 - 640.) * (half.0 - 640.) < 0.);
+		assert!((start.0 - end.0).abs() < 1e-2 && (start.1 - end.1).abs() < 1e-2);
+	}
+}
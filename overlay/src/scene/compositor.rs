@@ -0,0 +1,442 @@
+//! Hands the frames of `--backend scene` to the SteamVR compositor. Only scene
+//! applications can submit frames, and `ovr_overlay` binds neither them nor the
+//! compositor, so this talks to OpenVR through `openvr_sys` instead.
+//!
+//! That is the same `openvr_api`, so the watchdog still shuts OpenVR down, also
+//! from another thread while a call is stuck. The `openvr` crate on top of it can't
+//! initialize again after that, or after a failed attempt, which the sessions have
+//! to whenever SteamVR restarts.
+//!
+//! The frames are Vulkan images, which the compositor has to be able to copy from.
+//! So the Vulkan instance and device of wgpu are created here, with the extensions
+//! that the compositor asks for on top of those of wgpu, on the GPU of the headset.
+
+use super::{Target, Z_FAR, Z_NEAR};
+use crate::driver_detection;
+use crate::model::Isometry;
+use crate::neck;
+use crate::watchdog::Watchdog;
+
+use ash::vk::{self, Handle};
+use eyre::{eyre, Result, WrapErr};
+use nalgebra::Matrix4;
+use openvr_sys as sys;
+use std::ffi::{c_void, CStr, CString};
+use std::os::raw::c_char;
+use std::{mem, ptr};
+use wgpu_hal::api::Vulkan;
+
+/// The same format as [`super::COLOR_FORMAT`]. Its pixels already have the colors
+/// of the style, so the compositor takes them as gamma encoded.
+const COLOR_FORMAT: vk::Format = vk::Format::R8G8B8A8_UNORM;
+const APP_NAME: &[u8] = b"SlimeVR Overlay\0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eye {
+	Left,
+	Right,
+}
+impl Eye {
+	fn raw(self) -> sys::EVREye {
+		match self {
+			Self::Left => sys::EVREye_Eye_Left,
+			Self::Right => sys::EVREye_Eye_Right,
+		}
+	}
+}
+
+/// What the compositor needs to know about the device with every frame.
+#[derive(Debug, Clone, Copy)]
+struct Handles {
+	instance: vk::Instance,
+	physical_device: vk::PhysicalDevice,
+	device: vk::Device,
+	queue: vk::Queue,
+	queue_family_index: u32,
+}
+
+/// Shuts OpenVR down when dropped, unless the watchdog did already.
+struct Context(Watchdog);
+impl Drop for Context {
+	fn drop(&mut self) {
+		self.0.call("VR_Shutdown", || self.0.shutdown());
+	}
+}
+
+/// The OpenVR context of a scene application. It has to be dropped before the
+/// device that its frames are on.
+///
+/// The function tables are valid until OpenVR shuts down, which is what makes the
+/// calls through them safe.
+pub struct Compositor {
+	system: &'static sys::VR_IVRSystem_FnTable,
+	compositor: &'static sys::VR_IVRCompositor_FnTable,
+	handles: Handles,
+	_context: Context,
+}
+impl Compositor {
+	/// Starts a scene application, and creates the device for wgpu to render its
+	/// frames with. OpenVR is shut down through `watchdog`.
+	pub fn init(watchdog: &Watchdog) -> Result<(Self, wgpu::Device, wgpu::Queue)> {
+		let mut error = sys::EVRInitError_VRInitError_None;
+		// Safety: The context shuts it down again, after which the session doesn't
+		// call into OpenVR anymore.
+		unsafe {
+			sys::VR_InitInternal(
+				&mut error,
+				sys::EVRApplicationType_VRApplication_Scene,
+			)
+		};
+		if error != sys::EVRInitError_VRInitError_None {
+			// Safety: OpenVR has a static description for every error.
+			let description = unsafe {
+				CStr::from_ptr(sys::VR_GetVRInitErrorAsEnglishDescription(error))
+			};
+			return Err(eyre!(
+				"Failed to initialize OpenVR as a scene application: {}",
+				description.to_string_lossy()
+			));
+		}
+		let context = Context(watchdog.clone());
+		// Safety: OpenVR is initialized until the context is dropped, which is after
+		// the tables, since they are its fields.
+		let system: &sys::VR_IVRSystem_FnTable =
+			unsafe { interface(sys::IVRSystem_Version) }
+				.wrap_err("Failed to get IVRSystem")?;
+		let compositor: &sys::VR_IVRCompositor_FnTable =
+			unsafe { interface(sys::IVRCompositor_Version) }
+				.wrap_err("Failed to get IVRCompositor")?;
+		// Like the poses of the skeleton.
+		unsafe {
+			compositor.SetTrackingSpace.unwrap()(
+				sys::ETrackingUniverseOrigin_TrackingUniverseStanding,
+			)
+		};
+		// Safety: The handles stay with the compositor, which doesn't outlive the
+		// device.
+		let (device, queue, handles) = unsafe { create_device(system, compositor)? };
+		let compositor = Self {
+			system,
+			compositor,
+			handles,
+			_context: context,
+		};
+		Ok((compositor, device, queue))
+	}
+
+	/// The size of the frames of each eye that fits the headset.
+	pub fn render_target_size(&self) -> (u32, u32) {
+		let (mut width, mut height) = (0, 0);
+		unsafe {
+			self.system.GetRecommendedRenderTargetSize.unwrap()(&mut width, &mut height)
+		};
+		(width, height)
+	}
+
+	/// Where `eye` is relative to the headset, and its projection to wgpu's clip
+	/// space.
+	pub fn eye(&self, eye: Eye) -> (Isometry, Matrix4<f32>) {
+		let to_head = unsafe { self.system.GetEyeToHeadTransform.unwrap()(eye.raw()) };
+		let (mut left, mut right, mut top, mut bottom) = (0., 0., 0., 0.);
+		unsafe {
+			self.system.GetProjectionRaw.unwrap()(
+				eye.raw(),
+				&mut left,
+				&mut right,
+				&mut top,
+				&mut bottom,
+			)
+		};
+		// Like `GetProjectionMatrix` for Direct3D, which has the depth of wgpu.
+		let (idx, idy) = (1. / (right - left), 1. / (bottom - top));
+		let idz = 1. / (Z_FAR - Z_NEAR);
+		#[rustfmt::skip]
+		let projection = Matrix4::new(
+			2. * idx, 0., (right + left) * idx, 0.,
+			0., 2. * idy, (bottom + top) * idy, 0.,
+			0., 0., -Z_FAR * idz, -Z_FAR * Z_NEAR * idz,
+			0., 0., -1., 0.,
+		);
+		(neck::isometry(&to_head.m), projection)
+	}
+
+	/// Waits until it is time to render the next frame, and returns where the
+	/// headset will be once it shows up, if it is tracked.
+	pub fn wait_get_poses(&self) -> Result<Option<Isometry>> {
+		// Safety: Plain data, for which zeroes are an invalid pose.
+		let invalid: sys::TrackedDevicePose_t = unsafe { mem::zeroed() };
+		let mut poses = [invalid; sys::k_unMaxTrackedDeviceCount as usize];
+		let error = unsafe {
+			self.compositor.WaitGetPoses.unwrap()(
+				poses.as_mut_ptr(),
+				poses.len() as u32,
+				ptr::null_mut(),
+				0,
+			)
+		};
+		if error != sys::EVRCompositorError_VRCompositorError_None {
+			return Err(eyre!("Failed to wait for the next frame, error {error}"));
+		}
+		let hmd = &poses[sys::k_unTrackedDeviceIndex_Hmd as usize];
+		Ok(hmd
+			.bPoseIsValid
+			.then(|| neck::isometry(&hmd.mDeviceToAbsoluteTracking.m)))
+	}
+
+	/// Shows the last draw into `target` to `eye`.
+	pub fn submit(&self, eye: Eye, target: &Target) -> Result<()> {
+		let mut image = vk::Image::null();
+		// Safety: The image is only handed to the compositor, which copies from it.
+		unsafe {
+			target.color.as_hal::<Vulkan, _>(|texture| {
+				if let Some(texture) = texture {
+					image = texture.raw_handle();
+				}
+			});
+		}
+		if image == vk::Image::null() {
+			return Err(eyre!("The frame isn't a Vulkan image"));
+		}
+		let Handles {
+			instance,
+			physical_device,
+			device,
+			queue,
+			queue_family_index,
+		} = self.handles;
+		let mut data = sys::VRVulkanTextureData_t {
+			m_nImage: image.as_raw(),
+			m_pDevice: device.as_raw() as usize as *mut _,
+			m_pPhysicalDevice: physical_device.as_raw() as usize as *mut _,
+			m_pInstance: instance.as_raw() as usize as *mut _,
+			m_pQueue: queue.as_raw() as usize as *mut _,
+			m_nQueueFamilyIndex: queue_family_index,
+			m_nWidth: target.width,
+			m_nHeight: target.height,
+			m_nFormat: COLOR_FORMAT.as_raw() as u32,
+			m_nSampleCount: 1,
+		};
+		let mut texture = sys::Texture_t {
+			handle: &mut data as *mut _ as *mut c_void,
+			eType: sys::ETextureType_TextureType_Vulkan,
+			eColorSpace: sys::EColorSpace_ColorSpace_Gamma,
+		};
+		// Safety: The draw was submitted to the same queue before, and left the image
+		// in the layout that the compositor copies from, see `Target::for_compositor`.
+		let error = unsafe {
+			self.compositor.Submit.unwrap()(
+				eye.raw(),
+				&mut texture,
+				ptr::null_mut(),
+				sys::EVRSubmitFlags_Submit_Default,
+			)
+		};
+		if error != sys::EVRCompositorError_VRCompositorError_None {
+			return Err(eyre!("Failed to submit the frame, error {error}"));
+		}
+		Ok(())
+	}
+
+	/// Whether any tracked device comes from the SlimeVR driver, see
+	/// [`driver_detection`].
+	pub fn has_driver_trackers(&self) -> bool {
+		(0..sys::k_unMaxTrackedDeviceCount).any(|idx| {
+			let mut error = sys::ETrackedPropertyError_TrackedProp_Success;
+			let name = read_string(|buf, len| unsafe {
+				self.system.GetStringTrackedDeviceProperty.unwrap()(
+					idx,
+					sys::ETrackedDeviceProperty_Prop_TrackingSystemName_String,
+					buf,
+					len,
+					&mut error,
+				)
+			});
+			name.map_or(false, |name| {
+				driver_detection::is_driver_tracking_system(&name)
+			})
+		})
+	}
+}
+
+/// The function table of the interface `version`, like the C++ API wraps them.
+///
+/// # Safety
+///
+/// OpenVR has to be initialized, and stay so for as long as the table is used.
+unsafe fn interface<T>(version: &[u8]) -> Result<&'static T> {
+	let mut name = b"FnTable:".to_vec();
+	name.extend_from_slice(version);
+	let mut error = sys::EVRInitError_VRInitError_None;
+	let table = sys::VR_GetGenericInterface(name.as_ptr() as *const c_char, &mut error);
+	if error != sys::EVRInitError_VRInitError_None || table == 0 {
+		let version = String::from_utf8_lossy(version);
+		return Err(eyre!(
+			"SteamVR doesn't have {}",
+			version.trim_end_matches('\0')
+		));
+	}
+	Ok(&*(table as *const T))
+}
+
+/// Reads a string from OpenVR with `read`, which takes a buffer and its size, and
+/// returns the size that the string needs with its nul, or `0` if there is none.
+fn read_string(mut read: impl FnMut(*mut c_char, u32) -> u32) -> Option<CString> {
+	let len = read(ptr::null_mut(), 0);
+	if len == 0 {
+		return None;
+	}
+	let mut buf = vec![0u8; len as usize];
+	if read(buf.as_mut_ptr() as *mut c_char, len) != len {
+		return None;
+	}
+	CString::from_vec_with_nul(buf).ok()
+}
+
+/// Creates a Vulkan instance and a device on the GPU of the headset, with the
+/// extensions of both wgpu and the compositor, and hands them to wgpu.
+///
+/// # Safety
+///
+/// OpenVR has to be initialized, and the returned handles must not be used after
+/// the device is dropped.
+unsafe fn create_device(
+	system: &sys::VR_IVRSystem_FnTable,
+	compositor: &sys::VR_IVRCompositor_FnTable,
+) -> Result<(wgpu::Device, wgpu::Queue, Handles)> {
+	let entry = ash::Entry::load().wrap_err("Failed to load Vulkan")?;
+	let version = entry
+		.try_enumerate_instance_version()
+		.wrap_err("Failed to get the Vulkan version")?
+		.unwrap_or(vk::API_VERSION_1_0);
+	let flags = wgpu_hal::InstanceFlags::empty();
+	let mut extensions =
+		wgpu_hal::vulkan::Instance::required_extensions(&entry, version, flags)
+			.map_err(|e| eyre!("Failed to list the Vulkan extensions: {e}"))?;
+	add_extensions(
+		&mut extensions,
+		read_string(|buf, len| {
+			compositor.GetVulkanInstanceExtensionsRequired.unwrap()(buf, len)
+		}),
+	);
+	let names: Vec<_> = extensions.iter().map(|e| e.as_ptr()).collect();
+	let app_info = vk::ApplicationInfo::builder()
+		.application_name(CStr::from_bytes_with_nul(APP_NAME).unwrap())
+		// Like wgpu, which takes the newest version that it knows about, but Vulkan
+		// 1.0 only takes 1.0.
+		.api_version(if version < vk::API_VERSION_1_1 {
+			vk::API_VERSION_1_0
+		} else {
+			vk::HEADER_VERSION_COMPLETE
+		});
+	let info = vk::InstanceCreateInfo::builder()
+		.application_info(&app_info)
+		.enabled_extension_names(&names);
+	let raw_instance = entry
+		.create_instance(&info, None)
+		.wrap_err("Failed to create the Vulkan instance")?;
+	let instance_handle = raw_instance.handle();
+	let hal_instance = wgpu_hal::vulkan::Instance::from_raw(
+		entry,
+		raw_instance.clone(),
+		version,
+		0,
+		extensions,
+		flags,
+		false,
+		None,
+	)
+	.map_err(|e| eyre!("Failed to hand the Vulkan instance to wgpu: {e}"))?;
+
+	let mut physical_device = 0;
+	system.GetOutputDevice.unwrap()(
+		&mut physical_device,
+		sys::ETextureType_TextureType_Vulkan,
+		instance_handle.as_raw() as usize as *mut _,
+	);
+	if physical_device == 0 {
+		return Err(eyre!("SteamVR didn't say which GPU the headset is on"));
+	}
+	let physical_device = vk::PhysicalDevice::from_raw(physical_device);
+	let adapter = hal_instance
+		.expose_adapter(physical_device)
+		.ok_or_else(|| eyre!("wgpu can't render on the GPU of the headset"))?;
+	let features = wgpu::Features::empty();
+	let mut extensions = adapter.adapter.required_device_extensions(features);
+	add_extensions(
+		&mut extensions,
+		read_string(|buf, len| {
+			compositor.GetVulkanDeviceExtensionsRequired.unwrap()(
+				physical_device.as_raw() as usize as *mut _,
+				buf,
+				len,
+			)
+		}),
+	);
+	let mut phd_features = adapter
+		.adapter
+		.physical_device_features(&extensions, features);
+	// The same one as wgpu picks for its own devices.
+	let queue_family_index = 0;
+	let queue_info = vk::DeviceQueueCreateInfo::builder()
+		.queue_family_index(queue_family_index)
+		.queue_priorities(&[1.])
+		.build();
+	let names: Vec<_> = extensions.iter().map(|e| e.as_ptr()).collect();
+	let info = vk::DeviceCreateInfo::builder()
+		.queue_create_infos(std::slice::from_ref(&queue_info))
+		.enabled_extension_names(&names);
+	let info = phd_features.add_to_device_create_builder(info);
+	let raw_device = raw_instance
+		.create_device(physical_device, &info, None)
+		.wrap_err("Failed to create the Vulkan device")?;
+	let handles = Handles {
+		instance: instance_handle,
+		physical_device,
+		device: raw_device.handle(),
+		queue: raw_device.get_device_queue(queue_family_index, 0),
+		queue_family_index,
+	};
+	let device = adapter
+		.adapter
+		.device_from_raw(
+			raw_device,
+			true,
+			&extensions,
+			features,
+			queue_family_index,
+			0,
+		)
+		.wrap_err("Failed to hand the Vulkan device to wgpu")?;
+
+	let instance = wgpu::Instance::from_hal::<Vulkan>(hal_instance);
+	let adapter = instance.create_adapter_from_hal(adapter);
+	let (device, queue) = adapter
+		.create_device_from_hal(
+			device,
+			&wgpu::DeviceDescriptor {
+				label: Some("skeleton"),
+				features,
+				limits: wgpu::Limits::default(),
+			},
+			None,
+		)
+		.wrap_err("Failed to create graphics device")?;
+	Ok((device, queue, handles))
+}
+
+/// Adds the space separated extensions that the compositor asked for to those of
+/// wgpu, which wants their names for as long as the instance lives. That is once per
+/// session, so they are leaked.
+fn add_extensions(extensions: &mut Vec<&'static CStr>, required: Option<CString>) {
+	let Some(required) = required else {
+		return;
+	};
+	for name in required.as_bytes().split(|&b| b == b' ') {
+		// Can't have a nul, since it comes from a `CString`.
+		let name = CString::new(name).unwrap();
+		if name.as_bytes().is_empty() || extensions.contains(&name.as_c_str()) {
+			continue;
+		}
+		extensions.push(Box::leak(name.into_boxed_c_str()));
+	}
+}
@@ -0,0 +1,434 @@
+//! Renders the skeleton as shaded 3D geometry with wgpu. Unlike the overlays, and
+//! the lines that replays draw otherwise, the bones get their thickness and depth,
+//! and they can be drawn as an avatar instead.
+//!
+//! It renders into a [`Target`], which `--render-replay` reads back, see
+//! [`crate::replay`], and `--backend scene` hands to the SteamVR compositor, see
+//! [`session`].
+
+mod compositor;
+pub mod session;
+
+use crate::model::{Avatar, BoneMap, Mesh};
+use crate::RGBA;
+
+use eyre::{eyre, Result, WrapErr};
+use nalgebra::{Isometry3, Matrix4, Perspective3};
+use std::num::NonZeroU32;
+use std::path::Path;
+use wgpu::util::DeviceExt;
+
+const Z_NEAR: f32 = 0.05;
+const Z_FAR: f32 = 100.;
+const AVATAR_COLOR: RGBA = RGBA::SILVER;
+
+/// Not sRGB, so that the pixels come back with the colors of the style, like the
+/// lines of the replay.
+const COLOR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/// nalgebra's projections map depth to `-1..1` like OpenGL, but wgpu uses `0..1`.
+#[rustfmt::skip]
+const OPENGL_TO_WGPU: Matrix4<f32> = Matrix4::new(
+	1., 0., 0., 0.,
+	0., 1., 0., 0.,
+	0., 0., 0.5, 0.5,
+	0., 0., 0., 1.,
+);
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuVertex {
+	position: [f32; 3],
+	normal: [f32; 3],
+	color: [f32; 4],
+}
+
+/// What gets rendered to, and where it is read back to.
+pub struct Target {
+	color: wgpu::Texture,
+	color_view: wgpu::TextureView,
+	depth_view: wgpu::TextureView,
+	/// Each row of what gets copied out after every draw, padded to what wgpu
+	/// copies at once.
+	readback: wgpu::Buffer,
+	padded_row: u32,
+	copied: wgpu::Extent3d,
+	clear: wgpu::Color,
+	width: u32,
+	height: u32,
+}
+
+pub struct SceneRenderer {
+	device: wgpu::Device,
+	queue: wgpu::Queue,
+	pipeline: wgpu::RenderPipeline,
+	camera: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+	/// Drawn instead of the bones, if set.
+	avatar: Option<Avatar>,
+}
+impl SceneRenderer {
+	/// Renders on the fastest adapter there is.
+	pub async fn new(avatar: Option<Avatar>) -> Result<Self> {
+		let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+		let adapter = instance
+			.request_adapter(&wgpu::RequestAdapterOptions {
+				power_preference: wgpu::PowerPreference::HighPerformance,
+				force_fallback_adapter: false,
+				compatible_surface: None,
+			})
+			.await
+			.ok_or_else(|| eyre!("No suitable graphics adapter"))?;
+		let (device, queue) = adapter
+			.request_device(
+				&wgpu::DeviceDescriptor {
+					label: Some("skeleton"),
+					features: wgpu::Features::empty(),
+					limits: wgpu::Limits::default(),
+				},
+				None,
+			)
+			.await
+			.wrap_err("Failed to create graphics device")?;
+		Ok(Self::with_device(device, queue, avatar))
+	}
+
+	/// Renders with `device`, like the one that the compositor takes frames from.
+	pub fn with_device(
+		device: wgpu::Device,
+		queue: wgpu::Queue,
+		avatar: Option<Avatar>,
+	) -> Self {
+		let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+			label: Some("skeleton shader"),
+			source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+		});
+		let bind_group_layout =
+			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("camera"),
+				entries: &[wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				}],
+			});
+		let pipeline_layout =
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("skeleton"),
+				bind_group_layouts: &[&bind_group_layout],
+				push_constant_ranges: &[],
+			});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("skeleton"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: "vs_main",
+				buffers: &[wgpu::VertexBufferLayout {
+					array_stride: std::mem::size_of::<GpuVertex>() as u64,
+					step_mode: wgpu::VertexStepMode::Vertex,
+					attributes: &wgpu::vertex_attr_array![
+						0 => Float32x3,
+						1 => Float32x3,
+						2 => Float32x4,
+					],
+				}],
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: "fs_main",
+				targets: &[Some(wgpu::ColorTargetState {
+					format: COLOR_FORMAT,
+					blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+			}),
+			primitive: wgpu::PrimitiveState {
+				cull_mode: None,
+				..Default::default()
+			},
+			depth_stencil: Some(wgpu::DepthStencilState {
+				format: DEPTH_FORMAT,
+				depth_write_enabled: true,
+				depth_compare: wgpu::CompareFunction::Less,
+				stencil: Default::default(),
+				bias: Default::default(),
+			}),
+			multisample: Default::default(),
+			multiview: None,
+		});
+
+		let camera = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("camera"),
+			size: std::mem::size_of::<[f32; 16]>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("camera"),
+			layout: &bind_group_layout,
+			entries: &[wgpu::BindGroupEntry {
+				binding: 0,
+				resource: camera.as_entire_binding(),
+			}],
+		});
+
+		Self {
+			device,
+			queue,
+			pipeline,
+			camera,
+			bind_group,
+			avatar,
+		}
+	}
+
+	/// Renders the visible `bones` with their `colors`, or the avatar posed like
+	/// them, as seen with `view_proj`, into `target`.
+	pub fn draw(
+		&mut self,
+		target: &Target,
+		view_proj: &Matrix4<f32>,
+		bones: &BoneMap<slimevr_skeleton::Bone>,
+		colors: &BoneMap<RGBA>,
+	) {
+		let mut vertices = Vec::new();
+		let mut indices = Vec::new();
+		let mut add_mesh = |mesh: &Mesh, color: RGBA| {
+			let color = [color.r, color.g, color.b, color.a].map(|c| c as f32 / 255.);
+			let offset = vertices.len() as u32;
+			vertices.extend(mesh.vertices.iter().map(|v| GpuVertex {
+				position: v.position.coords.into(),
+				normal: v.normal.into(),
+				color,
+			}));
+			indices.extend(mesh.indices.iter().map(|i| i + offset));
+		};
+		match self.avatar.as_ref() {
+			// Hidden along with the bones, since it is skinned to all of them.
+			Some(avatar) => {
+				if bones.into_iter().any(|(_, bone)| bone.is_visible) {
+					add_mesh(&avatar.skin(bones), AVATAR_COLOR);
+				}
+			}
+			None => {
+				for (kind, bone) in bones {
+					if bone.is_visible {
+						add_mesh(&Mesh::capsule(&bone.capsule()), colors[kind]);
+					}
+				}
+			}
+		}
+
+		let vertex_buffer =
+			self.device
+				.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+					label: Some("skeleton vertices"),
+					contents: bytemuck::cast_slice(&vertices),
+					usage: wgpu::BufferUsages::VERTEX,
+				});
+		let index_buffer =
+			self.device
+				.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+					label: Some("skeleton indices"),
+					contents: bytemuck::cast_slice(&indices),
+					usage: wgpu::BufferUsages::INDEX,
+				});
+
+		let mut encoder =
+			self.device
+				.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+					label: Some("skeleton"),
+				});
+		self.queue.write_buffer(
+			&self.camera,
+			0,
+			bytemuck::cast_slice(view_proj.as_slice()),
+		);
+		{
+			let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("skeleton"),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view: &target.color_view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Clear(target.clear),
+						store: true,
+					},
+				})],
+				depth_stencil_attachment: Some(
+					wgpu::RenderPassDepthStencilAttachment {
+						view: &target.depth_view,
+						depth_ops: Some(wgpu::Operations {
+							load: wgpu::LoadOp::Clear(1.),
+							store: false,
+						}),
+						stencil_ops: None,
+					},
+				),
+			});
+			if !indices.is_empty() {
+				pass.set_pipeline(&self.pipeline);
+				pass.set_bind_group(0, &self.bind_group, &[]);
+				pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+				pass.set_index_buffer(
+					index_buffer.slice(..),
+					wgpu::IndexFormat::Uint32,
+				);
+				pass.draw_indexed(0..indices.len() as u32, 0, 0..1);
+			}
+		}
+		encoder.copy_texture_to_buffer(
+			target.color.as_image_copy(),
+			wgpu::ImageCopyBuffer {
+				buffer: &target.readback,
+				layout: wgpu::ImageDataLayout {
+					offset: 0,
+					bytes_per_row: NonZeroU32::new(target.padded_row),
+					rows_per_image: None,
+				},
+			},
+			target.copied,
+		);
+		self.queue.submit(Some(encoder.finish()));
+	}
+
+	/// Waits for the last draw into `target`, and returns its pixels as RGBA, row by
+	/// row. Only for a [`Target::readable`] one.
+	pub fn read_back(&self, target: &Target) -> Result<Vec<u8>> {
+		let slice = target.readback.slice(..);
+		let (sender, receiver) = std::sync::mpsc::channel();
+		slice.map_async(wgpu::MapMode::Read, move |r| {
+			let _ = sender.send(r);
+		});
+		self.device.poll(wgpu::Maintain::Wait);
+		receiver
+			.recv()
+			.wrap_err("The render was dropped")?
+			.wrap_err("Failed to read the render back")?;
+
+		let row = target.width as usize * 4;
+		let mut pixels = Vec::with_capacity(row * target.height as usize);
+		{
+			let mapped = slice.get_mapped_range();
+			for padded in mapped.chunks_exact(target.padded_row as usize) {
+				pixels.extend_from_slice(&padded[..row]);
+			}
+		}
+		target.readback.unmap();
+		Ok(pixels)
+	}
+}
+
+impl Target {
+	/// Renders images of `width` by `height` pixels to read back, with everything
+	/// but the skeleton transparent.
+	pub fn readable(scene: &SceneRenderer, width: u32, height: u32) -> Self {
+		let size = extent(width, height);
+		let usage = wgpu::TextureUsages::empty();
+		Self::new(scene, size, size, wgpu::Color::TRANSPARENT, usage)
+	}
+
+	/// Renders the frames of an eye, on a `background`, for the compositor to copy
+	/// them from, see [`compositor::Compositor::submit`].
+	pub fn for_compositor(
+		scene: &SceneRenderer,
+		width: u32,
+		height: u32,
+		background: RGBA,
+	) -> Self {
+		let [r, g, b, a] = [background.r, background.g, background.b, background.a]
+			.map(|c| f64::from(c) / 255.);
+		// Copying a single pixel out leaves the texture in the layout that the
+		// compositor expects, which wgpu has no other way to ask for.
+		let usage = wgpu::TextureUsages::TEXTURE_BINDING;
+		let clear = wgpu::Color { r, g, b, a };
+		Self::new(scene, extent(width, height), extent(1, 1), clear, usage)
+	}
+
+	fn new(
+		scene: &SceneRenderer,
+		size: wgpu::Extent3d,
+		copied: wgpu::Extent3d,
+		clear: wgpu::Color,
+		usage: wgpu::TextureUsages,
+	) -> Self {
+		let device = &scene.device;
+		let texture = |label, format, usage| {
+			device.create_texture(&wgpu::TextureDescriptor {
+				label: Some(label),
+				size,
+				mip_level_count: 1,
+				sample_count: 1,
+				dimension: wgpu::TextureDimension::D2,
+				format,
+				usage,
+				view_formats: &[],
+			})
+		};
+		let color = texture(
+			"skeleton",
+			COLOR_FORMAT,
+			wgpu::TextureUsages::RENDER_ATTACHMENT
+				| wgpu::TextureUsages::COPY_SRC
+				| usage,
+		);
+		let depth = texture(
+			"depth",
+			DEPTH_FORMAT,
+			wgpu::TextureUsages::RENDER_ATTACHMENT,
+		);
+		let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+		let padded_row = (copied.width * 4 + align - 1) / align * align;
+		let readback = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("readback"),
+			size: u64::from(padded_row) * u64::from(copied.height),
+			usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+			mapped_at_creation: false,
+		});
+		Self {
+			color_view: color.create_view(&Default::default()),
+			depth_view: depth.create_view(&Default::default()),
+			color,
+			readback,
+			padded_row,
+			copied,
+			clear,
+			width: size.width,
+			height: size.height,
+		}
+	}
+}
+
+fn extent(width: u32, height: u32) -> wgpu::Extent3d {
+	wgpu::Extent3d {
+		width,
+		height,
+		depth_or_array_layers: 1,
+	}
+}
+
+/// The avatar of `avatar_file`, or the bundled one if only `avatar` is set.
+pub fn load_avatar(avatar: bool, avatar_file: Option<&Path>) -> Result<Option<Avatar>> {
+	match (avatar, avatar_file) {
+		(_, Some(path)) => {
+			log::info!("Loading avatar {path:?}");
+			Avatar::load(path).map(Some)
+		}
+		(true, None) => Avatar::bundled().map(Some),
+		(false, None) => Ok(None),
+	}
+}
+
+/// The view projection matrix of a camera at `view`, with a vertical field of view
+/// of `fov_y` radians, for images `aspect` times as wide as they are high.
+pub fn view_projection(view: &Isometry3<f32>, fov_y: f32, aspect: f32) -> Matrix4<f32> {
+	let projection = Perspective3::new(aspect, fov_y, Z_NEAR, Z_FAR).to_homogeneous();
+	OPENGL_TO_WGPU * projection * view.to_homogeneous()
+}
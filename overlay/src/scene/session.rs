@@ -0,0 +1,157 @@
+//! The session of `--backend scene`, which runs instead of the overlay session, and
+//! is restarted like it, see `crate::overlay`. It renders the skeleton with depth
+//! from the eyes of the headset, as a scene application, so in place of a game.
+//!
+//! It only draws the bones, or the avatar. The panels, trails, and everything else
+//! that is made of overlays stay with the overlay backend.
+
+use super::compositor::{Compositor, Eye};
+use super::{load_avatar, SceneRenderer, Target};
+use crate::component::Publisher;
+use crate::driver_detection::{DriverDetector, DriverStatus};
+use crate::floor;
+use crate::model::skeleton::default_color;
+use crate::model::{BoneKind, BoneMap, Isometry};
+use crate::timebase::Stamped;
+use crate::watchdog::Watchdog;
+use crate::{OverlayOptions, RGBA};
+
+use eyre::{Result, WrapErr};
+use slimevr_skeleton::Bone;
+use solarxr::settings::DisplaySettings;
+use solarxr::FeedUpdate;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Around the skeleton, like in replays.
+const BACKGROUND: RGBA = RGBA::new(24, 24, 28, 255);
+/// How long to wait before asking for the next frame again, after the compositor
+/// failed to give us one.
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Dropped in the order of the fields, since OpenVR has to shut down before the
+/// device that its frames are on. Unless the watchdog did that already, because it
+/// got stuck.
+struct Headset {
+	compositor: Compositor,
+	eyes: [(Eye, Target); 2],
+	scene: SceneRenderer,
+}
+
+/// Runs the scene application until `stop` changes. Blocks the thread, since every
+/// frame waits for the compositor.
+pub fn run(
+	mut recv: watch::Receiver<Option<Stamped<FeedUpdate>>>,
+	display_settings: watch::Receiver<DisplaySettings>,
+	options: OverlayOptions,
+	tray_visible: watch::Receiver<bool>,
+	driver_status: Arc<watch::Sender<Option<DriverStatus>>>,
+	watchdog: Watchdog,
+	stop: watch::Receiver<()>,
+) -> Result<()> {
+	let OverlayOptions {
+		avatar,
+		avatar_file,
+		bus,
+		timebase,
+		floor_offset,
+		style,
+		..
+	} = options;
+	// Before taking over the headset, in case the avatar is broken.
+	let avatar = load_avatar(avatar, avatar_file.as_deref())?;
+
+	log::info!("Initializing OpenVR as a scene application");
+	let (compositor, device, queue) =
+		watchdog.call("VR_Init", || Compositor::init(&watchdog))?;
+	let scene = SceneRenderer::with_device(device, queue, avatar);
+	let (width, height) = compositor.render_target_size();
+	let eyes = [Eye::Left, Eye::Right].map(|eye| {
+		(
+			eye,
+			Target::for_compositor(&scene, width, height, BACKGROUND),
+		)
+	});
+	let mut headset = Headset {
+		compositor,
+		eyes,
+		scene,
+	};
+
+	let mut publisher = Publisher::new(bus, timebase);
+	let mut driver_detector = DriverDetector::new();
+	// From the last feed update, which stays up until the next one.
+	let mut feed: Vec<(BoneKind, Isometry, f32)> = Vec::new();
+	let mut poses = BoneMap::new([Bone::new(0., 0.); BoneKind::num_types()]);
+	let mut colors = BoneMap::new([RGBA::WHITE; BoneKind::num_types()]);
+
+	log::info!("Scene Loop");
+	while !stop.has_changed().unwrap_or(true) {
+		let changed = watchdog.call("GetStringTrackedDeviceProperty", || {
+			driver_detector.poll_with(|| headset.compositor.has_driver_trackers())
+		});
+		if let Some(status) = changed {
+			status.log();
+			driver_status.send_replace(Some(status));
+		}
+
+		// Also paces the loop to the frames of the headset.
+		let hmd = watchdog.call("WaitGetPoses", || headset.compositor.wait_get_poses());
+		let hmd = match hmd {
+			Ok(Some(hmd)) => hmd,
+			Ok(None) => continue,
+			Err(e) => {
+				log::warn!("{e:?}");
+				std::thread::sleep(RETRY_INTERVAL);
+				continue;
+			}
+		};
+
+		if recv.has_changed().wrap_err("The feed went away")? {
+			let guard = recv.borrow_and_update();
+			if let Some(update) = guard.as_ref() {
+				feed = crate::feed_bones(&update.value);
+				let bones = feed.iter().map(|&(kind, iso, _)| (kind, iso));
+				publisher.frame(update.time, bones);
+			}
+		}
+
+		let style = style.borrow();
+		let hidden: HashSet<BoneKind> = style.hidden().collect();
+		let is_visible = *tray_visible.borrow() && display_settings.borrow().is_visible;
+		let offset = *floor_offset.borrow();
+		for (kind, bone) in &mut poses {
+			bone.is_visible = false;
+			colors[kind] = style.color(kind).unwrap_or_else(|| default_color(kind));
+		}
+		for &(kind, mut iso, length) in &feed {
+			floor::apply(&mut iso, offset);
+			poses[kind] = Bone {
+				iso,
+				length,
+				radius: style.radius,
+				is_visible: is_visible && !hidden.contains(&kind),
+			};
+		}
+		drop(style);
+
+		let Headset {
+			compositor,
+			eyes,
+			scene,
+		} = &mut headset;
+		for (eye, target) in eyes.iter() {
+			let (to_head, projection) = compositor.eye(*eye);
+			let view = (hmd * to_head).inverse();
+			let view_proj = projection * view.to_homogeneous();
+			scene.draw(target, &view_proj, &poses, &colors);
+			let submitted = watchdog.call("Submit", || compositor.submit(*eye, target));
+			if let Err(e) = submitted {
+				log::error!("Error rendering scene: {e:?}");
+			}
+		}
+	}
+	Ok(())
+}
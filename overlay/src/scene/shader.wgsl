@@ -0,0 +1,34 @@
+struct Camera {
+	view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct VertexOutput {
+	@builtin(position) position: vec4<f32>,
+	@location(0) normal: vec3<f32>,
+	@location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(
+	@location(0) position: vec3<f32>,
+	@location(1) normal: vec3<f32>,
+	@location(2) color: vec4<f32>,
+) -> VertexOutput {
+	var out: VertexOutput;
+	out.position = camera.view_proj * vec4<f32>(position, 1.0);
+	out.normal = normal;
+	out.color = color;
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	// Simple directional light from above, so bones have visible depth.
+	let light = normalize(vec3<f32>(0.3, 1.0, 0.5));
+	let diffuse = max(dot(normalize(in.normal), light), 0.0);
+	let shade = 0.3 + 0.7 * diffuse;
+	return vec4<f32>(in.color.rgb * shade, in.color.a);
+}
@@ -0,0 +1,401 @@
+//! Lets users customize the overlay with [Rhai](https://rhai.rs) scripts, without
+//! having to recompile it.
+//!
+//! A script may define any of the following functions, which the overlay will call:
+//! - `on_start(skeleton)`: Called once, after the script is loaded.
+//! - `on_update(skeleton)`: Called after every feed update, right before the
+//!   skeleton gets rendered.
+//! - `on_event(skeleton, event)`: Called when something happens, before the next
+//!   `on_update`. The `event` is a map whose `kind` is one of:
+//!   - `"tracker_connected"` and `"tracker_disconnected"`, with the `device` id and
+//!     the `index` of the tracker on it.
+//!   - `"trackers"`, with the `count` of connected trackers, whenever it changes.
+//!   - `"standby"`, with `active` being whether the headset went into standby.
+//!   - `"recording"`, with `active` being whether recording was started from the
+//!     HTTP API.
+//!
+//! The `skeleton` argument has the following methods. Bones are referred to by
+//! name, like `"FootL"` or `"Chest"`.
+//! - `skeleton.pose(bone)`: Gets the pose of `bone`. The pose has `x`, `y`, `z`,
+//!   `qx`, `qy`, `qz`, `qw`, and `visible` properties.
+//! - `skeleton.set_color(bone, r, g, b, a)`: Changes the color of `bone`.
+//! - `skeleton.set_visible(bone, is_visible)`: Forces `bone` to be shown or
+//!   hidden, regardless of what the server says.
+//! - `skeleton.clear_visible(bone)`: Undoes `set_visible`.
+//! - `skeleton.selected()`: The name of the bone selected with a controller, or `()`
+//!   if there is no selection.
+
+use crate::component;
+use crate::model::skeleton::Skeleton;
+use crate::model::{BoneKind, BoneMap, Isometry, TrackerPose, TrackerState};
+use crate::RGBA;
+
+use eyre::{eyre, Result, WrapErr};
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST, INT};
+use stackvec::TryCollect;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// The pose of a bone, as seen by scripts.
+#[derive(Debug, Clone)]
+struct BonePose {
+	iso: Isometry,
+	is_visible: bool,
+}
+
+/// Something that happened, for the `on_event` hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptEvent {
+	TrackerConnected { device: u8, index: usize },
+	TrackerDisconnected { device: u8, index: usize },
+	Trackers(usize),
+	Standby(bool),
+	Recording(bool),
+}
+impl ScriptEvent {
+	/// The event that scripts see for `event` of the bus, if any.
+	pub fn from_bus(event: &component::Event) -> Option<Self> {
+		match *event {
+			component::Event::Frame(_) => None,
+			component::Event::Standby(is_standby) => Some(Self::Standby(is_standby)),
+			component::Event::Recording(is_on) => Some(Self::Recording(is_on)),
+			component::Event::Trackers(count) => Some(Self::Trackers(count)),
+		}
+	}
+
+	fn to_map(&self) -> Map {
+		let mut map = Map::new();
+		let mut set = |key: &str, value: Dynamic| {
+			map.insert(key.into(), value);
+		};
+		match *self {
+			Self::TrackerConnected { device, index } => {
+				set("kind", "tracker_connected".into());
+				set("device", (device as INT).into());
+				set("index", (index as INT).into());
+			}
+			Self::TrackerDisconnected { device, index } => {
+				set("kind", "tracker_disconnected".into());
+				set("device", (device as INT).into());
+				set("index", (index as INT).into());
+			}
+			Self::Trackers(count) => {
+				set("kind", "trackers".into());
+				set("count", (count as INT).into());
+			}
+			Self::Standby(active) => {
+				set("kind", "standby".into());
+				set("active", active.into());
+			}
+			Self::Recording(active) => {
+				set("kind", "recording".into());
+				set("active", active.into());
+			}
+		}
+		map
+	}
+}
+
+/// Finds the trackers that connected or disconnected since the last call, given
+/// whether each tracker was connected back then in `connected`.
+fn tracker_events(
+	connected: &mut HashMap<(u8, usize), bool>,
+	trackers: &[TrackerPose],
+) -> Vec<ScriptEvent> {
+	let mut events = Vec::new();
+	for tracker in trackers {
+		let (device, index) = tracker.id;
+		let is_connected = tracker.state != TrackerState::Disconnected;
+		let was_connected = connected.insert(tracker.id, is_connected);
+		match (was_connected, is_connected) {
+			(Some(true), true) | (Some(false) | None, false) => (),
+			(Some(false) | None, true) => {
+				events.push(ScriptEvent::TrackerConnected { device, index })
+			}
+			(Some(true), false) => {
+				events.push(ScriptEvent::TrackerDisconnected { device, index })
+			}
+		}
+	}
+	events
+}
+
+/// Changes to the skeleton that were requested by a script.
+#[derive(Debug, Default)]
+struct Requests {
+	colors: Vec<(BoneKind, RGBA)>,
+	visibility: Vec<(BoneKind, Option<bool>)>,
+}
+
+/// The `skeleton` argument that gets passed to scripts.
+///
+/// Scripts can't directly mutate the [`Skeleton`], so instead they get a snapshot
+/// of it, and any changes they make get applied after the script function returns.
+#[derive(Debug, Clone)]
+struct ScriptSkeleton {
+	poses: Arc<BoneMap<BonePose>>,
+	selected: Option<BoneKind>,
+	requests: Arc<Mutex<Requests>>,
+}
+impl ScriptSkeleton {
+	fn new(skeleton: &Skeleton, selected: Option<BoneKind>) -> Self {
+		let poses = skeleton
+			.bones
+			.iter()
+			.map(|(kind, bone)| {
+				let pose = BonePose {
+					iso: *bone.isometry(),
+					is_visible: bone.is_visible(),
+				};
+				(kind, pose)
+			})
+			.try_collect()
+			.expect("all bones should be present");
+		Self {
+			poses: Arc::new(poses),
+			selected,
+			requests: Default::default(),
+		}
+	}
+
+	fn selected(&mut self) -> Dynamic {
+		match self.selected {
+			Some(bone) => format!("{bone:?}").into(),
+			None => Dynamic::UNIT,
+		}
+	}
+
+	fn pose(&mut self, bone: &str) -> ScriptResult<BonePose> {
+		Ok(self.poses[parse_bone(bone)?].clone())
+	}
+
+	fn set_color(
+		&mut self,
+		bone: &str,
+		r: INT,
+		g: INT,
+		b: INT,
+		a: INT,
+	) -> ScriptResult<()> {
+		let bone = parse_bone(bone)?;
+		let channel = |c: INT| {
+			u8::try_from(c).map_err(|_| format!("Color channel out of range: {c}"))
+		};
+		let color = RGBA::new(channel(r)?, channel(g)?, channel(b)?, channel(a)?);
+		self.requests.lock().unwrap().colors.push((bone, color));
+		Ok(())
+	}
+
+	fn set_visible(&mut self, bone: &str, is_visible: bool) -> ScriptResult<()> {
+		let bone = parse_bone(bone)?;
+		self.requests
+			.lock()
+			.unwrap()
+			.visibility
+			.push((bone, Some(is_visible)));
+		Ok(())
+	}
+
+	fn clear_visible(&mut self, bone: &str) -> ScriptResult<()> {
+		let bone = parse_bone(bone)?;
+		self.requests.lock().unwrap().visibility.push((bone, None));
+		Ok(())
+	}
+}
+
+fn parse_bone(name: &str) -> ScriptResult<BoneKind> {
+	name.parse()
+		.map_err(|_| format!("Unknown bone: {name}").into())
+}
+
+fn new_engine() -> Engine {
+	let mut engine = Engine::new();
+	engine
+		.register_type_with_name::<BonePose>("BonePose")
+		.register_get("x", |p: &mut BonePose| p.iso.translation.x as f64)
+		.register_get("y", |p: &mut BonePose| p.iso.translation.y as f64)
+		.register_get("z", |p: &mut BonePose| p.iso.translation.z as f64)
+		.register_get("qx", |p: &mut BonePose| p.iso.rotation.i as f64)
+		.register_get("qy", |p: &mut BonePose| p.iso.rotation.j as f64)
+		.register_get("qz", |p: &mut BonePose| p.iso.rotation.k as f64)
+		.register_get("qw", |p: &mut BonePose| p.iso.rotation.w as f64)
+		.register_get("visible", |p: &mut BonePose| p.is_visible);
+	engine
+		.register_type_with_name::<ScriptSkeleton>("Skeleton")
+		.register_fn("pose", ScriptSkeleton::pose)
+		.register_fn("selected", ScriptSkeleton::selected)
+		.register_fn("set_color", ScriptSkeleton::set_color)
+		.register_fn("set_visible", ScriptSkeleton::set_visible)
+		.register_fn("clear_visible", ScriptSkeleton::clear_visible);
+	engine
+}
+
+/// A loaded user script, along with any state it has accumulated.
+pub struct ScriptHost {
+	engine: Engine,
+	ast: AST,
+	scope: Scope<'static>,
+	visibility: BoneMap<Option<bool>>,
+	/// Whether each tracker was connected in the last feed update.
+	connected: HashMap<(u8, usize), bool>,
+}
+impl ScriptHost {
+	/// Compiles the script at `path` and runs its top level statements.
+	pub fn load(path: &Path) -> Result<Self> {
+		let engine = new_engine();
+		let ast = engine
+			.compile_file(path.into())
+			.map_err(|e| eyre!("{e}"))
+			.wrap_err_with(|| format!("Failed to compile script {path:?}"))?;
+		let mut scope = Scope::new();
+		engine
+			.run_ast_with_scope(&mut scope, &ast)
+			.map_err(|e| eyre!("{e}"))
+			.wrap_err_with(|| format!("Failed to run script {path:?}"))?;
+		Ok(Self {
+			engine,
+			ast,
+			scope,
+			visibility: BoneMap::default(),
+			connected: HashMap::new(),
+		})
+	}
+
+	pub fn on_start(&mut self, skeleton: &mut Skeleton) -> Result<()> {
+		self.call("on_start", skeleton, None, None)
+	}
+
+	pub fn on_event(
+		&mut self,
+		skeleton: &mut Skeleton,
+		selected: Option<BoneKind>,
+		event: &ScriptEvent,
+	) -> Result<()> {
+		self.call("on_event", skeleton, selected, Some(event.to_map()))
+	}
+
+	/// Calls `on_event` for each tracker that connected or disconnected since the
+	/// last feed update.
+	pub fn on_trackers(
+		&mut self,
+		skeleton: &mut Skeleton,
+		selected: Option<BoneKind>,
+		trackers: &[TrackerPose],
+	) -> Result<()> {
+		for event in tracker_events(&mut self.connected, trackers) {
+			self.on_event(skeleton, selected, &event)?;
+		}
+		Ok(())
+	}
+
+	pub fn on_update(
+		&mut self,
+		skeleton: &mut Skeleton,
+		selected: Option<BoneKind>,
+	) -> Result<()> {
+		self.call("on_update", skeleton, selected, None)
+	}
+
+	/// The visibility that the script forced `bone` to have, if any.
+	pub fn visibility_override(&self, bone: BoneKind) -> Option<bool> {
+		self.visibility[bone]
+	}
+
+	fn call(
+		&mut self,
+		name: &str,
+		skeleton: &mut Skeleton,
+		selected: Option<BoneKind>,
+		event: Option<Map>,
+	) -> Result<()> {
+		// Scripts only need to define the hooks they care about.
+		let params = if event.is_some() { 2 } else { 1 };
+		let is_defined = self
+			.ast
+			.iter_functions()
+			.any(|f| f.name == name && f.params.len() == params);
+		if !is_defined {
+			return Ok(());
+		}
+
+		let script_skeleton = ScriptSkeleton::new(skeleton, selected);
+		let (scope, ast) = (&mut self.scope, &self.ast);
+		let arg = script_skeleton.clone();
+		match event {
+			Some(event) => {
+				self.engine
+					.call_fn::<Dynamic>(scope, ast, name, (arg, event))
+			}
+			None => self.engine.call_fn::<Dynamic>(scope, ast, name, (arg,)),
+		}
+		.map_err(|e| eyre!("{e}"))
+		.wrap_err_with(|| format!("Error in script function `{name}`"))?;
+
+		let requests = std::mem::take(&mut *script_skeleton.requests.lock().unwrap());
+		for (bone, color) in requests.colors {
+			skeleton.set_color(bone, color);
+		}
+		for (bone, is_visible) in requests.visibility {
+			self.visibility[bone] = is_visible;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn pose(device: u8, state: TrackerState) -> TrackerPose {
+		TrackerPose {
+			id: (device, 0),
+			iso: Isometry::identity(),
+			state,
+		}
+	}
+
+	#[test]
+	fn reports_trackers_that_connect_and_disconnect() {
+		let mut connected = HashMap::new();
+		let trackers = [
+			pose(1, TrackerState::Ok),
+			pose(2, TrackerState::Disconnected),
+		];
+		let events = tracker_events(&mut connected, &trackers);
+		assert_eq!(
+			events,
+			[ScriptEvent::TrackerConnected {
+				device: 1,
+				index: 0
+			}]
+		);
+		assert!(tracker_events(&mut connected, &trackers).is_empty());
+
+		// A stale tracker is still connected.
+		let trackers = [pose(1, TrackerState::Stale), pose(2, TrackerState::Ok)];
+		let events = tracker_events(&mut connected, &trackers);
+		assert_eq!(
+			events,
+			[ScriptEvent::TrackerConnected {
+				device: 2,
+				index: 0
+			}]
+		);
+
+		let trackers = [
+			pose(1, TrackerState::Disconnected),
+			pose(2, TrackerState::Ok),
+		];
+		let events = tracker_events(&mut connected, &trackers);
+		assert_eq!(
+			events,
+			[ScriptEvent::TrackerDisconnected {
+				device: 1,
+				index: 0
+			}]
+		);
+	}
+}
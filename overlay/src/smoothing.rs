@@ -0,0 +1,192 @@
+//! Exponential smoothing of each bone, which trades responsiveness for less jitter.
+//! Every frame, each bone moves towards where the feed puts it by a fraction that
+//! depends on how long ago the last frame was, so that after one time constant it
+//! has caught up with about two thirds of a jump. Longer time constants are
+//! smoother, but lag behind more.
+//!
+//! Feet and hands jitter the most, and the time constant of each bone can be set on
+//! its own. While tuning them, the preview draws the unsmoothed skeleton on top in
+//! another color, and the tray makes the selected bone smoother or snappier.
+
+use crate::config::Smoothing;
+use crate::model::skeleton::BONE_RADIUS;
+use crate::model::{BoneKind, BoneMap, Isometry};
+
+use nalgebra::Translation3;
+use std::time::{Duration, Instant};
+
+/// Frames further apart than this are a gap, like a bone that just showed up again,
+/// not motion to smooth out, so the bone jumps to where it is instead.
+const MAX_GAP: Duration = Duration::from_millis(250);
+/// What the tray multiplies or divides the time constant of a bone by.
+const STEP: f32 = 1.5;
+/// The bones of the preview are thinner, so that the smoothed ones show around them.
+pub const PREVIEW_BONE_RADIUS: f32 = BONE_RADIUS / 2.;
+/// The time constant in seconds that the tray starts from for a bone that wasn't
+/// smoothed, and below which it stops smoothing it.
+const MIN_TIME_CONSTANT: f32 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmoothingCommand {
+	/// Draws the unsmoothed skeleton too, or stops drawing it.
+	Preview(bool),
+	/// Changes the time constant of the selected bone by one step.
+	Stronger,
+	Weaker,
+}
+
+#[derive(Debug)]
+pub struct Smoother {
+	/// In seconds, `0` for bones that aren't smoothed.
+	time_constants: BoneMap<f32>,
+	/// Where each bone was drawn in the last frame, and when.
+	last: BoneMap<Option<(Instant, Isometry)>>,
+}
+impl Smoother {
+	pub fn new(config: &Smoothing) -> Self {
+		let mut smoother = Self {
+			time_constants: BoneMap::default(),
+			last: BoneMap::default(),
+		};
+		smoother.configure(config);
+		smoother
+	}
+
+	/// Takes the time constants of `config`, like after it was saved.
+	pub fn configure(&mut self, config: &Smoothing) {
+		for kind in BoneKind::iter() {
+			let ms = if config.enabled {
+				config.time_constant_ms(kind)
+			} else {
+				0.
+			};
+			self.time_constants[kind] = ms / 1000.;
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.time_constants.iter().any(|(_, tau)| *tau > 0.)
+	}
+
+	/// Makes `bone` smoother if `stronger`, or snappier. Returns its new time
+	/// constant in milliseconds.
+	pub fn adjust(&mut self, bone: BoneKind, stronger: bool) -> f32 {
+		let tau = &mut self.time_constants[bone];
+		*tau = if stronger {
+			(*tau * STEP).max(MIN_TIME_CONSTANT)
+		} else if *tau / STEP < MIN_TIME_CONSTANT {
+			0.
+		} else {
+			*tau / STEP
+		};
+		*tau * 1000.
+	}
+
+	/// Where to draw `bone` in the frame that is shown at `now`, if the feed puts it
+	/// at `iso`.
+	pub fn update(&mut self, bone: BoneKind, iso: Isometry, now: Instant) -> Isometry {
+		let tau = self.time_constants[bone];
+		let last = &mut self.last[bone];
+		let smoothed = match *last {
+			Some((time, prev)) if tau > 0. => {
+				let dt = now.saturating_duration_since(time);
+				if dt > MAX_GAP {
+					iso
+				} else {
+					between(&prev, &iso, 1. - (-dt.as_secs_f32() / tau).exp())
+				}
+			}
+			_ => iso,
+		};
+		*last = Some((now, smoothed));
+		smoothed
+	}
+}
+
+/// The isometry `t` of the way from `a` to `b`.
+fn between(a: &Isometry, b: &Isometry, t: f32) -> Isometry {
+	let translation = a.translation.vector.lerp(&b.translation.vector, t);
+	// Only fails for half turns, which are a gap more than motion.
+	let rotation = a
+		.rotation
+		.try_slerp(&b.rotation, t, f32::EPSILON)
+		.unwrap_or(b.rotation);
+	Isometry::from_parts(Translation3::from(translation), rotation)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use nalgebra::UnitQuaternion;
+	use std::collections::HashMap;
+
+	fn at_height(y: f32) -> Isometry {
+		Isometry::from_parts(Translation3::new(0., y, 0.), UnitQuaternion::identity())
+	}
+
+	fn smoother(time_constant_ms: f32) -> Smoother {
+		Smoother::new(&Smoothing {
+			enabled: true,
+			time_constant_ms,
+			bones: HashMap::from([("FootL".to_owned(), 0.)]),
+			..Smoothing::default()
+		})
+	}
+
+	/// How high the chest is drawn after jumping up by 1 m, in frames `step` apart
+	/// for `total`.
+	fn after_jump(smoother: &mut Smoother, step: Duration, total: Duration) -> f32 {
+		let start = Instant::now();
+		smoother.update(BoneKind::Chest, at_height(0.), start);
+		let mut iso = at_height(0.);
+		let mut t = Duration::ZERO;
+		while t < total {
+			t += step;
+			iso = smoother.update(BoneKind::Chest, at_height(1.), start + t);
+		}
+		iso.translation.vector.y
+	}
+
+	#[test]
+	fn catches_up_after_a_time_constant() {
+		let mut smoother = smoother(100.);
+		let frame = Duration::from_millis(10);
+		let y = after_jump(&mut smoother, frame, Duration::from_millis(100));
+		assert!((y - (1. - (-1f32).exp())).abs() < 1e-4);
+	}
+
+	#[test]
+	fn independent_of_the_frame_rate() {
+		let total = Duration::from_millis(60);
+		let slow = after_jump(&mut smoother(50.), Duration::from_millis(20), total);
+		let fast = after_jump(&mut smoother(50.), Duration::from_millis(5), total);
+		assert!((slow - fast).abs() < 1e-4);
+	}
+
+	#[test]
+	fn bones_without_smoothing_follow_the_feed() {
+		let mut smoother = smoother(100.);
+		let now = Instant::now();
+		smoother.update(BoneKind::FootL, at_height(0.), now);
+		let iso = smoother.update(BoneKind::FootL, at_height(1.), now);
+		assert_eq!(iso.translation.vector.y, 1.);
+		assert!(!Smoother::new(&Smoothing::default()).is_enabled());
+	}
+
+	#[test]
+	fn gaps_jump() {
+		let mut smoother = smoother(100.);
+		let y = after_jump(&mut smoother, MAX_GAP * 2, MAX_GAP);
+		assert_eq!(y, 1.);
+	}
+
+	#[test]
+	fn tray_steps() {
+		let mut smoother = smoother(0.);
+		assert_eq!(smoother.adjust(BoneKind::Chest, false), 0.);
+		assert!((smoother.adjust(BoneKind::Chest, true) - 10.).abs() < 1e-4);
+		assert!((smoother.adjust(BoneKind::Chest, true) - 15.).abs() < 1e-4);
+		smoother.adjust(BoneKind::Chest, false);
+		assert_eq!(smoother.adjust(BoneKind::Chest, false), 0.);
+	}
+}
@@ -0,0 +1,57 @@
+//! Detects when the headset goes into standby, so that we can stop rendering and
+//! receiving data while nobody is wearing it. Otherwise an overlay that was left
+//! running keeps using CPU and network all night.
+
+use ovr::sys::EDeviceActivityLevel as Activity;
+use ovr::TrackedDeviceIndex;
+use ovr_overlay as ovr;
+use std::time::{Duration, Instant};
+
+/// How often to ask OpenVR whether the headset is in standby.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Keeps track of whether the headset is in standby, and reports when that changes.
+#[derive(Debug)]
+pub struct StandbyDetector {
+	is_standby: bool,
+	last_poll: Option<Instant>,
+}
+impl StandbyDetector {
+	/// Starts out assuming `is_standby`, which is what the last OpenVR context saw if
+	/// the overlay got restarted.
+	pub fn new(is_standby: bool) -> Self {
+		Self {
+			is_standby,
+			last_poll: None,
+		}
+	}
+
+	/// Polls OpenVR at most once every [`POLL_INTERVAL`]. Returns whether the
+	/// headset is now in standby, if that changed.
+	pub fn poll(&mut self, context: &ovr::Context) -> Option<bool> {
+		let now = Instant::now();
+		if self
+			.last_poll
+			.map_or(false, |last| now - last < POLL_INTERVAL)
+		{
+			return None;
+		}
+		self.last_poll = Some(now);
+
+		// SteamVR goes from `Idle` to `Standby` after the delay that the user
+		// configured in its power settings, so we wait for that too.
+		let activity = context
+			.system_mngr()
+			.get_tracked_device_activity_level(TrackedDeviceIndex::HMD);
+		let is_standby = activity == Activity::k_EDeviceActivityLevel_Standby;
+		if is_standby == self.is_standby {
+			return None;
+		}
+		self.is_standby = is_standby;
+		Some(is_standby)
+	}
+
+	pub fn is_standby(&self) -> bool {
+		self.is_standby
+	}
+}
@@ -0,0 +1,205 @@
+//! Puts everything the overlay receives on one clock, the monotonic [`Instant`], no
+//! matter where it came from. The feeds of the server and of `--demo` don't carry
+//! timestamps, so they are stamped when they arrive. Raw samples count microseconds
+//! since the tracker booted instead, which [`SourceClock`] maps onto the overlay's
+//! clock by estimating the offset between the two from when the samples arrive.
+//! That keeps the spacing of the samples, rather than the jitter of the network.
+//!
+//! Wall clock time is only for exports, and comes from [`Timebase::wall_time`], so
+//! that it doesn't jump backwards when the system clock is adjusted.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How far back the smallest offset is looked for. Long enough to see a sample that
+/// wasn't delayed, short enough to follow clocks that drift apart.
+const WINDOW: Duration = Duration::from_secs(10);
+/// An offset that differs this much from the estimate means that the source started
+/// over, like a tracker that rebooted, so the estimate does too.
+const RESYNC_US: i64 = 1_000_000;
+
+/// Something received from a source, with when it happened on the overlay's clock.
+#[derive(Debug, Clone)]
+pub struct Stamped<T> {
+	pub time: Instant,
+	pub value: T,
+}
+impl<T> Stamped<T> {
+	/// Stamps `value` with the current time, for sources without timestamps.
+	pub fn now(value: T) -> Self {
+		Self {
+			time: Instant::now(),
+			value,
+		}
+	}
+}
+
+/// Turns the overlay's clock into wall clock time, from a single reading of the
+/// system clock.
+#[derive(Debug, Clone, Copy)]
+pub struct Timebase {
+	instant: Instant,
+	wall: SystemTime,
+}
+impl Timebase {
+	pub fn new() -> Self {
+		Self {
+			instant: Instant::now(),
+			wall: SystemTime::now(),
+		}
+	}
+
+	/// The wall clock time of `time`.
+	pub fn wall_time(&self, time: Instant) -> SystemTime {
+		self.wall + time.saturating_duration_since(self.instant)
+	}
+}
+
+/// Maps the timestamps of one source onto the overlay's clock.
+///
+/// The offset between the clocks is the smallest difference between arrival and
+/// timestamp within the last [`WINDOW`], since that is the sample that was delayed
+/// the least. The mapped times never go backwards, and never lie after the arrival.
+#[derive(Debug, Default)]
+pub struct SourceClock {
+	/// What microsecond offsets are relative to, the first arrival.
+	epoch: Option<Instant>,
+	/// The last timestamp, as received and as counted since the first one.
+	last_timestamp: Option<(u32, i64)>,
+	/// Arrivals with their offsets, in microseconds. Each offset is larger than the
+	/// ones before it, so the front is the smallest one in the window.
+	offsets: VecDeque<(Instant, i64)>,
+	last: Option<Instant>,
+}
+impl SourceClock {
+	/// Maps a timestamp in microseconds that wraps around, received at `arrival`.
+	pub fn map_wrapping_us(&mut self, timestamp_us: u32, arrival: Instant) -> Instant {
+		// Samples that the network reordered go back a little, which isn't a wrap
+		// around.
+		let unwrapped = match self.last_timestamp {
+			Some((last, unwrapped)) => {
+				unwrapped + timestamp_us.wrapping_sub(last) as i32 as i64
+			}
+			None => timestamp_us as i64,
+		};
+		self.last_timestamp = Some((timestamp_us, unwrapped));
+		self.map_us(unwrapped, arrival)
+	}
+
+	fn map_us(&mut self, timestamp_us: i64, arrival: Instant) -> Instant {
+		let epoch = *self.epoch.get_or_insert(arrival);
+		let offset = micros_between(epoch, arrival) - timestamp_us;
+		if let Some(&(_, estimate)) = self.offsets.front() {
+			if (offset - estimate).abs() > RESYNC_US {
+				log::debug!("Source clock jumped by {}us", offset - estimate);
+				self.offsets.clear();
+			}
+		}
+		while self.offsets.back().map_or(false, |&(_, o)| o >= offset) {
+			self.offsets.pop_back();
+		}
+		self.offsets.push_back((arrival, offset));
+		while self
+			.offsets
+			.front()
+			.map_or(false, |&(t, _)| arrival - t > WINDOW)
+		{
+			self.offsets.pop_front();
+		}
+		// Can't be empty, the one just pushed is still in the window.
+		let estimate = self.offsets[0].1;
+		let mapped = at(epoch, timestamp_us + estimate).min(arrival);
+		let mapped = self.last.map_or(mapped, |last| mapped.max(last));
+		self.last = Some(mapped);
+		mapped
+	}
+}
+
+/// The microseconds from `a` to `b`, negative if `b` is earlier.
+fn micros_between(a: Instant, b: Instant) -> i64 {
+	match b.checked_duration_since(a) {
+		Some(d) => d.as_micros() as i64,
+		None => -((a - b).as_micros() as i64),
+	}
+}
+
+/// `micros` microseconds after `epoch`, or before if negative.
+fn at(epoch: Instant, micros: i64) -> Instant {
+	let d = Duration::from_micros(micros.unsigned_abs());
+	if micros < 0 {
+		epoch.checked_sub(d).unwrap_or(epoch)
+	} else {
+		epoch + d
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MS: Duration = Duration::from_millis(1);
+
+	#[test]
+	fn keeps_the_spacing_of_the_samples() {
+		let start = Instant::now();
+		let mut clock = SourceClock::default();
+		let delays: [u32; 6] = [5, 1, 8, 2, 30, 1];
+		let mapped: Vec<_> = delays
+			.iter()
+			.enumerate()
+			.map(|(i, &delay)| {
+				let sent = i as u32 * 10_000;
+				let arrival = start + Duration::from_micros(sent as u64) + delay * MS;
+				clock.map_wrapping_us(1234 + sent, arrival)
+			})
+			.collect();
+		// From the second sample on, the smallest delay so far is 1ms.
+		for (i, t) in mapped.iter().enumerate().skip(1) {
+			assert_eq!(*t - start, MS + i as u32 * 10 * MS);
+		}
+	}
+
+	#[test]
+	fn continues_across_the_wrap_around() {
+		let start = Instant::now();
+		let mut clock = SourceClock::default();
+		let a = clock.map_wrapping_us(u32::MAX - 999, start);
+		let b = clock.map_wrapping_us(1000, start + 2 * MS);
+		assert_eq!(b - a, 2 * MS);
+	}
+
+	#[test]
+	fn resyncs_when_the_source_starts_over() {
+		let start = Instant::now();
+		let mut clock = SourceClock::default();
+		clock.map_wrapping_us(60_000_000, start);
+		let a = clock.map_wrapping_us(60_010_000, start + 10 * MS);
+		// Rebooted, and booting took a while.
+		let arrival = start + 3000 * MS;
+		let b = clock.map_wrapping_us(0, arrival);
+		let c = clock.map_wrapping_us(10_000, arrival + 10 * MS);
+		assert_eq!(a - start, 10 * MS);
+		assert_eq!(b, arrival);
+		assert_eq!(c - b, 10 * MS);
+	}
+
+	#[test]
+	fn never_goes_backwards() {
+		let start = Instant::now();
+		let mut clock = SourceClock::default();
+		let a = clock.map_wrapping_us(20_000, start);
+		// Reordered by the network.
+		let b = clock.map_wrapping_us(10_000, start + MS);
+		assert!(b >= a);
+		assert!(b <= start + MS);
+	}
+
+	#[test]
+	fn wall_time_follows_the_overlay_clock() {
+		let timebase = Timebase::new();
+		let later = timebase.instant + 1500 * MS;
+		assert_eq!(timebase.wall_time(later), timebase.wall + 1500 * MS);
+		let earlier = timebase.instant.checked_sub(MS).unwrap();
+		assert_eq!(timebase.wall_time(earlier), timebase.wall);
+	}
+}
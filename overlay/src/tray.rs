@@ -0,0 +1,334 @@
+//! A system tray icon with quick controls, so that the overlay can be controlled
+//! without needing a console window. Its menu can be used with the keyboard as
+//! well, which includes moving the selection between bones and the skeleton up and
+//! down, and tuning how much the selected bone is smoothed. It also starts and
+//! stops the CSV export, and switches between the `[themes]` of the config.
+
+use crate::component::{self, Bus};
+use crate::config::SkeletonStyle;
+use crate::floor::FloorCommand;
+use crate::picking::Navigate;
+use crate::smoothing::SmoothingCommand;
+
+use eyre::{Result, WrapErr};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use tokio::sync::{broadcast, mpsc, watch, Notify};
+use tokio_graceful_shutdown::SubsystemHandle;
+use tray_icon::menu::{
+	CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem, Submenu,
+};
+use tray_icon::{Icon, TrayIconBuilder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrayCommand {
+	SetVisible(bool),
+	SetLogVisible(bool),
+	SetRecording(bool),
+	Identify,
+	Navigate(Navigate),
+	Floor(FloorCommand),
+	Smoothing(SmoothingCommand),
+	/// Picks the theme with this index in the sorted names, or goes back to the
+	/// style from before the first pick.
+	Theme(Option<usize>),
+	Quit,
+}
+
+/// What the tray controls.
+pub struct Controls {
+	/// Shared with the HTTP API, the controller binding, and `SIGUSR1`.
+	pub visible: Arc<watch::Sender<bool>>,
+	/// Notified when the user asks to identify the selected bone.
+	pub identify: Arc<Notify>,
+	pub navigate: broadcast::Sender<Navigate>,
+	pub floor: broadcast::Sender<FloorCommand>,
+	pub log_visible: watch::Sender<bool>,
+	pub smoothing: broadcast::Sender<SmoothingCommand>,
+	/// Whether the smoothing preview starts out on.
+	pub preview: bool,
+	/// Starts and stops the CSV export, with [`component::Event::Recording`].
+	pub bus: Bus,
+	/// Whether `--export-csv` records from the start.
+	pub recording: bool,
+	/// Shared with the config reload and the HTTP API.
+	pub style: Arc<watch::Sender<SkeletonStyle>>,
+	pub themes: HashMap<String, SkeletonStyle>,
+}
+
+/// Runs the tray icon until shutdown.
+pub async fn run(subsys: SubsystemHandle, c: Controls) -> Result<()> {
+	let (cmd_sender, mut cmd_receiver) = mpsc::unbounded_channel();
+	let log_shown = *c.log_visible.borrow();
+	let (preview, recording) = (c.preview, c.recording);
+	let mut theme_names: Vec<String> = c.themes.keys().cloned().collect();
+	theme_names.sort();
+	let names = theme_names.clone();
+	thread::Builder::new()
+		.name("tray".to_string())
+		.spawn(move || {
+			let state = MenuState {
+				log_shown,
+				preview,
+				recording,
+			};
+			if let Err(e) = tray_thread(cmd_sender, state, &names) {
+				log::error!("{:?}", e.wrap_err("Tray icon failed"));
+			}
+		})
+		.wrap_err("Failed to spawn tray thread")?;
+
+	// The style from before the first theme was picked.
+	let mut before_theme = None;
+	loop {
+		tokio::select! {
+			_ = subsys.on_shutdown_requested() => {
+				log::debug!("tray shutdown requested");
+				return Ok(());
+			},
+			cmd = cmd_receiver.recv() => match cmd {
+				Some(TrayCommand::SetVisible(is_visible)) => {
+					log::info!("Setting visibility from tray: {is_visible}");
+					c.visible.send_replace(is_visible);
+				}
+				Some(TrayCommand::SetLogVisible(is_visible)) => {
+					log::info!("Setting log console visibility from tray: {is_visible}");
+					c.log_visible.send_replace(is_visible);
+				}
+				Some(TrayCommand::SetRecording(recording)) => {
+					log::info!("Setting recording from tray: {recording}");
+					// Only fails while there is no CSV export, which `main` always
+					// adds.
+					let _ = c.bus.send(component::Event::Recording(recording));
+				}
+				Some(TrayCommand::Identify) => c.identify.notify_one(),
+				// Only fails while there is no overlay session to move it in.
+				Some(TrayCommand::Navigate(n)) => {
+					let _ = c.navigate.send(n);
+				}
+				Some(TrayCommand::Floor(cmd)) => {
+					let _ = c.floor.send(cmd);
+				}
+				Some(TrayCommand::Smoothing(cmd)) => {
+					let _ = c.smoothing.send(cmd);
+				}
+				Some(TrayCommand::Theme(Some(i))) => {
+					let name = &theme_names[i];
+					log::info!("Switching to theme {name:?} from tray");
+					let before = c.style.send_replace(c.themes[name].clone());
+					before_theme.get_or_insert(before);
+				}
+				Some(TrayCommand::Theme(None)) => {
+					if let Some(before) = before_theme.take() {
+						log::info!("Leaving theme from tray");
+						c.style.send_replace(before);
+					}
+				}
+				Some(TrayCommand::Quit) => {
+					log::info!("Quitting from tray");
+					subsys.request_global_shutdown();
+				}
+				// The tray thread died, it already logged why.
+				None => return Ok(()),
+			},
+		}
+	}
+}
+
+/// What the checkboxes of the menu start out as.
+struct MenuState {
+	log_shown: bool,
+	preview: bool,
+	recording: bool,
+}
+
+/// Creates the tray icon, and runs the platform's event loop. Never returns unless
+/// there was an error.
+fn tray_thread(
+	cmd_sender: mpsc::UnboundedSender<TrayCommand>,
+	state: MenuState,
+	theme_names: &[String],
+) -> Result<()> {
+	#[cfg(target_os = "linux")]
+	gtk::init().wrap_err(
+		"Failed to initialize gtk, is `DISPLAY` or `WAYLAND_DISPLAY` set? Sending \
+		 SIGUSR1 still toggles the skeleton without the tray",
+	)?;
+
+	let MenuState {
+		log_shown,
+		preview,
+		recording,
+	} = state;
+	let visible_item = CheckMenuItem::new("Show skeleton", true, true, None);
+	let log_item = CheckMenuItem::new("Show log console", true, log_shown, None);
+	let recording_item = CheckMenuItem::new("Record to CSV", true, recording, None);
+	let identify_item = MenuItem::new("Identify selected bone", true, None);
+	let next_item = MenuItem::new("Select next bone", true, None);
+	let previous_item = MenuItem::new("Select previous bone", true, None);
+	let deselect_item = MenuItem::new("Deselect bone", true, None);
+	let raise_item = MenuItem::new("Raise skeleton", true, None);
+	let lower_item = MenuItem::new("Lower skeleton", true, None);
+	let snap_item = MenuItem::new("Snap skeleton to floor", true, None);
+	let reset_item = MenuItem::new("Reset skeleton height", true, None);
+	let preview_item = CheckMenuItem::new("Preview smoothing", true, preview, None);
+	let stronger_item = MenuItem::new("Smooth selected bone more", true, None);
+	let weaker_item = MenuItem::new("Smooth selected bone less", true, None);
+	let theme_menu = Submenu::new("Theme", !theme_names.is_empty());
+	let config_style_item = MenuItem::new("Style of the config", true, None);
+	let theme_items: Vec<MenuItem> = theme_names
+		.iter()
+		.map(|name| MenuItem::new(name, true, None))
+		.collect();
+	theme_menu
+		.append(&config_style_item)
+		.wrap_err("Failed to build tray menu")?;
+	for item in &theme_items {
+		theme_menu
+			.append(item)
+			.wrap_err("Failed to build tray menu")?;
+	}
+	let quit_item = MenuItem::new("Quit", true, None);
+	let menu = Menu::new();
+	menu.append_items(&[
+		&visible_item,
+		&log_item,
+		&recording_item,
+		&identify_item,
+		&PredefinedMenuItem::separator(),
+		&next_item,
+		&previous_item,
+		&deselect_item,
+		&PredefinedMenuItem::separator(),
+		&raise_item,
+		&lower_item,
+		&snap_item,
+		&reset_item,
+		&PredefinedMenuItem::separator(),
+		&preview_item,
+		&stronger_item,
+		&weaker_item,
+		&PredefinedMenuItem::separator(),
+		&theme_menu,
+		&PredefinedMenuItem::separator(),
+		&quit_item,
+	])
+	.wrap_err("Failed to build tray menu")?;
+
+	let _tray = TrayIconBuilder::new()
+		.with_menu(Box::new(menu))
+		.with_tooltip("SlimeVR Overlay")
+		.with_icon(icon())
+		.build()
+		.wrap_err("Failed to create tray icon")?;
+
+	// Menu items can't be sent across threads, so we track the checkbox state here.
+	let visible_id = visible_item.id().clone();
+	let log_id = log_item.id().clone();
+	let recording_id = recording_item.id().clone();
+	let identify_id = identify_item.id().clone();
+	let navigate_ids = [
+		(next_item.id().clone(), Navigate::Next),
+		(previous_item.id().clone(), Navigate::Previous),
+		(deselect_item.id().clone(), Navigate::Deselect),
+	];
+	let floor_ids = [
+		(raise_item.id().clone(), FloorCommand::Raise),
+		(lower_item.id().clone(), FloorCommand::Lower),
+		(snap_item.id().clone(), FloorCommand::Snap),
+		(reset_item.id().clone(), FloorCommand::Reset),
+	];
+	let preview_id = preview_item.id().clone();
+	let smoothing_ids = [
+		(stronger_item.id().clone(), SmoothingCommand::Stronger),
+		(weaker_item.id().clone(), SmoothingCommand::Weaker),
+	];
+	let config_style_id = config_style_item.id().clone();
+	let theme_ids: Vec<_> = theme_items.iter().map(|i| i.id().clone()).collect();
+	let quit_id = quit_item.id().clone();
+	thread::spawn(move || {
+		let mut is_visible = true;
+		let mut is_log_visible = log_shown;
+		let mut is_recording = recording;
+		let mut is_preview = preview;
+		for event in MenuEvent::receiver() {
+			let cmd = if event.id == visible_id {
+				is_visible = !is_visible;
+				TrayCommand::SetVisible(is_visible)
+			} else if event.id == log_id {
+				is_log_visible = !is_log_visible;
+				TrayCommand::SetLogVisible(is_log_visible)
+			} else if event.id == recording_id {
+				is_recording = !is_recording;
+				TrayCommand::SetRecording(is_recording)
+			} else if event.id == identify_id {
+				TrayCommand::Identify
+			} else if let Some((_, n)) =
+				navigate_ids.iter().find(|(id, _)| *id == event.id)
+			{
+				TrayCommand::Navigate(*n)
+			} else if let Some((_, cmd)) =
+				floor_ids.iter().find(|(id, _)| *id == event.id)
+			{
+				TrayCommand::Floor(*cmd)
+			} else if event.id == preview_id {
+				is_preview = !is_preview;
+				TrayCommand::Smoothing(SmoothingCommand::Preview(is_preview))
+			} else if let Some((_, cmd)) =
+				smoothing_ids.iter().find(|(id, _)| *id == event.id)
+			{
+				TrayCommand::Smoothing(*cmd)
+			} else if event.id == config_style_id {
+				TrayCommand::Theme(None)
+			} else if let Some(i) = theme_ids.iter().position(|id| *id == event.id) {
+				TrayCommand::Theme(Some(i))
+			} else if event.id == quit_id {
+				TrayCommand::Quit
+			} else {
+				continue;
+			};
+			if cmd_sender.send(cmd).is_err() {
+				return;
+			}
+		}
+	});
+
+	run_event_loop();
+	Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn run_event_loop() {
+	gtk::main();
+}
+
+#[cfg(target_os = "windows")]
+fn run_event_loop() {
+	use std::ptr;
+	use winapi::um::winuser::{DispatchMessageW, GetMessageW, TranslateMessage, MSG};
+	let mut msg: MSG = unsafe { std::mem::zeroed() };
+	while unsafe { GetMessageW(&mut msg, ptr::null_mut(), 0, 0) } > 0 {
+		unsafe {
+			TranslateMessage(&msg);
+			DispatchMessageW(&msg);
+		}
+	}
+}
+
+/// Draws a simple round icon, so that we don't need to ship an image file.
+fn icon() -> Icon {
+	const SIZE: u32 = 32;
+	// SlimeVR purple
+	const COLOR: [u8; 3] = [0x65, 0x45, 0x9e];
+	let center = (SIZE as f32 - 1.) / 2.;
+	let rgba = (0..SIZE * SIZE)
+		.flat_map(|i| {
+			let (x, y) = ((i % SIZE) as f32, (i / SIZE) as f32);
+			let dist = ((x - center).powi(2) + (y - center).powi(2)).sqrt();
+			let alpha = if dist <= center { 255 } else { 0 };
+			[COLOR[0], COLOR[1], COLOR[2], alpha]
+		})
+		.collect();
+	Icon::from_rgba(rgba, SIZE, SIZE).expect("icon should have a valid size")
+}
@@ -0,0 +1,124 @@
+//! Counts how often each bone actually changes. The server sends every bone in
+//! every feed update, even when the tracker behind it stopped sending, so a tracker
+//! that keeps dropping out for a moment is easy to miss otherwise.
+//!
+//! Bones that the server infers without a tracker of their own might never change
+//! quickly, so a bone only counts as slow once it was fast before.
+
+use crate::model::skeleton::Skeleton;
+use crate::model::{BoneKind, BoneMap};
+use crate::RGBA;
+
+use nalgebra::UnitQuaternion;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// How far back changes are counted.
+const WINDOW: Duration = Duration::from_secs(1);
+/// What slow bones are tinted with.
+const SLOW_COLOR: RGBA = RGBA::RED;
+
+#[derive(Debug, Default)]
+struct Rate {
+	last: Option<UnitQuaternion<f32>>,
+	/// When the rotation changed, within the last [`WINDOW`].
+	changes: VecDeque<Instant>,
+	/// Whether the bone ever got to the minimum rate.
+	was_fast: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct UpdateRates {
+	bones: BoneMap<Rate>,
+}
+impl UpdateRates {
+	/// Records the rotation of `bone` in a feed update that arrived at `now`.
+	pub fn update(&mut self, bone: BoneKind, rot: UnitQuaternion<f32>, now: Instant) {
+		let rate = &mut self.bones[bone];
+		if rate.last != Some(rot) {
+			rate.changes.push_back(now);
+			rate.last = Some(rot);
+		}
+	}
+
+	/// How many times per second `bone` changed recently.
+	pub fn rate(&self, bone: BoneKind) -> f32 {
+		self.bones[bone].changes.len() as f32 / WINDOW.as_secs_f32()
+	}
+
+	/// The bones that changed at least `min_hz` times per second before, but don't
+	/// anymore.
+	pub fn slow(&mut self, min_hz: f32, now: Instant) -> Vec<BoneKind> {
+		let mut slow = Vec::new();
+		for kind in BoneKind::iter() {
+			let rate = &mut self.bones[kind];
+			while rate.changes.front().map_or(false, |&t| now - t > WINDOW) {
+				rate.changes.pop_front();
+			}
+			let hz = self.rate(kind);
+			let rate = &mut self.bones[kind];
+			if hz >= min_hz {
+				rate.was_fast = true;
+			} else if rate.was_fast {
+				slow.push(kind);
+			}
+		}
+		slow
+	}
+}
+
+/// Tints bones that update too slowly, and logs when they start and stop being
+/// slow.
+#[derive(Debug)]
+pub struct SlowBoneTint {
+	min_hz: f32,
+	/// The colors that the slow bones had before they got tinted.
+	tinted: BoneMap<Option<RGBA>>,
+}
+impl SlowBoneTint {
+	pub fn new(min_hz: f32) -> Self {
+		Self {
+			min_hz,
+			tinted: BoneMap::default(),
+		}
+	}
+
+	/// Changes the color that `kind` goes back to once it is fast again. Returns
+	/// whether it is tinted right now.
+	pub fn set_untinted_color(&mut self, kind: BoneKind, color: RGBA) -> bool {
+		let tinted = &mut self.tinted[kind];
+		if let Some(c) = tinted {
+			*c = color;
+		}
+		tinted.is_some()
+	}
+
+	pub fn update(
+		&mut self,
+		rates: &mut UpdateRates,
+		skeleton: &mut Skeleton,
+		now: Instant,
+	) {
+		let slow = rates.slow(self.min_hz, now);
+		for kind in BoneKind::iter() {
+			let is_slow = slow.contains(&kind);
+			match (is_slow, self.tinted[kind]) {
+				(true, None) => {
+					log::warn!(
+						"{kind:?} is updating at {} Hz, below {} Hz",
+						rates.rate(kind),
+						self.min_hz
+					);
+					self.tinted[kind] = Some(skeleton.bones[kind].color());
+					skeleton.set_color(kind, SLOW_COLOR);
+				}
+				(false, Some(color)) => {
+					log::info!("{kind:?} is updating at {} Hz again", rates.rate(kind));
+					skeleton.set_color(kind, color);
+					self.tinted[kind] = None;
+				}
+				_ => (),
+			}
+		}
+	}
+}
@@ -0,0 +1,142 @@
+//! Lets the people who share this PC and its trackers each have their own `[users]`
+//! of the config, with their theme, their server, and their bone lengths. The
+//! overlay starts as the one from `--user`, or as nobody in particular, and the
+//! HTTP API switches between them with `POST /user/<name>` and back with
+//! `POST /user`, see [`crate::http_api`].
+//!
+//! Switching to someone draws the skeleton with their theme, reconnects to their
+//! server if it is another one, and proportions the skeleton with the bone lengths
+//! that were last sent while they were using it. The proportions themselves are the
+//! server's, so sharing a server means switching them in the SlimeVR app too.
+//!
+//! To tell who put the headset on, the overlay measures how high the headset is
+//! held while standing, as the highest it was held still at for a few seconds. Once
+//! that is close to the `headset_height` of someone who isn't the current user,
+//! it suggests switching to them. Taking the headset off starts over.
+
+use crate::config::User;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How far the headset may move up and down, in meters, while it is held still.
+const STILL: f32 = 0.03;
+/// How long it has to be held still to count.
+const HOLD: Duration = Duration::from_secs(3);
+/// How far the measured height may be from that of a user, in meters, to suggest
+/// them.
+const MATCH: f32 = 0.05;
+
+/// Where to remember the bone lengths of `user`, named `name`. Unless they set
+/// their own, it is `base` with their name before the extension.
+pub fn bone_lengths(base: &Path, name: &str, user: &User) -> PathBuf {
+	if let Some(path) = &user.bone_lengths {
+		return path.clone();
+	}
+	let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+	let file = match base.extension() {
+		Some(ext) => format!("{stem}.{name}.{}", ext.to_string_lossy()),
+		None => format!("{stem}.{name}"),
+	};
+	base.with_file_name(file)
+}
+
+/// The user of `users` whose `headset_height` is closest to `height`, if it is
+/// close enough.
+pub fn suggest(users: &HashMap<String, User>, height: f32) -> Option<&str> {
+	users
+		.iter()
+		.filter_map(|(name, user)| Some((name, (user.headset_height? - height).abs())))
+		.filter(|&(_, off)| off <= MATCH)
+		.min_by(|a, b| a.1.total_cmp(&b.1))
+		.map(|(name, _)| name.as_str())
+}
+
+/// Measures how high the headset is while standing.
+#[derive(Debug, Default)]
+pub struct HeightMeter {
+	/// Since when, and at which height, the headset is held still.
+	still: Option<(Instant, f32)>,
+	height: Option<f32>,
+}
+impl HeightMeter {
+	/// Takes the height of the headset at `now`.
+	pub fn update(&mut self, y: f32, now: Instant) {
+		match self.still {
+			Some((since, at)) if (y - at).abs() <= STILL => {
+				if now - since >= HOLD {
+					self.height = Some(self.height.map_or(at, |h| h.max(at)));
+				}
+			}
+			_ => self.still = Some((now, y)),
+		}
+	}
+
+	/// For the next person, once the headset was taken off.
+	pub fn reset(&mut self) {
+		*self = Self::default();
+	}
+
+	/// The highest the headset was held still at, once it was for long enough.
+	pub fn height(&self) -> Option<f32> {
+		self.height
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn user(headset_height: f32) -> User {
+		User {
+			headset_height: Some(headset_height),
+			..User::default()
+		}
+	}
+
+	#[test]
+	fn bone_lengths_are_per_user() {
+		let base = Path::new("data/bone_lengths.toml");
+		let path = bone_lengths(base, "alex", &User::default());
+		assert_eq!(path, Path::new("data/bone_lengths.alex.toml"));
+		let own = User {
+			bone_lengths: Some(PathBuf::from("alex.toml")),
+			..User::default()
+		};
+		assert_eq!(bone_lengths(base, "alex", &own), Path::new("alex.toml"));
+	}
+
+	#[test]
+	fn suggests_the_closest_user() {
+		let users = HashMap::from([
+			("alex".to_owned(), user(1.62)),
+			("sam".to_owned(), user(1.70)),
+			("kim".to_owned(), User::default()),
+		]);
+		assert_eq!(suggest(&users, 1.64), Some("alex"));
+		assert_eq!(suggest(&users, 1.67), Some("sam"));
+		assert_eq!(suggest(&users, 1.90), None);
+	}
+
+	#[test]
+	fn measures_the_standing_height() {
+		let mut meter = HeightMeter::default();
+		let start = Instant::now();
+		let at = |s: f32| start + Duration::from_secs_f32(s);
+		// Walking around doesn't count, only holding still.
+		meter.update(1.2, at(0.));
+		meter.update(1.6, at(1.));
+		meter.update(1.61, at(3.));
+		assert_eq!(meter.height(), None);
+		meter.update(1.62, at(4.5));
+		assert_eq!(meter.height(), Some(1.6));
+
+		// Sitting down is lower, so it is still the standing height.
+		meter.update(1.1, at(5.));
+		meter.update(1.1, at(9.));
+		assert_eq!(meter.height(), Some(1.6));
+		meter.reset();
+		assert_eq!(meter.height(), None);
+	}
+}
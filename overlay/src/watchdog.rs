@@ -0,0 +1,78 @@
+//! Detects when calls into OpenVR stop returning. When the compositor crashes, the
+//! IPC calls to SteamVR can block forever, which would freeze the whole overlay.
+
+use ovr_overlay as ovr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a single OpenVR call may take before we consider SteamVR hung.
+pub const CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Tracks the OpenVR call that is currently in progress, if any, for one context.
+/// Cloning it gives another handle to the same state, so it can be checked from a
+/// different thread than the one making the calls.
+#[derive(Debug, Clone, Default)]
+pub struct Watchdog {
+	current: Arc<Mutex<Option<(&'static str, Instant)>>>,
+	is_shut_down: Arc<AtomicBool>,
+}
+impl Watchdog {
+	/// Marks the start of a call named `call`, the call ends when the returned guard
+	/// is dropped.
+	pub fn enter(&self, call: &'static str) -> CallGuard<'_> {
+		*self.current.lock().unwrap() = Some((call, Instant::now()));
+		CallGuard(self)
+	}
+
+	/// Runs `f` as a call named `call`.
+	pub fn call<T>(&self, call: &'static str, f: impl FnOnce() -> T) -> T {
+		let _guard = self.enter(call);
+		f()
+	}
+
+	/// The call in progress and how long it has taken, if it took longer than
+	/// `timeout`.
+	pub fn stuck(&self, timeout: Duration) -> Option<(&'static str, Duration)> {
+		let (call, start) = (*self.current.lock().unwrap())?;
+		let elapsed = start.elapsed();
+		(elapsed > timeout).then_some((call, elapsed))
+	}
+
+	/// Shuts the OpenVR context down, unless that happened already. This works from
+	/// another thread than the one that is stuck in a call as well, it closes the
+	/// connection to SteamVR, which should make the call return.
+	pub fn shutdown(&self) {
+		if self.is_shut_down.swap(true, Ordering::AcqRel) {
+			return;
+		}
+		log::info!("Shutting down OpenVR context");
+		// Safety: The session stops once it notices, and doesn't use the context
+		// again.
+		unsafe { ovr::sys::VR_ShutdownInternal() };
+	}
+
+	/// Shuts the OpenVR context down when the returned guard is dropped, so that
+	/// it also happens when the session fails.
+	pub fn shutdown_on_drop(&self) -> ShutdownGuard<'_> {
+		ShutdownGuard(self)
+	}
+}
+
+/// Ends the call it was created for when dropped.
+#[must_use]
+pub struct CallGuard<'a>(&'a Watchdog);
+impl Drop for CallGuard<'_> {
+	fn drop(&mut self) {
+		*self.0.current.lock().unwrap() = None;
+	}
+}
+
+/// Shuts the OpenVR context down when dropped.
+#[must_use]
+pub struct ShutdownGuard<'a>(&'a Watchdog);
+impl Drop for ShutdownGuard<'_> {
+	fn drop(&mut self) {
+		self.0.call("VR_Shutdown", || self.0.shutdown());
+	}
+}
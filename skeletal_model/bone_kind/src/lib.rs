@@ -0,0 +1,407 @@
+//! The kinds of bones in a human skeleton, and how they relate to each other.
+//!
+//! This is the single source of truth for the topology of the skeleton, shared by the
+//! overlay, the skeletal model, and anything else that needs to walk the skeleton.
+//! Keeping it in one place means that they always agree on which bone is attached to
+//! which.
+
+use num_derive::{FromPrimitive, ToPrimitive};
+use num_traits::FromPrimitive;
+#[cfg(feature = "solarxr")]
+use solarxr_protocol::datatypes::BodyPart;
+use std::fmt;
+use std::str::FromStr;
+
+/// Describes the various types of bones in the skeleton.
+///
+/// All of this information is static rather than dynamic information. "Static" here
+/// refers to information that does not change - this information is "hard coded".
+/// This includes:
+/// - The parent/child relationships between the various types of bones
+/// - Which bones are mirror images of each other
+/// - Names of the bones
+///
+/// Where possible, this information is provided as [`const`] functions so that they
+/// can be evaluated at compile-time.
+///
+/// `BoneKind` is also represented as a `u8`, so it can be used as an index for an
+/// array. **Please note that we make no stability guarantees for the particular value
+/// that any variant gets, only that these values are contiguous and start at 0.** Use
+/// the variant directly or refer to the various functions implemented on this type
+/// for stability.
+///
+/// [`const`]: https://doc.rust-lang.org/std/keyword.const.html
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, FromPrimitive, ToPrimitive)]
+pub enum BoneKind {
+	Neck = 0,
+	Chest,
+	Waist,
+	Hip,
+	ThighL,
+	ThighR,
+	AnkleL,
+	AnkleR,
+	FootL,
+	FootR,
+
+	UpperArmL,
+	UpperArmR,
+	ForearmL,
+	ForearmR,
+	WristL,
+	WristR,
+
+	/// From the top of the neck to the top of the head. The skeletal model starts at
+	/// the neck, where the headset is, so this is a leaf that it has no use for. The
+	/// overlay draws it.
+	Head,
+}
+impl BoneKind {
+	/// The bone with the largest integer value
+	pub const fn max() -> BoneKind {
+		BoneKind::Head
+	}
+	pub const MAX: BoneKind = Self::max();
+
+	/// The bone with the smallest integer value
+	pub const fn min() -> BoneKind {
+		BoneKind::root()
+	}
+	pub const MIN: BoneKind = Self::min();
+
+	/// The root bone of the skeletal graph/tree.
+	pub const fn root() -> Self {
+		Self::Neck
+	}
+	pub const ROOT: BoneKind = Self::root();
+
+	/// Returns the number of unique kinds of bones. This is equivalent to the number
+	/// of variants in `BoneKind`
+	pub const fn num_types() -> usize {
+		BoneKind::max() as usize + 1
+	}
+	pub const NUM_TYPES: usize = Self::num_types();
+
+	/// Returns the children of any particular bone.
+	///
+	/// The slice is `'static`, which means the lifetime of the returned slice lives
+	/// for the entire duration of the program. This is because the parent/child
+	/// relationship of bones is known at compile-time.
+	pub const fn children(&self) -> &'static [Self] {
+		use BoneKind::*;
+		match self {
+			Neck => &[Chest, UpperArmL, UpperArmR, Head],
+			Chest => &[Waist],
+			Waist => &[Hip],
+			Hip => &[ThighL, ThighR],
+			ThighL => &[AnkleL],
+			ThighR => &[AnkleR],
+			AnkleL => &[FootL],
+			AnkleR => &[FootR],
+			FootL => &[],
+			FootR => &[],
+
+			UpperArmL => &[ForearmL],
+			UpperArmR => &[ForearmR],
+			ForearmL => &[WristL],
+			ForearmR => &[WristR],
+			WristR => &[],
+			WristL => &[],
+
+			Head => &[],
+		}
+	}
+
+	/// The parent of a bone, or `None` for the [`root`](Self::root).
+	pub const fn parent(&self) -> Option<BoneKind> {
+		use BoneKind::*;
+		Some(match self {
+			Neck => return None,
+			Chest => Neck,
+			Waist => Chest,
+			Hip => Waist,
+			ThighL => Hip,
+			ThighR => Hip,
+			AnkleL => ThighL,
+			AnkleR => ThighR,
+			FootL => AnkleL,
+			FootR => AnkleR,
+
+			UpperArmL => Neck,
+			UpperArmR => Neck,
+			ForearmL => UpperArmL,
+			ForearmR => UpperArmR,
+			WristL => ForearmL,
+			WristR => ForearmR,
+
+			Head => Neck,
+		})
+	}
+
+	/// The same bone on the other side of the body. Bones along the middle of the
+	/// body are their own mirror image.
+	pub const fn mirror(&self) -> Self {
+		use BoneKind::*;
+		match self {
+			Head | Neck | Chest | Waist | Hip => *self,
+			ThighL => ThighR,
+			ThighR => ThighL,
+			AnkleL => AnkleR,
+			AnkleR => AnkleL,
+			FootL => FootR,
+			FootR => FootL,
+
+			UpperArmL => UpperArmR,
+			UpperArmR => UpperArmL,
+			ForearmL => ForearmR,
+			ForearmR => ForearmL,
+			WristL => WristR,
+			WristR => WristL,
+		}
+	}
+
+	/// Which side of the body the bone is on, or `None` if it is along the middle.
+	pub const fn side(&self) -> Option<Side> {
+		use BoneKind::*;
+		match self {
+			Head | Neck | Chest | Waist | Hip => None,
+			ThighL | AnkleL | FootL | UpperArmL | ForearmL | WristL => Some(Side::Left),
+			ThighR | AnkleR | FootR | UpperArmR | ForearmR | WristR => {
+				Some(Side::Right)
+			}
+		}
+	}
+
+	/// A name for the bone to show to users, like `"Left lower leg"`. Use
+	/// [`Debug`](fmt::Debug) or [`FromStr`] for a name that stays the same across
+	/// languages.
+	pub const fn display_name(&self) -> &'static str {
+		use BoneKind::*;
+		match self {
+			Head => "Head",
+			Neck => "Neck",
+			Chest => "Chest",
+			Waist => "Waist",
+			Hip => "Hip",
+			ThighL => "Left thigh",
+			ThighR => "Right thigh",
+			AnkleL => "Left lower leg",
+			AnkleR => "Right lower leg",
+			FootL => "Left foot",
+			FootR => "Right foot",
+
+			UpperArmL => "Left upper arm",
+			UpperArmR => "Right upper arm",
+			ForearmL => "Left forearm",
+			ForearmR => "Right forearm",
+			WristL => "Left hand",
+			WristR => "Right hand",
+		}
+	}
+
+	/// Iterates over all the bones, in order of their integer value. This means the
+	/// [`root`](Self::root) comes first.
+	pub fn iter() -> std::iter::Map<std::ops::RangeInclusive<u8>, fn(u8) -> BoneKind> {
+		(Self::MIN as u8..=Self::MAX as u8).map(|x| x.try_into().unwrap())
+	}
+
+	/// Iterates over the parent of the bone, its parent, and so on up to the
+	/// [`root`](Self::root). Doesn't include the bone itself.
+	pub fn ancestors(&self) -> impl Iterator<Item = BoneKind> {
+		std::iter::successors(self.parent(), BoneKind::parent)
+	}
+
+	/// How many bones are between this one and the [`root`](Self::root), which has a
+	/// depth of 0.
+	pub fn depth(&self) -> usize {
+		self.ancestors().count()
+	}
+}
+impl TryFrom<u8> for BoneKind {
+	type Error = ();
+
+	fn try_from(value: u8) -> Result<Self, Self::Error> {
+		FromPrimitive::from_u8(value).ok_or(())
+	}
+}
+impl TryFrom<usize> for BoneKind {
+	type Error = ();
+	fn try_from(value: usize) -> Result<Self, Self::Error> {
+		FromPrimitive::from_usize(value).ok_or(())
+	}
+}
+impl fmt::Display for BoneKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(self.display_name())
+	}
+}
+/// Parses the name of the variant, such as `"FootL"`.
+impl FromStr for BoneKind {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Self::iter().find(|kind| format!("{kind:?}") == s).ok_or(())
+	}
+}
+impl From<BoneKind> for u8 {
+	fn from(other: BoneKind) -> Self {
+		other as _
+	}
+}
+impl From<BoneKind> for usize {
+	fn from(other: BoneKind) -> Self {
+		other as _
+	}
+}
+#[cfg(feature = "solarxr")]
+impl TryFrom<BodyPart> for BoneKind {
+	type Error = BodyPart;
+	fn try_from(other: BodyPart) -> Result<Self, Self::Error> {
+		use BodyPart as O;
+		Ok(match other {
+			O::NONE | O::LEFT_CONTROLLER | O::RIGHT_CONTROLLER => return Err(other),
+
+			O::NECK => Self::Neck,
+			O::CHEST => Self::Chest,
+			O::WAIST => Self::Waist,
+			O::HIP => Self::Hip,
+			O::LEFT_UPPER_LEG => Self::ThighL,
+			O::RIGHT_UPPER_LEG => Self::ThighR,
+			O::LEFT_LOWER_LEG => Self::AnkleL,
+			O::RIGHT_LOWER_LEG => Self::AnkleR,
+			O::LEFT_FOOT => Self::FootL,
+			O::RIGHT_FOOT => Self::FootR,
+
+			O::LEFT_UPPER_ARM => Self::UpperArmL,
+			O::RIGHT_UPPER_ARM => Self::UpperArmR,
+			O::LEFT_LOWER_ARM => Self::ForearmL,
+			O::RIGHT_LOWER_ARM => Self::ForearmR,
+			O::LEFT_HAND => Self::WristL,
+			O::RIGHT_HAND => Self::WristR,
+
+			O(_) => return Err(other),
+		})
+	}
+}
+
+/// A side of the body, from the perspective of the person whose body it is.
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
+pub enum Side {
+	Left,
+	Right,
+}
+impl Side {
+	pub const fn opposite(self) -> Self {
+		match self {
+			Self::Left => Self::Right,
+			Self::Right => Self::Left,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashSet;
+
+	#[test]
+	fn iter_is_contiguous() {
+		let all: Vec<_> = BoneKind::iter().collect();
+		assert_eq!(all.len(), BoneKind::NUM_TYPES);
+		for (i, kind) in all.iter().enumerate() {
+			assert_eq!(usize::from(*kind), i);
+			assert_eq!(BoneKind::try_from(i), Ok(*kind));
+		}
+		assert_eq!(all[0], BoneKind::ROOT);
+		assert_eq!(BoneKind::try_from(BoneKind::NUM_TYPES), Err(()));
+	}
+
+	#[test]
+	fn the_neck_is_the_root() {
+		assert_eq!(BoneKind::ROOT, BoneKind::Neck);
+		assert_eq!(BoneKind::Head.parent(), Some(BoneKind::Neck));
+		assert!(BoneKind::Head.children().is_empty());
+	}
+
+	#[test]
+	fn only_the_root_has_no_parent() {
+		for kind in BoneKind::iter() {
+			assert_eq!(kind.parent().is_none(), kind == BoneKind::ROOT, "{kind:?}");
+		}
+	}
+
+	#[test]
+	fn children_and_parents_agree() {
+		for kind in BoneKind::iter() {
+			for child in kind.children() {
+				assert_eq!(child.parent(), Some(kind), "{child:?}");
+			}
+			if let Some(parent) = kind.parent() {
+				assert!(parent.children().contains(&kind), "{kind:?}");
+			}
+		}
+	}
+
+	#[test]
+	fn every_bone_is_reachable_from_the_root() {
+		let mut seen = HashSet::new();
+		let mut stack = vec![BoneKind::ROOT];
+		while let Some(kind) = stack.pop() {
+			assert!(seen.insert(kind), "{kind:?} was reached twice");
+			stack.extend(kind.children());
+		}
+		assert_eq!(seen.len(), BoneKind::NUM_TYPES);
+	}
+
+	#[test]
+	fn ancestors_end_at_the_root() {
+		for kind in BoneKind::iter() {
+			let ancestors: Vec<_> = kind.ancestors().collect();
+			assert_eq!(ancestors.len(), kind.depth());
+			assert!(ancestors.len() < BoneKind::NUM_TYPES);
+			if kind != BoneKind::ROOT {
+				assert_eq!(ancestors.last(), Some(&BoneKind::ROOT), "{kind:?}");
+				assert_eq!(ancestors.first().copied(), kind.parent());
+			}
+		}
+	}
+
+	#[test]
+	fn mirror_is_symmetric() {
+		for kind in BoneKind::iter() {
+			let mirrored = kind.mirror();
+			assert_eq!(mirrored.mirror(), kind, "{kind:?}");
+			assert_eq!(mirrored.side(), kind.side().map(Side::opposite), "{kind:?}");
+			assert_eq!(mirrored == kind, kind.side().is_none(), "{kind:?}");
+			assert_eq!(mirrored.parent(), kind.parent().map(|p| p.mirror()));
+			assert_eq!(mirrored.depth(), kind.depth());
+		}
+	}
+
+	#[test]
+	fn children_are_on_the_same_side() {
+		for kind in BoneKind::iter() {
+			let Some(side) = kind.side() else {
+				continue;
+			};
+			for child in kind.children() {
+				assert_eq!(child.side(), Some(side), "{child:?}");
+			}
+		}
+	}
+
+	#[test]
+	fn names_are_unique() {
+		let names: HashSet<_> = BoneKind::iter().map(|k| k.display_name()).collect();
+		assert_eq!(names.len(), BoneKind::NUM_TYPES);
+	}
+
+	#[test]
+	fn parses_debug_names() {
+		for kind in BoneKind::iter() {
+			assert_eq!(format!("{kind:?}").parse(), Ok(kind));
+		}
+		assert_eq!("Left foot".parse::<BoneKind>(), Err(()));
+	}
+}
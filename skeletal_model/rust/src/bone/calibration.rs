@@ -0,0 +1,28 @@
+//! The pose of the bones when performing a calibration. This depends on the
+//! [`conventions`](crate::conventions) of this crate, which is why it lives here
+//! rather than with the rest of the static information about [`BoneKind`].
+
+use super::BoneKind;
+use crate::{
+	conventions::{forward_vec, up_vec},
+	newtypes::{Global, Local},
+	UnitQuat,
+};
+
+/// Returns the initial calibration pose of the bone. Rotating the up vector by
+/// this rotation would cause it to point in the same target direction as the bone.
+pub fn calibration_rotation(kind: BoneKind) -> Global<UnitQuat> {
+	use BoneKind::*;
+	Global(match kind {
+		FootL | FootR => UnitQuat::look_at_rh(&-up_vec(), &forward_vec()),
+		_ => UnitQuat::default(),
+	})
+}
+
+/// Returns the initial calibration pose of the bone, as a rotation relative to the
+/// parent bone. See also: [`calibration_rotation`]
+pub fn calibration_rotation_local(kind: BoneKind) -> Local<UnitQuat> {
+	let child_rot_g = calibration_rotation(kind);
+	let parent_rot_g = calibration_rotation(kind.parent().unwrap_or(kind));
+	Local(parent_rot_g.0.rotation_to(&child_rot_g.0))
+}
@@ -3,9 +3,11 @@
 //! For more info about skeletal bones and the role they play in the skeleton,
 //! see the module-level documentation on [`crate::skeleton`].
 
-mod bone_kind;
 pub mod bone_map;
+mod calibration;
 
-pub use self::bone_kind::BoneKind;
 #[doc(inline)]
 pub use self::bone_map::BoneMap;
+pub use self::calibration::{calibration_rotation, calibration_rotation_local};
+#[doc(inline)]
+pub use bone_kind::BoneKind;
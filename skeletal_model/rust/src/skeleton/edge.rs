@@ -1,6 +1,7 @@
 use derive_more::From;
 
 use crate::{
+	bone::calibration_rotation_local,
 	newtypes::{Global, Local},
 	BoneKind, UnitQuat,
 };
@@ -50,7 +51,7 @@ impl Edge {
 	pub fn new(kind: impl Into<EdgeKind>, length: f32) -> Self {
 		let kind = kind.into();
 		let calib_rot_l = match kind {
-			EdgeKind::Bone(k) => k.calibration_rotation_local(),
+			EdgeKind::Bone(k) => calibration_rotation_local(k),
 			_ => UnitQuat::identity().into(),
 		};
 		Self {
@@ -146,9 +146,9 @@ impl Skeleton {
 			let edge = g.update_edge(
 				parent,
 				child,
-				Edge::new(BoneKind::Neck, config.bone_lengths[BoneKind::Neck]),
+				Edge::new(BoneKind::ROOT, config.bone_lengths[BoneKind::ROOT]),
 			);
-			bone_map[BoneKind::Neck] = Some(edge);
+			bone_map[BoneKind::ROOT] = Some(edge);
 		}
 
 		// This closure adds all the immediate children of `parent_bone` to the graph
@@ -173,7 +173,7 @@ impl Skeleton {
 		};
 
 		// Call `add_child_bones` in a depth-first traversal to build the actual graph.
-		let mut bone_stack = vec![BoneKind::Neck];
+		let mut bone_stack = vec![BoneKind::ROOT];
 		while !bone_stack.is_empty() {
 			let parent_bone = bone_stack.pop().unwrap();
 			add_child_bones(parent_bone);
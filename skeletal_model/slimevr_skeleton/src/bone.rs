@@ -0,0 +1,44 @@
+use crate::{Capsule, Isometry};
+
+use nalgebra::Point3;
+
+/// Where a bone is, and how it is shaped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bone {
+	pub iso: Isometry,
+	/// In meters, from the head of the bone along its negative y axis.
+	pub length: f32,
+	/// In meters.
+	pub radius: f32,
+	pub is_visible: bool,
+}
+impl Bone {
+	/// A bone at the origin, with nothing to show yet.
+	pub fn new(length: f32, radius: f32) -> Self {
+		Self {
+			iso: Isometry::identity(),
+			length,
+			radius,
+			is_visible: false,
+		}
+	}
+
+	/// Where the bone starts.
+	pub fn head(&self) -> Point3<f32> {
+		Point3::from(self.iso.translation.vector)
+	}
+
+	/// Where the bone ends.
+	pub fn tail(&self) -> Point3<f32> {
+		self.iso * Point3::new(0., -self.length, 0.)
+	}
+
+	pub fn circumference(&self) -> f32 {
+		2. * std::f32::consts::PI * self.radius
+	}
+
+	/// The shape of the bone.
+	pub fn capsule(&self) -> Capsule {
+		Capsule::new(self.head(), self.tail(), self.radius)
+	}
+}
@@ -0,0 +1,260 @@
+use nalgebra::{Point3, Unit, Vector3};
+
+/// A line segment from `a` to `b`, padded by `radius` in every direction. This is the
+/// shape of a [`Bone`](crate::Bone).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capsule {
+	pub a: Point3<f32>,
+	pub b: Point3<f32>,
+	pub radius: f32,
+}
+impl Capsule {
+	pub fn new(a: Point3<f32>, b: Point3<f32>, radius: f32) -> Self {
+		Self { a, b, radius }
+	}
+
+	/// Casts a ray from `origin` along `dir`. Returns the distance along the ray to
+	/// the first point where it hits the capsule, if it hits at all.
+	pub fn raycast(
+		&self,
+		origin: &Point3<f32>,
+		dir: &Unit<Vector3<f32>>,
+	) -> Option<f32> {
+		// The capsule is the union of a cylinder and two spheres, so we take the
+		// closest hit among them.
+		let cylinder = self.raycast_cylinder(origin, dir);
+		let sphere_a = raycast_sphere(&self.a, self.radius, origin, dir);
+		let sphere_b = raycast_sphere(&self.b, self.radius, origin, dir);
+		[cylinder, sphere_a, sphere_b]
+			.into_iter()
+			.flatten()
+			.min_by(|x, y| x.total_cmp(y))
+	}
+
+	fn raycast_cylinder(
+		&self,
+		origin: &Point3<f32>,
+		dir: &Unit<Vector3<f32>>,
+	) -> Option<f32> {
+		let axis = self.b - self.a;
+		let axis_len_sq = axis.norm_squared();
+		if axis_len_sq == 0. {
+			return None;
+		}
+		let to_origin = origin - self.a;
+
+		// Project the ray and the origin onto the plane perpendicular to the axis,
+		// turning it into a 2d ray vs circle problem.
+		let d = dir.into_inner() - axis * (dir.dot(&axis) / axis_len_sq);
+		let o = to_origin - axis * (to_origin.dot(&axis) / axis_len_sq);
+
+		let a = d.norm_squared();
+		if a == 0. {
+			// Ray is parallel to the axis, so only the spheres can be hit.
+			return None;
+		}
+		let b = 2. * o.dot(&d);
+		let c = o.norm_squared() - self.radius * self.radius;
+		let t = smallest_non_negative_root(a, b, c)?;
+
+		// Check that the hit is between the two ends of the cylinder.
+		let hit = origin + dir.into_inner() * t;
+		let along_axis = (hit - self.a).dot(&axis) / axis_len_sq;
+		(0. ..=1.).contains(&along_axis).then_some(t)
+	}
+
+	/// The point on the segment from `a` to `b` that is closest to `p`.
+	pub fn closest_point_on_axis(&self, p: &Point3<f32>) -> Point3<f32> {
+		let axis = self.b - self.a;
+		let axis_len_sq = axis.norm_squared();
+		if axis_len_sq == 0. {
+			return self.a;
+		}
+		let t = ((p - self.a).dot(&axis) / axis_len_sq).clamp(0., 1.);
+		self.a + axis * t
+	}
+
+	/// The point on the surface of the capsule that is closest to `p`.
+	pub fn closest_point(&self, p: &Point3<f32>) -> Point3<f32> {
+		let on_axis = self.closest_point_on_axis(p);
+		let to_p = p - on_axis;
+		let dist = to_p.norm();
+		if dist == 0. {
+			// `p` is on the axis, so every direction is equally close. Pick one that
+			// is perpendicular to the axis.
+			let axis = self.b - self.a;
+			let perpendicular = axis
+				.cross(&Vector3::x())
+				.try_normalize(f32::EPSILON)
+				.or_else(|| axis.cross(&Vector3::y()).try_normalize(f32::EPSILON))
+				.unwrap_or_else(Vector3::x);
+			return on_axis + perpendicular * self.radius;
+		}
+		on_axis + to_p * (self.radius / dist)
+	}
+
+	/// The distance from `p` to the surface of the capsule. Negative if `p` is
+	/// inside of it.
+	pub fn signed_distance(&self, p: &Point3<f32>) -> f32 {
+		(p - self.closest_point_on_axis(p)).norm() - self.radius
+	}
+
+	pub fn contains(&self, p: &Point3<f32>) -> bool {
+		self.signed_distance(p) <= 0.
+	}
+
+	/// Whether this capsule overlaps with `other`.
+	pub fn intersects(&self, other: &Capsule) -> bool {
+		let (p, q) =
+			closest_points_between_segments(&self.a, &self.b, &other.a, &other.b);
+		(p - q).norm() <= self.radius + other.radius
+	}
+}
+
+/// Finds the closest pair of points on the segments `p1`-`q1` and `p2`-`q2`.
+///
+/// See "Real-Time Collision Detection" by Christer Ericson, section 5.1.9.
+fn closest_points_between_segments(
+	p1: &Point3<f32>,
+	q1: &Point3<f32>,
+	p2: &Point3<f32>,
+	q2: &Point3<f32>,
+) -> (Point3<f32>, Point3<f32>) {
+	let d1 = q1 - p1;
+	let d2 = q2 - p2;
+	let r = p1 - p2;
+	let a = d1.norm_squared();
+	let e = d2.norm_squared();
+	let f = d2.dot(&r);
+
+	let (s, t) = if a == 0. && e == 0. {
+		// Both segments are points.
+		(0., 0.)
+	} else if a == 0. {
+		(0., (f / e).clamp(0., 1.))
+	} else {
+		let c = d1.dot(&r);
+		if e == 0. {
+			((-c / a).clamp(0., 1.), 0.)
+		} else {
+			let b = d1.dot(&d2);
+			let denom = a * e - b * b;
+			// If the segments are parallel, any `s` works, so we use 0.
+			let s = if denom != 0. {
+				((b * f - c * e) / denom).clamp(0., 1.)
+			} else {
+				0.
+			};
+			let t = (b * s + f) / e;
+			if t < 0. {
+				((-c / a).clamp(0., 1.), 0.)
+			} else if t > 1. {
+				(((b - c) / a).clamp(0., 1.), 1.)
+			} else {
+				(s, t)
+			}
+		}
+	};
+	(p1 + d1 * s, p2 + d2 * t)
+}
+
+fn raycast_sphere(
+	center: &Point3<f32>,
+	radius: f32,
+	origin: &Point3<f32>,
+	dir: &Unit<Vector3<f32>>,
+) -> Option<f32> {
+	let o = origin - center;
+	let b = 2. * o.dot(dir);
+	let c = o.norm_squared() - radius * radius;
+	smallest_non_negative_root(1., b, c)
+}
+
+/// Solves `a*t^2 + b*t + c = 0`, returning the smallest `t >= 0`.
+fn smallest_non_negative_root(a: f32, b: f32, c: f32) -> Option<f32> {
+	let discriminant = b * b - 4. * a * c;
+	if discriminant < 0. {
+		return None;
+	}
+	let sqrt = discriminant.sqrt();
+	let t0 = (-b - sqrt) / (2. * a);
+	let t1 = (-b + sqrt) / (2. * a);
+	[t0, t1].into_iter().find(|t| *t >= 0.)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A vertical capsule from the origin up to `y = 1`, with a radius of `0.1`.
+	fn vertical() -> Capsule {
+		Capsule::new(Point3::origin(), Point3::new(0., 1., 0.), 0.1)
+	}
+
+	fn assert_close(a: f32, b: f32) {
+		assert!((a - b).abs() < 1e-5, "{a} != {b}");
+	}
+
+	#[test]
+	fn raycast_hits_cylinder() {
+		let origin = Point3::new(-1., 0.5, 0.);
+		let t = vertical().raycast(&origin, &Vector3::x_axis()).unwrap();
+		assert_close(t, 0.9);
+	}
+
+	#[test]
+	fn raycast_hits_end_cap() {
+		let origin = Point3::new(0., 2., 0.);
+		let t = vertical().raycast(&origin, &-Vector3::y_axis()).unwrap();
+		assert_close(t, 0.9);
+	}
+
+	#[test]
+	fn raycast_misses() {
+		let origin = Point3::new(-1., 0.5, 0.);
+		assert_eq!(vertical().raycast(&origin, &Vector3::z_axis()), None);
+		// Pointing away from the capsule
+		assert_eq!(vertical().raycast(&origin, &-Vector3::x_axis()), None);
+	}
+
+	#[test]
+	fn closest_point_on_axis() {
+		let c = vertical();
+		let p = c.closest_point_on_axis(&Point3::new(3., 0.5, 0.));
+		assert_eq!(p, Point3::new(0., 0.5, 0.));
+		// Past the ends gets clamped
+		let p = c.closest_point_on_axis(&Point3::new(0., -2., 0.));
+		assert_eq!(p, Point3::origin());
+	}
+
+	#[test]
+	fn closest_point() {
+		let p = vertical().closest_point(&Point3::new(0., 0.5, 2.));
+		assert_close((p - Point3::new(0., 0.5, 0.1)).norm(), 0.);
+		let p = vertical().closest_point(&Point3::new(0., 3., 0.));
+		assert_close((p - Point3::new(0., 1.1, 0.)).norm(), 0.);
+	}
+
+	#[test]
+	fn signed_distance() {
+		let c = vertical();
+		assert_close(c.signed_distance(&Point3::new(1., 0.5, 0.)), 0.9);
+		assert_close(c.signed_distance(&Point3::new(0., 0.5, 0.)), -0.1);
+		assert!(c.contains(&Point3::new(0.05, 1.05, 0.)));
+		assert!(!c.contains(&Point3::new(0.2, 0.5, 0.)));
+	}
+
+	#[test]
+	fn intersects() {
+		let c = vertical();
+		let crossing =
+			Capsule::new(Point3::new(-1., 0.5, 0.15), Point3::new(1., 0.5, 0.15), 0.1);
+		assert!(c.intersects(&crossing));
+		let far =
+			Capsule::new(Point3::new(-1., 0.5, 0.5), Point3::new(1., 0.5, 0.5), 0.1);
+		assert!(!c.intersects(&far));
+		let parallel =
+			Capsule::new(Point3::new(0.15, 0., 0.), Point3::new(0.15, 1., 0.), 0.1);
+		assert!(c.intersects(&parallel));
+	}
+}
@@ -0,0 +1,25 @@
+//! The skeleton of the overlay, without the overlay: which bones there are, where
+//! they are and how they are shaped, and the math on top of that, like casting rays
+//! at them, making up the neck and head from the headset, and keeping the bone
+//! lengths. Nothing in here depends on OpenVR, so exporters, servers and tests can
+//! use it too.
+//!
+//! Bones point down their negative y axis, from their head position, and
+//! [`Isometry`]s are in the standing tracking space of SteamVR, in meters.
+//!
+//! The bones are those of [`skeletal_model`], whose FK/IK solver is re-exported as
+//! [`solver`], for the bones that the server doesn't send.
+
+mod bone;
+mod capsule;
+pub mod neck;
+pub mod proportions;
+mod skeleton;
+
+pub use self::bone::Bone;
+pub use self::capsule::Capsule;
+pub use self::skeleton::{closest_bone, intersecting, raycast, Skeleton};
+pub use skeletal_model as solver;
+pub use skeletal_model::{BoneKind, BoneMap};
+
+pub type Isometry = nalgebra::Isometry3<f32>;
@@ -0,0 +1,176 @@
+//! Makes up the neck and head from the headset, for setups without a tracker on
+//! them. The server only sends the bones that it can compute, so with the usual six
+//! trackers the skeleton ends at the chest.
+//!
+//! The head goes from the headset to the neck pivot, which is at a fixed offset
+//! from the headset, and the neck hangs down from the pivot to the chest. Without a
+//! chest either, the neck points straight down, facing where the headset faces.
+//! Bones that are in the feed are left alone, and a head from the feed ends where
+//! the neck starts.
+
+use crate::{BoneKind, Isometry};
+
+use nalgebra::{Point3, Translation3, UnitQuaternion, Vector3};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Synthesized {
+	pub kind: BoneKind,
+	pub iso: Isometry,
+	pub length: f32,
+}
+
+#[derive(Debug)]
+pub struct NeckSynthesis {
+	/// Where the neck pivot is, relative to the headset.
+	pivot_offset: Vector3<f32>,
+	/// How long the neck is without a chest to reach down to.
+	neck_length: f32,
+}
+impl NeckSynthesis {
+	pub fn new(pivot_offset: [f32; 3], neck_length: f32) -> Self {
+		Self {
+			pivot_offset: pivot_offset.into(),
+			neck_length,
+		}
+	}
+
+	/// The neck and head bones that `feed` lacks, with the headset at `hmd`. `feed`
+	/// has the isometry and length of each bone in it.
+	pub fn synthesize(
+		&self,
+		hmd: &Isometry,
+		feed: impl Fn(BoneKind) -> Option<(Isometry, f32)>,
+	) -> Vec<Synthesized> {
+		let mut bones = Vec::new();
+		let head = feed(BoneKind::Head);
+		let pivot = match head {
+			Some((iso, length)) => tail(&iso, length),
+			None => {
+				let offset = self.pivot_offset;
+				bones.push(Synthesized {
+					kind: BoneKind::Head,
+					iso: Isometry::from_parts(
+						hmd.translation,
+						hmd.rotation * towards(offset),
+					),
+					length: offset.norm(),
+				});
+				hmd * Point3::from(offset)
+			}
+		};
+		if feed(BoneKind::Neck).is_none() {
+			let yaw = yaw(&hmd.rotation);
+			let (direction, length) = match feed(BoneKind::Chest) {
+				Some((chest, _)) => {
+					let direction = chest.translation.vector - pivot.coords;
+					(direction, direction.norm())
+				}
+				None => (-Vector3::y(), self.neck_length),
+			};
+			bones.push(Synthesized {
+				kind: BoneKind::Neck,
+				iso: Isometry::from_parts(
+					Translation3::from(pivot.coords),
+					yaw * towards(yaw.inverse() * direction),
+				),
+				length,
+			});
+		}
+		bones
+	}
+}
+
+/// Where a bone from `iso` that is `length` long ends. Bones point down their -y
+/// axis, from their head position.
+fn tail(iso: &Isometry, length: f32) -> Point3<f32> {
+	iso * Point3::new(0., -length, 0.)
+}
+
+/// Just the turn of `rotation` around the vertical axis.
+fn yaw(rotation: &UnitQuaternion<f32>) -> UnitQuaternion<f32> {
+	// Looking along the negative z axis, like the headset.
+	let forward = rotation * -Vector3::z();
+	let angle = f32::atan2(-forward.x, -forward.z);
+	UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle)
+}
+
+/// The rotation that turns the -y axis, which bones point down, towards `direction`.
+fn towards(direction: Vector3<f32>) -> UnitQuaternion<f32> {
+	UnitQuaternion::rotation_between(&-Vector3::y(), &direction).unwrap_or_else(|| {
+		UnitQuaternion::from_axis_angle(&Vector3::x_axis(), std::f32::consts::PI)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const EPSILON: f32 = 1e-5;
+
+	/// The headset 1.6 m up, turned to the left by `angle`.
+	fn hmd(angle: f32) -> Isometry {
+		Isometry::from_parts(
+			Translation3::new(0., 1.6, 0.),
+			UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle),
+		)
+	}
+
+	fn end(bone: &Synthesized) -> Vector3<f32> {
+		tail(&bone.iso, bone.length).coords
+	}
+
+	#[test]
+	fn head_ends_at_the_pivot() {
+		let synthesis = NeckSynthesis::new([0., -0.1, 0.1], 0.1);
+		let bones = synthesis.synthesize(&hmd(0.), |_| None);
+		let [head, neck] = &bones[..] else {
+			panic!("Expected a head and a neck, got {bones:?}");
+		};
+		assert_eq!(head.kind, BoneKind::Head);
+		assert!((end(head) - Vector3::new(0., 1.5, 0.1)).norm() < EPSILON);
+		assert!((neck.iso.translation.vector - end(head)).norm() < EPSILON);
+		assert!((end(neck) - Vector3::new(0., 1.4, 0.1)).norm() < EPSILON);
+	}
+
+	#[test]
+	fn neck_turns_with_the_headset() {
+		let synthesis = NeckSynthesis::new([0., -0.1, 0.], 0.1);
+		let angle = std::f32::consts::FRAC_PI_2;
+		let bones = synthesis.synthesize(&hmd(angle), |_| None);
+		let neck = bones.iter().find(|b| b.kind == BoneKind::Neck).unwrap();
+		assert!((neck.iso.rotation.angle() - angle).abs() < EPSILON);
+	}
+
+	#[test]
+	fn neck_reaches_the_chest() {
+		let synthesis = NeckSynthesis::new([0., -0.1, 0.], 0.1);
+		let chest = Isometry::from_parts(
+			Translation3::new(0., 1.2, 0.1),
+			UnitQuaternion::identity(),
+		);
+		let bones = synthesis.synthesize(&hmd(0.), |kind| {
+			(kind == BoneKind::Chest).then_some((chest, 0.3))
+		});
+		let neck = bones.iter().find(|b| b.kind == BoneKind::Neck).unwrap();
+		assert!((end(neck) - chest.translation.vector).norm() < EPSILON);
+	}
+
+	#[test]
+	fn bones_in_the_feed_are_kept() {
+		let synthesis = NeckSynthesis::new([0., -0.1, 0.], 0.1);
+		let head = Isometry::from_parts(
+			Translation3::new(0., 1.7, 0.),
+			UnitQuaternion::identity(),
+		);
+		let bones = synthesis.synthesize(&hmd(0.), |kind| {
+			(kind == BoneKind::Head).then_some((head, 0.2))
+		});
+		assert_eq!(bones.len(), 1);
+		assert_eq!(bones[0].kind, BoneKind::Neck);
+		let start = bones[0].iso.translation.vector;
+		assert!((start - Vector3::new(0., 1.5, 0.)).norm() < EPSILON);
+
+		let all = |_| Some((head, 0.2));
+		assert!(synthesis.synthesize(&hmd(0.), all).is_empty());
+	}
+}
@@ -0,0 +1,176 @@
+//! Remembers the bone lengths that the server sent last, so that the skeleton is
+//! proportioned correctly as soon as the overlay starts, instead of only once the
+//! first feed update arrives.
+//!
+//! They are kept in a small TOML file, keyed by the name of the bone:
+//! ```toml
+//! Chest = 0.3
+//! FootL = 0.05
+//! ```
+//! The file is only a cache, so when it can't be read the defaults are used.
+
+use crate::{BoneKind, BoneMap};
+
+use eyre::{Result, WrapErr};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_PATH: &str = "bone_lengths.toml";
+/// The server sends lengths with every feed update, and they rarely change, so
+/// changes are written at most this often.
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+/// Changes smaller than this, in meters, aren't worth writing.
+const TOLERANCE: f32 = 0.001;
+
+pub struct Proportions {
+	path: PathBuf,
+	lengths: BoneMap<Option<f32>>,
+	dirty: bool,
+	last_save: Option<Instant>,
+}
+impl Proportions {
+	/// Loads the lengths from `path`. A missing or broken file is like an empty one.
+	pub fn load(path: &Path) -> Self {
+		let lengths = match std::fs::read_to_string(path) {
+			Ok(contents) => {
+				let lengths = parse(&contents);
+				log::info!("Loaded the last known bone lengths from {path:?}");
+				lengths
+			}
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => BoneMap::default(),
+			Err(e) => {
+				log::warn!("Failed to read {path:?}, using default bone lengths: {e}");
+				BoneMap::default()
+			}
+		};
+		Self {
+			path: path.to_owned(),
+			lengths,
+			dirty: false,
+			last_save: None,
+		}
+	}
+
+	/// The last known length of each bone, if there is one.
+	pub fn lengths(&self) -> BoneMap<Option<f32>> {
+		self.lengths
+	}
+
+	/// Remembers the length that the server just sent for `kind`.
+	pub fn update(&mut self, kind: BoneKind, length: f32) {
+		if !length.is_finite() || length < 0. {
+			return;
+		}
+		let changed =
+			self.lengths[kind].map_or(true, |l| (l - length).abs() > TOLERANCE);
+		if changed {
+			self.lengths[kind] = Some(length);
+			self.dirty = true;
+		}
+	}
+
+	/// Writes the lengths if they changed, and [`SAVE_INTERVAL`] has passed since the
+	/// last write.
+	pub fn save_if_due(&mut self, now: Instant) {
+		if self.dirty && self.last_save.map_or(true, |t| now - t >= SAVE_INTERVAL) {
+			self.last_save = Some(now);
+			self.save();
+		}
+	}
+
+	/// Writes the lengths if they changed. Failing to is only logged, since the
+	/// file is just a cache.
+	pub fn save(&mut self) {
+		if !self.dirty {
+			return;
+		}
+		self.dirty = false;
+		if let Err(e) = self.write() {
+			log::warn!("{e:?}");
+		}
+	}
+
+	fn write(&self) -> Result<()> {
+		// Written next to it first, so that a crash doesn't leave half a file.
+		let tmp = self.path.with_extension("toml.tmp");
+		std::fs::write(&tmp, serialize(&self.lengths))
+			.wrap_err_with(|| format!("Failed to write {tmp:?}"))?;
+		std::fs::rename(&tmp, &self.path)
+			.wrap_err_with(|| format!("Failed to replace {:?}", self.path))
+	}
+}
+
+/// Bones that are unknown or have invalid lengths are skipped.
+fn parse(contents: &str) -> BoneMap<Option<f32>> {
+	let mut lengths = BoneMap::default();
+	let table: HashMap<String, f32> = match toml::from_str(contents) {
+		Ok(table) => table,
+		Err(e) => {
+			log::warn!("Ignoring the last known bone lengths: {e}");
+			return lengths;
+		}
+	};
+	for (name, length) in table {
+		match name.parse::<BoneKind>() {
+			Ok(kind) if length.is_finite() && length >= 0. => {
+				lengths[kind] = Some(length)
+			}
+			Ok(_) => log::warn!("Ignoring invalid length {length} of {name}"),
+			Err(()) => log::warn!("Ignoring the length of unknown bone {name:?}"),
+		}
+	}
+	lengths
+}
+
+fn serialize(lengths: &BoneMap<Option<f32>>) -> String {
+	// Sorted, so that the file doesn't reorder itself on every write. Rounded to a
+	// tenth of a millimeter, since the digits of an `f32` as an `f64` are noise.
+	let table: BTreeMap<String, f64> = lengths
+		.iter()
+		.filter_map(|(kind, length)| {
+			let length = (f64::from((*length)?) * 1e4).round() / 1e4;
+			Some((format!("{kind:?}"), length))
+		})
+		.collect();
+	toml::to_string(&table).expect("a flat table of floats always serializes")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trip() {
+		let mut lengths = BoneMap::default();
+		lengths[BoneKind::Chest] = Some(0.3);
+		lengths[BoneKind::FootL] = Some(0.05);
+		let contents = serialize(&lengths);
+		assert_eq!(contents, "Chest = 0.3\nFootL = 0.05\n");
+		let parsed = parse(&contents);
+		for kind in BoneKind::iter() {
+			assert_eq!(parsed[kind], lengths[kind], "{kind:?}");
+		}
+	}
+
+	#[test]
+	fn skips_what_it_cant_use() {
+		let parsed = parse("Chest = 0.3\nTail = 1.0\nFootL = -1.0\n");
+		assert_eq!(parsed[BoneKind::Chest], Some(0.3));
+		assert_eq!(parsed[BoneKind::FootL], None);
+		assert!(parse("not toml").iter().all(|(_, l)| l.is_none()));
+	}
+
+	#[test]
+	fn only_real_changes_are_saved() {
+		let mut p = Proportions::load(Path::new("does/not/exist.toml"));
+		assert!(!p.dirty);
+		p.update(BoneKind::Chest, 0.3);
+		assert!(p.dirty);
+		p.dirty = false;
+		p.update(BoneKind::Chest, 0.3005);
+		p.update(BoneKind::Neck, f32::NAN);
+		assert!(!p.dirty);
+		assert_eq!(p.lengths()[BoneKind::Chest], Some(0.3));
+	}
+}
@@ -0,0 +1,135 @@
+use crate::{Bone, BoneKind, BoneMap, Capsule};
+
+use nalgebra::{Point3, Unit, Vector3};
+
+/// Every bone, whether it is shown or not.
+#[derive(Debug, Clone)]
+pub struct Skeleton {
+	pub bones: BoneMap<Bone>,
+}
+impl Skeleton {
+	pub fn new(bones: BoneMap<Bone>) -> Self {
+		Self { bones }
+	}
+
+	/// See [`raycast`].
+	pub fn raycast(
+		&self,
+		origin: &Point3<f32>,
+		dir: &Unit<Vector3<f32>>,
+	) -> Option<(BoneKind, f32)> {
+		raycast(&self.bones, origin, dir)
+	}
+
+	/// See [`closest_bone`].
+	pub fn closest_bone(
+		&self,
+		point: &Point3<f32>,
+	) -> Option<(BoneKind, Point3<f32>, f32)> {
+		closest_bone(&self.bones, point)
+	}
+
+	/// See [`intersecting`].
+	pub fn intersecting(
+		&self,
+		capsule: &Capsule,
+	) -> impl Iterator<Item = BoneKind> + '_ {
+		intersecting(&self.bones, capsule)
+	}
+}
+
+/// Finds the closest visible bone of `bones` hit by a ray from `origin` along
+/// `dir`, and how far along the ray it was hit.
+pub fn raycast<'a>(
+	bones: impl IntoIterator<Item = (BoneKind, &'a Bone)>,
+	origin: &Point3<f32>,
+	dir: &Unit<Vector3<f32>>,
+) -> Option<(BoneKind, f32)> {
+	bones
+		.into_iter()
+		.filter(|(_kind, bone)| bone.is_visible)
+		.filter_map(|(kind, bone)| {
+			bone.capsule().raycast(origin, dir).map(|dist| (kind, dist))
+		})
+		.min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+/// Finds the visible bone of `bones` whose surface is closest to `point`, along
+/// with the closest point on it and the signed distance to it.
+pub fn closest_bone<'a>(
+	bones: impl IntoIterator<Item = (BoneKind, &'a Bone)>,
+	point: &Point3<f32>,
+) -> Option<(BoneKind, Point3<f32>, f32)> {
+	bones
+		.into_iter()
+		.filter(|(_kind, bone)| bone.is_visible)
+		.map(|(kind, bone)| {
+			let capsule = bone.capsule();
+			(
+				kind,
+				capsule.closest_point(point),
+				capsule.signed_distance(point),
+			)
+		})
+		.min_by(|(_, _, a), (_, _, b)| a.total_cmp(b))
+}
+
+/// All visible bones of `bones` that overlap with `capsule`.
+pub fn intersecting<'a>(
+	bones: impl IntoIterator<Item = (BoneKind, &'a Bone)> + 'a,
+	capsule: &Capsule,
+) -> impl Iterator<Item = BoneKind> + 'a {
+	let capsule = *capsule;
+	bones
+		.into_iter()
+		.filter(|(_kind, bone)| bone.is_visible)
+		.filter(move |(_kind, bone)| bone.capsule().intersects(&capsule))
+		.map(|(kind, _bone)| kind)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::Isometry;
+	use nalgebra::{Translation3, UnitQuaternion};
+
+	/// Only the chest is visible, standing upright with its head 1.5 m up.
+	fn skeleton() -> Skeleton {
+		let mut bones = BoneMap::new([Bone::new(0.3, 0.05); BoneKind::NUM_TYPES]);
+		let chest = &mut bones[BoneKind::Chest];
+		chest.iso = Isometry::from_parts(
+			Translation3::new(0., 1.5, 0.),
+			UnitQuaternion::identity(),
+		);
+		chest.is_visible = true;
+		Skeleton::new(bones)
+	}
+
+	#[test]
+	fn finds_visible_bones() {
+		let skeleton = skeleton();
+		let origin = Point3::new(0., 1.3, 1.);
+		let hit = skeleton.raycast(&origin, &-Vector3::z_axis());
+		let (kind, distance) = hit.unwrap();
+		assert_eq!(kind, BoneKind::Chest);
+		assert!((distance - 0.95).abs() < 1e-5, "{distance}");
+		// The other bones are at the origin, but hidden.
+		let below = Point3::new(0., -0.1, 1.);
+		assert_eq!(skeleton.raycast(&below, &-Vector3::z_axis()), None);
+
+		let (kind, _, distance) = skeleton.closest_bone(&origin).unwrap();
+		assert_eq!(kind, BoneKind::Chest);
+		assert!((distance - 0.95).abs() < 1e-5, "{distance}");
+	}
+
+	#[test]
+	fn intersects_visible_bones() {
+		let skeleton = skeleton();
+		let touching =
+			Capsule::new(Point3::new(-1., 1.4, 0.), Point3::new(1., 1.4, 0.), 0.01);
+		let found: Vec<_> = skeleton.intersecting(&touching).collect();
+		assert_eq!(found, [BoneKind::Chest]);
+		let at_the_origin = Capsule::new(Point3::origin(), Point3::origin(), 0.1);
+		assert_eq!(skeleton.intersecting(&at_the_origin).count(), 0);
+	}
+}
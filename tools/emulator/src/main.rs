@@ -0,0 +1,104 @@
+//! Pretends to be SlimeVR trackers, so that the server, the overlay, and the
+//! protocol can be tried out without owning any. Each emulated tracker has its own
+//! socket and MAC address, so the server sees them as separate devices.
+
+mod motion;
+mod tracker;
+
+use crate::motion::Motion;
+
+use clap::{Parser, ValueEnum};
+use eyre::{Result, WrapErr};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The port that the server listens on.
+const SERVER_PORT: u16 = 6969;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MotionKind {
+	/// Hold still
+	Still,
+	/// Sway back and forth around all three axes
+	Sine,
+}
+
+#[derive(Parser, Debug)]
+struct Args {
+	/// The address of the server. By default, the handshake is broadcast like real
+	/// trackers do, and whichever server answers gets the rotations
+	#[arg(long)]
+	server: Option<SocketAddr>,
+	/// How many trackers to emulate
+	#[arg(short = 'n', long, default_value_t = 1)]
+	trackers: usize,
+	/// How many rotations each tracker sends per second
+	#[arg(long, default_value_t = 100)]
+	rate: u32,
+	/// How the trackers move
+	#[arg(long, value_enum, default_value_t = MotionKind::Sine)]
+	motion: MotionKind,
+	/// How far the sine motion swings each way, in degrees
+	#[arg(long, default_value_t = 30.)]
+	amplitude: f32,
+	/// How long one swing of the sine motion takes, in seconds
+	#[arg(long, default_value_t = 4.)]
+	period: f32,
+	/// Play back the rotations of a capture instead, one packet in hex per line
+	#[arg(long)]
+	replay: Option<PathBuf>,
+	/// Stop after this many seconds, instead of running until ctrl-c
+	#[arg(long)]
+	duration: Option<f32>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+	let args = Args::parse();
+	color_eyre::install()?;
+	if args.rate == 0 {
+		eyre::bail!("`--rate` must be at least 1");
+	}
+
+	let motion = match (&args.replay, args.motion) {
+		(Some(path), _) => {
+			let contents = std::fs::read_to_string(path)
+				.wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+			let tracks = motion::parse_capture(&contents)
+				.wrap_err_with(|| format!("Failed to parse {}", path.display()))?;
+			println!("Replaying {} sensors from {}", tracks.len(), path.display());
+			Motion::Replay(tracks)
+		}
+		(None, MotionKind::Still) => Motion::Still,
+		(None, MotionKind::Sine) => Motion::Sine {
+			amplitude: args.amplitude.to_radians(),
+			period: Duration::from_secs_f32(args.period),
+		},
+	};
+	let motion = Arc::new(motion);
+	let target = args
+		.server
+		.unwrap_or_else(|| SocketAddr::from((Ipv4Addr::BROADCAST, SERVER_PORT)));
+	let rate = Duration::from_secs_f64(1. / f64::from(args.rate));
+
+	let mut trackers = tokio::task::JoinSet::new();
+	for index in 0..args.trackers {
+		trackers.spawn(tracker::run(index, target, motion.clone(), rate));
+	}
+	let stop = async {
+		match args.duration {
+			Some(secs) => tokio::time::sleep(Duration::from_secs_f32(secs)).await,
+			None => {
+				let _ = tokio::signal::ctrl_c().await;
+			}
+		}
+	};
+	tokio::select! {
+		_ = stop => Ok(()),
+		Some(result) = trackers.join_next() => {
+			result.wrap_err("Tracker task panicked")?
+		}
+	}
+}
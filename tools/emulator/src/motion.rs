@@ -0,0 +1,168 @@
+//! How the emulated trackers move.
+
+use eyre::{eyre, Result};
+use firmware_protocol::{Packet, SbPacket};
+use nalgebra::{Quaternion, UnitQuaternion};
+use std::collections::BTreeMap;
+use std::f32::consts::TAU;
+use std::time::Duration;
+
+/// How far apart in their swing neighbouring trackers are, in radians, so that
+/// they can be told apart in the server.
+const PHASE_STEP: f32 = 0.7;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Motion {
+	Still,
+	/// Sways around all three axes, by up to `amplitude` radians.
+	Sine {
+		amplitude: f32,
+		period: Duration,
+	},
+	/// The rotations of each sensor in a capture, in order. Tracker `n` plays back
+	/// the `n`th one, looping once it is done, and sharing them once there are more
+	/// trackers than sensors.
+	Replay(Vec<Vec<UnitQuaternion<f32>>>),
+}
+impl Motion {
+	/// The rotation of `tracker` for its `frame`th packet, sent `elapsed` after it
+	/// started.
+	pub fn rotation(
+		&self,
+		tracker: usize,
+		frame: usize,
+		elapsed: Duration,
+	) -> UnitQuaternion<f32> {
+		match self {
+			Self::Still => UnitQuaternion::identity(),
+			Self::Sine { amplitude, period } => {
+				let t = elapsed.as_secs_f32() / period.as_secs_f32() * TAU
+					+ tracker as f32 * PHASE_STEP;
+				// Different frequencies, so that it doesn't just swing along a line.
+				let angle = |f: f32| amplitude * (t * f).sin();
+				UnitQuaternion::from_euler_angles(angle(1.), angle(0.7), angle(1.3))
+			}
+			Self::Replay(tracks) => {
+				let track = &tracks[tracker % tracks.len()];
+				track[frame % track.len()]
+			}
+		}
+	}
+}
+
+/// Reads the rotations out of a capture with one packet from a tracker per line, in
+/// hex like Wireshark's "Copy as Hex Stream". Packets that end in a CRC-32 are fine
+/// too, and other packets are skipped.
+pub fn parse_capture(contents: &str) -> Result<Vec<Vec<UnitQuaternion<f32>>>> {
+	let mut tracks: BTreeMap<u8, Vec<UnitQuaternion<f32>>> = BTreeMap::new();
+	for (i, line) in contents.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let bytes = parse_hex(line).ok_or_else(|| eyre!("Line {} isn't hex", i + 1))?;
+		let packet = Packet::<SbPacket>::deserialize_from(&bytes)
+			.or_else(|_| Packet::<SbPacket>::deserialize_from_checked(&bytes));
+		if let Ok(packet) = packet {
+			if let (
+				_,
+				SbPacket::RotationData {
+					sensor_id, quat, ..
+				},
+			) = packet.split()
+			{
+				let q = UnitQuaternion::from_quaternion(Quaternion::from(quat));
+				tracks.entry(sensor_id).or_default().push(q);
+			}
+		}
+	}
+	if tracks.is_empty() {
+		return Err(eyre!("The capture has no rotations"));
+	}
+	Ok(tracks.into_values().collect())
+}
+
+/// Ignores whitespace and colons between bytes.
+fn parse_hex(line: &str) -> Option<Vec<u8>> {
+	let digits: Vec<u8> = line
+		.bytes()
+		.filter(|b| !b.is_ascii_whitespace() && *b != b':')
+		.collect();
+	if digits.len() % 2 != 0 {
+		return None;
+	}
+	digits
+		.chunks(2)
+		.map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use firmware_protocol::{SensorDataType, SlimeQuaternion};
+
+	fn hex(packet: SbPacket) -> String {
+		let mut buf = [0; 64];
+		let len = Packet::new(0, packet).serialize_into(&mut buf).unwrap();
+		buf[..len].iter().map(|b| format!("{b:02x}")).collect()
+	}
+
+	fn rotation(sensor_id: u8, w: f32) -> SbPacket {
+		SbPacket::RotationData {
+			sensor_id,
+			data_type: SensorDataType::Normal,
+			quat: SlimeQuaternion {
+				i: 0.,
+				j: 0.,
+				k: 0.,
+				w,
+			},
+			calibration_info: 0,
+		}
+	}
+
+	#[test]
+	fn captures_are_split_by_sensor() {
+		let capture = [
+			hex(rotation(1, 1.)),
+			"# Comments and other packets are skipped".to_owned(),
+			hex(SbPacket::Heartbeat),
+			hex(rotation(0, -1.)),
+			hex(rotation(1, 1.)),
+		]
+		.join("\n");
+		let tracks = parse_capture(&capture).unwrap();
+		assert_eq!(tracks.iter().map(Vec::len).collect::<Vec<_>>(), [1, 2]);
+	}
+
+	#[test]
+	fn captures_without_rotations_are_rejected() {
+		assert!(parse_capture(&hex(SbPacket::Heartbeat)).is_err());
+		assert!(parse_capture("not hex").is_err());
+	}
+
+	#[test]
+	fn replay_loops() {
+		let a = UnitQuaternion::identity();
+		let b = UnitQuaternion::from_euler_angles(0.1, 0., 0.);
+		let motion = Motion::Replay(vec![vec![a, b]]);
+		assert_eq!(motion.rotation(0, 2, Duration::ZERO), a);
+		assert_eq!(motion.rotation(3, 1, Duration::ZERO), b);
+	}
+
+	#[test]
+	fn sine_stays_within_amplitude() {
+		let motion = Motion::Sine {
+			amplitude: 0.5,
+			period: Duration::from_secs(4),
+		};
+		for ms in (0..4000).step_by(50) {
+			let q = motion.rotation(1, 0, Duration::from_millis(ms));
+			let (roll, pitch, yaw) = q.euler_angles();
+			for angle in [roll, pitch, yaw] {
+				assert!(angle.abs() <= 0.5 + 1e-4, "{angle} at {ms} ms");
+			}
+		}
+	}
+}
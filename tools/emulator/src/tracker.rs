@@ -0,0 +1,153 @@
+//! One emulated tracker, which talks to the server like the firmware does: it
+//! broadcasts its handshake until a server answers, registers its sensor, and then
+//! streams rotations, answering heartbeats and pings along the way. A server that
+//! goes quiet sends it back to searching.
+
+use crate::motion::Motion;
+
+use eyre::{eyre, Result, WrapErr};
+use firmware_protocol::{
+	BoardType, CbPacket, ImuType, McuType, Packet, SbPacket, SensorDataType,
+	SensorStatus, PROTOCOL_BUILD,
+};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::UdpSocket;
+use tokio::time::MissedTickBehavior;
+
+/// How often to send the handshake while there is no server.
+const HANDSHAKE_INTERVAL: Duration = Duration::from_secs(1);
+/// The server sends a heartbeat every second, so hearing nothing for this long
+/// means that it is gone.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(3);
+/// Each tracker has a single sensor.
+const SENSOR_ID: u8 = 0;
+
+struct Connection {
+	socket: UdpSocket,
+	/// The sequence number of the last packet.
+	seq: u64,
+}
+impl Connection {
+	async fn send(&mut self, to: SocketAddr, packet: SbPacket) -> Result<()> {
+		self.seq += 1;
+		let mut buf = [0; 512];
+		let len = Packet::new(self.seq, packet)
+			.serialize_into(&mut buf)
+			.map_err(|e| eyre!("Failed to serialize packet: {e:?}"))?;
+		self.socket
+			.send_to(&buf[..len], to)
+			.await
+			.wrap_err("Failed to send packet")?;
+		Ok(())
+	}
+}
+
+/// A locally administered address, which is how the server tells the trackers
+/// apart when they all come from the same IP.
+fn mac_address(index: usize) -> [u8; 6] {
+	let [.., hi, lo] = (index as u32).to_be_bytes();
+	[0x02, b'S', b'l', b'm', hi, lo]
+}
+
+fn handshake(index: usize) -> SbPacket {
+	SbPacket::Handshake {
+		board: BoardType::Custom,
+		imu: ImuType::Bno085,
+		mcu: McuType::Unknown(0),
+		imu_info: (0, 0, 0),
+		build: PROTOCOL_BUILD,
+		firmware: concat!("SlimeVR-Rust emulator ", env!("CARGO_PKG_VERSION")).into(),
+		mac_address: mac_address(index),
+	}
+}
+
+/// Runs tracker number `index`, which sends its handshake to `target` and then a
+/// rotation every `rate`, moving by `motion`.
+pub async fn run(
+	index: usize,
+	target: SocketAddr,
+	motion: Arc<Motion>,
+	rate: Duration,
+) -> Result<()> {
+	let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))
+		.await
+		.wrap_err("Failed to bind socket")?;
+	socket
+		.set_broadcast(true)
+		.wrap_err("Failed to allow broadcasts")?;
+	let mut conn = Connection { socket, seq: 0 };
+
+	let start = Instant::now();
+	let mut server: Option<SocketAddr> = None;
+	let mut last_rx = start;
+	let mut frame = 0;
+	let mut handshakes = tokio::time::interval(HANDSHAKE_INTERVAL);
+	let mut rotations = tokio::time::interval(rate);
+	rotations.set_missed_tick_behavior(MissedTickBehavior::Skip);
+	let mut buf = [0; 2048];
+	loop {
+		if server.is_some() && last_rx.elapsed() >= SERVER_TIMEOUT {
+			println!("Tracker {index}: lost the server, searching again");
+			server = None;
+		}
+		tokio::select! {
+			received = conn.socket.recv_from(&mut buf) => {
+				let (len, from) = received.wrap_err("Failed to receive packet")?;
+				// Like the firmware, anything that isn't a packet is ignored.
+				let Ok(packet) = Packet::<CbPacket>::deserialize_from(&buf[..len]) else {
+					continue;
+				};
+				last_rx = Instant::now();
+				match packet.split().1 {
+					CbPacket::HandshakeResponse { version } => {
+						if server != Some(from) {
+							let version = version as char;
+							println!("Tracker {index}: found server {version} at {from}");
+						}
+						server = Some(from);
+						// Also after a server restarted, which forgot about the
+						// sensor.
+						let info = SbPacket::SensorInfo {
+							sensor_id: SENSOR_ID,
+							sensor_status: SensorStatus::Ok,
+							sensor_type: ImuType::Bno085,
+						};
+						conn.send(from, info).await?;
+						let [a, b, c, d, e, f] = mac_address(index);
+						let id = [a, b, c, d, e, f, 0, SENSOR_ID];
+						let unique_id = SbPacket::SensorUniqueId {
+							sensor_id: SENSOR_ID,
+							unique_id: u64::from_be_bytes(id),
+						};
+						conn.send(from, unique_id).await?;
+					}
+					CbPacket::Discovery => conn.send(from, handshake(index)).await?,
+					CbPacket::Heartbeat => conn.send(from, SbPacket::Heartbeat).await?,
+					CbPacket::Ping { challenge } => {
+						conn.send(from, SbPacket::Ping { challenge }).await?
+					}
+					_ => (),
+				}
+			}
+			_ = handshakes.tick(), if server.is_none() => {
+				conn.send(target, handshake(index)).await?
+			}
+			_ = rotations.tick(), if server.is_some() => {
+				let Some(server) = server else {
+					continue;
+				};
+				let rotation = motion.rotation(index, frame, start.elapsed());
+				frame += 1;
+				let packet = SbPacket::RotationData {
+					sensor_id: SENSOR_ID,
+					data_type: SensorDataType::Normal,
+					quat: rotation.into_inner().into(),
+					calibration_info: 0,
+				};
+				conn.send(server, packet).await?
+			}
+		}
+	}
+}
@@ -0,0 +1,129 @@
+//! Bridges between the ESP-NOW receiver in `firmware/receiver` and the server. The
+//! receiver hands over the packets of its trackers over USB serial, see
+//! [`receiver`], and each tracker gets its own UDP socket towards the server, so
+//! that the server tells them apart by their port, like Wi-Fi trackers by their
+//! address. It also hands out the slots that the trackers send in, see [`slots`].
+
+mod receiver;
+mod slots;
+
+use crate::receiver::{Event, Mac, Receiver, MAX_FRAME_LEN};
+use crate::slots::Slots;
+
+use clap::Parser;
+use eyre::{Result, WrapErr};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+struct Args {
+	/// The serial port of the receiver, like `COM3` or `/dev/ttyACM0`
+	#[arg(long)]
+	port: String,
+	/// Where the server listens for trackers
+	#[arg(long, default_value = "127.0.0.1:6969")]
+	server: SocketAddr,
+	/// Pairs with every tracker that asks to, while running
+	#[arg(long)]
+	pair: bool,
+	/// Where to keep the slots of the trackers
+	#[arg(long, default_value = "espnow_slots.txt")]
+	slots: PathBuf,
+	/// How long each slot is, in microseconds. The trackers stop sending half a
+	/// millisecond before the end of theirs.
+	#[arg(
+		long,
+		default_value_t = 2000,
+		value_parser = clap::value_parser!(u16).range(1000..)
+	)]
+	slot_us: u16,
+}
+
+fn main() -> Result<()> {
+	color_eyre::install()?;
+	let args = Args::parse();
+	let mut receiver = Receiver::open(&args.port)?;
+	let mut slots = Slots::load(args.slots)?;
+	receiver.schedule(slots.count(), args.slot_us)?;
+	let mut trackers: HashMap<Mac, UdpSocket> = HashMap::new();
+	// The trackers that we told about `--pair` already.
+	let mut not_paired = HashSet::new();
+	println!("Bridging {} to {}", args.port, args.server);
+	loop {
+		match receiver.poll()? {
+			Some(Event::Ready) => {
+				println!("The receiver started");
+				receiver.schedule(slots.count(), args.slot_us)?;
+			}
+			Some(Event::Pair(tracker)) if args.pair => {
+				let count = slots.count();
+				let slot = slots.assign(tracker)?;
+				receiver.accept(tracker, slot)?;
+				println!("Paired with {} in slot {slot}", receiver::display(&tracker));
+				if slots.count() != count {
+					receiver.schedule(slots.count(), args.slot_us)?;
+				}
+			}
+			Some(Event::Pair(tracker)) => {
+				if not_paired.insert(tracker) {
+					println!(
+						"{} wants to pair, restart with `--pair` to let it",
+						receiver::display(&tracker)
+					);
+				}
+			}
+			Some(Event::Frame(tracker, packet)) => {
+				let socket = match trackers.entry(tracker) {
+					Entry::Occupied(e) => e.into_mut(),
+					Entry::Vacant(e) => {
+						println!("{} is sending", receiver::display(&tracker));
+						e.insert(connect(args.server)?)
+					}
+				};
+				if let Err(e) = socket.send(&packet) {
+					eprintln!("Failed to send to the server: {e}");
+				}
+			}
+			None => (),
+		}
+
+		let mut buf = [0; MAX_FRAME_LEN + 1];
+		for (tracker, socket) in &trackers {
+			loop {
+				match socket.recv(&mut buf) {
+					Ok(n) if n <= MAX_FRAME_LEN => {
+						receiver.send(*tracker, &buf[..n])?
+					}
+					Ok(_) => {
+						eprintln!("Dropping a packet that doesn't fit into a frame")
+					}
+					Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+					// When the server isn't running yet.
+					Err(e) if e.kind() == ErrorKind::ConnectionRefused => break,
+					Err(e) => {
+						eprintln!("Failed to receive from the server: {e}");
+						break;
+					}
+				}
+			}
+		}
+	}
+}
+
+/// A socket of its own for a tracker, that only talks to the server.
+fn connect(server: SocketAddr) -> Result<UdpSocket> {
+	let any: SocketAddr = if server.is_ipv4() {
+		"0.0.0.0:0".parse().unwrap()
+	} else {
+		"[::]:0".parse().unwrap()
+	};
+	let socket = UdpSocket::bind(any).wrap_err("Failed to open a UDP socket")?;
+	socket
+		.connect(server)
+		.and_then(|()| socket.set_nonblocking(true))
+		.wrap_err_with(|| format!("Failed to connect to {server}"))?;
+	Ok(socket)
+}
@@ -0,0 +1,192 @@
+//! Talks to the receiver firmware in `firmware/receiver`, with the lines of its
+//! `lines.rs`. They are text in between the defmt frames of its log, which are
+//! skipped.
+
+use eyre::{bail, eyre, Result, WrapErr};
+use serialport::SerialPort;
+use std::fmt::Write as _;
+use std::io::{ErrorKind, Read, Write};
+use std::time::Duration;
+
+/// The USB serial port ignores it.
+const BAUD_RATE: u32 = 115_200;
+/// How long to wait for the receiver, before looking at the server again.
+const READ_TIMEOUT: Duration = Duration::from_millis(1);
+const PREFIX: &[u8] = b"SLMV ESPNOW ";
+/// The most that fits into one ESP-NOW frame.
+pub const MAX_FRAME_LEN: usize = 250;
+
+pub type Mac = [u8; 6];
+
+/// What the receiver reported.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event {
+	/// It started, and has forgotten its peers.
+	Ready,
+	/// A tracker asked to pair.
+	Pair(Mac),
+	/// A tracker sent a packet.
+	Frame(Mac, Vec<u8>),
+}
+
+pub struct Receiver {
+	port: Box<dyn SerialPort>,
+	/// What was read, but isn't a whole line yet.
+	pending: Vec<u8>,
+}
+impl Receiver {
+	pub fn open(path: &str) -> Result<Self> {
+		let port = serialport::new(path, BAUD_RATE)
+			.timeout(READ_TIMEOUT)
+			.open()
+			.wrap_err_with(|| format!("Failed to open {path}"))?;
+		Ok(Self {
+			port,
+			pending: Vec::new(),
+		})
+	}
+
+	/// The next thing that the receiver reported, or `None` if it didn't report
+	/// anything for a bit.
+	pub fn poll(&mut self) -> Result<Option<Event>> {
+		if self.pending.is_empty() || !self.pending.contains(&b'\n') {
+			let mut buf = [0; 1024];
+			match self.port.read(&mut buf) {
+				Ok(n) => self.pending.extend_from_slice(&buf[..n]),
+				Err(e) if e.kind() == ErrorKind::TimedOut => (),
+				Err(e) => return Err(e).wrap_err("Failed to read from the receiver"),
+			}
+		}
+		match take_line(&mut self.pending) {
+			Some(line) => parse_event(&line).map(Some),
+			None => Ok(None),
+		}
+	}
+
+	/// Pairs with `tracker`, after it asked to, and tells it which slot to send in.
+	pub fn accept(&mut self, tracker: Mac, slot: u8) -> Result<()> {
+		self.send_line(&format!("ACCEPT {} {slot}", hex(&tracker)))
+	}
+
+	/// Starts the beacons of a cycle of `slots` slots, or stops them if there are
+	/// none.
+	pub fn schedule(&mut self, slots: u8, slot_us: u16) -> Result<()> {
+		match slots {
+			0 => self.send_line("SLOTS 0 0"),
+			_ => self.send_line(&format!("SLOTS {slots} {slot_us}")),
+		}
+	}
+
+	pub fn send(&mut self, tracker: Mac, packet: &[u8]) -> Result<()> {
+		self.send_line(&format!("SEND {} {}", hex(&tracker), hex(packet)))
+	}
+
+	fn send_line(&mut self, line: &str) -> Result<()> {
+		self.port
+			.write_all(format!("{line}\n").as_bytes())
+			.and_then(|()| self.port.flush())
+			.wrap_err("Failed to send to the receiver")
+	}
+}
+
+/// Takes the first line out of `pending`, with everything before it. Leaves what
+/// might be the start of the next one.
+fn take_line(pending: &mut Vec<u8>) -> Option<String> {
+	let Some(start) = pending.windows(PREFIX.len()).position(|w| w == PREFIX) else {
+		// Keep what could be the start of the prefix.
+		let keep = pending.len().min(PREFIX.len() - 1);
+		pending.drain(..pending.len() - keep);
+		return None;
+	};
+	let end = start + pending[start..].iter().position(|&b| b == b'\n')?;
+	let line =
+		String::from_utf8_lossy(&pending[start + PREFIX.len()..end]).into_owned();
+	pending.drain(..=end);
+	Some(line)
+}
+
+fn parse_event(line: &str) -> Result<Event> {
+	let words: Vec<_> = line.split(' ').collect();
+	let event = match words[..] {
+		["READY"] => Event::Ready,
+		["PAIR", tracker] => Event::Pair(parse_mac(tracker)?),
+		["FRAME", tracker, packet] => Event::Frame(parse_mac(tracker)?, unhex(packet)?),
+		_ => bail!("The receiver sent something unexpected: {line:?}"),
+	};
+	Ok(event)
+}
+
+pub fn parse_mac(hex: &str) -> Result<Mac> {
+	unhex(hex)?
+		.try_into()
+		.map_err(|_| eyre!("Invalid MAC address {hex:?}"))
+}
+
+/// Like `aa:bb:cc:dd:ee:ff`, for people.
+pub fn display(mac: &Mac) -> String {
+	let hex: Vec<_> = mac.iter().map(|b| format!("{b:02x}")).collect();
+	hex.join(":")
+}
+
+fn hex(bytes: &[u8]) -> String {
+	let mut hex = String::with_capacity(2 * bytes.len());
+	for b in bytes {
+		let _ = write!(hex, "{b:02x}");
+	}
+	hex
+}
+
+fn unhex(hex: &str) -> Result<Vec<u8>> {
+	if !hex.is_ascii() || hex.len() % 2 != 0 {
+		bail!("Invalid hex {hex:?}");
+	}
+	(0..hex.len())
+		.step_by(2)
+		.map(|i| {
+			u8::from_str_radix(&hex[i..i + 2], 16)
+				.wrap_err_with(|| format!("Invalid hex {hex:?}"))
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn finds_lines_between_frames() {
+		// A defmt frame, the start of a line, and then the rest of it.
+		let mut pending = b"\x05\x8a\x01\0\0SLMV ESP".to_vec();
+		assert_eq!(take_line(&mut pending), None);
+		assert!(pending.ends_with(b"SLMV ESP"));
+		pending.extend_from_slice(b"NOW READY\n\0\x07");
+		assert_eq!(take_line(&mut pending).as_deref(), Some("READY"));
+		assert_eq!(pending, b"\0\x07");
+	}
+
+	#[test]
+	fn events() {
+		const TRACKER: Mac = [0x34, 0x85, 0x18, 0x01, 0xab, 0xcd];
+		assert_eq!(parse_event("READY").unwrap(), Event::Ready);
+		assert_eq!(
+			parse_event("PAIR 34851801abcd").unwrap(),
+			Event::Pair(TRACKER)
+		);
+		assert_eq!(
+			parse_event("FRAME 34851801abcd 000102ff").unwrap(),
+			Event::Frame(TRACKER, vec![0, 1, 2, 0xff])
+		);
+		assert!(parse_event("PAIR 3485").is_err());
+		assert!(parse_event("FRAME 34851801abcd 0g").is_err());
+		assert!(parse_event("HELLO").is_err());
+	}
+
+	#[test]
+	fn hex_round_trips() {
+		let packet = [0, 0x7f, 0x80, 0xff];
+		assert_eq!(hex(&packet), "007f80ff");
+		assert_eq!(unhex(&hex(&packet)).unwrap(), packet);
+		assert!(unhex("007").is_err());
+		assert!(unhex("éé").is_err());
+	}
+}
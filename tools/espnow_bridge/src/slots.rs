@@ -0,0 +1,101 @@
+//! Which slot each tracker sends in, see `firmware/src/networking/espnow/tdma.rs`.
+//! They are kept in a text file, with a line like `34:85:18:01:ab:cd 0` for each
+//! tracker, so that the trackers keep their slots when the bridge restarts.
+
+use crate::receiver::{self, Mac};
+
+use eyre::{bail, eyre, Result, WrapErr};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+pub struct Slots {
+	path: PathBuf,
+	slots: BTreeMap<Mac, u8>,
+}
+impl Slots {
+	/// The slots in `path`, none if it doesn't exist yet.
+	pub fn load(path: PathBuf) -> Result<Self> {
+		let slots = match std::fs::read_to_string(&path) {
+			Ok(contents) => parse(&contents)
+				.wrap_err_with(|| format!("Invalid slots in {path:?}"))?,
+			Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+			Err(e) => {
+				return Err(e).wrap_err_with(|| format!("Failed to read {path:?}"))
+			}
+		};
+		Ok(Self { path, slots })
+	}
+
+	/// How many slots a cycle has, enough for the highest one handed out.
+	pub fn count(&self) -> u8 {
+		self.slots.values().max().map_or(0, |&max| max + 1)
+	}
+
+	/// The slot of `tracker`, which gets the lowest free one if it has none yet.
+	pub fn assign(&mut self, tracker: Mac) -> Result<u8> {
+		if let Some(&slot) = self.slots.get(&tracker) {
+			return Ok(slot);
+		}
+		let Some(slot) = lowest_free(&self.slots) else {
+			bail!("All slots are taken, remove a tracker from {:?}", self.path);
+		};
+		self.slots.insert(tracker, slot);
+		std::fs::write(&self.path, format(&self.slots))
+			.wrap_err_with(|| format!("Failed to write {:?}", self.path))?;
+		Ok(slot)
+	}
+}
+
+fn lowest_free(slots: &BTreeMap<Mac, u8>) -> Option<u8> {
+	(0..=u8::MAX).find(|slot| !slots.values().any(|s| s == slot))
+}
+
+fn parse(contents: &str) -> Result<BTreeMap<Mac, u8>> {
+	let mut slots = BTreeMap::new();
+	for line in contents.lines().map(str::trim).filter(|l| !l.is_empty()) {
+		let (mac, slot) = line
+			.split_once(' ')
+			.ok_or_else(|| eyre!("Expected `<mac> <slot>`, not {line:?}"))?;
+		let mac = receiver::parse_mac(&mac.replace(':', ""))?;
+		let slot = slot
+			.trim()
+			.parse()
+			.wrap_err_with(|| format!("Invalid slot in {line:?}"))?;
+		if slots.insert(mac, slot).is_some() {
+			bail!("{} has more than one slot", receiver::display(&mac));
+		}
+	}
+	Ok(slots)
+}
+
+fn format(slots: &BTreeMap<Mac, u8>) -> String {
+	slots
+		.iter()
+		.map(|(mac, slot)| format!("{} {slot}\n", receiver::display(mac)))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trips() {
+		let contents = "34:85:18:01:ab:cd 0\n34:85:18:01:ab:ce 2\n";
+		let slots = parse(contents).unwrap();
+		assert_eq!(slots[&[0x34, 0x85, 0x18, 0x01, 0xab, 0xce]], 2);
+		assert_eq!(format(&slots), contents);
+		assert!(parse("34:85:18:01:ab:cd 0\n34:85:18:01:ab:cd 1").is_err());
+		assert!(parse("34:85:18:01:ab:cd").is_err());
+		assert!(parse("34:85:18:01:ab:cd 256").is_err());
+	}
+
+	#[test]
+	fn fills_gaps_first() {
+		let mut slots = parse("34:85:18:01:ab:cd 0\n34:85:18:01:ab:ce 2\n").unwrap();
+		assert_eq!(lowest_free(&slots), Some(1));
+		slots.insert([0; 6], 1);
+		assert_eq!(lowest_free(&slots), Some(3));
+	}
+}
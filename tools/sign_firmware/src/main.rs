@@ -0,0 +1,192 @@
+//! Signs firmware images for updates over the air, so that trackers built with the
+//! matching `OTA_PUBLIC_KEY` take them. The signature goes after the image, see
+//! [`OTA_SIGNATURE_LEN`].
+
+use clap::{Parser, Subcommand};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use eyre::{bail, eyre, Result, WrapErr};
+use firmware_protocol::{crc32, OTA_SIGNATURE_LEN, OTA_SIGNATURE_MAGIC};
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser, Debug)]
+struct Args {
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Makes a new key pair, and prints the public key to build the firmware with
+	Keygen {
+		/// Where to write the private key
+		out: PathBuf,
+	},
+	/// Appends the signature to an image
+	Sign {
+		/// The private key from `keygen`
+		#[arg(long)]
+		key: PathBuf,
+		image: PathBuf,
+		/// Where to write the signed image, by default next to it
+		#[arg(long)]
+		out: Option<PathBuf>,
+	},
+	/// Checks that a signed image is signed with a public key
+	Verify {
+		/// The public key, in hex
+		#[arg(long)]
+		public_key: String,
+		image: PathBuf,
+	},
+}
+
+fn main() -> Result<()> {
+	color_eyre::install()?;
+	match Args::parse().command {
+		Command::Keygen { out } => {
+			let key = SigningKey::generate(&mut OsRng);
+			write_key(&out, &key)?;
+			println!("Wrote the private key to {out:?}, keep it to yourself.");
+			println!("OTA_PUBLIC_KEY={}", hex(key.verifying_key().as_bytes()));
+		}
+		Command::Sign { key, image, out } => {
+			let key = std::fs::read_to_string(&key)
+				.wrap_err_with(|| format!("Failed to read {key:?}"))?;
+			let key = SigningKey::from_bytes(&unhex(&key)?);
+			let signed = sign(&read(&image)?, &key)?;
+			let out = out.unwrap_or_else(|| image.with_extension("signed.bin"));
+			std::fs::write(&out, &signed)
+				.wrap_err_with(|| format!("Failed to write {out:?}"))?;
+			println!(
+				"Wrote {out:?}, with {} bytes and the CRC {:#010x}",
+				signed.len(),
+				crc32(&signed)
+			);
+		}
+		Command::Verify { public_key, image } => {
+			let key = VerifyingKey::from_bytes(&unhex(&public_key)?)
+				.map_err(|_| eyre!("That isn't a public key"))?;
+			verify(&read(&image)?, &key)?;
+			println!("{image:?} is signed with that key");
+		}
+	}
+	Ok(())
+}
+
+/// Writes `key` to a new file at `path`, that only the user can read. An existing
+/// file is left alone, since it might be the key of other trackers.
+fn write_key(path: &Path, key: &SigningKey) -> Result<()> {
+	let mut options = OpenOptions::new();
+	options.write(true).create_new(true);
+	#[cfg(unix)]
+	std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+	let mut file = options.open(path).map_err(|e| match e.kind() {
+		ErrorKind::AlreadyExists => {
+			eyre!("{path:?} already exists, and might be the key of other trackers")
+		}
+		_ => eyre!(e).wrap_err(format!("Failed to create {path:?}")),
+	})?;
+	file.write_all(hex(&key.to_bytes()).as_bytes())
+		.wrap_err_with(|| format!("Failed to write {path:?}"))
+}
+
+fn read(path: &Path) -> Result<Vec<u8>> {
+	std::fs::read(path).wrap_err_with(|| format!("Failed to read {path:?}"))
+}
+
+/// `image` with its signature appended.
+fn sign(image: &[u8], key: &SigningKey) -> Result<Vec<u8>> {
+	if is_signed(image) {
+		bail!("The image is already signed");
+	}
+	let signature = key.sign(&Sha256::digest(image));
+	let mut signed = image.to_vec();
+	signed.extend_from_slice(&OTA_SIGNATURE_MAGIC);
+	signed.extend_from_slice(&signature.to_bytes());
+	Ok(signed)
+}
+
+/// Checks `signed` the same way the firmware does.
+fn verify(signed: &[u8], key: &VerifyingKey) -> Result<()> {
+	if !is_signed(signed) {
+		bail!("The image isn't signed");
+	}
+	let (image, trailer) = signed.split_at(signed.len() - OTA_SIGNATURE_LEN);
+	let signature = &trailer[OTA_SIGNATURE_MAGIC.len()..];
+	let signature = Signature::from_bytes(signature.try_into().unwrap());
+	key.verify_strict(&Sha256::digest(image), &signature)
+		.map_err(|_| eyre!("The image isn't signed with that key"))
+}
+
+/// Whether `image` ends in a signature, of whichever key.
+fn is_signed(image: &[u8]) -> bool {
+	image.len() > OTA_SIGNATURE_LEN
+		&& image[image.len() - OTA_SIGNATURE_LEN..].starts_with(&OTA_SIGNATURE_MAGIC)
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn unhex(hex: &str) -> Result<[u8; 32]> {
+	let hex = hex.trim();
+	if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		bail!("Keys are 32 bytes in hex");
+	}
+	let byte = |i: usize| u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+	Ok(std::array::from_fn(byte))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn signs_and_verifies() {
+		let key = SigningKey::from_bytes(&[7; 32]);
+		let image = [0xE9, 1, 2, 3];
+		let signed = sign(&image, &key).unwrap();
+		assert_eq!(signed.len(), image.len() + OTA_SIGNATURE_LEN);
+		verify(&signed, &key.verifying_key()).unwrap();
+		assert!(sign(&signed, &key).is_err());
+
+		let other = SigningKey::from_bytes(&[8; 32]);
+		assert!(verify(&signed, &other.verifying_key()).is_err());
+		let mut tampered = signed;
+		tampered[1] ^= 1;
+		assert!(verify(&tampered, &key.verifying_key()).is_err());
+		assert!(verify(&image, &key.verifying_key()).is_err());
+	}
+
+	#[test]
+	fn keeps_existing_keys() {
+		let dir =
+			std::env::temp_dir().join(format!("sign_firmware_{}", std::process::id()));
+		std::fs::create_dir_all(&dir).unwrap();
+		let path = dir.join("key");
+		let _ = std::fs::remove_file(&path);
+		let key = SigningKey::from_bytes(&[7; 32]);
+		write_key(&path, &key).unwrap();
+		assert!(write_key(&path, &SigningKey::from_bytes(&[8; 32])).is_err());
+		let written = std::fs::read_to_string(&path).unwrap();
+		assert_eq!(unhex(&written).unwrap(), key.to_bytes());
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::PermissionsExt;
+			let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+			assert_eq!(mode & 0o777, 0o600);
+		}
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn keys_in_hex() {
+		let bytes: [u8; 32] = std::array::from_fn(|i| i as u8 * 8);
+		assert_eq!(unhex(&format!("{}\n", hex(&bytes))).unwrap(), bytes);
+		assert!(unhex("abc").is_err());
+	}
+}
@@ -0,0 +1,164 @@
+//! Talks to the serial console of the firmware, with the `STORAGE` commands of
+//! `firmware/src/storage/console.rs`. Their answers are lines of text in between
+//! the defmt frames of the log, which are skipped.
+
+use eyre::{bail, eyre, Result, WrapErr};
+use serialport::SerialPort;
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+/// The USB serial port ignores it, but UART adapters need one.
+const BAUD_RATE: u32 = 115_200;
+/// The console only looks at what came in every 100 ms, and reads a bit of it at a
+/// time, so long records take a while to arrive.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+const PREFIX: &[u8] = b"SLMV STORAGE ";
+
+/// What the firmware answered about a slot.
+#[derive(Debug, PartialEq, Eq)]
+enum Answer {
+	Data(Vec<u8>),
+	Empty,
+	Ok,
+}
+
+pub struct Console {
+	port: Box<dyn SerialPort>,
+	/// What was read, but isn't a whole answer yet.
+	pending: Vec<u8>,
+}
+impl Console {
+	pub fn open(path: &str) -> Result<Self> {
+		let port = serialport::new(path, BAUD_RATE)
+			.timeout(READ_TIMEOUT)
+			.open()
+			.wrap_err_with(|| format!("Failed to open {path}"))?;
+		Ok(Self {
+			port,
+			pending: Vec::new(),
+		})
+	}
+
+	/// The payload of the record in `slot`, `None` if it is empty.
+	pub fn read(&mut self, slot: u8) -> Result<Option<Vec<u8>>> {
+		match self.ask(slot, &format!("STORAGE READ {slot}"))? {
+			Answer::Data(payload) => Ok(Some(payload)),
+			Answer::Empty => Ok(None),
+			Answer::Ok => bail!("The tracker didn't send the record in slot {slot}"),
+		}
+	}
+
+	pub fn write(&mut self, slot: u8, payload: &[u8]) -> Result<()> {
+		let hex = crate::records::hex(payload);
+		self.ask(slot, &format!("STORAGE WRITE {slot} {hex}"))?;
+		Ok(())
+	}
+
+	pub fn clear(&mut self, slot: u8) -> Result<()> {
+		self.ask(slot, &format!("STORAGE CLEAR {slot}"))?;
+		Ok(())
+	}
+
+	/// Restarts the tracker, which then loads the records that were written.
+	pub fn reboot(&mut self) -> Result<()> {
+		self.send("REBOOT")
+	}
+
+	fn send(&mut self, command: &str) -> Result<()> {
+		self.port
+			.write_all(format!("{command}\n").as_bytes())
+			.and_then(|()| self.port.flush())
+			.wrap_err("Failed to send to the tracker")
+	}
+
+	/// Sends `command`, and waits for the answer about `slot`.
+	fn ask(&mut self, slot: u8, command: &str) -> Result<Answer> {
+		// Answers that came too late for the last command.
+		self.pending.clear();
+		self.port
+			.clear(serialport::ClearBuffer::Input)
+			.wrap_err("Failed to clear the serial port")?;
+		self.send(command)?;
+		let deadline = Instant::now() + ANSWER_TIMEOUT;
+		loop {
+			while let Some(line) = take_line(&mut self.pending) {
+				if let Some(answer) = parse_answer(&line, slot)? {
+					return Ok(answer);
+				}
+			}
+			if Instant::now() > deadline {
+				bail!(
+					"The tracker didn't answer `{}`. It needs a firmware with the \
+					 `console` feature, and nothing else may use the port",
+					command.split(' ').take(2).collect::<Vec<_>>().join(" ")
+				);
+			}
+			let mut buf = [0; 256];
+			match self.port.read(&mut buf) {
+				Ok(n) => self.pending.extend_from_slice(&buf[..n]),
+				Err(e) if e.kind() == ErrorKind::TimedOut => (),
+				Err(e) => return Err(e).wrap_err("Failed to read from the tracker"),
+			}
+		}
+	}
+}
+
+/// Takes the first answer out of `pending`, with everything before it. Leaves what
+/// might be the start of the next one.
+fn take_line(pending: &mut Vec<u8>) -> Option<String> {
+	let Some(start) = pending.windows(PREFIX.len()).position(|w| w == PREFIX) else {
+		// Keep what could be the start of the prefix.
+		let keep = pending.len().min(PREFIX.len() - 1);
+		pending.drain(..pending.len() - keep);
+		return None;
+	};
+	let end = start + pending[start..].iter().position(|&b| b == b'\n')?;
+	let line =
+		String::from_utf8_lossy(&pending[start + PREFIX.len()..end]).into_owned();
+	pending.drain(..=end);
+	Some(line)
+}
+
+/// What `line` answers about `slot`, or `None` if it is about another one.
+fn parse_answer(line: &str, slot: u8) -> Result<Option<Answer>> {
+	let (about, rest) = line.split_once(' ').unwrap_or((line, ""));
+	if about != "?" && about != slot.to_string() {
+		return Ok(None);
+	}
+	let (what, payload) = rest.split_once(' ').unwrap_or((rest, ""));
+	let answer = match what {
+		"DATA" => Answer::Data(crate::records::unhex(payload)?),
+		"EMPTY" => Answer::Empty,
+		"OK" => Answer::Ok,
+		"ERROR" => return Err(eyre!("The tracker failed at slot {slot}: {payload}")),
+		_ => bail!("The tracker answered something unexpected: {line:?}"),
+	};
+	Ok(Some(answer))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn finds_answers_between_frames() {
+		// A defmt frame, the start of an answer, and then the rest of it.
+		let mut pending = b"\x05\x8a\x01\0\0SLMV STO".to_vec();
+		assert_eq!(take_line(&mut pending), None);
+		assert!(pending.ends_with(b"SLMV STO"));
+		pending.extend_from_slice(b"RAGE 3 DATA 0132\n\0\x07");
+		assert_eq!(take_line(&mut pending).as_deref(), Some("3 DATA 0132"));
+		assert_eq!(pending, b"\0\x07");
+	}
+
+	#[test]
+	fn answers() {
+		let data = parse_answer("3 DATA 0132", 3).unwrap();
+		assert_eq!(data, Some(Answer::Data(vec![1, 0x32])));
+		assert_eq!(parse_answer("0 EMPTY", 0).unwrap(), Some(Answer::Empty));
+		assert_eq!(parse_answer("1 OK", 3).unwrap(), None);
+		assert!(parse_answer("3 ERROR flash", 3).is_err());
+		assert!(parse_answer("? ERROR usage", 3).is_err());
+	}
+}
@@ -0,0 +1,180 @@
+//! Backs up and restores what a tracker keeps in flash, see `firmware/src/storage`,
+//! over its serial console. That moves the settings to another tracker, or fixes a
+//! broken config, without reflashing the firmware. The records are kept as TOML,
+//! see [`records`], so that they can be edited by hand.
+
+mod console;
+mod records;
+
+use crate::console::Console;
+use crate::records::{Records, SLOTS};
+
+use clap::{Parser, Subcommand};
+use eyre::{bail, Result, WrapErr};
+use std::path::{Path, PathBuf};
+use std::process::Command as Process;
+
+/// At the top of the files that we write.
+const HEADER: &str = "\
+# What a SlimeVR tracker keeps in flash, see tools/tracker_storage.
+# The calibrations only fit the tracker they were taken on, leave them out when
+# restoring to another one. The Wi-Fi password is in here as well.
+
+";
+
+#[derive(Parser, Debug)]
+struct Args {
+	/// The serial port of the tracker, like `COM3` or `/dev/ttyACM0`
+	#[arg(long, global = true)]
+	port: Option<String>,
+	#[command(subcommand)]
+	command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Lists the serial ports
+	Ports,
+	/// Reads the records of the tracker into a TOML file
+	Backup {
+		#[arg(default_value = "tracker.toml")]
+		out: PathBuf,
+	},
+	/// Writes the records of a TOML file to the tracker, and restarts it
+	Restore {
+		file: PathBuf,
+		/// Leaves a record as it is on the tracker, like `calibration`
+		#[arg(long)]
+		skip: Vec<String>,
+	},
+	/// Opens the records of the tracker in an editor, and writes back what changed
+	Edit,
+}
+
+fn main() -> Result<()> {
+	color_eyre::install()?;
+	let args = Args::parse();
+	let port = match (&args.command, args.port) {
+		(Command::Ports, _) => return list_ports(),
+		(_, Some(port)) => port,
+		(_, None) => bail!("Which port is the tracker on? Pass `--port`, see `ports`"),
+	};
+	let mut console = Console::open(&port)?;
+	match args.command {
+		Command::Ports => unreachable!(),
+		Command::Backup { out } => {
+			let records = read_all(&mut console)?;
+			save(&records, &out)?;
+			println!("Wrote the records of the tracker to {out:?}");
+		}
+		Command::Restore { file, skip } => {
+			for name in &skip {
+				if !SLOTS.iter().any(|(n, _)| n == name) {
+					bail!("There is no record named {name:?}");
+				}
+			}
+			let records = load(&file)?;
+			let mut payloads = records.payloads()?;
+			payloads.retain(|name, _| !skip.iter().any(|s| s == name));
+			for (name, payload) in &payloads {
+				println!("Writing {name}");
+				console.write(index(name), payload)?;
+			}
+			console.reboot()?;
+			println!("Restored {} records, the tracker restarts", payloads.len());
+		}
+		Command::Edit => {
+			let before = read_all(&mut console)?;
+			let path = std::env::temp_dir().join("tracker_storage.toml");
+			save(&before, &path)?;
+			edit(&path)?;
+			let after = load(&path)?;
+			let (before, after) = (before.payloads()?, after.payloads()?);
+			let mut changed = 0;
+			for &(name, slot) in &SLOTS {
+				match after.get(name) {
+					Some(payload) if before.get(name) != Some(payload) => {
+						println!("Writing {name}");
+						console.write(slot, payload)?;
+					}
+					None if before.contains_key(name) => {
+						println!("Clearing {name}");
+						console.clear(slot)?;
+					}
+					_ => continue,
+				}
+				changed += 1;
+			}
+			if changed == 0 {
+				println!("Nothing changed");
+			} else {
+				console.reboot()?;
+				println!("Changed {changed} records, the tracker restarts");
+			}
+			// It has the Wi-Fi password in it.
+			let _ = std::fs::remove_file(&path);
+		}
+	}
+	Ok(())
+}
+
+fn list_ports() -> Result<()> {
+	let ports = serialport::available_ports().wrap_err("Failed to list ports")?;
+	if ports.is_empty() {
+		println!("There are no serial ports, is the tracker plugged in?");
+	}
+	for port in ports {
+		match port.port_type {
+			serialport::SerialPortType::UsbPort(usb) => println!(
+				"{} (USB {:04x}:{:04x} {})",
+				port.port_name,
+				usb.vid,
+				usb.pid,
+				usb.product.unwrap_or_default()
+			),
+			_ => println!("{}", port.port_name),
+		}
+	}
+	Ok(())
+}
+
+/// The index of the slot named `name`, which is one of [`SLOTS`].
+fn index(name: &str) -> u8 {
+	SLOTS.iter().find(|(n, _)| *n == name).unwrap().1
+}
+
+fn read_all(console: &mut Console) -> Result<Records> {
+	let mut records = Records::default();
+	for &(name, slot) in &SLOTS {
+		if let Some(payload) = console.read(slot)? {
+			records.insert(name, &payload);
+		}
+	}
+	Ok(records)
+}
+
+fn save(records: &Records, path: &Path) -> Result<()> {
+	let toml = toml::to_string(records).wrap_err("Failed to write the records")?;
+	std::fs::write(path, format!("{HEADER}{toml}"))
+		.wrap_err_with(|| format!("Failed to write {path:?}"))
+}
+
+fn load(path: &Path) -> Result<Records> {
+	let contents = std::fs::read_to_string(path)
+		.wrap_err_with(|| format!("Failed to read {path:?}"))?;
+	toml::from_str(&contents).wrap_err_with(|| format!("Invalid records in {path:?}"))
+}
+
+/// Opens `path` in the editor of `VISUAL` or `EDITOR`, and waits until it is closed.
+fn edit(path: &Path) -> Result<()> {
+	let editor = std::env::var("VISUAL")
+		.or_else(|_| std::env::var("EDITOR"))
+		.unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_owned());
+	let status = Process::new(&editor).arg(path).status().wrap_err_with(|| {
+		format!("Failed to start {editor}, set `EDITOR` to another")
+	})?;
+	if !status.success() {
+		bail!("{editor} failed, so nothing was written to the tracker");
+	}
+	Ok(())
+}
@@ -0,0 +1,381 @@
+//! The records that the firmware keeps in flash, as TOML. Their layouts are those of
+//! the firmware, which are defined next to where it loads them:
+//!
+//! - the calibration in `firmware/src/imu/calibration.rs`,
+//! - the magnetometer calibration in `firmware/src/imu/mag_calibration.rs`,
+//! - the Wi-Fi credentials in `firmware/src/networking/wifi/credentials.rs`,
+//! - the ESP-NOW receiver in `firmware/src/networking/espnow/pairing.rs`,
+//! - and the config in `firmware/src/config.rs`.
+//!
+//! Records of a layout that we don't know, like from a newer firmware, are kept in
+//! hex under `[raw]`, so that they survive a backup and restore all the same.
+
+use eyre::{bail, eyre, Result};
+use firmware_protocol::{FUSION_BUDGET, FUSION_BUILT_IN, FUSION_DCM};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The name of each slot in the TOML, and its index in `Slot` of
+/// `firmware/src/storage/mod.rs`.
+pub const SLOTS: [(&str, u8); 5] = [
+	("calibration", 0),
+	("wifi", 1),
+	("espnow", 2),
+	("config", 3),
+	("mag_calibration", 4),
+];
+const CALIBRATION_VERSION: u8 = 1;
+/// The version, and nine `f32`s.
+const CALIBRATION_LEN: usize = 1 + 9 * 4;
+const MAG_CALIBRATION_VERSION: u8 = 1;
+/// The version, and twelve `f32`s.
+const MAG_CALIBRATION_LEN: usize = 1 + 12 * 4;
+const CONFIG_VERSION: u8 = 1;
+/// The version, the rotation rate, and the accelerometer gain.
+const CONFIG_V1_LEN: usize = 1 + 2 + 2;
+/// And whether the LED is on, the fusion algorithm, and the TX power.
+const CONFIG_LEN: usize = CONFIG_V1_LEN + 3;
+/// 21 dBm, the most that the firmware transmits with.
+const DEFAULT_TX_POWER: u8 = 84;
+
+/// Everything that was read from a tracker, or that is written to one.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Records {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub config: Option<Config>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub calibration: Option<Calibration>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub mag_calibration: Option<MagCalibration>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub wifi: Option<Wifi>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub espnow: Option<EspNow>,
+	/// The records that we don't know the layout of, in hex, by the name of their
+	/// slot.
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	pub raw: BTreeMap<String, String>,
+}
+
+/// The settings that the server can change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+	/// The most rotations per second for each sensor, `0` for no limit.
+	pub rotation_rate_hz: u16,
+	/// How strongly the fusion corrects with the accelerometer, in thousandths of
+	/// its default.
+	pub accel_gain_milli: u16,
+	pub led: bool,
+	pub fusion: Fusion,
+	/// The most power that Wi-Fi transmits with, in quarter dBm.
+	pub tx_power_quarter_dbm: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fusion {
+	BuiltIn,
+	Budget,
+	Dcm,
+}
+
+/// The corrections for the IMU, which only fit the IMU they were taken on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Calibration {
+	pub gyro_bias: [f32; 3],
+	pub accel_offset: [f32; 3],
+	pub accel_scale: [f32; 3],
+}
+
+/// The corrections for the magnetometer, which only fit the tracker they were taken
+/// on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MagCalibration {
+	/// In microtesla.
+	pub hard_iron: [f32; 3],
+	/// Row by row.
+	pub soft_iron: [f32; 9],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Wifi {
+	pub ssid: String,
+	pub password: String,
+}
+
+/// The receiver that the tracker is paired with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EspNow {
+	/// Its MAC address, like `12:34:56:78:9a:bc`.
+	pub receiver: String,
+	/// The slot it handed out, which older receivers didn't.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub slot: Option<u8>,
+}
+
+impl Records {
+	/// Adds the payload of the record in slot `name`.
+	pub fn insert(&mut self, name: &str, payload: &[u8]) {
+		let known = match name {
+			"config" => decode_config(payload).map(|c| self.config = Some(c)),
+			"calibration" => {
+				decode_calibration(payload).map(|c| self.calibration = Some(c))
+			}
+			"mag_calibration" => {
+				decode_mag_calibration(payload).map(|c| self.mag_calibration = Some(c))
+			}
+			"wifi" => decode_wifi(payload).map(|w| self.wifi = Some(w)),
+			"espnow" => decode_espnow(payload).map(|e| self.espnow = Some(e)),
+			_ => None,
+		};
+		if known.is_none() {
+			self.raw.insert(name.to_owned(), hex(payload));
+		}
+	}
+
+	/// The payload of each record, by the name of its slot.
+	pub fn payloads(&self) -> Result<BTreeMap<&'static str, Vec<u8>>> {
+		let mut payloads = BTreeMap::new();
+		for (name, hex) in &self.raw {
+			let Some(&(name, _)) = SLOTS.iter().find(|(n, _)| n == name) else {
+				bail!("There is no slot named {name:?} under [raw]");
+			};
+			payloads.insert(name, unhex(hex)?);
+		}
+		if let Some(c) = &self.config {
+			payloads.insert("config", encode_config(c));
+		}
+		if let Some(c) = &self.calibration {
+			payloads.insert("calibration", encode_calibration(c));
+		}
+		if let Some(c) = &self.mag_calibration {
+			payloads.insert("mag_calibration", encode_mag_calibration(c));
+		}
+		if let Some(w) = &self.wifi {
+			payloads.insert("wifi", encode_wifi(w)?);
+		}
+		if let Some(e) = &self.espnow {
+			payloads.insert("espnow", encode_espnow(e)?);
+		}
+		Ok(payloads)
+	}
+}
+
+fn decode_config(payload: &[u8]) -> Option<Config> {
+	if !(CONFIG_V1_LEN..=CONFIG_LEN).contains(&payload.len())
+		|| payload[0] != CONFIG_VERSION
+	{
+		return None;
+	}
+	let u16_at = |i: usize| u16::from_le_bytes([payload[i], payload[i + 1]]);
+	let fusion = match payload.get(6).map_or(FUSION_BUILT_IN, |&b| b.into()) {
+		FUSION_BUILT_IN => Fusion::BuiltIn,
+		FUSION_BUDGET => Fusion::Budget,
+		FUSION_DCM => Fusion::Dcm,
+		_ => return None,
+	};
+	Some(Config {
+		rotation_rate_hz: u16_at(1),
+		accel_gain_milli: u16_at(3),
+		led: payload.get(5).map_or(true, |&b| b != 0),
+		fusion,
+		tx_power_quarter_dbm: payload.get(7).copied().unwrap_or(DEFAULT_TX_POWER),
+	})
+}
+
+fn encode_config(config: &Config) -> Vec<u8> {
+	let fusion = match config.fusion {
+		Fusion::BuiltIn => FUSION_BUILT_IN,
+		Fusion::Budget => FUSION_BUDGET,
+		Fusion::Dcm => FUSION_DCM,
+	};
+	let mut payload = vec![CONFIG_VERSION];
+	payload.extend_from_slice(&config.rotation_rate_hz.to_le_bytes());
+	payload.extend_from_slice(&config.accel_gain_milli.to_le_bytes());
+	payload.push(config.led.into());
+	payload.push(fusion as u8);
+	payload.push(config.tx_power_quarter_dbm);
+	payload
+}
+
+fn decode_calibration(payload: &[u8]) -> Option<Calibration> {
+	if payload.len() != CALIBRATION_LEN || payload[0] != CALIBRATION_VERSION {
+		return None;
+	}
+	let value = |i: usize| {
+		let at = 1 + 4 * i;
+		f32::from_le_bytes(payload[at..at + 4].try_into().unwrap())
+	};
+	let vector = |first: usize| std::array::from_fn(|i| value(first + i));
+	Some(Calibration {
+		gyro_bias: vector(0),
+		accel_offset: vector(3),
+		accel_scale: vector(6),
+	})
+}
+
+fn encode_calibration(calibration: &Calibration) -> Vec<u8> {
+	let mut payload = vec![CALIBRATION_VERSION];
+	let values = calibration
+		.gyro_bias
+		.iter()
+		.chain(&calibration.accel_offset)
+		.chain(&calibration.accel_scale);
+	for value in values {
+		payload.extend_from_slice(&value.to_le_bytes());
+	}
+	payload
+}
+
+/// The hard iron offset, and the soft iron matrix row by row.
+fn decode_mag_calibration(payload: &[u8]) -> Option<MagCalibration> {
+	if payload.len() != MAG_CALIBRATION_LEN || payload[0] != MAG_CALIBRATION_VERSION {
+		return None;
+	}
+	let value = |i: usize| {
+		let at = 1 + 4 * i;
+		f32::from_le_bytes(payload[at..at + 4].try_into().unwrap())
+	};
+	Some(MagCalibration {
+		hard_iron: std::array::from_fn(value),
+		soft_iron: std::array::from_fn(|i| value(3 + i)),
+	})
+}
+
+fn encode_mag_calibration(calibration: &MagCalibration) -> Vec<u8> {
+	let mut payload = vec![MAG_CALIBRATION_VERSION];
+	for value in calibration.hard_iron.iter().chain(&calibration.soft_iron) {
+		payload.extend_from_slice(&value.to_le_bytes());
+	}
+	payload
+}
+
+/// The length of the SSID, the SSID, the length of the password, and the password.
+fn decode_wifi(payload: &[u8]) -> Option<Wifi> {
+	let (&ssid_len, rest) = payload.split_first()?;
+	let ssid = rest.get(..ssid_len.into())?;
+	let (&password_len, rest) = rest[ssid.len()..].split_first()?;
+	let password = rest.get(..password_len.into())?;
+	Some(Wifi {
+		ssid: String::from_utf8(ssid.to_vec()).ok()?,
+		password: String::from_utf8(password.to_vec()).ok()?,
+	})
+}
+
+fn encode_wifi(wifi: &Wifi) -> Result<Vec<u8>> {
+	// The limits of Wi-Fi, which the firmware checks as well.
+	if wifi.ssid.is_empty() || wifi.ssid.len() > 32 || wifi.password.len() > 64 {
+		bail!("The SSID has to be 1 to 32 bytes long, and the password at most 64");
+	}
+	let mut payload = vec![wifi.ssid.len() as u8];
+	payload.extend_from_slice(wifi.ssid.as_bytes());
+	payload.push(wifi.password.len() as u8);
+	payload.extend_from_slice(wifi.password.as_bytes());
+	Ok(payload)
+}
+
+/// The MAC address, and the slot if the receiver handed one out.
+fn decode_espnow(payload: &[u8]) -> Option<EspNow> {
+	let (receiver, slot) = match payload.len() {
+		6 => (payload, None),
+		7 => (&payload[..6], Some(payload[6])),
+		_ => return None,
+	};
+	let receiver: Vec<String> = receiver.iter().map(|b| format!("{b:02x}")).collect();
+	Some(EspNow {
+		receiver: receiver.join(":"),
+		slot,
+	})
+}
+
+fn encode_espnow(espnow: &EspNow) -> Result<Vec<u8>> {
+	let mut payload = unhex(&espnow.receiver.replace(':', ""))
+		.ok()
+		.filter(|mac| mac.len() == 6)
+		.ok_or_else(|| eyre!("{:?} isn't a MAC address", espnow.receiver))?;
+	payload.extend(espnow.slot);
+	Ok(payload)
+}
+
+pub fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn unhex(hex: &str) -> Result<Vec<u8>> {
+	let hex = hex.trim();
+	if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		bail!("{hex:?} isn't hex");
+	}
+	(0..hex.len() / 2)
+		.map(|i| Ok(u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)?))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn round_trips() {
+		let mut records = Records::default();
+		// As the firmware stores them.
+		let config = [1, 50, 0, 0xE8, 0x03, 0, 2, 60];
+		let wifi = b"\x04home\x06secret";
+		let espnow = [0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 3];
+		let mut calibration = vec![1];
+		for value in [0.01f32, 0., 0., 0.1, 0., -0.1, 1., 1.02, 0.98] {
+			calibration.extend_from_slice(&value.to_le_bytes());
+		}
+		records.insert("config", &config);
+		records.insert("wifi", wifi);
+		records.insert("espnow", &espnow);
+		records.insert("calibration", &calibration);
+		let mut mag_calibration = vec![1];
+		for value in [12.5f32, -3., 40., 1.1, 0., 0., 0., 0.9, 0., 0., 0., 1.] {
+			mag_calibration.extend_from_slice(&value.to_le_bytes());
+		}
+		records.insert("mag_calibration", &mag_calibration);
+		assert!(records.raw.is_empty());
+		assert_eq!(
+			records.espnow.as_ref().unwrap().receiver,
+			"12:34:56:78:9a:bc"
+		);
+		let config_record = records.config.as_ref().unwrap();
+		assert_eq!(config_record.fusion, Fusion::Dcm);
+		assert!(!config_record.led);
+		assert_eq!(config_record.tx_power_quarter_dbm, 60);
+
+		let parsed: Records =
+			toml::from_str(&toml::to_string(&records).unwrap()).unwrap();
+		assert_eq!(parsed, records);
+		let payloads = parsed.payloads().unwrap();
+		assert_eq!(payloads["config"], config);
+		assert_eq!(payloads["wifi"], wifi);
+		assert_eq!(payloads["espnow"], espnow);
+		assert_eq!(payloads["calibration"], calibration);
+		assert_eq!(payloads["mag_calibration"], mag_calibration);
+	}
+
+	#[test]
+	fn keeps_what_it_doesnt_know() {
+		let mut records = Records::default();
+		// A version that this tool doesn't know.
+		records.insert("calibration", &[2, 1, 2, 3]);
+		// Stored before the LED, the fusion, and the TX power were.
+		records.insert("config", &[1, 0, 0, 0xE8, 0x03]);
+		assert_eq!(records.raw["calibration"], "02010203");
+		let config = records.config.as_ref().unwrap();
+		assert_eq!(config.fusion, Fusion::BuiltIn);
+		assert_eq!(config.tx_power_quarter_dbm, DEFAULT_TX_POWER);
+		assert_eq!(records.payloads().unwrap()["calibration"], [2, 1, 2, 3]);
+
+		records.raw.insert("nope".to_owned(), "00".to_owned());
+		assert!(records.payloads().is_err());
+	}
+}
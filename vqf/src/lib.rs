@@ -1,17 +1,18 @@
 //! This crate reimplements most of the relevant parts of the VQF algorithm from
 //! <https://github.com/dlaidig/vqf/blob/f2a63375604e0b025048d181ba6a204e96ce2559/vqf/pyvqf.py>
-//! Currently this is just copy-pasted from the python code, but it should be made more
-//! idiomatic before actually using it. I have marked areas most likely to contain bugs
-//! with ඞ
+//! It is still close to a line by line translation of the python code, so the names
+//! follow it too. Only the 6D and 9D orientation are ported, not the debugging
+//! outputs or the offline variant.
 //!
 //! The original code is licensed under the MIT license, so this crate is also licensed under the MIT license.
 
 #![no_std]
 #![allow(non_snake_case)]
 
-use core::f32::consts::PI;
+use core::f32::consts::{PI, SQRT_2};
 
-use nalgebra::{ArrayStorage, U2, U9};
+use nalgebra::{ArrayStorage, Quaternion, U2, U9};
+use num_traits::Float;
 
 type Quat = nalgebra::UnitQuaternion<f32>;
 type Vec2 = nalgebra::Vector2<f32>;
@@ -20,6 +21,9 @@ type Mat2x3 = nalgebra::Matrix2x3<f32>;
 type Mat2x9 = nalgebra::Matrix<f32, U2, U9, ArrayStorage<f32, 2, 9>>;
 type Mat2x2 = nalgebra::Matrix2<f32>;
 type Mat3x3 = nalgebra::Matrix3<f32>;
+type Vec9 = nalgebra::SVector<f32, 9>;
+/// The state of a low pass filter of an `N` dimensional signal.
+type FilterState<const N: usize> = nalgebra::SMatrix<f32, 2, N>;
 
 const EPS: f32 = 1e-6;
 
@@ -205,18 +209,66 @@ pub struct Vqf {
 
 impl Vqf {
 	pub fn new(gyrTs: f32, accTs: f32, magTs: f32, params: VqfParameters) -> Vqf {
-		Vqf {
+		let mut vqf = Vqf {
 			_params: params,
 			_state: Default::default(),
-			_coeffs: VQFCoefficients {
-				gyrTs,
-				accTs,
-				magTs,
-				..Default::default()
-			},
+			_coeffs: Default::default(),
+		};
+		vqf.setTs(gyrTs, accTs, magTs);
+		vqf.resetState();
+		vqf
+	}
+
+	/// Changes the sampling times, for example after the sensor rate changed. Unlike
+	/// the python code, this keeps the current state.
+	pub fn setTs(&mut self, gyrTs: f32, accTs: f32, magTs: f32) {
+		assert!(gyrTs > 0.0 && accTs > 0.0 && magTs > 0.0);
+		let p = &self._params;
+		let c = &mut self._coeffs;
+		c.gyrTs = gyrTs;
+		c.accTs = accTs;
+		c.magTs = magTs;
+
+		(c.accLpB, c.accLpA) = filterCoeffs(p.tauAcc, accTs);
+		c.kMag = gainFromTau(p.tauMag, magTs);
+
+		c.biasP0 = (p.biasSigmaInit * 100.0).powi(2);
+		// the system noise increases the variance from 0 to (0.1 °/s)^2 in
+		// biasForgettingTime seconds
+		c.biasV = (0.1 * 100.0).powi(2) * accTs / p.biasForgettingTime;
+
+		let pMotion = (p.biasSigmaMotion * 100.0).powi(2);
+		c.biasMotionW = pMotion.powi(2) / c.biasV + pMotion;
+		c.biasVerticalW = c.biasMotionW / p.biasVerticalForgettingFactor.max(1e-10);
+
+		let pRest = (p.biasSigmaRest * 100.0).powi(2);
+		c.biasRestW = pRest.powi(2) / c.biasV + pRest;
+
+		(c.restGyrLpB, c.restGyrLpA) = filterCoeffs(p.restFilterTau, gyrTs);
+		(c.restAccLpB, c.restAccLpA) = filterCoeffs(p.restFilterTau, accTs);
+
+		c.kMagRef = gainFromTau(p.magRefTau, magTs);
+		if p.magCurrentTau > 0.0 {
+			(c.magNormDipLpB, c.magNormDipLpA) = filterCoeffs(p.magCurrentTau, magTs);
 		}
 	}
 
+	/// Changes how quickly pitch and roll follow the accelerometer, keeping the current
+	/// state like `setTs` does.
+	pub fn setTauAcc(&mut self, tauAcc: f32) {
+		self._params.tauAcc = tauAcc;
+		let (gyrTs, accTs, magTs) =
+			(self._coeffs.gyrTs, self._coeffs.accTs, self._coeffs.magTs);
+		self.setTs(gyrTs, accTs, magTs);
+	}
+
+	pub fn resetState(&mut self) {
+		self._state = VqfState {
+			biasP: Mat3x3::from_diagonal_element(self._coeffs.biasP0),
+			..Default::default()
+		};
+	}
+
 	pub fn updateGyr(&mut self, gyr: Vec3) {
 		if self._params.restBiasEstEnabled || self._params.magDistRejectionEnabled {
 			let gyrLp = filterVec(
@@ -251,10 +303,14 @@ impl Vqf {
 		if gyrNorm > EPS {
 			let c = (angle / 2.0).cos();
 			let s = (angle / 2.0).sin() / gyrNorm;
-			let gyrStepQuat = Quat::from_quaternion(
-				[c, s * gyrNoBias[0], s * gyrNoBias[1], s * gyrNoBias[2]].into(),
-			);
+			let gyrStepQuat = Quat::new_unchecked(Quaternion::new(
+				c,
+				s * gyrNoBias[0],
+				s * gyrNoBias[1],
+				s * gyrNoBias[2],
+			));
 			self._state.gyrQuat = self._state.gyrQuat * gyrStepQuat;
+			self._state.gyrQuat.renormalize();
 		}
 	}
 
@@ -305,22 +361,19 @@ impl Vqf {
 		);
 
 		// transform to 6D earth frame and normalize
-		let accEarth = self._state.accQuat * self._state.lastAccLp;
+		let accEarth = (self._state.accQuat * self._state.lastAccLp).normalize();
 
 		// inclination correction
 		let q_w = ((accEarth[2] + 1.0) / 2.0).sqrt();
-		let accCorrQuat;
-		if q_w > EPS {
-			accCorrQuat = Quat::from_quaternion(
-				[q_w, 0.5 * accEarth[1] / q_w, -0.5 * accEarth[0] / q_w, 0.0].into(),
-			);
+		let accCorrQuat = if q_w > EPS {
+			Quaternion::new(q_w, 0.5 * accEarth[1] / q_w, -0.5 * accEarth[0] / q_w, 0.0)
 		} else {
-			accCorrQuat = Quat::from_quaternion([0.0, 1.0, 0.0, 0.0].into());
-		}
-		self._state.accQuat = accCorrQuat * self._state.accQuat;
-
-		// calculate correction angular rate to facilitate debugging
-		// self._state.lastAccCorrAngularRate = (accEarth[2]).acos() / self._coeffs.accTs;
+			// to avoid numeric issues when acc is close to [0 0 -1], i.e. the correction
+			// step is close (<= 0.00011°) to 180°
+			Quaternion::new(0.0, 1.0, 0.0, 0.0)
+		};
+		self._state.accQuat =
+			Quat::new_normalize(accCorrQuat * self._state.accQuat.into_inner());
 
 		// bias estimation
 		if self._params.motionBiasEstEnabled || self._params.restBiasEstEnabled {
@@ -329,22 +382,21 @@ impl Vqf {
 
 			// get rotation matrix corresponding to accGyrQuat
 			let accGyrQuat = self.getQuat6D();
-			// ඞ
 			let R = accGyrQuat.to_rotation_matrix().into_inner();
 
 			// calculate R*b_hat (only the x and y component, as z is not needed)
 			let biasLp = (R * bias).xy();
 
-			// low-pass filter R and R*b_hat
-			let mut R = filterVec(
-				R.reshape_generic(nalgebra::Const::<9>, nalgebra::Const::<1>),
+			// low-pass filter R and R*b_hat, flattened in row-major order like in python
+			let R = filterVec(
+				Vec9::from_iterator(R.transpose().iter().copied()),
 				self._params.tauAcc,
 				accTs,
 				self._coeffs.accLpB,
 				self._coeffs.accLpA,
 				&mut self._state.motionBiasEstRLpState,
-			)
-			.reshape_generic(nalgebra::Const::<3>, nalgebra::Const::<3>);
+			);
+			let mut R = Mat3x3::from_row_slice(R.as_slice());
 			let biasLp = filterVec(
 				biasLp,
 				self._params.tauAcc,
@@ -362,17 +414,12 @@ impl Vqf {
 				R = Mat3x3::identity();
 				w = Some(Vec3::repeat(self._coeffs.biasRestW));
 			} else if self._params.motionBiasEstEnabled {
-				// ඞ
+				let Rb = R * bias;
 				e = Some(Vec3::new(
-					-accEarth[1] / accTs + biasLp[0]
-						- R[0] * bias[0] - R[1] * bias[1]
-						- R[2] * bias[2],
-					accEarth[0] / accTs + biasLp[1]
-						- R[3] * bias[0] - R[4] * bias[1]
-						- R[5] * bias[2],
-					-R[6] * bias[0] - R[7] * bias[1] - R[8] * bias[2],
+					-accEarth[1] / accTs + biasLp[0] - Rb[0],
+					accEarth[0] / accTs + biasLp[1] - Rb[1],
+					-Rb[2],
 				));
-				R = Mat3x3::repeat(f32::NAN);
 				w = Some(Vec3::new(
 					self._coeffs.biasMotionW,
 					self._coeffs.biasMotionW,
@@ -399,7 +446,7 @@ impl Vqf {
 				// clip disagreement to -2..2 °/s
 				// (this also effectively limits the harm done by the first inclination correction step)
 				let e = e.unwrap();
-				let e = Vec3::from_fn(|x, y| e[(x, y)].clamp(-biasClip, biasClip));
+				let e = e.map(|x| x.clamp(-biasClip, biasClip));
 
 				// step 2: K = P R^T inv(W + R P R^T)
 				let K = self._state.biasP
@@ -415,10 +462,9 @@ impl Vqf {
 				self._state.biasP -= K * R * self._state.biasP;
 
 				// clip bias estimate to -2..2 °/s
-				bias = Vec3::from_fn(|x, y| bias[(x, y)].clamp(-biasClip, biasClip));
+				bias = bias.map(|x| x.clamp(-biasClip, biasClip));
 			}
 
-			// ඞ
 			self._state.bias = bias;
 		}
 	}
@@ -578,27 +624,64 @@ impl Vqf {
 	pub fn getQuat6D(&self) -> Quat {
 		self._state.accQuat * self.getQuat3D()
 	}
+
+	pub fn getQuat9D(&self) -> Quat {
+		Quat::from_axis_angle(&Vec3::z_axis(), self._state.delta) * self.getQuat6D()
+	}
+
+	/// The estimated gyro bias, in rad/s.
+	pub fn getBiasEstimate(&self) -> Vec3 {
+		self._state.bias
+	}
+
+	pub fn getRestDetected(&self) -> bool {
+		self._state.restDetected
+	}
+
+	pub fn getMagDistDetected(&self) -> bool {
+		self._state.magDistDetected
+	}
+}
+
+/// The coefficients `b` and `a` (without `a0 == 1`) of a second order Butterworth
+/// low pass filter with time constant `tau`.
+fn filterCoeffs(tau: f32, Ts: f32) -> (Vec3, Vec2) {
+	assert!(tau > 0.0 && Ts > 0.0);
+	// second order Butterworth filter based on https://stackoverflow.com/a/52764064
+	// time constant of dampened, non-oscillating part of step response
+	let fc = (SQRT_2 / (2.0 * PI)) / tau;
+	let C = (PI * fc * Ts).tan();
+	let D = C.powi(2) + SQRT_2 * C + 1.0;
+	let b0 = C.powi(2) / D;
+	let b = Vec3::new(b0, 2.0 * b0, b0);
+	// a0 = 1.0
+	let a = Vec2::new(
+		2.0 * (C.powi(2) - 1.0) / D,        // a1
+		(1.0 - SQRT_2 * C + C.powi(2)) / D, // a2
+	);
+	(b, a)
+}
+
+fn gainFromTau(tau: f32, Ts: f32) -> f32 {
+	assert!(Ts > 0.0);
+	if tau < 0.0 {
+		0.0 // k=0 for negative tau (disable update)
+	} else if tau == 0.0 {
+		1.0 // k=1 for tau=0
+	} else {
+		1.0 - (-Ts / tau).exp() // fc = 1/(2*pi*tau)
+	}
 }
 
-fn filterVec<const N: usize, const M: usize>(
+fn filterVec<const N: usize>(
 	x: nalgebra::SVector<f32, N>,
 	tau: f32,
 	Ts: f32,
 	b: Vec3,
 	a: Vec2,
-	state: &mut nalgebra::Matrix<
-		f32,
-		nalgebra::Const<M>,
-		nalgebra::Const<N>,
-		ArrayStorage<f32, M, N>,
-	>,
-) -> nalgebra::Matrix<
-	f32,
-	nalgebra::Const<N>,
-	nalgebra::Const<1>,
-	ArrayStorage<f32, N, 1>,
-> {
-	assert!(M >= N);
+	state: &mut FilterState<N>,
+) -> nalgebra::SVector<f32, N> {
+	assert!(N >= 2);
 	// to avoid depending on a single sample, average the first samples (for duration tau)
 	// and then use this average to calculate the filter initial state
 	if state[(0, 0)].is_nan() {
@@ -606,25 +689,19 @@ fn filterVec<const N: usize, const M: usize>(
 		if state[(0, 1)].is_nan() {
 			// first sample
 			state[(0, 1)] = 0.0; // state[0, 1] is used to store the sample count
-					 // ඞ
-			state.get_mut((1, ..)).unwrap().fill(0.0); // state[1, :] is used to store the sum
+			state.row_mut(1).fill(0.0); // state[1, :] is used to store the sum
 		}
 
 		state[(0, 1)] += 1.0;
-		let mut out = nalgebra::SMatrix::zeros();
-		// ඞ
-		for (i, x) in x.iter().enumerate() {
-			state[(1, i)] += *x;
+		let mut out = nalgebra::SVector::zeros();
+		for i in 0..N {
+			state[(1, i)] += x[i];
 			out[i] = state[(1, i)] / state[(0, 1)];
 		}
 
 		if state[(0, 1)] * Ts >= tau {
 			for i in 0..N {
-				let init = filterInitialState(out[i], b, a);
-				// ඞ
-				for j in 0..M {
-					state[(j, i)] = init[j];
-				}
+				state.set_column(i, &filterInitialState(out[i], b, a));
 			}
 		}
 		return out;
@@ -637,32 +714,19 @@ fn filterInitialState(x0: f32, b: Vec3, a: Vec2) -> Vec2 {
 	Vec2::new(x0 * (1.0 - b[0]), x0 * (b[2] - a[1]))
 }
 
-fn filterStep<const N: usize, const M: usize>(
+fn filterStep<const N: usize>(
 	x: nalgebra::SVector<f32, N>,
 	b: Vec3,
 	a: Vec2,
-	state: &mut nalgebra::Matrix<
-		f32,
-		nalgebra::Const<M>,
-		nalgebra::Const<N>,
-		ArrayStorage<f32, M, N>,
-	>,
-) -> nalgebra::Matrix<
-	f32,
-	nalgebra::Const<N>,
-	nalgebra::Const<1>,
-	ArrayStorage<f32, N, 1>,
-> {
+	state: &mut FilterState<N>,
+) -> nalgebra::SVector<f32, N> {
 	// difference equations based on scipy.signal.lfilter documentation
 	// assumes that a0 == 1.0
-	let y = b[0] * x + nalgebra::SMatrix::repeat(state[0]);
-	// ඞ
+	let mut y = nalgebra::SVector::zeros();
 	for i in 0..N {
+		y[i] = b[0] * x[i] + state[(0, i)];
 		state[(0, i)] = b[1] * x[i] - a[0] * y[i] + state[(1, i)];
-	}
-	// ඞ
-	for i in 0..N {
 		state[(1, i)] = b[2] * x[i] - a[1] * y[i];
 	}
-	return y;
+	y
 }